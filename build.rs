@@ -0,0 +1,14 @@
+// 启用 `grpc` feature 时从 proto/clipfocus.proto 生成 gRPC 服务代码，使用内置的 protoc，不依赖系统安装
+fn main() {
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/clipfocus.proto");
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("vendored protoc not found");
+        // SAFETY: 构建脚本是单线程的，设置环境变量时没有其他线程读取
+        unsafe { std::env::set_var("PROTOC", protoc) };
+        tonic_build::configure()
+            .build_client(false)
+            .compile_protos(&["proto/clipfocus.proto"], &["proto"])
+            .expect("failed to compile proto/clipfocus.proto");
+    }
+}
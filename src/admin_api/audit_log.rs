@@ -0,0 +1,17 @@
+use actix_web::{Responder, get, web};
+
+use crate::{
+    audit::AuditLogQuery,
+    sqlx_utils::DbPool,
+    user_api::{audit_log::audit_log_page, auth::AdminReadToken},
+};
+
+// 查询全部用户的审计日志，可按用户、事件、结果、IP 和时间范围过滤
+#[get("/audit_log")]
+pub async fn list_audit_log(
+    _admin: AdminReadToken,
+    pool: web::Data<DbPool>,
+    query: web::Query<AuditLogQuery>,
+) -> impl Responder {
+    audit_log_page(&query, &pool).await
+}
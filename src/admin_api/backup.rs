@@ -0,0 +1,44 @@
+use actix_web::{HttpRequest, Responder, post, web};
+use log::{info, warn};
+use serde_json::json;
+
+use crate::{
+    audit::AuditRecord,
+    backup::{BackupError, run_backup},
+    spatial_api::models::AppState,
+    sqlx_utils::{
+        DbPool,
+        models::{ApiResponse, ErrorCode, ResponseData},
+    },
+    user_api::auth::AdminToken,
+};
+
+// 立即备份数据库（仅 SQLite），备份目录、保留数量和上传配置同定时备份
+#[post("/backup")]
+pub async fn backup(
+    admin: AdminToken,
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+) -> impl Responder {
+    match run_backup(&pool, &app_state.config.backup).await {
+        Ok(backup) => {
+            info!("管理员手动备份数据库: {}", backup.file);
+            AuditRecord::admin_action("backup", &admin, &req, &app_state)
+                .detail(json!({ "file": backup.file }))
+                .record(&pool)
+                .await;
+            ApiResponse::new("数据库备份成功", ResponseData::Json(json!(backup)))
+        }
+        Err(BackupError::Unsupported) => {
+            ApiResponse::error(ErrorCode::InvalidRequest, "只支持备份 SQLite 数据库")
+        }
+        Err(BackupError::InProgress) => {
+            ApiResponse::error(ErrorCode::RequestInProgress, "已有备份正在进行")
+        }
+        Err(e) => {
+            warn!("manual backup failed: {}", e);
+            ApiResponse::error(ErrorCode::InternalError, "数据库备份失败")
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use actix_web::{HttpRequest, Responder, delete, get, post, web};
+use log::info;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    audit::AuditRecord,
+    spatial_api::models::AppState,
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiResponse, ErrorCode, ResponseData},
+    },
+    user_api::auth::{AdminReadToken, AdminToken},
+};
+
+/// 任务列表默认 / 最大返回数量
+const DEFAULT_JOBS_LIMIT: i64 = 50;
+const MAX_JOBS_LIMIT: i64 = 200;
+
+// 任务列表参数，status 为 pending / running / done / dead
+#[derive(Deserialize)]
+pub struct JobsQuery {
+    pub status: Option<String>,
+    pub kind: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+// 列出后台任务（按创建时间倒序）
+#[get("/jobs")]
+pub async fn list_jobs(
+    _admin: AdminReadToken,
+    pool: web::Data<DbPool>,
+    query: web::Query<JobsQuery>,
+) -> impl Responder {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_JOBS_LIMIT)
+        .clamp(1, MAX_JOBS_LIMIT);
+    let offset = query.offset.unwrap_or(0).max(0);
+
+    match db::get_jobs(
+        query.status.as_deref(),
+        query.kind.as_deref(),
+        limit,
+        offset,
+        &pool,
+    )
+    .await
+    {
+        Ok((total, jobs)) => ApiResponse::new(
+            "获取后台任务成功",
+            ResponseData::Json(json!({
+                "total": total,
+                "limit": limit,
+                "offset": offset,
+                "jobs": jobs,
+            })),
+        ),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取后台任务失败"),
+    }
+}
+
+// 重新执行死信任务
+#[post("/jobs/{id}/retry")]
+pub async fn retry_job(
+    admin: AdminToken,
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    id: web::Path<String>,
+) -> impl Responder {
+    let now = chrono::Utc::now().timestamp();
+    match db::retry_job(&id, now, &pool).await {
+        Ok(true) => {
+            info!("管理员重新执行后台任务 {}", id);
+            AuditRecord::admin_action("retry_job", &admin, &req, &app_state)
+                .detail(json!({ "job_id": id.as_str() }))
+                .record(&pool)
+                .await;
+            ApiResponse::new("后台任务将重新执行", ResponseData::Null)
+        }
+        Ok(false) => ApiResponse::error(ErrorCode::NotFound, "任务不存在或不是死信任务"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "重新执行后台任务失败"),
+    }
+}
+
+// 删除未在执行中的后台任务
+#[delete("/jobs/{id}")]
+pub async fn delete_job(
+    admin: AdminToken,
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    id: web::Path<String>,
+) -> impl Responder {
+    match db::delete_job(&id, &pool).await {
+        Ok(true) => {
+            info!("管理员删除后台任务 {}", id);
+            AuditRecord::admin_action("delete_job", &admin, &req, &app_state)
+                .detail(json!({ "job_id": id.as_str() }))
+                .record(&pool)
+                .await;
+            ApiResponse::new("后台任务已删除", ResponseData::Null)
+        }
+        Ok(false) => ApiResponse::error(ErrorCode::NotFound, "任务不存在或正在执行"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "删除后台任务失败"),
+    }
+}
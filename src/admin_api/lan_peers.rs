@@ -0,0 +1,24 @@
+use actix_web::{Responder, get, web};
+use serde_json::json;
+
+use crate::{
+    spatial_api::models::AppState,
+    sqlx_utils::models::{ApiResponse, ResponseData},
+    user_api::auth::AdminReadToken,
+};
+
+// 列出局域网内通过 mDNS 发现的其他服务器（未启用局域网发现时为空）
+#[get("/lan_peers")]
+pub async fn list_lan_peers(
+    _admin: AdminReadToken,
+    app_state: web::Data<AppState>,
+) -> impl Responder {
+    let discovery = &app_state.discovery;
+    ApiResponse::new(
+        "获取局域网节点成功",
+        ResponseData::Json(json!({
+            "enabled": discovery.is_active(),
+            "peers": discovery.peers(),
+        })),
+    )
+}
@@ -0,0 +1,109 @@
+use actix_web::{Responder, get, web};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::{
+    spatial_api::models::{AppState, GetStats},
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiResponse, ErrorCode, ResponseData},
+    },
+    user_api::auth::AdminReadToken,
+};
+
+pub(crate) mod audit_log;
+pub(crate) mod backup;
+pub(crate) mod jobs;
+pub(crate) mod lan_peers;
+pub(crate) mod purge;
+pub(crate) mod rooms;
+pub(crate) mod users;
+
+pub fn admin_api() -> actix_web::Scope {
+    web::scope("/admin")
+        .service(dashboard)
+        .service(purge::purge)
+        .service(backup::backup)
+        .service(users::list_users)
+        .service(users::set_disabled)
+        .service(users::set_role)
+        .service(users::set_quota)
+        .service(users::user_storage)
+        .service(rooms::list_rooms)
+        .service(rooms::room_sessions)
+        .service(rooms::disconnect_all)
+        .service(rooms::disconnect_session)
+        .service(audit_log::list_audit_log)
+        .service(lan_peers::list_lan_peers)
+        .service(jobs::list_jobs)
+        .service(jobs::retry_job)
+        .service(jobs::delete_job)
+}
+
+/// 数据库中的统计数据（管理后台使用）
+#[derive(Debug, Serialize)]
+pub struct DatabaseStats {
+    pub users: i64,
+    /// 数据库文件大小（字节）
+    pub db_bytes: i64,
+    /// 待投递的定时剪贴板（投递队列深度）
+    pub pending_scheduled_clips: i64,
+    /// 未解决的同步冲突
+    pub open_sync_conflicts: i64,
+    /// 保存的剪贴板总数
+    pub clips: i64,
+    /// 最近一分钟通过 REST 接口保存的剪贴板
+    pub clips_last_minute: i64,
+    /// 最近一分钟创建的定时剪贴板
+    pub scheduled_clips_last_minute: i64,
+}
+
+// 管理后台数据：在线会话、吞吐量、数据库大小和队列深度
+#[get("/dashboard")]
+async fn dashboard(
+    _admin: AdminReadToken,
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+) -> impl Responder {
+    let now = chrono::Utc::now().timestamp();
+    let (room_stats, db_stats) = match tokio::join!(
+        app_state.room_manager.send(GetStats),
+        db::get_database_stats(now, &pool)
+    ) {
+        (Ok(room_stats), Ok(db_stats)) => (room_stats, db_stats),
+        _ => return ApiResponse::error(ErrorCode::InternalError, "获取管理后台数据失败"),
+    };
+
+    ApiResponse::new(
+        "获取管理后台数据成功",
+        ResponseData::Json(json!({
+            "generated_at": now,
+            "connections": {
+                "rooms": room_stats.rooms,
+                "sessions": room_stats.sessions,
+            },
+            "throughput": {
+                "messages_per_minute": room_stats.messages_per_minute,
+                "events_per_minute": room_stats.events_per_minute,
+                "clips_per_minute": room_stats.messages_per_minute as i64
+                    + db_stats.clips_last_minute
+                    + db_stats.scheduled_clips_last_minute,
+            },
+            "latency": {
+                "avg_rtt_ms": room_stats.avg_rtt_ms,
+                "max_rtt_ms": room_stats.max_rtt_ms,
+            },
+            "queues": {
+                "scheduled_clips": db_stats.pending_scheduled_clips,
+                "sync_conflicts": db_stats.open_sync_conflicts,
+                "long_poll_waiting": room_stats.waiting_pollers,
+                "event_log": room_stats.logged_events,
+            },
+            "database": {
+                "bytes": db_stats.db_bytes,
+                "users": db_stats.users,
+                "clips": db_stats.clips,
+            },
+        })),
+    )
+}
@@ -0,0 +1,229 @@
+use actix_web::{HttpRequest, Responder, post, web};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+
+use crate::{
+    audit::AuditRecord,
+    blob_store::{DELETE_BLOBS_JOB, blob_store},
+    job_queue::NewJob,
+    spatial_api::{
+        binary,
+        models::{AppState, PurgeClip, PurgeUser},
+    },
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiResponse, ErrorCode, ResponseData},
+    },
+    user_api::{
+        auth::{AdminToken, missing_secret, required_secret},
+        storage::head_key,
+    },
+};
+
+/// 清除报告签名密钥的派生上下文
+const PURGE_SIGNING_CONTEXT: &str = "ClipFocusServer 2026-10 purge report signing";
+
+/// 清除报告，签名后返回给管理员留存
+#[derive(Debug, Serialize)]
+pub struct PurgeReport {
+    pub id: String,
+    /// user / clip
+    pub subject_type: String,
+    pub subject_id: String,
+    pub purged_at: i64,
+    /// 各表删除的行数
+    pub database: BTreeMap<String, u64>,
+    /// 删除的文件
+    pub files: Vec<String>,
+    /// 断开的在线会话数
+    pub sessions_disconnected: usize,
+    /// 从内存事件日志中移除的事件数
+    pub cached_events: usize,
+}
+
+impl PurgeReport {
+    pub(crate) fn new(subject_type: &str, subject_id: &str) -> Self {
+        PurgeReport {
+            id: uuid::Uuid::new_v4().to_string(),
+            subject_type: subject_type.to_string(),
+            subject_id: subject_id.to_string(),
+            purged_at: chrono::Utc::now().timestamp(),
+            database: BTreeMap::new(),
+            files: Vec::new(),
+            sessions_disconnected: 0,
+            cached_events: 0,
+        }
+    }
+
+    // 以 purge_signing_key 的密钥对报告 JSON 做 keyed hash
+    pub(crate) fn sign(&self, key: &[u8; 32]) -> String {
+        let body = serde_json::to_vec(self).unwrap_or_default();
+        blake3::keyed_hash(key, &body).to_hex().to_string()
+    }
+}
+
+// 由 PURGE_SIGNING_KEY 派生报告的签名密钥，未设置时返回错误响应，调用方应在清除数据前检查
+pub(crate) fn purge_signing_key() -> Result<[u8; 32], ApiResponse> {
+    required_secret("PURGE_SIGNING_KEY")
+        .map(|secret| blake3::derive_key(PURGE_SIGNING_CONTEXT, secret.as_bytes()))
+        .ok_or_else(|| missing_secret("PURGE_SIGNING_KEY"))
+}
+
+// 清除请求，confirm 必须与 id 一致，避免误操作
+#[derive(Deserialize)]
+pub struct PurgeRequest {
+    /// user / clip
+    pub subject_type: String,
+    pub id: String,
+    pub confirm: String,
+}
+
+// 不可逆地清除用户或剪贴板的全部数据（数据库、文件和内存缓存），用于处理删除/下架请求
+#[post("/purge")]
+pub async fn purge(
+    admin: AdminToken,
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    request: web::Json<PurgeRequest>,
+) -> impl Responder {
+    let request = request.into_inner();
+    if request.confirm != request.id {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "confirm 必须与 id 一致");
+    }
+
+    // 无法签名时不清除数据，避免返回无法验证的报告
+    let key = match purge_signing_key() {
+        Ok(key) => key,
+        Err(response) => return response,
+    };
+
+    let mut report = PurgeReport::new(&request.subject_type, &request.id);
+    let result = match report.subject_type.as_str() {
+        "user" => purge_user(&mut report, &pool, &app_state).await,
+        "clip" => purge_clip(&mut report, &pool, &app_state).await,
+        _ => return ApiResponse::error(ErrorCode::InvalidRequest, "无效的清除对象类型"),
+    };
+    if let Err(e) = result {
+        warn!(
+            "purge {} {} failed: {}",
+            report.subject_type, report.subject_id, e
+        );
+        return ApiResponse::error(ErrorCode::InternalError, "数据清除失败");
+    }
+    info!(
+        "已清除 {} {} 的数据（报告 {}）",
+        report.subject_type, report.subject_id, report.id
+    );
+    // 清除用户时其审计日志一并删除，只保留这条清除记录
+    let mut audit = AuditRecord::admin_action("purge", &admin, &req, &app_state).detail(json!({
+        "subject_type": report.subject_type,
+        "subject_id": report.subject_id,
+        "report_id": report.id,
+    }));
+    if report.subject_type == "user" {
+        audit = audit.user(&report.subject_id);
+    }
+    audit.record(&pool).await;
+
+    let signature = report.sign(&key);
+    ApiResponse::new(
+        "数据已清除",
+        ResponseData::Json(json!({
+            "report": report,
+            "signature": signature,
+            "algorithm": "blake3-keyed",
+        })),
+    )
+}
+
+// 用户注销账号（DELETE /user/account）时同样调用
+pub(crate) async fn purge_user(
+    report: &mut PurgeReport,
+    pool: &DbPool,
+    app_state: &AppState,
+) -> Result<(), sqlx::Error> {
+    let user_id = &report.subject_id;
+    // 先断开会话，避免清除过程中写入新数据
+    report.sessions_disconnected = app_state
+        .room_manager
+        .send(PurgeUser {
+            user_id: user_id.clone(),
+        })
+        .await
+        .unwrap_or_default();
+
+    let head_uri = match db::get_user_by_id(user_id, pool).await {
+        Ok(user) => user.head_uri,
+        Err(sqlx::Error::RowNotFound) => None,
+        Err(e) => return Err(e),
+    };
+    report.database = db::purge_user_data(user_id, pool)
+        .await?
+        .into_iter()
+        .map(|(table, rows)| (table.to_string(), rows))
+        .collect();
+
+    let mut paths: Vec<String> = head_uri.as_deref().map(head_key).into_iter().collect();
+    match binary::user_clip_files(user_id).await {
+        Ok(files) => paths.extend(files.into_iter().map(|(path, _)| path)),
+        Err(e) => warn!("list clip files of {} failed: {}", user_id, e),
+    }
+    remove_static_files(report, paths, pool).await;
+    let uploads = format!("uploads/{}", report.subject_id);
+    match tokio::fs::remove_dir_all(format!("./static/{}", uploads)).await {
+        Ok(_) => report.files.push(uploads),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => warn!("remove {} failed: {}", uploads, e),
+    }
+    Ok(())
+}
+
+// 从存储中删除文件，记录删除成功的文件；删除失败的文件交给后台任务重试
+async fn remove_static_files(report: &mut PurgeReport, paths: Vec<String>, pool: &DbPool) {
+    let mut failed = Vec::new();
+    for path in paths {
+        match blob_store().delete(&path).await {
+            Ok(_) => report.files.push(path),
+            Err(e) => {
+                warn!("remove {} failed: {}", path, e);
+                failed.push(path);
+            }
+        }
+    }
+    if failed.is_empty() {
+        return;
+    }
+    if let Err(e) = NewJob::new(DELETE_BLOBS_JOB, json!({ "keys": failed }))
+        .enqueue(pool)
+        .await
+    {
+        warn!("enqueue deletion of {:?} failed: {}", failed, e);
+    }
+}
+
+async fn purge_clip(
+    report: &mut PurgeReport,
+    pool: &DbPool,
+    app_state: &AppState,
+) -> Result<(), sqlx::Error> {
+    report.database = db::purge_clip_data(&report.subject_id, pool)
+        .await?
+        .into_iter()
+        .map(|(table, rows)| (table.to_string(), rows))
+        .collect();
+    report.cached_events = app_state
+        .room_manager
+        .send(PurgeClip {
+            clip_id: report.subject_id.clone(),
+        })
+        .await
+        .unwrap_or_default();
+    match binary::find_clip_files(&report.subject_id).await {
+        Ok(paths) => remove_static_files(report, paths, pool).await,
+        Err(e) => warn!("find files of clip {} failed: {}", report.subject_id, e),
+    }
+    Ok(())
+}
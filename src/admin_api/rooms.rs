@@ -0,0 +1,116 @@
+use actix_web::{HttpRequest, Responder, delete, get, web};
+use log::info;
+use serde_json::json;
+
+use crate::{
+    audit::AuditRecord,
+    spatial_api::models::{AppState, DisconnectSessions, GetRooms, GetSessions},
+    sqlx_utils::{
+        DbPool,
+        models::{ApiResponse, ErrorCode, ResponseData},
+    },
+    user_api::auth::{AdminReadToken, AdminToken},
+};
+
+/// 管理员断开会话时发给客户端的关闭原因
+const ADMIN_DISCONNECT_REASON: &str = "disconnected by admin";
+
+// 列出在线的房间（每个用户一个房间）及其会话数
+#[get("/rooms")]
+pub async fn list_rooms(_admin: AdminReadToken, app_state: web::Data<AppState>) -> impl Responder {
+    match app_state.room_manager.send(GetRooms).await {
+        Ok(rooms) => {
+            let sessions: usize = rooms.iter().map(|room| room.sessions).sum();
+            ApiResponse::new(
+                "获取房间列表成功",
+                ResponseData::Json(json!({ "sessions": sessions, "rooms": rooms })),
+            )
+        }
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取房间列表失败"),
+    }
+}
+
+// 查看用户房间内的会话
+#[get("/rooms/{user_id}")]
+pub async fn room_sessions(
+    _admin: AdminReadToken,
+    app_state: web::Data<AppState>,
+    user_id: web::Path<String>,
+) -> impl Responder {
+    match app_state
+        .room_manager
+        .send(GetSessions {
+            user_id: user_id.into_inner(),
+        })
+        .await
+    {
+        Ok(sessions) => ApiResponse::new("获取会话成功", ResponseData::Json(json!(sessions))),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取会话失败"),
+    }
+}
+
+// 断开用户的全部会话
+#[delete("/rooms/{user_id}/sessions")]
+pub async fn disconnect_all(
+    admin: AdminToken,
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    user_id: web::Path<String>,
+) -> impl Responder {
+    let audit = AuditRecord::admin_action("disconnect_sessions", &admin, &req, &app_state);
+    disconnect(audit, &app_state, &pool, user_id.into_inner(), None).await
+}
+
+// 断开用户的指定会话
+#[delete("/rooms/{user_id}/sessions/{session_id}")]
+pub async fn disconnect_session(
+    admin: AdminToken,
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    let (user_id, session_id) = path.into_inner();
+    let audit = AuditRecord::admin_action("disconnect_sessions", &admin, &req, &app_state);
+    disconnect(audit, &app_state, &pool, user_id, Some(session_id)).await
+}
+
+async fn disconnect(
+    audit: AuditRecord,
+    app_state: &AppState,
+    pool: &DbPool,
+    user_id: String,
+    session_id: Option<String>,
+) -> ApiResponse {
+    let disconnected = match app_state
+        .room_manager
+        .send(DisconnectSessions {
+            user_id: user_id.clone(),
+            session_id: session_id.clone(),
+            reason: ADMIN_DISCONNECT_REASON.to_string(),
+        })
+        .await
+    {
+        Ok(disconnected) => disconnected,
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "断开会话失败"),
+    };
+    if disconnected == 0 {
+        return ApiResponse::error(ErrorCode::NotFound, "会话不存在");
+    }
+    info!(
+        "管理员断开用户 {} 的 {} 个会话（{}）",
+        user_id,
+        disconnected,
+        session_id.as_deref().unwrap_or("全部")
+    );
+    audit
+        .user(&user_id)
+        .detail(json!({ "session_id": session_id, "sessions_disconnected": disconnected }))
+        .record(pool)
+        .await;
+    ApiResponse::new(
+        "会话已断开",
+        ResponseData::Json(json!({ "sessions_disconnected": disconnected })),
+    )
+}
@@ -0,0 +1,225 @@
+use actix_web::{HttpRequest, Responder, get, put, web};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    audit::AuditRecord,
+    models::UserRole,
+    spatial_api::models::{AppState, DisconnectSessions, PushEvent},
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiResponse, ErrorCode, ResponseData},
+    },
+    user_api::{
+        auth::{AdminReadToken, AdminToken},
+        quota::quota_usage,
+        storage::storage_report,
+    },
+};
+
+/// 用户列表默认 / 最大返回数量
+const DEFAULT_USERS_LIMIT: i32 = 50;
+const MAX_USERS_LIMIT: i32 = 200;
+
+/// 管理接口返回的用户信息（不包含密码）
+#[derive(Debug, Serialize)]
+pub struct AdminUser {
+    pub user_id: String,
+    pub username: String,
+    pub email: String,
+    pub disabled: bool,
+    pub role: UserRole,
+    pub org_id: Option<String>,
+    pub email_verified: bool,
+    /// 保存的剪贴板数
+    pub clip_count: i64,
+}
+
+// 用户列表参数，q 按用户名或邮箱搜索（不区分大小写）
+#[derive(Deserialize)]
+pub struct UsersQuery {
+    pub q: Option<String>,
+    pub limit: Option<i32>,
+    pub offset: Option<i32>,
+}
+
+// 列出 / 搜索用户
+#[get("/users")]
+pub async fn list_users(
+    _admin: AdminReadToken,
+    pool: web::Data<DbPool>,
+    query: web::Query<UsersQuery>,
+) -> impl Responder {
+    let text = query.q.as_deref().unwrap_or("").trim();
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_USERS_LIMIT)
+        .clamp(1, MAX_USERS_LIMIT);
+    let offset = query.offset.unwrap_or(0).max(0);
+
+    match db::search_users(text, limit, offset, &pool).await {
+        Ok((total, users)) => ApiResponse::new(
+            "获取用户列表成功",
+            ResponseData::Json(json!({
+                "total": total,
+                "limit": limit,
+                "offset": offset,
+                "users": users,
+            })),
+        ),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取用户列表失败"),
+    }
+}
+
+// 停用 / 启用账号
+#[derive(Deserialize)]
+pub struct SetDisabled {
+    pub disabled: bool,
+}
+
+// 停用账号后通知并断开其所有会话，刷新令牌随之失效；已签发的访问令牌在过期前仍可调用 REST 接口
+#[put("/users/{id}/disabled")]
+pub async fn set_disabled(
+    admin: AdminToken,
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    id: web::Path<String>,
+    set: web::Json<SetDisabled>,
+) -> impl Responder {
+    match db::set_user_disabled(&id, set.disabled, &pool).await {
+        Ok(true) => {}
+        Ok(false) => return ApiResponse::error(ErrorCode::NotFound, "用户不存在"),
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "修改账号状态失败"),
+    }
+
+    let mut disconnected = 0;
+    if set.disabled {
+        info!("管理员停用账号 {}", id);
+        app_state.room_manager.do_send(PushEvent {
+            user_id: id.to_string(),
+            event: "account_disabled".to_string(),
+            payload: json!({ "reason": "admin" }),
+        });
+        disconnected = app_state
+            .room_manager
+            .send(DisconnectSessions {
+                user_id: id.to_string(),
+                session_id: None,
+                reason: "account disabled".to_string(),
+            })
+            .await
+            .unwrap_or_default();
+    } else {
+        info!("管理员启用账号 {}", id);
+    }
+    AuditRecord::admin_action("set_disabled", &admin, &req, &app_state)
+        .user(&id)
+        .detail(json!({ "disabled": set.disabled }))
+        .record(&pool)
+        .await;
+
+    ApiResponse::new(
+        if set.disabled { "账号已停用" } else { "账号已启用" },
+        ResponseData::Json(json!({
+            "user_id": id.as_str(),
+            "disabled": set.disabled,
+            "sessions_disconnected": disconnected,
+        })),
+    )
+}
+
+// 修改账号角色
+#[derive(Deserialize)]
+pub struct SetRole {
+    pub role: UserRole,
+}
+
+// 修改后用户需要重新登录或刷新令牌，新的访问令牌才带有新角色
+#[put("/users/{id}/role")]
+pub async fn set_role(
+    admin: AdminToken,
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    id: web::Path<String>,
+    set: web::Json<SetRole>,
+) -> impl Responder {
+    match db::set_user_role(&id, set.role, &pool).await {
+        Ok(true) => {
+            info!("管理员将账号 {} 的角色修改为 {:?}", id, set.role);
+            AuditRecord::admin_action("set_role", &admin, &req, &app_state)
+                .user(&id)
+                .detail(json!({ "role": set.role }))
+                .record(&pool)
+                .await;
+            ApiResponse::new(
+                "角色已修改，刷新令牌后生效",
+                ResponseData::Json(json!({ "user_id": id.as_str(), "role": set.role })),
+            )
+        }
+        Ok(false) => ApiResponse::error(ErrorCode::NotFound, "用户不存在"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "修改角色失败"),
+    }
+}
+
+// 设置个人存储配额
+#[derive(Deserialize)]
+pub struct SetQuota {
+    /// 配额（字节），为空时恢复默认配额
+    pub quota_bytes: Option<i64>,
+}
+
+// 只影响之后的写入，已超出配额的数据不会被删除
+#[put("/users/{id}/quota")]
+pub async fn set_quota(
+    admin: AdminToken,
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    id: web::Path<String>,
+    set: web::Json<SetQuota>,
+) -> impl Responder {
+    if set.quota_bytes.is_some_and(|quota_bytes| quota_bytes < 0) {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "配额不能为负数");
+    }
+    match db::set_user_quota(&id, set.quota_bytes, &pool).await {
+        Ok(true) => {
+            info!("管理员将账号 {} 的存储配额修改为 {:?}", id, set.quota_bytes);
+            AuditRecord::admin_action("set_quota", &admin, &req, &app_state)
+                .user(&id)
+                .detail(json!({ "quota_bytes": set.quota_bytes }))
+                .record(&pool)
+                .await;
+        }
+        Ok(false) => return ApiResponse::error(ErrorCode::NotFound, "用户不存在"),
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "修改存储配额失败"),
+    }
+    // 返回生效的配额（未单独设置时为默认配额）和当前用量
+    match quota_usage(&id, &**pool).await {
+        Ok(Some(usage)) => ApiResponse::new(
+            "存储配额已修改",
+            ResponseData::Json(json!({
+                "user_id": id.as_str(),
+                "quota_bytes": usage.user.quota_bytes,
+                "used_bytes": usage.user.used_bytes,
+            })),
+        ),
+        _ => ApiResponse::error(ErrorCode::InternalError, "获取存储配额失败"),
+    }
+}
+
+// 查看用户的存储占用（与 /user/storage 相同）
+#[get("/users/{id}/storage")]
+pub async fn user_storage(
+    _admin: AdminReadToken,
+    pool: web::Data<DbPool>,
+    id: web::Path<String>,
+) -> impl Responder {
+    match db::get_user_by_id(&id, &pool).await {
+        Ok(_) => storage_report(&id, &pool).await,
+        Err(sqlx::Error::RowNotFound) => ApiResponse::error(ErrorCode::NotFound, "用户不存在"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取存储占用失败"),
+    }
+}
@@ -0,0 +1,99 @@
+use actix_web::{Responder, get, post, web};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    spatial_api::models::{AppState, BroadcastEvent},
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiResponse, ErrorCode, ResponseData},
+    },
+    user_api::auth::AdminToken,
+};
+
+pub fn announcement_api() -> actix_web::Scope {
+    web::scope("/announcements")
+        .service(list_announcements)
+        .service(create_announcement)
+}
+
+/// 服务器公告（维护通知、版本弃用提醒等）
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Announcement {
+    pub id: String,
+    /// 级别：info / warning / critical
+    pub level: String,
+    pub title: String,
+    pub content: String,
+    pub created_at: i64,
+    /// 过期时间戳，过期后不再返回给客户端
+    pub expires_at: Option<i64>,
+}
+
+const ANNOUNCEMENT_LEVELS: &[&str] = &["info", "warning", "critical"];
+
+// 发布公告
+#[derive(Deserialize)]
+pub struct CreateAnnouncement {
+    pub level: Option<String>,
+    pub title: String,
+    pub content: String,
+    pub expires_at: Option<i64>,
+}
+
+#[post("")]
+async fn create_announcement(
+    _admin: AdminToken,
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    create: web::Json<CreateAnnouncement>,
+) -> impl Responder {
+    let create = create.into_inner();
+    let level = create.level.unwrap_or_else(|| "info".to_string());
+    if !ANNOUNCEMENT_LEVELS.contains(&level.as_str()) {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "无效的公告级别");
+    }
+
+    let announcement = Announcement {
+        id: uuid::Uuid::new_v4().to_string(),
+        level,
+        title: create.title,
+        content: create.content,
+        created_at: chrono::Utc::now().timestamp(),
+        expires_at: create.expires_at,
+    };
+    info!("发布公告: {}", announcement.title);
+
+    match db::insert_announcement(&announcement, &pool).await {
+        Ok(_) => {
+            // 推送给所有在线会话，离线用户通过列表接口获取
+            app_state.room_manager.do_send(BroadcastEvent {
+                event: "announcement".to_string(),
+                payload: json!(announcement),
+            });
+            ApiResponse::new("公告发布成功", ResponseData::Json(json!(announcement)))
+        }
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "公告发布失败"),
+    }
+}
+
+// 获取公告列表
+#[derive(Deserialize)]
+pub struct AnnouncementQuery {
+    /// 只返回该时间戳之后发布的公告
+    pub since: Option<i64>,
+}
+
+#[get("")]
+async fn list_announcements(
+    pool: web::Data<DbPool>,
+    query: web::Query<AnnouncementQuery>,
+) -> impl Responder {
+    match db::get_announcements_since(query.since.unwrap_or(0), &pool).await {
+        Ok(announcements) => {
+            ApiResponse::new("获取公告成功", ResponseData::Json(json!(announcements)))
+        }
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取公告失败"),
+    }
+}
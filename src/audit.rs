@@ -0,0 +1,152 @@
+use actix_web::HttpRequest;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    spatial_api::models::AppState,
+    sqlx_utils::{
+        DbPool, db,
+        models::{Cursor, ErrorCode},
+    },
+    user_api::{
+        auth::{RequireRole, RoleRequirement},
+        sessions::SessionClient,
+    },
+};
+
+/// 审计日志记录的安全相关事件
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum AuditEvent {
+    /// 登录（密码或第三方账号），失败时记录错误码
+    Login,
+    /// 刷新令牌
+    TokenRefreshed,
+    /// 修改密码或通过邮件重置密码
+    PasswordChanged,
+    TwoFactorEnabled,
+    TwoFactorDisabled,
+    /// 绑定第三方账号
+    IdentityLinked,
+    /// 解除绑定第三方账号
+    IdentityUnlinked,
+    /// 注销登录会话
+    SessionRevoked,
+    DeviceRegistered,
+    DeviceRevoked,
+    /// 管理员操作，detail.action 为操作名称
+    AdminAction,
+}
+
+/// 审计日志条目
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct AuditLogEntry {
+    pub id: String,
+    pub event: AuditEvent,
+    /// 事件涉及的用户（管理员操作的对象），登录失败且账号不存在时为空
+    pub user_id: Option<String>,
+    /// 执行管理员操作的账号，使用 ADMIN_TOKEN 时为空
+    pub actor_id: Option<String>,
+    pub success: bool,
+    /// 失败时的错误码
+    pub error_code: Option<ErrorCode>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    /// 事件相关的附加信息
+    #[schema(value_type = Object)]
+    pub detail: Value,
+    pub created_at: i64,
+}
+
+/// 审计日志查询条件，按时间倒序，按 next_cursor 翻页
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditLogQuery {
+    /// 只返回该用户的事件（用户接口忽略该参数）
+    pub user_id: Option<String>,
+    pub event: Option<AuditEvent>,
+    pub success: Option<bool>,
+    pub ip: Option<String>,
+    /// 起始时间（Unix 秒，包含）
+    pub since: Option<i64>,
+    /// 截止时间（Unix 秒，不包含）
+    pub until: Option<i64>,
+    pub limit: Option<i64>,
+    /// 上一页返回的 next_cursor
+    #[param(value_type = Option<String>)]
+    pub cursor: Option<Cursor>,
+}
+
+/// 审计日志列表默认 / 最大返回数量
+pub const DEFAULT_AUDIT_LOG_LIMIT: i64 = 50;
+pub const MAX_AUDIT_LOG_LIMIT: i64 = 200;
+
+/// 待写入的审计日志条目，由请求构造后补充用户、结果和附加信息
+pub struct AuditRecord {
+    pub event: AuditEvent,
+    pub user_id: Option<String>,
+    pub actor_id: Option<String>,
+    pub error_code: Option<ErrorCode>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub detail: Value,
+}
+
+impl AuditRecord {
+    // IP 和 User-Agent 与登录会话记录的相同（IP 考虑可信代理）
+    pub fn new(event: AuditEvent, req: &HttpRequest, app_state: &AppState) -> Self {
+        let client = SessionClient::from_request(req, app_state);
+        AuditRecord {
+            event,
+            user_id: None,
+            actor_id: None,
+            error_code: None,
+            ip: client.ip,
+            user_agent: client.user_agent,
+            detail: Value::Null,
+        }
+    }
+
+    pub fn user(mut self, user_id: &str) -> Self {
+        self.user_id = Some(user_id.to_string());
+        self
+    }
+
+    /// 管理员操作，操作名称记录在 detail.action 中
+    pub fn admin_action<R: RoleRequirement>(
+        action: &str,
+        admin: &RequireRole<R>,
+        req: &HttpRequest,
+        app_state: &AppState,
+    ) -> Self {
+        let mut record = AuditRecord::new(AuditEvent::AdminAction, req, app_state)
+            .detail(json!({ "action": action }));
+        record.actor_id = admin.user_id().map(str::to_string);
+        record
+    }
+
+    /// 操作结果，错误码为空表示成功
+    pub fn outcome(mut self, error_code: Option<ErrorCode>) -> Self {
+        self.error_code = error_code;
+        self
+    }
+
+    /// 附加信息，两者都是对象时合并字段
+    pub fn detail(mut self, detail: Value) -> Self {
+        match (&mut self.detail, detail) {
+            (Value::Object(current), Value::Object(fields)) => current.extend(fields),
+            (current, detail) => *current = detail,
+        }
+        self
+    }
+
+    // 写入失败只记录日志，不影响请求本身
+    pub async fn record(self, pool: &DbPool) {
+        if let Err(e) = db::insert_audit_log(&self, pool).await {
+            warn!("写入审计日志 {:?} 失败: {}", self.event, e);
+        }
+    }
+}
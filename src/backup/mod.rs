@@ -0,0 +1,193 @@
+use actix::prelude::*;
+use chrono::Utc;
+use log::{info, warn};
+use serde::Serialize;
+use std::path::Path;
+use tokio::sync::Mutex;
+
+use crate::config::BackupConfig;
+use crate::sqlx_utils::{
+    DbPool,
+    backend::{Backend, backend},
+    db,
+};
+
+/// 备份文件名前缀，轮换时只删除符合该前缀的文件
+const BACKUP_PREFIX: &str = "clipfocus-";
+const BACKUP_SUFFIX: &str = ".db";
+
+/// 同一时间只运行一个备份（定时备份和手动备份共用）
+static RUNNING: Mutex<()> = Mutex::const_new(());
+
+/// 一次备份的结果
+#[derive(Debug, Serialize)]
+pub struct BackupInfo {
+    pub file: String,
+    pub bytes: u64,
+    pub created_at: i64,
+    /// 是否已上传到 S3 兼容存储
+    pub uploaded: bool,
+    /// 轮换时删除的旧备份
+    pub removed: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum BackupError {
+    /// 当前数据库不是 SQLite
+    Unsupported,
+    /// 已有备份正在进行
+    InProgress,
+    Failed(String),
+}
+
+impl std::fmt::Display for BackupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackupError::Unsupported => write!(f, "only SQLite databases can be backed up"),
+            BackupError::InProgress => write!(f, "another backup is in progress"),
+            BackupError::Failed(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+// 备份数据库到配置的目录，随后上传（如已配置）并删除超出保留数量的旧备份
+pub async fn run_backup(pool: &DbPool, config: &BackupConfig) -> Result<BackupInfo, BackupError> {
+    if backend() != Backend::Sqlite {
+        return Err(BackupError::Unsupported);
+    }
+    let Ok(_running) = RUNNING.try_lock() else {
+        return Err(BackupError::InProgress);
+    };
+    let failed = |e: &dyn std::fmt::Display| BackupError::Failed(e.to_string());
+
+    tokio::fs::create_dir_all(&config.dir)
+        .await
+        .map_err(|e| failed(&e))?;
+    let now = Utc::now();
+    let file = format!(
+        "{}{}{}",
+        BACKUP_PREFIX,
+        now.format("%Y%m%d-%H%M%S-%3f"),
+        BACKUP_SUFFIX
+    );
+    let path = Path::new(&config.dir).join(&file);
+    let path_str = path.to_string_lossy();
+    // VACUUM INTO 在一个读事务中复制，备份期间仍可正常写入
+    db::vacuum_into(&path_str, pool)
+        .await
+        .map_err(|e| failed(&e))?;
+    let bytes = tokio::fs::metadata(&path)
+        .await
+        .map_err(|e| failed(&e))?
+        .len();
+    info!("数据库已备份到 {}（{} 字节）", path_str, bytes);
+
+    let uploaded = match &config.s3 {
+        Some(s3) => upload(s3, &file, &path).await,
+        None => false,
+    };
+    let removed = match rotate(&config.dir, config.keep).await {
+        Ok(removed) => removed,
+        Err(e) => {
+            warn!("rotate backups in {} failed: {}", config.dir, e);
+            Vec::new()
+        }
+    };
+    Ok(BackupInfo {
+        file,
+        bytes,
+        created_at: now.timestamp(),
+        uploaded,
+        removed,
+    })
+}
+
+// 上传备份文件，失败时只记录日志，本地备份仍然有效
+#[cfg(feature = "s3")]
+async fn upload(config: &crate::config::S3Config, file: &str, path: &Path) -> bool {
+    let key = format!("{}{}", config.prefix, file);
+    let result = match tokio::fs::read(path).await {
+        Ok(data) => crate::s3::put_object(config, &key, data).await,
+        Err(e) => Err(e.to_string()),
+    };
+    match result {
+        Ok(()) => {
+            info!("备份已上传到 {}/{}", config.bucket, key);
+            true
+        }
+        Err(e) => {
+            warn!("upload backup {} failed: {}", key, e);
+            false
+        }
+    }
+}
+
+#[cfg(not(feature = "s3"))]
+async fn upload(_config: &crate::config::S3Config, file: &str, _path: &Path) -> bool {
+    warn!(
+        "backup.s3 is configured but the s3 feature is disabled, {} is kept locally only",
+        file
+    );
+    false
+}
+
+// 按文件名（即备份时间）排序，删除超出保留数量的旧备份，返回删除的文件名
+async fn rotate(dir: &str, keep: usize) -> std::io::Result<Vec<String>> {
+    if keep == 0 {
+        return Ok(Vec::new());
+    }
+    let mut backups = Vec::new();
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with(BACKUP_PREFIX) && name.ends_with(BACKUP_SUFFIX) {
+            backups.push(name);
+        }
+    }
+    backups.sort();
+    let expired = backups.len().saturating_sub(keep);
+    let mut removed = Vec::new();
+    for name in backups.into_iter().take(expired) {
+        tokio::fs::remove_file(Path::new(dir).join(&name)).await?;
+        removed.push(name);
+    }
+    if !removed.is_empty() {
+        info!("删除 {} 个旧备份", removed.len());
+    }
+    Ok(removed)
+}
+
+/// 定时备份调度器（backup.enabled 为 true 时启动）
+pub struct BackupScheduler {
+    pool: DbPool,
+    config: BackupConfig,
+}
+
+impl BackupScheduler {
+    pub fn new(pool: DbPool, config: BackupConfig) -> Self {
+        Self { pool, config }
+    }
+}
+
+impl Actor for BackupScheduler {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        info!(
+            "Backup scheduler started, backing up every {}s to {}",
+            self.config.interval_secs, self.config.dir
+        );
+
+        ctx.run_interval(self.config.interval(), |act, ctx| {
+            let pool = act.pool.clone();
+            let config = act.config.clone();
+            async move {
+                if let Err(e) = run_backup(&pool, &config).await {
+                    warn!("scheduled backup failed: {}", e);
+                }
+            }
+            .into_actor(act)
+            .wait(ctx);
+        });
+    }
+}
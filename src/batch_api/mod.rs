@@ -0,0 +1,401 @@
+use actix_web::{HttpRequest, Responder, post, web};
+use chrono::{SubsecRound, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::{
+    clip_api::{normalize_tags, notify, prepare_clip, visible_clip},
+    device_group_api::DeviceId,
+    idempotency::{self, Idempotency, IdempotencyKey},
+    models::{ClipItem, CreateClipRequest},
+    scheduled_api::{CreateScheduledClip, ScheduledClip, new_scheduled_clip},
+    spatial_api::models::{AppState, PushEvent},
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiResponse, ErrorCode, ResponseData},
+    },
+    user_api::{auth::BearerToken, quota::check_quota, settings},
+};
+
+pub fn batch_api() -> actix_web::Scope {
+    web::scope("/batch").service(run_batch)
+}
+
+/// 单次批量请求允许的最大操作数
+const MAX_BATCH_OPERATIONS: usize = 100;
+
+/// 批量操作，通过 `op` 字段区分类型
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BatchOperation {
+    /// 创建定时投递，字段同 `POST /scheduled_clips`
+    CreateScheduledClip(CreateScheduledClip),
+    /// 取消定时投递
+    CancelScheduledClip { id: String },
+    /// 修改用户设置，规则同 `PUT /user/settings`
+    UpdateSettings { settings: Map<String, Value> },
+    /// 创建剪贴板，字段同 `POST /clips`
+    CreateClip(CreateClipRequest),
+    /// 修改剪贴板，字段同 `PUT /clips/{id}`，version 对应 If-Match
+    UpdateClip {
+        id: Uuid,
+        version: i64,
+        #[serde(default)]
+        accessed: bool,
+        tags: Option<Vec<String>>,
+    },
+    /// 删除剪贴板，version 对应 If-Match
+    DeleteClip { id: Uuid, version: i64 },
+    /// 替换剪贴板的标签，version 对应 If-Match
+    SetTags {
+        id: Uuid,
+        version: i64,
+        tags: Vec<String>,
+    },
+}
+
+// 批量操作请求
+#[derive(Serialize, Deserialize)]
+pub struct BatchRequest {
+    pub operations: Vec<BatchOperation>,
+}
+
+/// 校验通过、等待在事务中执行的操作
+enum PreparedOperation {
+    CreateScheduledClip(ScheduledClip),
+    CancelScheduledClip(String),
+    UpdateSettings(Vec<(String, Option<String>)>),
+    CreateClip(Box<ClipItem>, String),
+    UpdateClip(Box<ClipItem>, i64),
+    DeleteClip(Box<ClipItem>, i64),
+}
+
+// 批量执行操作：全部成功才提交，任意一项失败则全部回滚
+#[post("")]
+async fn run_batch(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    device_id: DeviceId,
+    idempotency_key: IdempotencyKey,
+    req: HttpRequest,
+    batch: web::Json<BatchRequest>,
+) -> impl Responder {
+    let body = serde_json::to_vec(&*batch).unwrap_or_default();
+    let fingerprint = idempotency::fingerprint(&req, &body);
+    match idempotency_key
+        .begin(&bearer_token.user_id, &fingerprint, &pool)
+        .await
+    {
+        Ok(Idempotency::Proceed) => {}
+        Ok(Idempotency::Replay(response)) | Err(response) => return response,
+    }
+
+    let response = execute_batch(
+        &pool,
+        &app_state,
+        &bearer_token.user_id,
+        &device_id,
+        batch.into_inner(),
+    )
+    .await;
+    idempotency_key
+        .finish(&bearer_token.user_id, response, &pool)
+        .await
+}
+
+// 单项操作的执行结果，失败时 status 为单独请求该操作时的 HTTP 状态码
+fn op_result(index: usize, result: Result<Value, ApiResponse>) -> Value {
+    match result {
+        Ok(value) => json!({ "index": index, "ok": true, "result": value }),
+        Err(error) => json!({
+            "index": index,
+            "ok": false,
+            "status": error.status().as_u16(),
+            "error": error,
+        }),
+    }
+}
+
+// 同一批次中已被前面的操作修改过的剪贴板以修改后的内容为准
+async fn staged_clip(
+    staged: &HashMap<Uuid, ClipItem>,
+    user_id: &str,
+    id: &Uuid,
+    device_id: &DeviceId,
+    pool: &DbPool,
+) -> Result<ClipItem, ApiResponse> {
+    match staged.get(id) {
+        Some(clip) => Ok(clip.clone()),
+        None => visible_clip(user_id, id, device_id, pool).await,
+    }
+}
+
+// 剪贴板版本不一致或已被删除
+fn clip_conflict() -> ApiResponse {
+    ApiResponse::error(
+        ErrorCode::VersionConflict,
+        "剪贴板已被其他设备修改或已删除，请刷新后重试",
+    )
+}
+
+// 整个批量请求失败时的响应：失败项之前的操作已回滚，之后的操作未执行
+fn batch_failed(
+    message: &str,
+    code: ErrorCode,
+    failed: usize,
+    error: ApiResponse,
+    total: usize,
+) -> ApiResponse {
+    let mut results: Vec<Value> = (0..failed)
+        .map(|index| json!({ "index": index, "ok": false, "rolled_back": true }))
+        .collect();
+    results.push(op_result(failed, Err(error)));
+    results.extend(
+        (failed + 1..total).map(|index| json!({ "index": index, "ok": false, "skipped": true })),
+    );
+    ApiResponse::error_with_data(
+        code,
+        &format!("{}，第 {} 项操作失败，全部操作已回滚", message, failed),
+        ResponseData::Json(json!({ "committed": false, "results": results })),
+    )
+}
+
+async fn execute_batch(
+    pool: &DbPool,
+    app_state: &AppState,
+    user_id: &str,
+    device_id: &DeviceId,
+    batch: BatchRequest,
+) -> ApiResponse {
+    let total = batch.operations.len();
+    if total == 0 || total > MAX_BATCH_OPERATIONS {
+        return ApiResponse::error(
+            ErrorCode::InvalidRequest,
+            &format!("操作数量必须在 1 到 {} 之间", MAX_BATCH_OPERATIONS),
+        );
+    }
+    info!("用户 {} 执行批量操作，共 {} 项", user_id, total);
+
+    let groups = match db::get_device_groups(user_id, pool).await {
+        Ok(groups) => groups,
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "批量操作失败"),
+    };
+
+    // 先校验全部操作，避免无效请求开启事务
+    let mut prepared = Vec::with_capacity(total);
+    let mut staged = HashMap::new();
+    for (index, operation) in batch.operations.into_iter().enumerate() {
+        let result = match operation {
+            BatchOperation::CreateScheduledClip(create) => {
+                new_scheduled_clip(user_id, create, &app_state.content_policy, &groups)
+                    .map(PreparedOperation::CreateScheduledClip)
+            }
+            BatchOperation::CancelScheduledClip { id } => {
+                Ok(PreparedOperation::CancelScheduledClip(id))
+            }
+            BatchOperation::UpdateSettings { settings } => settings::parse_changes(settings)
+                .map(PreparedOperation::UpdateSettings)
+                .map_err(|reason| ApiResponse::error(ErrorCode::InvalidRequest, &reason)),
+            BatchOperation::CreateClip(create) => prepare_clip(pool, app_state, user_id, create)
+                .await
+                .map(|(clip, hash)| PreparedOperation::CreateClip(Box::new(clip), hash)),
+            BatchOperation::UpdateClip {
+                id,
+                version,
+                accessed,
+                tags,
+            } => staged_clip(&staged, user_id, &id, device_id, pool)
+                .await
+                .map(|mut clip| {
+                    if accessed {
+                        clip.accessed_at = Utc::now().trunc_subsecs(3);
+                    }
+                    if let Some(tags) = tags {
+                        clip.tags = normalize_tags(tags);
+                    }
+                    staged.insert(id, clip.clone());
+                    PreparedOperation::UpdateClip(Box::new(clip), version)
+                }),
+            BatchOperation::DeleteClip { id, version } => {
+                staged_clip(&staged, user_id, &id, device_id, pool)
+                    .await
+                    .map(|clip| {
+                        staged.remove(&id);
+                        PreparedOperation::DeleteClip(Box::new(clip), version)
+                    })
+            }
+            BatchOperation::SetTags { id, version, tags } => {
+                staged_clip(&staged, user_id, &id, device_id, pool)
+                    .await
+                    .map(|mut clip| {
+                        clip.tags = normalize_tags(tags);
+                        staged.insert(id, clip.clone());
+                        PreparedOperation::UpdateClip(Box::new(clip), version)
+                    })
+            }
+        };
+        match result {
+            Ok(operation) => prepared.push(operation),
+            Err(error) => {
+                let code = error.code.unwrap_or(ErrorCode::InvalidRequest);
+                return batch_failed("批量操作校验失败", code, index, error, total);
+            }
+        }
+    }
+
+    let new_bytes = prepared
+        .iter()
+        .map(|operation| match operation {
+            PreparedOperation::CreateScheduledClip(clip) => clip.content.len() as i64,
+            PreparedOperation::CreateClip(clip, _) => clip.size,
+            _ => 0,
+        })
+        .sum();
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "批量操作失败"),
+    };
+    if new_bytes > 0
+        && let Err(response) = check_quota(user_id, new_bytes, &mut tx).await
+    {
+        return response;
+    }
+    let mut results = Vec::with_capacity(total);
+    let mut settings_changed = false;
+    // 提交后需要通知其他设备的剪贴板变更
+    let mut clip_events: Vec<(ClipItem, &str)> = Vec::new();
+    let mut deduped_ids = Vec::new();
+    for (index, operation) in prepared.into_iter().enumerate() {
+        let result = match operation {
+            PreparedOperation::CreateScheduledClip(clip) => {
+                match db::insert_scheduled_clip(&clip, &mut tx).await {
+                    Ok(_) => Ok(json!(clip)),
+                    Err(_) => Err(ApiResponse::error(
+                        ErrorCode::InternalError,
+                        "定时投递创建失败",
+                    )),
+                }
+            }
+            PreparedOperation::CancelScheduledClip(id) => {
+                match db::update_scheduled_clip_status(&id, user_id, "cancelled", &mut tx).await {
+                    Ok(true) => Ok(json!({ "id": id })),
+                    Ok(false) => Err(ApiResponse::error(
+                        ErrorCode::NotFound,
+                        "定时投递不存在或已投递",
+                    )),
+                    Err(_) => Err(ApiResponse::error(
+                        ErrorCode::InternalError,
+                        "取消定时投递失败",
+                    )),
+                }
+            }
+            PreparedOperation::UpdateSettings(updates) => {
+                settings_changed = true;
+                match db::write_user_settings(user_id, &updates, &mut tx).await {
+                    Ok(_) => Ok(json!(
+                        updates.iter().map(|(key, _)| key).collect::<Vec<_>>()
+                    )),
+                    Err(_) => Err(ApiResponse::error(ErrorCode::InternalError, "设置保存失败")),
+                }
+            }
+            PreparedOperation::CreateClip(clip, hash) => {
+                match db::write_clip_insert(user_id, &clip, &hash, &mut tx).await {
+                    // 重复的内容：已有的剪贴板被移到最前，提交后再读取
+                    Ok(Some(id)) => {
+                        deduped_ids.push(id);
+                        Ok(json!({ "id": id, "deduped": true }))
+                    }
+                    Ok(None) => {
+                        let mut value = json!(clip);
+                        value["deduped"] = json!(false);
+                        clip_events.push((*clip, "clip_created"));
+                        Ok(value)
+                    }
+                    Err(_) => Err(ApiResponse::error(
+                        ErrorCode::InternalError,
+                        "剪贴板保存失败",
+                    )),
+                }
+            }
+            PreparedOperation::UpdateClip(mut clip, version) => {
+                let modified_by = device_id.0.as_deref();
+                match db::write_clip_update(user_id, &clip, Some(version), modified_by, &mut tx)
+                    .await
+                {
+                    Ok(Some(version)) => {
+                        clip.version = version;
+                        let value = json!(clip);
+                        clip_events.push((*clip, "clip_updated"));
+                        Ok(value)
+                    }
+                    Ok(None) => Err(clip_conflict()),
+                    Err(_) => Err(ApiResponse::error(
+                        ErrorCode::InternalError,
+                        "剪贴板修改失败",
+                    )),
+                }
+            }
+            PreparedOperation::DeleteClip(clip, version) => {
+                match db::write_clip_delete(user_id, &clip.id, Some(version), &mut tx).await {
+                    Ok(true) => {
+                        let value = json!({ "id": clip.id });
+                        clip_events.push((*clip, "clip_deleted"));
+                        Ok(value)
+                    }
+                    Ok(false) => Err(clip_conflict()),
+                    Err(_) => Err(ApiResponse::error(
+                        ErrorCode::InternalError,
+                        "剪贴板删除失败",
+                    )),
+                }
+            }
+        };
+        match result {
+            Ok(value) => results.push(op_result(index, Ok(value))),
+            Err(error) => {
+                // 事务未提交，drop 时自动回滚
+                let code = error.code.unwrap_or(ErrorCode::InternalError);
+                return batch_failed("批量操作执行失败", code, index, error, total);
+            }
+        }
+    }
+    if let Err(e) = tx.commit().await {
+        warn!("commit batch failed: {}", e);
+        return ApiResponse::error(ErrorCode::InternalError, "批量操作失败");
+    }
+
+    // 提交后再通知其他设备
+    if settings_changed {
+        match settings::load_settings(user_id, pool).await {
+            Ok(settings) => app_state.room_manager.do_send(PushEvent {
+                user_id: user_id.to_string(),
+                event: "settings_changed".to_string(),
+                payload: Value::Object(settings),
+            }),
+            Err(e) => warn!("load settings after batch failed: {}", e),
+        }
+    }
+    for id in deduped_ids {
+        match db::get_clip(user_id, &id, pool).await {
+            Ok(Some(clip)) => clip_events.push((clip, "clip_updated")),
+            Ok(None) => {}
+            Err(e) => warn!("load clip {} after batch failed: {}", id, e),
+        }
+    }
+    for (clip, event) in clip_events {
+        let payload = match event {
+            "clip_deleted" => json!({ "id": clip.id }),
+            _ => json!(clip),
+        };
+        notify(user_id, &clip, event, payload, pool, app_state).await;
+    }
+
+    ApiResponse::new(
+        "批量操作成功",
+        ResponseData::Json(json!({ "committed": true, "results": results })),
+    )
+}
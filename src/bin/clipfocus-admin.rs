@@ -0,0 +1,349 @@
+// ClipFocus 命令行管理工具：与服务器读取相同的配置文件和环境变量，直接连接数据库，
+// 通过服务器的业务逻辑（UserService、Janitor 等）完成维护操作
+use dotenvy::dotenv;
+use serde_json::json;
+use std::collections::HashMap;
+use std::error::Error;
+use std::{env, fs, io, process};
+
+use clipfocus::blob_store;
+use clipfocus::config::Config;
+use clipfocus::logging::init_logging;
+use clipfocus::models::UserRole;
+use clipfocus::retention_api::janitor::Janitor;
+use clipfocus::spatial_api::models::RoomManager;
+use clipfocus::sqlx_utils::DbPool;
+use clipfocus::sqlx_utils::db::{self, init_pool};
+use clipfocus::sqlx_utils::models::ResponseData;
+use clipfocus::user_api::RegisterUser;
+use clipfocus::user_api::service::UserService;
+use clipfocus::user_api::storage::storage_report;
+
+const USAGE: &str = "\
+用法: clipfocus-admin <命令> [参数]
+
+命令:
+  create-user <用户名> <邮箱> [密码] [--role user|admin|readonly]
+      创建账号，不受注册开关限制，邮箱视为已验证
+  reset-password <用户名或邮箱> [新密码]
+      重置密码，该用户的所有设备需要重新登录
+  rotate-jwt-secret [--env-file <路径>]
+      生成新的 JWT_SECRET 写入 .env 文件（默认 ./.env），重启服务器后生效
+  migrate
+      执行数据库迁移
+  prune
+      立即执行一次清理：保留策略、过期剪贴板、回收站、分块上传和审计日志
+  vacuum
+      整理数据库文件，回收已删除数据占用的空间
+  stats [用户名或邮箱]
+      输出数据库统计，指定用户时输出该用户的存储占用
+
+省略密码时从标准输入读取一行，避免密码出现在命令历史中。
+配置与服务器相同：配置文件和 CLIPFOCUS_* 环境变量。";
+
+/// 解析后的命令
+#[derive(Debug, PartialEq)]
+enum Command {
+    CreateUser {
+        username: String,
+        email: String,
+        password: Option<String>,
+        role: UserRole,
+    },
+    ResetPassword {
+        user: String,
+        password: Option<String>,
+    },
+    RotateJwtSecret {
+        env_file: String,
+    },
+    Migrate,
+    Prune,
+    Vacuum,
+    Stats {
+        user: Option<String>,
+    },
+}
+
+impl Command {
+    fn parse(args: &[String]) -> Result<Command, String> {
+        let (name, rest) = args.split_first().ok_or("缺少命令")?;
+        let (positional, mut options) = split_args(rest)?;
+        let command = match (name.as_str(), positional.as_slice()) {
+            ("create-user", [username, email, password @ ..]) if password.len() <= 1 => {
+                let role = options.remove("--role").unwrap_or("user");
+                Command::CreateUser {
+                    username: username.to_string(),
+                    email: email.to_string(),
+                    password: password.first().map(|p| p.to_string()),
+                    role: serde_json::from_value(json!(role))
+                        .map_err(|_| format!("无效的角色 {}", role))?,
+                }
+            }
+            ("reset-password", [user, password @ ..]) if password.len() <= 1 => {
+                Command::ResetPassword {
+                    user: user.to_string(),
+                    password: password.first().map(|p| p.to_string()),
+                }
+            }
+            ("rotate-jwt-secret", []) => Command::RotateJwtSecret {
+                env_file: options.remove("--env-file").unwrap_or(".env").to_string(),
+            },
+            ("migrate", []) => Command::Migrate,
+            ("prune", []) => Command::Prune,
+            ("vacuum", []) => Command::Vacuum,
+            ("stats", []) => Command::Stats { user: None },
+            ("stats", [user]) => Command::Stats {
+                user: Some(user.to_string()),
+            },
+            _ => return Err(format!("无效的命令或参数: {}", args.join(" "))),
+        };
+        if let Some(option) = options.keys().next() {
+            return Err(format!("{} 不支持选项 {}", name, option));
+        }
+        Ok(command)
+    }
+}
+
+// 拆分位置参数和 `--名称 值` 形式的选项
+fn split_args(args: &[String]) -> Result<(Vec<&str>, HashMap<&str, &str>), String> {
+    let mut positional = Vec::new();
+    let mut options = HashMap::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg.starts_with("--") {
+            let value = args.next().ok_or(format!("选项 {} 缺少值", arg))?;
+            options.insert(arg.as_str(), value.as_str());
+        } else {
+            positional.push(arg.as_str());
+        }
+    }
+    Ok((positional, options))
+}
+
+#[actix_web::main]
+async fn main() {
+    dotenv().ok();
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() || matches!(args[0].as_str(), "help" | "-h" | "--help") {
+        println!("{}", USAGE);
+        return;
+    }
+    let command = match Command::parse(&args) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(e) = run(command).await {
+        eprintln!("错误: {}", e);
+        process::exit(1);
+    }
+}
+
+async fn run(command: Command) -> Result<(), Box<dyn Error>> {
+    match command {
+        Command::CreateUser {
+            username,
+            email,
+            password,
+            role,
+        } => {
+            let (config, pool) = connect().await?;
+            let user = RegisterUser {
+                username,
+                email,
+                password: password_or_stdin(password)?,
+                accepted_terms: HashMap::new(),
+            };
+            let user_id = UserService::new(pool, config.auth)
+                .create(&user, role)
+                .await
+                .map_err(|e| e.into_response("创建用户失败").message)?;
+            println!("已创建用户 {}（{}）", user.username, user_id);
+        }
+        Command::ResetPassword { user, password } => {
+            let (config, pool) = connect().await?;
+            let user_id = find_user(&user, &pool).await?;
+            UserService::new(pool, config.auth)
+                .reset_password(&user_id, &password_or_stdin(password)?)
+                .await
+                .map_err(|e| e.into_response("重置密码失败").message)?;
+            println!("已重置用户 {} 的密码，所有设备需要重新登录", user);
+        }
+        // 轮换密钥只修改 .env 文件，不需要连接数据库
+        Command::RotateJwtSecret { env_file } => rotate_jwt_secret(&env_file)?,
+        Command::Migrate => {
+            let (_, pool) = connect().await?;
+            let version = db::crate_db(&pool).await?;
+            println!("数据库已迁移到版本 {}", version);
+        }
+        Command::Prune => {
+            // 删除的剪贴板通过房间管理器通知设备（多节点部署时经集群转发）
+            let (config, pool) = connect().await?;
+            blob_store::install(&config.storage)?;
+            Janitor::new(
+                pool.clone(),
+                RoomManager::spawn(&config),
+                config.auth.audit_log_retention_days,
+                config.trash.retention_days,
+            )
+            .run_once()
+            .await;
+            println!("清理完成");
+        }
+        Command::Vacuum => {
+            let (_, pool) = connect().await?;
+            let before = database_bytes(&pool).await?;
+            db::vacuum(&pool).await?;
+            let after = database_bytes(&pool).await?;
+            println!("数据库整理完成：{} -> {} 字节", before, after);
+        }
+        Command::Stats { user: None } => {
+            let (_, pool) = connect().await?;
+            let stats = db::get_database_stats(chrono::Utc::now().timestamp(), &pool).await?;
+            println!("{}", serde_json::to_string_pretty(&stats)?);
+        }
+        Command::Stats { user: Some(user) } => {
+            let (config, pool) = connect().await?;
+            let user_id = find_user(&user, &pool).await?;
+            blob_store::install(&config.storage)?;
+            let report = storage_report(&user_id, &pool).await;
+            match report.data {
+                ResponseData::Json(data) if report.code.is_none() => {
+                    println!("{}", serde_json::to_string_pretty(&data)?)
+                }
+                _ => return Err(report.message.into()),
+            }
+        }
+    }
+    Ok(())
+}
+
+// 加载配置、初始化日志并连接数据库
+async fn connect() -> Result<(Config, DbPool), Box<dyn Error>> {
+    let config = Config::load()?;
+    init_logging(&config.logging);
+    let pool = init_pool(&config.database).await?;
+    Ok((config, pool))
+}
+
+// 按用户名或邮箱查找用户 ID
+async fn find_user(username_or_email: &str, pool: &DbPool) -> Result<String, String> {
+    db::get_user_by_username_or_email(username_or_email, pool)
+        .await
+        .map(|user| user.user_id)
+        .map_err(|_| format!("用户 {} 不存在", username_or_email))
+}
+
+// 数据库文件大小（字节）
+async fn database_bytes(pool: &DbPool) -> Result<i64, sqlx::Error> {
+    let stats = db::get_database_stats(chrono::Utc::now().timestamp(), pool).await?;
+    Ok(stats.db_bytes)
+}
+
+// 命令行未提供密码时从标准输入读取一行
+fn password_or_stdin(password: Option<String>) -> io::Result<String> {
+    if let Some(password) = password {
+        return Ok(password);
+    }
+    eprint!("密码: ");
+    let mut line = String::new();
+    io::stdin().read_line(&mut line)?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+// 生成新的 JWT_SECRET 并写入 env_file（文件不存在时创建）
+//
+// 已签发的访问令牌随之失效；刷新令牌保存在数据库中，不受影响，客户端刷新后即可继续使用
+fn rotate_jwt_secret(env_file: &str) -> Result<(), Box<dyn Error>> {
+    let contents = match fs::read_to_string(env_file) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let secret = hex::encode(rand::random::<[u8; 32]>());
+    fs::write(env_file, set_env_var(&contents, "JWT_SECRET", &secret))?;
+    println!("已将新的 JWT_SECRET 写入 {}，重启服务器后生效", env_file);
+    println!("已签发的访问令牌将失效，客户端使用刷新令牌重新获取即可");
+    println!("注意：环境变量中设置的 JWT_SECRET 优先于 .env 文件");
+    Ok(())
+}
+
+// 替换 .env 内容中 key 的值（包括 `export KEY=` 形式），不存在时追加到末尾
+fn set_env_var(contents: &str, key: &str, value: &str) -> String {
+    let line = format!("{}={}", key, value);
+    let mut found = false;
+    let mut lines: Vec<&str> = contents
+        .lines()
+        .map(|current| {
+            let name = current.trim_start();
+            let name = name.strip_prefix("export ").unwrap_or(name);
+            if name.split('=').next().map(str::trim) == Some(key) && name.contains('=') {
+                found = true;
+                line.as_str()
+            } else {
+                current
+            }
+        })
+        .collect();
+    if !found {
+        lines.push(&line);
+    }
+    let mut updated = lines.join("\n");
+    updated.push('\n');
+    updated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn parses_commands_and_options() {
+        assert_eq!(
+            Command::parse(&args("create-user alice a@example.com --role admin")),
+            Ok(Command::CreateUser {
+                username: "alice".to_string(),
+                email: "a@example.com".to_string(),
+                password: None,
+                role: UserRole::Admin,
+            })
+        );
+        assert_eq!(
+            Command::parse(&args("stats")),
+            Ok(Command::Stats { user: None })
+        );
+        assert!(Command::parse(&args("create-user alice a@example.com --role owner")).is_err());
+        assert!(Command::parse(&args("vacuum --role admin")).is_err());
+        assert!(Command::parse(&args("migrate extra")).is_err());
+        assert!(Command::parse(&args("rotate-jwt-secret --env-file")).is_err());
+    }
+
+    #[test]
+    fn set_env_var_replaces_or_appends() {
+        assert_eq!(
+            set_env_var(
+                "DATABASE_URL=sqlite://data.db\nJWT_SECRET=old\n",
+                "JWT_SECRET",
+                "new"
+            ),
+            "DATABASE_URL=sqlite://data.db\nJWT_SECRET=new\n"
+        );
+        assert_eq!(
+            set_env_var("export JWT_SECRET = old", "JWT_SECRET", "new"),
+            "JWT_SECRET=new\n"
+        );
+        assert_eq!(
+            set_env_var("DATABASE_URL=sqlite://data.db", "JWT_SECRET", "new"),
+            "DATABASE_URL=sqlite://data.db\nJWT_SECRET=new\n"
+        );
+        assert_eq!(set_env_var("", "JWT_SECRET", "new"), "JWT_SECRET=new\n");
+    }
+}
@@ -0,0 +1,293 @@
+use futures::future::{BoxFuture, FutureExt};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::UNIX_EPOCH;
+
+use crate::config::{StorageBackend, StorageConfig};
+use crate::job_queue::{Job, JobHandler};
+
+/// 本地存储的根目录，对象名为其中的相对路径
+pub const STATIC_DIR: &str = "./static";
+
+/// 对象的字节数和版本，内容改变时版本随之改变，用于生成 ETag
+#[derive(Debug, Clone)]
+pub struct BlobMeta {
+    pub size: u64,
+    pub version: String,
+}
+
+/// 附件（头像、二进制剪贴板文件）的存储接口，不同部署可替换具体实现
+///
+/// 对象名为 `/` 分隔的相对路径，如 `heads/{uuid}`、`clips/{user_id}/{clip_id}.png`；
+/// 读取不存在的对象时返回 `ErrorKind::NotFound` 错误
+pub trait BlobStore: Send + Sync {
+    fn put<'a>(&'a self, key: &'a str, data: Vec<u8>) -> BoxFuture<'a, io::Result<()>>;
+
+    /// 把本地临时文件移入存储，成功后临时文件不再存在
+    fn put_file<'a>(&'a self, key: &'a str, path: &'a Path) -> BoxFuture<'a, io::Result<()>>;
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, io::Result<Vec<u8>>>;
+
+    fn stat<'a>(&'a self, key: &'a str) -> BoxFuture<'a, io::Result<BlobMeta>>;
+
+    /// 删除对象，对象不存在时同样返回成功
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, io::Result<()>>;
+
+    /// 对象名以 prefix 开头的全部对象，返回 (对象名, 字节数)
+    fn list<'a>(&'a self, prefix: &'a str) -> BoxFuture<'a, io::Result<Vec<(String, u64)>>>;
+}
+
+/// 保存在本地目录的实现，单节点部署的默认选项
+pub struct LocalBlobStore {
+    root: PathBuf,
+}
+
+impl LocalBlobStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.root.join(key)
+    }
+
+    // 创建对象所在的目录
+    async fn create_parent(&self, key: &str) -> io::Result<PathBuf> {
+        let path = self.path(key);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        Ok(path)
+    }
+}
+
+impl BlobStore for LocalBlobStore {
+    fn put<'a>(&'a self, key: &'a str, data: Vec<u8>) -> BoxFuture<'a, io::Result<()>> {
+        async move {
+            let path = self.create_parent(key).await?;
+            tokio::fs::write(path, data).await
+        }
+        .boxed()
+    }
+
+    fn put_file<'a>(&'a self, key: &'a str, path: &'a Path) -> BoxFuture<'a, io::Result<()>> {
+        async move {
+            let target = self.create_parent(key).await?;
+            // 临时文件与存储目录不在同一文件系统时无法重命名，改为复制
+            if tokio::fs::rename(path, &target).await.is_err() {
+                tokio::fs::copy(path, &target).await?;
+                tokio::fs::remove_file(path).await?;
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, io::Result<Vec<u8>>> {
+        tokio::fs::read(self.path(key)).boxed()
+    }
+
+    fn stat<'a>(&'a self, key: &'a str) -> BoxFuture<'a, io::Result<BlobMeta>> {
+        async move {
+            let metadata = tokio::fs::metadata(self.path(key)).await?;
+            if !metadata.is_file() {
+                return Err(io::ErrorKind::NotFound.into());
+            }
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|duration| duration.as_nanos())
+                .unwrap_or_default();
+            Ok(BlobMeta {
+                size: metadata.len(),
+                version: format!("{:x}", modified),
+            })
+        }
+        .boxed()
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, io::Result<()>> {
+        async move {
+            match tokio::fs::remove_file(self.path(key)).await {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            }
+        }
+        .boxed()
+    }
+
+    fn list<'a>(&'a self, prefix: &'a str) -> BoxFuture<'a, io::Result<Vec<(String, u64)>>> {
+        async move {
+            // 从前缀中最后一个 / 之前的目录开始逐层遍历
+            let base = prefix.rfind('/').map_or("", |end| &prefix[..end]);
+            let mut objects = Vec::new();
+            let mut dirs = vec![base.to_string()];
+            while let Some(dir) = dirs.pop() {
+                let mut entries = match tokio::fs::read_dir(self.path(&dir)).await {
+                    Ok(entries) => entries,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                    Err(e) => return Err(e),
+                };
+                while let Some(entry) = entries.next_entry().await? {
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    let key = match dir.as_str() {
+                        "" => name,
+                        dir => format!("{}/{}", dir, name),
+                    };
+                    let metadata = entry.metadata().await?;
+                    if metadata.is_dir() {
+                        dirs.push(key);
+                    } else if metadata.is_file() && key.starts_with(prefix) {
+                        objects.push((key, metadata.len()));
+                    }
+                }
+            }
+            Ok(objects)
+        }
+        .boxed()
+    }
+}
+
+/// 保存在 S3 兼容存储（需要启用 `s3` feature），多节点共享同一个存储桶
+#[cfg(feature = "s3")]
+pub struct S3BlobStore {
+    config: crate::config::S3Config,
+}
+
+#[cfg(feature = "s3")]
+impl S3BlobStore {
+    pub fn new(config: crate::config::S3Config) -> Self {
+        Self { config }
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.config.prefix, key)
+    }
+}
+
+#[cfg(feature = "s3")]
+impl BlobStore for S3BlobStore {
+    fn put<'a>(&'a self, key: &'a str, data: Vec<u8>) -> BoxFuture<'a, io::Result<()>> {
+        async move {
+            crate::s3::put_object(&self.config, &self.key(key), data)
+                .await
+                .map_err(io::Error::other)
+        }
+        .boxed()
+    }
+
+    fn put_file<'a>(&'a self, key: &'a str, path: &'a Path) -> BoxFuture<'a, io::Result<()>> {
+        async move {
+            let data = tokio::fs::read(path).await?;
+            self.put(key, data).await?;
+            tokio::fs::remove_file(path).await
+        }
+        .boxed()
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, io::Result<Vec<u8>>> {
+        async move {
+            crate::s3::get_object(&self.config, &self.key(key))
+                .await
+                .map_err(io::Error::other)?
+                .ok_or_else(|| io::ErrorKind::NotFound.into())
+        }
+        .boxed()
+    }
+
+    fn stat<'a>(&'a self, key: &'a str) -> BoxFuture<'a, io::Result<BlobMeta>> {
+        async move {
+            let (size, etag) = crate::s3::head_object(&self.config, &self.key(key))
+                .await
+                .map_err(io::Error::other)?
+                .ok_or(io::ErrorKind::NotFound)?;
+            Ok(BlobMeta {
+                size,
+                version: etag,
+            })
+        }
+        .boxed()
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, io::Result<()>> {
+        async move {
+            crate::s3::delete_object(&self.config, &self.key(key))
+                .await
+                .map_err(io::Error::other)
+        }
+        .boxed()
+    }
+
+    fn list<'a>(&'a self, prefix: &'a str) -> BoxFuture<'a, io::Result<Vec<(String, u64)>>> {
+        async move {
+            let objects = crate::s3::list_objects(&self.config, &self.key(prefix))
+                .await
+                .map_err(io::Error::other)?;
+            Ok(objects
+                .into_iter()
+                .filter_map(|(key, size)| {
+                    let key = key.strip_prefix(&self.config.prefix)?.to_string();
+                    Some((key, size))
+                })
+                .collect())
+        }
+        .boxed()
+    }
+}
+
+static BLOB_STORE: OnceLock<Box<dyn BlobStore>> = OnceLock::new();
+
+// 按配置选择存储后端，只在启动时调用一次
+pub fn install(config: &StorageConfig) -> Result<(), String> {
+    let store: Box<dyn BlobStore> = match config.backend {
+        StorageBackend::Local => Box::new(LocalBlobStore::new(STATIC_DIR)),
+        #[cfg(feature = "s3")]
+        StorageBackend::S3 => match &config.s3 {
+            Some(s3) => Box::new(S3BlobStore::new(s3.clone())),
+            None => return Err("storage.backend is s3 but storage.s3 is not set".to_string()),
+        },
+        #[cfg(not(feature = "s3"))]
+        StorageBackend::S3 => {
+            return Err("storage.backend is s3 but the s3 feature is disabled".to_string());
+        }
+    };
+    BLOB_STORE
+        .set(store)
+        .map_err(|_| "blob store is already installed".to_string())
+}
+
+/// 当前使用的存储，未初始化时（如单元测试）为本地存储
+pub fn blob_store() -> &'static dyn BlobStore {
+    BLOB_STORE
+        .get_or_init(|| Box::new(LocalBlobStore::new(STATIC_DIR)))
+        .as_ref()
+}
+
+/// 后台任务类型：删除存储中的对象，payload 为 `{"keys": [...]}`
+pub const DELETE_BLOBS_JOB: &str = "delete_blobs";
+
+/// 删除对象的后台任务处理器，用于重试请求中删除失败的文件
+pub struct DeleteBlobs;
+
+impl JobHandler for DeleteBlobs {
+    fn run<'a>(&'a self, job: &'a Job) -> BoxFuture<'a, Result<(), String>> {
+        async move {
+            let keys: Vec<String> = serde_json::from_value(job.payload["keys"].clone())
+                .map_err(|e| format!("invalid payload: {}", e))?;
+            let mut failed = Vec::new();
+            for key in &keys {
+                if let Err(e) = blob_store().delete(key).await {
+                    failed.push(format!("{}: {}", key, e));
+                }
+            }
+            if failed.is_empty() {
+                Ok(())
+            } else {
+                Err(format!("delete failed: {}", failed.join("; ")))
+            }
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,436 @@
+use actix_web::{HttpResponse, Responder, get, http::header, post, web, web::Bytes};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chrono::Utc;
+use futures::{StreamExt, stream};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::path::Path;
+use uuid::Uuid;
+
+use crate::{
+    blob_store::blob_store,
+    clip_api::store_clip,
+    content_policy::PolicyViolation,
+    device_group_api::{DeviceGroup, Visibility},
+    models::{ClipFilter, ClipItem, SyncStatus, check_encryption, content_hash},
+    spatial_api::{
+        binary::{self, BINARY_CLIP_TYPES, save_clip_file},
+        models::{AppState, PushEvent},
+    },
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiEnvelope, ApiResponse, Cursor, ErrorCode, ResponseData},
+    },
+    user_api::auth::BearerToken,
+};
+
+/// 导出文件的格式名称和版本，导入时据此识别文件
+const EXPORT_FORMAT: &str = "clipfocus.clips";
+const EXPORT_VERSION: u32 = 1;
+
+/// 导出时每次从数据库读取的剪贴板数量
+const EXPORT_BATCH: i32 = 100;
+
+/// 导入文件单行的最大长度，附件经 Base64 编码后约为原大小的 4/3
+const MAX_IMPORT_LINE: usize = binary::MAX_FRAME_SIZE * 2;
+
+/// 导入结果中最多列出的失败行数
+const MAX_REPORTED_ERRORS: usize = 100;
+
+/// 导出文件的第一行
+#[derive(Serialize, Deserialize)]
+struct ExportHeader {
+    format: String,
+    version: u32,
+    exported_at: i64,
+}
+
+/// 导出文件中的剪贴板，每行一个
+#[derive(Serialize, Deserialize)]
+struct ExportedClip {
+    #[serde(flatten)]
+    clip: ClipItem,
+    /// 图片 / RTF 剪贴板的文件内容（Base64）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    attachment: Option<String>,
+}
+
+fn ndjson_line(value: &impl Serialize) -> Vec<u8> {
+    let mut line = serde_json::to_vec(value).unwrap_or_default();
+    line.push(b'\n');
+    line
+}
+
+// 导出全部剪贴板（NDJSON）：第一行为导出信息，之后每行一个剪贴板，图片 / RTF 附带文件内容
+//
+// 按创建时间从新到旧分批读取并边读边发送，导出大量剪贴板时不占用过多内存
+#[utoipa::path(
+    summary = "导出剪贴板",
+    security(("bearer" = [])),
+    responses((
+        status = 200,
+        description = "NDJSON，第一行为 format / version / exported_at，之后每行一个剪贴板",
+        content_type = "application/x-ndjson",
+        body = String,
+    )),
+)]
+#[get("/export")]
+pub async fn export_clips(pool: web::Data<DbPool>, bearer_token: BearerToken) -> HttpResponse {
+    let now = Utc::now();
+    let header = ExportHeader {
+        format: EXPORT_FORMAT.to_string(),
+        version: EXPORT_VERSION,
+        exported_at: now.timestamp(),
+    };
+    info!("用户 {} 导出剪贴板", bearer_token.user_id);
+
+    let first = stream::once(async move { Ok(Bytes::from(ndjson_line(&header))) });
+    let state = ExportState {
+        pool,
+        user_id: bearer_token.user_id,
+        cursor: None,
+        done: false,
+    };
+    let clips = stream::try_unfold(state, export_batch);
+    HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"clips-{}.ndjson\"",
+                now.format("%Y%m%d")
+            ),
+        ))
+        .streaming(first.chain(clips))
+}
+
+struct ExportState {
+    pool: web::Data<DbPool>,
+    user_id: String,
+    cursor: Option<Cursor>,
+    done: bool,
+}
+
+// 读取下一批剪贴板并编码为 NDJSON，读取失败时中断响应
+async fn export_batch(
+    mut state: ExportState,
+) -> Result<Option<(Bytes, ExportState)>, actix_web::Error> {
+    if state.done {
+        return Ok(None);
+    }
+    let filter = ClipFilter {
+        clip_type: None,
+        device_id: None,
+        tags: None,
+        start_date: None,
+        end_date: None,
+        search_text: None,
+        limit: Some(EXPORT_BATCH),
+        offset: None,
+        pinned_only: None,
+        tag_match: None,
+        cursor: state.cursor.take(),
+    };
+    let clips = db::get_clips(&state.user_id, None, &filter, &state.pool)
+        .await
+        .map_err(|e| {
+            warn!("export clips for {} failed: {}", state.user_id, e);
+            actix_web::error::ErrorInternalServerError("export failed")
+        })?;
+    if clips.is_empty() {
+        return Ok(None);
+    }
+    state.done = clips.len() < EXPORT_BATCH as usize;
+    state.cursor = clips.last().map(|clip| Cursor {
+        at: clip.created_at.timestamp_millis(),
+        id: clip.id.to_string(),
+    });
+
+    let mut lines = Vec::new();
+    for clip in clips {
+        let attachment = if BINARY_CLIP_TYPES.contains(&clip.content_type) {
+            match blob_store().get(&clip.content).await {
+                Ok(data) => Some(BASE64.encode(data)),
+                Err(e) => {
+                    warn!("read file of clip {} failed: {}", clip.id, e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        lines.extend(ndjson_line(&ExportedClip { clip, attachment }));
+    }
+    Ok(Some((Bytes::from(lines), state)))
+}
+
+// 导入 export 导出的文件（NDJSON），已有的剪贴板（ID 或内容相同）跳过
+//
+// 单行无效或超出配额时记录失败并继续导入其他行，data 为导入、跳过和失败的数量
+#[utoipa::path(
+    summary = "导入剪贴板",
+    request_body(content = String, content_type = "application/x-ndjson"),
+    security(("bearer" = [])),
+    responses((
+        status = 200,
+        description = "data 为 imported / skipped / failed 数量，errors 为失败的行（最多 100 条）",
+        body = ApiEnvelope<Value>,
+    )),
+)]
+#[post("/import")]
+pub async fn import_clips(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    mut payload: web::Payload,
+) -> impl Responder {
+    let user_id = &bearer_token.user_id;
+    let groups = match db::get_device_groups(user_id, &pool).await {
+        Ok(groups) => groups,
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "导入剪贴板失败"),
+    };
+    let mut importer = Importer {
+        user_id,
+        pool: &pool,
+        app_state: &app_state,
+        groups,
+        line: 0,
+        header_seen: false,
+        imported: 0,
+        skipped: 0,
+        errors: Vec::new(),
+        failed: 0,
+    };
+
+    // 按行处理请求体，只缓存未读完的一行
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut scanned = 0;
+    while let Some(chunk) = payload.next().await {
+        let Ok(chunk) = chunk else {
+            return ApiResponse::error(ErrorCode::InvalidRequest, "读取导入文件失败");
+        };
+        buffer.extend_from_slice(&chunk);
+        while let Some(offset) = buffer[scanned..].iter().position(|&b| b == b'\n') {
+            let end = scanned + offset;
+            if let Err(response) = importer.import_line(&buffer[..end]).await {
+                return response;
+            }
+            buffer.drain(..=end);
+            scanned = 0;
+        }
+        if buffer.len() > MAX_IMPORT_LINE {
+            return ApiResponse::error(
+                ErrorCode::InvalidRequest,
+                &format!("第 {} 行过长", importer.line + 1),
+            );
+        }
+        scanned = buffer.len();
+    }
+    if let Err(response) = importer.import_line(&buffer).await {
+        return response;
+    }
+    if !importer.header_seen {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "导入文件为空");
+    }
+    importer.finish()
+}
+
+struct Importer<'a> {
+    user_id: &'a str,
+    pool: &'a DbPool,
+    app_state: &'a AppState,
+    groups: Vec<DeviceGroup>,
+    /// 当前行号（从 1 开始）
+    line: usize,
+    header_seen: bool,
+    imported: usize,
+    skipped: usize,
+    failed: usize,
+    errors: Vec<Value>,
+}
+
+impl Importer<'_> {
+    // 处理一行，只有文件格式不正确时返回错误并中止导入
+    async fn import_line(&mut self, line: &[u8]) -> Result<(), ApiResponse> {
+        self.line += 1;
+        let line = line.trim_ascii();
+        if line.is_empty() {
+            return Ok(());
+        }
+        if !self.header_seen {
+            let supported = serde_json::from_slice::<ExportHeader>(line).is_ok_and(|header| {
+                header.format == EXPORT_FORMAT && header.version <= EXPORT_VERSION
+            });
+            if !supported {
+                return Err(ApiResponse::error(
+                    ErrorCode::InvalidRequest,
+                    "不是支持的剪贴板导出文件",
+                ));
+            }
+            self.header_seen = true;
+            return Ok(());
+        }
+
+        match self.import_clip(line).await {
+            Ok(true) => self.imported += 1,
+            Ok(false) => self.skipped += 1,
+            Err(error) => {
+                self.failed += 1;
+                if self.errors.len() < MAX_REPORTED_ERRORS {
+                    self.errors.push(json!({
+                        "line": self.line,
+                        "status": error.status().as_u16(),
+                        "error": error,
+                    }));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // 导入一个剪贴板，已存在时返回 false
+    async fn import_clip(&self, line: &[u8]) -> Result<bool, ApiResponse> {
+        let internal_error = |_| ApiResponse::error(ErrorCode::InternalError, "剪贴板导入失败");
+        let ExportedClip {
+            mut clip,
+            attachment,
+        } = serde_json::from_slice(line)
+            .map_err(|e| ApiResponse::error(ErrorCode::InvalidRequest, &e.to_string()))?;
+
+        // ID 相同的剪贴板已导入过；ID 被其他用户占用时换一个新 ID
+        match db::get_clip_owner(&clip.id, self.pool)
+            .await
+            .map_err(internal_error)?
+        {
+            Some(owner) if owner == self.user_id => return Ok(false),
+            Some(_) => clip.id = Uuid::new_v4(),
+            None => {}
+        }
+        if check_encryption(clip.key_id.as_deref(), clip.nonce.as_deref()).is_err() {
+            return Err(ApiResponse::error(
+                ErrorCode::InvalidRequest,
+                "key_id 和 nonce 必须同时提供",
+            ));
+        }
+        clip.encrypted = clip.key_id.is_some();
+        clip.sync_status = SyncStatus::Synced;
+        // 可见范围引用的分组不存在时（如从其他服务器导入）改为所有设备可见
+        if clip.visibility.validate(&self.groups).is_err() {
+            clip.visibility = Visibility::default();
+        }
+        // HTML 清理和敏感标记以本服务器的规则为准
+        self.app_state.content_policy.sanitize_clip(&mut clip);
+        self.app_state.content_policy.classify_clip(&mut clip);
+
+        if BINARY_CLIP_TYPES.contains(&clip.content_type) {
+            return self.import_file_clip(clip, attachment).await;
+        }
+        let policy = &self.app_state.content_policy;
+        let checked = if clip.encrypted {
+            policy.check_encrypted_clip(clip.content_type.as_str(), clip.content.len())
+        } else {
+            policy.check_clip(clip.content_type.as_str(), &clip.content)
+        };
+        checked.map_err(policy_violation)?;
+        clip.size = clip.content.len() as i64;
+        let hash = content_hash(clip.content_type, &clip.content);
+        if db::has_clip_content(self.user_id, &hash, self.pool)
+            .await
+            .map_err(internal_error)?
+        {
+            return Ok(false);
+        }
+        self.store(clip, &hash).await
+    }
+
+    // 图片 / RTF 剪贴板：保存附带的文件，文件路径改为本服务器的路径
+    async fn import_file_clip(
+        &self,
+        mut clip: ClipItem,
+        attachment: Option<String>,
+    ) -> Result<bool, ApiResponse> {
+        let data = attachment
+            .and_then(|attachment| BASE64.decode(attachment).ok())
+            .ok_or_else(|| ApiResponse::error(ErrorCode::InvalidRequest, "缺少剪贴板文件内容"))?;
+        let extension = Path::new(&clip.content)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or("");
+        let mime_type = binary::mime_for(extension);
+        self.app_state
+            .content_policy
+            .check_blob(clip.content_type.as_str(), mime_type, data.len())
+            .map_err(policy_violation)?;
+        // 加密的剪贴板为密文，无法识别文件类型
+        if !clip.encrypted {
+            binary::check_mime(mime_type, binary::sniff_mime(&data)).map_err(policy_violation)?;
+        }
+
+        clip.content = save_clip_file(
+            self.user_id,
+            &clip.id,
+            binary::extension_for(mime_type),
+            &data,
+        )
+        .await
+        .map_err(|_| ApiResponse::error(ErrorCode::InternalError, "剪贴板文件保存失败"))?;
+        clip.size = data.len() as i64;
+        // 文件路径唯一，二进制剪贴板不参与按内容去重
+        let hash = content_hash(clip.content_type, &clip.content);
+        let key = clip.content.clone();
+        let stored = self.store(clip, &hash).await;
+        if !matches!(stored, Ok(true)) {
+            let _ = blob_store().delete(&key).await;
+        }
+        stored
+    }
+
+    // 检查配额并写入剪贴板，保留导出时的固定状态
+    async fn store(&self, clip: ClipItem, hash: &str) -> Result<bool, ApiResponse> {
+        let pinned = clip.pinned;
+        let (clip, deduped) = store_clip(self.user_id, clip, hash, self.pool).await?;
+        if deduped {
+            return Ok(false);
+        }
+        if pinned
+            && let Err(e) =
+                db::set_clip_pinned(self.user_id, &clip.id, true, None, None, self.pool).await
+        {
+            warn!("pin imported clip {} failed: {}", clip.id, e);
+        }
+        Ok(true)
+    }
+
+    // 通知其他设备重新同步，返回导入结果
+    fn finish(self) -> ApiResponse {
+        info!(
+            "用户 {} 导入剪贴板：导入 {}，跳过 {}，失败 {}",
+            self.user_id, self.imported, self.skipped, self.failed
+        );
+        let summary = json!({
+            "imported": self.imported,
+            "skipped": self.skipped,
+            "failed": self.failed,
+        });
+        if self.imported > 0 {
+            self.app_state.room_manager.do_send(PushEvent {
+                user_id: self.user_id.to_string(),
+                event: "clips_imported".to_string(),
+                payload: summary.clone(),
+            });
+        }
+        let mut data = summary;
+        data["errors"] = json!(self.errors);
+        ApiResponse::new("剪贴板导入完成", ResponseData::Json(data))
+    }
+}
+
+fn policy_violation(violation: PolicyViolation) -> ApiResponse {
+    ApiResponse::error_with_data(
+        ErrorCode::PolicyViolation,
+        "内容不符合服务器策略",
+        ResponseData::Json(json!(violation)),
+    )
+}
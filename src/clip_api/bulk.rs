@@ -0,0 +1,331 @@
+use actix_web::{HttpRequest, Responder, post, web};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    blob_store::blob_store,
+    clip_api::{normalize_tags, notify},
+    device_group_api::DeviceId,
+    idempotency::{self, Idempotency, IdempotencyKey},
+    models::ClipItem,
+    spatial_api::{binary::BINARY_CLIP_TYPES, models::AppState},
+    sqlx_utils::{
+        Db, DbPool, db,
+        models::{ApiEnvelope, ApiResponse, ErrorCode, ResponseData},
+    },
+    user_api::auth::BearerToken,
+};
+
+/// 单次批量请求允许的最大操作数
+const MAX_BULK_OPERATIONS: usize = 500;
+
+/// 剪贴板批量操作，通过 `op` 字段区分类型
+///
+/// 提供 version 时与当前版本号一致才执行（对应 If-Match），不提供时不检查版本
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BulkOperation {
+    /// 彻底删除剪贴板（包括回收站中的），不能恢复
+    Delete { id: Uuid, version: Option<i64> },
+    /// 移到回收站
+    MoveToTrash { id: Uuid, version: Option<i64> },
+    /// 添加和移除标签，同一标签同时出现在两者中时以移除为准
+    Tag {
+        id: Uuid,
+        version: Option<i64>,
+        #[serde(default)]
+        add: Vec<String>,
+        #[serde(default)]
+        remove: Vec<String>,
+    },
+    /// 固定或取消固定
+    Pin {
+        id: Uuid,
+        version: Option<i64>,
+        pinned: bool,
+    },
+}
+
+impl BulkOperation {
+    fn id(&self) -> Uuid {
+        match self {
+            BulkOperation::Delete { id, .. }
+            | BulkOperation::MoveToTrash { id, .. }
+            | BulkOperation::Tag { id, .. }
+            | BulkOperation::Pin { id, .. } => *id,
+        }
+    }
+}
+
+/// 剪贴板批量操作请求
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BulkRequest {
+    pub operations: Vec<BulkOperation>,
+}
+
+/// 单项操作的结果
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BulkItemResult {
+    pub index: usize,
+    pub id: Uuid,
+    pub ok: bool,
+    /// 成功后剪贴板的版本号，彻底删除时为空
+    pub version: Option<i64>,
+    /// 失败原因：NOT_FOUND（不存在、不可见或状态不符）、VERSION_CONFLICT（版本号不一致）
+    pub error: Option<ErrorCode>,
+}
+
+/// 剪贴板批量操作的结果，results 与请求中的操作一一对应
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BulkResults {
+    pub succeeded: usize,
+    pub failed: usize,
+    pub results: Vec<BulkItemResult>,
+}
+
+/// 单项操作成功后，提交事务时需要执行的后续处理
+enum Applied {
+    /// 剪贴板被彻底删除，仍在使用中时需要通知设备，二进制剪贴板需要删除文件
+    Purged {
+        clip: ClipItem,
+        was_live: bool,
+    },
+    Trashed(ClipItem),
+    Updated(ClipItem),
+}
+
+// 批量处理剪贴板：彻底删除、移到回收站、修改标签、固定
+//
+// 所有操作按顺序在同一个事务中执行，单项失败（剪贴板不存在、版本号不一致）只记录在该项的结果中，
+// 不影响其他操作；数据库出错时全部回滚
+#[utoipa::path(
+    summary = "批量处理剪贴板",
+    params(DeviceId, IdempotencyKey),
+    request_body = BulkRequest,
+    security(("bearer" = [])),
+    responses((status = 200, body = ApiEnvelope<BulkResults>)),
+)]
+#[post("/bulk")]
+pub async fn bulk_clips(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    device_id: DeviceId,
+    idempotency_key: IdempotencyKey,
+    req: HttpRequest,
+    bulk: web::Json<BulkRequest>,
+) -> impl Responder {
+    let body = serde_json::to_vec(&*bulk).unwrap_or_default();
+    let fingerprint = idempotency::fingerprint(&req, &body);
+    match idempotency_key
+        .begin(&bearer_token.user_id, &fingerprint, &pool)
+        .await
+    {
+        Ok(Idempotency::Proceed) => {}
+        Ok(Idempotency::Replay(response)) | Err(response) => return response,
+    }
+
+    let response = execute_bulk(
+        &pool,
+        &app_state,
+        &bearer_token.user_id,
+        &device_id,
+        bulk.into_inner(),
+    )
+    .await;
+    idempotency_key
+        .finish(&bearer_token.user_id, response, &pool)
+        .await
+}
+
+async fn execute_bulk(
+    pool: &DbPool,
+    app_state: &AppState,
+    user_id: &str,
+    device_id: &DeviceId,
+    bulk: BulkRequest,
+) -> ApiResponse {
+    let total = bulk.operations.len();
+    if total == 0 || total > MAX_BULK_OPERATIONS {
+        return ApiResponse::error(
+            ErrorCode::InvalidRequest,
+            &format!("操作数量必须在 1 到 {} 之间", MAX_BULK_OPERATIONS),
+        );
+    }
+    let internal_error = || ApiResponse::error(ErrorCode::InternalError, "批量处理剪贴板失败");
+    let groups = match db::get_device_groups(user_id, pool).await {
+        Ok(groups) => groups,
+        Err(_) => return internal_error(),
+    };
+
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(_) => return internal_error(),
+    };
+    let mut results = Vec::with_capacity(total);
+    let mut applied = Vec::new();
+    for (index, operation) in bulk.operations.into_iter().enumerate() {
+        let id = operation.id();
+        // 请求设备不可见的剪贴板与不存在相同
+        let clip = match db::get_clip_with_trash(user_id, &id, &mut tx).await {
+            Ok(clip) => {
+                clip.filter(|(clip, _)| clip.visibility.allows(device_id.0.as_deref(), &groups))
+            }
+            Err(e) => {
+                warn!("load clip {} for bulk operation failed: {}", id, e);
+                return internal_error();
+            }
+        };
+        let outcome = match clip {
+            Some((clip, deleted_at)) => {
+                apply(user_id, device_id, operation, clip, deleted_at, &mut tx).await
+            }
+            None => Ok(Err(ErrorCode::NotFound)),
+        };
+        let result = match outcome {
+            Ok(Ok(done)) => {
+                let version = match &done {
+                    Applied::Purged { .. } => None,
+                    Applied::Trashed(clip) | Applied::Updated(clip) => Some(clip.version),
+                };
+                applied.push(done);
+                Ok(version)
+            }
+            Ok(Err(code)) => Err(code),
+            Err(e) => {
+                // 事务未提交，drop 时自动回滚
+                warn!("bulk operation on clip {} failed: {}", id, e);
+                return internal_error();
+            }
+        };
+        results.push(BulkItemResult {
+            index,
+            id,
+            ok: result.is_ok(),
+            version: result.ok().flatten(),
+            error: result.err(),
+        });
+    }
+    if let Err(e) = tx.commit().await {
+        warn!("commit bulk operations failed: {}", e);
+        return internal_error();
+    }
+
+    let succeeded = applied.len();
+    info!(
+        "用户 {} 批量处理剪贴板，成功 {} 项，共 {} 项",
+        user_id, succeeded, total
+    );
+    // 提交后再删除文件并通知其他设备
+    for done in applied {
+        match done {
+            Applied::Purged { clip, was_live } => {
+                if BINARY_CLIP_TYPES.contains(&clip.content_type)
+                    && let Err(e) = blob_store().delete(&clip.content).await
+                {
+                    warn!("remove {} failed: {}", clip.content, e);
+                }
+                if was_live {
+                    let payload = json!({ "id": clip.id });
+                    notify(user_id, &clip, "clip_deleted", payload, pool, app_state).await;
+                }
+            }
+            Applied::Trashed(clip) => {
+                let payload = json!({ "id": clip.id });
+                notify(user_id, &clip, "clip_deleted", payload, pool, app_state).await;
+            }
+            Applied::Updated(clip) => {
+                notify(user_id, &clip, "clip_updated", json!(clip), pool, app_state).await;
+            }
+        }
+    }
+
+    let results = BulkResults {
+        succeeded,
+        failed: total - succeeded,
+        results,
+    };
+    ApiResponse::new("批量处理剪贴板完成", ResponseData::Json(json!(results)))
+}
+
+// 在事务中执行单项操作，外层错误为数据库错误，内层错误为该项失败的原因
+async fn apply(
+    user_id: &str,
+    device_id: &DeviceId,
+    operation: BulkOperation,
+    mut clip: ClipItem,
+    deleted_at: Option<i64>,
+    tx: &mut sqlx::Transaction<'_, Db>,
+) -> Result<Result<Applied, ErrorCode>, sqlx::Error> {
+    let current = clip.version;
+    let stale = |version: Option<i64>| version.is_some_and(|version| version != current);
+    let in_trash = deleted_at.is_some();
+    let modified_by = device_id.0.as_deref();
+    Ok(match operation {
+        BulkOperation::Delete { version, .. } => {
+            if stale(version) {
+                Err(ErrorCode::VersionConflict)
+            } else if db::write_clip_purge(user_id, &clip.id, version, tx).await? {
+                Ok(Applied::Purged {
+                    clip,
+                    was_live: !in_trash,
+                })
+            } else {
+                Err(ErrorCode::VersionConflict)
+            }
+        }
+        _ if in_trash => Err(ErrorCode::NotFound),
+        BulkOperation::MoveToTrash { version, .. } => {
+            if stale(version) {
+                Err(ErrorCode::VersionConflict)
+            } else if db::write_clip_delete(user_id, &clip.id, version, tx).await? {
+                clip.version += 1;
+                Ok(Applied::Trashed(clip))
+            } else {
+                Err(ErrorCode::VersionConflict)
+            }
+        }
+        BulkOperation::Tag {
+            version,
+            add,
+            remove,
+            ..
+        } => {
+            if stale(version) {
+                return Ok(Err(ErrorCode::VersionConflict));
+            }
+            let remove = normalize_tags(remove);
+            let mut tags = clip.tags.clone();
+            tags.extend(add);
+            clip.tags = normalize_tags(tags);
+            clip.tags.retain(|tag| !remove.contains(tag));
+            match db::write_clip_update(user_id, &clip, version, modified_by, tx).await? {
+                Some(version) => {
+                    clip.version = version;
+                    Ok(Applied::Updated(clip))
+                }
+                None => Err(ErrorCode::VersionConflict),
+            }
+        }
+        BulkOperation::Pin {
+            version, pinned, ..
+        } => {
+            if stale(version) {
+                return Ok(Err(ErrorCode::VersionConflict));
+            }
+            match db::set_clip_pinned(user_id, &clip.id, pinned, version, modified_by, &mut *tx)
+                .await?
+            {
+                Some(version) => {
+                    clip.version = version;
+                    clip.pinned = pinned;
+                    Ok(Applied::Updated(clip))
+                }
+                None => Err(ErrorCode::VersionConflict),
+            }
+        }
+    })
+}
@@ -0,0 +1,924 @@
+use actix_web::{HttpRequest, Responder, delete, get, post, put, web};
+use chrono::{DateTime, SubsecRound, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::path::Path;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use uuid::Uuid;
+
+use crate::{
+    blob_store::blob_store,
+    clip_api::service::{ClipService, find_visible, stale_clip},
+    concurrency::{IfMatch, Versioned},
+    contact_api::ensure_not_blocked,
+    device_group_api::{DeviceId, Visibility, push_visible},
+    idempotency::{self, Idempotency, IdempotencyKey},
+    models::{
+        ClipChange, ClipFilter, ClipItem, ClipSearchHit, ClipSource, ClipType, CreateClipRequest,
+        SyncStatus, TagMatch, TrashedClip, UpdateClipRequest, check_encryption, check_expiry,
+        content_hash,
+    },
+    share_api::create_share,
+    spatial_api::{
+        binary::{BINARY_CLIP_TYPES, save_clip_file},
+        models::{AppState, PushEvent},
+    },
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiEnvelope, ApiResponse, Cursor, ErrorCode, Page, ResponseData},
+    },
+    sync_api::{self, ResolveConflict, SyncConflict},
+    user_api::{app_rules::load_app_rules, auth::BearerToken, quota::check_quota},
+    webhook_api::{self, WebhookEvent},
+};
+
+pub(crate) mod archive;
+pub(crate) mod bulk;
+pub mod revisions;
+pub mod service;
+
+pub fn clip_api() -> actix_web::Scope {
+    web::scope("/clips")
+        .service(create_clip)
+        .service(list_clips)
+        .service(search_clips)
+        .service(list_changes)
+        .service(list_trash)
+        .service(archive::export_clips)
+        .service(archive::import_clips)
+        .service(bulk::bulk_clips)
+        .service(get_clip)
+        .service(update_clip)
+        .service(delete_clip)
+        .service(restore_clip)
+        .service(pin_clip)
+        .service(unpin_clip)
+        .service(resolve_clip_conflict)
+        .service(revisions::list_revisions)
+        .service(revisions::restore_revision)
+        .service(create_share)
+        .service(send_to_contact)
+}
+
+/// 剪贴板接口的 OpenAPI 文档
+#[derive(OpenApi)]
+#[openapi(paths(
+    create_clip,
+    list_clips,
+    search_clips,
+    list_changes,
+    list_trash,
+    archive::export_clips,
+    archive::import_clips,
+    bulk::bulk_clips,
+    get_clip,
+    update_clip,
+    resolve_clip_conflict,
+    revisions::list_revisions,
+    revisions::restore_revision,
+    delete_clip,
+    restore_clip,
+    pin_clip,
+    unpin_clip,
+    send_to_contact,
+))]
+pub struct ClipApiDoc;
+
+/// 未提供预览时截取的字符数
+pub(crate) const PREVIEW_CHARS: usize = 200;
+
+/// 单次查询返回的默认 / 最大数量
+const DEFAULT_LIST_LIMIT: i32 = 50;
+const MAX_LIST_LIMIT: i32 = 500;
+
+/// 全文搜索每页的默认 / 最大数量
+const DEFAULT_SEARCH_LIMIT: i32 = 20;
+const MAX_SEARCH_LIMIT: i32 = 100;
+
+/// 增量同步每次返回的默认 / 最大变更数量
+const DEFAULT_CHANGES_LIMIT: i32 = 500;
+const MAX_CHANGES_LIMIT: i32 = 1000;
+
+/// 全文索引使用 trigram 分词，查询至少需要 3 个字符
+const MIN_SEARCH_CHARS: usize = 3;
+
+// 去掉空白和重复的标签，按名称排序（与查询时返回的顺序一致）
+pub(crate) fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut tags: Vec<String> = tags
+        .into_iter()
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+// 创建剪贴板项目
+#[utoipa::path(
+    summary = "创建剪贴板",
+    params(IdempotencyKey),
+    security(("bearer" = [])),
+    responses((
+        status = 200,
+        description = "data 为剪贴板，deduped 为 true 时表示内容重复，返回的是已有的剪贴板",
+        body = ApiEnvelope<ClipItem>,
+    )),
+)]
+#[post("")]
+async fn create_clip(
+    pool: web::Data<DbPool>,
+    clips: web::Data<ClipService>,
+    bearer_token: BearerToken,
+    idempotency_key: IdempotencyKey,
+    req: HttpRequest,
+    create: web::Json<CreateClipRequest>,
+) -> impl Responder {
+    let body = serde_json::to_vec(&*create).unwrap_or_default();
+    let fingerprint = idempotency::fingerprint(&req, &body);
+    match idempotency_key
+        .begin(&bearer_token.user_id, &fingerprint, &pool)
+        .await
+    {
+        Ok(Idempotency::Proceed) => {}
+        Ok(Idempotency::Replay(response)) | Err(response) => return response,
+    }
+
+    let response = save_clip(&clips, &bearer_token.user_id, create.into_inner()).await;
+    idempotency_key
+        .finish(&bearer_token.user_id, response, &pool)
+        .await
+}
+
+async fn save_clip(clips: &ClipService, user_id: &str, create: CreateClipRequest) -> ApiResponse {
+    let (clip, deduped) = match clips.create(user_id, create).await {
+        Ok(added) => added,
+        Err(e) => return e.into(),
+    };
+    let message = if deduped {
+        "剪贴板已存在"
+    } else {
+        "剪贴板保存成功"
+    };
+    let mut value = json!(clip);
+    value["deduped"] = json!(deduped);
+    ApiResponse::new(message, ResponseData::Json(value))
+}
+
+// 在同一事务中检查存储配额并写入剪贴板，返回保存后的剪贴板
+//
+// 已有相同内容时返回移到最前的已有剪贴板，第二项为 true
+pub(crate) async fn store_clip(
+    user_id: &str,
+    clip: ClipItem,
+    hash: &str,
+    pool: &DbPool,
+) -> Result<(ClipItem, bool), ApiResponse> {
+    let internal_error = |_| ApiResponse::error(ErrorCode::InternalError, "剪贴板保存失败");
+    let mut tx = pool.begin().await.map_err(internal_error)?;
+    check_quota(user_id, clip.size, &mut tx).await?;
+    let deduped = db::write_clip_insert(user_id, &clip, hash, &mut tx)
+        .await
+        .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    match deduped {
+        Some(id) => db::get_clip(user_id, &id, pool)
+            .await
+            .map_err(internal_error)?
+            .map(|clip| (clip, true))
+            .ok_or_else(|| ApiResponse::error(ErrorCode::InternalError, "剪贴板保存失败")),
+        None => Ok((clip, false)),
+    }
+}
+
+// 校验创建请求，生成剪贴板和内容哈希（不检查存储配额）
+pub(crate) async fn prepare_clip(
+    pool: &DbPool,
+    app_state: &AppState,
+    user_id: &str,
+    create: CreateClipRequest,
+) -> Result<(ClipItem, String), ApiResponse> {
+    // 端到端加密：content 为密文，服务器不生成预览，也不检查内容
+    if check_encryption(create.key_id.as_deref(), create.nonce.as_deref()).is_err() {
+        return Err(ApiResponse::error(
+            ErrorCode::InvalidRequest,
+            "key_id 和 nonce 必须同时提供",
+        ));
+    }
+    if check_expiry(create.expires_at).is_err() {
+        return Err(ApiResponse::error(
+            ErrorCode::InvalidRequest,
+            "expires_at 必须晚于当前时间",
+        ));
+    }
+    let encrypted = create.key_id.is_some();
+    if encrypted && create.preview.is_some() {
+        return Err(ApiResponse::error(
+            ErrorCode::InvalidRequest,
+            "加密的剪贴板不能包含明文预览",
+        ));
+    }
+    let checked = if encrypted {
+        app_state
+            .content_policy
+            .check_encrypted_clip(create.content_type.as_str(), create.content.len())
+    } else {
+        app_state
+            .content_policy
+            .check_clip(create.content_type.as_str(), &create.content)
+    };
+    if let Err(violation) = checked {
+        return Err(ApiResponse::error_with_data(
+            ErrorCode::PolicyViolation,
+            "内容不符合服务器策略",
+            ResponseData::Json(json!(violation)),
+        ));
+    }
+    // 剪贴板必须来自已注册且未注销的设备
+    let internal_error = |_| ApiResponse::error(ErrorCode::InternalError, "剪贴板保存失败");
+    db::get_active_device(user_id, &create.device_id.to_string(), pool)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(|| ApiResponse::error(ErrorCode::InvalidRequest, "设备未注册或已注销"))?;
+    let groups = db::get_device_groups(user_id, pool)
+        .await
+        .map_err(internal_error)?;
+    create.visibility.validate(&groups)?;
+    let size = create.content.len() as i64;
+
+    // 数据库中保存毫秒时间戳，返回值与之后查询的结果保持一致
+    let now = Utc::now().trunc_subsecs(3);
+    let mut clip = ClipItem {
+        id: Uuid::new_v4(),
+        device_id: create.device_id,
+        content_type: create.content_type,
+        preview: match create.preview {
+            Some(preview) => preview,
+            None if encrypted => String::new(),
+            None => create.content.chars().take(PREVIEW_CHARS).collect(),
+        },
+        content: create.content,
+        size,
+        source_app: create.source_app,
+        created_at: now,
+        accessed_at: now,
+        sync_status: SyncStatus::Synced,
+        encrypted,
+        tags: normalize_tags(create.tags.unwrap_or_default()),
+        visibility: create.visibility,
+        key_id: create.key_id,
+        nonce: create.nonce,
+        version: 1,
+        pinned: false,
+        source: ClipSource::Own,
+        shared_by: None,
+        expires_at: create.expires_at.map(|at| at.trunc_subsecs(3)),
+        sensitive: None,
+    };
+    // 按用户的应用规则屏蔽或添加标签
+    let app_rules = load_app_rules(user_id, pool)
+        .await
+        .map_err(internal_error)?;
+    if let Err(violation) = app_rules.apply(&mut clip) {
+        return Err(ApiResponse::error_with_data(
+            ErrorCode::PolicyViolation,
+            "来源应用已被应用规则屏蔽",
+            ResponseData::Json(json!(violation)),
+        ));
+    }
+    app_state.content_policy.sanitize_clip(&mut clip);
+    app_state.content_policy.classify_clip(&mut clip);
+    let hash = content_hash(clip.content_type, &clip.content);
+    Ok((clip, hash))
+}
+
+// 按剪贴板的可见范围通知用户的其他设备，新建和删除时同时触发 Webhook
+pub(crate) async fn notify(
+    user_id: &str,
+    clip: &ClipItem,
+    event: &str,
+    payload: serde_json::Value,
+    pool: &DbPool,
+    app_state: &AppState,
+) {
+    if let Some(webhook_event) = WebhookEvent::for_clip_event(event) {
+        webhook_api::enqueue(user_id, webhook_event, payload.clone(), pool).await;
+    }
+    if let Err(e) = push_visible(
+        user_id,
+        &clip.visibility,
+        event,
+        payload,
+        pool,
+        &app_state.room_manager,
+    )
+    .await
+    {
+        warn!("push {} for clip {} failed: {}", event, clip.id, e);
+    }
+}
+
+// 查询参数（标签以逗号分隔，tag_match 为 all 时需同时包含所有标签，为 any 时包含任一标签）
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ClipQuery {
+    pub clip_type: Option<ClipType>,
+    pub device_id: Option<Uuid>,
+    pub tags: Option<String>,
+    pub start_date: Option<DateTime<Utc>>,
+    pub end_date: Option<DateTime<Utc>>,
+    pub search_text: Option<String>,
+    pub limit: Option<i32>,
+    /// 已废弃，请使用 cursor
+    pub offset: Option<i32>,
+    pub pinned_only: Option<bool>,
+    pub tag_match: Option<TagMatch>,
+    /// 上一页返回的 next_cursor
+    #[param(value_type = Option<String>)]
+    pub cursor: Option<Cursor>,
+}
+
+impl From<ClipQuery> for ClipFilter {
+    fn from(query: ClipQuery) -> Self {
+        ClipFilter {
+            clip_type: query.clip_type,
+            device_id: query.device_id,
+            tags: query.tags.map(|tags| {
+                tags.split(',')
+                    .map(|tag| tag.trim().to_string())
+                    .filter(|tag| !tag.is_empty())
+                    .collect()
+            }),
+            start_date: query.start_date,
+            end_date: query.end_date,
+            search_text: query.search_text,
+            limit: query.limit,
+            offset: query.offset,
+            pinned_only: query.pinned_only,
+            tag_match: query.tag_match,
+            cursor: query.cursor,
+        }
+    }
+}
+
+// 查询剪贴板列表（只返回请求设备可见的项目，按创建时间倒序），按 next_cursor 翻页
+#[utoipa::path(
+    summary = "查询剪贴板列表",
+    params(DeviceId, ClipQuery),
+    security(("bearer" = [])),
+    responses((status = 200, body = ApiEnvelope<Page<ClipItem>>)),
+)]
+#[get("")]
+async fn list_clips(
+    clips: web::Data<ClipService>,
+    bearer_token: BearerToken,
+    device_id: DeviceId,
+    query: web::Query<ClipQuery>,
+) -> impl Responder {
+    let filter = ClipFilter::from(query.into_inner());
+    match clips
+        .list(&bearer_token.user_id, device_id.0.as_deref(), filter)
+        .await
+    {
+        Ok(page) => ApiResponse::new("获取剪贴板成功", ResponseData::Json(json!(page))),
+        Err(e) => e.into(),
+    }
+}
+
+// 全文搜索参数
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchQuery {
+    pub q: String,
+    pub clip_type: Option<ClipType>,
+    pub limit: Option<i32>,
+    pub offset: Option<i32>,
+}
+
+/// 全文搜索结果（一页）
+#[derive(Serialize, ToSchema)]
+pub struct SearchResults {
+    /// 匹配的总数
+    pub total: i64,
+    pub limit: i32,
+    pub offset: i32,
+    pub results: Vec<ClipSearchHit>,
+}
+
+// 全文搜索剪贴板内容和预览（按相关度排序，只返回请求设备可见的项目）
+#[utoipa::path(
+    summary = "全文搜索剪贴板",
+    params(DeviceId, SearchQuery),
+    security(("bearer" = [])),
+    responses((status = 200, body = ApiEnvelope<SearchResults>)),
+)]
+#[get("/search")]
+async fn search_clips(
+    pool: web::Data<DbPool>,
+    bearer_token: BearerToken,
+    device_id: DeviceId,
+    query: web::Query<SearchQuery>,
+) -> impl Responder {
+    let query = query.into_inner();
+    let text = query.q.trim();
+    if text.chars().count() < MIN_SEARCH_CHARS {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "搜索内容至少需要 3 个字符");
+    }
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_SEARCH_LIMIT)
+        .clamp(1, MAX_SEARCH_LIMIT);
+    let offset = query.offset.unwrap_or(0).max(0);
+
+    match db::search_clips(
+        &bearer_token.user_id,
+        device_id.0.as_deref(),
+        text,
+        query.clip_type,
+        limit,
+        offset,
+        &pool,
+    )
+    .await
+    {
+        Ok((total, hits)) => {
+            let results = SearchResults {
+                total,
+                limit,
+                offset,
+                results: hits,
+            };
+            ApiResponse::new("搜索剪贴板成功", ResponseData::Json(json!(results)))
+        }
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "搜索剪贴板失败"),
+    }
+}
+
+// 增量同步参数，since 为上次同步返回的 cursor，首次同步为 0
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ChangesQuery {
+    #[serde(default)]
+    pub since: i64,
+    pub limit: Option<i32>,
+}
+
+/// 增量同步结果
+#[derive(Serialize, ToSchema)]
+pub struct ClipChanges {
+    /// 下次请求使用的 since
+    pub cursor: i64,
+    pub has_more: bool,
+    pub changes: Vec<ClipChange>,
+}
+
+// 获取 since 之后的剪贴板变更（离线设备重新上线后据此与服务器对齐）
+//
+// has_more 为 true 时用返回的 cursor 继续请求，直到 has_more 为 false
+#[utoipa::path(
+    summary = "获取剪贴板变更",
+    params(DeviceId, ChangesQuery),
+    security(("bearer" = [])),
+    responses((status = 200, body = ApiEnvelope<ClipChanges>)),
+)]
+#[get("/changes")]
+async fn list_changes(
+    clips: web::Data<ClipService>,
+    bearer_token: BearerToken,
+    device_id: DeviceId,
+    query: web::Query<ChangesQuery>,
+) -> impl Responder {
+    let device_id = device_id.0.as_deref();
+    match clips
+        .changes(&bearer_token.user_id, device_id, query.since, query.limit)
+        .await
+    {
+        Ok(changes) => ApiResponse::new("获取剪贴板变更成功", ResponseData::Json(json!(changes))),
+        Err(e) => e.into(),
+    }
+}
+
+// 回收站查询参数
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TrashQuery {
+    pub limit: Option<i32>,
+    /// 上一页返回的 next_cursor
+    #[param(value_type = Option<String>)]
+    pub cursor: Option<Cursor>,
+}
+
+// 查询回收站（只返回请求设备可见的项目，按删除时间倒序），按 next_cursor 翻页
+#[utoipa::path(
+    summary = "查询回收站",
+    params(DeviceId, TrashQuery),
+    security(("bearer" = [])),
+    responses((status = 200, body = ApiEnvelope<Page<TrashedClip>>)),
+)]
+#[get("/trash")]
+async fn list_trash(
+    pool: web::Data<DbPool>,
+    bearer_token: BearerToken,
+    device_id: DeviceId,
+    query: web::Query<TrashQuery>,
+) -> impl Responder {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_LIST_LIMIT)
+        .clamp(1, MAX_LIST_LIMIT);
+    let user_id = &bearer_token.user_id;
+    let device_id = device_id.0.as_deref();
+    // 多查询一条以判断是否还有下一页
+    let result = tokio::try_join!(
+        db::get_trashed_clips(
+            user_id,
+            device_id,
+            query.cursor.as_ref(),
+            i64::from(limit) + 1,
+            &pool
+        ),
+        db::count_trashed_clips(user_id, device_id, &pool),
+    );
+    match result {
+        Ok((clips, total)) => {
+            let page = Page::new(clips, total, limit as usize, |trashed| Cursor {
+                at: trashed.deleted_at,
+                id: trashed.clip.id.to_string(),
+            });
+            ApiResponse::new("获取回收站成功", ResponseData::Json(json!(page)))
+        }
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取回收站失败"),
+    }
+}
+
+// 获取单个剪贴板项目
+#[utoipa::path(
+    summary = "获取剪贴板",
+    params(DeviceId),
+    security(("bearer" = [])),
+    responses((
+        status = 200,
+        headers(("ETag" = String, description = "剪贴板版本号")),
+        body = ApiEnvelope<ClipItem>,
+    )),
+)]
+#[get("/{id}")]
+async fn get_clip(
+    clips: web::Data<ClipService>,
+    bearer_token: BearerToken,
+    device_id: DeviceId,
+    id: web::Path<Uuid>,
+) -> Versioned {
+    match clips
+        .get(&bearer_token.user_id, device_id.0.as_deref(), &id)
+        .await
+    {
+        Ok(clip) => Versioned::new(
+            ApiResponse::new("获取剪贴板成功", ResponseData::Json(json!(clip))),
+            clip.version,
+        ),
+        Err(e) => e.into(),
+    }
+}
+
+// 获取请求设备可见的剪贴板，不可见时与不存在相同
+pub(crate) async fn visible_clip(
+    user_id: &str,
+    id: &Uuid,
+    device_id: &DeviceId,
+    pool: &DbPool,
+) -> Result<ClipItem, ApiResponse> {
+    find_visible(user_id, device_id.0.as_deref(), id, pool)
+        .await
+        .map_err(ApiResponse::from)
+}
+
+// 修改剪贴板：标记已访问、替换标签（需要携带 If-Match）
+#[utoipa::path(
+    summary = "修改剪贴板",
+    params(DeviceId, IfMatch),
+    security(("bearer" = [])),
+    responses(
+        (
+            status = 200,
+            headers(("ETag" = String, description = "修改后的版本号")),
+            body = ApiEnvelope<ClipItem>,
+        ),
+        (
+            status = 409,
+            description = "与其他设备的修改冲突，data 为记录的同步冲突",
+            body = ApiEnvelope<SyncConflict>,
+        ),
+        (status = 412, description = "版本号已过期，data 为当前版本号", body = ApiEnvelope<Value>),
+        (status = 428, description = "缺少 If-Match 请求头", body = ApiEnvelope<Value>),
+    ),
+)]
+#[put("/{id}")]
+async fn update_clip(
+    clips: web::Data<ClipService>,
+    bearer_token: BearerToken,
+    device_id: DeviceId,
+    if_match: IfMatch,
+    id: web::Path<Uuid>,
+    update: web::Json<UpdateClipRequest>,
+) -> Versioned {
+    let device_id = device_id.0.as_deref();
+    match clips
+        .update(
+            &bearer_token.user_id,
+            device_id,
+            if_match.0,
+            &id,
+            update.into_inner(),
+        )
+        .await
+    {
+        Ok(clip) => Versioned::new(
+            ApiResponse::new("剪贴板修改成功", ResponseData::Json(json!(clip))),
+            clip.version,
+        ),
+        Err(e) => e.into(),
+    }
+}
+
+// 解决剪贴板的冲突，请求体与 /sync/conflicts/{id}/resolve 相同
+#[utoipa::path(
+    summary = "解决剪贴板冲突",
+    params(DeviceId),
+    security(("bearer" = [])),
+    responses((status = 200, body = ApiEnvelope<Value>)),
+)]
+#[post("/{id}/resolve")]
+async fn resolve_clip_conflict(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    device_id: DeviceId,
+    id: web::Path<Uuid>,
+    resolve: web::Json<ResolveConflict>,
+) -> impl Responder {
+    let user_id = &bearer_token.user_id;
+    let clip = match visible_clip(user_id, &id, &device_id, &pool).await {
+        Ok(clip) => clip,
+        Err(response) => return response,
+    };
+    let conflict =
+        match db::get_open_sync_conflict_for_item(user_id, &clip.id.to_string(), &pool).await {
+            Ok(Some(conflict)) => conflict,
+            Ok(None) => return ApiResponse::error(ErrorCode::NotFound, "剪贴板没有未解决的冲突"),
+            Err(_) => return ApiResponse::error(ErrorCode::InternalError, "解决冲突失败"),
+        };
+    sync_api::resolve_open_conflict(user_id, conflict, resolve.into_inner(), &pool, &app_state)
+        .await
+}
+
+// 删除剪贴板（需要携带 If-Match），删除的剪贴板移到回收站，超过保留期后彻底删除
+#[utoipa::path(
+    summary = "删除剪贴板",
+    params(DeviceId, IfMatch),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, body = ApiEnvelope<Value>),
+        (status = 412, description = "版本号已过期，data 为当前版本号", body = ApiEnvelope<Value>),
+        (status = 428, description = "缺少 If-Match 请求头", body = ApiEnvelope<Value>),
+    ),
+)]
+#[delete("/{id}")]
+async fn delete_clip(
+    clips: web::Data<ClipService>,
+    bearer_token: BearerToken,
+    device_id: DeviceId,
+    if_match: IfMatch,
+    id: web::Path<Uuid>,
+) -> Versioned {
+    let device_id = device_id.0.as_deref();
+    match clips
+        .delete(&bearer_token.user_id, device_id, if_match.0, &id)
+        .await
+    {
+        Ok(()) => ApiResponse::new("剪贴板已移到回收站", ResponseData::Null).into(),
+        Err(e) => e.into(),
+    }
+}
+
+// 从回收站恢复剪贴板（标签和固定状态不变），并向可见的设备推送 clip_created 事件
+#[utoipa::path(
+    summary = "恢复剪贴板",
+    params(DeviceId),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, body = ApiEnvelope<ClipItem>),
+        (status = 404, description = "回收站中没有该剪贴板", body = ApiEnvelope<Value>),
+    ),
+)]
+#[post("/{id}/restore")]
+async fn restore_clip(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    device_id: DeviceId,
+    id: web::Path<Uuid>,
+) -> Versioned {
+    let user_id = &bearer_token.user_id;
+    let not_found = || ApiResponse::error(ErrorCode::NotFound, "回收站中没有该剪贴板").into();
+    let internal_error = || ApiResponse::error(ErrorCode::InternalError, "恢复剪贴板失败").into();
+    match db::restore_clip(user_id, &id, device_id.0.as_deref(), &pool).await {
+        Ok(true) => {}
+        Ok(false) => return not_found(),
+        Err(_) => return internal_error(),
+    }
+    let clip = match db::get_clip(user_id, &id, &pool).await {
+        Ok(Some(clip)) => clip,
+        Ok(None) => return not_found(),
+        Err(_) => return internal_error(),
+    };
+    info!("用户 {} 从回收站恢复剪贴板 {}", user_id, clip.id);
+    notify(
+        user_id,
+        &clip,
+        "clip_created",
+        json!(clip),
+        &pool,
+        &app_state,
+    )
+    .await;
+    let version = clip.version;
+    Versioned::new(
+        ApiResponse::new("剪贴板已恢复", ResponseData::Json(json!(clip))),
+        version,
+    )
+}
+
+// 固定剪贴板，固定的项目不会被保留策略清理（需要携带 If-Match）
+#[utoipa::path(
+    summary = "固定剪贴板",
+    params(DeviceId, IfMatch),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, body = ApiEnvelope<ClipItem>),
+        (status = 412, description = "版本号已过期，data 为当前版本号", body = ApiEnvelope<Value>),
+        (status = 428, description = "缺少 If-Match 请求头", body = ApiEnvelope<Value>),
+    ),
+)]
+#[put("/{id}/pin")]
+async fn pin_clip(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    device_id: DeviceId,
+    if_match: IfMatch,
+    id: web::Path<Uuid>,
+) -> Versioned {
+    set_pinned(&pool, &app_state, &bearer_token, &device_id, if_match, &id, true).await
+}
+
+// 取消固定剪贴板（需要携带 If-Match）
+#[utoipa::path(
+    summary = "取消固定剪贴板",
+    params(DeviceId, IfMatch),
+    security(("bearer" = [])),
+    responses(
+        (status = 200, body = ApiEnvelope<ClipItem>),
+        (status = 412, description = "版本号已过期，data 为当前版本号", body = ApiEnvelope<Value>),
+        (status = 428, description = "缺少 If-Match 请求头", body = ApiEnvelope<Value>),
+    ),
+)]
+#[delete("/{id}/pin")]
+async fn unpin_clip(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    device_id: DeviceId,
+    if_match: IfMatch,
+    id: web::Path<Uuid>,
+) -> Versioned {
+    set_pinned(&pool, &app_state, &bearer_token, &device_id, if_match, &id, false).await
+}
+
+async fn set_pinned(
+    pool: &DbPool,
+    app_state: &AppState,
+    bearer_token: &BearerToken,
+    device_id: &DeviceId,
+    if_match: IfMatch,
+    id: &Uuid,
+    pinned: bool,
+) -> Versioned {
+    let user_id = &bearer_token.user_id;
+    let mut clip = match visible_clip(user_id, id, device_id, pool).await {
+        Ok(clip) => clip,
+        Err(response) => return response.into(),
+    };
+    let modified_by = device_id.0.as_deref();
+    match db::set_clip_pinned(user_id, &clip.id, pinned, if_match.0, modified_by, pool).await {
+        Ok(Some(version)) => {
+            info!("用户 {} {}剪贴板 {}", user_id, if pinned { "固定" } else { "取消固定" }, clip.id);
+            clip.version = version;
+            clip.pinned = pinned;
+            notify(user_id, &clip, "clip_updated", json!(clip), pool, app_state).await;
+            Versioned::new(
+                ApiResponse::new("剪贴板修改成功", ResponseData::Json(json!(clip))),
+                version,
+            )
+        }
+        Ok(None) => stale_clip(user_id, &clip.id, pool).await.into(),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "剪贴板修改失败").into(),
+    }
+}
+
+// 把剪贴板发送给联系人：复制一份到对方的历史记录（source 为 shared），并推送到对方的房间
+//
+// 对方收到的剪贴板不属于任何设备（device_id 为空 UUID），所有设备可见
+#[utoipa::path(
+    summary = "发送剪贴板给联系人",
+    params(DeviceId),
+    security(("bearer" = [])),
+    responses((status = 200, body = ApiEnvelope<Value>)),
+)]
+#[post("/{id}/send_to/{friend_id}")]
+async fn send_to_contact(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    device_id: DeviceId,
+    path: web::Path<(Uuid, String)>,
+) -> impl Responder {
+    let (id, friend_id) = path.into_inner();
+    let user_id = &bearer_token.user_id;
+    let clip = match visible_clip(user_id, &id, &device_id, &pool).await {
+        Ok(clip) => clip,
+        Err(response) => return response,
+    };
+    // 加密的剪贴板使用发送者的密钥，对方无法解密
+    if clip.encrypted {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "加密的剪贴板无法发送");
+    }
+    match db::get_contact_relation(user_id, &friend_id, &pool).await {
+        Ok(Some((_, status))) if status == "accepted" => {}
+        Ok(_) => return ApiResponse::error(ErrorCode::NotFound, "联系人不存在"),
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "剪贴板发送失败"),
+    }
+    let message = "无法向该用户发送剪贴板";
+    if let Err(response) = ensure_not_blocked(user_id, &friend_id, message, &pool).await {
+        return response;
+    }
+
+    let now = Utc::now().trunc_subsecs(3);
+    let mut shared = ClipItem {
+        id: Uuid::new_v4(),
+        device_id: Uuid::nil(),
+        created_at: now,
+        accessed_at: now,
+        sync_status: SyncStatus::Synced,
+        tags: Vec::new(),
+        visibility: Visibility::All,
+        version: 1,
+        pinned: false,
+        source: ClipSource::Shared,
+        shared_by: Some(user_id.clone()),
+        ..clip
+    };
+    // 二进制剪贴板复制一份文件到对方的目录
+    let binary = BINARY_CLIP_TYPES.contains(&shared.content_type);
+    if binary {
+        let extension = Path::new(&shared.content)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or("")
+            .to_string();
+        let copied = match blob_store().get(&shared.content).await {
+            Ok(data) => save_clip_file(&friend_id, &shared.id, &extension, &data).await,
+            Err(e) => Err(e),
+        };
+        match copied {
+            Ok(path) => shared.content = path,
+            Err(e) => {
+                warn!("copy clip file {} failed: {}", clip.id, e);
+                return ApiResponse::error(ErrorCode::InternalError, "剪贴板发送失败");
+            }
+        }
+    }
+    let hash = content_hash(shared.content_type, &shared.content);
+    let (shared, deduped) = match store_clip(&friend_id, shared.clone(), &hash, &pool).await {
+        Ok(stored) => stored,
+        Err(response) => {
+            if binary {
+                let _ = blob_store().delete(&shared.content).await;
+            }
+            return response;
+        }
+    };
+    info!("用户 {} 发送剪贴板 {} 给 {}", user_id, clip.id, friend_id);
+
+    let event = if deduped { "clip_updated" } else { "clip_created" };
+    if let Some(webhook_event) = WebhookEvent::for_clip_event(event) {
+        webhook_api::enqueue(&friend_id, webhook_event, json!(shared), &pool).await;
+    }
+    app_state.room_manager.do_send(PushEvent {
+        user_id: friend_id.clone(),
+        event: event.to_string(),
+        payload: json!(shared),
+    });
+    ApiResponse::new("剪贴板已发送", ResponseData::Null)
+}
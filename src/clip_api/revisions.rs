@@ -0,0 +1,210 @@
+use actix_web::{get, post, web};
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::sync::OnceLock;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    clip_api::service::ClipService,
+    concurrency::{IfMatch, Versioned},
+    config::RevisionsConfig,
+    device_group_api::DeviceId,
+    models::ClipItem,
+    sqlx_utils::models::{ApiEnvelope, ApiResponse, ResponseData},
+    user_api::auth::BearerToken,
+};
+
+static MAX_REVISIONS: OnceLock<i64> = OnceLock::new();
+
+// 设置每个剪贴板保留的历史版本数，只在启动时调用一次
+pub fn install(config: &RevisionsConfig) {
+    let _ = MAX_REVISIONS.set(config.max_per_clip);
+}
+
+// 每个剪贴板保留的历史版本数，未设置时（如单元测试）使用默认值
+pub(crate) fn max_revisions() -> i64 {
+    *MAX_REVISIONS.get_or_init(|| RevisionsConfig::default().max_per_clip)
+}
+
+/// 剪贴板的历史版本：内容或标签被修改前的状态
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ClipRevision {
+    /// 该版本的版本号，即被修改前剪贴板的 version
+    pub revision: i64,
+    pub content: String,
+    pub preview: String,
+    pub size: i64,
+    pub tags: Vec<String>,
+    /// 做出该版本修改的设备
+    pub modified_by: Option<String>,
+    /// 被替换的时间（秒）
+    pub created_at: i64,
+}
+
+// 列出剪贴板的历史版本（按版本号倒序）
+#[utoipa::path(
+    summary = "获取剪贴板的历史版本",
+    params(DeviceId),
+    security(("bearer" = [])),
+    responses((status = 200, body = ApiEnvelope<Vec<ClipRevision>>)),
+)]
+#[get("/{id}/revisions")]
+pub async fn list_revisions(
+    clips: web::Data<ClipService>,
+    bearer_token: BearerToken,
+    device_id: DeviceId,
+    id: web::Path<Uuid>,
+) -> ApiResponse {
+    match clips
+        .revisions(&bearer_token.user_id, device_id.0.as_deref(), &id)
+        .await
+    {
+        Ok(revisions) => ApiResponse::new("获取历史版本成功", ResponseData::Json(json!(revisions))),
+        Err(e) => e.into(),
+    }
+}
+
+// 把剪贴板的内容和标签恢复为历史版本（需要携带 If-Match），恢复前的状态同样保存为历史版本
+#[utoipa::path(
+    summary = "恢复剪贴板的历史版本",
+    params(DeviceId, IfMatch),
+    security(("bearer" = [])),
+    responses(
+        (
+            status = 200,
+            headers(("ETag" = String, description = "恢复后的版本号")),
+            body = ApiEnvelope<ClipItem>,
+        ),
+        (status = 404, description = "剪贴板或历史版本不存在", body = ApiEnvelope<Value>),
+        (status = 412, description = "版本号已过期，data 为当前版本号", body = ApiEnvelope<Value>),
+        (status = 428, description = "缺少 If-Match 请求头", body = ApiEnvelope<Value>),
+    ),
+)]
+#[post("/{id}/revisions/{revision}/restore")]
+pub async fn restore_revision(
+    clips: web::Data<ClipService>,
+    bearer_token: BearerToken,
+    device_id: DeviceId,
+    if_match: IfMatch,
+    path: web::Path<(Uuid, i64)>,
+) -> Versioned {
+    let (id, revision) = path.into_inner();
+    match clips
+        .restore_revision(
+            &bearer_token.user_id,
+            device_id.0.as_deref(),
+            if_match.0,
+            &id,
+            revision,
+        )
+        .await
+    {
+        Ok(clip) => Versioned::new(
+            ApiResponse::new("已恢复历史版本", ResponseData::Json(json!(clip))),
+            clip.version,
+        ),
+        Err(e) => e.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::{ClipItem, content_hash};
+    use crate::sqlx_utils::{db, migrations::run_migrations};
+    use serde_json::json;
+    use sqlx::any::AnyPoolOptions;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn keeps_previous_versions_and_restores_them() {
+        // 内存数据库按连接隔离，只使用一个连接
+        let pool = AnyPoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        run_migrations(&pool).await.unwrap();
+        let mut clip: ClipItem = serde_json::from_value(json!({
+            "id": Uuid::new_v4(),
+            "device_id": Uuid::new_v4(),
+            "content_type": "text",
+            "content": "first",
+            "preview": "first",
+            "size": 5,
+            "created_at": "2026-01-01T00:00:00Z",
+            "accessed_at": "2026-01-01T00:00:00Z",
+            "sync_status": "synced",
+            "encrypted": false,
+            "tags": ["a"],
+        }))
+        .unwrap();
+        let mut tx = pool.begin().await.unwrap();
+        let hash = content_hash(clip.content_type, &clip.content);
+        db::write_clip_insert("alice", &clip, &hash, &mut tx)
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+
+        // 只标记已访问时不保存历史版本
+        let version = db::update_clip("alice", &clip, Some(1), None, &pool)
+            .await
+            .unwrap();
+        assert_eq!(version, Some(2));
+        assert!(
+            db::get_clip_revisions("alice", &clip.id, &pool)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        clip.tags = vec!["b".to_string()];
+        db::update_clip("alice", &clip, Some(2), Some("laptop"), &pool)
+            .await
+            .unwrap();
+        clip.content = "second".to_string();
+        db::restore_clip_revision("alice", &clip, Some(3), Some("phone"), &pool)
+            .await
+            .unwrap();
+        let revisions = db::get_clip_revisions("alice", &clip.id, &pool)
+            .await
+            .unwrap();
+        let summary: Vec<_> = revisions
+            .iter()
+            .map(|r| (r.revision, r.content.as_str(), r.tags.clone()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (3, "first", vec!["b".to_string()]),
+                (2, "first", vec!["a".to_string()]),
+            ]
+        );
+        assert_eq!(revisions[0].modified_by.as_deref(), Some("laptop"));
+
+        // 恢复后内容和标签与历史版本一致
+        let revision = db::get_clip_revision("alice", &clip.id, 2, &pool)
+            .await
+            .unwrap()
+            .unwrap();
+        clip.content = revision.content;
+        clip.tags = revision.tags;
+        db::restore_clip_revision("alice", &clip, Some(4), None, &pool)
+            .await
+            .unwrap();
+        let restored = db::get_clip("alice", &clip.id, &pool)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (restored.content.as_str(), restored.tags, restored.version),
+            ("first", vec!["a".to_string()], 5)
+        );
+        assert!(
+            db::restore_clip_revision("alice", &clip, Some(4), None, &pool)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,393 @@
+use chrono::{SubsecRound, Utc};
+use log::info;
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+use crate::{
+    clip_api::{
+        ClipChanges, DEFAULT_CHANGES_LIMIT, DEFAULT_LIST_LIMIT, MAX_CHANGES_LIMIT, MAX_LIST_LIMIT,
+        normalize_tags, notify, prepare_clip, revisions::ClipRevision, store_clip,
+    },
+    concurrency::Versioned,
+    models::{ClipFilter, ClipItem, CreateClipRequest, UpdateClipRequest},
+    spatial_api::models::AppState,
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiResponse, Cursor, ErrorCode, Page},
+    },
+    sync_api::{self, SyncConflict},
+    user_api::quota::check_quota,
+};
+
+/// 剪贴板操作失败的原因，由各接口转换为对应的响应
+pub enum ClipError {
+    /// 剪贴板不存在或对请求设备不可见
+    NotFound,
+    /// 版本号已过期，附带当前版本号
+    Stale(i64),
+    /// 与其他设备并发修改，附带当前版本号和记录的同步冲突
+    Conflict(i64, Value),
+    /// 请求被拒绝（参数无效、超出配额、违反内容策略等），附带错误响应
+    Rejected(ApiResponse),
+    /// 数据库等内部错误，附带返回给客户端的提示
+    Internal(&'static str),
+}
+
+impl From<ApiResponse> for ClipError {
+    fn from(response: ApiResponse) -> Self {
+        ClipError::Rejected(response)
+    }
+}
+
+impl From<ClipError> for Versioned {
+    fn from(error: ClipError) -> Self {
+        match error {
+            ClipError::NotFound => ApiResponse::error(ErrorCode::NotFound, "剪贴板不存在").into(),
+            ClipError::Stale(version) => Versioned::stale(version),
+            ClipError::Conflict(version, conflict) => Versioned::conflicted(version, conflict),
+            ClipError::Rejected(response) => response.into(),
+            ClipError::Internal(message) => {
+                ApiResponse::error(ErrorCode::InternalError, message).into()
+            }
+        }
+    }
+}
+
+impl From<ClipError> for ApiResponse {
+    fn from(error: ClipError) -> Self {
+        Versioned::from(error).into()
+    }
+}
+
+/// 剪贴板的业务逻辑（保存、查询、修改、删除和增量同步），REST 和 gRPC 接口共用
+///
+/// device_id 为请求设备，只能访问该设备可见的剪贴板；为 None 时不限制可见范围
+#[derive(Clone)]
+pub struct ClipService {
+    pool: DbPool,
+    app_state: AppState,
+}
+
+impl ClipService {
+    pub fn new(pool: DbPool, app_state: AppState) -> Self {
+        ClipService { pool, app_state }
+    }
+
+    /// 保存剪贴板并通知用户的其他设备
+    ///
+    /// 已有相同内容时返回移到最前的已有剪贴板，第二项为 true
+    pub async fn create(
+        &self,
+        user_id: &str,
+        create: CreateClipRequest,
+    ) -> Result<(ClipItem, bool), ClipError> {
+        let (clip, hash) = prepare_clip(&self.pool, &self.app_state, user_id, create).await?;
+        let (clip, deduped) = store_clip(user_id, clip, &hash, &self.pool).await?;
+
+        // 重复的内容：返回移到最前的已有剪贴板
+        let event = if deduped {
+            info!("用户 {} 重复保存剪贴板 {}", user_id, clip.id);
+            "clip_updated"
+        } else {
+            info!("用户 {} 保存剪贴板 {}", user_id, clip.id);
+            "clip_created"
+        };
+        notify(
+            user_id,
+            &clip,
+            event,
+            json!(clip),
+            &self.pool,
+            &self.app_state,
+        )
+        .await;
+        Ok((clip, deduped))
+    }
+
+    /// 获取请求设备可见的剪贴板，不可见时与不存在相同
+    pub async fn get(
+        &self,
+        user_id: &str,
+        device_id: Option<&str>,
+        id: &Uuid,
+    ) -> Result<ClipItem, ClipError> {
+        find_visible(user_id, device_id, id, &self.pool).await
+    }
+
+    /// 按条件查询请求设备可见的剪贴板（按创建时间倒序）
+    pub async fn list(
+        &self,
+        user_id: &str,
+        device_id: Option<&str>,
+        mut filter: ClipFilter,
+    ) -> Result<Page<ClipItem>, ClipError> {
+        let limit = filter
+            .limit
+            .unwrap_or(DEFAULT_LIST_LIMIT)
+            .clamp(1, MAX_LIST_LIMIT);
+        // 多查询一条以判断是否还有下一页
+        filter.limit = Some(limit + 1);
+        filter.offset = Some(filter.offset.unwrap_or(0).max(0));
+
+        let (clips, total) = tokio::try_join!(
+            db::get_clips(user_id, device_id, &filter, &self.pool),
+            db::count_clips(user_id, device_id, &filter, &self.pool),
+        )
+        .map_err(|_| ClipError::Internal("获取剪贴板失败"))?;
+        Ok(Page::new(clips, total, limit as usize, |clip| Cursor {
+            at: clip.created_at.timestamp_millis(),
+            id: clip.id.to_string(),
+        }))
+    }
+
+    /// 获取 since 之后请求设备可见的剪贴板变更
+    pub async fn changes(
+        &self,
+        user_id: &str,
+        device_id: Option<&str>,
+        since: i64,
+        limit: Option<i32>,
+    ) -> Result<ClipChanges, ClipError> {
+        let internal_error = |_| ClipError::Internal("获取剪贴板变更失败");
+        let since = since.max(0);
+        let limit = limit
+            .unwrap_or(DEFAULT_CHANGES_LIMIT)
+            .clamp(1, MAX_CHANGES_LIMIT);
+        // 先读取最新游标再查询变更，查询期间新增的变更不会被跳过
+        let latest = db::get_change_cursor(user_id, &self.pool)
+            .await
+            .map_err(internal_error)?;
+        let changes = db::get_clip_changes(user_id, device_id, since, limit, &self.pool)
+            .await
+            .map_err(internal_error)?;
+
+        // 不足一页说明已经同步到最新，游标直接跳到最新（跳过不可见剪贴板的变更）
+        let has_more = changes.len() == limit as usize;
+        let last_seq = changes.last().map(|change| change.seq).unwrap_or(since);
+        let cursor = if has_more {
+            last_seq
+        } else {
+            last_seq.max(latest).max(since)
+        };
+        Ok(ClipChanges {
+            cursor,
+            has_more,
+            changes,
+        })
+    }
+
+    /// 标记已访问、替换标签，expected_version 为 None 时不校验版本
+    pub async fn update(
+        &self,
+        user_id: &str,
+        device_id: Option<&str>,
+        expected_version: Option<i64>,
+        id: &Uuid,
+        update: UpdateClipRequest,
+    ) -> Result<ClipItem, ClipError> {
+        let mut clip = find_visible(user_id, device_id, id, &self.pool).await?;
+        if update.accessed {
+            clip.accessed_at = Utc::now().trunc_subsecs(3);
+        }
+        if let Some(tags) = update.tags {
+            clip.tags = normalize_tags(tags);
+        }
+
+        match db::update_clip(user_id, &clip, expected_version, device_id, &self.pool).await {
+            Ok(Some(version)) => {
+                clip.version = version;
+                let payload = json!(clip);
+                notify(
+                    user_id,
+                    &clip,
+                    "clip_updated",
+                    payload,
+                    &self.pool,
+                    &self.app_state,
+                )
+                .await;
+                Ok(clip)
+            }
+            Ok(None) => Err(self
+                .concurrent_edit(user_id, clip, expected_version, device_id)
+                .await),
+            Err(_) => Err(ClipError::Internal("剪贴板修改失败")),
+        }
+    }
+
+    /// 获取请求设备可见的剪贴板的历史版本（按版本号倒序）
+    pub async fn revisions(
+        &self,
+        user_id: &str,
+        device_id: Option<&str>,
+        id: &Uuid,
+    ) -> Result<Vec<ClipRevision>, ClipError> {
+        let clip = find_visible(user_id, device_id, id, &self.pool).await?;
+        db::get_clip_revisions(user_id, &clip.id, &self.pool)
+            .await
+            .map_err(|_| ClipError::Internal("获取历史版本失败"))
+    }
+
+    /// 把剪贴板的内容和标签恢复为历史版本 revision，expected_version 为 None 时不校验版本
+    pub async fn restore_revision(
+        &self,
+        user_id: &str,
+        device_id: Option<&str>,
+        expected_version: Option<i64>,
+        id: &Uuid,
+        revision: i64,
+    ) -> Result<ClipItem, ClipError> {
+        let mut clip = find_visible(user_id, device_id, id, &self.pool).await?;
+        let revision = match db::get_clip_revision(user_id, &clip.id, revision, &self.pool).await {
+            Ok(Some(revision)) => revision,
+            Ok(None) => {
+                return Err(ApiResponse::error(ErrorCode::NotFound, "历史版本不存在").into());
+            }
+            Err(_) => return Err(ClipError::Internal("获取历史版本失败")),
+        };
+        check_quota(user_id, (revision.size - clip.size).max(0), &self.pool).await?;
+        clip.content = revision.content;
+        clip.preview = revision.preview;
+        clip.size = revision.size;
+        clip.tags = revision.tags;
+        clip.accessed_at = Utc::now().trunc_subsecs(3);
+
+        match db::restore_clip_revision(user_id, &clip, expected_version, device_id, &self.pool)
+            .await
+        {
+            Ok(Some(version)) => {
+                info!(
+                    "用户 {} 将剪贴板 {} 恢复为版本 {}",
+                    user_id, clip.id, revision.revision
+                );
+                clip.version = version;
+                let payload = json!(clip);
+                notify(
+                    user_id,
+                    &clip,
+                    "clip_updated",
+                    payload,
+                    &self.pool,
+                    &self.app_state,
+                )
+                .await;
+                Ok(clip)
+            }
+            Ok(None) => Err(stale_clip(user_id, &clip.id, &self.pool).await),
+            Err(_) => Err(ClipError::Internal("恢复历史版本失败")),
+        }
+    }
+
+    /// 把剪贴板移到回收站，expected_version 为 None 时不校验版本
+    pub async fn delete(
+        &self,
+        user_id: &str,
+        device_id: Option<&str>,
+        expected_version: Option<i64>,
+        id: &Uuid,
+    ) -> Result<(), ClipError> {
+        let clip = find_visible(user_id, device_id, id, &self.pool).await?;
+        match db::delete_clip(user_id, &clip.id, expected_version, &self.pool).await {
+            Ok(true) => {
+                info!("用户 {} 删除剪贴板 {}", user_id, clip.id);
+                let payload = json!({ "id": clip.id });
+                notify(
+                    user_id,
+                    &clip,
+                    "clip_deleted",
+                    payload,
+                    &self.pool,
+                    &self.app_state,
+                )
+                .await;
+                Ok(())
+            }
+            Ok(false) => Err(stale_clip(user_id, &clip.id, &self.pool).await),
+            Err(_) => Err(ClipError::Internal("剪贴板删除失败")),
+        }
+    }
+
+    // 版本号过期的修改：剪贴板最近一次由其他设备修改时视为并发修改，记录同步冲突（剪贴板标记为冲突状态），
+    // 由用户通过 POST /clips/{id}/resolve 选择保留的版本或提供合并后的内容；
+    // 最近一次修改来自同一设备或无法识别设备时与其他接口一样视为版本过期
+    async fn concurrent_edit(
+        &self,
+        user_id: &str,
+        mut attempted: ClipItem,
+        expected_version: Option<i64>,
+        device_id: Option<&str>,
+    ) -> ClipError {
+        let (Some(expected_version), Some(device)) = (expected_version, device_id) else {
+            return stale_clip(user_id, &attempted.id, &self.pool).await;
+        };
+        let (current, modified_by) = match tokio::try_join!(
+            db::get_clip(user_id, &attempted.id, &self.pool),
+            db::get_clip_modified_by(user_id, &attempted.id, &self.pool),
+        ) {
+            Ok((Some(current), modified_by)) => (current, modified_by),
+            Ok((None, _)) => return ClipError::NotFound,
+            Err(_) => return ClipError::Internal("获取剪贴板失败"),
+        };
+        let Some(modified_by) = modified_by.filter(|modified_by| modified_by != device) else {
+            return ClipError::Stale(current.version);
+        };
+
+        // local 为设备基于旧版本做出的修改，remote 为其他设备修改后的当前版本
+        attempted.version = expected_version;
+        let mut local = json!(attempted);
+        local["modified_by"] = json!(device);
+        let mut remote = json!(current);
+        remote["modified_by"] = json!(modified_by);
+        let conflict = SyncConflict {
+            id: Uuid::new_v4().to_string(),
+            item_id: current.id.to_string(),
+            local,
+            remote,
+            created_at: Utc::now().timestamp(),
+            resolution: None,
+            resolved_at: None,
+        };
+        info!(
+            "用户 {} 的设备 {} 与 {} 并发修改剪贴板 {}",
+            user_id, device, modified_by, conflict.item_id
+        );
+        match sync_api::record_conflict(user_id, &conflict, &self.pool, &self.app_state).await {
+            Ok(_) => ClipError::Conflict(current.version, json!(conflict)),
+            Err(_) => ClipError::Internal("剪贴板修改失败"),
+        }
+    }
+}
+
+// 获取请求设备可见的剪贴板，不可见时与不存在相同
+pub(crate) async fn find_visible(
+    user_id: &str,
+    device_id: Option<&str>,
+    id: &Uuid,
+    pool: &DbPool,
+) -> Result<ClipItem, ClipError> {
+    let internal_error = |_| ClipError::Internal("获取剪贴板失败");
+    let clip = db::get_clip(user_id, id, pool)
+        .await
+        .map_err(internal_error)?
+        .ok_or(ClipError::NotFound)?;
+    if clip.visibility.is_all() {
+        return Ok(clip);
+    }
+    let groups = db::get_device_groups(user_id, pool)
+        .await
+        .map_err(internal_error)?;
+    if clip.visibility.allows(device_id, &groups) {
+        Ok(clip)
+    } else {
+        Err(ClipError::NotFound)
+    }
+}
+
+// 修改失败时区分版本不一致（附带当前版本号）和剪贴板已被删除
+pub(crate) async fn stale_clip(user_id: &str, id: &Uuid, pool: &DbPool) -> ClipError {
+    match db::get_clip(user_id, id, pool).await {
+        Ok(Some(clip)) => ClipError::Stale(clip.version),
+        Ok(None) => ClipError::NotFound,
+        Err(_) => ClipError::Internal("获取剪贴板失败"),
+    }
+}
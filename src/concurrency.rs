@@ -0,0 +1,115 @@
+use actix_web::body::BoxBody;
+use actix_web::dev::Payload;
+use actix_web::http::{StatusCode, header};
+use actix_web::{Error, FromRequest, HttpRequest, HttpResponse, Responder};
+use serde_json::json;
+use std::future::{Ready, ready};
+use utoipa::IntoParams;
+
+use crate::sqlx_utils::models::{ApiResponse, ErrorCode, ResponseData};
+
+/// 版本号对应的 ETag，如 `"3"`
+pub fn etag(version: i64) -> String {
+    format!("\"{}\"", version)
+}
+
+/// 请求头 `If-Match` 中的版本号，`*` 表示不校验版本（强制覆盖）
+///
+/// 修改类接口必须携带该请求头，缺少时返回 428
+#[derive(IntoParams)]
+#[into_params(names("If-Match"), parameter_in = Header)]
+pub struct IfMatch(#[param(value_type = String, required = true)] pub Option<i64>);
+
+impl FromRequest for IfMatch {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let Some(value) = req.headers().get(header::IF_MATCH) else {
+            return ready(Err(ApiResponse::into_error(
+                ErrorCode::PreconditionRequired,
+                "缺少 If-Match 请求头",
+            )));
+        };
+        let value = value.to_str().unwrap_or("").trim();
+        if value == "*" {
+            return ready(Ok(IfMatch(None)));
+        }
+        // 兼容弱校验前缀 W/ 和引号
+        let version = value.trim_start_matches("W/").trim_matches('"').parse();
+        ready(version.map(|v| IfMatch(Some(v))).map_err(|_| {
+            ApiResponse::into_error(ErrorCode::InvalidRequest, "无效的 If-Match 请求头")
+        }))
+    }
+}
+
+/// 带版本号的响应，成功时附带 `ETag` 头
+pub struct Versioned {
+    response: ApiResponse,
+    version: Option<i64>,
+    status: StatusCode,
+}
+
+impl Versioned {
+    pub fn new(response: ApiResponse, version: i64) -> Self {
+        Versioned {
+            response,
+            version: Some(version),
+            status: StatusCode::OK,
+        }
+    }
+
+    /// 版本已过期，返回 412 和当前版本号
+    pub fn stale(current: i64) -> Self {
+        Versioned {
+            response: ApiResponse::error_with_data(
+                ErrorCode::VersionConflict,
+                "资源已被其他设备修改，请刷新后重试",
+                ResponseData::Json(json!({ "version": current })),
+            ),
+            version: Some(current),
+            status: StatusCode::PRECONDITION_FAILED,
+        }
+    }
+
+    /// 与其他设备的修改冲突且已记录为同步冲突，返回 409、当前版本号和冲突记录
+    pub fn conflicted(current: i64, conflict: serde_json::Value) -> Self {
+        Versioned {
+            response: ApiResponse::error_with_data(
+                ErrorCode::EditConflict,
+                "与其他设备的修改冲突，请选择保留的版本",
+                ResponseData::Json(conflict),
+            ),
+            version: Some(current),
+            status: StatusCode::CONFLICT,
+        }
+    }
+}
+
+impl From<ApiResponse> for Versioned {
+    fn from(response: ApiResponse) -> Self {
+        Versioned {
+            status: response.status(),
+            response,
+            version: None,
+        }
+    }
+}
+
+impl From<Versioned> for ApiResponse {
+    fn from(versioned: Versioned) -> Self {
+        versioned.response
+    }
+}
+
+impl Responder for Versioned {
+    type Body = BoxBody;
+
+    fn respond_to(self, _req: &HttpRequest) -> HttpResponse<Self::Body> {
+        let mut builder = HttpResponse::build(self.status);
+        if let Some(version) = self.version {
+            builder.insert_header((header::ETAG, etag(version)));
+        }
+        builder.json(self.response)
+    }
+}
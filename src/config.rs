@@ -0,0 +1,659 @@
+use figment::{
+    Figment,
+    providers::{Env, Format, Serialized, Toml},
+};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::time::Duration;
+
+/// 服务器配置
+///
+/// 按以下顺序加载，后者覆盖前者：
+/// - 内置默认值
+/// - 配置文件 `clipfocus.toml`（可通过 `CLIPFOCUS_CONFIG` 指定路径，文件不存在时跳过）
+/// - `DATABASE_URL` 环境变量，对应 `database.url`；`TLS_CERT`、`TLS_KEY` 环境变量，对应 `tls.cert`、`tls.key`
+/// - `CLIPFOCUS_` 前缀的环境变量，嵌套字段用 `__` 分隔，例如 `CLIPFOCUS_SERVER__PORT=8080`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub auth: AuthConfig,
+    pub spatial: SpatialConfig,
+    pub shutdown: ShutdownConfig,
+    pub logging: LoggingConfig,
+    pub upload: UploadConfig,
+    pub trash: TrashConfig,
+    pub revisions: RevisionsConfig,
+    pub backup: BackupConfig,
+    pub storage: StorageConfig,
+    pub cluster: ClusterConfig,
+    pub webhook: WebhookConfig,
+    pub jobs: JobsConfig,
+    pub discovery: DiscoveryConfig,
+    pub grpc: GrpcConfig,
+    pub tls: TlsConfig,
+    pub cors: CorsConfig,
+    pub quota: QuotaConfig,
+}
+
+/// HTTP 服务监听地址和运行环境
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+    /// 运行环境，决定 CORS 等配置的默认值
+    pub environment: Environment,
+    /// JSON 等一次读入内存的请求体的最大字节数，超出时返回 413（PAYLOAD_TOO_LARGE）；
+    /// 头像、分块上传和导入以流的方式读取，分别按各自的限制检查
+    pub max_body_bytes: usize,
+}
+
+/// 运行环境
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Environment {
+    /// 开发环境：默认额外允许 localhost 上任意端口的网页跨域访问
+    Development,
+    #[default]
+    Production,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: "0.0.0.0".to_string(),
+            port: 3000,
+            environment: Environment::default(),
+            max_body_bytes: 2 * 1024 * 1024,
+        }
+    }
+}
+
+/// 数据库
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DatabaseConfig {
+    /// 连接地址，`sqlite://` 或 `postgres://`，也可以通过 `DATABASE_URL` 环境变量指定
+    pub url: String,
+    /// 等待 SQLite 数据库锁的超时时间（秒）
+    pub busy_timeout_secs: u64,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            url: "sqlite://data.db".to_string(),
+            busy_timeout_secs: 5,
+        }
+    }
+}
+
+/// 登录令牌
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    /// 访问令牌有效期（秒）
+    pub access_token_ttl_secs: usize,
+    /// 第三方登录
+    pub oauth: OAuthConfig,
+    /// 审计日志保留天数，0 表示永久保留
+    pub audit_log_retention_days: i64,
+    /// 连续登录失败后的临时锁定
+    pub lockout: LoginLockoutConfig,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            access_token_ttl_secs: 15 * 60,
+            oauth: OAuthConfig::default(),
+            audit_log_retention_days: 180,
+            lockout: LoginLockoutConfig::default(),
+        }
+    }
+}
+
+/// 登录锁定：同一账号或 IP 连续失败达到阈值后锁定 base_secs 秒，之后每次失败锁定时长翻倍，
+/// 最长 max_secs 秒；最近一次失败超过 max_secs 秒后重新计数
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoginLockoutConfig {
+    /// 同一账号连续失败多少次后锁定，0 表示不按账号锁定
+    pub account_threshold: u32,
+    /// 同一 IP 连续失败多少次后锁定，0 表示不按 IP 锁定
+    pub ip_threshold: u32,
+    pub base_secs: u64,
+    pub max_secs: u64,
+}
+
+impl Default for LoginLockoutConfig {
+    fn default() -> Self {
+        Self {
+            account_threshold: 5,
+            ip_threshold: 20,
+            base_secs: 30,
+            max_secs: 3600,
+        }
+    }
+}
+
+/// 第三方登录（需要启用 `oauth` feature），只有配置了客户端的提供方可用
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OAuthConfig {
+    pub github: Option<OAuthClientConfig>,
+    pub google: Option<OAuthClientConfig>,
+    /// 允许的客户端回跳地址，登录完成后令牌附加在回跳地址的片段（#）中，
+    /// 未指定回跳地址时回调接口直接返回 JSON。
+    ///
+    /// 回跳地址的协议、主机和端口需要与其中一个地址相同，路径与之相同；
+    /// 该地址的路径以 `/` 结尾时也允许其下的路径
+    pub allowed_redirects: Vec<String>,
+    /// 登录流程的有效期（秒），超时后回调时的 state 无效
+    pub state_ttl_secs: i64,
+}
+
+impl Default for OAuthConfig {
+    fn default() -> Self {
+        Self {
+            github: None,
+            google: None,
+            allowed_redirects: vec!["clipfocus://oauth".to_string()],
+            state_ttl_secs: 10 * 60,
+        }
+    }
+}
+
+/// 在提供方登记的 OAuth 客户端
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OAuthClientConfig {
+    pub client_id: String,
+    pub client_secret: String,
+    /// 回调地址，需要与提供方登记的一致，
+    /// 如 `https://clip.example.com/api/v1/user/oauth/github/callback`
+    pub redirect_uri: String,
+}
+
+/// WebSocket 心跳与连接清理
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpatialConfig {
+    /// 向客户端发送 ping 的间隔（秒）
+    pub heartbeat_interval_secs: u64,
+    /// 超过该时间未收到客户端响应则断开连接（秒）
+    pub client_timeout_secs: u64,
+    /// 房间管理器清理死亡连接的间隔（秒）
+    pub cleanup_interval_secs: u64,
+    /// 握手时未提供令牌的连接需要在该时间内发送 auth 消息，否则断开（秒）
+    pub auth_timeout_secs: u64,
+    /// 协商了 compression 能力的会话，超过该字节数的文本消息压缩后以二进制帧发送，0 表示不压缩
+    pub compression_threshold: usize,
+    /// 每个用户在本节点同时在线的会话数上限，0 表示不限制
+    pub max_sessions_per_user: usize,
+    /// 达到会话数上限时的处理方式
+    pub session_limit_policy: SessionLimitPolicy,
+    /// 把心跳记录的设备在线时间批量写入数据库的间隔（秒）
+    pub last_seen_flush_secs: u64,
+    /// 会话暂停同步期间收到的剪贴板的处理方式
+    pub paused_sync_policy: PausedSyncPolicy,
+}
+
+impl Default for SpatialConfig {
+    fn default() -> Self {
+        Self {
+            heartbeat_interval_secs: 5,
+            client_timeout_secs: 30,
+            cleanup_interval_secs: 30,
+            auth_timeout_secs: 10,
+            compression_threshold: 4096,
+            max_sessions_per_user: 32,
+            session_limit_policy: SessionLimitPolicy::Reject,
+            last_seen_flush_secs: 60,
+            paused_sync_policy: PausedSyncPolicy::Queue,
+        }
+    }
+}
+
+impl SpatialConfig {
+    pub fn heartbeat_interval(&self) -> Duration {
+        Duration::from_secs(self.heartbeat_interval_secs)
+    }
+
+    pub fn client_timeout(&self) -> Duration {
+        Duration::from_secs(self.client_timeout_secs)
+    }
+
+    pub fn cleanup_interval(&self) -> Duration {
+        Duration::from_secs(self.cleanup_interval_secs)
+    }
+
+    pub fn auth_timeout(&self) -> Duration {
+        Duration::from_secs(self.auth_timeout_secs)
+    }
+
+    pub fn last_seen_flush_interval(&self) -> Duration {
+        Duration::from_secs(self.last_seen_flush_secs.max(1))
+    }
+}
+
+/// 会话数达到上限时的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionLimitPolicy {
+    /// 拒绝新会话
+    #[default]
+    Reject,
+    /// 断开最早建立的会话（关闭原因为 `session.evicted`），接受新会话
+    EvictOldest,
+}
+
+/// 会话暂停同步（`sync.pause`）期间收到的剪贴板的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PausedSyncPolicy {
+    /// 暂存在内存中，恢复同步后按顺序发送（每个会话最多保留 256 条，超出时丢弃最早的）
+    #[default]
+    Queue,
+    /// 直接丢弃，客户端恢复后可通过增量同步补齐
+    Drop,
+}
+
+/// 优雅停机
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShutdownConfig {
+    /// 通知 WebSocket 会话后等待其断开的最长时间（秒）
+    pub drain_timeout_secs: u64,
+    /// 关闭帧中建议客户端重连前等待的时间（秒）
+    pub reconnect_after_secs: u64,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            drain_timeout_secs: 10,
+            reconnect_after_secs: 5,
+        }
+    }
+}
+
+impl ShutdownConfig {
+    pub fn drain_timeout(&self) -> Duration {
+        Duration::from_secs(self.drain_timeout_secs)
+    }
+}
+
+/// 分块上传
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UploadConfig {
+    /// 分块上传的单个剪贴板最大字节数
+    pub max_clip_size: usize,
+    /// 单个分块最大字节数，也是未指定分块大小时的默认值
+    pub max_chunk_size: usize,
+    /// 上传会话的有效期（秒），过期未完成的上传连同已上传的分块一起清理
+    pub session_ttl_secs: i64,
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        Self {
+            max_clip_size: 256 * 1024 * 1024,
+            max_chunk_size: 8 * 1024 * 1024,
+            session_ttl_secs: 24 * 3600,
+        }
+    }
+}
+
+/// 剪贴板回收站
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrashConfig {
+    /// 删除的剪贴板在回收站中保留的天数，超过后彻底删除，0 表示永久保留
+    pub retention_days: i64,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        Self { retention_days: 30 }
+    }
+}
+
+/// 剪贴板的历史版本
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RevisionsConfig {
+    /// 每个剪贴板保留的历史版本数，超出时删除最旧的版本，0 表示不保留历史版本
+    pub max_per_clip: i64,
+}
+
+impl Default for RevisionsConfig {
+    fn default() -> Self {
+        Self { max_per_clip: 20 }
+    }
+}
+
+/// SQLite 数据库定时备份（PostgreSQL 请使用 pg_dump 等外部工具）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackupConfig {
+    /// 是否定时备份，关闭时仍可通过 `POST /admin/backup` 手动备份
+    pub enabled: bool,
+    /// 定时备份的间隔（秒）
+    pub interval_secs: u64,
+    /// 备份文件的保存目录
+    pub dir: String,
+    /// 本地保留的备份数量，超出时删除最旧的备份，0 表示不删除
+    pub keep: usize,
+    /// 备份完成后上传到 S3 兼容存储（需要启用 `s3` feature）
+    pub s3: Option<S3Config>,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_secs: 24 * 3600,
+            dir: "./backups".to_string(),
+            keep: 7,
+            s3: None,
+        }
+    }
+}
+
+impl BackupConfig {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs)
+    }
+}
+
+/// 头像和二进制剪贴板文件的存储位置，多节点部署时需要使用共享的 S3 兼容存储
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    pub backend: StorageBackend,
+    /// backend 为 `s3` 时的连接配置（需要启用 `s3` feature）
+    pub s3: Option<S3Config>,
+}
+
+/// 存储后端
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// 保存在本地的 `./static` 目录
+    #[default]
+    Local,
+    /// 保存在 S3 兼容存储（如 MinIO），对象名为 `{prefix}heads/...`、`{prefix}clips/...`
+    S3,
+}
+
+/// S3 兼容存储，按路径风格（`{endpoint}/{bucket}/{key}`）访问
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct S3Config {
+    /// 服务地址，如 `https://s3.us-east-1.amazonaws.com`
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    /// 对象名前缀，如 `clipfocus/`
+    pub prefix: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+}
+
+impl Default for S3Config {
+    fn default() -> Self {
+        Self {
+            endpoint: String::new(),
+            region: "us-east-1".to_string(),
+            bucket: String::new(),
+            prefix: String::new(),
+            access_key_id: String::new(),
+            secret_access_key: String::new(),
+        }
+    }
+}
+
+/// 多节点部署：各节点通过 Redis 发布/订阅转发房间事件，使连接到不同节点的会话互相收到推送
+///
+/// 在线会话列表、长轮询的事件序号和未确认消息的重发仍然只在各自节点内有效，
+/// 负载均衡需要让同一客户端的长轮询请求保持在同一节点；推送结果中的送达会话数只统计本节点
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClusterConfig {
+    /// Redis 地址，如 `redis://127.0.0.1:6379`，未设置时房间只在本进程内（需要启用 `redis` feature）
+    pub redis_url: Option<String>,
+    /// 频道名前缀，用户的频道为 `{prefix}user:{user_id}`，全局广播为 `{prefix}broadcast`
+    pub channel_prefix: String,
+    /// 节点 ID，默认每次启动随机生成，用于忽略自己发布的消息
+    pub node_id: String,
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        Self {
+            redis_url: None,
+            channel_prefix: "clipfocus:".to_string(),
+            node_id: uuid::Uuid::new_v4().to_string(),
+        }
+    }
+}
+
+/// 向用户注册的 Webhook 推送剪贴板和设备事件（需要启用 `webhooks` feature）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebhookConfig {
+    /// 每个用户最多注册的 Webhook 数量
+    pub max_per_user: usize,
+    /// 是否允许 http:// 地址（仅用于开发和内网部署），默认只允许 https://
+    pub allow_http: bool,
+    /// 检查待投递事件的间隔（秒）
+    pub poll_interval_secs: u64,
+    /// 单次请求的超时时间（秒）
+    pub timeout_secs: u64,
+    /// 每个事件最多尝试的次数（包括第一次），用完后放弃
+    pub max_attempts: u32,
+    /// 第一次重试前等待的时间（秒），之后每次翻倍
+    pub retry_base_secs: i64,
+    /// 重试等待时间的上限（秒）
+    pub retry_max_secs: i64,
+    /// 已完成的投递记录保留的天数，0 表示永久保留
+    pub log_retention_days: i64,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            max_per_user: 10,
+            allow_http: false,
+            poll_interval_secs: 5,
+            timeout_secs: 10,
+            max_attempts: 8,
+            retry_base_secs: 30,
+            retry_max_secs: 6 * 3600,
+            log_retention_days: 30,
+        }
+    }
+}
+
+impl WebhookConfig {
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_secs(self.poll_interval_secs.max(1))
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+}
+
+/// 后台任务队列
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JobsConfig {
+    /// 检查待执行任务的间隔（秒）
+    pub poll_interval_secs: u64,
+    /// 每次检查最多同时执行的任务数
+    pub concurrency: i64,
+    /// 单个任务的执行超时（秒），超时视为失败
+    pub timeout_secs: u64,
+    /// 第一次重试前等待的时间（秒），之后每次翻倍
+    pub retry_base_secs: i64,
+    /// 重试等待时间的上限（秒）
+    pub retry_max_secs: i64,
+    /// 已完成的任务保留的天数，0 表示永久保留；死信任务保留到管理员处理为止
+    pub retention_days: i64,
+}
+
+impl Default for JobsConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval_secs: 5,
+            concurrency: 4,
+            timeout_secs: 300,
+            retry_base_secs: 30,
+            retry_max_secs: 3600,
+            retention_days: 7,
+        }
+    }
+}
+
+impl JobsConfig {
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_secs(self.poll_interval_secs.max(1))
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+}
+
+/// 局域网发现：以 mDNS（`_clipfocus._tcp`）广播本服务器，同一网络中的桌面客户端无需手动配置地址
+/// （需要启用 `mdns` feature）
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiscoveryConfig {
+    /// 是否广播本服务器并搜索同一网络中的其他服务器
+    pub enabled: bool,
+    /// 广播的实例名称，默认为主机名
+    pub instance_name: Option<String>,
+    /// 广播的端口，默认为 `server.port`（在反向代理后面时改为代理的端口）
+    pub port: Option<u16>,
+}
+
+/// gRPC 接口（需要启用 `grpc` feature），与 HTTP 服务使用不同的端口
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GrpcConfig {
+    pub enabled: bool,
+    /// 监听地址，默认与 `server.host` 相同
+    pub host: Option<String>,
+    pub port: u16,
+}
+
+impl Default for GrpcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: None,
+            port: 50051,
+        }
+    }
+}
+
+/// 内置 TLS（需要启用 `tls` feature），同时配置证书和私钥时 HTTP 服务改为 HTTPS（WebSocket 为 wss://）
+///
+/// 证书文件更新后（如 certbot 续期）自动重新加载，无需重启
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TlsConfig {
+    /// PEM 格式的证书链文件，也可以通过 `TLS_CERT` 环境变量指定
+    pub cert: Option<String>,
+    /// PEM 格式的私钥文件（PKCS#8、PKCS#1 或 SEC1），也可以通过 `TLS_KEY` 环境变量指定
+    pub key: Option<String>,
+}
+
+/// 跨域访问（CORS），网页和 Tauri 客户端访问 REST 接口以及 WebSocket 握手时按来源（Origin）检查，
+/// 不带 Origin 的请求（原生客户端）不受影响
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CorsConfig {
+    /// 允许的来源，如 `https://app.example.com`（协议、主机和端口，不带路径），`*` 表示任意来源；
+    /// 为空时只允许 Tauri 客户端，开发环境另外允许 localhost。
+    /// 环境变量写法：`CLIPFOCUS_CORS__ALLOWED_ORIGINS='["https://app.example.com"]'`
+    pub allowed_origins: Vec<String>,
+    /// 是否允许携带 Cookie 等凭据，不能与 `*` 同时使用
+    pub allow_credentials: bool,
+    /// 浏览器缓存预检结果的时间（秒）
+    pub max_age: usize,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: Vec::new(),
+            allow_credentials: false,
+            max_age: 3600,
+        }
+    }
+}
+
+/// 个人存储配额：剪贴板（包括文件）、定时投递和头像占用的总字节数
+///
+/// 管理员可以为单个用户设置配额（`PUT /admin/users/{id}/quota`），覆盖默认值；
+/// 用户所在组织设置了配额时同时检查组织配额
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuotaConfig {
+    /// 默认的个人配额（字节），不设置时不限制
+    pub default_user_bytes: Option<i64>,
+}
+
+/// 日志输出
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    /// 日志级别过滤，语法同 `RUST_LOG`（例如 `info,sqlx=warn`），设置了 `RUST_LOG` 时以环境变量为准
+    pub level: String,
+    /// 输出格式
+    pub format: LogFormat,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            format: LogFormat::Text,
+        }
+    }
+}
+
+/// 日志输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// 便于阅读的单行文本
+    Text,
+    /// 每行一个 JSON 对象，附带 span 字段，便于日志收集
+    Json,
+}
+
+impl Config {
+    /// 配置文件路径
+    pub fn path() -> String {
+        env::var("CLIPFOCUS_CONFIG").unwrap_or_else(|_| "clipfocus.toml".to_string())
+    }
+
+    /// 加载配置文件和环境变量
+    pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        let config = Figment::from(Serialized::defaults(Config::default()))
+            .merge(Toml::file(Self::path()))
+            .merge(Env::raw().only(&["DATABASE_URL"]).map(|_| "database.url".into()))
+            .merge(Env::raw().only(&["TLS_CERT", "TLS_KEY"]).split("_"))
+            .merge(Env::prefixed("CLIPFOCUS_").ignore(&["config"]).split("__"))
+            .extract()?;
+        Ok(config)
+    }
+}
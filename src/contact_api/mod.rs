@@ -0,0 +1,286 @@
+use actix_web::{Responder, delete, get, post, web};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    spatial_api::models::{AppState, PushEvent},
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiResponse, ErrorCode, ResponseData},
+    },
+    user_api::{User, auth::BearerToken},
+};
+
+pub fn contact_api() -> actix_web::Scope {
+    web::scope("/contacts")
+        .service(list_contacts)
+        .service(list_contact_requests)
+        .service(send_contact_request)
+        .service(accept_contact_request)
+        .service(reject_contact_request)
+        .service(list_blocked_users)
+        .service(block_user)
+        .service(unblock_user)
+        .service(remove_contact)
+}
+
+/// 联系人（对方的信息 + 关系状态）
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Contact {
+    pub user_id: String,
+    pub username: String,
+    pub head_uri: Option<String>,
+    /// pending / accepted
+    pub status: String,
+    /// 是否由当前用户发起
+    pub outgoing: bool,
+    pub updated_at: i64,
+}
+
+/// 黑名单中的用户
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BlockedUser {
+    pub user_id: String,
+    pub username: String,
+    pub blocked_at: i64,
+}
+
+// 按用户名或邮箱查找用户，其他组织的用户视为不存在
+async fn find_user(
+    user_id: &str,
+    username_or_email: &str,
+    pool: &DbPool,
+) -> Result<User, ApiResponse> {
+    let not_found = || ApiResponse::error(ErrorCode::UserNotFound, "用户不存在");
+    let target = db::get_user_by_username_or_email(username_or_email, pool)
+        .await
+        .map_err(|_| not_found())?;
+    match db::get_user_org(user_id, pool).await {
+        Ok(org) if org.as_ref().map(|(org_id, _)| org_id) == target.org_id.as_ref() => Ok(target),
+        Ok(_) => Err(not_found()),
+        Err(_) => Err(ApiResponse::error(ErrorCode::InternalError, "查找用户失败")),
+    }
+}
+
+/// 任意一方拉黑对方时返回 Blocked 错误，且不透露是谁拉黑了谁
+///
+/// 所有把一个用户的内容或邀请送达另一个用户的接口都需要先检查
+pub async fn ensure_not_blocked(
+    user_id: &str,
+    other_id: &str,
+    message: &str,
+    pool: &DbPool,
+) -> Result<(), ApiResponse> {
+    match db::is_blocked_between(user_id, other_id, pool).await {
+        Ok(false) => Ok(()),
+        Ok(true) => Err(ApiResponse::error(ErrorCode::Blocked, message)),
+        Err(_) => Err(ApiResponse::error(ErrorCode::InternalError, "查询黑名单失败")),
+    }
+}
+
+// 获取联系人列表
+#[get("")]
+async fn list_contacts(pool: web::Data<DbPool>, bearer_token: BearerToken) -> impl Responder {
+    match db::get_contacts(&bearer_token.user_id, "accepted", &pool).await {
+        Ok(contacts) => ApiResponse::new("获取联系人成功", ResponseData::Json(json!(contacts))),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取联系人失败"),
+    }
+}
+
+// 获取待处理的联系人请求（收到的和发出的）
+#[get("/requests")]
+async fn list_contact_requests(
+    pool: web::Data<DbPool>,
+    bearer_token: BearerToken,
+) -> impl Responder {
+    match db::get_contacts(&bearer_token.user_id, "pending", &pool).await {
+        Ok(requests) => ApiResponse::new("获取联系人请求成功", ResponseData::Json(json!(requests))),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取联系人请求失败"),
+    }
+}
+
+// 发起联系人请求
+#[derive(Deserialize)]
+pub struct ContactRequest {
+    pub username_or_email: String,
+}
+
+#[post("/requests")]
+async fn send_contact_request(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    request: web::Json<ContactRequest>,
+) -> impl Responder {
+    let target = match find_user(&bearer_token.user_id, &request.username_or_email, &pool).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    if target.user_id == bearer_token.user_id {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "不能添加自己为联系人");
+    }
+    let message = "无法向该用户发送联系人请求";
+    if let Err(response) =
+        ensure_not_blocked(&bearer_token.user_id, &target.user_id, message, &pool).await
+    {
+        return response;
+    }
+
+    match db::get_contact_relation(&bearer_token.user_id, &target.user_id, &pool).await {
+        Ok(Some((_, status))) if status == "accepted" => {
+            ApiResponse::error(ErrorCode::AlreadyExists, "对方已是联系人")
+        }
+        // 对方已向自己发起请求，直接接受
+        Ok(Some((requester_id, _))) if requester_id == target.user_id => {
+            match db::accept_contact_request(&target.user_id, &bearer_token.user_id, &pool).await {
+                Ok(_) => {
+                    notify_contact_accepted(&app_state, &target.user_id, &bearer_token);
+                    ApiResponse::new("已添加联系人", ResponseData::Null)
+                }
+                Err(_) => ApiResponse::error(ErrorCode::InternalError, "联系人请求发送失败"),
+            }
+        }
+        Ok(Some(_)) => ApiResponse::error(ErrorCode::AlreadyExists, "已发送过联系人请求"),
+        Ok(None) => {
+            match db::insert_contact_request(&bearer_token.user_id, &target.user_id, &pool).await {
+                Ok(_) => {
+                    info!(
+                        "用户 {} 向 {} 发起联系人请求",
+                        bearer_token.user_id, target.user_id
+                    );
+                    app_state.room_manager.do_send(PushEvent {
+                        user_id: target.user_id,
+                        event: "contact_request".to_string(),
+                        payload: json!({
+                            "user_id": bearer_token.user_id,
+                            "username": bearer_token.username,
+                        }),
+                    });
+                    ApiResponse::new("联系人请求已发送", ResponseData::Null)
+                }
+                Err(_) => ApiResponse::error(ErrorCode::InternalError, "联系人请求发送失败"),
+            }
+        }
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "联系人请求发送失败"),
+    }
+}
+
+// 通知请求发起方：请求已被接受
+fn notify_contact_accepted(app_state: &AppState, requester_id: &str, accepter: &BearerToken) {
+    app_state.room_manager.do_send(PushEvent {
+        user_id: requester_id.to_string(),
+        event: "contact_accepted".to_string(),
+        payload: json!({
+            "user_id": accepter.user_id,
+            "username": accepter.username,
+        }),
+    });
+}
+
+// 接受联系人请求
+#[post("/requests/{user_id}/accept")]
+async fn accept_contact_request(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    requester_id: web::Path<String>,
+) -> impl Responder {
+    let message = "无法接受该用户的联系人请求";
+    if let Err(response) =
+        ensure_not_blocked(&bearer_token.user_id, &requester_id, message, &pool).await
+    {
+        return response;
+    }
+    match db::accept_contact_request(&requester_id, &bearer_token.user_id, &pool).await {
+        Ok(true) => {
+            notify_contact_accepted(&app_state, &requester_id, &bearer_token);
+            ApiResponse::new("已添加联系人", ResponseData::Null)
+        }
+        Ok(false) => ApiResponse::error(ErrorCode::NotFound, "联系人请求不存在"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "接受联系人请求失败"),
+    }
+}
+
+// 拒绝收到的请求或撤回发出的请求
+#[delete("/requests/{user_id}")]
+async fn reject_contact_request(
+    pool: web::Data<DbPool>,
+    bearer_token: BearerToken,
+    other_id: web::Path<String>,
+) -> impl Responder {
+    match db::get_contact_relation(&bearer_token.user_id, &other_id, &pool).await {
+        Ok(Some((_, status))) if status == "pending" => {
+            match db::delete_contact(&bearer_token.user_id, &other_id, &pool).await {
+                Ok(_) => ApiResponse::new("联系人请求已删除", ResponseData::Null),
+                Err(_) => ApiResponse::error(ErrorCode::InternalError, "删除联系人请求失败"),
+            }
+        }
+        Ok(_) => ApiResponse::error(ErrorCode::NotFound, "联系人请求不存在"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "删除联系人请求失败"),
+    }
+}
+
+// 删除联系人
+#[delete("/{user_id}")]
+async fn remove_contact(
+    pool: web::Data<DbPool>,
+    bearer_token: BearerToken,
+    contact_id: web::Path<String>,
+) -> impl Responder {
+    match db::delete_contact(&bearer_token.user_id, &contact_id, &pool).await {
+        Ok(true) => ApiResponse::new("联系人已删除", ResponseData::Null),
+        Ok(false) => ApiResponse::error(ErrorCode::NotFound, "联系人不存在"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "删除联系人失败"),
+    }
+}
+
+// 获取黑名单
+#[get("/blocked")]
+async fn list_blocked_users(
+    pool: web::Data<DbPool>,
+    bearer_token: BearerToken,
+) -> impl Responder {
+    match db::get_blocked_users(&bearer_token.user_id, &pool).await {
+        Ok(users) => ApiResponse::new("获取黑名单成功", ResponseData::Json(json!(users))),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取黑名单失败"),
+    }
+}
+
+// 拉黑用户（会同时删除联系人关系和待处理的请求）
+#[post("/blocked")]
+async fn block_user(
+    pool: web::Data<DbPool>,
+    bearer_token: BearerToken,
+    request: web::Json<ContactRequest>,
+) -> impl Responder {
+    let target = match find_user(&bearer_token.user_id, &request.username_or_email, &pool).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    if target.user_id == bearer_token.user_id {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "不能拉黑自己");
+    }
+
+    match db::block_user(&bearer_token.user_id, &target.user_id, &pool).await {
+        Ok(_) => {
+            info!("用户 {} 拉黑了 {}", bearer_token.user_id, target.user_id);
+            ApiResponse::new("已拉黑该用户", ResponseData::Null)
+        }
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "拉黑失败"),
+    }
+}
+
+// 取消拉黑
+#[delete("/blocked/{user_id}")]
+async fn unblock_user(
+    pool: web::Data<DbPool>,
+    bearer_token: BearerToken,
+    blocked_id: web::Path<String>,
+) -> impl Responder {
+    match db::unblock_user(&bearer_token.user_id, &blocked_id, &pool).await {
+        Ok(true) => ApiResponse::new("已取消拉黑", ResponseData::Null),
+        Ok(false) => ApiResponse::error(ErrorCode::NotFound, "该用户不在黑名单中"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "取消拉黑失败"),
+    }
+}
@@ -0,0 +1,225 @@
+use actix_cors::Cors;
+use actix_web::http::{Method, Uri, header::HeaderValue};
+
+use crate::config::{Config, Environment};
+
+/// 未配置 `cors.allowed_origins` 时允许的来源：Tauri 客户端在各平台 WebView 中的来源
+const DEFAULT_ORIGINS: &[&str] = &[
+    "tauri://localhost",
+    "http://tauri.localhost",
+    "https://tauri.localhost",
+];
+
+/// 开发环境默认额外允许的主机（任意端口）
+const DEVELOPMENT_HOSTS: &[&str] = &["localhost", "127.0.0.1", "[::1]"];
+
+/// REST 接口允许的请求方法
+const REST_METHODS: [Method; 5] = [
+    Method::GET,
+    Method::POST,
+    Method::PUT,
+    Method::PATCH,
+    Method::DELETE,
+];
+
+/// REST 接口允许的请求头
+const REST_HEADERS: &[&str] = &[
+    "accept",
+    "authorization",
+    "content-type",
+    "if-match",
+    "if-none-match",
+    "idempotency-key",
+    "x-admin-token",
+    "x-chunk-checksum",
+    "x-request-id",
+    // 设备和客户端信息
+    "x-device-id",
+    "x-device-name",
+    "x-client-platform",
+    "x-client-version",
+];
+
+/// 实时同步接口（WebSocket 握手、SSE、长轮询）只使用 GET
+const REALTIME_METHODS: [Method; 1] = [Method::GET];
+
+/// 实时同步接口允许的请求头：令牌、设备和客户端信息，以及 SSE 断线重连
+const REALTIME_HEADERS: &[&str] = &[
+    "authorization",
+    "x-device-id",
+    "x-device-name",
+    "x-client-platform",
+    "x-client-version",
+    "last-event-id",
+];
+
+/// 网页可以读取的响应头
+const EXPOSED_HEADERS: &[&str] = &[
+    "content-disposition",
+    "etag",
+    "location",
+    "retry-after",
+    "x-request-id",
+];
+
+/// 允许的来源
+#[derive(Debug, Clone, PartialEq)]
+enum Origins {
+    Any,
+    /// 列出的来源；localhost 为 true 时另外允许 localhost 上的任意端口
+    List {
+        origins: Vec<String>,
+        localhost: bool,
+    },
+}
+
+/// 校验后的跨域访问策略，由配置创建一次，每个 worker 由此创建 CORS 中间件
+#[derive(Debug, Clone)]
+pub struct CorsPolicy {
+    origins: Origins,
+    allow_credentials: bool,
+    max_age: usize,
+}
+
+impl CorsPolicy {
+    /// 校验 `cors` 配置，未配置来源时按运行环境使用默认值
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        let cors = &config.cors;
+        let origins = if cors.allowed_origins.is_empty() {
+            Origins::List {
+                origins: DEFAULT_ORIGINS.iter().map(|o| o.to_string()).collect(),
+                localhost: config.server.environment == Environment::Development,
+            }
+        } else if cors.allowed_origins.iter().any(|o| o == "*") {
+            if cors.allowed_origins.len() > 1 {
+                return Err("cors.allowed_origins: \"*\" must be the only entry".to_string());
+            }
+            Origins::Any
+        } else {
+            for origin in &cors.allowed_origins {
+                validate_origin(origin)?;
+            }
+            Origins::List {
+                origins: cors.allowed_origins.clone(),
+                localhost: false,
+            }
+        };
+        if origins == Origins::Any && cors.allow_credentials {
+            return Err(
+                "cors.allow_credentials cannot be combined with allowed_origins = [\"*\"]"
+                    .to_string(),
+            );
+        }
+        Ok(CorsPolicy {
+            origins,
+            allow_credentials: cors.allow_credentials,
+            max_age: cors.max_age,
+        })
+    }
+
+    /// 创建 REST 接口的 CORS 中间件，来源不在允许范围内的请求返回 400
+    pub fn middleware(&self) -> Cors {
+        self.build(REST_METHODS, REST_HEADERS)
+    }
+
+    /// 创建实时同步接口的 CORS 中间件，只允许 GET 和握手需要的请求头，
+    /// 来源不在允许范围内的 WebSocket 握手同样返回 400
+    pub fn realtime_middleware(&self) -> Cors {
+        self.build(REALTIME_METHODS, REALTIME_HEADERS)
+    }
+
+    fn build(&self, methods: impl IntoIterator<Item = Method>, headers: &[&'static str]) -> Cors {
+        let mut cors = Cors::default()
+            .allowed_methods(methods)
+            .allowed_headers(headers.iter().copied())
+            .expose_headers(EXPOSED_HEADERS.iter().copied())
+            .max_age(self.max_age)
+            // 不只依赖浏览器检查响应头：WebSocket 握手不受同源策略限制，需要由服务器拒绝
+            .block_on_origin_mismatch(true);
+        match &self.origins {
+            Origins::Any => cors = cors.allow_any_origin(),
+            Origins::List { origins, localhost } => {
+                for origin in origins {
+                    cors = cors.allowed_origin(origin);
+                }
+                if *localhost {
+                    cors = cors.allowed_origin_fn(|origin, _| is_localhost(origin));
+                }
+            }
+        }
+        if self.allow_credentials {
+            cors = cors.supports_credentials();
+        }
+        cors
+    }
+}
+
+// 来源只能包含协议、主机和端口（浏览器发送的 Origin 不带路径和结尾的 /）
+fn validate_origin(origin: &str) -> Result<(), String> {
+    let invalid = || format!("cors.allowed_origins: invalid origin {}", origin);
+    let uri: Uri = origin.parse().map_err(|_| invalid())?;
+    if uri.scheme().is_none() || uri.host().is_none() || origin.ends_with('/') {
+        return Err(invalid());
+    }
+    Ok(())
+}
+
+// 是否为 localhost 上任意端口的 http / https 来源
+fn is_localhost(origin: &HeaderValue) -> bool {
+    let Ok(uri) = origin.to_str().unwrap_or_default().parse::<Uri>() else {
+        return false;
+    };
+    matches!(uri.scheme_str(), Some("http" | "https"))
+        && uri
+            .host()
+            .is_some_and(|host| DEVELOPMENT_HOSTS.contains(&host))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(origins: &[&str], allow_credentials: bool) -> Result<CorsPolicy, String> {
+        let mut config = Config::default();
+        config.cors.allowed_origins = origins.iter().map(|o| o.to_string()).collect();
+        config.cors.allow_credentials = allow_credentials;
+        CorsPolicy::from_config(&config)
+    }
+
+    #[test]
+    fn validates_configured_origins() {
+        assert!(policy(&["https://app.example.com", "tauri://localhost"], true).is_ok());
+        assert_eq!(policy(&["*"], false).unwrap().origins, Origins::Any);
+        assert!(policy(&["*"], true).is_err());
+        assert!(policy(&["*", "https://app.example.com"], false).is_err());
+        assert!(policy(&["https://app.example.com/"], false).is_err());
+        assert!(policy(&["app.example.com"], false).is_err());
+    }
+
+    #[test]
+    fn development_also_allows_localhost() {
+        let mut config = Config::default();
+        assert!(matches!(
+            CorsPolicy::from_config(&config).unwrap().origins,
+            Origins::List {
+                localhost: false,
+                ..
+            }
+        ));
+        config.server.environment = Environment::Development;
+        assert!(matches!(
+            CorsPolicy::from_config(&config).unwrap().origins,
+            Origins::List {
+                localhost: true,
+                ..
+            }
+        ));
+
+        let origin = |value| is_localhost(&HeaderValue::from_static(value));
+        assert!(origin("http://localhost:5173"));
+        assert!(origin("http://127.0.0.1:1420"));
+        assert!(origin("http://[::1]:8080"));
+        assert!(!origin("http://localhost.evil.com"));
+        assert!(!origin("file://localhost"));
+    }
+}
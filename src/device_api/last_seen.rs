@@ -0,0 +1,66 @@
+use actix::prelude::*;
+use log::{debug, info, warn};
+use std::time::Duration;
+
+use crate::spatial_api::models::{RoomManager, TakeDeviceLastSeen};
+use crate::sqlx_utils::{DbPool, db};
+
+/// 设备最近一次在线的时间（收到心跳或会话离开时记录）
+#[derive(Debug, Clone)]
+pub struct DeviceLastSeen {
+    pub user_id: String,
+    pub device_id: String,
+    pub last_seen_at: i64,
+}
+
+/// 周期性地把房间管理器记录的设备在线时间批量写入 `devices.last_seen_at`，
+/// 避免每次心跳都写数据库
+pub struct LastSeenRecorder {
+    pool: DbPool,
+    room_manager: Addr<RoomManager>,
+    flush_interval: Duration,
+}
+
+impl LastSeenRecorder {
+    pub fn new(pool: DbPool, room_manager: Addr<RoomManager>, flush_interval: Duration) -> Self {
+        Self {
+            pool,
+            room_manager,
+            flush_interval,
+        }
+    }
+}
+
+// 取出尚未写入的设备在线时间并批量写入，停机时也会调用一次
+pub async fn flush_last_seen(pool: &DbPool, room_manager: &Addr<RoomManager>) {
+    let devices = match room_manager.send(TakeDeviceLastSeen).await {
+        Ok(devices) => devices,
+        Err(e) => {
+            warn!("取出设备在线时间失败: {}", e);
+            return;
+        }
+    };
+    if devices.is_empty() {
+        return;
+    }
+    match db::touch_devices_at(&devices, pool).await {
+        Ok(()) => debug!("已更新 {} 个设备的在线时间", devices.len()),
+        Err(e) => warn!("更新 {} 个设备的在线时间失败: {}", devices.len(), e),
+    }
+}
+
+impl Actor for LastSeenRecorder {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        info!("LastSeenRecorder started");
+
+        ctx.run_interval(self.flush_interval, |act, ctx| {
+            let pool = act.pool.clone();
+            let room_manager = act.room_manager.clone();
+            async move { flush_last_seen(&pool, &room_manager).await }
+                .into_actor(act)
+                .wait(ctx);
+        });
+    }
+}
@@ -0,0 +1,500 @@
+use actix::Addr;
+use actix_web::{HttpRequest, Responder, delete, get, post, put, web};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::{HashMap, HashSet};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+use crate::{
+    audit::{AuditEvent, AuditRecord},
+    models::ClipType,
+    spatial_api::models::{
+        AppState, DisconnectDevice, GetSessions, PauseDeviceSync, PushEvent, RoomManager,
+        SetSyncRules,
+    },
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiEnvelope, ApiResponse, Cursor, ErrorCode, Page, ResponseData},
+    },
+    user_api::auth::BearerToken,
+    webhook_api::{self, WebhookEvent},
+};
+
+pub mod last_seen;
+
+pub fn device_api() -> actix_web::Scope {
+    web::scope("/devices")
+        .service(list_devices)
+        .service(register_device)
+        .service(rename_device)
+        .service(revoke_device)
+        .service(get_sync_rule)
+        .service(set_sync_rule)
+        .service(delete_sync_rule)
+        .service(pause_sync)
+}
+
+/// 设备接口的 OpenAPI 文档
+#[derive(OpenApi)]
+#[openapi(paths(
+    list_devices,
+    register_device,
+    rename_device,
+    revoke_device,
+    get_sync_rule,
+    set_sync_rule,
+    delete_sync_rule,
+    pause_sync
+))]
+pub struct DeviceApiDoc;
+
+/// 设备列表单页的默认 / 最大数量
+const DEFAULT_LIST_LIMIT: i64 = 50;
+const MAX_LIST_LIMIT: i64 = 200;
+
+/// 设备名称的最大长度（字符数）
+const MAX_DEVICE_NAME_CHARS: usize = 64;
+
+/// 已注册的设备
+///
+/// 设备 ID 由服务端生成，客户端在 WebSocket 握手（`device_id`）和创建剪贴板时携带
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct Device {
+    pub id: String,
+    pub name: String,
+    pub platform: Option<String>,
+    pub created_at: i64,
+    /// 最近一次在线的时间：建立 WebSocket 连接、收到心跳或会话断开时记录，
+    /// 心跳和断开时间定期批量写入，可能比实际晚一个写入周期
+    pub last_seen_at: Option<i64>,
+    /// 注销时间，注销后的设备不能再连接
+    pub revoked_at: Option<i64>,
+}
+
+// 校验设备名称，返回去掉首尾空白后的名称
+fn device_name(name: &str) -> Result<String, &'static str> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("设备名称不能为空");
+    }
+    if name.chars().count() > MAX_DEVICE_NAME_CHARS {
+        return Err("设备名称过长");
+    }
+    Ok(name.to_string())
+}
+
+/// 设备列表中的设备，附带在线状态
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DeviceStatus {
+    #[serde(flatten)]
+    pub device: Device,
+    /// 当前是否有 WebSocket 会话
+    pub online: bool,
+}
+
+// 获取设备列表，按注册时间排序，按 next_cursor 翻页
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeviceQuery {
+    /// 是否包含已注销的设备
+    #[serde(default)]
+    pub include_revoked: bool,
+    pub limit: Option<i64>,
+    /// 上一页返回的 next_cursor
+    #[param(value_type = Option<String>)]
+    pub cursor: Option<Cursor>,
+}
+
+#[utoipa::path(
+    summary = "获取设备列表",
+    params(DeviceQuery),
+    security(("bearer" = [])),
+    responses((status = 200, body = ApiEnvelope<Page<DeviceStatus>>)),
+)]
+#[get("")]
+async fn list_devices(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    query: web::Query<DeviceQuery>,
+) -> impl Responder {
+    let user_id = &bearer_token.user_id;
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_LIST_LIMIT)
+        .clamp(1, MAX_LIST_LIMIT);
+    // 多查询一条以判断是否还有下一页
+    let result = tokio::try_join!(
+        db::get_devices(
+            user_id,
+            query.include_revoked,
+            query.cursor.as_ref(),
+            limit + 1,
+            &pool,
+        ),
+        db::count_devices(user_id, query.include_revoked, &pool),
+    );
+    let (devices, total) = match result {
+        Ok(result) => result,
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "获取设备列表失败"),
+    };
+
+    // 当前有 WebSocket 会话的设备标记为在线
+    let online: HashSet<String> = app_state
+        .room_manager
+        .send(GetSessions {
+            user_id: user_id.clone(),
+        })
+        .await
+        .map(|sessions| {
+            sessions
+                .into_iter()
+                .filter_map(|session| session.client.device_id)
+                .collect()
+        })
+        .unwrap_or_default();
+    let devices = devices
+        .into_iter()
+        .map(|device| DeviceStatus {
+            online: online.contains(&device.id),
+            device,
+        })
+        .collect();
+    let page = Page::new(devices, total, limit as usize, |status: &DeviceStatus| {
+        Cursor {
+            at: status.device.created_at,
+            id: status.device.id.clone(),
+        }
+    });
+    ApiResponse::new("获取设备列表成功", ResponseData::Json(json!(page)))
+}
+
+// 注册设备
+#[derive(Deserialize, ToSchema)]
+pub struct RegisterDevice {
+    pub name: String,
+    pub platform: Option<String>,
+}
+
+#[utoipa::path(
+    summary = "注册设备",
+    security(("bearer" = [])),
+    responses((status = 200, body = ApiEnvelope<Device>)),
+)]
+#[post("")]
+async fn register_device(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    register: web::Json<RegisterDevice>,
+) -> impl Responder {
+    let register = register.into_inner();
+    let name = match device_name(&register.name) {
+        Ok(name) => name,
+        Err(reason) => return ApiResponse::error(ErrorCode::InvalidRequest, reason),
+    };
+    let device = Device {
+        id: uuid::Uuid::new_v4().to_string(),
+        name,
+        platform: register
+            .platform
+            .filter(|platform| !platform.trim().is_empty()),
+        created_at: chrono::Utc::now().timestamp(),
+        last_seen_at: None,
+        revoked_at: None,
+    };
+
+    match db::insert_device(&bearer_token.user_id, &device, &pool).await {
+        Ok(_) => {
+            info!(
+                "用户 {} 注册设备 {} ({})",
+                bearer_token.user_id, device.name, device.id
+            );
+            AuditRecord::new(AuditEvent::DeviceRegistered, &req, &app_state)
+                .user(&bearer_token.user_id)
+                .detail(json!({ "device_id": device.id, "name": device.name }))
+                .record(&pool)
+                .await;
+            webhook_api::enqueue(
+                &bearer_token.user_id,
+                WebhookEvent::DeviceRegistered,
+                json!(device),
+                &pool,
+            )
+            .await;
+            app_state.room_manager.do_send(PushEvent {
+                user_id: bearer_token.user_id,
+                event: "device_registered".to_string(),
+                payload: json!(device),
+            });
+            ApiResponse::new("设备注册成功", ResponseData::Json(json!(device)))
+        }
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "设备注册失败"),
+    }
+}
+
+// 重命名设备
+#[derive(Deserialize, ToSchema)]
+pub struct RenameDevice {
+    pub name: String,
+}
+
+#[utoipa::path(
+    summary = "重命名设备",
+    security(("bearer" = [])),
+    responses((status = 200, body = ApiEnvelope<Device>)),
+)]
+#[put("/{id}")]
+async fn rename_device(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    id: web::Path<String>,
+    rename: web::Json<RenameDevice>,
+) -> impl Responder {
+    let name = match device_name(&rename.name) {
+        Ok(name) => name,
+        Err(reason) => return ApiResponse::error(ErrorCode::InvalidRequest, reason),
+    };
+
+    match db::rename_device(&bearer_token.user_id, &id, &name, &pool).await {
+        Ok(Some(device)) => {
+            app_state.room_manager.do_send(PushEvent {
+                user_id: bearer_token.user_id,
+                event: "device_renamed".to_string(),
+                payload: json!(device),
+            });
+            ApiResponse::new("设备重命名成功", ResponseData::Json(json!(device)))
+        }
+        Ok(None) => ApiResponse::error(ErrorCode::NotFound, "设备不存在或已注销"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "设备重命名失败"),
+    }
+}
+
+// 注销设备：断开该设备的在线会话，之后该设备不能再连接或上传剪贴板
+#[utoipa::path(
+    summary = "注销设备",
+    security(("bearer" = [])),
+    responses((status = 200, description = "data 为 id 和断开的会话数", body = ApiEnvelope<Value>)),
+)]
+#[delete("/{id}")]
+async fn revoke_device(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    id: web::Path<String>,
+) -> impl Responder {
+    match db::revoke_device(&bearer_token.user_id, &id, &pool).await {
+        Ok(true) => {}
+        Ok(false) => return ApiResponse::error(ErrorCode::NotFound, "设备不存在或已注销"),
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "设备注销失败"),
+    }
+    info!("用户 {} 注销设备 {}", bearer_token.user_id, id);
+    AuditRecord::new(AuditEvent::DeviceRevoked, &req, &app_state)
+        .user(&bearer_token.user_id)
+        .detail(json!({ "device_id": *id }))
+        .record(&pool)
+        .await;
+
+    let disconnected = app_state
+        .room_manager
+        .send(DisconnectDevice {
+            user_id: bearer_token.user_id.clone(),
+            device_id: id.to_string(),
+        })
+        .await
+        .unwrap_or_else(|e| {
+            warn!("disconnect device {} failed: {}", id, e);
+            0
+        });
+    app_state.room_manager.do_send(PushEvent {
+        user_id: bearer_token.user_id,
+        event: "device_revoked".to_string(),
+        payload: json!({ "id": *id }),
+    });
+    ApiResponse::new(
+        "设备已注销",
+        ResponseData::Json(json!({ "id": *id, "disconnected_sessions": disconnected })),
+    )
+}
+
+/// 设备的同步规则，只有符合规则的剪贴板才会推送到该设备或出现在它的增量同步中
+///
+/// 没有设置规则的设备接收所有剪贴板
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct SyncRule {
+    /// 只同步这些类型，为空时不限制类型
+    pub clip_types: Option<Vec<ClipType>>,
+    /// 只同步不超过该大小（字节）的剪贴板，为空时不限制大小
+    pub max_size: Option<i64>,
+}
+
+impl SyncRule {
+    /// 是否同步该类型和大小的剪贴板
+    pub fn allows(&self, content_type: ClipType, size: i64) -> bool {
+        self.clip_types
+            .as_ref()
+            .is_none_or(|types| types.contains(&content_type))
+            && self.max_size.is_none_or(|max_size| size <= max_size)
+    }
+}
+
+/// 从数据库加载用户设备的同步规则，同步到房间管理器
+pub async fn sync_device_rules(
+    user_id: &str,
+    pool: &DbPool,
+    room_manager: &Addr<RoomManager>,
+) -> Result<(), sqlx::Error> {
+    let rules = db::get_device_sync_rules(user_id, pool).await?;
+    room_manager.do_send(SetSyncRules {
+        user_id: user_id.to_string(),
+        rules: rules.into_iter().collect::<HashMap<_, _>>(),
+    });
+    Ok(())
+}
+
+// 获取设备的同步规则，没有设置时 data 为 null
+#[utoipa::path(
+    summary = "获取设备的同步规则",
+    security(("bearer" = [])),
+    responses((status = 200, body = ApiEnvelope<Option<SyncRule>>)),
+)]
+#[get("/{id}/sync_rules")]
+async fn get_sync_rule(
+    pool: web::Data<DbPool>,
+    bearer_token: BearerToken,
+    id: web::Path<String>,
+) -> impl Responder {
+    match db::get_device_sync_rule(&bearer_token.user_id, &id, &pool).await {
+        Ok(rule) => ApiResponse::new("获取同步规则成功", ResponseData::Json(json!(rule))),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取同步规则失败"),
+    }
+}
+
+// 设置设备的同步规则，覆盖之前的规则，之后的推送和增量同步立即生效
+#[utoipa::path(
+    summary = "设置设备的同步规则",
+    security(("bearer" = [])),
+    responses((status = 200, body = ApiEnvelope<SyncRule>)),
+)]
+#[put("/{id}/sync_rules")]
+async fn set_sync_rule(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    id: web::Path<String>,
+    rule: web::Json<SyncRule>,
+) -> impl Responder {
+    let user_id = &bearer_token.user_id;
+    if rule.max_size.is_some_and(|max_size| max_size < 0) {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "max_size 不能为负数");
+    }
+    match db::get_active_device(user_id, &id, &pool).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return ApiResponse::error(ErrorCode::NotFound, "设备不存在或已注销"),
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "设置同步规则失败"),
+    }
+    if db::set_device_sync_rule(user_id, &id, &rule, &pool)
+        .await
+        .is_err()
+    {
+        return ApiResponse::error(ErrorCode::InternalError, "设置同步规则失败");
+    }
+    info!("用户 {} 设置设备 {} 的同步规则", user_id, id);
+    sync_rules_changed(user_id, &pool, &app_state.room_manager).await;
+    ApiResponse::new(
+        "同步规则已设置",
+        ResponseData::Json(json!(rule.into_inner())),
+    )
+}
+
+// 删除设备的同步规则，之后该设备接收所有剪贴板
+#[utoipa::path(
+    summary = "删除设备的同步规则",
+    security(("bearer" = [])),
+    responses((status = 200, body = ApiEnvelope<Value>)),
+)]
+#[delete("/{id}/sync_rules")]
+async fn delete_sync_rule(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    id: web::Path<String>,
+) -> impl Responder {
+    let user_id = &bearer_token.user_id;
+    match db::delete_device_sync_rule(user_id, &id, &pool).await {
+        Ok(true) => {
+            sync_rules_changed(user_id, &pool, &app_state.room_manager).await;
+            ApiResponse::new("同步规则已删除", ResponseData::Null)
+        }
+        Ok(false) => ApiResponse::error(ErrorCode::NotFound, "设备没有设置同步规则"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "删除同步规则失败"),
+    }
+}
+
+/// 暂停或恢复设备的同步
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SyncPause {
+    pub paused: bool,
+}
+
+// 暂停或恢复设备的剪贴板同步（用于屏幕共享、输入密码等场景），对之后建立的会话同样生效，
+// 暂停期间的剪贴板按 spatial.paused_sync_policy 暂存到恢复时发送或直接丢弃；
+// 暂停状态只保存在内存中，服务器重启后恢复同步
+#[utoipa::path(
+    summary = "暂停或恢复设备的同步",
+    request_body = SyncPause,
+    security(("bearer" = [])),
+    responses((status = 200, description = "data 为 id、paused 和在线的会话数", body = ApiEnvelope<Value>)),
+)]
+#[put("/{id}/sync_pause")]
+async fn pause_sync(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    id: web::Path<String>,
+    pause: web::Json<SyncPause>,
+) -> impl Responder {
+    let user_id = &bearer_token.user_id;
+    match db::get_active_device(user_id, &id, &pool).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return ApiResponse::error(ErrorCode::NotFound, "设备不存在或已注销"),
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "设置同步状态失败"),
+    }
+    let sessions = match app_state
+        .room_manager
+        .send(PauseDeviceSync {
+            user_id: user_id.clone(),
+            device_id: id.to_string(),
+            paused: pause.paused,
+        })
+        .await
+    {
+        Ok(sessions) => sessions,
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "设置同步状态失败"),
+    };
+    info!(
+        "用户 {} {}设备 {} 的同步",
+        user_id,
+        if pause.paused { "暂停" } else { "恢复" },
+        id
+    );
+    let message = if pause.paused {
+        "同步已暂停"
+    } else {
+        "同步已恢复"
+    };
+    ApiResponse::new(
+        message,
+        ResponseData::Json(json!({ "id": *id, "paused": pause.paused, "sessions": sessions })),
+    )
+}
+
+async fn sync_rules_changed(user_id: &str, pool: &DbPool, room_manager: &Addr<RoomManager>) {
+    if let Err(e) = sync_device_rules(user_id, pool, room_manager).await {
+        warn!("reload sync rules for {} failed: {}", user_id, e);
+    }
+}
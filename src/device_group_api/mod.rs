@@ -0,0 +1,338 @@
+use actix::Addr;
+use actix_web::dev::Payload;
+use actix_web::{Error, FromRequest, HttpRequest, Responder, delete, get, post, put, web};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashSet;
+use std::future::{Ready, ready};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    spatial_api::models::{AppState, PushEvent, PushToDevices, RoomManager, SetDeviceRouting},
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiResponse, ErrorCode, ResponseData},
+    },
+    user_api::auth::BearerToken,
+};
+
+pub fn device_group_api() -> actix_web::Scope {
+    web::scope("/device_groups")
+        .service(list_device_groups)
+        .service(create_device_group)
+        .service(update_device_group)
+        .service(delete_device_group)
+}
+
+/// 设备分组（如 "work"、"home"）
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeviceGroup {
+    pub id: String,
+    pub name: String,
+    /// 隔离分组：组内设备产生的剪贴板只同步到同组设备
+    pub isolated: bool,
+    /// 组内设备 ID（与 WebSocket 握手时的 device_id 一致）
+    pub device_ids: Vec<String>,
+    pub created_at: i64,
+}
+
+/// 剪贴板的可见范围，默认所有设备可见
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "scope", rename_all = "snake_case")]
+pub enum Visibility {
+    #[default]
+    All,
+    /// 仅指定设备分组内的设备可见
+    Group { group_id: String },
+    /// 仅指定设备可见
+    Device { device_id: String },
+}
+
+impl Visibility {
+    pub fn is_all(&self) -> bool {
+        *self == Visibility::All
+    }
+
+    /// 可见的设备 ID，None 表示所有设备
+    pub fn device_ids(&self, groups: &[DeviceGroup]) -> Option<HashSet<String>> {
+        match self {
+            Visibility::All => None,
+            Visibility::Group { group_id } => Some(
+                groups
+                    .iter()
+                    .filter(|group| group.id == *group_id)
+                    .flat_map(|group| group.device_ids.iter().cloned())
+                    .collect(),
+            ),
+            Visibility::Device { device_id } => Some(HashSet::from([device_id.clone()])),
+        }
+    }
+
+    /// 指定设备是否可见，未提供设备 ID 的请求只能看到所有设备可见的剪贴板
+    pub fn allows(&self, device_id: Option<&str>, groups: &[DeviceGroup]) -> bool {
+        match self.device_ids(groups) {
+            Some(devices) => device_id.is_some_and(|device_id| devices.contains(device_id)),
+            None => true,
+        }
+    }
+
+    /// 校验可见范围引用的分组属于该用户
+    pub fn validate(&self, groups: &[DeviceGroup]) -> Result<(), ApiResponse> {
+        match self {
+            Visibility::Group { group_id } if !groups.iter().any(|group| group.id == *group_id) => {
+                Err(ApiResponse::error(ErrorCode::NotFound, "设备分组不存在"))
+            }
+            Visibility::Device { device_id } if device_id.trim().is_empty() => Err(
+                ApiResponse::error(ErrorCode::InvalidRequest, "设备 ID 不能为空"),
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// 请求方的设备 ID，取自请求头 `X-Device-Id` 或查询参数 `device_id`
+#[derive(IntoParams)]
+#[into_params(names("X-Device-Id"), parameter_in = Header)]
+pub struct DeviceId(pub Option<String>);
+
+impl FromRequest for DeviceId {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let device_id = req
+            .headers()
+            .get("X-Device-Id")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string())
+            .or_else(|| {
+                web::Query::<DeviceIdQuery>::from_query(req.query_string())
+                    .ok()
+                    .and_then(|query| query.into_inner().device_id)
+            })
+            .filter(|v| !v.is_empty());
+        ready(Ok(DeviceId(device_id)))
+    }
+}
+
+#[derive(Deserialize)]
+struct DeviceIdQuery {
+    device_id: Option<String>,
+}
+
+/// 从数据库加载用户的分组，同步路由规则到房间管理器
+pub async fn sync_device_routing(
+    user_id: &str,
+    pool: &DbPool,
+    room_manager: &Addr<RoomManager>,
+) -> Result<Vec<DeviceGroup>, sqlx::Error> {
+    let groups = db::get_device_groups(user_id, pool).await?;
+    room_manager.do_send(SetDeviceRouting {
+        user_id: user_id.to_string(),
+        isolated_groups: groups
+            .iter()
+            .filter(|group| group.isolated)
+            .map(|group| group.device_ids.iter().cloned().collect())
+            .collect(),
+    });
+    Ok(groups)
+}
+
+// 按可见范围推送事件：所有设备可见时推送到整个房间，否则只推送给范围内的设备
+pub async fn push_visible(
+    user_id: &str,
+    visibility: &Visibility,
+    event: &str,
+    payload: serde_json::Value,
+    pool: &DbPool,
+    room_manager: &Addr<RoomManager>,
+) -> Result<(), sqlx::Error> {
+    if visibility.is_all() {
+        room_manager.do_send(PushEvent {
+            user_id: user_id.to_string(),
+            event: event.to_string(),
+            payload,
+        });
+        return Ok(());
+    }
+    // 可见范围按推送时的分组成员计算
+    let groups = db::get_device_groups(user_id, pool).await?;
+    room_manager.do_send(PushToDevices {
+        user_id: user_id.to_string(),
+        device_ids: visibility.device_ids(&groups).unwrap_or_default(),
+        event: event.to_string(),
+        payload,
+    });
+    Ok(())
+}
+
+// 按可见范围推送事件并等待推送完成，返回收到事件的会话数
+pub async fn deliver_visible(
+    user_id: &str,
+    visibility: &Visibility,
+    event: &str,
+    payload: serde_json::Value,
+    pool: &DbPool,
+    room_manager: &Addr<RoomManager>,
+) -> Result<usize, sqlx::Error> {
+    let delivered = if visibility.is_all() {
+        room_manager
+            .send(PushEvent {
+                user_id: user_id.to_string(),
+                event: event.to_string(),
+                payload,
+            })
+            .await
+    } else {
+        let groups = db::get_device_groups(user_id, pool).await?;
+        room_manager
+            .send(PushToDevices {
+                user_id: user_id.to_string(),
+                device_ids: visibility.device_ids(&groups).unwrap_or_default(),
+                event: event.to_string(),
+                payload,
+            })
+            .await
+    };
+    // 房间管理器不可用时视为未送达
+    Ok(delivered.unwrap_or(0))
+}
+
+// 分组修改后更新路由规则并通知用户的所有设备
+async fn groups_changed(user_id: &str, pool: &DbPool, app_state: &AppState) {
+    match sync_device_routing(user_id, pool, &app_state.room_manager).await {
+        Ok(groups) => app_state.room_manager.do_send(PushEvent {
+            user_id: user_id.to_string(),
+            event: "device_groups_changed".to_string(),
+            payload: json!(groups),
+        }),
+        Err(e) => warn!("reload device groups for {} failed: {}", user_id, e),
+    }
+}
+
+// 获取设备分组
+#[get("")]
+async fn list_device_groups(
+    pool: web::Data<DbPool>,
+    bearer_token: BearerToken,
+) -> impl Responder {
+    match db::get_device_groups(&bearer_token.user_id, &pool).await {
+        Ok(groups) => ApiResponse::new("获取设备分组成功", ResponseData::Json(json!(groups))),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取设备分组失败"),
+    }
+}
+
+// 创建设备分组
+#[derive(Deserialize)]
+pub struct CreateDeviceGroup {
+    pub name: String,
+    #[serde(default)]
+    pub isolated: bool,
+    #[serde(default)]
+    pub device_ids: Vec<String>,
+}
+
+#[post("")]
+async fn create_device_group(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    create: web::Json<CreateDeviceGroup>,
+) -> impl Responder {
+    let create = create.into_inner();
+    let name = create.name.trim();
+    if name.is_empty() {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "分组名称不能为空");
+    }
+
+    let mut device_ids = create.device_ids;
+    device_ids.sort();
+    device_ids.dedup();
+    let group = DeviceGroup {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: name.to_string(),
+        isolated: create.isolated,
+        device_ids,
+        created_at: chrono::Utc::now().timestamp(),
+    };
+    info!("用户 {} 创建设备分组 {}", bearer_token.user_id, group.name);
+
+    match db::insert_device_group(&bearer_token.user_id, &group, &pool).await {
+        Ok(true) => {
+            groups_changed(&bearer_token.user_id, &pool, &app_state).await;
+            ApiResponse::new("设备分组创建成功", ResponseData::Json(json!(group)))
+        }
+        Ok(false) => ApiResponse::error(ErrorCode::AlreadyExists, "分组名称已存在"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "设备分组创建失败"),
+    }
+}
+
+// 修改设备分组（只修改提供的字段）
+#[derive(Deserialize)]
+pub struct UpdateDeviceGroup {
+    pub name: Option<String>,
+    pub isolated: Option<bool>,
+    pub device_ids: Option<Vec<String>>,
+}
+
+#[put("/{id}")]
+async fn update_device_group(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    id: web::Path<String>,
+    update: web::Json<UpdateDeviceGroup>,
+) -> impl Responder {
+    let groups = match db::get_device_groups(&bearer_token.user_id, &pool).await {
+        Ok(groups) => groups,
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "设备分组修改失败"),
+    };
+    let Some(mut group) = groups.into_iter().find(|group| group.id == *id) else {
+        return ApiResponse::error(ErrorCode::NotFound, "设备分组不存在");
+    };
+
+    let update = update.into_inner();
+    if let Some(name) = update.name {
+        let name = name.trim();
+        if name.is_empty() {
+            return ApiResponse::error(ErrorCode::InvalidRequest, "分组名称不能为空");
+        }
+        group.name = name.to_string();
+    }
+    if let Some(isolated) = update.isolated {
+        group.isolated = isolated;
+    }
+    if let Some(mut device_ids) = update.device_ids {
+        device_ids.sort();
+        device_ids.dedup();
+        group.device_ids = device_ids;
+    }
+
+    match db::update_device_group(&bearer_token.user_id, &group, &pool).await {
+        Ok(true) => {
+            groups_changed(&bearer_token.user_id, &pool, &app_state).await;
+            ApiResponse::new("设备分组修改成功", ResponseData::Json(json!(group)))
+        }
+        Ok(false) => ApiResponse::error(ErrorCode::AlreadyExists, "分组名称已存在"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "设备分组修改失败"),
+    }
+}
+
+// 删除设备分组
+#[delete("/{id}")]
+async fn delete_device_group(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    id: web::Path<String>,
+) -> impl Responder {
+    match db::delete_device_group(&bearer_token.user_id, &id, &pool).await {
+        Ok(true) => {
+            groups_changed(&bearer_token.user_id, &pool, &app_state).await;
+            ApiResponse::new("设备分组已删除", ResponseData::Null)
+        }
+        Ok(false) => ApiResponse::error(ErrorCode::NotFound, "设备分组不存在"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "设备分组删除失败"),
+    }
+}
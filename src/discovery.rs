@@ -0,0 +1,241 @@
+use log::warn;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::sync::{Arc, Mutex};
+
+use crate::config::Config;
+
+/// 局域网内发现的其他 ClipFocus 服务器
+#[derive(Debug, Clone, Serialize)]
+pub struct LanPeer {
+    /// 服务实例全名，如 `office._clipfocus._tcp.local.`
+    pub name: String,
+    pub host: String,
+    pub addresses: Vec<String>,
+    pub port: u16,
+    /// TXT 记录：version、protocol、path、node_id
+    pub properties: BTreeMap<String, String>,
+    /// 最近一次解析到该服务的时间
+    pub last_seen_at: i64,
+}
+
+type Peers = Arc<Mutex<HashMap<String, LanPeer>>>;
+
+/// 局域网发现：以 mDNS 广播本服务器，并记录同一网络中发现的其他服务器
+///
+/// 未启用（`discovery.enabled` 为 false 或未启用 `mdns` feature）时不广播，节点列表为空
+#[derive(Clone, Default)]
+pub struct LanDiscovery {
+    peers: Peers,
+    service: Option<Arc<transport::Service>>,
+}
+
+impl LanDiscovery {
+    /// 按配置开始广播和搜索，失败时只记录日志
+    pub fn start(config: &Config) -> Self {
+        let mut discovery = LanDiscovery::default();
+        if !config.discovery.enabled {
+            return discovery;
+        }
+        let instance = config
+            .discovery
+            .instance_name
+            .clone()
+            .unwrap_or_else(default_instance_name);
+        let port = config.discovery.port.unwrap_or(config.server.port);
+        match transport::start(
+            &instance,
+            &host_name(&instance),
+            port,
+            &config.cluster.node_id,
+            discovery.peers.clone(),
+        ) {
+            Ok(service) => discovery.service = Some(Arc::new(service)),
+            Err(e) => warn!("LAN discovery disabled: {}", e),
+        }
+        discovery
+    }
+
+    /// 是否正在广播本服务器
+    pub fn is_active(&self) -> bool {
+        self.service.is_some()
+    }
+
+    /// 发现的服务器，按名称排序
+    pub fn peers(&self) -> Vec<LanPeer> {
+        let mut peers: Vec<LanPeer> = self
+            .peers
+            .lock()
+            .map(|peers| peers.values().cloned().collect())
+            .unwrap_or_default();
+        peers.sort_by(|a, b| a.name.cmp(&b.name));
+        peers
+    }
+
+    /// 停机时撤销广播，让其他设备尽快移除本服务器
+    pub fn shutdown(&self) {
+        if let Some(service) = &self.service {
+            service.shutdown();
+        }
+    }
+}
+
+// 默认的实例名称：主机名，取不到时为 clipfocus
+fn default_instance_name() -> String {
+    ["HOSTNAME", "COMPUTERNAME"]
+        .iter()
+        .filter_map(|key| env::var(key).ok())
+        .chain(std::fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .find(|name| !name.is_empty())
+        .unwrap_or_else(|| "clipfocus".to_string())
+}
+
+// mDNS 主机名只保留字母、数字和连字符，如 `office-pc.local.`
+fn host_name(instance: &str) -> String {
+    let label: String = instance
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let label = label.trim_matches('-');
+    format!(
+        "{}.local.",
+        if label.is_empty() { "clipfocus" } else { label }
+    )
+}
+
+#[cfg(feature = "mdns")]
+mod transport {
+    use log::{info, warn};
+    use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+
+    use super::{LanPeer, Peers};
+    use crate::spatial_api::models::PROTOCOL_VERSION;
+
+    /// 广播和搜索的 mDNS 服务类型
+    const SERVICE_TYPE: &str = "_clipfocus._tcp.local.";
+
+    /// 运行中的 mDNS 服务（独立线程），保存本服务器的实例全名用于撤销广播
+    pub struct Service {
+        daemon: ServiceDaemon,
+        fullname: String,
+    }
+
+    impl Service {
+        pub fn shutdown(&self) {
+            if let Err(e) = self.daemon.unregister(&self.fullname) {
+                warn!("unregister mDNS service failed: {}", e);
+            }
+            let _ = self.daemon.shutdown();
+        }
+    }
+
+    pub fn start(
+        instance: &str,
+        host: &str,
+        port: u16,
+        node_id: &str,
+        peers: Peers,
+    ) -> Result<Service, String> {
+        let daemon = ServiceDaemon::new().map_err(|e| e.to_string())?;
+        let protocol = PROTOCOL_VERSION.to_string();
+        let properties = [
+            ("version", env!("CARGO_PKG_VERSION")),
+            ("protocol", protocol.as_str()),
+            ("path", "/api/v1"),
+            ("node_id", node_id),
+        ];
+        let service = ServiceInfo::new(SERVICE_TYPE, instance, host, "", port, &properties[..])
+            .map_err(|e| e.to_string())?
+            .enable_addr_auto();
+        let fullname = service.get_fullname().to_string();
+        daemon.register(service).map_err(|e| e.to_string())?;
+        let events = daemon.browse(SERVICE_TYPE).map_err(|e| e.to_string())?;
+        info!("Advertising {} on port {} via mDNS", fullname, port);
+
+        let node_id = node_id.to_string();
+        actix::spawn(async move {
+            while let Ok(event) = events.recv_async().await {
+                handle_event(event, &node_id, &peers);
+            }
+        });
+        Ok(Service { daemon, fullname })
+    }
+
+    // 记录解析到的服务器（忽略本节点自己的广播），服务撤销时移除
+    fn handle_event(event: ServiceEvent, node_id: &str, peers: &Peers) {
+        let Ok(mut peers) = peers.lock() else {
+            return;
+        };
+        match event {
+            ServiceEvent::ServiceResolved(service) => {
+                if service.get_property_val_str("node_id") == Some(node_id) {
+                    return;
+                }
+                let mut addresses: Vec<String> = service
+                    .get_addresses()
+                    .iter()
+                    .map(|address| address.to_string())
+                    .collect();
+                addresses.sort();
+                let peer = LanPeer {
+                    name: service.get_fullname().to_string(),
+                    host: service.get_hostname().to_string(),
+                    addresses,
+                    port: service.get_port(),
+                    properties: service
+                        .get_properties()
+                        .iter()
+                        .map(|property| {
+                            (property.key().to_string(), property.val_str().to_string())
+                        })
+                        .collect(),
+                    last_seen_at: chrono::Utc::now().timestamp(),
+                };
+                // 地址陆续解析时会多次收到同一服务器，只在第一次发现时记录
+                if !peers.contains_key(&peer.name) {
+                    info!("Discovered LAN peer {} at {:?}", peer.name, peer.addresses);
+                }
+                peers.insert(peer.name.clone(), peer);
+            }
+            ServiceEvent::ServiceRemoved(_, fullname) if peers.remove(&fullname).is_some() => {
+                info!("LAN peer {} left", fullname);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(not(feature = "mdns"))]
+mod transport {
+    use super::Peers;
+
+    pub struct Service;
+
+    impl Service {
+        pub fn shutdown(&self) {}
+    }
+
+    pub fn start(
+        _instance: &str,
+        _host: &str,
+        _port: u16,
+        _node_id: &str,
+        _peers: Peers,
+    ) -> Result<Service, String> {
+        Err("discovery.enabled is set but the mdns feature is disabled".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn host_name_keeps_dns_safe_characters() {
+        assert_eq!(host_name("office-pc"), "office-pc.local.");
+        assert_eq!(host_name("Alice's Mac"), "Alice-s-Mac.local.");
+        assert_eq!(host_name("办公室"), "clipfocus.local.");
+    }
+}
@@ -0,0 +1,270 @@
+use actix_web::dev::Payload;
+use actix_web::http::header;
+use actix_web::{Error, FromRequest, HttpRequest, HttpResponse, Responder, get, web};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::Sha256;
+use std::future::{Ready, ready};
+use std::path::Path;
+use uuid::Uuid;
+
+use crate::{
+    blob_store::blob_store,
+    mailer::public_base_url,
+    spatial_api::binary,
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiResponse, ErrorCode, ResponseData},
+    },
+    user_api::{
+        auth::{BearerToken, missing_secret, required_secret},
+        storage::head_key,
+    },
+};
+
+pub fn file_api() -> actix_web::Scope {
+    web::scope("/files")
+        .service(sign_file_url)
+        .service(get_head)
+        .service(get_clip_file)
+}
+
+/// 文件接口的路径前缀，签名覆盖完整的请求路径
+const FILES_PATH: &str = "/api/v1/files";
+
+/// 签名链接的密钥所在的环境变量
+const SIGNING_KEY_ENV: &str = "FILE_SIGNING_KEY";
+
+/// 签名链接默认 / 最短 / 最长有效期（秒）
+const DEFAULT_SIGNED_URL_TTL_SECS: i64 = 3600;
+const MIN_SIGNED_URL_TTL_SECS: i64 = 60;
+const MAX_SIGNED_URL_TTL_SECS: i64 = 7 * 24 * 3600;
+
+/// 头像文件名为随机 UUID，修改头像时生成新文件，可以长期缓存
+const HEAD_CACHE_CONTROL: &str = "private, max-age=31536000, immutable";
+
+/// 剪贴板文件可能被同一 clip_id 重新推送覆盖，每次使用前需校验 ETag
+const CLIP_CACHE_CONTROL: &str = "private, no-cache";
+
+fn not_found() -> Error {
+    ApiResponse::into_error(ErrorCode::NotFound, "文件不存在")
+}
+
+// 以 FILE_SIGNING_KEY 对路径、用户和过期时间计算 HMAC-SHA256，未设置密钥时签名链接不可用
+fn signing_mac(path: &str, user_id: &str, expires: i64) -> Option<Hmac<Sha256>> {
+    let key = required_secret(SIGNING_KEY_ENV)?;
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("{}\n{}\n{}", path, user_id, expires).as_bytes());
+    Some(mac)
+}
+
+/// 生成带签名的文件链接，持有链接即可在有效期内以 user_id 的身份访问该文件，
+/// 用于 `<img>` 等无法携带 Authorization 头的场景；未设置 FILE_SIGNING_KEY 时返回 None
+///
+/// path 为文件接口下的相对路径，如 `heads/{id}`、`clips/{id}`
+pub fn sign_url(path: &str, user_id: &str, expires_at: i64) -> Option<String> {
+    let path = format!("{}/{}", FILES_PATH, path);
+    let sig = hex::encode(
+        signing_mac(&path, user_id, expires_at)?
+            .finalize()
+            .into_bytes(),
+    );
+    Some(format!(
+        "{}{}?uid={}&expires={}&sig={}",
+        public_base_url(),
+        path,
+        user_id,
+        expires_at,
+        sig
+    ))
+}
+
+#[derive(Deserialize)]
+struct FileSignature {
+    uid: String,
+    expires: i64,
+    sig: String,
+}
+
+/// 访问文件的用户：带签名的链接使用签名中的用户，否则从 Authorization 头读取
+///
+/// 签名链接只证明身份，头像可见范围等权限仍按该用户检查
+pub struct FileAccess {
+    pub user_id: String,
+}
+
+impl FromRequest for FileAccess {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let Ok(signature) = web::Query::<FileSignature>::from_query(req.query_string()) else {
+            return match BearerToken::from_request(req, payload).into_inner() {
+                Ok(bearer_token) => ready(Ok(FileAccess {
+                    user_id: bearer_token.user_id,
+                })),
+                Err(e) => ready(Err(e)),
+            };
+        };
+        let now = chrono::Utc::now().timestamp();
+        // 未设置签名密钥时所有签名链接都无效
+        let signed = hex::decode(&signature.sig).is_ok_and(|sig| {
+            signing_mac(req.path(), &signature.uid, signature.expires)
+                .is_some_and(|mac| mac.verify_slice(&sig).is_ok())
+        });
+        if !signed || signature.expires <= now {
+            return ready(Err(ApiResponse::into_error(
+                ErrorCode::LinkExpired,
+                "链接无效或已过期",
+            )));
+        }
+        ready(Ok(FileAccess {
+            user_id: signature.into_inner().uid,
+        }))
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SignFileUrl {
+    /// 文件接口下的相对路径：`heads/{id}` 或 `clips/{id}`
+    pub path: String,
+    /// 有效期（秒），默认 1 小时，最长 7 天
+    pub expires_in_secs: Option<i64>,
+}
+
+// 为当前用户生成文件的签名链接，签名时不检查文件是否存在，访问时再按用户校验权限
+#[get("/signed_url")]
+async fn sign_file_url(
+    bearer_token: BearerToken,
+    query: web::Query<SignFileUrl>,
+) -> impl Responder {
+    let valid_path = query
+        .path
+        .split_once('/')
+        .is_some_and(|(kind, id)| matches!(kind, "heads" | "clips") && Uuid::parse_str(id).is_ok());
+    if !valid_path {
+        return ApiResponse::error(
+            ErrorCode::InvalidRequest,
+            "路径需要是 heads/{id} 或 clips/{id}",
+        );
+    }
+    let ttl = query.expires_in_secs.unwrap_or(DEFAULT_SIGNED_URL_TTL_SECS);
+    if !(MIN_SIGNED_URL_TTL_SECS..=MAX_SIGNED_URL_TTL_SECS).contains(&ttl) {
+        return ApiResponse::error(
+            ErrorCode::InvalidRequest,
+            &format!(
+                "有效期需要在 {} 到 {} 秒之间",
+                MIN_SIGNED_URL_TTL_SECS, MAX_SIGNED_URL_TTL_SECS
+            ),
+        );
+    }
+    let expires_at = chrono::Utc::now().timestamp() + ttl;
+    let Some(url) = sign_url(&query.path, &bearer_token.user_id, expires_at) else {
+        return missing_secret(SIGNING_KEY_ENV);
+    };
+    ApiResponse::new(
+        "签名链接生成成功",
+        ResponseData::Json(json!({ "url": url, "expires_at": expires_at })),
+    )
+}
+
+// 从存储中读取文件并返回，ETag 由文件大小和版本生成，与 If-None-Match 一致时返回 304
+pub(crate) async fn serve_file(
+    req: &HttpRequest,
+    key: &str,
+    content_type: Option<&str>,
+    cache_control: &str,
+) -> Result<HttpResponse, Error> {
+    let metadata = blob_store().stat(key).await.map_err(|_| not_found())?;
+    let etag = format!("\"{:x}-{}\"", metadata.size, metadata.version);
+
+    let not_modified = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value
+                .split(',')
+                .any(|tag| tag.trim() == "*" || tag.trim().trim_start_matches("W/") == etag)
+        });
+    if not_modified {
+        return Ok(HttpResponse::NotModified()
+            .insert_header((header::ETAG, etag))
+            .insert_header((header::CACHE_CONTROL, cache_control))
+            .finish());
+    }
+
+    let data = blob_store().get(key).await.map_err(|_| not_found())?;
+    let content_type = content_type
+        .or_else(|| binary::sniff_mime(&data))
+        .unwrap_or("application/octet-stream");
+    Ok(HttpResponse::Ok()
+        .insert_header((header::CONTENT_TYPE, content_type))
+        .insert_header((header::ETAG, etag))
+        .insert_header((header::CACHE_CONTROL, cache_control))
+        .insert_header((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .body(data))
+}
+
+// 获取头像（自己的头像或联系人的头像），可以使用签名链接代替 Authorization 头
+#[get("/heads/{id}")]
+async fn get_head(
+    pool: web::Data<DbPool>,
+    access: FileAccess,
+    req: HttpRequest,
+    id: web::Path<Uuid>,
+) -> Result<HttpResponse, Error> {
+    let head_uri = id.to_string();
+    let head_mime = match db::get_visible_avatar(&access.user_id, &head_uri, &pool).await {
+        Ok(Some(head_mime)) => head_mime,
+        // 无权查看与不存在相同，避免泄露头像 ID 是否有效
+        Ok(None) => return Err(not_found()),
+        Err(_) => {
+            return Err(ApiResponse::into_error(
+                ErrorCode::InternalError,
+                "获取头像失败",
+            ));
+        }
+    };
+    // 早期上传的头像没有记录类型，按文件头识别
+    serve_file(
+        &req,
+        &head_key(&head_uri),
+        head_mime.as_deref(),
+        HEAD_CACHE_CONTROL,
+    )
+    .await
+}
+
+// 获取通过二进制帧推送的剪贴板文件（只能获取自己的文件），可以使用签名链接代替 Authorization 头
+#[get("/clips/{id}")]
+async fn get_clip_file(
+    access: FileAccess,
+    req: HttpRequest,
+    id: web::Path<Uuid>,
+) -> Result<HttpResponse, Error> {
+    let files = binary::user_clip_files(&access.user_id)
+        .await
+        .map_err(|_| not_found())?;
+    let clip_id = id.to_string();
+    let key = files
+        .into_iter()
+        .map(|(key, _)| key)
+        .find(|key| Path::new(key).file_stem() == Some(clip_id.as_ref()));
+    let Some(key) = key else {
+        return Err(not_found());
+    };
+    let extension = Path::new(&key)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("");
+    serve_file(
+        &req,
+        &key,
+        Some(binary::mime_for(extension)),
+        CLIP_CACHE_CONTROL,
+    )
+    .await
+}
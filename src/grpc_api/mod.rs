@@ -0,0 +1,51 @@
+use log::warn;
+use std::net::ToSocketAddrs;
+
+use crate::{
+    clip_api::service::ClipService, config::Config, spatial_api::models::AppState,
+    sqlx_utils::DbPool,
+};
+
+/// gRPC 接口，定义见 `proto/clipfocus.proto`，与 REST 接口共用 ClipService 中的剪贴板和增量同步逻辑
+#[cfg(feature = "grpc")]
+mod service;
+
+/// 按配置在单独的端口启动 gRPC 服务，失败时只记录日志，不影响 HTTP 服务
+pub fn start(config: &Config, pool: DbPool, app_state: AppState, clips: ClipService) {
+    if !config.grpc.enabled {
+        return;
+    }
+    let host = config.grpc.host.as_deref().unwrap_or(&config.server.host);
+    let addr = match (host, config.grpc.port).to_socket_addrs() {
+        Ok(mut addrs) => addrs.next(),
+        Err(e) => {
+            warn!("gRPC disabled: invalid address {}: {}", host, e);
+            return;
+        }
+    };
+    let Some(addr) = addr else {
+        warn!("gRPC disabled: {} does not resolve to an address", host);
+        return;
+    };
+    if let Err(e) = service::serve(addr, pool, app_state, clips) {
+        warn!("gRPC disabled: {}", e);
+    }
+}
+
+#[cfg(not(feature = "grpc"))]
+mod service {
+    use std::net::SocketAddr;
+
+    use crate::{
+        clip_api::service::ClipService, spatial_api::models::AppState, sqlx_utils::DbPool,
+    };
+
+    pub fn serve(
+        _addr: SocketAddr,
+        _pool: DbPool,
+        _app_state: AppState,
+        _clips: ClipService,
+    ) -> Result<(), String> {
+        Err("grpc.enabled is set but the grpc feature is disabled".to_string())
+    }
+}
@@ -0,0 +1,677 @@
+// tonic 的接口统一以 Status 作为错误类型
+#![allow(clippy::result_large_err)]
+
+use actix::Addr;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use tokio::sync::mpsc;
+use tokio_stream::Stream;
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::codegen::Bytes;
+use tonic::{Code, Request, Response, Status, Streaming};
+use uuid::Uuid;
+
+use crate::{
+    clip_api::service::{ClipError, ClipService},
+    device_group_api::{DeviceId, Visibility},
+    models::{self, ClipFilter, ClipItem, ClipType, TagMatch},
+    spatial_api::{
+        admit_session,
+        models::{AppState, ClientInfo, GetSessions, PollEvents, PollResult, RoomManager},
+    },
+    sqlx_utils::{
+        DbPool,
+        models::{ApiResponse, Cursor, ErrorCode},
+    },
+    user_api::auth::{BearerToken, bearer_from_access_token},
+};
+
+pub mod pb {
+    tonic::include_proto!("clipfocus.v1");
+}
+
+use pb::clip_focus_server::{ClipFocus, ClipFocusServer};
+
+/// 同步流中等待发送的消息数，客户端读取过慢时暂停转发房间事件
+const SYNC_STREAM_BUFFER: usize = 64;
+
+/// 在 addr 上监听并启动 gRPC 服务
+pub fn serve(
+    addr: SocketAddr,
+    pool: DbPool,
+    app_state: AppState,
+    clips: ClipService,
+) -> Result<(), String> {
+    // 先同步绑定端口，端口被占用时在启动阶段报错
+    let listener = std::net::TcpListener::bind(addr).map_err(|e| e.to_string())?;
+    listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+    let listener = tokio::net::TcpListener::from_std(listener).map_err(|e| e.to_string())?;
+    info!("gRPC listening on {}", addr);
+
+    let service = ClipFocusServer::new(GrpcService {
+        pool,
+        app_state,
+        clips,
+    });
+    actix_web::rt::spawn(async move {
+        let incoming = TcpListenerStream::new(listener);
+        if let Err(e) = tonic::transport::Server::builder()
+            .add_service(service)
+            .serve_with_incoming(incoming)
+            .await
+        {
+            warn!("gRPC server stopped: {}", e);
+        }
+    });
+    Ok(())
+}
+
+struct GrpcService {
+    pool: DbPool,
+    app_state: AppState,
+    clips: ClipService,
+}
+
+// 错误码对应的 gRPC 状态码
+fn grpc_code(code: ErrorCode) -> Code {
+    match code {
+        ErrorCode::InternalError => Code::Internal,
+        ErrorCode::InvalidRequest | ErrorCode::PolicyViolation => Code::InvalidArgument,
+        ErrorCode::AuthMissing
+        | ErrorCode::AuthInvalid
+        | ErrorCode::AuthExpired
+        | ErrorCode::InvalidCredentials
+        | ErrorCode::TwoFactorRequired
+        | ErrorCode::TwoFactorInvalid => Code::Unauthenticated,
+        ErrorCode::Forbidden
+        | ErrorCode::TermsNotAccepted
+        | ErrorCode::RegistrationClosed
+        | ErrorCode::Blocked
+        | ErrorCode::AccountDisabled
+        | ErrorCode::EmailNotVerified => Code::PermissionDenied,
+        ErrorCode::UserNotFound | ErrorCode::NotFound | ErrorCode::LinkExpired => Code::NotFound,
+        ErrorCode::DuplicateEmail | ErrorCode::AlreadyExists => Code::AlreadyExists,
+        ErrorCode::PreconditionRequired | ErrorCode::VersionConflict => Code::FailedPrecondition,
+        ErrorCode::EditConflict | ErrorCode::RequestInProgress => Code::Aborted,
+        ErrorCode::IdempotencyKeyReused => Code::InvalidArgument,
+        ErrorCode::QuotaExceeded
+        | ErrorCode::RateLimited
+        | ErrorCode::RenameCooldown
+        | ErrorCode::LoginLocked
+        | ErrorCode::PayloadTooLarge => Code::ResourceExhausted,
+        ErrorCode::MailDeliveryFailed => Code::Unavailable,
+    }
+}
+
+// REST 接口的错误响应转为 gRPC 状态，details 为 `{"code", "data"}` JSON
+fn api_status(response: ApiResponse) -> Status {
+    let code = response.code.unwrap_or(ErrorCode::InternalError);
+    let details = json!({ "code": code, "data": response.data });
+    Status::with_details(
+        grpc_code(code),
+        response.message,
+        Bytes::from(details.to_string()),
+    )
+}
+
+fn clip_status(error: ClipError) -> Status {
+    api_status(error.into())
+}
+
+fn error_status(code: ErrorCode, message: &str) -> Status {
+    api_status(ApiResponse::error(code, message))
+}
+
+fn invalid(message: &str) -> Status {
+    error_status(ErrorCode::InvalidRequest, message)
+}
+
+// 读取 metadata 中的文本值
+fn metadata<T>(request: &Request<T>, key: &str) -> Option<String> {
+    request
+        .metadata()
+        .get(key)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+// 校验 authorization metadata 中的访问令牌（与 REST 接口相同），并读取请求设备
+fn caller<T>(request: &Request<T>) -> Result<(BearerToken, DeviceId), Status> {
+    let token = metadata(request, "authorization")
+        .ok_or_else(|| error_status(ErrorCode::AuthMissing, "缺少访问令牌"))?;
+    let token = token
+        .strip_prefix("Bearer ")
+        .ok_or_else(|| error_status(ErrorCode::AuthInvalid, "无效的令牌格式"))?;
+    let bearer_token =
+        bearer_from_access_token(token).map_err(|code| error_status(code, code.description()))?;
+    Ok((bearer_token, DeviceId(metadata(request, "x-device-id"))))
+}
+
+// 枚举在 JSON 中的名称，如 ClipType::FilePath 为 file_path
+fn json_name<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(Value::String(name)) => name,
+        _ => String::new(),
+    }
+}
+
+fn millis(at: DateTime<Utc>) -> i64 {
+    at.timestamp_millis()
+}
+
+fn from_millis(ms: i64) -> Result<DateTime<Utc>, Status> {
+    DateTime::from_timestamp_millis(ms).ok_or_else(|| invalid("无效的时间戳"))
+}
+
+fn parse_uuid(id: &str, message: &str) -> Result<Uuid, Status> {
+    Uuid::parse_str(id.trim()).map_err(|_| invalid(message))
+}
+
+fn parse_clip_type(name: &str) -> Result<ClipType, Status> {
+    ClipType::from_name(name.trim()).ok_or_else(|| invalid("无效的内容类型"))
+}
+
+fn visibility_to_pb(visibility: &Visibility) -> pb::Visibility {
+    match visibility {
+        Visibility::All => pb::Visibility {
+            scope: "all".to_string(),
+            ..Default::default()
+        },
+        Visibility::Group { group_id } => pb::Visibility {
+            scope: "group".to_string(),
+            group_id: Some(group_id.clone()),
+            device_id: None,
+        },
+        Visibility::Device { device_id } => pb::Visibility {
+            scope: "device".to_string(),
+            group_id: None,
+            device_id: Some(device_id.clone()),
+        },
+    }
+}
+
+fn visibility_from_pb(visibility: Option<pb::Visibility>) -> Result<Visibility, Status> {
+    let Some(visibility) = visibility else {
+        return Ok(Visibility::All);
+    };
+    match visibility.scope.as_str() {
+        "" | "all" => Ok(Visibility::All),
+        "group" => Ok(Visibility::Group {
+            group_id: visibility.group_id.unwrap_or_default(),
+        }),
+        "device" => Ok(Visibility::Device {
+            device_id: visibility.device_id.unwrap_or_default(),
+        }),
+        _ => Err(invalid("无效的可见范围")),
+    }
+}
+
+fn clip_to_pb(clip: ClipItem) -> pb::Clip {
+    pb::Clip {
+        id: clip.id.to_string(),
+        device_id: clip.device_id.to_string(),
+        content_type: clip.content_type.as_str().to_string(),
+        content: clip.content,
+        preview: clip.preview,
+        size: clip.size,
+        source_app: clip.source_app,
+        created_at: millis(clip.created_at),
+        accessed_at: millis(clip.accessed_at),
+        sync_status: json_name(&clip.sync_status),
+        encrypted: clip.encrypted,
+        tags: clip.tags,
+        visibility: Some(visibility_to_pb(&clip.visibility)),
+        key_id: clip.key_id,
+        nonce: clip.nonce,
+        version: clip.version,
+        pinned: clip.pinned,
+        source: json_name(&clip.source),
+        shared_by: clip.shared_by,
+        expires_at: clip.expires_at.map(millis),
+        sensitive: clip.sensitive.as_ref().map(json_name),
+    }
+}
+
+fn create_from_pb(create: pb::CreateClipRequest) -> Result<models::CreateClipRequest, Status> {
+    Ok(models::CreateClipRequest {
+        device_id: parse_uuid(&create.device_id, "无效的设备 ID")?,
+        content_type: parse_clip_type(&create.content_type)?,
+        content: create.content,
+        preview: create.preview,
+        source_app: create.source_app,
+        tags: (!create.tags.is_empty()).then_some(create.tags),
+        visibility: visibility_from_pb(create.visibility)?,
+        key_id: create.key_id,
+        nonce: create.nonce,
+        expires_at: create.expires_at.map(from_millis).transpose()?,
+    })
+}
+
+fn filter_from_pb(list: pb::ListClipsRequest) -> Result<ClipFilter, Status> {
+    let tag_match = match list.tag_match.as_deref() {
+        None | Some("") => None,
+        Some("all") => Some(TagMatch::All),
+        Some("any") => Some(TagMatch::Any),
+        Some(_) => return Err(invalid("tag_match 只能为 all 或 any")),
+    };
+    Ok(ClipFilter {
+        clip_type: list
+            .content_type
+            .as_deref()
+            .map(parse_clip_type)
+            .transpose()?,
+        device_id: list
+            .device_id
+            .as_deref()
+            .map(|id| parse_uuid(id, "无效的设备 ID"))
+            .transpose()?,
+        tags: (!list.tags.is_empty()).then_some(list.tags),
+        start_date: list.start_date.map(from_millis).transpose()?,
+        end_date: list.end_date.map(from_millis).transpose()?,
+        search_text: list.search_text,
+        limit: list.limit,
+        offset: None,
+        pinned_only: list.pinned_only.then_some(true),
+        tag_match,
+        cursor: list
+            .cursor
+            .map(|cursor| Cursor::try_from(cursor).map_err(invalid))
+            .transpose()?,
+    })
+}
+
+// 修改和删除必须提供 version，force 时不校验版本（相当于 If-Match: *）
+fn expected_version(version: Option<i64>, force: bool) -> Result<Option<i64>, Status> {
+    match (version, force) {
+        (_, true) => Ok(None),
+        (Some(version), false) => Ok(Some(version)),
+        (None, false) => Err(error_status(
+            ErrorCode::PreconditionRequired,
+            "缺少 version",
+        )),
+    }
+}
+
+type SyncStream = Pin<Box<dyn Stream<Item = Result<pb::SyncFrame, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl ClipFocus for GrpcService {
+    type SyncStreamStream = SyncStream;
+
+    async fn create_clip(
+        &self,
+        request: Request<pb::CreateClipRequest>,
+    ) -> Result<Response<pb::CreateClipResponse>, Status> {
+        let (bearer_token, _) = caller(&request)?;
+        let create = create_from_pb(request.into_inner())?;
+        let (clip, deduped) = self
+            .clips
+            .create(&bearer_token.user_id, create)
+            .await
+            .map_err(clip_status)?;
+        Ok(Response::new(pb::CreateClipResponse {
+            clip: Some(clip_to_pb(clip)),
+            deduped,
+        }))
+    }
+
+    async fn get_clip(
+        &self,
+        request: Request<pb::GetClipRequest>,
+    ) -> Result<Response<pb::Clip>, Status> {
+        let (bearer_token, device_id) = caller(&request)?;
+        let id = parse_uuid(&request.get_ref().id, "无效的剪贴板 ID")?;
+        let clip = self
+            .clips
+            .get(&bearer_token.user_id, device_id.0.as_deref(), &id)
+            .await
+            .map_err(clip_status)?;
+        Ok(Response::new(clip_to_pb(clip)))
+    }
+
+    async fn list_clips(
+        &self,
+        request: Request<pb::ListClipsRequest>,
+    ) -> Result<Response<pb::ListClipsResponse>, Status> {
+        let (bearer_token, device_id) = caller(&request)?;
+        let filter = filter_from_pb(request.into_inner())?;
+        let page = self
+            .clips
+            .list(&bearer_token.user_id, device_id.0.as_deref(), filter)
+            .await
+            .map_err(clip_status)?;
+        Ok(Response::new(pb::ListClipsResponse {
+            items: page.items.into_iter().map(clip_to_pb).collect(),
+            total: page.total,
+            next_cursor: page.next_cursor,
+        }))
+    }
+
+    async fn update_clip(
+        &self,
+        request: Request<pb::UpdateClipRequest>,
+    ) -> Result<Response<pb::Clip>, Status> {
+        let (bearer_token, device_id) = caller(&request)?;
+        let update = request.into_inner();
+        let id = parse_uuid(&update.id, "无效的剪贴板 ID")?;
+        let expected = expected_version(update.version, update.force)?;
+        let changes = models::UpdateClipRequest {
+            accessed: update.accessed,
+            tags: update.tags.map(|tags| tags.tags),
+        };
+        let clip = self
+            .clips
+            .update(
+                &bearer_token.user_id,
+                device_id.0.as_deref(),
+                expected,
+                &id,
+                changes,
+            )
+            .await
+            .map_err(clip_status)?;
+        Ok(Response::new(clip_to_pb(clip)))
+    }
+
+    async fn delete_clip(
+        &self,
+        request: Request<pb::DeleteClipRequest>,
+    ) -> Result<Response<pb::DeleteClipResponse>, Status> {
+        let (bearer_token, device_id) = caller(&request)?;
+        let delete = request.into_inner();
+        let id = parse_uuid(&delete.id, "无效的剪贴板 ID")?;
+        let expected = expected_version(delete.version, delete.force)?;
+        self.clips
+            .delete(&bearer_token.user_id, device_id.0.as_deref(), expected, &id)
+            .await
+            .map_err(clip_status)?;
+        Ok(Response::new(pb::DeleteClipResponse {}))
+    }
+
+    async fn get_changes(
+        &self,
+        request: Request<pb::GetChangesRequest>,
+    ) -> Result<Response<pb::GetChangesResponse>, Status> {
+        let (bearer_token, device_id) = caller(&request)?;
+        let query = request.get_ref();
+        let changes = self
+            .clips
+            .changes(
+                &bearer_token.user_id,
+                device_id.0.as_deref(),
+                query.since,
+                query.limit,
+            )
+            .await
+            .map_err(clip_status)?;
+        Ok(Response::new(pb::GetChangesResponse {
+            cursor: changes.cursor,
+            has_more: changes.has_more,
+            changes: changes
+                .changes
+                .into_iter()
+                .map(|change| pb::ClipChange {
+                    seq: change.seq,
+                    op: json_name(&change.op),
+                    clip_id: change.clip_id.to_string(),
+                    clip: change.clip.map(clip_to_pb),
+                })
+                .collect(),
+        }))
+    }
+
+    // 与 WebSocket 握手相同地校验账号和设备，之后转发房间事件（与 SSE 共用事件日志），
+    // 并处理客户端发送的消息
+    async fn sync_stream(
+        &self,
+        request: Request<Streaming<pb::SyncFrame>>,
+    ) -> Result<Response<Self::SyncStreamStream>, Status> {
+        let (bearer_token, device_id) = caller(&request)?;
+        let client = ClientInfo {
+            device_id: device_id.0,
+            platform: metadata(&request, "x-client-platform"),
+            app_version: metadata(&request, "x-client-version"),
+            device_name: metadata(&request, "x-device-name"),
+        };
+        let room_manager = self.app_state.room_manager.clone();
+        admit_session(&bearer_token, &client, &self.pool, &room_manager)
+            .await
+            .map_err(|e| error_status(e.code, e.message))?;
+        let last_event_id = metadata(&request, "last-event-id").and_then(|id| id.parse().ok());
+        info!(
+            "用户 {} 的 {} 建立 gRPC 同步流",
+            bearer_token.user_id,
+            client.label()
+        );
+
+        let (sender, receiver) = mpsc::channel(SYNC_STREAM_BUFFER);
+        tokio::spawn(forward_events(
+            room_manager,
+            bearer_token.user_id.clone(),
+            client.device_id.clone(),
+            last_event_id,
+            sender.clone(),
+        ));
+        let session = SyncSession {
+            user_id: bearer_token.user_id,
+            device_id: client.device_id,
+            app_state: self.app_state.clone(),
+            clips: self.clips.clone(),
+            sender,
+        };
+        tokio::spawn(session.receive(request.into_inner()));
+        Ok(Response::new(Box::pin(ReceiverStream::new(receiver))))
+    }
+}
+
+type FrameSender = mpsc::Sender<Result<pb::SyncFrame, Status>>;
+
+fn reply_frame(event: &str, ref_id: String, payload: Value) -> pb::SyncFrame {
+    pb::SyncFrame {
+        r#type: event.to_string(),
+        id: ref_id,
+        payload: payload.to_string(),
+        seq: 0,
+    }
+}
+
+// 把房间事件转发到同步流，直到客户端断开、服务器停机或用户数据被清除
+async fn forward_events(
+    room_manager: Addr<RoomManager>,
+    user_id: String,
+    device_id: Option<String>,
+    mut cursor: Option<u64>,
+    sender: FrameSender,
+) {
+    loop {
+        let poll = PollEvents {
+            user_id: user_id.clone(),
+            device_id: device_id.clone(),
+            since: cursor,
+        };
+        let events = match room_manager.send(poll).await {
+            // 首次连接或游标超前时只返回当前游标，从这里开始等待
+            Ok(PollResult::Ready {
+                events,
+                cursor: latest,
+            }) if events.is_empty() => {
+                cursor = Some(latest);
+                continue;
+            }
+            Ok(PollResult::Ready { events, .. }) => events,
+            Ok(PollResult::Wait(receiver)) => tokio::select! {
+                _ = sender.closed() => return,
+                events = receiver => match events {
+                    Ok(events) => events,
+                    Err(_) => return,
+                },
+            },
+            Err(_) => return,
+        };
+        for event in events {
+            cursor = Some(event.seq);
+            let frame = pb::SyncFrame {
+                r#type: event.event,
+                id: String::new(),
+                payload: event.payload.to_string(),
+                seq: event.seq,
+            };
+            if sender.send(Ok(frame)).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+// 同步流中处理客户端消息的一端
+struct SyncSession {
+    user_id: String,
+    device_id: Option<String>,
+    app_state: AppState,
+    clips: ClipService,
+    sender: FrameSender,
+}
+
+impl SyncSession {
+    async fn receive(self, mut inbound: Streaming<pb::SyncFrame>) {
+        loop {
+            let frame = match inbound.message().await {
+                Ok(Some(frame)) => frame,
+                Ok(None) => break,
+                Err(e) => {
+                    info!("用户 {} 的 gRPC 同步流断开: {}", self.user_id, e);
+                    break;
+                }
+            };
+            let reply = self.handle(frame).await;
+            if self.sender.send(Ok(reply)).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    // 处理一条客户端消息，返回回复或 error 消息（与 WebSocket 的 error 帧相同）
+    async fn handle(&self, frame: pb::SyncFrame) -> pb::SyncFrame {
+        let ref_id = frame.id;
+        if let Err(wait) = self.app_state.rate_limiters.ws.check(&self.user_id) {
+            let detail = json!({ "retry_after": crate::rate_limit::retry_after_secs(wait) });
+            return error_frame(ErrorCode::RateLimited, ref_id, detail);
+        }
+        let result = match frame.r#type.as_str() {
+            "clip.push" => self.push_clip(&frame.payload, &ref_id).await,
+            "presence" => self.presence(&ref_id).await,
+            other => Err((
+                ErrorCode::InvalidRequest,
+                json!({ "reason": format!("unknown message type: {}", other) }),
+            )),
+        };
+        match result {
+            Ok((event, payload)) => reply_frame(event, ref_id, payload),
+            Err((code, detail)) => error_frame(code, ref_id, detail),
+        }
+    }
+
+    // clip.push：payload 为 CreateClipRequest，与 REST 创建剪贴板相同地保存并通知其他设备
+    async fn push_clip(
+        &self,
+        payload: &str,
+        ref_id: &str,
+    ) -> Result<(&'static str, Value), (ErrorCode, Value)> {
+        let create = serde_json::from_str::<models::CreateClipRequest>(payload).map_err(|e| {
+            (
+                ErrorCode::InvalidRequest,
+                json!({ "reason": e.to_string() }),
+            )
+        })?;
+        // 剪贴板必须来自同步流建立时校验过的设备
+        if self.device_id.as_deref() != Some(create.device_id.to_string().as_str()) {
+            let detail = json!({ "reason": "device_id does not match the session" });
+            return Err((ErrorCode::Forbidden, detail));
+        }
+        let (clip, deduped) = self
+            .clips
+            .create(&self.user_id, create)
+            .await
+            .map_err(|error| {
+                let response = ApiResponse::from(error);
+                let code = response.code.unwrap_or(ErrorCode::InternalError);
+                (code, json!(response.data))
+            })?;
+        let ack = json!({
+            "ref_id": ref_id,
+            "clip_id": clip.id,
+            "version": clip.version,
+            "created_at": clip.created_at,
+            "deduped": deduped,
+        });
+        Ok(("clip.ack", ack))
+    }
+
+    // presence：查询在线会话
+    async fn presence(&self, ref_id: &str) -> Result<(&'static str, Value), (ErrorCode, Value)> {
+        let sessions = self
+            .app_state
+            .room_manager
+            .send(GetSessions {
+                user_id: self.user_id.clone(),
+            })
+            .await
+            .map_err(|_| (ErrorCode::InternalError, Value::Null))?;
+        Ok((
+            "presence",
+            json!({ "ref_id": ref_id, "sessions": sessions }),
+        ))
+    }
+}
+
+fn error_frame(code: ErrorCode, ref_id: String, detail: Value) -> pb::SyncFrame {
+    let error = json!({
+        "code": code,
+        "message": code.description(),
+        "ref_id": ref_id,
+        "detail": detail,
+    });
+    reply_frame("error", ref_id, error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expected_version_requires_version_or_force() {
+        assert_eq!(expected_version(Some(3), false).unwrap(), Some(3));
+        assert_eq!(expected_version(Some(3), true).unwrap(), None);
+        let status = expected_version(None, false).unwrap_err();
+        assert_eq!(status.code(), Code::FailedPrecondition);
+    }
+
+    #[test]
+    fn visibility_round_trips() {
+        for visibility in [
+            Visibility::All,
+            Visibility::Group {
+                group_id: "g1".to_string(),
+            },
+            Visibility::Device {
+                device_id: "d1".to_string(),
+            },
+        ] {
+            let pb = visibility_to_pb(&visibility);
+            assert_eq!(visibility_from_pb(Some(pb)).unwrap(), visibility);
+        }
+        assert_eq!(visibility_from_pb(None).unwrap(), Visibility::All);
+        assert!(
+            visibility_from_pb(Some(pb::Visibility {
+                scope: "everyone".to_string(),
+                ..Default::default()
+            }))
+            .is_err()
+        );
+    }
+}
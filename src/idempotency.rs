@@ -0,0 +1,123 @@
+use actix_web::dev::Payload;
+use actix_web::{Error, FromRequest, HttpRequest};
+use log::{info, warn};
+use std::future::{Ready, ready};
+use utoipa::IntoParams;
+
+use crate::sqlx_utils::{
+    DbPool, db,
+    models::{ApiResponse, ErrorCode},
+};
+
+/// 幂等键保留时间（秒）
+const IDEMPOTENCY_KEY_TTL: i64 = 24 * 60 * 60;
+
+/// 幂等键最大长度
+const MAX_KEY_LEN: usize = 255;
+
+/// 请求头 `Idempotency-Key`，未携带时不做幂等处理
+///
+/// 客户端在网络不稳定时可使用同一个键重试创建类请求，服务端直接返回首次成功的响应，
+/// 避免重复创建。失败的请求不会被记录，可以使用同一个键重试。
+#[derive(IntoParams)]
+#[into_params(names("Idempotency-Key"), parameter_in = Header)]
+pub struct IdempotencyKey(Option<String>);
+
+/// 幂等检查结果
+pub enum Idempotency {
+    /// 首次请求，继续处理
+    Proceed,
+    /// 重试请求，直接返回首次的响应
+    Replay(ApiResponse),
+}
+
+impl FromRequest for IdempotencyKey {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let key = req
+            .headers()
+            .get("Idempotency-Key")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty());
+        ready(Ok(IdempotencyKey(key)))
+    }
+}
+
+/// 计算请求指纹，用于识别同一个键被用于不同的请求
+pub fn fingerprint(req: &HttpRequest, body: &[u8]) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(req.method().as_str().as_bytes());
+    hasher.update(b"\n");
+    hasher.update(req.path().as_bytes());
+    hasher.update(b"\n");
+    hasher.update(body);
+    hasher.finalize().to_hex().to_string()
+}
+
+impl IdempotencyKey {
+    /// 占用幂等键；返回 Err 时直接把错误响应返回给客户端
+    pub async fn begin(
+        &self,
+        user_id: &str,
+        fingerprint: &str,
+        pool: &DbPool,
+    ) -> Result<Idempotency, ApiResponse> {
+        let Some(key) = &self.0 else {
+            return Ok(Idempotency::Proceed);
+        };
+        if key.len() > MAX_KEY_LEN {
+            return Err(ApiResponse::error(
+                ErrorCode::InvalidRequest,
+                "Idempotency-Key 过长",
+            ));
+        }
+
+        let expired_before = chrono::Utc::now().timestamp() - IDEMPOTENCY_KEY_TTL;
+        match db::claim_idempotency_key(user_id, key, fingerprint, expired_before, pool).await {
+            Ok(true) => return Ok(Idempotency::Proceed),
+            Ok(false) => {}
+            Err(e) => {
+                warn!("claim idempotency key failed: {}", e);
+                return Err(ApiResponse::error(ErrorCode::InternalError, "请求处理失败"));
+            }
+        }
+
+        match db::get_idempotency_record(user_id, key, pool).await {
+            Ok(Some((stored, _))) if stored != fingerprint => Err(ApiResponse::error(
+                ErrorCode::IdempotencyKeyReused,
+                "幂等键已用于其他请求",
+            )),
+            Ok(Some((_, Some(response)))) => match serde_json::from_str(&response) {
+                Ok(response) => {
+                    info!("用户 {} 重试请求，返回幂等键 {} 的原响应", user_id, key);
+                    Ok(Idempotency::Replay(response))
+                }
+                Err(_) => Err(ApiResponse::error(ErrorCode::InternalError, "请求处理失败")),
+            },
+            // 记录为空说明刚好被并发释放，同样交给客户端稍后重试
+            Ok(Some((_, None))) | Ok(None) => Err(ApiResponse::error(
+                ErrorCode::RequestInProgress,
+                "相同幂等键的请求正在处理中",
+            )),
+            Err(_) => Err(ApiResponse::error(ErrorCode::InternalError, "请求处理失败")),
+        }
+    }
+
+    /// 记录响应：成功时保存以便重放，失败时释放幂等键
+    pub async fn finish(&self, user_id: &str, response: ApiResponse, pool: &DbPool) -> ApiResponse {
+        let Some(key) = &self.0 else {
+            return response;
+        };
+        let result = match (&response.code, serde_json::to_string(&response)) {
+            (None, Ok(text)) => db::complete_idempotency_key(user_id, key, &text, pool).await,
+            _ => db::release_idempotency_key(user_id, key, pool).await,
+        };
+        if let Err(e) = result {
+            warn!("save idempotency key failed: {}", e);
+        }
+        response
+    }
+}
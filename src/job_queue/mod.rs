@@ -0,0 +1,103 @@
+use futures::future::BoxFuture;
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::Executor;
+
+use crate::sqlx_utils::{Db, db};
+
+pub mod worker;
+
+/// 未指定时任务最多执行的次数（包括第一次）
+pub const DEFAULT_MAX_ATTEMPTS: i64 = 5;
+
+/// 后台任务及其最近一次执行的结果
+#[derive(Debug, Clone, Serialize)]
+pub struct Job {
+    pub id: String,
+    /// 任务类型，决定由哪个处理器执行
+    pub kind: String,
+    pub payload: Value,
+    /// 优先级，越大越先执行
+    pub priority: i64,
+    /// pending / running / done / dead（重试次数用完，等待管理员重试或删除）
+    pub status: String,
+    pub attempts: i64,
+    pub max_attempts: i64,
+    /// 下次执行的时间（秒），执行中为租约到期时间，完成后为空
+    pub run_at: Option<i64>,
+    /// 最近一次执行失败的原因
+    pub error: Option<String>,
+    pub created_at: i64,
+    pub finished_at: Option<i64>,
+}
+
+/// 任务处理器，按任务类型注册到 [`worker::JobQueue`]
+///
+/// 返回错误时按指数退避重试，同一任务可能因节点崩溃或超时被执行多次，处理器应当是幂等的
+pub trait JobHandler: Send + Sync {
+    fn run<'a>(&'a self, job: &'a Job) -> BoxFuture<'a, Result<(), String>>;
+}
+
+/// 待入队的任务，如 `NewJob::new("delete_blobs", payload).priority(10).enqueue(&pool)`
+pub struct NewJob {
+    kind: String,
+    payload: Value,
+    priority: i64,
+    max_attempts: i64,
+    delay_secs: i64,
+}
+
+impl NewJob {
+    pub fn new(kind: &str, payload: Value) -> Self {
+        NewJob {
+            kind: kind.to_string(),
+            payload,
+            priority: 0,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            delay_secs: 0,
+        }
+    }
+
+    /// 优先级，默认为 0
+    pub fn priority(mut self, priority: i64) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// 最多执行的次数（包括第一次），默认为 DEFAULT_MAX_ATTEMPTS
+    pub fn max_attempts(mut self, max_attempts: i64) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// 延迟 delay_secs 秒后执行
+    pub fn delay(mut self, delay_secs: i64) -> Self {
+        self.delay_secs = delay_secs.max(0);
+        self
+    }
+
+    /// 保存任务，由任务队列在后台执行，返回任务 ID
+    ///
+    /// executor 可以是事务，任务与其他修改一起提交
+    pub async fn enqueue(
+        self,
+        executor: impl Executor<'_, Database = Db>,
+    ) -> Result<String, sqlx::Error> {
+        let now = chrono::Utc::now().timestamp();
+        let job = Job {
+            id: uuid::Uuid::new_v4().to_string(),
+            kind: self.kind,
+            payload: self.payload,
+            priority: self.priority,
+            status: "pending".to_string(),
+            attempts: 0,
+            max_attempts: self.max_attempts,
+            run_at: Some(now + self.delay_secs),
+            error: None,
+            created_at: now,
+            finished_at: None,
+        };
+        db::insert_job(&job, executor).await?;
+        Ok(job.id)
+    }
+}
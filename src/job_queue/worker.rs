@@ -0,0 +1,270 @@
+use actix::prelude::*;
+use log::{info, warn};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::JobsConfig;
+use crate::job_queue::{Job, JobHandler};
+use crate::sqlx_utils::{DbPool, db};
+
+/// 已完成任务的清理间隔
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// 保存的失败原因的最大长度
+const MAX_ERROR_LEN: usize = 500;
+
+/// 任务类型到处理器的映射
+type Handlers = Arc<HashMap<String, Arc<dyn JobHandler>>>;
+
+/// 后台任务队列，周期性地按优先级取出到期的任务交给注册的处理器执行，
+/// 失败时按指数退避重试，重试次数用完后进入死信
+///
+/// 多个节点共用数据库时，任务执行前先获取租约，同一时间只由一个节点执行；
+/// 节点在执行中崩溃时，任务在租约到期后重新执行
+pub struct JobQueue {
+    pool: DbPool,
+    config: JobsConfig,
+    handlers: Handlers,
+}
+
+impl JobQueue {
+    pub fn new(pool: DbPool, config: JobsConfig) -> Self {
+        Self {
+            pool,
+            config,
+            handlers: Arc::new(HashMap::new()),
+        }
+    }
+
+    /// 注册任务类型的处理器
+    pub fn handler(mut self, kind: &str, handler: impl JobHandler + 'static) -> Self {
+        Arc::make_mut(&mut self.handlers).insert(kind.to_string(), Arc::new(handler));
+        self
+    }
+}
+
+// 第 attempts 次执行失败后等待的时间（秒）：从 retry_base_secs 开始每次翻倍，不超过 retry_max_secs
+fn retry_delay(attempts: i64, config: &JobsConfig) -> i64 {
+    let exponent = attempts.clamp(1, 31) as u32 - 1;
+    config
+        .retry_base_secs
+        .saturating_mul(1 << exponent)
+        .min(config.retry_max_secs)
+}
+
+// 记录一次执行的结果：成功时完成任务，失败时安排重试，次数用完后进入死信
+fn record_attempt(job: &mut Job, result: Result<(), String>, now: i64, config: &JobsConfig) {
+    match result {
+        Ok(()) => {
+            job.status = "done".to_string();
+            job.run_at = None;
+            job.error = None;
+            job.finished_at = Some(now);
+        }
+        Err(e) => {
+            if job.attempts >= job.max_attempts {
+                job.status = "dead".to_string();
+                job.run_at = None;
+                job.finished_at = Some(now);
+            } else {
+                job.status = "pending".to_string();
+                job.run_at = Some(now + retry_delay(job.attempts, config));
+            }
+            job.error = Some(e.chars().take(MAX_ERROR_LEN).collect());
+        }
+    }
+}
+
+// 执行一个任务并记录结果
+async fn run_job(mut job: Job, pool: &DbPool, handlers: &Handlers, config: &JobsConfig) {
+    // 先获取租约（run_at 推迟到超时之后），避免多个节点或下一轮检查重复执行
+    let now = chrono::Utc::now().timestamp();
+    let lease_until = now + config.timeout().as_secs() as i64 * 2;
+    match db::claim_job(&job, lease_until, pool).await {
+        Ok(true) => job.attempts += 1,
+        Ok(false) => return,
+        Err(e) => {
+            warn!("claim job {} failed: {}", job.id, e);
+            return;
+        }
+    }
+
+    let result = match handlers.get(&job.kind) {
+        Some(handler) => tokio::time::timeout(config.timeout(), handler.run(&job))
+            .await
+            .unwrap_or_else(|_| Err(format!("timed out after {:?}", config.timeout()))),
+        None => Err(format!("no handler for job kind {}", job.kind)),
+    };
+    record_attempt(&mut job, result, chrono::Utc::now().timestamp(), config);
+    if job.status == "dead" {
+        warn!(
+            "后台任务 {}（{}）失败 {} 次，进入死信: {}",
+            job.id,
+            job.kind,
+            job.attempts,
+            job.error.as_deref().unwrap_or_default()
+        );
+    }
+    if let Err(e) = db::finish_job(&job, pool).await {
+        warn!("save job {} failed: {}", job.id, e);
+    }
+}
+
+// 执行所有已到时间的任务（每次最多 concurrency 个）
+async fn run_due(pool: DbPool, handlers: Handlers, config: JobsConfig) {
+    let now = chrono::Utc::now().timestamp();
+    let due = match db::get_due_jobs(now, config.concurrency.max(1), &pool).await {
+        Ok(due) => due,
+        Err(e) => {
+            warn!("查询待执行的后台任务失败: {}", e);
+            return;
+        }
+    };
+    futures::future::join_all(
+        due.into_iter()
+            .map(|job| run_job(job, &pool, &handlers, &config)),
+    )
+    .await;
+}
+
+// 删除超过保留天数的已完成任务
+async fn prune_jobs(pool: DbPool, retention_days: i64) {
+    if retention_days <= 0 {
+        return;
+    }
+    let before = chrono::Utc::now().timestamp() - retention_days * 24 * 3600;
+    match db::delete_jobs_before(before, &pool).await {
+        Ok(0) => {}
+        Ok(deleted) => info!("删除 {} 个已完成的后台任务", deleted),
+        Err(e) => warn!("清理后台任务失败: {}", e),
+    }
+}
+
+impl Actor for JobQueue {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        let mut kinds: Vec<&str> = self.handlers.keys().map(String::as_str).collect();
+        kinds.sort();
+        info!("JobQueue started with handlers {:?}", kinds);
+
+        ctx.run_interval(self.config.poll_interval(), |act, ctx| {
+            run_due(act.pool.clone(), act.handlers.clone(), act.config.clone())
+                .into_actor(act)
+                .wait(ctx);
+        });
+        ctx.run_interval(PRUNE_INTERVAL, |act, ctx| {
+            prune_jobs(act.pool.clone(), act.config.retention_days)
+                .into_actor(act)
+                .wait(ctx);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::job_queue::NewJob;
+    use crate::sqlx_utils::migrations::run_migrations;
+    use futures::future::BoxFuture;
+    use serde_json::json;
+    use sqlx::any::AnyPoolOptions;
+    use std::sync::Mutex;
+
+    /// 记录执行顺序，payload 中 fail 为 true 的任务执行失败
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl JobHandler for Arc<Recorder> {
+        fn run<'a>(&'a self, job: &'a Job) -> BoxFuture<'a, Result<(), String>> {
+            Box::pin(async move {
+                self.0.lock().unwrap().push(job.payload["name"].to_string());
+                match job.payload["fail"].as_bool() {
+                    Some(true) => Err("failed".to_string()),
+                    _ => Ok(()),
+                }
+            })
+        }
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_max() {
+        let config = JobsConfig {
+            retry_base_secs: 30,
+            retry_max_secs: 100,
+            ..JobsConfig::default()
+        };
+        assert_eq!(retry_delay(1, &config), 30);
+        assert_eq!(retry_delay(2, &config), 60);
+        assert_eq!(retry_delay(3, &config), 100);
+        assert_eq!(retry_delay(64, &config), 100);
+    }
+
+    #[tokio::test]
+    async fn runs_by_priority_and_dead_letters_failures() {
+        // 内存数据库按连接隔离，只使用一个连接
+        let pool = AnyPoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        run_migrations(&pool).await.unwrap();
+        let recorder = Arc::new(Recorder::default());
+        let handlers: Handlers = Arc::new(HashMap::from([(
+            "record".to_string(),
+            Arc::new(recorder.clone()) as Arc<dyn JobHandler>,
+        )]));
+        let config = JobsConfig {
+            concurrency: 1,
+            retry_base_secs: 0,
+            ..JobsConfig::default()
+        };
+
+        NewJob::new("record", json!({ "name": "low" }))
+            .enqueue(&pool)
+            .await
+            .unwrap();
+        NewJob::new("record", json!({ "name": "later" }))
+            .priority(100)
+            .delay(3600)
+            .enqueue(&pool)
+            .await
+            .unwrap();
+        let failing = NewJob::new("record", json!({ "name": "high", "fail": true }))
+            .priority(10)
+            .max_attempts(2)
+            .enqueue(&pool)
+            .await
+            .unwrap();
+        let unknown = NewJob::new("missing", json!({}))
+            .priority(-1)
+            .max_attempts(1)
+            .enqueue(&pool)
+            .await
+            .unwrap();
+
+        // 每轮执行一个任务：失败的高优先级任务重试一次后进入死信，延迟的任务不执行
+        for _ in 0..4 {
+            run_due(pool.clone(), handlers.clone(), config.clone()).await;
+        }
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            [r#""high""#, r#""high""#, r#""low""#]
+        );
+        let failing = db::get_job(&failing, &pool).await.unwrap().unwrap();
+        assert_eq!(failing.status, "dead");
+        assert_eq!(failing.attempts, 2);
+        assert_eq!(failing.error.as_deref(), Some("failed"));
+        let unknown = db::get_job(&unknown, &pool).await.unwrap().unwrap();
+        assert_eq!(unknown.status, "dead");
+
+        // 管理员重试死信任务
+        assert!(db::retry_job(&failing.id, 0, &pool).await.unwrap());
+        let retried = db::get_job(&failing.id, &pool).await.unwrap().unwrap();
+        assert_eq!(
+            (retried.status.as_str(), retried.attempts),
+            ("pending", 0)
+        );
+    }
+}
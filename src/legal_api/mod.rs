@@ -0,0 +1,160 @@
+use actix_web::{Responder, get, post, web};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    config::AuthConfig,
+    spatial_api::models::AppState,
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiResponse, ErrorCode, ResponseData},
+    },
+    user_api::auth::{AdminToken, TermsExemptToken, generate_access_token},
+};
+
+pub fn legal_api() -> actix_web::Scope {
+    web::scope("/legal")
+        .service(current_documents)
+        .service(publish_document)
+        .service(accept_document)
+        .service(list_acceptances)
+}
+
+/// 条款类型：服务条款 / 隐私政策
+const LEGAL_KINDS: &[&str] = &["tos", "privacy"];
+
+/// 某个版本的服务条款或隐私政策
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LegalDocument {
+    pub kind: String,
+    pub version: i64,
+    pub title: String,
+    /// 条款正文或链接
+    pub content: String,
+    pub published_at: i64,
+}
+
+/// 用户接受条款的记录
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LegalAcceptance {
+    pub kind: String,
+    pub version: i64,
+    pub accepted_at: i64,
+}
+
+/// 签发访问令牌，并根据条款接受状态设置 terms_pending 标记、根据账号设置角色
+pub async fn issue_access_token(
+    user_id: &str,
+    username: &str,
+    session_id: Option<&str>,
+    config: &AuthConfig,
+    pool: &DbPool,
+) -> Result<String, String> {
+    let terms_pending = db::has_pending_terms(user_id, pool)
+        .await
+        .map_err(|e| format!("Failed to check terms: {}", e))?;
+    let role = db::get_user_role(user_id, pool)
+        .await
+        .map_err(|e| format!("Failed to load role: {}", e))?;
+    generate_access_token(user_id, username, session_id, terms_pending, role, config)
+}
+
+// 获取当前生效的条款（无需登录）
+#[get("/current")]
+async fn current_documents(pool: web::Data<DbPool>) -> impl Responder {
+    match db::get_current_legal_documents(&pool).await {
+        Ok(documents) => ApiResponse::new("获取条款成功", ResponseData::Json(json!(documents))),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取条款失败"),
+    }
+}
+
+// 发布新版本条款，所有用户需要重新接受
+#[derive(Deserialize)]
+pub struct PublishDocument {
+    pub kind: String,
+    pub title: String,
+    pub content: String,
+}
+
+#[post("")]
+async fn publish_document(
+    _admin: AdminToken,
+    pool: web::Data<DbPool>,
+    publish: web::Json<PublishDocument>,
+) -> impl Responder {
+    if !LEGAL_KINDS.contains(&publish.kind.as_str()) {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "无效的条款类型");
+    }
+    match db::insert_legal_document(&publish.kind, &publish.title, &publish.content, &pool).await {
+        Ok(document) => {
+            info!("发布条款 {} 版本 {}", document.kind, document.version);
+            ApiResponse::new("条款发布成功", ResponseData::Json(json!(document)))
+        }
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "条款发布失败"),
+    }
+}
+
+// 接受条款，返回更新了条款状态的新令牌
+#[derive(Deserialize)]
+pub struct AcceptDocument {
+    pub kind: String,
+    pub version: i64,
+}
+
+#[post("/accept")]
+async fn accept_document(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: TermsExemptToken,
+    accept: web::Json<AcceptDocument>,
+) -> impl Responder {
+    // 只能接受当前最新版本
+    let is_current = match db::get_current_legal_documents(&pool).await {
+        Ok(documents) => documents
+            .iter()
+            .any(|doc| doc.kind == accept.kind && doc.version == accept.version),
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "接受条款失败"),
+    };
+    if !is_current {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "条款版本不是最新版本");
+    }
+
+    if db::insert_legal_acceptance(&bearer_token.user_id, &accept.kind, accept.version, &pool)
+        .await
+        .is_err()
+    {
+        return ApiResponse::error(ErrorCode::InternalError, "接受条款失败");
+    }
+    info!(
+        "用户 {} 接受条款 {} 版本 {}",
+        bearer_token.user_id, accept.kind, accept.version
+    );
+
+    match issue_access_token(
+        &bearer_token.user_id,
+        &bearer_token.username,
+        bearer_token.session_id.as_deref(),
+        &app_state.config.auth,
+        &pool,
+    )
+    .await
+    {
+        Ok(token) => ApiResponse::new("已接受条款", ResponseData::Text(token)),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "接受条款失败"),
+    }
+}
+
+// 获取条款接受历史
+#[get("/acceptances")]
+async fn list_acceptances(
+    pool: web::Data<DbPool>,
+    bearer_token: TermsExemptToken,
+) -> impl Responder {
+    match db::get_legal_acceptances(&bearer_token.user_id, &pool).await {
+        Ok(acceptances) => {
+            ApiResponse::new("获取接受记录成功", ResponseData::Json(json!(acceptances)))
+        }
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取接受记录失败"),
+    }
+}
@@ -0,0 +1,46 @@
+// ClipFocus 服务器的全部模块，由服务器（main.rs）和命令行管理工具（bin/clipfocus-admin.rs）共用
+pub mod admin_api;
+pub mod announcement_api;
+pub mod audit;
+pub mod backup;
+pub mod batch_api;
+pub mod blob_store;
+pub mod concurrency;
+pub mod config;
+pub mod contact_api;
+pub mod cors;
+pub mod device_api;
+pub mod discovery;
+pub mod device_group_api;
+pub mod content_policy;
+pub mod file_api;
+pub mod grpc_api;
+pub mod idempotency;
+pub mod job_queue;
+pub mod legal_api;
+pub mod logging;
+pub mod clip_api;
+pub mod mailer;
+pub mod models;
+pub mod openapi_api;
+pub mod org_api;
+pub mod rate_limit;
+pub mod report_api;
+pub mod retention_api;
+#[cfg(feature = "s3")]
+pub mod s3;
+pub mod scheduled_api;
+pub mod sensitive;
+pub mod server_api;
+pub mod share_api;
+pub mod shutdown;
+pub mod sqlx_utils;
+pub mod user_api;
+pub mod spatial_api;
+pub mod stats_api;
+pub mod sync_api;
+pub mod tls;
+pub mod tag_api;
+pub mod upload_api;
+pub mod utils;
+pub mod webhook_api;
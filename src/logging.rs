@@ -0,0 +1,112 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::Error;
+use actix_web::error::InternalError;
+use std::io::IsTerminal;
+use std::time::Instant;
+use tracing::{Instrument, debug, info_span};
+use tracing_subscriber::EnvFilter;
+use uuid::Uuid;
+
+use crate::config::{LogFormat, LoggingConfig};
+
+/// 请求 ID 请求头：客户端提供时沿用（便于串联客户端日志），否则由服务器生成，并在响应中返回
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// 客户端提供的请求 ID 最大长度，超长或包含其他字符时重新生成
+const MAX_REQUEST_ID_LEN: usize = 64;
+
+/// 初始化日志输出，设置了 `RUST_LOG` 时以环境变量为准
+///
+/// 其他模块使用的 `log` 宏同样转发到 tracing，输出时带有所在 span 的字段（request_id、session_id 等）
+pub fn init_logging(config: &LoggingConfig) {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&config.level));
+    // 与之前一样输出到 stderr，重定向到文件时不输出颜色
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal());
+    match config.format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .init(),
+    }
+}
+
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+// 请求 ID 中间件：为每个请求创建带有 request_id 的 span，处理期间的日志都关联到该请求，
+// WebSocket 会话的 span 也以该 span 为父级
+pub async fn request_id(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| is_valid_request_id(id))
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    let span = info_span!(
+        "request",
+        request_id = %id,
+        method = %req.method(),
+        path = %req.path(),
+    );
+
+    let started = Instant::now();
+    let result = next.call(req).instrument(span.clone()).await;
+    let header_value = HeaderValue::from_str(&id).ok();
+    let header_name = HeaderName::from_static(REQUEST_ID_HEADER);
+    match result {
+        Ok(mut res) => {
+            span.in_scope(|| {
+                debug!(
+                    status = res.status().as_u16(),
+                    elapsed_ms = started.elapsed().as_millis() as u64,
+                    "request completed"
+                )
+            });
+            if let Some(value) = header_value {
+                res.headers_mut().insert(header_name, value);
+            }
+            Ok(res)
+        }
+        // 内层中间件（例如限流）返回的错误同样带上请求 ID
+        Err(e) => {
+            let mut response = e.error_response();
+            span.in_scope(|| debug!(status = response.status().as_u16(), "request rejected"));
+            if let Some(value) = header_value {
+                response.headers_mut().insert(header_name, value);
+            }
+            Err(InternalError::from_response(e, response).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_id_validation() {
+        assert!(is_valid_request_id("0f6c1e2a-trace.1_a"));
+        assert!(!is_valid_request_id(""));
+        assert!(!is_valid_request_id("has space"));
+        assert!(!is_valid_request_id("换行\n"));
+        assert!(!is_valid_request_id(&"a".repeat(MAX_REQUEST_ID_LEN + 1)));
+    }
+}
@@ -0,0 +1,121 @@
+use futures::future::{BoxFuture, FutureExt};
+use log::info;
+use std::env;
+
+/// 邮件发送接口，不同部署可替换具体实现
+///
+/// 在请求处理中直接调用，实现不能阻塞异步运行时
+pub trait Mailer: Send + Sync {
+    fn send<'a>(
+        &'a self,
+        to: &'a str,
+        subject: &'a str,
+        body: &'a str,
+    ) -> BoxFuture<'a, Result<(), String>>;
+}
+
+/// 只把邮件内容写入日志的实现，用于开发环境和未配置邮件服务的实例
+pub struct LogMailer;
+
+impl Mailer for LogMailer {
+    fn send<'a>(
+        &'a self,
+        to: &'a str,
+        subject: &'a str,
+        body: &'a str,
+    ) -> BoxFuture<'a, Result<(), String>> {
+        info!("📧 Mail to {} | {}\n{}", to, subject, body);
+        futures::future::ready(Ok(())).boxed()
+    }
+}
+
+/// 通过 SMTP 发送邮件（需要启用 `smtp` feature），从环境变量读取配置：
+///
+/// - `SMTP_HOST`：服务器地址，未设置时使用 LogMailer
+/// - `SMTP_PORT`：端口，默认按加密方式使用 587 / 465
+/// - `SMTP_STARTTLS`：是否使用 STARTTLS，默认 true，false 时使用 TLS 直连
+/// - `SMTP_USERNAME` / `SMTP_PASSWORD`：登录凭据
+/// - `SMTP_FROM`：发件人，如 `ClipFocus <noreply@example.com>`
+#[cfg(feature = "smtp")]
+pub struct SmtpMailer {
+    transport: lettre::AsyncSmtpTransport<lettre::Tokio1Executor>,
+    from: lettre::message::Mailbox,
+}
+
+#[cfg(feature = "smtp")]
+impl SmtpMailer {
+    pub fn from_env() -> Result<Option<Self>, String> {
+        use lettre::transport::smtp::authentication::Credentials;
+        type SmtpTransport = lettre::AsyncSmtpTransport<lettre::Tokio1Executor>;
+
+        let Ok(host) = env::var("SMTP_HOST") else {
+            return Ok(None);
+        };
+        let starttls = env::var("SMTP_STARTTLS")
+            .map(|v| !matches!(v.as_str(), "false" | "0"))
+            .unwrap_or(true);
+        let mut builder = if starttls {
+            SmtpTransport::starttls_relay(&host)
+        } else {
+            SmtpTransport::relay(&host)
+        }
+        .map_err(|e| format!("invalid SMTP_HOST {}: {}", host, e))?;
+        if let Some(port) = env::var("SMTP_PORT").ok().and_then(|v| v.parse().ok()) {
+            builder = builder.port(port);
+        }
+        if let (Ok(username), Ok(password)) = (env::var("SMTP_USERNAME"), env::var("SMTP_PASSWORD"))
+        {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+        let from = env::var("SMTP_FROM")
+            .map_err(|_| "SMTP_FROM is required when SMTP_HOST is set".to_string())?
+            .parse()
+            .map_err(|e| format!("invalid SMTP_FROM: {}", e))?;
+        info!("通过 SMTP 服务器 {} 发送邮件", host);
+        Ok(Some(SmtpMailer {
+            transport: builder.build(),
+            from,
+        }))
+    }
+}
+
+#[cfg(feature = "smtp")]
+impl Mailer for SmtpMailer {
+    fn send<'a>(
+        &'a self,
+        to: &'a str,
+        subject: &'a str,
+        body: &'a str,
+    ) -> BoxFuture<'a, Result<(), String>> {
+        use lettre::AsyncTransport;
+
+        async move {
+            let message = lettre::Message::builder()
+                .from(self.from.clone())
+                .to(to.parse().map_err(|e| format!("invalid recipient {}: {}", to, e))?)
+                .subject(subject)
+                .body(body.to_string())
+                .map_err(|e| e.to_string())?;
+            self.transport
+                .send(message)
+                .await
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        }
+        .boxed()
+    }
+}
+
+/// 根据配置选择邮件发送实现
+pub fn mailer_from_env() -> Result<std::sync::Arc<dyn Mailer>, String> {
+    #[cfg(feature = "smtp")]
+    if let Some(mailer) = SmtpMailer::from_env()? {
+        return Ok(std::sync::Arc::new(mailer));
+    }
+    Ok(std::sync::Arc::new(LogMailer))
+}
+
+/// 邮件中链接使用的服务对外地址，如 `https://clip.example.com`
+pub fn public_base_url() -> String {
+    env::var("PUBLIC_BASE_URL").unwrap_or_else(|_| "http://127.0.0.1:3000".to_string())
+}
@@ -1,66 +1,210 @@
-mod sqlx_utils;
-mod user_api;
-mod spatial_api;
-mod utils;
 
 use actix::Actor;
-use actix_web::{App, HttpServer, error as actix_error, web};
-use actix_cors::Cors; // 引入 CORS
+use actix_web::{App, HttpServer, error as actix_error, middleware, web};
 use dotenvy::dotenv;
 use log::info;
 use std::error::Error;
 
-use crate::spatial_api::models::{AppState, RoomManager};
-use crate::sqlx_utils::db::init_pool;
-use crate::user_api::user_api;
-use crate::spatial_api::ws_api;
+use clipfocus::{blob_store, grpc_api, sqlx_utils, tls};
+use clipfocus::admin_api::admin_api;
+use clipfocus::blob_store::{DELETE_BLOBS_JOB, DeleteBlobs};
+use clipfocus::announcement_api::announcement_api;
+use clipfocus::backup::BackupScheduler;
+use clipfocus::batch_api::batch_api;
+use clipfocus::clip_api::clip_api;
+use clipfocus::clip_api::revisions;
+use clipfocus::clip_api::service::ClipService;
+use clipfocus::config::Config;
+use clipfocus::contact_api::contact_api;
+use clipfocus::content_policy::ContentPolicy;
+use clipfocus::cors::CorsPolicy;
+use clipfocus::device_api::device_api;
+use clipfocus::device_api::last_seen::LastSeenRecorder;
+use clipfocus::device_group_api::device_group_api;
+use clipfocus::file_api::file_api;
+use clipfocus::job_queue::worker::JobQueue;
+use clipfocus::legal_api::legal_api;
+use clipfocus::logging::{init_logging, request_id};
+use clipfocus::mailer::mailer_from_env;
+use clipfocus::openapi_api::{openapi_json, swagger_ui};
+use clipfocus::org_api::org_api;
+use clipfocus::rate_limit::rate_limit;
+use clipfocus::report_api::report_api;
+use clipfocus::retention_api::janitor::Janitor;
+use clipfocus::retention_api::retention_api;
+use clipfocus::scheduled_api::models::DeliveryScheduler;
+use clipfocus::scheduled_api::scheduled_api;
+use clipfocus::server_api::{error_codes, server_info, server_time};
+use clipfocus::share_api::share_api;
+use clipfocus::stats_api::stats_api;
+use clipfocus::shutdown::shutdown_on_signal;
+use clipfocus::spatial_api::models::AppState;
+use clipfocus::sqlx_utils::db::init_pool;
+use clipfocus::sqlx_utils::models::{invalid_json, invalid_request};
+use clipfocus::user_api::{quota, two_factor, user_api};
+use clipfocus::user_api::service::UserService;
+use clipfocus::spatial_api::ws_api;
+use clipfocus::sync_api::sync_api;
+use clipfocus::tag_api::tag_api;
+use clipfocus::upload_api::upload_api;
+use clipfocus::webhook_api::dispatcher::WebhookDispatcher;
+use clipfocus::webhook_api::webhook_api;
 
 #[actix_web::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     dotenv().ok();
-    env_logger::Builder::from_default_env()
-        .filter_level(log::LevelFilter::Info)
-        .init();
 
-    let http_port = 3000;
+    // 加载配置文件和环境变量，随后按配置初始化日志
+    let config = Config::load()?;
+    init_logging(&config.logging);
+    info!("Loaded config from {} and CLIPFOCUS_* environment", Config::path());
 
     // 初始化数据库连接池
-    let pool = init_pool().await?;
+    let pool = init_pool(&config.database).await?;
     sqlx_utils::db::crate_db(&pool)
         .await
         .map_err(actix_error::ErrorInternalServerError)
         .err();
 
-    // 初始化房间管理器 Actor
-    let room_manager = RoomManager::new().start();
+    // 已有用户启用两步验证时必须设置加密密钥
+    two_factor::check_encryption_key(&pool).await?;
 
-    // 创建共享状态
-    let app_state = AppState {
-        room_manager: room_manager.clone(),
-    };
+    // 按配置选择头像和剪贴板文件的存储后端
+    blob_store::install(&config.storage)?;
+    // 默认的个人存储配额
+    quota::install(&config.quota);
+    // 每个剪贴板保留的历史版本数
+    revisions::install(&config.revisions);
 
-    info!("Starting Actix-Web server on http://127.0.0.1:{}", http_port);
+    // 加载实例级内容策略
+    let content_policy = ContentPolicy::load()?;
 
-    HttpServer::new(move || {
-        // 配置 CORS
-        let cors = Cors::default()
-            .allow_any_origin() // 允许所有来源访问，可根据需求改为 .allowed_origin("http://tauri.localhost")
-            .allow_any_method() // 允许 GET, POST 等请求方法
-            .allow_any_header() // 允许所有请求头
-            .supports_credentials(); // 如果需要发送 Cookie 或授权头
+    // 创建共享状态（内部启动房间管理器 Actor）
+    let app_state = AppState::new(content_policy, mailer_from_env()?, config.clone());
 
+    // 启动定时投递调度器
+    DeliveryScheduler::new(pool.clone(), app_state.room_manager.clone()).start();
+
+    // 启动保留策略清理器
+    Janitor::new(
+        pool.clone(),
+        app_state.room_manager.clone(),
+        config.auth.audit_log_retention_days,
+        config.trash.retention_days,
+    )
+    .start();
+
+    // 启动设备在线时间的批量写入
+    LastSeenRecorder::new(
+        pool.clone(),
+        app_state.room_manager.clone(),
+        config.spatial.last_seen_flush_interval(),
+    )
+    .start();
+
+    // 启动 Webhook 投递器
+    WebhookDispatcher::new(pool.clone(), config.webhook.clone()).start();
+
+    // 启动后台任务队列
+    JobQueue::new(pool.clone(), config.jobs.clone())
+        .handler(DELETE_BLOBS_JOB, DeleteBlobs)
+        .start();
+
+    // 账号和剪贴板的业务逻辑，HTTP 和 gRPC 接口共用
+    let users = web::Data::new(UserService::new(pool.clone(), config.auth.clone()));
+    let clips = web::Data::new(ClipService::new(pool.clone(), app_state.clone()));
+
+    // 启动 gRPC 服务（单独的端口）
+    grpc_api::start(&config, pool.clone(), app_state.clone(), clips.get_ref().clone());
+
+    // 启动数据库定时备份
+    if config.backup.enabled {
+        BackupScheduler::new(pool.clone(), config.backup.clone()).start();
+    }
+
+    info!(
+        "Starting Actix-Web server on http://{}:{}",
+        config.server.host, config.server.port
+    );
+
+    let room_manager = app_state.room_manager.clone();
+    let shutdown_pool = pool.clone();
+    let discovery = app_state.discovery.clone();
+    // 按配置的来源白名单检查跨域请求
+    let cors = CorsPolicy::from_config(&config)?;
+    let max_body_bytes = config.server.max_body_bytes;
+
+    let server = HttpServer::new(move || {
         App::new()
-            .wrap(cors) // 使用 CORS 中间件
+            .wrap(middleware::from_fn(request_id)) // 请求 ID 与日志 span，位于最外层
             .app_data(web::Data::new(app_state.clone()))
             .app_data(web::Data::new(pool.clone()))
+            .app_data(users.clone())
+            .app_data(clips.clone())
+            // 请求体、查询参数和路径参数解析失败时同样返回带错误码的响应，请求体超出大小限制时返回 413
+            .app_data(web::JsonConfig::default()
+                .limit(max_body_bytes)
+                .error_handler(|e, _| invalid_json(e)))
+            .app_data(web::PayloadConfig::new(max_body_bytes))
+            .app_data(web::QueryConfig::default().error_handler(|e, _| invalid_request(e)))
+            .app_data(web::PathConfig::default().error_handler(|e, _| invalid_request(e)))
+            // 实时同步接口和 REST 接口使用各自的 CORS 策略；CORS 位于限流外层，限流的响应同样带有 CORS 头
             .service(web::scope("/api/v1")
-                .service(user_api())
-                .service(ws_api())
+                .service(ws_api()
+                    .wrap(middleware::from_fn(rate_limit)) // 按用户 / IP 限流
+                    .wrap(cors.realtime_middleware()))
+                .service(web::scope("")
+                    .wrap(middleware::from_fn(rate_limit))
+                    .wrap(cors.middleware())
+                    .service(server_info)
+                    .service(error_codes)
+                    .service(server_time)
+                    .service(openapi_json)
+                    .service(swagger_ui)
+                    .service(user_api())
+                    .service(announcement_api())
+                    .service(scheduled_api())
+                    .service(contact_api())
+                    .service(legal_api())
+                    .service(batch_api())
+                    .service(sync_api())
+                    .service(device_api())
+                    .service(device_group_api())
+                    .service(admin_api())
+                    .service(report_api())
+                    .service(retention_api())
+                    .service(org_api())
+                    .service(clip_api())
+                    .service(upload_api())
+                    .service(tag_api())
+                    .service(stats_api())
+                    .service(share_api())
+                    .service(file_api())
+                    .service(webhook_api())
+                )
             )
-    })
-    .bind(("0.0.0.0", http_port))?
-    .run()
-    .await?;
+    });
+    let address = (config.server.host.as_str(), config.server.port);
+    let server = match tls::server_config(&config.tls)? {
+        // 配置了证书时直接以 HTTPS 提供服务，WebSocket 地址为 wss://
+        #[cfg(feature = "tls")]
+        Some(tls_config) => server.bind_rustls_0_23(address, tls_config)?,
+        None => server.bind(address)?,
+    }
+    // 停机信号由 shutdown_on_signal 处理，先通知 WebSocket 会话再停止服务
+    .disable_signals()
+    // 会话已在 shutdown_on_signal 中等待过，仍未断开的连接不再额外等待
+    .shutdown_timeout(1)
+    .run();
+
+    actix_web::rt::spawn(shutdown_on_signal(
+        server.handle(),
+        room_manager,
+        discovery,
+        shutdown_pool,
+        config.shutdown.clone(),
+    ));
+    server.await?;
 
     Ok(())
 }
@@ -1,11 +1,18 @@
 // clipfocus-core/src/models/mod.rs
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
+use crate::audit::AuditEvent;
+use crate::device_group_api::Visibility;
+use crate::sensitive::SensitiveCategory;
+use crate::sqlx_utils::models::Cursor;
+
 /// 剪贴板内容类型
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
-#[sqlx(type_name = "clip_type", rename_all = "snake_case")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
 pub enum ClipType {
     Text,           // 纯文本
     Html,           // HTML内容
@@ -16,8 +23,52 @@ pub enum ClipType {
     Unknown,        // 未知类型
 }
 
+impl ClipType {
+    /// 全部内容类型
+    pub const ALL: &'static [ClipType] = &[
+        ClipType::Text,
+        ClipType::Html,
+        ClipType::Url,
+        ClipType::FilePath,
+        ClipType::Image,
+        ClipType::Rtf,
+        ClipType::Unknown,
+    ];
+
+    /// 类型名称，与内容策略中的类型名一致
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClipType::Text => "text",
+            ClipType::Html => "html",
+            ClipType::Url => "url",
+            ClipType::FilePath => "file_path",
+            ClipType::Image => "image",
+            ClipType::Rtf => "rtf",
+            ClipType::Unknown => "unknown",
+        }
+    }
+
+    /// 按类型名称解析，名称无效时返回 None
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|clip_type| clip_type.as_str() == name)
+    }
+}
+
+/// 剪贴板内容哈希（SHA-256，十六进制），同一用户内相同类型和内容的剪贴板只保存一份
+pub fn content_hash(content_type: ClipType, content: &str) -> String {
+    use sha2::{Digest, Sha256};
+    let mut hasher = Sha256::new();
+    hasher.update(content_type.as_str().as_bytes());
+    hasher.update([0]);
+    hasher.update(content.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
 /// 剪贴板项目
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ClipItem {
     pub id: Uuid,
     pub device_id: Uuid,
@@ -49,11 +100,85 @@ pub struct ClipItem {
     
     /// 标签/分类
     pub tags: Vec<String>,
+
+    /// 可见范围
+    #[serde(default)]
+    pub visibility: Visibility,
+
+    /// 端到端加密使用的密钥 ID（由客户端管理，服务器只保存密文）
+    #[serde(default)]
+    pub key_id: Option<String>,
+
+    /// 端到端加密使用的随机数（Base64）
+    #[serde(default)]
+    pub nonce: Option<String>,
+
+    /// 版本号，每次修改加 1，修改和删除时通过 If-Match 校验
+    #[serde(default = "default_version")]
+    pub version: i64,
+
+    /// 已固定（收藏），固定的项目不会被保留策略清理
+    #[serde(default)]
+    pub pinned: bool,
+
+    /// 来源：自己的设备复制，或联系人发送
+    #[serde(default)]
+    pub source: ClipSource,
+
+    /// 发送者的用户 ID（仅 source 为 shared 时）
+    #[serde(default)]
+    pub shared_by: Option<String>,
+
+    /// 过期时间，到期后从所有设备删除（用于密码、验证码等敏感内容）
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+
+    /// 识别出的敏感内容类别，敏感剪贴板的预览已遮盖，且不参与全文搜索
+    #[serde(default)]
+    pub sensitive: Option<SensitiveCategory>,
+}
+
+fn default_version() -> i64 {
+    1
+}
+
+impl ClipItem {
+    /// 校验加密字段：加密的剪贴板必须同时提供密钥 ID 和随机数，且不能包含明文预览
+    pub fn check_encryption(&self) -> Result<(), &'static str> {
+        check_encryption(self.key_id.as_deref(), self.nonce.as_deref())?;
+        if self.encrypted != self.key_id.is_some() {
+            return Err("encrypted clips must provide key_id and nonce");
+        }
+        if self.encrypted && !self.preview.is_empty() {
+            return Err("encrypted clips must not contain a plaintext preview");
+        }
+        Ok(())
+    }
+}
+
+/// 密钥 ID 和随机数必须同时提供且不能为空
+pub fn check_encryption(key_id: Option<&str>, nonce: Option<&str>) -> Result<(), &'static str> {
+    match (key_id, nonce) {
+        (None, None) => Ok(()),
+        (Some(key_id), Some(nonce)) if !key_id.trim().is_empty() && !nonce.trim().is_empty() => {
+            Ok(())
+        }
+        _ => Err("key_id and nonce must be provided together"),
+    }
+}
+
+/// 过期时间必须晚于当前时间
+pub fn check_expiry(expires_at: Option<DateTime<Utc>>) -> Result<(), &'static str> {
+    match expires_at {
+        Some(expires_at) if expires_at <= Utc::now() => Err("expires_at must be in the future"),
+        _ => Ok(()),
+    }
 }
 
 /// 同步状态
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
-#[sqlx(type_name = "sync_status", rename_all = "snake_case")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
 pub enum SyncStatus {
     Local,          // 仅本地
     Syncing,        // 同步中
@@ -61,8 +186,36 @@ pub enum SyncStatus {
     Conflict,       // 冲突
 }
 
+/// 剪贴板来源
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum ClipSource {
+    /// 用户自己的设备
+    #[default]
+    Own,
+    /// 联系人发送
+    Shared,
+}
+
+/// 账号角色
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum UserRole {
+    /// 普通用户
+    #[default]
+    User,
+    /// 管理员，访问令牌带有该角色时可以调用管理接口
+    Admin,
+    /// 只读管理员，可以调用只查看数据的管理接口，不能修改或删除数据
+    Readonly,
+}
+
 /// 剪贴板项目创建请求
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct CreateClipRequest {
     pub device_id: Uuid,
     pub content_type: ClipType,
@@ -70,10 +223,17 @@ pub struct CreateClipRequest {
     pub preview: Option<String>,
     pub source_app: Option<String>,
     pub tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub visibility: Visibility,
+    /// 提供 key_id 和 nonce 时 content 为客户端加密后的密文（Base64）
+    pub key_id: Option<String>,
+    pub nonce: Option<String>,
+    /// 过期时间，必须晚于当前时间，到期后服务器删除剪贴板并通知所有设备
+    pub expires_at: Option<DateTime<Utc>>,
 }
 
 /// 剪贴板项目更新请求
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct UpdateClipRequest {
     pub accessed: bool,
     pub tags: Option<Vec<String>>,
@@ -90,6 +250,109 @@ pub struct ClipFilter {
     pub search_text: Option<String>,
     pub limit: Option<i32>,
     pub offset: Option<i32>,
+    /// 只返回已固定的项目
+    pub pinned_only: Option<bool>,
+    /// 多个标签的匹配方式，默认需同时包含所有标签
+    pub tag_match: Option<TagMatch>,
+    /// 上一页返回的 next_cursor，提供时忽略 offset
+    #[serde(default)]
+    pub cursor: Option<Cursor>,
+}
+
+/// 按多个标签过滤时的匹配方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TagMatch {
+    /// 同时包含所有标签
+    #[default]
+    All,
+    /// 包含任一标签
+    Any,
+}
+
+impl TagMatch {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TagMatch::All => "all",
+            TagMatch::Any => "any",
+        }
+    }
+}
+
+/// 全文搜索结果
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ClipSearchHit {
+    pub clip: ClipItem,
+    /// 匹配内容的片段，匹配部分用 `<mark></mark>` 标记
+    pub snippet: String,
+    /// 相关度，越大越相关
+    pub score: f64,
+}
+
+/// 回收站中的剪贴板
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TrashedClip {
+    #[serde(flatten)]
+    pub clip: ClipItem,
+    /// 移到回收站的时间（毫秒时间戳）
+    pub deleted_at: i64,
+}
+
+/// 剪贴板变更类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum ChangeOp {
+    Created,
+    Updated,
+    Deleted,
+}
+
+/// 以文本列保存的枚举，在 `Any` 驱动下按字符串读写
+macro_rules! impl_any_text_type {
+    ($($ty:ty),*) => {
+        $(
+            impl sqlx::Type<sqlx::Any> for $ty {
+                fn type_info() -> sqlx::any::AnyTypeInfo {
+                    <str as sqlx::Type<sqlx::Any>>::type_info()
+                }
+
+                fn compatible(ty: &sqlx::any::AnyTypeInfo) -> bool {
+                    <str as sqlx::Type<sqlx::Any>>::compatible(ty)
+                }
+            }
+
+            impl<'r> sqlx::Decode<'r, sqlx::Any> for $ty {
+                fn decode(
+                    value: sqlx::any::AnyValueRef<'r>,
+                ) -> Result<Self, sqlx::error::BoxDynError> {
+                    let text = <&str as sqlx::Decode<sqlx::Any>>::decode(value)?;
+                    // 文本与 serde 的 snake_case 名称一致
+                    Ok(serde_json::from_value(serde_json::Value::String(text.to_string()))?)
+                }
+            }
+        )*
+    };
+}
+
+impl_any_text_type!(
+    ClipType,
+    SyncStatus,
+    ChangeOp,
+    ClipSource,
+    UserRole,
+    SensitiveCategory,
+    AuditEvent
+);
+
+/// 增量同步中的一条变更（同一剪贴板只返回最新的一条）
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ClipChange {
+    pub seq: i64,
+    pub op: ChangeOp,
+    pub clip_id: Uuid,
+    /// 剪贴板当前的内容，删除时为空
+    pub clip: Option<ClipItem>,
 }
 
 
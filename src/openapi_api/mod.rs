@@ -0,0 +1,123 @@
+use actix_web::{HttpResponse, Responder, get, http::header};
+use std::sync::OnceLock;
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::openapi::{Content, Ref, Response};
+use utoipa::{Modify, OpenApi};
+
+use crate::{
+    clip_api::ClipApiDoc, device_api::DeviceApiDoc, sqlx_utils::models::ApiErrorEnvelope,
+    user_api::UserApiDoc,
+};
+
+/// 接口文档，客户端据此生成类型化的接口绑定
+///
+/// 所有接口都返回 `ApiEnvelope` 结构（即 ApiResponse），失败时 HTTP 状态码由 code 决定
+#[derive(OpenApi)]
+#[openapi(
+    nest(
+        (path = "/api/v1/user", api = UserApiDoc, tags = ["user"]),
+        (path = "/api/v1/clips", api = ClipApiDoc, tags = ["clips"]),
+        (path = "/api/v1/devices", api = DeviceApiDoc, tags = ["devices"]),
+    ),
+    components(schemas(ApiErrorEnvelope)),
+    modifiers(&BearerAuth, &ErrorResponses),
+)]
+pub struct ApiDoc;
+
+// 访问令牌通过 Authorization: Bearer 请求头携带
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        let mut scheme = Http::new(HttpAuthScheme::Bearer);
+        scheme.bearer_format = Some("JWT".to_string());
+        components.add_security_scheme("bearer", SecurityScheme::Http(scheme));
+    }
+}
+
+// 每个接口都可能返回失败响应，统一登记为 default 响应
+struct ErrorResponses;
+
+impl Modify for ErrorResponses {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let response = Response::builder()
+            .description("失败，HTTP 状态码和 code 见 /error_codes")
+            .content(
+                "application/json",
+                Content::new(Some(Ref::from_schema_name("ApiErrorEnvelope"))),
+            )
+            .build();
+        for item in openapi.paths.paths.values_mut() {
+            let operations = [
+                &mut item.get,
+                &mut item.put,
+                &mut item.post,
+                &mut item.delete,
+                &mut item.patch,
+            ];
+            for operation in operations.into_iter().flatten() {
+                operation
+                    .responses
+                    .responses
+                    .entry("default".to_string())
+                    .or_insert_with(|| response.clone().into());
+            }
+        }
+    }
+}
+
+// 文档在运行期间不会变化，只生成一次
+fn openapi_document() -> &'static str {
+    static DOCUMENT: OnceLock<String> = OnceLock::new();
+    DOCUMENT.get_or_init(|| ApiDoc::openapi().to_json().unwrap_or_default())
+}
+
+// OpenAPI 文档（无需登录）
+#[get("/openapi.json")]
+pub async fn openapi_json() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(openapi_document())
+}
+
+/// Swagger UI 页面，脚本和样式从 CDN 加载
+const SWAGGER_UI_PAGE: &str = r##"<!DOCTYPE html>
+<html><head><meta charset="utf-8"><title>ClipFocusServer API</title>
+<link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body><div id="swagger-ui"></div>
+<script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+<script>SwaggerUIBundle({ url: "openapi.json", dom_id: "#swagger-ui" });</script>
+</body></html>
+"##;
+
+// 浏览接口文档的 Swagger UI 页面（无需登录）
+#[get("/docs")]
+pub async fn swagger_ui() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .body(SWAGGER_UI_PAGE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    #[test]
+    fn document_nests_api_paths() {
+        let document: Value = serde_json::from_str(openapi_document()).unwrap();
+        let paths = &document["paths"];
+        assert!(paths["/api/v1/user/login"]["post"].is_object());
+        assert!(paths["/api/v1/clips"]["get"].is_object());
+        assert!(paths["/api/v1/clips/{id}"]["put"].is_object());
+        assert!(paths["/api/v1/devices/{id}"]["delete"].is_object());
+        let schemes = &document["components"]["securitySchemes"];
+        assert_eq!(schemes["bearer"]["scheme"], "bearer");
+        assert!(document["components"]["schemas"]["ClipItem"].is_object());
+        let login = &paths["/api/v1/user/login"]["post"]["responses"];
+        assert!(login["default"]["content"]["application/json"].is_object());
+    }
+}
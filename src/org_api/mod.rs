@@ -0,0 +1,298 @@
+use actix_web::{Responder, delete, get, post, put, web};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    contact_api::ensure_not_blocked,
+    spatial_api::models::{AppState, PushEvent},
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiResponse, ErrorCode, ResponseData},
+    },
+    user_api::auth::{AdminReadToken, AdminToken, BearerToken},
+};
+
+pub fn org_api() -> actix_web::Scope {
+    web::scope("/orgs")
+        .service(list_organizations)
+        .service(create_organization)
+        .service(update_organization_quota)
+        .service(current_organization)
+        .service(list_members)
+        .service(add_member)
+        .service(update_member)
+        .service(remove_member)
+}
+
+/// 组织（公司/家庭），组织之间的用户互相不可见
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Organization {
+    pub id: String,
+    pub name: String,
+    /// 组织的存储配额（字节），为空表示不限制
+    pub quota_bytes: Option<i64>,
+    pub created_at: i64,
+}
+
+/// 组织成员
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrgMember {
+    pub user_id: String,
+    pub username: String,
+    pub email: String,
+    /// owner / admin / member
+    pub role: String,
+}
+
+/// 可以分配给成员的角色，owner 只能在创建组织时指定
+const MEMBER_ROLES: &[&str] = &["admin", "member"];
+
+// 两个用户是否属于同一组织（都不属于任何组织也视为同一组织）
+pub async fn same_org(
+    user_id: &str,
+    other_id: &str,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    let (org, other_org) = tokio::try_join!(
+        db::get_user_org(user_id, pool),
+        db::get_user_org(other_id, pool)
+    )?;
+    Ok(org.map(|(org_id, _)| org_id) == other_org.map(|(org_id, _)| org_id))
+}
+
+// 当前用户所在组织及其管理员身份，不是组织管理员时返回错误响应
+async fn org_admin(user_id: &str, pool: &DbPool) -> Result<String, ApiResponse> {
+    match db::get_user_org(user_id, pool).await {
+        Ok(Some((org_id, role))) if role == "owner" || role == "admin" => Ok(org_id),
+        Ok(Some(_)) => Err(ApiResponse::error(
+            ErrorCode::Forbidden,
+            "需要组织管理员权限",
+        )),
+        Ok(None) => Err(ApiResponse::error(ErrorCode::NotFound, "未加入任何组织")),
+        Err(_) => Err(ApiResponse::error(ErrorCode::InternalError, "获取组织失败")),
+    }
+}
+
+// 获取所有组织（服务器管理员）
+#[get("")]
+async fn list_organizations(_admin: AdminReadToken, pool: web::Data<DbPool>) -> impl Responder {
+    match db::get_organizations(&pool).await {
+        Ok(orgs) => ApiResponse::new("获取组织成功", ResponseData::Json(json!(orgs))),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取组织失败"),
+    }
+}
+
+// 创建组织并指定所有者（服务器管理员），所有者不能已属于其他组织
+#[derive(Deserialize)]
+pub struct CreateOrganization {
+    pub name: String,
+    pub owner: String,
+    pub quota_bytes: Option<i64>,
+}
+
+#[post("")]
+async fn create_organization(
+    _admin: AdminToken,
+    pool: web::Data<DbPool>,
+    create: web::Json<CreateOrganization>,
+) -> impl Responder {
+    let create = create.into_inner();
+    let name = create.name.trim();
+    if name.is_empty() {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "组织名称不能为空");
+    }
+    if create.quota_bytes.is_some_and(|quota| quota < 0) {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "配额不能为负数");
+    }
+    let owner = match db::get_user_by_username_or_email(&create.owner, &pool).await {
+        Ok(user) => user,
+        Err(_) => return ApiResponse::error(ErrorCode::UserNotFound, "用户不存在"),
+    };
+
+    let org = Organization {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: name.to_string(),
+        quota_bytes: create.quota_bytes,
+        created_at: chrono::Utc::now().timestamp(),
+    };
+    match db::insert_organization(&org, &owner.user_id, &pool).await {
+        Ok(true) => {
+            info!("创建组织 {}，所有者 {}", org.name, owner.user_id);
+            ApiResponse::new("组织创建成功", ResponseData::Json(json!(org)))
+        }
+        Ok(false) => ApiResponse::error(ErrorCode::AlreadyExists, "该用户已属于其他组织"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "组织创建失败"),
+    }
+}
+
+// 修改组织配额（服务器管理员）
+#[derive(Deserialize)]
+pub struct UpdateQuota {
+    pub quota_bytes: Option<i64>,
+}
+
+#[put("/{id}/quota")]
+async fn update_organization_quota(
+    _admin: AdminToken,
+    pool: web::Data<DbPool>,
+    id: web::Path<String>,
+    update: web::Json<UpdateQuota>,
+) -> impl Responder {
+    if update.quota_bytes.is_some_and(|quota| quota < 0) {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "配额不能为负数");
+    }
+    match db::update_organization_quota(&id, update.quota_bytes, &pool).await {
+        Ok(true) => ApiResponse::new("配额修改成功", ResponseData::Null),
+        Ok(false) => ApiResponse::error(ErrorCode::NotFound, "组织不存在"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "配额修改失败"),
+    }
+}
+
+// 获取当前用户所在的组织、角色和用量
+#[get("/current")]
+async fn current_organization(
+    pool: web::Data<DbPool>,
+    bearer_token: BearerToken,
+) -> impl Responder {
+    let (org_id, role) = match db::get_user_org(&bearer_token.user_id, &pool).await {
+        Ok(Some(membership)) => membership,
+        Ok(None) => return ApiResponse::error(ErrorCode::NotFound, "未加入任何组织"),
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "获取组织失败"),
+    };
+    match tokio::try_join!(
+        db::get_organization(&org_id, &pool),
+        db::get_org_usage_bytes(&org_id, &pool)
+    ) {
+        Ok((Some(org), used_bytes)) => ApiResponse::new(
+            "获取组织成功",
+            ResponseData::Json(json!({
+                "organization": org,
+                "role": role,
+                "used_bytes": used_bytes,
+            })),
+        ),
+        Ok((None, _)) => ApiResponse::error(ErrorCode::NotFound, "未加入任何组织"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取组织失败"),
+    }
+}
+
+// 获取组织成员（组织管理员）
+#[get("/current/members")]
+async fn list_members(pool: web::Data<DbPool>, bearer_token: BearerToken) -> impl Responder {
+    let org_id = match org_admin(&bearer_token.user_id, &pool).await {
+        Ok(org_id) => org_id,
+        Err(response) => return response,
+    };
+    match db::get_org_members(&org_id, &pool).await {
+        Ok(members) => ApiResponse::new("获取组织成员成功", ResponseData::Json(json!(members))),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取组织成员失败"),
+    }
+}
+
+// 添加组织成员（组织管理员），只能添加未加入任何组织的用户
+#[derive(Deserialize)]
+pub struct AddMember {
+    pub username_or_email: String,
+    pub role: Option<String>,
+}
+
+#[post("/current/members")]
+async fn add_member(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    add: web::Json<AddMember>,
+) -> impl Responder {
+    let org_id = match org_admin(&bearer_token.user_id, &pool).await {
+        Ok(org_id) => org_id,
+        Err(response) => return response,
+    };
+    let add = add.into_inner();
+    let role = add.role.unwrap_or_else(|| "member".to_string());
+    if !MEMBER_ROLES.contains(&role.as_str()) {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "无效的角色");
+    }
+    let user = match db::get_user_by_username_or_email(&add.username_or_email, &pool).await {
+        Ok(user) => user,
+        Err(_) => return ApiResponse::error(ErrorCode::UserNotFound, "用户不存在"),
+    };
+    // 拉黑关系中的用户不能互相邀请加入组织
+    let message = "无法邀请该用户加入组织";
+    if let Err(response) =
+        ensure_not_blocked(&bearer_token.user_id, &user.user_id, message, &pool).await
+    {
+        return response;
+    }
+
+    match db::add_org_member(&org_id, &user.user_id, &role, &pool).await {
+        Ok(true) => {
+            info!("用户 {} 加入组织 {}（{}）", user.user_id, org_id, role);
+            app_state.room_manager.do_send(PushEvent {
+                user_id: user.user_id.clone(),
+                event: "org_joined".to_string(),
+                payload: json!({ "org_id": org_id, "role": role }),
+            });
+            ApiResponse::new(
+                "成员添加成功",
+                ResponseData::Json(json!({ "user_id": user.user_id, "role": role })),
+            )
+        }
+        Ok(false) => ApiResponse::error(ErrorCode::AlreadyExists, "该用户已属于某个组织"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "成员添加失败"),
+    }
+}
+
+// 修改成员角色（组织管理员），不能修改所有者
+#[derive(Deserialize)]
+pub struct UpdateMember {
+    pub role: String,
+}
+
+#[put("/current/members/{user_id}")]
+async fn update_member(
+    pool: web::Data<DbPool>,
+    bearer_token: BearerToken,
+    user_id: web::Path<String>,
+    update: web::Json<UpdateMember>,
+) -> impl Responder {
+    let org_id = match org_admin(&bearer_token.user_id, &pool).await {
+        Ok(org_id) => org_id,
+        Err(response) => return response,
+    };
+    if !MEMBER_ROLES.contains(&update.role.as_str()) {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "无效的角色");
+    }
+    match db::update_org_member_role(&org_id, &user_id, &update.role, &pool).await {
+        Ok(true) => ApiResponse::new("成员角色修改成功", ResponseData::Null),
+        Ok(false) => ApiResponse::error(ErrorCode::NotFound, "成员不存在或为组织所有者"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "成员角色修改失败"),
+    }
+}
+
+// 移除组织成员（组织管理员），不能移除所有者
+#[delete("/current/members/{user_id}")]
+async fn remove_member(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    user_id: web::Path<String>,
+) -> impl Responder {
+    let org_id = match org_admin(&bearer_token.user_id, &pool).await {
+        Ok(org_id) => org_id,
+        Err(response) => return response,
+    };
+    match db::remove_org_member(&org_id, &user_id, &pool).await {
+        Ok(true) => {
+            info!("用户 {} 被移出组织 {}", user_id, org_id);
+            app_state.room_manager.do_send(PushEvent {
+                user_id: user_id.into_inner(),
+                event: "org_left".to_string(),
+                payload: json!({ "org_id": org_id }),
+            });
+            ApiResponse::new("成员已移除", ResponseData::Null)
+        }
+        Ok(false) => ApiResponse::error(ErrorCode::NotFound, "成员不存在或为组织所有者"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "成员移除失败"),
+    }
+}
@@ -0,0 +1,271 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header;
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpRequest, HttpResponse, web};
+use log::warn;
+use std::collections::HashMap;
+use std::env;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::spatial_api::models::AppState;
+use crate::sqlx_utils::models::{ApiResponse, ErrorCode};
+use crate::user_api::auth::validate_access_token;
+
+/// 按 IP 限流的认证接口（防止暴力破解密码）
+const AUTH_PATHS: &[&str] = &[
+    "/api/v1/user/register",
+    "/api/v1/user/login",
+    "/api/v1/user/refresh_token",
+    "/api/v1/user/verify_email",
+    "/api/v1/user/resend_verification",
+    "/api/v1/user/forgot_password",
+    "/api/v1/user/reset_password",
+];
+
+/// 令牌桶数量超过该值时清理已经回满的桶
+const MAX_IDLE_BUCKETS: usize = 10_000;
+
+/// 令牌桶参数：最多累积 burst 个令牌，每分钟补充 per_minute 个
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+
+impl RateLimit {
+    /// 从环境变量 `RATE_LIMIT_{NAME}_BURST` / `RATE_LIMIT_{NAME}_PER_MINUTE` 读取，
+    /// per_minute 为 0 时不限流
+    pub fn from_env(name: &str, burst: u32, per_minute: u32) -> Self {
+        let read = |suffix: &str, default: u32| {
+            env::var(format!("RATE_LIMIT_{}_{}", name, suffix))
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        };
+        RateLimit {
+            burst: read("BURST", burst).max(1),
+            per_minute: read("PER_MINUTE", per_minute),
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+/// 按键（用户 ID 或 IP）分别计数的令牌桶限流器
+#[derive(Clone)]
+pub struct RateLimiter {
+    limit: RateLimit,
+    buckets: Arc<Mutex<HashMap<String, Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        RateLimiter {
+            limit,
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// 消耗一个令牌，令牌不足时返回需要等待的时间
+    pub fn check(&self, key: &str) -> Result<(), Duration> {
+        if self.limit.per_minute == 0 {
+            return Ok(());
+        }
+        let burst = self.limit.burst as f64;
+        let per_sec = self.limit.per_minute as f64 / 60.0;
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+
+        if buckets.len() > MAX_IDLE_BUCKETS {
+            buckets.retain(|_, bucket| {
+                let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+                bucket.tokens + elapsed * per_sec < burst
+            });
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: burst,
+            updated_at: now,
+        });
+        let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * per_sec).min(burst);
+        bucket.updated_at = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_sec))
+        }
+    }
+}
+
+/// 全部限流器，限制可通过环境变量调整
+#[derive(Clone)]
+pub struct RateLimiters {
+    /// 注册、登录、刷新令牌、邮箱验证和找回密码，按 IP 限流
+    pub auth: RateLimiter,
+    /// 其他 HTTP 接口，已登录时按用户、否则按 IP 限流
+    pub api: RateLimiter,
+    /// WebSocket 消息，按用户限流（同一用户的所有会话共用）
+    pub ws: RateLimiter,
+    /// 可信的反向代理地址，只有来自这些地址的请求才使用 X-Forwarded-For 中的客户端 IP
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+impl RateLimiters {
+    pub fn from_env() -> Self {
+        RateLimiters {
+            auth: RateLimiter::new(RateLimit::from_env("AUTH", 10, 10)),
+            api: RateLimiter::new(RateLimit::from_env("API", 120, 600)),
+            ws: RateLimiter::new(RateLimit::from_env("WS", 60, 600)),
+            trusted_proxies: trusted_proxies_from_env(),
+        }
+    }
+
+    /// 客户端 IP：默认使用 TCP 连接的对端地址；对端是可信代理时，
+    /// 从 X-Forwarded-For 的最右侧开始跳过可信代理，取第一个不可信的地址
+    pub fn client_ip(&self, req: &HttpRequest) -> Option<IpAddr> {
+        let peer = req.peer_addr()?.ip();
+        if !self.trusted_proxies.contains(&peer) {
+            return Some(peer);
+        }
+        let forwarded: Vec<IpAddr> = req
+            .headers()
+            .get_all("x-forwarded-for")
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|hop| hop.trim().parse().ok())
+            .collect();
+        Some(
+            forwarded
+                .into_iter()
+                .rev()
+                .find(|hop| !self.trusted_proxies.contains(hop))
+                .unwrap_or(peer),
+        )
+    }
+}
+
+// 从环境变量 `TRUSTED_PROXIES` 读取可信代理地址（逗号分隔），未配置时不信任任何转发头
+fn trusted_proxies_from_env() -> Vec<IpAddr> {
+    env::var("TRUSTED_PROXIES")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|addr| !addr.is_empty())
+        .filter_map(|addr| match addr.parse() {
+            Ok(ip) => Some(ip),
+            Err(_) => {
+                warn!("忽略无效的可信代理地址: {}", addr);
+                None
+            }
+        })
+        .collect()
+}
+
+/// 等待时间向上取整为秒，用于 Retry-After
+pub fn retry_after_secs(wait: Duration) -> u64 {
+    wait.as_secs_f64().ceil().max(1.0) as u64
+}
+
+// 限流中间件：超出限制时返回 429 和 Retry-After
+pub async fn rate_limit(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    if let Some(app_state) = req.app_data::<web::Data<AppState>>() {
+        let limiters = &app_state.rate_limiters;
+        let ip = limiters
+            .client_ip(req.request())
+            .map(|ip| ip.to_string())
+            .unwrap_or_else(|| "unknown".to_string());
+        let user_id = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .and_then(|token| validate_access_token(token.trim()).ok())
+            .map(|claims| claims.user_id);
+
+        let checked = if AUTH_PATHS.contains(&req.path()) {
+            limiters.auth.check(&format!("ip:{}", ip))
+        } else {
+            match user_id {
+                Some(user_id) => limiters.api.check(&format!("user:{}", user_id)),
+                None => limiters.api.check(&format!("ip:{}", ip)),
+            }
+        };
+        if let Err(wait) = checked {
+            return Err(too_many_requests(retry_after_secs(wait)));
+        }
+    }
+    next.call(req).await
+}
+
+fn too_many_requests(retry_after: u64) -> Error {
+    let message = "请求过于频繁，请稍后重试";
+    let body = ApiResponse::error(ErrorCode::RateLimited, message);
+    let response = HttpResponse::TooManyRequests()
+        .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+        .json(body);
+    InternalError::from_response(message, response).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(burst: u32, per_minute: u32) -> RateLimiter {
+        RateLimiter::new(RateLimit { burst, per_minute })
+    }
+
+    #[test]
+    fn check_allows_burst_then_limits() {
+        let limiter = limiter(2, 60);
+        assert!(limiter.check("a").is_ok());
+        assert!(limiter.check("a").is_ok());
+        let wait = limiter.check("a").unwrap_err();
+        assert!(wait > Duration::from_millis(900) && wait <= Duration::from_secs(1));
+        assert_eq!(retry_after_secs(wait), 1);
+    }
+
+    #[test]
+    fn check_counts_keys_separately() {
+        let limiter = limiter(1, 60);
+        assert!(limiter.check("a").is_ok());
+        assert!(limiter.check("a").is_err());
+        assert!(limiter.check("b").is_ok());
+    }
+
+    #[test]
+    fn check_refills_over_time() {
+        // 每 10 毫秒补充一个令牌
+        let limiter = limiter(1, 6000);
+        assert!(limiter.check("a").is_ok());
+        assert!(limiter.check("a").is_err());
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(limiter.check("a").is_ok());
+    }
+
+    #[test]
+    fn check_without_limit() {
+        let limiter = limiter(1, 0);
+        for _ in 0..10 {
+            assert!(limiter.check("a").is_ok());
+        }
+    }
+
+    #[test]
+    fn retry_after_rounds_up() {
+        assert_eq!(retry_after_secs(Duration::from_millis(1)), 1);
+        assert_eq!(retry_after_secs(Duration::from_millis(1500)), 2);
+        assert_eq!(retry_after_secs(Duration::from_secs(30)), 30);
+    }
+}
@@ -0,0 +1,241 @@
+use actix_web::{HttpRequest, Responder, get, post, web};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    audit::AuditRecord,
+    org_api::same_org,
+    spatial_api::models::{AppState, PushEvent},
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiResponse, ErrorCode, ResponseData},
+    },
+    user_api::auth::{AdminReadToken, AdminToken, BearerToken},
+};
+
+pub fn report_api() -> actix_web::Scope {
+    web::scope("/reports")
+        .service(create_report)
+        .service(list_reports)
+        .service(resolve_report)
+}
+
+/// 举报记录，管理员处理后记录处理动作
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AbuseReport {
+    pub id: String,
+    pub reporter_id: String,
+    /// 举报对象类型：clip / user
+    pub target_type: String,
+    /// 被举报的剪贴板 ID 或用户 ID
+    pub target_id: String,
+    /// 被举报内容的发送者
+    pub reported_user_id: String,
+    pub reason: String,
+    pub details: Option<String>,
+    pub created_at: i64,
+    /// open / resolved
+    pub status: String,
+    /// 处理动作，未处理时为空
+    pub action: Option<String>,
+    pub note: Option<String>,
+    pub resolved_at: Option<i64>,
+}
+
+const TARGET_TYPES: &[&str] = &["clip", "user"];
+
+const REPORT_REASONS: &[&str] = &[
+    "spam",
+    "harassment",
+    "illegal",
+    "malware",
+    "sensitive",
+    "other",
+];
+
+/// 管理员可执行的处理动作
+const REPORT_ACTIONS: &[&str] = &["dismiss", "remove_clip", "warn_user", "disable_user"];
+
+// 举报收到的剪贴板或发送者
+#[derive(Deserialize)]
+pub struct CreateReport {
+    pub target_type: String,
+    pub target_id: String,
+    pub reported_user_id: String,
+    pub reason: String,
+    pub details: Option<String>,
+}
+
+#[post("")]
+async fn create_report(
+    pool: web::Data<DbPool>,
+    bearer_token: BearerToken,
+    create: web::Json<CreateReport>,
+) -> impl Responder {
+    let create = create.into_inner();
+    if !TARGET_TYPES.contains(&create.target_type.as_str()) {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "无效的举报对象类型");
+    }
+    if !REPORT_REASONS.contains(&create.reason.as_str()) {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "无效的举报原因");
+    }
+    if create.reported_user_id == bearer_token.user_id {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "不能举报自己");
+    }
+    // 其他组织的用户视为不存在
+    match same_org(&bearer_token.user_id, &create.reported_user_id, &pool).await {
+        Ok(true) if db::get_user_by_id(&create.reported_user_id, &pool).await.is_ok() => {}
+        Ok(_) => return ApiResponse::error(ErrorCode::UserNotFound, "用户不存在"),
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "举报提交失败"),
+    }
+
+    let report = AbuseReport {
+        id: uuid::Uuid::new_v4().to_string(),
+        reporter_id: bearer_token.user_id,
+        target_type: create.target_type,
+        target_id: create.target_id,
+        reported_user_id: create.reported_user_id,
+        reason: create.reason,
+        details: create.details,
+        created_at: chrono::Utc::now().timestamp(),
+        status: "open".to_string(),
+        action: None,
+        note: None,
+        resolved_at: None,
+    };
+    info!(
+        "用户 {} 举报 {} {}（{}）",
+        report.reporter_id, report.target_type, report.target_id, report.reason
+    );
+
+    match db::insert_abuse_report(&report, &pool).await {
+        // 不向举报人返回处理信息
+        Ok(_) => ApiResponse::new("举报已提交", ResponseData::Json(json!({ "id": report.id }))),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "举报提交失败"),
+    }
+}
+
+// 举报审核队列
+#[derive(Deserialize)]
+pub struct ReportQuery {
+    /// open / resolved，默认 open
+    pub status: Option<String>,
+}
+
+#[get("")]
+async fn list_reports(
+    _admin: AdminReadToken,
+    pool: web::Data<DbPool>,
+    query: web::Query<ReportQuery>,
+) -> impl Responder {
+    let status = query.status.as_deref().unwrap_or("open");
+    match db::get_abuse_reports(status, &pool).await {
+        Ok(reports) => ApiResponse::new("获取举报成功", ResponseData::Json(json!(reports))),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取举报失败"),
+    }
+}
+
+// 处理举报，处理动作记录在举报上
+#[derive(Deserialize)]
+pub struct ResolveReport {
+    pub action: String,
+    pub note: Option<String>,
+}
+
+#[post("/{id}/resolve")]
+async fn resolve_report(
+    admin: AdminToken,
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    id: web::Path<String>,
+    resolve: web::Json<ResolveReport>,
+) -> impl Responder {
+    let resolve = resolve.into_inner();
+    if !REPORT_ACTIONS.contains(&resolve.action.as_str()) {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "无效的处理动作");
+    }
+    let report = match db::get_abuse_report(&id, &pool).await {
+        Ok(Some(report)) if report.status == "open" => report,
+        Ok(_) => return ApiResponse::error(ErrorCode::NotFound, "举报不存在或已处理"),
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "处理举报失败"),
+    };
+    if resolve.action == "remove_clip" && report.target_type != "clip" {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "举报对象不是剪贴板");
+    }
+
+    // 服务端保存的剪贴板直接彻底删除（不进回收站），经 WebSocket 转发的剪贴板只能通知设备删除
+    if resolve.action == "remove_clip"
+        && let Ok(clip_id) = uuid::Uuid::parse_str(&report.target_id)
+        && db::purge_clip(&report.reported_user_id, &clip_id, &pool)
+            .await
+            .is_err()
+    {
+        return ApiResponse::error(ErrorCode::InternalError, "处理举报失败");
+    }
+    if resolve.action == "disable_user"
+        && db::set_user_disabled(&report.reported_user_id, true, &pool)
+            .await
+            .is_err()
+    {
+        return ApiResponse::error(ErrorCode::InternalError, "处理举报失败");
+    }
+    let resolved_at = match db::resolve_abuse_report(
+        &id,
+        &resolve.action,
+        resolve.note.as_deref(),
+        &pool,
+    )
+    .await
+    {
+        Ok(Some(resolved_at)) => resolved_at,
+        Ok(None) => return ApiResponse::error(ErrorCode::NotFound, "举报不存在或已处理"),
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "处理举报失败"),
+    };
+    info!(
+        "管理员处理举报 {}：{}（被举报用户 {}）",
+        report.id, resolve.action, report.reported_user_id
+    );
+    AuditRecord::admin_action("resolve_report", &admin, &req, &app_state)
+        .user(&report.reported_user_id)
+        .detail(json!({ "report_id": report.id, "report_action": resolve.action }))
+        .record(&pool)
+        .await;
+
+    let notice = |user_id: &str, event: &str, payload| {
+        app_state.room_manager.do_send(PushEvent {
+            user_id: user_id.to_string(),
+            event: event.to_string(),
+            payload,
+        })
+    };
+    match resolve.action.as_str() {
+        "remove_clip" => {
+            // 发送方和接收方的设备都删除该剪贴板
+            let payload = json!({ "id": report.target_id, "reason": report.reason });
+            notice(&report.reported_user_id, "clip_removed", payload.clone());
+            notice(&report.reporter_id, "clip_removed", payload);
+        }
+        "warn_user" => notice(
+            &report.reported_user_id,
+            "account_warning",
+            json!({ "reason": report.reason, "note": resolve.note }),
+        ),
+        "disable_user" => notice(
+            &report.reported_user_id,
+            "account_disabled",
+            json!({ "reason": report.reason }),
+        ),
+        _ => {}
+    }
+
+    ApiResponse::new(
+        "举报已处理",
+        ResponseData::Json(json!({
+            "id": report.id,
+            "action": resolve.action,
+            "resolved_at": resolved_at,
+        })),
+    )
+}
@@ -0,0 +1,271 @@
+use actix::prelude::*;
+use log::{info, warn};
+use serde_json::{Map, Value, json};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::blob_store::blob_store;
+use crate::device_group_api::push_visible;
+use crate::retention_api::{RetainedClip, RetentionPolicy};
+use crate::spatial_api::{binary::BINARY_CLIP_TYPES, models::RoomManager};
+use crate::sqlx_utils::{DbPool, db};
+use crate::upload_api::remove_upload_dir;
+use crate::webhook_api::{self, WebhookEvent};
+
+/// 保留策略清理间隔
+const PRUNE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// 过期剪贴板的检查间隔，剪贴板最迟在过期后该时间内被删除
+const EXPIRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 清理器，周期性地按用户的保留策略删除旧剪贴板及其附件文件，删除已过期的剪贴板，
+/// 并清理回收站、过期未完成的分块上传和超过保留期的审计日志
+pub struct Janitor {
+    pool: DbPool,
+    room_manager: Addr<RoomManager>,
+    // 审计日志保留天数，0 表示永久保留
+    audit_log_retention_days: i64,
+    // 回收站保留天数，0 表示永久保留
+    trash_retention_days: i64,
+}
+
+impl Janitor {
+    pub fn new(
+        pool: DbPool,
+        room_manager: Addr<RoomManager>,
+        audit_log_retention_days: i64,
+        trash_retention_days: i64,
+    ) -> Self {
+        Self {
+            pool,
+            room_manager,
+            audit_log_retention_days,
+            trash_retention_days,
+        }
+    }
+
+    /// 立即执行一次全部清理（保留策略、过期剪贴板、回收站、分块上传和审计日志），供命令行管理工具使用
+    pub async fn run_once(&self) {
+        prune_all(
+            self.pool.clone(),
+            self.room_manager.clone(),
+            self.audit_log_retention_days,
+            self.trash_retention_days,
+        )
+        .await;
+        prune_expired(self.pool.clone(), self.room_manager.clone()).await;
+    }
+}
+
+// 读取所有设置了保留策略的用户
+async fn retention_policies(pool: &DbPool) -> Result<Vec<(String, RetentionPolicy)>, sqlx::Error> {
+    let mut settings: HashMap<String, Map<String, Value>> = HashMap::new();
+    for (user_id, key, raw) in db::get_retention_settings(pool).await? {
+        match serde_json::from_str(&raw) {
+            Ok(value) => {
+                settings.entry(user_id).or_default().insert(key, value);
+            }
+            Err(e) => warn!("用户 {} 的设置项 {} 无法解析: {}", user_id, key, e),
+        }
+    }
+    Ok(settings
+        .into_iter()
+        .map(|(user_id, settings)| (user_id, RetentionPolicy::from_settings(&settings)))
+        .filter(|(_, policy)| policy.is_limited())
+        .collect())
+}
+
+// 删除过期未完成的分块上传及其分块文件
+async fn prune_uploads(pool: &DbPool) {
+    match db::delete_expired_uploads(chrono::Utc::now().timestamp(), pool).await {
+        Ok(expired) => {
+            for (user_id, id) in &expired {
+                remove_upload_dir(user_id, id).await;
+            }
+            if !expired.is_empty() {
+                info!("清理 {} 个过期的分块上传", expired.len());
+            }
+        }
+        Err(e) => warn!("清理过期的分块上传失败: {}", e),
+    }
+}
+
+// 删除超过保留期的审计日志
+async fn prune_audit_log(retention_days: i64, pool: &DbPool) {
+    if retention_days <= 0 {
+        return;
+    }
+    let before = chrono::Utc::now().timestamp() - retention_days * 24 * 60 * 60;
+    match db::delete_audit_log_before(before, pool).await {
+        Ok(0) => {}
+        Ok(deleted) => info!("清理 {} 条超过保留期的审计日志", deleted),
+        Err(e) => warn!("清理审计日志失败: {}", e),
+    }
+}
+
+// 彻底删除在回收站中超过保留期的剪贴板及其附件文件
+async fn prune_trash(retention_days: i64, pool: &DbPool) {
+    if retention_days <= 0 {
+        return;
+    }
+    let before = chrono::Utc::now().timestamp_millis() - retention_days * 24 * 60 * 60 * 1000;
+    let trashed = match db::get_trash_expired_clips(before, pool).await {
+        Ok(trashed) => trashed,
+        Err(e) => {
+            warn!("查询回收站中过期的剪贴板失败: {}", e);
+            return;
+        }
+    };
+    let mut purged = 0;
+    for (user_id, clip) in &trashed {
+        match purge_clip(user_id, clip, pool).await {
+            Ok(true) => purged += 1,
+            Ok(false) => {}
+            Err(e) => warn!(
+                "清理用户 {} 回收站中的剪贴板 {} 失败: {}",
+                user_id, clip.id, e
+            ),
+        }
+    }
+    if purged > 0 {
+        info!("清理回收站中 {} 个超过保留期的剪贴板", purged);
+    }
+}
+
+// 按保留策略清理所有用户的剪贴板
+async fn prune_all(
+    pool: DbPool,
+    room_manager: Addr<RoomManager>,
+    audit_log_retention_days: i64,
+    trash_retention_days: i64,
+) {
+    prune_uploads(&pool).await;
+    prune_audit_log(audit_log_retention_days, &pool).await;
+    prune_trash(trash_retention_days, &pool).await;
+    let policies = match retention_policies(&pool).await {
+        Ok(policies) => policies,
+        Err(e) => {
+            warn!("查询保留策略失败: {}", e);
+            return;
+        }
+    };
+    for (user_id, policy) in policies {
+        match prune_user(&user_id, &policy, &pool, &room_manager).await {
+            Ok(0) => {}
+            Ok(pruned) => info!("按保留策略清理用户 {} 的 {} 个剪贴板", user_id, pruned),
+            Err(e) => warn!("清理用户 {} 的剪贴板失败: {}", user_id, e),
+        }
+    }
+}
+
+// 彻底删除剪贴板及其附件文件，剪贴板已不存在时返回 false
+async fn purge_clip(
+    user_id: &str,
+    clip: &RetainedClip,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    if !db::purge_clip(user_id, &clip.id, pool).await? {
+        return Ok(false);
+    }
+    if BINARY_CLIP_TYPES.contains(&clip.content_type)
+        && let Err(e) = blob_store().delete(&clip.content).await
+    {
+        warn!("remove {} failed: {}", clip.content, e);
+    }
+    Ok(true)
+}
+
+// 彻底删除剪贴板及其附件文件，并通知可见的设备和 Webhook，剪贴板已不存在时返回 false
+async fn remove_clip(
+    user_id: &str,
+    clip: &RetainedClip,
+    event: &str,
+    payload: Value,
+    pool: &DbPool,
+    room_manager: &Addr<RoomManager>,
+) -> Result<bool, sqlx::Error> {
+    if !purge_clip(user_id, clip, pool).await? {
+        return Ok(false);
+    }
+    if let Some(webhook_event) = WebhookEvent::for_clip_event(event) {
+        webhook_api::enqueue(user_id, webhook_event, payload.clone(), pool).await;
+    }
+    push_visible(
+        user_id,
+        &clip.visibility,
+        event,
+        payload,
+        pool,
+        room_manager,
+    )
+    .await?;
+    Ok(true)
+}
+
+// 删除所有已过期的剪贴板，并向可见的设备推送 clip.expired 事件
+async fn prune_expired(pool: DbPool, room_manager: Addr<RoomManager>) {
+    let now = chrono::Utc::now().timestamp_millis();
+    let expired = match db::get_expired_clips(now, &pool).await {
+        Ok(expired) => expired,
+        Err(e) => {
+            warn!("查询过期的剪贴板失败: {}", e);
+            return;
+        }
+    };
+    let mut removed = 0;
+    for (user_id, clip) in &expired {
+        let payload = json!({ "id": clip.id });
+        match remove_clip(user_id, clip, "clip.expired", payload, &pool, &room_manager).await {
+            Ok(true) => removed += 1,
+            Ok(false) => {}
+            Err(e) => warn!("删除用户 {} 过期的剪贴板 {} 失败: {}", user_id, clip.id, e),
+        }
+    }
+    if removed > 0 {
+        info!("删除 {} 个过期的剪贴板", removed);
+    }
+}
+
+// 删除超出保留策略的剪贴板、附件文件，并通知用户的设备，返回删除的项目数
+async fn prune_user(
+    user_id: &str,
+    policy: &RetentionPolicy,
+    pool: &DbPool,
+    room_manager: &Addr<RoomManager>,
+) -> Result<usize, sqlx::Error> {
+    let clips = db::get_retention_candidates(user_id, pool).await?;
+    let now = chrono::Utc::now().timestamp_millis();
+    let mut pruned = 0;
+    for clip in policy.select_prunable(&clips, now) {
+        let payload = json!({ "id": clip.id, "reason": "retention" });
+        // 已被用户删除的项目不计数
+        if remove_clip(user_id, clip, "clip_deleted", payload, pool, room_manager).await? {
+            pruned += 1;
+        }
+    }
+    Ok(pruned)
+}
+
+impl Actor for Janitor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        info!("Janitor started");
+
+        ctx.run_interval(PRUNE_INTERVAL, |act, ctx| {
+            prune_all(
+                act.pool.clone(),
+                act.room_manager.clone(),
+                act.audit_log_retention_days,
+                act.trash_retention_days,
+            )
+                .into_actor(act)
+                .wait(ctx);
+        });
+        ctx.run_interval(EXPIRY_INTERVAL, |act, ctx| {
+            prune_expired(act.pool.clone(), act.room_manager.clone())
+                .into_actor(act)
+                .wait(ctx);
+        });
+    }
+}
@@ -0,0 +1,222 @@
+pub mod janitor;
+
+use actix_web::{Responder, get, put, web};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use uuid::Uuid;
+
+use crate::{
+    device_group_api::Visibility,
+    models::ClipType,
+    spatial_api::models::{AppState, PushEvent},
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiResponse, ErrorCode, ResponseData},
+    },
+    user_api::{
+        app_rules::{get_app_rules, put_app_rules},
+        auth::BearerToken,
+        settings::{load_settings, parse_changes},
+    },
+};
+
+pub fn retention_api() -> actix_web::Scope {
+    // 设置下的其他接口与保留策略共用 /settings 作用域
+    web::scope("/settings")
+        .service(get_retention)
+        .service(put_retention)
+        .service(get_app_rules)
+        .service(put_app_rules)
+}
+
+/// 每天的毫秒数
+const DAY_MILLIS: i64 = 24 * 3600 * 1000;
+
+/// 剪贴板保留策略，保存在用户设置的 `retention_*` 项中
+///
+/// 未设置或为 0 表示不限制，超出任一限制的旧项目会被 Janitor 定期清理，已固定的项目不计入也不会被清理
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    /// 最多保留的项目数
+    #[serde(default)]
+    pub max_items: Option<i64>,
+    /// 最长保留天数
+    #[serde(default)]
+    pub max_age_days: Option<i64>,
+    /// 最多占用的字节数（包括附件文件）
+    #[serde(default)]
+    pub max_bytes: Option<i64>,
+}
+
+impl RetentionPolicy {
+    /// 从用户设置中读取保留策略
+    pub fn from_settings(settings: &Map<String, Value>) -> Self {
+        let limit = |key: &str| settings.get(key).and_then(Value::as_i64);
+        Self {
+            max_items: limit("retention_max_items"),
+            max_age_days: limit("retention_max_age_days"),
+            max_bytes: limit("retention_max_bytes"),
+        }
+    }
+
+    // 转换为设置修改，值为 null 时恢复默认（不限制）
+    fn to_changes(&self) -> Map<String, Value> {
+        let mut changes = Map::new();
+        changes.insert("retention_max_items".to_string(), json!(self.max_items));
+        changes.insert("retention_max_age_days".to_string(), json!(self.max_age_days));
+        changes.insert("retention_max_bytes".to_string(), json!(self.max_bytes));
+        changes
+    }
+
+    /// 是否设置了任一限制
+    pub fn is_limited(&self) -> bool {
+        [self.max_items, self.max_age_days, self.max_bytes]
+            .iter()
+            .any(|limit| limit.is_some_and(|n| n > 0))
+    }
+
+    /// 按策略选出需要清理的项目，`clips` 需按创建时间从新到旧排列，`now` 为毫秒时间戳
+    pub fn select_prunable<'a>(&self, clips: &'a [RetainedClip], now: i64) -> Vec<&'a RetainedClip> {
+        let positive = |limit: Option<i64>| limit.filter(|n| *n > 0);
+        let oldest = positive(self.max_age_days).map(|days| now - days * DAY_MILLIS);
+        let mut total_bytes = 0;
+        clips
+            .iter()
+            .enumerate()
+            .filter(|(index, clip)| {
+                total_bytes += clip.size;
+                positive(self.max_items).is_some_and(|max| *index as i64 >= max)
+                    || oldest.is_some_and(|oldest| clip.created_at < oldest)
+                    || positive(self.max_bytes).is_some_and(|max| total_bytes > max)
+            })
+            .map(|(_, clip)| clip)
+            .collect()
+    }
+}
+
+/// 保留策略清理时需要的剪贴板信息
+#[derive(Debug, Clone)]
+pub struct RetainedClip {
+    pub id: Uuid,
+    pub content_type: ClipType,
+    /// 文本内容，二进制剪贴板为 static 目录下的文件路径
+    pub content: String,
+    pub size: i64,
+    /// 毫秒时间戳
+    pub created_at: i64,
+    pub visibility: Visibility,
+}
+
+// 获取保留策略
+#[get("/retention")]
+async fn get_retention(pool: web::Data<DbPool>, bearer_token: BearerToken) -> impl Responder {
+    match load_settings(&bearer_token.user_id, &pool).await {
+        Ok(settings) => ApiResponse::new(
+            "获取保留策略成功",
+            ResponseData::Json(json!(RetentionPolicy::from_settings(&settings))),
+        ),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取保留策略失败"),
+    }
+}
+
+// 修改保留策略（整体替换，未提供的限制恢复为不限制）
+#[put("/retention")]
+async fn put_retention(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    policy: web::Json<RetentionPolicy>,
+) -> impl Responder {
+    let policy = policy.into_inner();
+    info!("用户 {} 修改保留策略: {:?}", bearer_token.user_id, policy);
+
+    let updates = match parse_changes(policy.to_changes()) {
+        Ok(updates) => updates,
+        Err(reason) => return ApiResponse::error(ErrorCode::InvalidRequest, &reason),
+    };
+    if db::upsert_user_settings(&bearer_token.user_id, &updates, &pool)
+        .await
+        .is_err()
+    {
+        return ApiResponse::error(ErrorCode::InternalError, "保留策略保存失败");
+    }
+
+    match load_settings(&bearer_token.user_id, &pool).await {
+        Ok(settings) => {
+            let policy = RetentionPolicy::from_settings(&settings);
+            app_state.room_manager.do_send(PushEvent {
+                user_id: bearer_token.user_id.clone(),
+                event: "settings_changed".to_string(),
+                payload: Value::Object(settings),
+            });
+            ApiResponse::new("保留策略保存成功", ResponseData::Json(json!(policy)))
+        }
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "保留策略保存失败"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clip(size: i64, created_at: i64) -> RetainedClip {
+        RetainedClip {
+            id: Uuid::new_v4(),
+            content_type: ClipType::Text,
+            content: String::new(),
+            size,
+            created_at,
+            visibility: Visibility::default(),
+        }
+    }
+
+    fn prunable(policy: &RetentionPolicy, clips: &[RetainedClip], now: i64) -> Vec<Uuid> {
+        policy
+            .select_prunable(clips, now)
+            .into_iter()
+            .map(|clip| clip.id)
+            .collect()
+    }
+
+    #[test]
+    fn unlimited_policy_keeps_everything() {
+        let clips = vec![clip(10, 0), clip(10, 0)];
+        let policy = RetentionPolicy {
+            max_items: Some(0),
+            ..Default::default()
+        };
+        assert!(!policy.is_limited());
+        assert!(prunable(&policy, &clips, 10 * DAY_MILLIS).is_empty());
+    }
+
+    #[test]
+    fn prunes_items_beyond_each_limit() {
+        let now = 10 * DAY_MILLIS;
+        let clips = vec![
+            clip(40, now),
+            clip(40, now - DAY_MILLIS),
+            clip(40, now - 3 * DAY_MILLIS),
+            clip(40, now - 4 * DAY_MILLIS),
+        ];
+        let ids: Vec<Uuid> = clips.iter().map(|clip| clip.id).collect();
+
+        let by_items = RetentionPolicy {
+            max_items: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(prunable(&by_items, &clips, now), ids[3..]);
+
+        let by_age = RetentionPolicy {
+            max_age_days: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(prunable(&by_age, &clips, now), ids[2..]);
+
+        let by_bytes = RetentionPolicy {
+            max_bytes: Some(100),
+            ..Default::default()
+        };
+        assert_eq!(prunable(&by_bytes, &clips, now), ids[2..]);
+    }
+}
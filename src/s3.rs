@@ -0,0 +1,258 @@
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use reqwest::{Method, Response, StatusCode};
+use sha2::{Digest, Sha256};
+
+use crate::config::S3Config;
+
+// HMAC-SHA256
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+// AWS Signature Version 4 的签名密钥
+fn signing_key(secret_access_key: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let key = hmac_sha256(
+        format!("AWS4{}", secret_access_key).as_bytes(),
+        date.as_bytes(),
+    );
+    let key = hmac_sha256(&key, region.as_bytes());
+    let key = hmac_sha256(&key, service.as_bytes());
+    hmac_sha256(&key, b"aws4_request")
+}
+
+// 按 Signature Version 4 的规则编码，只保留 A-Z a-z 0-9 - _ . ~（对象名中的 / 可以保留）
+fn uri_encode(value: &str, keep_slash: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if keep_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+// 发送签名后的请求，key 为空时访问存储桶本身
+async fn send(
+    config: &S3Config,
+    method: Method,
+    key: &str,
+    query: &[(&str, &str)],
+    body: Vec<u8>,
+) -> Result<Response, String> {
+    let mut query: Vec<(String, String)> = query
+        .iter()
+        .map(|(name, value)| (uri_encode(name, false), uri_encode(value, false)))
+        .collect();
+    query.sort();
+    let query = query
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join("&");
+    let mut url = format!(
+        "{}/{}/{}",
+        config.endpoint.trim_end_matches('/'),
+        config.bucket,
+        uri_encode(key, true)
+    );
+    if !query.is_empty() {
+        url = format!("{}?{}", url, query);
+    }
+    let url = reqwest::Url::parse(&url).map_err(|e| format!("invalid S3 url {}: {}", url, e))?;
+    let host = match (url.host_str(), url.port()) {
+        (Some(host), Some(port)) => format!("{}:{}", host, port),
+        (Some(host), None) => host.to_string(),
+        (None, _) => return Err(format!("S3 url {} has no host", url)),
+    };
+
+    let now = Utc::now();
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+    let payload_hash = hex::encode(Sha256::digest(&body));
+    let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+    let canonical_request = format!(
+        "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+        method,
+        url.path(),
+        query,
+        host,
+        payload_hash,
+        amz_date,
+        signed_headers,
+        payload_hash
+    );
+    let scope = format!("{}/{}/s3/aws4_request", date, config.region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+    let signing_key = signing_key(&config.secret_access_key, &date, &config.region, "s3");
+    let signature = hex::encode(hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+    let authorization = format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        config.access_key_id, scope, signed_headers, signature
+    );
+
+    reqwest::Client::new()
+        .request(method, url)
+        .header("x-amz-date", amz_date)
+        .header("x-amz-content-sha256", payload_hash)
+        .header(reqwest::header::AUTHORIZATION, authorization)
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// 非 2xx 响应转换为错误信息
+async fn check(response: Response) -> Result<Response, String> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    Err(format!("S3 responded {}: {}", status, body))
+}
+
+// 以 PUT Object 上传对象
+pub async fn put_object(config: &S3Config, key: &str, data: Vec<u8>) -> Result<(), String> {
+    check(send(config, Method::PUT, key, &[], data).await?).await?;
+    Ok(())
+}
+
+// 下载对象，不存在时返回 None
+pub async fn get_object(config: &S3Config, key: &str) -> Result<Option<Vec<u8>>, String> {
+    let response = send(config, Method::GET, key, &[], Vec::new()).await?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let data = check(response)
+        .await?
+        .bytes()
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(Some(data.to_vec()))
+}
+
+// 读取对象的字节数和 ETag，不存在时返回 None
+pub async fn head_object(config: &S3Config, key: &str) -> Result<Option<(u64, String)>, String> {
+    let response = send(config, Method::HEAD, key, &[], Vec::new()).await?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let response = check(response).await?;
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("")
+    };
+    let size = header(reqwest::header::CONTENT_LENGTH).parse().unwrap_or(0);
+    let etag = header(reqwest::header::ETAG).trim_matches('"').to_string();
+    Ok(Some((size, etag)))
+}
+
+// 删除对象，对象不存在时 S3 同样返回成功
+pub async fn delete_object(config: &S3Config, key: &str) -> Result<(), String> {
+    check(send(config, Method::DELETE, key, &[], Vec::new()).await?).await?;
+    Ok(())
+}
+
+// 以 ListObjectsV2 列出前缀下的全部对象，返回 (对象名, 字节数)
+pub async fn list_objects(config: &S3Config, prefix: &str) -> Result<Vec<(String, u64)>, String> {
+    let mut objects = Vec::new();
+    let mut token: Option<String> = None;
+    loop {
+        let mut query = vec![("list-type", "2"), ("prefix", prefix)];
+        if let Some(token) = &token {
+            query.push(("continuation-token", token));
+        }
+        let response = check(send(config, Method::GET, "", &query, Vec::new()).await?).await?;
+        let body = response.text().await.map_err(|e| e.to_string())?;
+        for contents in xml_elements(&body, "Contents") {
+            let key = xml_elements(contents, "Key").next().map(xml_unescape);
+            let size = xml_elements(contents, "Size")
+                .next()
+                .and_then(|size| size.parse().ok());
+            if let (Some(key), Some(size)) = (key, size) {
+                objects.push((key, size));
+            }
+        }
+        let truncated = xml_elements(&body, "IsTruncated").next() == Some("true");
+        token = xml_elements(&body, "NextContinuationToken")
+            .next()
+            .map(xml_unescape);
+        if !truncated || token.is_none() {
+            return Ok(objects);
+        }
+    }
+}
+
+// 依次取出 XML 中指定元素的内容，ListObjectsV2 的响应结构简单，不需要完整的 XML 解析
+fn xml_elements<'a>(xml: &'a str, tag: &str) -> impl Iterator<Item = &'a str> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let mut rest = xml;
+    std::iter::from_fn(move || {
+        let start = rest.find(&open)? + open.len();
+        let end = start + rest[start..].find(&close)?;
+        let element = &rest[start..end];
+        rest = &rest[end + close.len()..];
+        Some(element)
+    })
+}
+
+fn xml_unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signing_key_matches_aws_example() {
+        // AWS 文档中 Signature Version 4 派生签名密钥的示例
+        let key = signing_key(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "20120215",
+            "us-east-1",
+            "iam",
+        );
+        assert_eq!(
+            hex::encode(key),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+    }
+
+    #[test]
+    fn list_objects_response_is_parsed() {
+        let xml = "<ListBucketResult><IsTruncated>true</IsTruncated>\
+            <Contents><Key>clips/u1/a.png</Key><Size>12</Size></Contents>\
+            <Contents><Key>heads/a&amp;b</Key><Size>3</Size></Contents>\
+            <NextContinuationToken>t1</NextContinuationToken></ListBucketResult>";
+        let keys: Vec<&str> = xml_elements(xml, "Contents")
+            .filter_map(|contents| xml_elements(contents, "Key").next())
+            .collect();
+        assert_eq!(keys, ["clips/u1/a.png", "heads/a&amp;b"]);
+        assert_eq!(xml_unescape(keys[1]), "heads/a&b");
+        assert_eq!(xml_elements(xml, "IsTruncated").next(), Some("true"));
+        assert_eq!(uri_encode("clips/u 1", true), "clips/u%201");
+        assert_eq!(uri_encode("a/b", false), "a%2Fb");
+    }
+}
@@ -0,0 +1,190 @@
+pub mod models;
+
+use actix_web::{HttpRequest, Responder, delete, get, post, web};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    content_policy::ContentPolicy,
+    device_group_api::{DeviceGroup, DeviceId, Visibility},
+    idempotency::{self, Idempotency, IdempotencyKey},
+    models::ClipType,
+    spatial_api::models::AppState,
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiResponse, ErrorCode, ResponseData},
+    },
+    user_api::{auth::BearerToken, quota::check_quota},
+};
+
+pub fn scheduled_api() -> actix_web::Scope {
+    web::scope("/scheduled_clips")
+        .service(create_scheduled_clip)
+        .service(list_scheduled_clips)
+        .service(cancel_scheduled_clip)
+}
+
+/// 定时投递的剪贴板（到达 deliver_at 时推送到用户房间）
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScheduledClip {
+    pub id: String,
+    pub user_id: String,
+    pub content_type: ClipType,
+    pub content: String,
+    pub deliver_at: i64,
+    pub created_at: i64,
+    /// pending / delivered / cancelled
+    pub status: String,
+    /// 可见范围，投递时只推送给范围内的设备
+    #[serde(default)]
+    pub visibility: Visibility,
+}
+
+// 创建定时投递
+#[derive(Serialize, Deserialize)]
+pub struct CreateScheduledClip {
+    pub content_type: ClipType,
+    pub content: String,
+    /// 投递时间戳（秒）
+    pub deliver_at: i64,
+    #[serde(default)]
+    pub visibility: Visibility,
+}
+
+#[post("")]
+async fn create_scheduled_clip(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    idempotency_key: IdempotencyKey,
+    req: HttpRequest,
+    create: web::Json<CreateScheduledClip>,
+) -> impl Responder {
+    let body = serde_json::to_vec(&*create).unwrap_or_default();
+    let fingerprint = idempotency::fingerprint(&req, &body);
+    match idempotency_key
+        .begin(&bearer_token.user_id, &fingerprint, &pool)
+        .await
+    {
+        Ok(Idempotency::Proceed) => {}
+        Ok(Idempotency::Replay(response)) | Err(response) => return response,
+    }
+
+    let response = schedule_clip(
+        &pool,
+        &app_state,
+        &bearer_token.user_id,
+        create.into_inner(),
+    )
+    .await;
+    idempotency_key
+        .finish(&bearer_token.user_id, response, &pool)
+        .await
+}
+
+async fn schedule_clip(
+    pool: &DbPool,
+    app_state: &AppState,
+    user_id: &str,
+    create: CreateScheduledClip,
+) -> ApiResponse {
+    let groups = match db::get_device_groups(user_id, pool).await {
+        Ok(groups) => groups,
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "定时投递创建失败"),
+    };
+    let clip = match new_scheduled_clip(user_id, create, &app_state.content_policy, &groups) {
+        Ok(clip) => clip,
+        Err(response) => return response,
+    };
+    if let Err(response) = check_quota(user_id, clip.content.len() as i64, pool).await {
+        return response;
+    }
+    info!(
+        "用户 {} 创建定时投递，投递时间 {}",
+        clip.user_id, clip.deliver_at
+    );
+
+    match db::insert_scheduled_clip(&clip, pool).await {
+        Ok(_) => ApiResponse::new("定时投递创建成功", ResponseData::Json(json!(clip))),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "定时投递创建失败"),
+    }
+}
+
+/// 校验并构造定时投递（不写入数据库），校验失败时返回错误响应
+pub fn new_scheduled_clip(
+    user_id: &str,
+    create: CreateScheduledClip,
+    content_policy: &ContentPolicy,
+    groups: &[DeviceGroup],
+) -> Result<ScheduledClip, ApiResponse> {
+    let now = chrono::Utc::now().timestamp();
+    if create.deliver_at <= now {
+        return Err(ApiResponse::error(
+            ErrorCode::InvalidRequest,
+            "投递时间必须晚于当前时间",
+        ));
+    }
+    let checked = content_policy.check_clip(create.content_type.as_str(), &create.content);
+    if let Err(violation) = checked {
+        return Err(ApiResponse::error_with_data(
+            ErrorCode::PolicyViolation,
+            "内容不符合服务器策略",
+            ResponseData::Json(json!(violation)),
+        ));
+    }
+    create.visibility.validate(groups)?;
+    let content = match create.content_type {
+        ClipType::Html => content_policy.sanitize_html(&create.content),
+        _ => create.content,
+    };
+
+    Ok(ScheduledClip {
+        id: uuid::Uuid::new_v4().to_string(),
+        user_id: user_id.to_string(),
+        content_type: create.content_type,
+        content,
+        deliver_at: create.deliver_at,
+        created_at: now,
+        status: "pending".to_string(),
+        visibility: create.visibility,
+    })
+}
+
+// 获取待投递列表（只返回请求设备可见的项目）
+#[get("")]
+async fn list_scheduled_clips(
+    pool: web::Data<DbPool>,
+    bearer_token: BearerToken,
+    device_id: DeviceId,
+) -> impl Responder {
+    let user_id = &bearer_token.user_id;
+    let (clips, groups) = match tokio::try_join!(
+        db::get_pending_scheduled_clips(user_id, &pool),
+        db::get_device_groups(user_id, &pool)
+    ) {
+        Ok(result) => result,
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "获取定时投递失败"),
+    };
+    let clips: Vec<ScheduledClip> = clips
+        .into_iter()
+        .filter(|clip| clip.visibility.allows(device_id.0.as_deref(), &groups))
+        .collect();
+    ApiResponse::new("获取定时投递成功", ResponseData::Json(json!(clips)))
+}
+
+// 取消定时投递
+#[delete("/{id}")]
+async fn cancel_scheduled_clip(
+    pool: web::Data<DbPool>,
+    bearer_token: BearerToken,
+    id: web::Path<String>,
+) -> impl Responder {
+    match db::update_scheduled_clip_status(&id, &bearer_token.user_id, "cancelled", pool.get_ref())
+        .await
+    {
+        Ok(true) => ApiResponse::new("定时投递已取消", ResponseData::Null),
+        Ok(false) => ApiResponse::error(ErrorCode::NotFound, "定时投递不存在或已投递"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "取消定时投递失败"),
+    }
+}
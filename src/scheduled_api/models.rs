@@ -0,0 +1,97 @@
+use actix::prelude::*;
+use log::{info, warn};
+use serde_json::json;
+use std::time::Duration;
+
+use crate::device_group_api::deliver_visible;
+use crate::scheduled_api::ScheduledClip;
+use crate::spatial_api::models::RoomManager;
+use crate::sqlx_utils::{DbPool, db};
+
+/// 定时投递检查间隔
+const DELIVERY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// 定时投递调度器，周期性地把到期的剪贴板推送到用户房间
+pub struct DeliveryScheduler {
+    pool: DbPool,
+    room_manager: Addr<RoomManager>,
+}
+
+impl DeliveryScheduler {
+    pub fn new(pool: DbPool, room_manager: Addr<RoomManager>) -> Self {
+        Self { pool, room_manager }
+    }
+}
+
+// 投递所有已到期的剪贴板
+async fn deliver_due_clips(pool: DbPool, room_manager: Addr<RoomManager>) {
+    let now = chrono::Utc::now().timestamp();
+    match db::get_due_scheduled_clips(now, &pool).await {
+        Ok(clips) => deliver_clips(clips, &pool, &room_manager).await,
+        Err(e) => warn!("查询到期的定时投递失败: {}", e),
+    }
+}
+
+// 用户重新连接时补发已到期但尚未送达的剪贴板
+pub async fn deliver_pending_for_user(
+    user_id: String,
+    pool: DbPool,
+    room_manager: Addr<RoomManager>,
+) {
+    let now = chrono::Utc::now().timestamp();
+    match db::get_user_due_scheduled_clips(&user_id, now, &pool).await {
+        Ok(clips) => deliver_clips(clips, &pool, &room_manager).await,
+        Err(e) => warn!("查询用户 {} 到期的定时投递失败: {}", user_id, e),
+    }
+}
+
+// 逐个投递：先抢占状态避免重复投递，没有会话收到时恢复为 pending，等待用户重新连接
+async fn deliver_clips(
+    clips: Vec<ScheduledClip>,
+    pool: &DbPool,
+    room_manager: &Addr<RoomManager>,
+) {
+    for mut clip in clips {
+        match db::update_scheduled_clip_status(&clip.id, &clip.user_id, "delivered", pool).await {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(e) => {
+                warn!("更新定时投递 {} 状态失败: {}", clip.id, e);
+                continue;
+            }
+        }
+        clip.status = "delivered".to_string();
+        let delivered = deliver_visible(
+            &clip.user_id,
+            &clip.visibility,
+            "scheduled_clip",
+            json!(clip),
+            pool,
+            room_manager,
+        )
+        .await
+        .unwrap_or_else(|e| {
+            warn!("加载用户 {} 的设备分组失败: {}", clip.user_id, e);
+            0
+        });
+        if delivered > 0 {
+            info!("投递定时剪贴板 {} 给用户 {}", clip.id, clip.user_id);
+        } else if let Err(e) = db::release_scheduled_clip(&clip.id, pool).await {
+            warn!("恢复定时投递 {} 状态失败: {}", clip.id, e);
+        }
+    }
+}
+
+impl Actor for DeliveryScheduler {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        info!("DeliveryScheduler started");
+
+        ctx.run_interval(DELIVERY_INTERVAL, |act, ctx| {
+            deliver_due_clips(act.pool.clone(), act.room_manager.clone())
+                .into_actor(act)
+                .wait(ctx);
+        });
+    }
+}
@@ -0,0 +1,119 @@
+use actix_web::{Responder, get, web};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    spatial_api::{
+        binary,
+        models::{AppState, PROTOCOL_VERSION, SERVER_CAPABILITIES},
+    },
+    sqlx_utils::models::{ApiResponse, ErrorCode, ResponseData},
+    user_api::{email_verification_required, oauth, registration_open},
+};
+
+// 服务器信息（无需登录），客户端在登录前据此调整界面
+#[get("/server_info")]
+pub async fn server_info(app_state: web::Data<AppState>) -> impl Responder {
+    let policy = &app_state.content_policy;
+    let grpc_enabled = cfg!(feature = "grpc") && app_state.config.grpc.enabled;
+    // 单个 json! 字面量过长会超出宏的递归深度，features 单独构造
+    let features = json!({
+        "registration_open": registration_open(),
+        "e2e_encryption": true,
+        "push": true,
+        "scheduled_clips": true,
+        "contacts": true,
+        "batch": true,
+        "long_poll": true,
+        "event_stream": true,
+        "device_groups": true,
+        "visibility_scopes": true,
+        "organizations": true,
+        "clips": true,
+        "refresh_tokens": true,
+        "devices": true,
+        "binary_clips": true,
+        "clip_search": true,
+        "clip_changes": true,
+        "clip_export": true,
+        "rate_limits": true,
+        "file_serving": true,
+        "password_reset": true,
+        "chunked_uploads": true,
+        "openapi": true,
+        "presence": true,
+        "sync_rules": true,
+        "clip_expiry": true,
+        "sensitive_detection": policy.sensitive.enabled,
+        "html_sanitization": policy.html.enabled,
+        "app_rules": true,
+        "webhooks": cfg!(feature = "webhooks"),
+        "lan_discovery": app_state.discovery.is_active(),
+        "two_factor": true,
+        "audit_log": true,
+        "login_lockout": true,
+        "signed_file_urls": true,
+        "clip_stats": true,
+        "sync_pause": true,
+        "trash_retention_days": app_state.config.trash.retention_days,
+        "oauth_providers": oauth::configured_providers(&app_state.config.auth.oauth),
+        "email_verification_required": email_verification_required(),
+    });
+    let info = json!({
+        "name": env!("CARGO_PKG_NAME"),
+        "version": env!("CARGO_PKG_VERSION"),
+        "protocol_versions": [PROTOCOL_VERSION],
+        "capabilities": SERVER_CAPABILITIES,
+        "features": features,
+        // gRPC 接口的端口，未启用时为 null
+        "grpc_port": grpc_enabled.then_some(app_state.config.grpc.port),
+        "limits": {
+            "allowed_types": policy.allowed_types,
+            "max_clip_sizes": policy.max_sizes,
+            "max_frame_size": binary::MAX_FRAME_SIZE,
+            "compression_threshold": app_state.config.spatial.compression_threshold,
+            "max_sessions_per_user": app_state.config.spatial.max_sessions_per_user,
+            "max_upload_size": app_state.config.upload.max_clip_size,
+            "max_chunk_size": app_state.config.upload.max_chunk_size,
+        },
+    });
+    ApiResponse::new("获取服务器信息成功", ResponseData::Json(info))
+}
+
+// 错误码目录（无需登录），客户端据此把错误码映射为本地化文案，status 为对应的 HTTP 状态码
+#[get("/error_codes")]
+pub async fn error_codes() -> impl Responder {
+    let catalog: Vec<_> = ErrorCode::ALL
+        .iter()
+        .map(|code| {
+            json!({
+                "code": code,
+                "description": code.description(),
+                "status": code.status().as_u16(),
+            })
+        })
+        .collect();
+    ApiResponse::new("获取错误码成功", ResponseData::Json(json!(catalog)))
+}
+
+// 时间同步参数
+#[derive(Deserialize)]
+pub struct TimeQuery {
+    /// 客户端发送请求时的本地时间（毫秒），原样返回用于估算往返延迟
+    pub client_time: Option<i64>,
+}
+
+// 服务器时间（无需登录），客户端据此校正时钟偏差（同 NTP）：
+// 设客户端收到响应的时间为 t4，则
+// offset = ((received_at - client_time) + (server_time - t4)) / 2
+// rtt = (t4 - client_time) - (server_time - received_at)
+#[get("/time")]
+pub async fn server_time(query: web::Query<TimeQuery>) -> impl Responder {
+    let received_at = chrono::Utc::now().timestamp_millis();
+    let time = json!({
+        "client_time": query.client_time,
+        "received_at": received_at,
+        "server_time": chrono::Utc::now().timestamp_millis(),
+    });
+    ApiResponse::new("获取服务器时间成功", ResponseData::Json(time))
+}
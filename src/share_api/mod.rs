@@ -0,0 +1,228 @@
+use actix_web::{Either, Error, HttpRequest, HttpResponse, Responder, delete, get, post, web};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::path::Path;
+use uuid::Uuid;
+
+use crate::{
+    clip_api::visible_clip,
+    device_group_api::DeviceId,
+    file_api::serve_file,
+    mailer::public_base_url,
+    models::ClipType,
+    spatial_api::{binary, models::AppState},
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiResponse, ErrorCode, ResponseData},
+    },
+    user_api::auth::{
+        BearerToken, generate_refresh_token, hash_refresh_token, missing_secret, required_secret,
+    },
+};
+
+pub fn share_api() -> actix_web::Scope {
+    web::scope("/shares")
+        .service(list_shares)
+        .service(revoke_share)
+        .service(open_share)
+}
+
+/// 分享链接签名密钥的派生上下文
+const SHARE_SIGNING_CONTEXT: &str = "ClipFocusServer 2026-10 share link signing";
+
+/// 分享链接默认 / 最短 / 最长有效期（秒）
+const DEFAULT_SHARE_TTL_SECS: i64 = 24 * 3600;
+const MIN_SHARE_TTL_SECS: i64 = 60;
+const MAX_SHARE_TTL_SECS: i64 = 30 * 24 * 3600;
+
+/// 分享的文件可能随时被撤销，不允许缓存
+const SHARE_CACHE_CONTROL: &str = "no-store";
+
+/// 剪贴板分享链接（不包含令牌，令牌只在创建时返回一次）
+#[derive(Debug, Serialize)]
+pub struct Share {
+    pub id: String,
+    pub clip_id: String,
+    pub created_at: i64,
+    pub expires_at: i64,
+}
+
+// 由 SHARE_SIGNING_KEY 派生链接的签名密钥，未设置时返回 None（不能创建或打开分享链接）
+fn signing_key() -> Option<[u8; 32]> {
+    required_secret("SHARE_SIGNING_KEY")
+        .map(|secret| blake3::derive_key(SHARE_SIGNING_CONTEXT, secret.as_bytes()))
+}
+
+// 对令牌和过期时间签名，链接被篡改时无需查询数据库即可拒绝
+fn sign(key: &[u8; 32], token: &str, expires_at: i64) -> blake3::Hash {
+    blake3::keyed_hash(key, format!("{}.{}", token, expires_at).as_bytes())
+}
+
+fn share_url(key: &[u8; 32], token: &str, expires_at: i64) -> String {
+    format!(
+        "{}/api/v1/shares/public/{}?expires={}&sig={}",
+        public_base_url(),
+        token,
+        expires_at,
+        sign(key, token, expires_at).to_hex()
+    )
+}
+
+// 创建分享链接
+#[derive(Deserialize)]
+pub struct CreateShare {
+    /// 有效期（秒），默认 1 天，最长 30 天
+    pub expires_in_secs: Option<i64>,
+}
+
+// 为剪贴板创建公开的分享链接，任何人无需登录即可在有效期内查看
+#[post("/{id}/share")]
+pub(crate) async fn create_share(
+    pool: web::Data<DbPool>,
+    bearer_token: BearerToken,
+    device_id: DeviceId,
+    id: web::Path<Uuid>,
+    create: Option<web::Json<CreateShare>>,
+) -> impl Responder {
+    let Some(key) = signing_key() else {
+        return missing_secret("SHARE_SIGNING_KEY");
+    };
+    let user_id = &bearer_token.user_id;
+    let clip = match visible_clip(user_id, &id, &device_id, &pool).await {
+        Ok(clip) => clip,
+        Err(response) => return response,
+    };
+    // 服务器只保存加密剪贴板的密文，分享后对方也无法查看
+    if clip.encrypted {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "加密的剪贴板无法分享");
+    }
+    let ttl = create
+        .and_then(|create| create.expires_in_secs)
+        .unwrap_or(DEFAULT_SHARE_TTL_SECS);
+    if !(MIN_SHARE_TTL_SECS..=MAX_SHARE_TTL_SECS).contains(&ttl) {
+        return ApiResponse::error(
+            ErrorCode::InvalidRequest,
+            &format!(
+                "有效期需要在 {} 到 {} 秒之间",
+                MIN_SHARE_TTL_SECS, MAX_SHARE_TTL_SECS
+            ),
+        );
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let token = generate_refresh_token();
+    let share = Share {
+        id: Uuid::new_v4().to_string(),
+        clip_id: clip.id.to_string(),
+        created_at: now,
+        expires_at: now + ttl,
+    };
+    match db::insert_share(user_id, &share, &hash_refresh_token(&token), &pool).await {
+        Ok(_) => {
+            info!("用户 {} 分享剪贴板 {}（{} 秒）", user_id, share.clip_id, ttl);
+            let url = share_url(&key, &token, share.expires_at);
+            ApiResponse::new(
+                "分享链接创建成功",
+                ResponseData::Json(json!({ "share": share, "url": url })),
+            )
+        }
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "分享链接创建失败"),
+    }
+}
+
+// 获取有效的分享链接
+#[get("")]
+async fn list_shares(pool: web::Data<DbPool>, bearer_token: BearerToken) -> impl Responder {
+    let now = chrono::Utc::now().timestamp();
+    match db::get_active_shares(&bearer_token.user_id, now, &pool).await {
+        Ok(shares) => ApiResponse::new("获取分享链接成功", ResponseData::Json(json!(shares))),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取分享链接失败"),
+    }
+}
+
+// 撤销分享链接，撤销后链接立即失效
+#[delete("/{id}")]
+async fn revoke_share(
+    pool: web::Data<DbPool>,
+    bearer_token: BearerToken,
+    id: web::Path<String>,
+) -> impl Responder {
+    match db::revoke_share(&bearer_token.user_id, &id, &pool).await {
+        Ok(true) => {
+            info!("用户 {} 撤销分享链接 {}", bearer_token.user_id, id);
+            ApiResponse::new("分享链接已撤销", ResponseData::Null)
+        }
+        Ok(false) => ApiResponse::error(ErrorCode::NotFound, "分享链接不存在"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "撤销分享链接失败"),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ShareSignature {
+    pub expires: i64,
+    pub sig: String,
+}
+
+fn not_found() -> Error {
+    ApiResponse::into_error(ErrorCode::NotFound, "分享链接无效或已过期")
+}
+
+// 打开分享链接（无需登录）：文本类剪贴板返回 JSON，图片 / RTF 返回文件
+//
+// 签名无效、已过期、已撤销和剪贴板已删除时都返回 404，不区分原因；
+// HTML 剪贴板在返回前按内容策略再次清理（包括启用清理之前保存的剪贴板）
+#[get("/public/{token}")]
+async fn open_share(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    req: HttpRequest,
+    token: web::Path<String>,
+    signature: web::Query<ShareSignature>,
+) -> Result<Either<ApiResponse, HttpResponse>, Error> {
+    let now = chrono::Utc::now().timestamp();
+    // 未设置签名密钥时所有链接都无效
+    let signed = signing_key().is_some_and(|key| {
+        blake3::Hash::from_hex(&signature.sig)
+            .is_ok_and(|sig| sig == sign(&key, &token, signature.expires))
+    });
+    if !signed || signature.expires <= now {
+        return Err(not_found());
+    }
+
+    let internal_error = |_| ApiResponse::into_error(ErrorCode::InternalError, "获取分享内容失败");
+    let (user_id, share) = db::get_share_by_token(&hash_refresh_token(&token), now, &pool)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(not_found)?;
+    let clip_id = Uuid::parse_str(&share.clip_id).map_err(|_| not_found())?;
+    let clip = db::get_clip(&user_id, &clip_id, &pool)
+        .await
+        .map_err(internal_error)?
+        .ok_or_else(not_found)?;
+
+    if binary::BINARY_CLIP_TYPES.contains(&clip.content_type) {
+        let extension = Path::new(&clip.content)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or("");
+        let mime_type = binary::mime_for(extension);
+        let response =
+            serve_file(&req, &clip.content, Some(mime_type), SHARE_CACHE_CONTROL).await?;
+        return Ok(Either::Right(response));
+    }
+    let content = match clip.content_type {
+        ClipType::Html if !clip.encrypted => app_state.content_policy.sanitize_html(&clip.content),
+        _ => clip.content,
+    };
+    Ok(Either::Left(ApiResponse::new(
+        "获取分享内容成功",
+        ResponseData::Json(json!({
+            "id": clip.id,
+            "content_type": clip.content_type,
+            "content": content,
+            "created_at": clip.created_at,
+            "expires_at": share.expires_at,
+        })),
+    )))
+}
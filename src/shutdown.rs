@@ -0,0 +1,93 @@
+use actix::Addr;
+use actix_web::dev::ServerHandle;
+use log::{info, warn};
+use std::time::Duration;
+use tokio::time::{Instant, sleep};
+
+use crate::config::ShutdownConfig;
+use crate::device_api::last_seen::flush_last_seen;
+use crate::discovery::LanDiscovery;
+use crate::spatial_api::models::{GetStats, RoomManager, Shutdown};
+use crate::sqlx_utils::DbPool;
+
+/// 等待会话断开时查询剩余会话数的间隔
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 收到 SIGTERM / SIGINT 后按顺序停机：
+///
+/// 1. 停止接受新连接，撤销局域网（mDNS）广播
+/// 2. 通知所有 WebSocket 会话（`server.shutdown` 关闭帧，附带重连等待时间），结束长轮询请求
+/// 3. 等待会话断开，最多等待 `drain_timeout_secs`
+/// 4. 写入尚未保存的设备在线时间
+/// 5. 关闭数据库连接池，等待进行中的写入完成（SQLite 同时写回 WAL）
+/// 6. 停止 HTTP 服务
+pub async fn shutdown_on_signal(
+    server: ServerHandle,
+    room_manager: Addr<RoomManager>,
+    discovery: LanDiscovery,
+    pool: DbPool,
+    config: ShutdownConfig,
+) {
+    wait_for_signal().await;
+    info!("Shutdown signal received, draining sessions");
+    server.pause().await;
+    discovery.shutdown();
+
+    match room_manager
+        .send(Shutdown {
+            reconnect_after_secs: config.reconnect_after_secs,
+        })
+        .await
+    {
+        Ok(notified) => info!("Sent server.shutdown to {} sessions", notified),
+        Err(e) => warn!("Failed to notify sessions of shutdown: {}", e),
+    }
+    drain_sessions(&room_manager, config.drain_timeout()).await;
+    flush_last_seen(&pool, &room_manager).await;
+
+    pool.close().await;
+    info!("Database pool closed");
+
+    server.stop(true).await;
+}
+
+// 等待 SIGTERM（仅 Unix）或 Ctrl+C
+async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = terminate.recv() => {}
+                    _ = tokio::signal::ctrl_c() => {}
+                }
+                return;
+            }
+            Err(e) => warn!("Failed to listen for SIGTERM: {}", e),
+        }
+    }
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        warn!("Failed to listen for Ctrl+C: {}", e);
+    }
+}
+
+// 等待所有会话离开房间，超时后不再等待
+async fn drain_sessions(room_manager: &Addr<RoomManager>, timeout: Duration) {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let remaining = match room_manager.send(GetStats).await {
+            Ok(stats) => stats.sessions,
+            Err(_) => return,
+        };
+        if remaining == 0 {
+            info!("All sessions drained");
+            return;
+        }
+        if Instant::now() >= deadline {
+            warn!("{} sessions still open after {:?}, shutting down anyway", remaining, timeout);
+            return;
+        }
+        sleep(DRAIN_POLL_INTERVAL).await;
+    }
+}
@@ -0,0 +1,294 @@
+use flate2::Compression;
+use flate2::write::DeflateEncoder;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::Path;
+use tokio::io::AsyncReadExt;
+use uuid::Uuid;
+
+use crate::blob_store::blob_store;
+use crate::content_policy::PolicyViolation;
+use crate::device_group_api::Visibility;
+use crate::models::ClipType;
+
+/// WebSocket 单帧最大字节数（二进制剪贴板需要整帧发送，不支持分片）
+pub const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+/// 可以通过二进制帧推送的剪贴板类型
+pub const BINARY_CLIP_TYPES: &[ClipType] = &[ClipType::Image, ClipType::Rtf];
+
+/// 客户端推送的二进制帧头
+///
+/// 二进制帧格式：4 字节大端序的头部长度 + 头部 JSON + 剪贴板原始字节
+#[derive(Debug, Deserialize)]
+pub struct BinaryClipHeader {
+    /// 消息 ID，回复 clip.ack 或 error 时作为 ref_id
+    #[serde(default)]
+    pub id: Option<String>,
+    pub clip_id: Uuid,
+    pub content_type: ClipType,
+    pub mime_type: String,
+    #[serde(default)]
+    pub preview: Option<String>,
+    #[serde(default)]
+    pub source_app: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub visibility: Visibility,
+    /// 端到端加密时，帧中的原始字节为密文
+    #[serde(default)]
+    pub key_id: Option<String>,
+    #[serde(default)]
+    pub nonce: Option<String>,
+    /// 过期时间，到期后从所有设备删除
+    #[serde(default)]
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// 转发给其他会话的二进制帧头，clip.content 为文件的相对路径
+#[derive(Debug, Serialize)]
+pub struct BinaryClipPush<'a> {
+    pub r#type: &'static str,
+    pub mime_type: &'a str,
+    pub clip: &'a crate::models::ClipItem,
+}
+
+// 拆分二进制帧，返回头部和原始字节
+pub fn decode_frame(frame: &[u8]) -> Result<(BinaryClipHeader, &[u8]), String> {
+    let Some((len, rest)) = frame.split_first_chunk::<4>() else {
+        return Err("binary frame is too short".to_string());
+    };
+    let len = u32::from_be_bytes(*len) as usize;
+    if len > rest.len() {
+        return Err("header length exceeds frame size".to_string());
+    }
+    let (header, data) = rest.split_at(len);
+    let header = serde_json::from_slice(header).map_err(|e| format!("invalid header: {}", e))?;
+    Ok((header, data))
+}
+
+// 组装二进制帧
+pub fn encode_frame(header: &impl Serialize, data: &[u8]) -> Vec<u8> {
+    let header = serde_json::to_vec(header).unwrap_or_default();
+    let mut frame = Vec::with_capacity(4 + header.len() + data.len());
+    frame.extend_from_slice(&(header.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&header);
+    frame.extend_from_slice(data);
+    frame
+}
+
+// 把文本消息压缩为二进制帧，头部为 `{"type":"compressed","encoding":"deflate"}`，
+// 数据为 UTF-8 文本的 raw DEFLATE 字节；压缩后没有变小时返回 None，按原文本发送
+pub fn compress_text(text: &str) -> Option<Vec<u8>> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(text.as_bytes()).ok()?;
+    let data = encoder.finish().ok()?;
+    let header = serde_json::json!({ "type": "compressed", "encoding": "deflate" });
+    let frame = encode_frame(&header, &data);
+    (frame.len() < text.len()).then_some(frame)
+}
+
+// 根据 MIME 类型确定文件扩展名
+pub fn extension_for(mime_type: &str) -> &'static str {
+    match mime_type.split(';').next().unwrap_or("").trim() {
+        "image/png" => "png",
+        "image/jpeg" => "jpg",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "image/bmp" => "bmp",
+        "image/tiff" => "tiff",
+        "application/rtf" | "text/rtf" => "rtf",
+        _ => "bin",
+    }
+}
+
+// 根据文件扩展名确定 MIME 类型（extension_for 的反向映射）
+pub fn mime_for(extension: &str) -> &'static str {
+    match extension {
+        "png" => "image/png",
+        "jpg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        "tiff" => "image/tiff",
+        "rtf" => "application/rtf",
+        _ => "application/octet-stream",
+    }
+}
+
+// 根据文件头（magic bytes）识别文件类型，无法识别时返回 None
+pub fn sniff_mime(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if data.starts_with(b"\xFF\xD8\xFF") {
+        Some("image/jpeg")
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+        Some("image/webp")
+    } else if data.starts_with(b"BM") {
+        Some("image/bmp")
+    } else if data.starts_with(b"II*\0") || data.starts_with(b"MM\0*") {
+        Some("image/tiff")
+    } else if data.starts_with(b"{\\rtf") {
+        Some("application/rtf")
+    } else {
+        None
+    }
+}
+
+/// 识别文件类型需要读取的字节数
+pub const SNIFF_LEN: usize = 16;
+
+// 读取文件开头并识别文件类型
+pub async fn sniff_file(path: impl AsRef<Path>) -> std::io::Result<Option<&'static str>> {
+    let file = tokio::fs::File::open(path).await?;
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    file.take(SNIFF_LEN as u64).read_to_end(&mut head).await?;
+    Ok(sniff_mime(&head))
+}
+
+// 校验根据文件头识别出的类型（sniff_mime）与声明的 MIME 类型一致
+//
+// 能识别的格式必须与声明的类型相同；无法识别时，只有声明为能识别的格式（例如 image/png）才拒绝，
+// 其他格式（例如 image/heic）按声明的类型保存为 .bin 文件。加密的剪贴板内容为密文，不做校验
+pub fn check_mime(mime_type: &str, detected: Option<&str>) -> Result<(), PolicyViolation> {
+    let matches = match detected {
+        Some(detected) => detected == mime_for(extension_for(mime_type)),
+        None => extension_for(mime_type) == "bin",
+    };
+    if matches {
+        Ok(())
+    } else {
+        Err(PolicyViolation::MimeMismatch {
+            mime_type: mime_type.to_string(),
+            detected: detected.map(str::to_string),
+        })
+    }
+}
+
+// 保存剪贴板文件，返回对象名（即剪贴板的 content）
+pub async fn save_clip_file(
+    user_id: &str,
+    clip_id: &Uuid,
+    extension: &str,
+    data: &[u8],
+) -> std::io::Result<String> {
+    let key = format!("clips/{}/{}.{}", user_id, clip_id, extension);
+    blob_store().put(&key, data.to_vec()).await?;
+    Ok(key)
+}
+
+// 用户保存的全部剪贴板文件，返回 (对象名, 字节数)
+pub async fn user_clip_files(user_id: &str) -> std::io::Result<Vec<(String, u64)>> {
+    blob_store().list(&format!("clips/{}/", user_id)).await
+}
+
+// 查找指定剪贴板的文件（不知道所属用户时查找所有用户的文件），返回对象名
+pub async fn find_clip_files(clip_id: &str) -> std::io::Result<Vec<String>> {
+    let files = blob_store().list("clips/").await?;
+    Ok(files
+        .into_iter()
+        .map(|(key, _)| key)
+        .filter(|key| {
+            let stem = Path::new(key)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy());
+            stem.as_deref() == Some(clip_id)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::io::Read;
+
+    fn header() -> serde_json::Value {
+        json!({
+            "id": "m1",
+            "clip_id": "bbbbbbbb-1111-1111-1111-111111111111",
+            "content_type": "image",
+            "mime_type": "image/png",
+            "tags": ["a"],
+        })
+    }
+
+    #[test]
+    fn decode_frame_round_trip() {
+        let frame = encode_frame(&header(), b"\x89PNG data");
+        let (decoded, data) = decode_frame(&frame).unwrap();
+        assert_eq!(decoded.id.as_deref(), Some("m1"));
+        assert_eq!(decoded.content_type, ClipType::Image);
+        assert_eq!(decoded.mime_type, "image/png");
+        assert_eq!(decoded.tags, vec!["a".to_string()]);
+        assert_eq!(data, b"\x89PNG data");
+    }
+
+    #[test]
+    fn decode_frame_allows_empty_data() {
+        let frame = encode_frame(&header(), b"");
+        let (_, data) = decode_frame(&frame).unwrap();
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn decode_frame_rejects_short_frame() {
+        assert!(decode_frame(b"").is_err());
+        assert!(decode_frame(&[0, 0, 1]).is_err());
+    }
+
+    #[test]
+    fn decode_frame_rejects_oversized_header_length() {
+        let mut frame = encode_frame(&header(), b"data");
+        let len = frame.len() as u32;
+        frame[..4].copy_from_slice(&len.to_be_bytes());
+        assert_eq!(
+            decode_frame(&frame).unwrap_err(),
+            "header length exceeds frame size"
+        );
+    }
+
+    #[test]
+    fn compress_text_round_trip() {
+        let text = json!({ "type": "clip.push", "payload": "<p>html</p>".repeat(500) }).to_string();
+        let frame = compress_text(&text).unwrap();
+        assert!(frame.len() < text.len() / 10);
+        let len = u32::from_be_bytes(frame[..4].try_into().unwrap()) as usize;
+        let header: serde_json::Value = serde_json::from_slice(&frame[4..4 + len]).unwrap();
+        assert_eq!(header["encoding"], "deflate");
+        let mut decoded = String::new();
+        flate2::read::DeflateDecoder::new(&frame[4 + len..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, text);
+        // 压缩不能减小体积的短消息按原文本发送
+        assert!(compress_text("{}").is_none());
+    }
+
+    #[test]
+    fn check_mime_compares_magic_bytes() {
+        let check = |mime_type, data: &[u8]| check_mime(mime_type, sniff_mime(data));
+        assert_eq!(sniff_mime(b"\x89PNG\r\n\x1a\nrest"), Some("image/png"));
+        assert_eq!(sniff_mime(b"{\\rtf1\\ansi"), Some("application/rtf"));
+        assert!(check("image/png", b"\x89PNG\r\n\x1a\n").is_ok());
+        assert!(check("image/jpeg; q=1", b"\xFF\xD8\xFF\xE0").is_ok());
+        assert!(check("text/rtf", b"{\\rtf1").is_ok());
+        // 伪装成图片的其他文件
+        assert!(check("image/png", b"MZ\x90\0").is_err());
+        assert!(check("image/gif", b"\x89PNG\r\n\x1a\n").is_err());
+        // 无法识别的格式按声明的类型处理
+        assert!(check("image/heic", b"\0\0\0\x18ftypheic").is_ok());
+        assert!(check("image/heic", b"\x89PNG\r\n\x1a\n").is_err());
+    }
+
+    #[test]
+    fn decode_frame_rejects_invalid_header() {
+        let mut frame = 3u32.to_be_bytes().to_vec();
+        frame.extend_from_slice(b"{}xdata");
+        let err = decode_frame(&frame).unwrap_err();
+        assert!(err.starts_with("invalid header"), "{}", err);
+    }
+}
@@ -0,0 +1,317 @@
+use actix::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use crate::config::ClusterConfig;
+use crate::device_api::SyncRule;
+use crate::models::ClipType;
+use crate::spatial_api::models::RoomManager;
+
+/// 需要转发给其他节点的房间操作
+///
+/// 同一用户的会话可能连接到不同节点，每个节点只处理连接到自己的会话
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClusterMessage {
+    /// 服务端事件，devices 为 None 时推送给用户的全部设备
+    Push {
+        user_id: String,
+        devices: Option<HashSet<String>>,
+        event: String,
+        payload: serde_json::Value,
+    },
+    /// 客户端的协议消息，devices 已按发送方的隔离分组计算
+    Relay {
+        user_id: String,
+        sender_session_id: String,
+        devices: Option<HashSet<String>>,
+        to_session: Option<String>,
+        event: String,
+        payload: serde_json::Value,
+    },
+    /// 二进制剪贴板帧（base64）
+    RelayBinary {
+        user_id: String,
+        sender_session_id: String,
+        devices: Option<HashSet<String>>,
+        clip_id: String,
+        content_type: ClipType,
+        size: i64,
+        frame: String,
+    },
+    /// 推送给所有在线会话的服务端事件
+    Broadcast {
+        event: String,
+        payload: serde_json::Value,
+    },
+    SetDeviceRouting {
+        user_id: String,
+        isolated_groups: Vec<HashSet<String>>,
+    },
+    SetSyncRules {
+        user_id: String,
+        rules: HashMap<String, SyncRule>,
+    },
+    DisconnectDevice {
+        user_id: String,
+        device_id: String,
+    },
+    SetDevicePaused {
+        user_id: String,
+        device_id: String,
+        paused: bool,
+    },
+    DisconnectSessions {
+        user_id: String,
+        session_id: Option<String>,
+        reason: String,
+    },
+    DisconnectLoginSession {
+        user_id: String,
+        login_session_id: String,
+        reason: String,
+    },
+    PurgeUser {
+        user_id: String,
+    },
+    PurgeClip {
+        clip_id: String,
+    },
+}
+
+/// 其他节点转发的房间操作，只在本节点执行，不再转发
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct RemoteMessage(pub ClusterMessage);
+
+/// 把本节点的房间操作发布给其他节点，发布在后台任务中进行，不阻塞 RoomManager
+#[derive(Clone)]
+pub struct ClusterPublisher {
+    sender: mpsc::UnboundedSender<ClusterMessage>,
+}
+
+impl ClusterPublisher {
+    pub fn publish(&self, message: ClusterMessage) {
+        let _ = self.sender.send(message);
+    }
+}
+
+// 按配置连接 Redis 并启动发布和订阅任务，未配置 redis_url 时返回 None（房间只在本节点内）
+pub fn start(config: &ClusterConfig, room_manager: Addr<RoomManager>) -> Option<ClusterPublisher> {
+    let redis_url = config.redis_url.as_ref()?;
+    let (sender, outgoing) = mpsc::unbounded_channel();
+    if let Err(e) = transport::start(redis_url, config, outgoing, room_manager) {
+        warn!("cluster disabled, rooms are local to this node: {}", e);
+        return None;
+    }
+    Some(ClusterPublisher { sender })
+}
+
+#[cfg(feature = "redis")]
+mod transport {
+    use actix::Addr;
+    use futures::StreamExt;
+    use redis::AsyncCommands;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+    use tracing::{info, warn};
+
+    use serde::{Deserialize, Serialize};
+
+    use super::{ClusterMessage, RemoteMessage};
+    use crate::config::ClusterConfig;
+    use crate::spatial_api::models::RoomManager;
+
+    /// 连接断开后重新连接的间隔
+    const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+    /// 发布到频道的消息，附带来源节点，节点收到自己发布的消息时忽略
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Envelope {
+        node_id: String,
+        #[serde(flatten)]
+        message: ClusterMessage,
+    }
+
+    // 发布的频道：与用户相关的操作按 user_id 分频道，其他操作使用广播频道
+    fn channel(message: &ClusterMessage, prefix: &str) -> String {
+        let user_id = match message {
+            ClusterMessage::Push { user_id, .. }
+            | ClusterMessage::Relay { user_id, .. }
+            | ClusterMessage::RelayBinary { user_id, .. }
+            | ClusterMessage::SetDeviceRouting { user_id, .. }
+            | ClusterMessage::SetSyncRules { user_id, .. }
+            | ClusterMessage::DisconnectDevice { user_id, .. }
+            | ClusterMessage::SetDevicePaused { user_id, .. }
+            | ClusterMessage::DisconnectSessions { user_id, .. }
+            | ClusterMessage::DisconnectLoginSession { user_id, .. }
+            | ClusterMessage::PurgeUser { user_id } => user_id,
+            ClusterMessage::Broadcast { .. } | ClusterMessage::PurgeClip { .. } => {
+                return format!("{}broadcast", prefix);
+            }
+        };
+        format!("{}user:{}", prefix, user_id)
+    }
+
+    pub fn start(
+        redis_url: &str,
+        config: &ClusterConfig,
+        outgoing: mpsc::UnboundedReceiver<ClusterMessage>,
+        room_manager: Addr<RoomManager>,
+    ) -> Result<(), String> {
+        let client = redis::Client::open(redis_url).map_err(|e| e.to_string())?;
+        let node_id = config.node_id.clone();
+        info!(
+            "Cluster node {} relaying rooms through Redis channels {}*",
+            node_id, config.channel_prefix
+        );
+        actix::spawn(publish(
+            client.clone(),
+            config.channel_prefix.clone(),
+            node_id.clone(),
+            outgoing,
+        ));
+        actix::spawn(subscribe(
+            client,
+            config.channel_prefix.clone(),
+            node_id,
+            room_manager,
+        ));
+        Ok(())
+    }
+
+    // 依次发布本节点的房间操作，连接失败时丢弃消息并在下一条消息时重新连接
+    async fn publish(
+        client: redis::Client,
+        prefix: String,
+        node_id: String,
+        mut outgoing: mpsc::UnboundedReceiver<ClusterMessage>,
+    ) {
+        let mut connection = None;
+        while let Some(message) = outgoing.recv().await {
+            let channel = channel(&message, &prefix);
+            let payload = match serde_json::to_string(&Envelope {
+                node_id: node_id.clone(),
+                message,
+            }) {
+                Ok(payload) => payload,
+                Err(e) => {
+                    warn!("serialize cluster message failed: {}", e);
+                    continue;
+                }
+            };
+            if connection.is_none() {
+                match client.get_multiplexed_tokio_connection().await {
+                    Ok(conn) => connection = Some(conn),
+                    Err(e) => {
+                        warn!("connect to Redis failed, dropping {}: {}", channel, e);
+                        continue;
+                    }
+                }
+            }
+            if let Some(conn) = connection.as_mut()
+                && let Err(e) = conn.publish::<_, _, ()>(&channel, payload).await
+            {
+                warn!("publish to {} failed: {}", channel, e);
+                connection = None;
+            }
+        }
+    }
+
+    // 订阅所有用户频道和广播频道，把其他节点发布的操作交给本节点的 RoomManager，断开后自动重连
+    async fn subscribe(
+        client: redis::Client,
+        prefix: String,
+        node_id: String,
+        room_manager: Addr<RoomManager>,
+    ) {
+        loop {
+            let subscribed = async {
+                let mut pubsub = client.get_async_pubsub().await?;
+                pubsub.psubscribe(format!("{}user:*", prefix)).await?;
+                pubsub.subscribe(format!("{}broadcast", prefix)).await?;
+                Ok::<_, redis::RedisError>(pubsub)
+            };
+            match subscribed.await {
+                Ok(pubsub) => {
+                    info!("Subscribed to Redis channels {}*", prefix);
+                    let mut messages = pubsub.into_on_message();
+                    while let Some(message) = messages.next().await {
+                        let payload: Vec<u8> = match message.get_payload() {
+                            Ok(payload) => payload,
+                            Err(e) => {
+                                warn!("read cluster message failed: {}", e);
+                                continue;
+                            }
+                        };
+                        match serde_json::from_slice::<Envelope>(&payload) {
+                            Ok(envelope) if envelope.node_id == node_id => {}
+                            Ok(envelope) => room_manager.do_send(RemoteMessage(envelope.message)),
+                            Err(e) => warn!(
+                                "invalid cluster message on {}: {}",
+                                message.get_channel_name(),
+                                e
+                            ),
+                        }
+                    }
+                    warn!("Redis subscription closed, reconnecting");
+                }
+                Err(e) => warn!("subscribe to Redis failed: {}", e),
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn user_messages_use_per_user_channels() {
+            let push = ClusterMessage::Push {
+                user_id: "u1".to_string(),
+                devices: None,
+                event: "clip_created".to_string(),
+                payload: serde_json::json!({ "id": "c1" }),
+            };
+            assert_eq!(channel(&push, "clipfocus:"), "clipfocus:user:u1");
+            let purge = ClusterMessage::PurgeClip {
+                clip_id: "c1".to_string(),
+            };
+            assert_eq!(channel(&purge, "clipfocus:"), "clipfocus:broadcast");
+
+            let envelope = Envelope {
+                node_id: "n1".to_string(),
+                message: push,
+            };
+            let json = serde_json::to_value(&envelope).unwrap();
+            assert_eq!(json["type"], "push");
+            assert_eq!(json["node_id"], "n1");
+            let parsed: Envelope = serde_json::from_value(json).unwrap();
+            assert!(
+                matches!(parsed.message, ClusterMessage::Push { user_id, .. } if user_id == "u1")
+            );
+        }
+    }
+}
+
+#[cfg(not(feature = "redis"))]
+mod transport {
+    use actix::Addr;
+    use tokio::sync::mpsc;
+
+    use super::ClusterMessage;
+    use crate::config::ClusterConfig;
+    use crate::spatial_api::models::RoomManager;
+
+    pub fn start(
+        _redis_url: &str,
+        _config: &ClusterConfig,
+        _outgoing: mpsc::UnboundedReceiver<ClusterMessage>,
+        _room_manager: Addr<RoomManager>,
+    ) -> Result<(), String> {
+        Err("cluster.redis_url is set but the redis feature is disabled".to_string())
+    }
+}
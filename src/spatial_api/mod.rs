@@ -1,32 +1,413 @@
+pub mod binary;
+pub(crate) mod cluster;
 pub mod models;
-use actix::{Actor, StreamHandler};
-use actix_web::{App, Error, HttpRequest, HttpResponse, HttpServer, get, web};
+use actix::Addr;
+use actix_web::{
+    Error, HttpRequest, HttpResponse, Responder, get,
+    http::header,
+    web::{self, Bytes},
+};
 use actix_web_actors::ws;
+use futures::{StreamExt, stream};
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Duration;
+use tracing::{debug, info, warn};
 
-use crate::{spatial_api::models::{AppState, MyWs}, user_api::auth::BearerToken};
+use crate::{
+    device_api::sync_device_rules,
+    device_group_api::{DeviceId, sync_device_routing},
+    spatial_api::{
+        binary::MAX_FRAME_SIZE,
+        models::{
+            AppState, ClientInfo, GetPresence, GetSessions, MyWs, PollEvents, PollResult,
+            RoomManager,
+        },
+    },
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiResponse, ErrorCode, ResponseData},
+    },
+    user_api::auth::{BearerToken, WsToken},
+};
 
 pub fn ws_api() -> actix_web::Scope {
-    return web::scope("/spatial").service(index);
+    web::scope("/spatial")
+        .service(index)
+        .service(list_sessions)
+        .service(presence)
+        .service(poll_events)
+        .service(event_stream)
+}
+
+// 从查询参数（?device_id=&platform=&app_version=&device_name=）和 X-Client-* / X-Device-* 请求头
+// 读取客户端元数据
+fn client_info_from_request(req: &HttpRequest) -> ClientInfo {
+    let mut client = web::Query::<ClientInfo>::from_query(req.query_string())
+        .map(|query| query.into_inner())
+        .unwrap_or_default();
+    let header = |name: &str| {
+        req.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string())
+    };
+    client.device_id = client.device_id.or_else(|| header("X-Device-Id"));
+    client.platform = client.platform.or_else(|| header("X-Client-Platform"));
+    client.app_version = client.app_version.or_else(|| header("X-Client-Version"));
+    client.device_name = client.device_name.or_else(|| header("X-Device-Name"));
+    client
+}
+
+/// 拒绝建立会话的原因
+pub(crate) struct SessionRejected {
+    pub code: ErrorCode,
+    pub message: &'static str,
+}
+
+impl SessionRejected {
+    fn new(code: ErrorCode, message: &'static str) -> Self {
+        Self { code, message }
+    }
+}
+
+// 校验账号、登录会话和设备并加载设备分组路由规则，握手时和 auth 消息认证后共用
+pub(crate) async fn admit_session(
+    bearer_token: &BearerToken,
+    client: &ClientInfo,
+    pool: &DbPool,
+    room_manager: &Addr<RoomManager>,
+) -> Result<(), SessionRejected> {
+    let user_id = bearer_token.user_id.as_str();
+    // 已停用的账号不能建立连接（访问令牌在过期前仍然有效）
+    match db::is_user_disabled(user_id, pool).await {
+        Ok(false) => {}
+        Ok(true) => {
+            return Err(SessionRejected::new(
+                ErrorCode::AccountDisabled,
+                "账号已被停用",
+            ));
+        }
+        Err(_) => {
+            return Err(SessionRejected::new(
+                ErrorCode::InternalError,
+                "账号校验失败",
+            ));
+        }
+    }
+
+    // 已注销的登录会话不能再建立连接（访问令牌在过期前仍然有效）
+    if let Some(session_id) = &bearer_token.session_id {
+        match db::is_login_session_active(session_id, pool).await {
+            Ok(true) => {}
+            Ok(false) => {
+                return Err(SessionRejected::new(ErrorCode::AuthInvalid, "登录已注销"));
+            }
+            Err(_) => {
+                return Err(SessionRejected::new(
+                    ErrorCode::InternalError,
+                    "登录会话校验失败",
+                ));
+            }
+        }
+    }
+
+    // 提供了设备 ID 时必须是该用户已注册且未注销的设备
+    if let Some(device_id) = &client.device_id {
+        match db::get_active_device(user_id, device_id, pool).await {
+            Ok(Some(_)) => {
+                if let Err(e) = db::touch_device(user_id, device_id, pool).await {
+                    warn!(
+                        user_id = %user_id,
+                        device_id = %device_id,
+                        error = %e,
+                        "failed to update last seen of device",
+                    );
+                }
+            }
+            Ok(None) => {
+                return Err(SessionRejected::new(
+                    ErrorCode::Forbidden,
+                    "设备未注册或已注销",
+                ));
+            }
+            Err(_) => {
+                return Err(SessionRejected::new(
+                    ErrorCode::InternalError,
+                    "设备校验失败",
+                ));
+            }
+        }
+    }
+
+    // 加载设备分组路由规则
+    if let Err(e) = sync_device_routing(user_id, pool, room_manager).await {
+        warn!(user_id = %user_id, error = %e, "failed to load device groups");
+    }
+    if let Err(e) = sync_device_rules(user_id, pool, room_manager).await {
+        warn!(user_id = %user_id, error = %e, "failed to load sync rules");
+    }
+    Ok(())
 }
 
 // WebSocket端点
+//
+// 令牌通过 Authorization 头或 `?token=` 查询参数提供（浏览器无法设置握手请求头）；
+// 两者都没有时先建立连接，客户端需要在 auth_timeout_secs 内发送
+// `{"type":"auth","payload":{"token":"..."}}`，否则连接被关闭
 #[get("/ws")]
 async fn index(
-    bearer_token: BearerToken,
+    ws_token: WsToken,
     req: HttpRequest,
     stream: web::Payload,
     data: web::Data<AppState>,
+    pool: web::Data<DbPool>,
 ) -> Result<HttpResponse, Error> {
-    let user_id = bearer_token.user_id;
-    
-    println!("WebSocket connection requested for user: {}", user_id);
-    
-    let resp = ws::start(
-        MyWs::new(user_id, data.room_manager.clone()),
+    let client = client_info_from_request(&req);
+
+    let bearer_token = match ws_token.0 {
+        Some(bearer_token) => {
+            admit_session(&bearer_token, &client, &pool, &data.room_manager)
+                .await
+                .map_err(|e| ApiResponse::into_error(e.code, e.message))?;
+            info!(
+                user_id = %bearer_token.user_id,
+                device = %client.label(),
+                "WebSocket connection requested",
+            );
+            Some(bearer_token)
+        }
+        None => {
+            info!(device = %client.label(), "WebSocket connection requested without token");
+            None
+        }
+    };
+
+    // 二进制剪贴板整帧发送，需要放宽默认的 64KB 帧大小限制
+    let resp = ws::WsResponseBuilder::new(
+        MyWs::new(
+            bearer_token,
+            client,
+            data.room_manager.clone(),
+            data.content_policy.clone(),
+            pool.get_ref().clone(),
+            data.rate_limiters.ws.clone(),
+            &data.config.spatial,
+        ),
         &req,
         stream,
-    );
-    
-    println!("WebSocket response: {:?}", resp);
+    )
+    .frame_size(MAX_FRAME_SIZE)
+    .start();
+
+    debug!(response = ?resp, "WebSocket response");
     resp
-}
\ No newline at end of file
+}
+
+// 获取当前用户的在线会话列表
+#[get("/sessions")]
+async fn list_sessions(bearer_token: BearerToken, data: web::Data<AppState>) -> impl Responder {
+    match data
+        .room_manager
+        .send(GetSessions {
+            user_id: bearer_token.user_id,
+        })
+        .await
+    {
+        Ok(sessions) => ApiResponse::new("获取会话成功", ResponseData::Json(json!(sessions))),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取会话失败"),
+    }
+}
+
+// 获取当前用户的在线状态：每个会话的设备信息、连接时间和最近一次心跳时间
+#[get("/presence")]
+async fn presence(bearer_token: BearerToken, data: web::Data<AppState>) -> impl Responder {
+    match data
+        .room_manager
+        .send(GetPresence {
+            user_id: bearer_token.user_id,
+        })
+        .await
+    {
+        Ok(presence) => ApiResponse::new("获取在线状态成功", ResponseData::Json(json!(presence))),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取在线状态失败"),
+    }
+}
+
+/// 长轮询默认等待时间（秒）
+const DEFAULT_POLL_TIMEOUT: u64 = 30;
+
+/// 长轮询最长等待时间（秒）
+const MAX_POLL_TIMEOUT: u64 = 60;
+
+// 长轮询参数
+#[derive(Deserialize)]
+pub struct PollQuery {
+    /// 上次返回的游标，首次请求不传（也可以用 `since`）
+    #[serde(alias = "since")]
+    pub cursor: Option<u64>,
+    /// 等待时间，如 "30s" 或 "30"
+    pub timeout: Option<String>,
+}
+
+// 长轮询获取事件（WS 和 SSE 都不可用时的兜底方案）
+//
+// 游标之后已有事件时立即返回，否则最多等待 timeout 秒；返回这一批事件和下次请求使用的游标，
+// 超时没有新事件时返回空列表，游标不变
+#[get("/poll")]
+async fn poll_events(
+    bearer_token: BearerToken,
+    device_id: DeviceId,
+    data: web::Data<AppState>,
+    query: web::Query<PollQuery>,
+) -> impl Responder {
+    let timeout = query
+        .timeout
+        .as_deref()
+        .and_then(|t| t.trim_end_matches('s').parse::<u64>().ok())
+        .unwrap_or(DEFAULT_POLL_TIMEOUT)
+        .min(MAX_POLL_TIMEOUT);
+    let poll = || PollEvents {
+        user_id: bearer_token.user_id.clone(),
+        device_id: device_id.0.clone(),
+        since: query.cursor,
+    };
+
+    let (events, cursor) = match data.room_manager.send(poll()).await {
+        Ok(PollResult::Ready { events, cursor }) => (events, cursor),
+        Ok(PollResult::Wait(receiver)) => {
+            match tokio::time::timeout(Duration::from_secs(timeout), receiver).await {
+                // 被第一个新事件唤醒后重新读取，一并返回同时到达的其他事件
+                Ok(Ok(woken)) => match data.room_manager.send(poll()).await {
+                    Ok(PollResult::Ready { events, cursor }) => (events, cursor),
+                    _ => {
+                        let cursor = woken.last().map(|event| event.seq).unwrap_or_default();
+                        (woken, cursor)
+                    }
+                },
+                // 超时没有新事件，游标不变
+                _ => (Vec::new(), query.cursor.unwrap_or_default()),
+            }
+        }
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "获取事件失败"),
+    };
+    ApiResponse::new(
+        "获取事件成功",
+        ResponseData::Json(json!({ "events": events, "cursor": cursor })),
+    )
+}
+
+/// SSE 连接空闲时发送注释行的间隔，避免代理因长时间没有数据断开连接
+const SSE_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// SSE 断开后客户端重连前等待的时间（毫秒）
+const SSE_RETRY_MS: u64 = 3000;
+
+// SSE 参数
+#[derive(Deserialize)]
+pub struct EventStreamQuery {
+    /// 最后收到的事件 ID，等同于 Last-Event-ID 请求头（请求头优先）
+    pub last_event_id: Option<u64>,
+}
+
+// SSE 端点（WebSocket 被代理拦截时的兜底方案），推送与 WebSocket 相同的房间事件
+//
+// 每个事件的 id 为序号，event 为事件类型，data 与长轮询返回的事件相同；断线重连时
+// EventSource 自动携带 Last-Event-ID 请求头，从事件日志中补发之后的事件。
+// 令牌和设备信息的提供方式与 WebSocket 握手相同（`?token=`、`?device_id=`）
+#[get("/events")]
+async fn event_stream(
+    ws_token: WsToken,
+    req: HttpRequest,
+    query: web::Query<EventStreamQuery>,
+    data: web::Data<AppState>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, Error> {
+    let Some(bearer_token) = ws_token.0 else {
+        return Err(ApiResponse::into_error(
+            ErrorCode::AuthMissing,
+            "缺少访问令牌",
+        ));
+    };
+    let client = client_info_from_request(&req);
+    admit_session(&bearer_token, &client, &pool, &data.room_manager)
+        .await
+        .map_err(|e| ApiResponse::into_error(e.code, e.message))?;
+    let last_event_id = req
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+        .or(query.last_event_id);
+    info!(
+        user_id = %bearer_token.user_id,
+        device = %client.label(),
+        last_event_id = ?last_event_id,
+        "SSE connection opened",
+    );
+
+    let state = EventStreamState {
+        room_manager: data.room_manager.clone(),
+        user_id: bearer_token.user_id,
+        device_id: client.device_id,
+        cursor: last_event_id,
+    };
+    let retry = stream::once(async { Ok(Bytes::from(format!("retry: {}\n\n", SSE_RETRY_MS))) });
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        // 禁止 nginx 缓冲响应
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(retry.chain(stream::try_unfold(state, next_sse_events))))
+}
+
+struct EventStreamState {
+    room_manager: Addr<RoomManager>,
+    user_id: String,
+    device_id: Option<String>,
+    cursor: Option<u64>,
+}
+
+// 等待下一批事件并编码为 SSE，与长轮询共用房间管理器的事件日志和等待队列
+//
+// 空闲超过 SSE_KEEPALIVE_INTERVAL 时发送注释行；服务器停机或用户数据被清除时结束响应
+async fn next_sse_events(
+    mut state: EventStreamState,
+) -> Result<Option<(Bytes, EventStreamState)>, Error> {
+    let result = state
+        .room_manager
+        .send(PollEvents {
+            user_id: state.user_id.clone(),
+            device_id: state.device_id.clone(),
+            since: state.cursor,
+        })
+        .await;
+    let events = match result {
+        // 首次连接或游标超前（服务器重启后序号重置）时只返回当前游标，
+        // 发送不带数据的 id 行，使客户端重连时从这里继续
+        Ok(PollResult::Ready { events, cursor }) if events.is_empty() => {
+            state.cursor = Some(cursor);
+            return Ok(Some((Bytes::from(format!("id: {}\n\n", cursor)), state)));
+        }
+        Ok(PollResult::Ready { events, .. }) => events,
+        Ok(PollResult::Wait(receiver)) => {
+            match tokio::time::timeout(SSE_KEEPALIVE_INTERVAL, receiver).await {
+                Ok(Ok(events)) => events,
+                Ok(Err(_)) => return Ok(None),
+                Err(_) => return Ok(Some((Bytes::from_static(b": keepalive\n\n"), state))),
+            }
+        }
+        Err(_) => return Ok(None),
+    };
+
+    let mut body = String::new();
+    for event in &events {
+        let data = serde_json::to_string(event).unwrap_or_default();
+        body.push_str(&format!(
+            "id: {}\nevent: {}\ndata: {}\n\n",
+            event.seq, event.event, data
+        ));
+    }
+    state.cursor = events.last().map(|event| event.seq).or(state.cursor);
+    Ok(Some((Bytes::from(body), state)))
+}
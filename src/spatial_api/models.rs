@@ -1,64 +1,473 @@
 use actix::{WeakAddr, prelude::*};
-use actix_web::{Error, HttpRequest, HttpResponse, web};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use actix_web::web::Bytes;
 use actix_web_actors::ws;
-use chrono::Local;
-use std::collections::HashMap;
+use chrono::{Local, SubsecRound, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+use tracing::{Instrument, Span, debug, info, info_span, warn};
 use uuid::Uuid;
 
+use crate::blob_store::blob_store;
+use crate::config::{Config, PausedSyncPolicy, SessionLimitPolicy, SpatialConfig};
+use crate::content_policy::ContentPolicy;
+use crate::device_api::{SyncRule, last_seen::DeviceLastSeen};
+use crate::discovery::LanDiscovery;
+use crate::device_group_api::Visibility;
+use crate::mailer::Mailer;
+use crate::clip_api::{PREVIEW_CHARS, normalize_tags, store_clip};
+use crate::models::{
+    ClipFilter, ClipItem, ClipSource, ClipType, SyncStatus, check_encryption, check_expiry,
+    content_hash,
+};
+use crate::user_api::quota::check_quota;
+use crate::rate_limit::{RateLimiter, RateLimiters, retry_after_secs};
+use crate::scheduled_api::models::deliver_pending_for_user;
+use crate::spatial_api::admit_session;
+use crate::spatial_api::binary::{self, BinaryClipPush};
+use crate::spatial_api::cluster::{self, ClusterMessage, ClusterPublisher, RemoteMessage};
+use crate::sqlx_utils::{
+    DbPool, db,
+    models::{ApiResponse, Cursor, ErrorCode, truncate_page},
+};
+use crate::user_api::app_rules::load_app_rules;
+use crate::user_api::auth::{BearerToken, bearer_from_access_token};
+use crate::user_api::lockout::LoginLockout;
+use crate::webhook_api::{self, WebhookEvent};
+
+/// 客户端元数据，握手时通过请求头/查询参数提供，也可以在 hello 消息中更新
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ClientInfo {
+    /// 已注册的设备 ID（见 `/devices`），只能在握手时提供，用于设备分组路由和标识消息来源
+    pub device_id: Option<String>,
+    pub platform: Option<String>,
+    pub app_version: Option<String>,
+    pub device_name: Option<String>,
+}
+
+impl ClientInfo {
+    // 用于日志和系统消息的简短描述
+    pub fn label(&self) -> String {
+        let name = self
+            .device_name
+            .as_deref()
+            .or(self.platform.as_deref())
+            .unwrap_or("unknown device");
+        match &self.app_version {
+            Some(version) => format!("{} (v{})", name, version),
+            None => name.to_string(),
+        }
+    }
+}
+
+/// 协议版本，hello/welcome 交换时告知客户端
+///
+/// - 1：hello / welcome 和服务端事件
+/// - 2：剪贴板同步消息（clip.push / clip.ack / clip.request_history / presence）
+/// - 3：确认送达（acked_delivery 能力和 ack 消息）
+/// - 4：会话上下线事件（presence.joined / presence.left / presence.updated）
+/// - 5：推送剪贴板给指定会话（direct 消息）
+/// - 6：压缩大消息（compression 能力）
+/// - 7：暂停同步（sync.pause / sync.resume）
+pub const PROTOCOL_VERSION: u32 = 7;
+
+/// 服务端支持的能力，与客户端 hello 中声明的能力取交集后按会话生效
+///
+/// - `json_events`：服务端事件以 `{"type", "payload"}` JSON 文本发送，否则降级为 `[SYSTEM]` 文本
+/// - `acked_delivery`：其他设备转发来的剪贴板带有递增的 `seq`，客户端以 `ack` 确认，
+///   未确认的剪贴板在同一设备重连后重发（需要同时协商 `json_events`）
+/// - `compression`：超过 `spatial.compression_threshold` 字节的文本消息压缩后以二进制帧发送，
+///   帧头为 `{"type":"compressed","encoding":"deflate"}`，数据为原文本的 raw DEFLATE 字节
+pub const SERVER_CAPABILITIES: &[&str] = &["json_events", "acked_delivery", "compression"];
+
+/// 每个会话最多保留的待确认消息数，超出时丢弃最早的消息（客户端发现序号不连续时应增量同步补齐）
+const MAX_PENDING_DELIVERIES: usize = 256;
+
+/// 设备断开后保留其待确认消息的时间（秒），在此期间重连会重发
+const PENDING_DELIVERY_TTL: i64 = 10 * 60;
+
+// 房间内的单个会话
+struct SessionEntry {
+    addr: WeakAddr<MyWs>,
+    client: ClientInfo,
+    // 建立连接所用令牌的登录会话，旧令牌没有
+    login_session_id: Option<String>,
+    connected_at: i64,
+    // 平滑后的往返延迟（毫秒），收到第一个 pong 之前为空
+    rtt_ms: Option<u64>,
+    // 最近一次收到心跳响应的时间
+    last_heartbeat_at: i64,
+    // 协商了 acked_delivery 的会话的待确认消息
+    outbox: Option<Outbox>,
+    // 暂停同步时为 Some，其中为暂存的剪贴板（按 paused_sync_policy 丢弃时始终为空）
+    paused: Option<VecDeque<Outbound>>,
+}
+
+impl SessionEntry {
+    fn presence(&self, session_id: &str) -> Presence {
+        Presence {
+            session_id: session_id.to_string(),
+            client: self.client.clone(),
+            connected_at: self.connected_at,
+            last_heartbeat_at: self.last_heartbeat_at,
+        }
+    }
+}
+
+// 会话的待确认消息队列，同一设备重连后序号延续
+#[derive(Default)]
+struct Outbox {
+    last_seq: u64,
+    pending: VecDeque<Delivery>,
+    // 设备断开的时间，在线时为空
+    detached_at: Option<i64>,
+}
+
+impl Outbox {
+    // 分配序号并加入队列，队列已满时丢弃最早的消息
+    fn push(&mut self, message: Outbound) -> Delivery {
+        self.last_seq += 1;
+        let delivery = Delivery {
+            seq: self.last_seq,
+            message,
+        };
+        self.pending.push_back(delivery.clone());
+        if self.pending.len() > MAX_PENDING_DELIVERIES {
+            self.pending.pop_front();
+        }
+        delivery
+    }
+
+    // 确认 seq 及之前的全部消息，返回确认的消息数
+    fn ack(&mut self, seq: u64) -> usize {
+        let before = self.pending.len();
+        while self
+            .pending
+            .front()
+            .is_some_and(|delivery| delivery.seq <= seq)
+        {
+            self.pending.pop_front();
+        }
+        before - self.pending.len()
+    }
+}
+
+/// 会话信息（会话列表接口返回）
+#[derive(Debug, Serialize)]
+pub struct SessionInfo {
+    pub session_id: String,
+    pub client: ClientInfo,
+    /// 建立连接所用令牌的登录会话，见 `/user/sessions`
+    pub login_session_id: Option<String>,
+    pub connected_at: i64,
+    /// 最近一次收到心跳响应的时间
+    pub last_heartbeat_at: i64,
+    /// 心跳测得的往返延迟（毫秒）
+    pub rtt_ms: Option<u64>,
+    /// 等待客户端确认的消息数，未协商 acked_delivery 时为空
+    pub pending_deliveries: Option<usize>,
+}
+
+/// 在线会话（在线状态接口和 presence.* 事件返回）
+#[derive(Debug, Clone, Serialize)]
+pub struct Presence {
+    pub session_id: String,
+    #[serde(flatten)]
+    pub client: ClientInfo,
+    pub connected_at: i64,
+    /// 最近一次收到心跳响应的时间，收到第一个 pong 之前为连接时间
+    pub last_heartbeat_at: i64,
+}
+
+/// 每个用户（以及全局广播）保留的最近事件数，供长轮询客户端补齐
+const MAX_LOGGED_EVENTS: usize = 256;
+
+/// 事件保留时间（秒），超过后由定期清理任务移除
+const EVENT_LOG_TTL: i64 = 5 * 60;
+
+/// 吞吐量统计的时间窗口
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// 房间管理器的实时统计（管理后台使用）
+#[derive(Debug, Serialize)]
+pub struct RoomStats {
+    /// 有在线会话的用户数
+    pub rooms: usize,
+    pub sessions: usize,
+    /// 最近一分钟客户端发出并转发的消息数
+    pub messages_per_minute: usize,
+    /// 最近一分钟服务端推送的事件数
+    pub events_per_minute: usize,
+    /// 正在等待的长轮询请求数
+    pub waiting_pollers: usize,
+    /// 事件日志中保留的事件数
+    pub logged_events: usize,
+    /// 已测得延迟的会话的平均 / 最大往返延迟（毫秒）
+    pub avg_rtt_ms: Option<u64>,
+    pub max_rtt_ms: Option<u64>,
+}
+
+/// 房间概况（管理接口的房间列表返回），每个用户一个房间
+#[derive(Debug, Serialize)]
+pub struct RoomSummary {
+    pub user_id: String,
+    pub sessions: usize,
+    /// 最早建立的会话的连接时间
+    pub connected_at: i64,
+}
+
+/// 带序号的服务端事件（长轮询接口返回）
+#[derive(Debug, Clone, Serialize)]
+pub struct SequencedEvent {
+    pub seq: u64,
+    #[serde(rename = "type")]
+    pub event: String,
+    pub payload: serde_json::Value,
+    pub created_at: i64,
+    /// 仅对这些设备可见，None 表示所有设备
+    #[serde(skip)]
+    pub devices: Option<HashSet<String>>,
+}
+
+impl SequencedEvent {
+    fn visible_to(&self, device_id: Option<&str>) -> bool {
+        match &self.devices {
+            Some(devices) => device_id.is_some_and(|device_id| devices.contains(device_id)),
+            None => true,
+        }
+    }
+}
+
+// 等待新事件的长轮询请求
+struct Poller {
+    device_id: Option<String>,
+    sender: oneshot::Sender<Vec<SequencedEvent>>,
+}
+
+/// 长轮询结果：已有新事件时立即返回，否则等待下一个事件
+pub enum PollResult {
+    Ready {
+        events: Vec<SequencedEvent>,
+        cursor: u64,
+    },
+    Wait(oneshot::Receiver<Vec<SequencedEvent>>),
+}
+
+/// 房间操作失败的原因，WebSocket 会话据此向客户端发送 error 帧
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoomError {
+    /// 同一会话重复加入房间
+    AlreadyJoined,
+    /// 会话不在房间中（未加入或已被移出）
+    NotInRoom,
+    /// 服务器正在停机，不再接受新会话
+    ShuttingDown,
+    /// 用户的会话数已达到上限
+    SessionLimitReached,
+}
+
+impl RoomError {
+    /// 对应的错误码
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            RoomError::AlreadyJoined => ErrorCode::AlreadyExists,
+            RoomError::NotInRoom => ErrorCode::NotFound,
+            RoomError::ShuttingDown => ErrorCode::InternalError,
+            RoomError::SessionLimitReached => ErrorCode::RateLimited,
+        }
+    }
+}
+
+impl std::fmt::Display for RoomError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            RoomError::AlreadyJoined => "session already joined the room",
+            RoomError::NotInRoom => "session is not in the room",
+            RoomError::ShuttingDown => "server is shutting down",
+            RoomError::SessionLimitReached => "too many sessions for this user",
+        })
+    }
+}
+
+impl std::error::Error for RoomError {}
+
 // 房间管理器
 pub struct RoomManager {
-    // user_id -> session_id -> WeakAddr
-    rooms: HashMap<String, HashMap<String, WeakAddr<MyWs>>>,
+    // user_id -> session_id -> SessionEntry
+    rooms: HashMap<String, HashMap<String, SessionEntry>>,
+    // 最近推送给各用户的事件
+    event_log: HashMap<String, VecDeque<SequencedEvent>>,
+    // 最近推送给所有用户的事件
+    broadcast_log: VecDeque<SequencedEvent>,
+    // 等待新事件的长轮询请求
+    pollers: HashMap<String, Vec<Poller>>,
+    // 最近一个事件的序号（进程内单调递增）
+    last_seq: u64,
+    // user_id -> 隔离分组的设备 ID 集合，分组内设备发出的剪贴板只路由到同组设备
+    device_routing: HashMap<String, Vec<HashSet<String>>>,
+    // user_id -> device_id -> 设备的同步规则
+    sync_rules: HashMap<String, HashMap<String, SyncRule>>,
+    // (user_id, device_id) -> 已断开的设备未确认的消息，重连后重发
+    detached_outboxes: HashMap<(String, String), Outbox>,
+    // 最近一分钟转发的客户端消息时间
+    message_times: VecDeque<Instant>,
+    // 最近一分钟推送的服务端事件时间
+    event_times: VecDeque<Instant>,
+    // 清理死亡连接的间隔
+    cleanup_interval: Duration,
+    // 每个用户同时在线的会话数上限（0 表示不限制）及达到上限时的处理方式
+    max_sessions_per_user: usize,
+    session_limit_policy: SessionLimitPolicy,
+    // 多节点部署时把房间操作发布给其他节点
+    cluster: Option<ClusterPublisher>,
+    // 已开始停机，不再接受新会话
+    shutting_down: bool,
+    // (user_id, device_id) -> 设备最近一次在线的时间，尚未写入数据库
+    device_last_seen: HashMap<(String, String), i64>,
+    // (user_id, device_id) 通过接口暂停了同步的设备，之后建立的会话同样处于暂停状态
+    paused_devices: HashSet<(String, String)>,
+    // 会话暂停同步期间收到的剪贴板的处理方式
+    paused_sync_policy: PausedSyncPolicy,
 }
 
 impl RoomManager {
-    pub fn new() -> Self {
+    /// 启动房间管理器 Actor，按配置加入多节点集群
+    pub fn spawn(config: &Config) -> Addr<RoomManager> {
+        RoomManager::create(|ctx| {
+            let cluster = cluster::start(&config.cluster, ctx.address());
+            RoomManager::new(&config.spatial, cluster)
+        })
+    }
+
+    pub fn new(config: &SpatialConfig, cluster: Option<ClusterPublisher>) -> Self {
         Self {
             rooms: HashMap::new(),
+            event_log: HashMap::new(),
+            broadcast_log: VecDeque::new(),
+            pollers: HashMap::new(),
+            last_seq: 0,
+            device_routing: HashMap::new(),
+            sync_rules: HashMap::new(),
+            detached_outboxes: HashMap::new(),
+            message_times: VecDeque::new(),
+            event_times: VecDeque::new(),
+            cleanup_interval: config.cleanup_interval(),
+            max_sessions_per_user: config.max_sessions_per_user,
+            session_limit_policy: config.session_limit_policy,
+            cluster,
+            shutting_down: false,
+            device_last_seen: HashMap::new(),
+            paused_devices: HashSet::new(),
+            paused_sync_policy: config.paused_sync_policy,
+        }
+    }
+
+    // 把本节点执行的房间操作发布给其他节点，未启用多节点部署时不构造消息
+    fn publish(&self, message: impl FnOnce() -> ClusterMessage) {
+        if let Some(cluster) = &self.cluster {
+            cluster.publish(message());
         }
     }
 
     // 清理指定用户的死亡连接
     fn cleanup_dead_connections(&mut self, user_id: &str) {
+        let mut removed = Vec::new();
         if let Some(sessions) = self.rooms.get_mut(user_id) {
             // 先收集死亡的 session_id
             let dead_sessions: Vec<String> = sessions
                 .iter()
-                .filter(|(_, weak_addr)| weak_addr.upgrade().is_none())
+                .filter(|(_, entry)| entry.addr.upgrade().is_none())
                 .map(|(session_id, _)| session_id.clone())
                 .collect();
             
             // 移除死亡的连接
             for session_id in dead_sessions {
-                sessions.remove(&session_id);
-                println!("🧹 Cleaned up dead session: {}", &session_id[..8]);
+                if let Some(entry) = sessions.remove(&session_id) {
+                    removed.push(entry);
+                }
+                debug!(user_id = %user_id, session_id = %session_id, "cleaned up dead session");
             }
             
             // 如果房间为空，移除整个房间
             if sessions.is_empty() {
                 self.rooms.remove(user_id);
-                println!("🗑️ Room {} is now empty and removed", user_id);
+                debug!(user_id = %user_id, "room is now empty and removed");
             }
         }
+        for entry in removed {
+            self.detach_outbox(user_id, entry);
+        }
+    }
+
+    // 会话断开时保留其设备未确认的消息，未提供设备 ID 的会话无法识别重连，直接丢弃
+    fn detach_outbox(&mut self, user_id: &str, entry: SessionEntry) {
+        if let (Some(mut outbox), Some(device_id)) = (entry.outbox, entry.client.device_id)
+            && !outbox.pending.is_empty()
+        {
+            outbox.detached_at = Some(chrono::Utc::now().timestamp());
+            self.detached_outboxes
+                .insert((user_id.to_string(), device_id), outbox);
+        }
     }
 
     // 加入房间
-    pub fn join_room(&mut self, user_id: &str, session_id: String, addr: Addr<MyWs>) {
+    //
+    // 返回加入后房间内的会话数
+    pub fn join_room(
+        &mut self,
+        user_id: &str,
+        session_id: String,
+        addr: Addr<MyWs>,
+        client: ClientInfo,
+        login_session_id: Option<String>,
+    ) -> Result<usize, RoomError> {
+        if self.shutting_down {
+            return Err(RoomError::ShuttingDown);
+        }
         // 先清理死亡连接
         self.cleanup_dead_connections(user_id);
-        
-        let sessions = self
+        if self
             .rooms
-            .entry(user_id.to_string())
-            .or_insert_with(HashMap::new);
-        
-        sessions.insert(session_id.clone(), addr.downgrade());
+            .get(user_id)
+            .is_some_and(|sessions| sessions.contains_key(&session_id))
+        {
+            return Err(RoomError::AlreadyJoined);
+        }
+        self.enforce_session_limit(user_id)?;
+
+        let label = client.label();
+        let now = chrono::Utc::now().timestamp();
+        let paused = client.device_id.as_ref().and_then(|device_id| {
+            self.paused_devices
+                .contains(&(user_id.to_string(), device_id.clone()))
+                .then(VecDeque::new)
+        });
+        let sessions = self.rooms.entry(user_id.to_string()).or_default();
+        let entry = SessionEntry {
+            addr: addr.downgrade(),
+            client,
+            login_session_id,
+            connected_at: now,
+            rtt_ms: None,
+            last_heartbeat_at: now,
+            outbox: None,
+            paused,
+        };
+        let presence = entry.presence(&session_id);
+        sessions.insert(session_id.clone(), entry);
         
         let count = sessions.len();
-        println!(
-            "✅ User {} (session {}) joined room. Total active users: {}",
-            user_id, &session_id[..8], count
+        info!(
+            user_id = %user_id,
+            session_id = %session_id,
+            device = %label,
+            sessions = count,
+            "joined room",
         );
 
         // 发送欢迎消息给新用户
@@ -67,15 +476,63 @@ impl RoomManager {
             count
         )));
 
-        // 通知房间内的其他用户
-        let join_msg = format!("[SYSTEM] New user joined. Active users: {}", count);
-        if let Some(sessions) = self.rooms.get(user_id) {
-            for (sid, weak_addr) in sessions {
-                if sid != &session_id {
-                    if let Some(addr) = weak_addr.upgrade() {
-                        addr.do_send(ClientMessage(join_msg.clone()));
-                    }
-                }
+        // 通知房间内的其他会话
+        self.notify_presence(user_id, "presence.joined", &presence);
+        Ok(count)
+    }
+
+    // 用户的会话数达到上限时按策略拒绝新会话，或断开最早建立的会话为新会话腾出位置
+    fn enforce_session_limit(&mut self, user_id: &str) -> Result<(), RoomError> {
+        let max = self.max_sessions_per_user;
+        if max == 0 {
+            return Ok(());
+        }
+        loop {
+            let Some(sessions) = self.rooms.get(user_id) else {
+                return Ok(());
+            };
+            if sessions.len() < max {
+                return Ok(());
+            }
+            if self.session_limit_policy == SessionLimitPolicy::Reject {
+                warn!(user_id = %user_id, sessions = sessions.len(), "session limit reached");
+                return Err(RoomError::SessionLimitReached);
+            }
+            let Some((oldest, addr)) = sessions
+                .iter()
+                .min_by_key(|(_, entry)| entry.connected_at)
+                .map(|(session_id, entry)| (session_id.clone(), entry.addr.clone()))
+            else {
+                return Ok(());
+            };
+            if let Some(addr) = addr.upgrade() {
+                addr.do_send(Disconnect {
+                    reason: "session.evicted".to_string(),
+                });
+            }
+            warn!(
+                user_id = %user_id,
+                session_id = %oldest,
+                "session limit reached, evicting oldest session",
+            );
+            self.leave_room(user_id, &oldest);
+        }
+    }
+
+    // 会话上线、下线或更新元数据时通知用户的其他会话（附带在线会话数）
+    fn notify_presence(&self, user_id: &str, event: &str, presence: &Presence) {
+        let Some(sessions) = self.rooms.get(user_id) else {
+            return;
+        };
+        let event = ServerEvent {
+            event: event.to_string(),
+            payload: serde_json::json!({ "session": presence, "sessions": sessions.len() }),
+        };
+        for (session_id, entry) in sessions {
+            if session_id != &presence.session_id
+                && let Some(addr) = entry.addr.upgrade()
+            {
+                addr.do_send(event.clone());
             }
         }
     }
@@ -84,220 +541,1857 @@ impl RoomManager {
     pub fn leave_room(&mut self, user_id: &str, session_id: &str) {
         let mut remaining = 0;
         let mut should_remove_room = false;
+        let mut label = String::from("unknown device");
+        let mut removed = None;
+        let mut presence = None;
         
         if let Some(sessions) = self.rooms.get_mut(user_id) {
-            sessions.remove(session_id);
+            if let Some(entry) = sessions.remove(session_id) {
+                label = entry.client.label();
+                // 设备的最后在线时间为会话离开的时间
+                if let Some(device_id) = &entry.client.device_id {
+                    self.device_last_seen.insert(
+                        (user_id.to_string(), device_id.clone()),
+                        chrono::Utc::now().timestamp(),
+                    );
+                }
+                presence = Some(entry.presence(session_id));
+                removed = Some(entry);
+            }
             remaining = sessions.len();
             should_remove_room = sessions.is_empty();
         }
+        if let Some(entry) = removed {
+            self.detach_outbox(user_id, entry);
+        }
         
         if should_remove_room {
             self.rooms.remove(user_id);
-            println!("🗑️ Room {} is now empty and removed", user_id);
+            info!(
+                user_id = %user_id,
+                session_id = %session_id,
+                device = %label,
+                "left room, room is now empty and removed",
+            );
         } else {
-            println!(
-                "👋 User {} (session {}) left room. Remaining users: {}",
-                user_id, &session_id[..8], remaining
+            info!(
+                user_id = %user_id,
+                session_id = %session_id,
+                device = %label,
+                sessions = remaining,
+                "left room",
             );
 
-            // 通知剩余用户
-            let leave_msg = format!("[SYSTEM] User left. Remaining users: {}", remaining);
-            if let Some(sessions) = self.rooms.get(user_id) {
-                for (_, weak_addr) in sessions {
-                    if let Some(addr) = weak_addr.upgrade() {
-                        addr.do_send(ClientMessage(leave_msg.clone()));
-                    }
-                }
+            // 通知剩余的会话
+            if let Some(presence) = presence {
+                self.notify_presence(user_id, "presence.left", &presence);
             }
         }
     }
 
-    // 广播消息（排除指定 session）
+    // 广播消息（排除指定 session），发送方属于隔离分组时只发给同组设备
+    //
+    // 返回送达的会话数，发送方不在房间中时返回 NotInRoom
     pub fn broadcast_to_room_excluding(
         &mut self,
         user_id: &str,
         message: String,
         exclude_session: Option<&str>,
-    ) {
-        // 先清理死亡连接
+    ) -> Result<usize, RoomError> {
+        record_rate(&mut self.message_times);
+        let allowed =
+            exclude_session.and_then(|session_id| self.routing_targets(user_id, session_id));
         self.cleanup_dead_connections(user_id);
-        
-        // 收集所有活跃的地址（避免借用冲突）
-        let addresses: Vec<Addr<MyWs>> = if let Some(sessions) = self.rooms.get(user_id) {
-            sessions
-                .iter()
-                .filter(|(session_id, _)| {
-                    if let Some(exclude) = exclude_session {
-                        session_id.as_str() != exclude
-                    } else {
-                        true
-                    }
-                })
-                .filter_map(|(_, weak_addr)| weak_addr.upgrade())
-                .collect()
-        } else {
-            Vec::new()
+
+        let sessions = self.rooms.get(user_id);
+        if let Some(sender) = exclude_session
+            && !sessions.is_some_and(|sessions| sessions.contains_key(sender))
+        {
+            return Err(RoomError::NotInRoom);
+        }
+        let mut delivered = 0;
+        for (session_id, entry) in sessions.into_iter().flatten() {
+            if exclude_session == Some(session_id.as_str()) {
+                continue;
+            }
+            if let Some(allowed) = &allowed
+                && !entry
+                    .client
+                    .device_id
+                    .as_ref()
+                    .is_some_and(|device_id| allowed.contains(device_id))
+            {
+                continue;
+            }
+            if let Some(addr) = entry.addr.upgrade() {
+                addr.do_send(ClientMessage(message.clone()));
+                delivered += 1;
+            }
+        }
+        Ok(delivered)
+    }
+
+    // 转发客户端的协议消息给房间内的其他会话，返回送达的会话数
+    //
+    // devices 为剪贴板的可见设备（None 表示不限制），发送方属于隔离分组时再限制为同组设备；
+    // 协商了 acked_delivery 的会话收到带序号的消息，并保留到客户端确认
+    pub fn relay_to_room(
+        &mut self,
+        user_id: &str,
+        sender_session_id: &str,
+        devices: Option<HashSet<String>>,
+        target_session_id: Option<&str>,
+        message: Outbound,
+    ) -> usize {
+        record_rate(&mut self.message_times);
+        let allowed = self.relay_targets(user_id, sender_session_id, devices);
+        self.cleanup_dead_connections(user_id);
+
+        let rules = self.sync_rules.get(user_id);
+        let clip = message.clip_summary();
+        let policy = self.paused_sync_policy;
+        let Some(sessions) = self.rooms.get_mut(user_id) else {
+            return 0;
         };
-        
-        // 发送消息
-        for addr in addresses {
-            addr.do_send(ClientMessage(message.clone()));
+        let mut delivered = 0;
+        for (session_id, entry) in sessions.iter_mut() {
+            if session_id == sender_session_id
+                || target_session_id.is_some_and(|target| target != session_id)
+                || !syncs_clip(rules, entry.client.device_id.as_deref(), clip)
+            {
+                continue;
+            }
+            if let Some(allowed) = &allowed
+                && !entry
+                    .client
+                    .device_id
+                    .as_ref()
+                    .is_some_and(|device_id| allowed.contains(device_id))
+            {
+                continue;
+            }
+            let Some(addr) = entry.addr.upgrade() else {
+                continue;
+            };
+            if message.is_clip()
+                && let Some(held) = &mut entry.paused
+            {
+                hold_while_paused(policy, held, message.clone());
+                continue;
+            }
+            deliver(user_id, session_id, entry, &addr, message.clone());
+            delivered += 1;
         }
+        delivered
     }
 
-    // 广播给所有人
-    pub fn broadcast_to_room(&mut self, user_id: &str, message: String) {
-        self.broadcast_to_room_excluding(user_id, message, None);
+    // 暂停或恢复会话的同步，恢复时按顺序发送暂存的剪贴板
+    //
+    // 返回恢复时发送的剪贴板数，会话不在房间中时返回 None
+    fn set_session_paused(
+        &mut self,
+        user_id: &str,
+        session_id: &str,
+        paused: bool,
+    ) -> Option<usize> {
+        let entry = self
+            .rooms
+            .get_mut(user_id)
+            .and_then(|sessions| sessions.get_mut(session_id))?;
+        if paused {
+            entry.paused.get_or_insert_with(VecDeque::new);
+            info!(user_id = %user_id, session_id = %session_id, "sync paused");
+            return Some(0);
+        }
+        let held = entry.paused.take().unwrap_or_default();
+        let count = held.len();
+        if let Some(addr) = entry.addr.upgrade() {
+            for message in held {
+                deliver(user_id, session_id, entry, &addr, message);
+            }
+        }
+        info!(
+            user_id = %user_id,
+            session_id = %session_id,
+            delivered = count,
+            "sync resumed",
+        );
+        Some(count)
     }
 
-    // 获取活跃用户数
-    pub fn get_room_user_count(&mut self, user_id: &str) -> usize {
+    // 暂停或恢复设备的同步（包括之后建立的会话），并通知该设备的会话，返回在线的会话数
+    pub fn set_device_paused(&mut self, user_id: &str, device_id: &str, paused: bool) -> usize {
+        let key = (user_id.to_string(), device_id.to_string());
+        if paused {
+            self.paused_devices.insert(key);
+        } else {
+            self.paused_devices.remove(&key);
+        }
         self.cleanup_dead_connections(user_id);
-        
-        self.rooms
+        let session_ids: Vec<String> = self
+            .rooms
             .get(user_id)
-            .map(|sessions| sessions.len())
-            .unwrap_or(0)
+            .into_iter()
+            .flatten()
+            .filter(|(_, entry)| entry.client.device_id.as_deref() == Some(device_id))
+            .map(|(session_id, _)| session_id.clone())
+            .collect();
+        for session_id in &session_ids {
+            let Some(delivered) = self.set_session_paused(user_id, session_id, paused) else {
+                continue;
+            };
+            let event = sync_pause_event(paused, self.paused_sync_policy, delivered, None);
+            if let Some(addr) = self
+                .rooms
+                .get(user_id)
+                .and_then(|sessions| sessions.get(session_id))
+                .and_then(|entry| entry.addr.upgrade())
+            {
+                addr.do_send(event);
+            }
+        }
+        session_ids.len()
     }
 
-    // 调试信息
-    pub fn debug_rooms(&mut self) {
-        println!("=== DEBUG: Room Status ===");
-        
-        // 清理所有房间的死亡连接
-        let user_ids: Vec<String> = self.rooms.keys().cloned().collect();
-        for user_id in user_ids {
-            self.cleanup_dead_connections(&user_id);
+    // 启用会话的确认送达，同一设备之前未确认的消息在新会话中按原序号重发
+    fn enable_acked_delivery(&mut self, user_id: &str, session_id: &str) {
+        let Some(entry) = self
+            .rooms
+            .get_mut(user_id)
+            .and_then(|sessions| sessions.get_mut(session_id))
+        else {
+            return;
+        };
+        if entry.outbox.is_some() {
+            return;
         }
-        
-        if self.rooms.is_empty() {
-            println!("No active rooms");
-        } else {
-            for (user_id, sessions) in &self.rooms {
-                println!("Room '{}': {} active session(s)", user_id, sessions.len());
+        let mut outbox = entry
+            .client
+            .device_id
+            .clone()
+            .and_then(|device_id| {
+                self.detached_outboxes
+                    .remove(&(user_id.to_string(), device_id))
+            })
+            .unwrap_or_default();
+        outbox.detached_at = None;
+        if !outbox.pending.is_empty() {
+            info!(
+                user_id = %user_id,
+                session_id = %session_id,
+                pending = outbox.pending.len(),
+                "redelivering unacknowledged messages",
+            );
+            if let Some(addr) = entry.addr.upgrade() {
+                for delivery in &outbox.pending {
+                    addr.do_send(delivery.clone());
+                }
             }
         }
-        println!("==========================");
+        entry.outbox = Some(outbox);
     }
 
-    // 清理所有房间的死亡连接（定期任务用）
-    pub fn cleanup_all_rooms(&mut self) {
-        let user_ids: Vec<String> = self.rooms.keys().cloned().collect();
-        for user_id in user_ids {
-            self.cleanup_dead_connections(&user_id);
+    // 客户端确认收到 seq 及之前的全部消息
+    pub fn ack_deliveries(&mut self, user_id: &str, session_id: &str, seq: u64) {
+        if let Some(outbox) = self
+            .rooms
+            .get_mut(user_id)
+            .and_then(|sessions| sessions.get_mut(session_id))
+            .and_then(|entry| entry.outbox.as_mut())
+        {
+            let acked = outbox.ack(seq);
+            debug!(
+                user_id = %user_id,
+                session_id = %session_id,
+                seq,
+                acked,
+                "deliveries acknowledged",
+            );
         }
     }
-}
 
-impl Actor for RoomManager {
-    type Context = Context<Self>;
-    
-    fn started(&mut self, ctx: &mut Self::Context) {
-        println!("🚀 RoomManager started");
-        
-        // 定期清理死亡连接（每30秒）
-        ctx.run_interval(Duration::from_secs(30), |act, _| {
-            println!("🧹 Running periodic cleanup...");
-            act.cleanup_all_rooms();
-        });
+    // 转发的目标设备：在剪贴板的可见设备（None 表示不限制）中，发送方属于隔离分组时再限制为同组设备
+    fn relay_targets(
+        &self,
+        user_id: &str,
+        sender_session_id: &str,
+        devices: Option<HashSet<String>>,
+    ) -> Option<HashSet<String>> {
+        match (self.routing_targets(user_id, sender_session_id), devices) {
+            (Some(group), Some(devices)) => Some(&group & &devices),
+            (group, devices) => group.or(devices),
+        }
     }
-}
 
-// ============ 消息定义 ============
+    // 发送方所在隔离分组的全部设备，不属于任何隔离分组时返回 None（不限制）
+    fn routing_targets(&self, user_id: &str, session_id: &str) -> Option<HashSet<String>> {
+        let device_id = self
+            .rooms
+            .get(user_id)?
+            .get(session_id)?
+            .client
+            .device_id
+            .as_ref()?;
+        let groups: Vec<&HashSet<String>> = self
+            .device_routing
+            .get(user_id)?
+            .iter()
+            .filter(|group| group.contains(device_id))
+            .collect();
+        if groups.is_empty() {
+            return None;
+        }
+        Some(groups.into_iter().flatten().cloned().collect())
+    }
 
-#[derive(Message)]
-#[rtype(result = "()")]
-pub struct ClientMessage(pub String);
+    // 更新用户的设备分组路由规则
+    pub fn set_device_routing(&mut self, user_id: &str, isolated_groups: Vec<HashSet<String>>) {
+        if isolated_groups.is_empty() {
+            self.device_routing.remove(user_id);
+        } else {
+            self.device_routing.insert(user_id.to_string(), isolated_groups);
+        }
+    }
 
-#[derive(Message)]
-#[rtype(result = "()")]
-pub struct JoinRoom {
-    pub user_id: String,
-    pub session_id: String,
-    pub addr: Addr<MyWs>,
-}
+    // 更新用户设备的同步规则
+    pub fn set_sync_rules(&mut self, user_id: &str, rules: HashMap<String, SyncRule>) {
+        if rules.is_empty() {
+            self.sync_rules.remove(user_id);
+        } else {
+            self.sync_rules.insert(user_id.to_string(), rules);
+        }
+    }
 
-#[derive(Message)]
-#[rtype(result = "()")]
-pub struct LeaveRoom {
-    pub user_id: String,
-    pub session_id: String,
-}
+    // 推送事件给房间内所有会话，由各会话按协商的能力决定格式
+    pub fn send_event_to_room(&mut self, user_id: &str, event: ServerEvent) -> usize {
+        self.send_event_to_devices(user_id, None, event)
+    }
 
-#[derive(Message)]
-#[rtype(result = "()")]
-pub struct SendToRoom {
-    pub user_id: String,
-    pub message: String,
-    pub sender_session_id: String,
-}
+    // 推送事件给房间内指定设备的会话，devices 为 None 时推送给所有会话
+    //
+    // 返回收到事件的会话数（包括等待中的长轮询请求），剪贴板事件不推送给同步规则排除的设备
+    pub fn send_event_to_devices(
+        &mut self,
+        user_id: &str,
+        devices: Option<HashSet<String>>,
+        event: ServerEvent,
+    ) -> usize {
+        let logged = self.record_event(Some(user_id), devices, &event);
+        self.cleanup_dead_connections(user_id);
+        let rules = self.sync_rules.get(user_id);
+        let clip = clip_summary(&event.event, &event.payload);
+        let mut delivered = 0;
+        if let Some(pollers) = self.pollers.remove(user_id) {
+            // 不可见的长轮询请求继续等待
+            let waiting: Vec<Poller> = pollers
+                .into_iter()
+                .filter_map(|poller| {
+                    let device_id = poller.device_id.as_deref();
+                    if logged.visible_to(device_id) && syncs_clip(rules, device_id, clip) {
+                        if poller.sender.send(vec![logged.clone()]).is_ok() {
+                            delivered += 1;
+                        }
+                        None
+                    } else {
+                        Some(poller)
+                    }
+                })
+                .collect();
+            if !waiting.is_empty() {
+                self.pollers.insert(user_id.to_string(), waiting);
+            }
+        }
 
-#[derive(Message)]
-#[rtype(result = "usize")]
-pub struct GetRoomUserCount {
-    pub user_id: String,
-}
+        let policy = self.paused_sync_policy;
+        let Some(sessions) = self.rooms.get_mut(user_id) else {
+            return delivered;
+        };
+        for entry in sessions.values_mut() {
+            let device_id = entry.client.device_id.as_deref();
+            if !logged.visible_to(device_id) || !syncs_clip(rules, device_id, clip) {
+                continue;
+            }
+            if CLIP_EVENTS.contains(&event.event.as_str())
+                && let Some(held) = &mut entry.paused
+            {
+                hold_while_paused(policy, held, Outbound::Event(event.clone()));
+                continue;
+            }
+            if let Some(addr) = entry.addr.upgrade() {
+                addr.do_send(event.clone());
+                delivered += 1;
+            }
+        }
+        delivered
+    }
 
-#[derive(Message)]
-#[rtype(result = "()")]
-pub struct DebugRooms;
+    // 记录事件并分配序号，user_id 为 None 时记入全局广播日志
+    fn record_event(
+        &mut self,
+        user_id: Option<&str>,
+        devices: Option<HashSet<String>>,
+        event: &ServerEvent,
+    ) -> SequencedEvent {
+        record_rate(&mut self.event_times);
+        self.last_seq += 1;
+        let logged = SequencedEvent {
+            seq: self.last_seq,
+            event: event.event.clone(),
+            payload: event.payload.clone(),
+            created_at: chrono::Utc::now().timestamp(),
+            devices,
+        };
+        let log = match user_id {
+            Some(user_id) => self.event_log.entry(user_id.to_string()).or_default(),
+            None => &mut self.broadcast_log,
+        };
+        log.push_back(logged.clone());
+        if log.len() > MAX_LOGGED_EVENTS {
+            log.pop_front();
+        }
+        logged
+    }
 
-// ============ Handler 实现 ============
+    // 长轮询：返回 since 之后该设备可见的事件，没有新事件时登记等待
+    pub fn poll_events(
+        &mut self,
+        user_id: &str,
+        device_id: Option<String>,
+        since: Option<u64>,
+    ) -> PollResult {
+        // 未提供游标或游标超前（服务器重启后序号重置）时返回当前游标
+        let since = match since {
+            Some(since) if since <= self.last_seq => since,
+            _ => {
+                return PollResult::Ready {
+                    events: Vec::new(),
+                    cursor: self.last_seq,
+                };
+            }
+        };
 
-impl Handler<JoinRoom> for RoomManager {
-    type Result = ();
+        let rules = self.sync_rules.get(user_id);
+        let mut events: Vec<SequencedEvent> = self
+            .event_log
+            .get(user_id)
+            .into_iter()
+            .flatten()
+            .chain(self.broadcast_log.iter())
+            .filter(|event| {
+                event.seq > since
+                    && event.visible_to(device_id.as_deref())
+                    && syncs_clip(
+                        rules,
+                        device_id.as_deref(),
+                        clip_summary(&event.event, &event.payload),
+                    )
+            })
+            .cloned()
+            .collect();
+        if !events.is_empty() {
+            events.sort_by_key(|event| event.seq);
+            let cursor = events.last().map(|event| event.seq).unwrap_or(since);
+            return PollResult::Ready { events, cursor };
+        }
 
-    fn handle(&mut self, msg: JoinRoom, _: &mut Context<Self>) -> Self::Result {
-        self.join_room(&msg.user_id, msg.session_id, msg.addr);
+        let (tx, rx) = oneshot::channel();
+        let pollers = self.pollers.entry(user_id.to_string()).or_default();
+        pollers.retain(|poller| !poller.sender.is_closed());
+        pollers.push(Poller {
+            device_id,
+            sender: tx,
+        });
+        PollResult::Wait(rx)
+    }
+
+    // 清理过期的事件日志和已超时的长轮询请求
+    fn cleanup_event_logs(&mut self) {
+        let expired_before = chrono::Utc::now().timestamp() - EVENT_LOG_TTL;
+        for log in self
+            .event_log
+            .values_mut()
+            .chain(std::iter::once(&mut self.broadcast_log))
+        {
+            while log.front().is_some_and(|event| event.created_at < expired_before) {
+                log.pop_front();
+            }
+        }
+        self.event_log.retain(|_, log| !log.is_empty());
+        self.pollers.retain(|_, pollers| {
+            pollers.retain(|poller| !poller.sender.is_closed());
+            !pollers.is_empty()
+        });
+        let detached_before = chrono::Utc::now().timestamp() - PENDING_DELIVERY_TTL;
+        self.detached_outboxes.retain(|_, outbox| {
+            outbox
+                .detached_at
+                .is_some_and(|detached_at| detached_at >= detached_before)
+        });
+    }
+
+    // 清除用户在内存中的全部数据并断开其会话，返回断开的会话数
+    pub fn purge_user(&mut self, user_id: &str) -> usize {
+        let sessions = self.rooms.remove(user_id).unwrap_or_default();
+        for entry in sessions.values() {
+            if let Some(addr) = entry.addr.upgrade() {
+                addr.do_send(Disconnect {
+                    reason: "account purged".to_string(),
+                });
+            }
+        }
+        self.event_log.remove(user_id);
+        self.pollers.remove(user_id);
+        self.device_routing.remove(user_id);
+        self.sync_rules.remove(user_id);
+        self.paused_devices.retain(|(owner, _)| owner != user_id);
+        self.detached_outboxes
+            .retain(|(owner, _), _| owner != user_id);
+        sessions.len()
+    }
+
+    // 断开用户指定设备的全部会话（设备被注销），返回断开的会话数
+    pub fn disconnect_device(&mut self, user_id: &str, device_id: &str) -> usize {
+        self.cleanup_dead_connections(user_id);
+        let Some(sessions) = self.rooms.get(user_id) else {
+            return 0;
+        };
+        let mut disconnected = 0;
+        for entry in sessions.values() {
+            if entry.client.device_id.as_deref() == Some(device_id)
+                && let Some(addr) = entry.addr.upgrade()
+            {
+                addr.do_send(Disconnect {
+                    reason: "device revoked".to_string(),
+                });
+                disconnected += 1;
+            }
+        }
+        disconnected
+    }
+
+    // 断开用户的指定会话（session_id 为空时断开全部会话），返回断开的会话数
+    pub fn disconnect_sessions(
+        &mut self,
+        user_id: &str,
+        session_id: Option<&str>,
+        reason: &str,
+    ) -> usize {
+        self.cleanup_dead_connections(user_id);
+        let Some(sessions) = self.rooms.get(user_id) else {
+            return 0;
+        };
+        let mut disconnected = 0;
+        for (sid, entry) in sessions {
+            if session_id.is_none_or(|session_id| session_id == sid)
+                && let Some(addr) = entry.addr.upgrade()
+            {
+                addr.do_send(Disconnect {
+                    reason: reason.to_string(),
+                });
+                disconnected += 1;
+            }
+        }
+        disconnected
+    }
+
+    // 断开通过指定登录会话的令牌建立的全部会话（登录会话被注销时），返回断开的会话数
+    pub fn disconnect_login_session(
+        &mut self,
+        user_id: &str,
+        login_session_id: &str,
+        reason: &str,
+    ) -> usize {
+        self.cleanup_dead_connections(user_id);
+        let Some(sessions) = self.rooms.get(user_id) else {
+            return 0;
+        };
+        let mut disconnected = 0;
+        for entry in sessions.values() {
+            if entry.login_session_id.as_deref() == Some(login_session_id)
+                && let Some(addr) = entry.addr.upgrade()
+            {
+                addr.do_send(Disconnect {
+                    reason: reason.to_string(),
+                });
+                disconnected += 1;
+            }
+        }
+        disconnected
+    }
+
+    // 列出所有房间及其会话数，会话多的在前
+    pub fn get_rooms(&mut self) -> Vec<RoomSummary> {
+        self.cleanup_all_rooms();
+        let mut rooms: Vec<RoomSummary> = self
+            .rooms
+            .iter()
+            .map(|(user_id, sessions)| RoomSummary {
+                user_id: user_id.clone(),
+                sessions: sessions.len(),
+                connected_at: sessions
+                    .values()
+                    .map(|entry| entry.connected_at)
+                    .min()
+                    .unwrap_or_default(),
+            })
+            .collect();
+        rooms.sort_by(|a, b| b.sessions.cmp(&a.sessions).then(a.user_id.cmp(&b.user_id)));
+        rooms
+    }
+
+    // 服务器停机：以 `server.shutdown` 关闭帧断开所有会话，并结束等待中的长轮询请求
+    //
+    // 返回通知的会话数
+    pub fn shutdown(&mut self, reconnect_after_secs: u64) -> usize {
+        self.shutting_down = true;
+        // 丢弃发送端后长轮询请求立即返回空结果，游标不变
+        self.pollers.clear();
+        self.cleanup_all_rooms();
+        let mut notified = 0;
+        for sessions in self.rooms.values() {
+            for entry in sessions.values() {
+                if let Some(addr) = entry.addr.upgrade() {
+                    addr.do_send(ServerShutdown {
+                        reconnect_after_secs,
+                    });
+                    notified += 1;
+                }
+            }
+        }
+        notified
+    }
+
+    // 从事件日志中移除与指定剪贴板有关的事件，返回移除的事件数
+    pub fn purge_clip(&mut self, clip_id: &str) -> usize {
+        let mut removed = 0;
+        for log in self
+            .event_log
+            .values_mut()
+            .chain(std::iter::once(&mut self.broadcast_log))
+        {
+            let before = log.len();
+            log.retain(|event| event.payload.get("id").and_then(|id| id.as_str()) != Some(clip_id));
+            removed += before - log.len();
+        }
+        // 待确认的消息同样移除
+        for outbox in self
+            .rooms
+            .values_mut()
+            .flat_map(|sessions| sessions.values_mut())
+            .filter_map(|entry| entry.outbox.as_mut())
+            .chain(self.detached_outboxes.values_mut())
+        {
+            let before = outbox.pending.len();
+            outbox
+                .pending
+                .retain(|delivery| delivery.message.clip_id() != Some(clip_id));
+            removed += before - outbox.pending.len();
+        }
+        removed
+    }
+
+    // 获取用户的所有活跃会话
+    pub fn get_presence(&mut self, user_id: &str) -> Vec<Presence> {
+        self.cleanup_dead_connections(user_id);
+
+        let mut presence: Vec<Presence> = self
+            .rooms
+            .get(user_id)
+            .map(|sessions| {
+                sessions
+                    .iter()
+                    .map(|(session_id, entry)| entry.presence(session_id))
+                    .collect()
+            })
+            .unwrap_or_default();
+        presence.sort_by_key(|presence| presence.connected_at);
+        presence
+    }
+
+    pub fn get_sessions(&mut self, user_id: &str) -> Vec<SessionInfo> {
+        self.cleanup_dead_connections(user_id);
+
+        self.rooms
+            .get(user_id)
+            .map(|sessions| {
+                sessions
+                    .iter()
+                    .map(|(session_id, entry)| SessionInfo {
+                        session_id: session_id.clone(),
+                        client: entry.client.clone(),
+                        login_session_id: entry.login_session_id.clone(),
+                        connected_at: entry.connected_at,
+                        last_heartbeat_at: entry.last_heartbeat_at,
+                        rtt_ms: entry.rtt_ms,
+                        pending_deliveries: entry
+                            .outbox
+                            .as_ref()
+                            .map(|outbox| outbox.pending.len()),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    // 更新会话的客户端元数据，协商了 acked_delivery 时启用确认送达
+    pub fn update_client_info(
+        &mut self,
+        user_id: &str,
+        session_id: &str,
+        client: ClientInfo,
+        acked_delivery: bool,
+    ) {
+        if let Some(entry) = self
+            .rooms
+            .get_mut(user_id)
+            .and_then(|sessions| sessions.get_mut(session_id))
+        {
+            info!(
+                user_id = %user_id,
+                session_id = %session_id,
+                device = %client.label(),
+                "session identified",
+            );
+            entry.client = client;
+            let presence = entry.presence(session_id);
+            self.notify_presence(user_id, "presence.updated", &presence);
+        }
+        if acked_delivery {
+            self.enable_acked_delivery(user_id, session_id);
+        }
+    }
+
+    // 更新会话的往返延迟（收到心跳响应时）
+    pub fn update_latency(&mut self, user_id: &str, session_id: &str, rtt_ms: u64) {
+        if let Some(entry) = self
+            .rooms
+            .get_mut(user_id)
+            .and_then(|sessions| sessions.get_mut(session_id))
+        {
+            entry.rtt_ms = Some(rtt_ms);
+            entry.last_heartbeat_at = chrono::Utc::now().timestamp();
+            if let Some(device_id) = &entry.client.device_id {
+                self.device_last_seen.insert(
+                    (user_id.to_string(), device_id.clone()),
+                    entry.last_heartbeat_at,
+                );
+            }
+        }
+    }
+
+    // 取出尚未写入数据库的设备在线时间
+    pub fn take_device_last_seen(&mut self) -> Vec<DeviceLastSeen> {
+        self.device_last_seen
+            .drain()
+            .map(|((user_id, device_id), last_seen_at)| DeviceLastSeen {
+                user_id,
+                device_id,
+                last_seen_at,
+            })
+            .collect()
+    }
+
+    // 获取活跃用户数
+    pub fn get_room_user_count(&mut self, user_id: &str) -> usize {
+        self.cleanup_dead_connections(user_id);
+        
+        self.rooms
+            .get(user_id)
+            .map(|sessions| sessions.len())
+            .unwrap_or(0)
+    }
+
+    // 调试信息
+    pub fn debug_rooms(&mut self) {
+        
+        // 清理所有房间的死亡连接
+        let user_ids: Vec<String> = self.rooms.keys().cloned().collect();
+        for user_id in user_ids {
+            self.cleanup_dead_connections(&user_id);
+        }
+        
+        if self.rooms.is_empty() {
+            info!("no active rooms");
+        }
+        for (user_id, sessions) in &self.rooms {
+            info!(user_id = %user_id, sessions = sessions.len(), "room status");
+        }
+    }
+
+    // 推送事件给所有房间的所有会话
+    pub fn send_event_to_all(&mut self, event: ServerEvent) {
+        let logged = self.record_event(None, None, &event);
+        for (_, pollers) in self.pollers.drain() {
+            for poller in pollers {
+                let _ = poller.sender.send(vec![logged.clone()]);
+            }
+        }
+
+        self.cleanup_all_rooms();
+
+        for sessions in self.rooms.values() {
+            for entry in sessions.values() {
+                if let Some(addr) = entry.addr.upgrade() {
+                    addr.do_send(event.clone());
+                }
+            }
+        }
+    }
+
+    // 实时统计
+    pub fn stats(&mut self) -> RoomStats {
+        self.cleanup_all_rooms();
+        trim_rate(&mut self.message_times);
+        trim_rate(&mut self.event_times);
+        let rtts: Vec<u64> = self
+            .rooms
+            .values()
+            .flat_map(|sessions| sessions.values())
+            .filter_map(|entry| entry.rtt_ms)
+            .collect();
+        RoomStats {
+            rooms: self.rooms.len(),
+            sessions: self.rooms.values().map(|sessions| sessions.len()).sum(),
+            messages_per_minute: self.message_times.len(),
+            events_per_minute: self.event_times.len(),
+            waiting_pollers: self.pollers.values().map(|pollers| pollers.len()).sum(),
+            logged_events: self.broadcast_log.len()
+                + self.event_log.values().map(|log| log.len()).sum::<usize>(),
+            avg_rtt_ms: (!rtts.is_empty()).then(|| rtts.iter().sum::<u64>() / rtts.len() as u64),
+            max_rtt_ms: rtts.iter().max().copied(),
+        }
+    }
+
+    // 清理所有房间的死亡连接（定期任务用）
+    pub fn cleanup_all_rooms(&mut self) {
+        let user_ids: Vec<String> = self.rooms.keys().cloned().collect();
+        for user_id in user_ids {
+            self.cleanup_dead_connections(&user_id);
+        }
+    }
+}
+
+// 记录一次发生时间并移除窗口外的记录
+fn record_rate(times: &mut VecDeque<Instant>) {
+    trim_rate(times);
+    times.push_back(Instant::now());
+}
+
+fn trim_rate(times: &mut VecDeque<Instant>) {
+    while times
+        .front()
+        .is_some_and(|time| time.elapsed() > RATE_WINDOW)
+    {
+        times.pop_front();
+    }
+}
+
+impl Actor for RoomManager {
+    type Context = Context<Self>;
+    
+    fn started(&mut self, ctx: &mut Self::Context) {
+        info!("RoomManager started");
+        
+        // 定期清理死亡连接
+        ctx.run_interval(self.cleanup_interval, |act, _| {
+            debug!("running periodic cleanup");
+            act.cleanup_all_rooms();
+            act.cleanup_event_logs();
+        });
+    }
+}
+
+// ============ 消息定义 ============
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ClientMessage(pub String);
+
+/// 发往单个会话的二进制帧
+#[derive(Message, Clone)]
+#[rtype(result = "()")]
+pub struct ClientBinary(pub Bytes);
+
+/// 需要客户端确认的消息，seq 在会话内（同一设备重连后）递增
+#[derive(Message, Clone)]
+#[rtype(result = "()")]
+pub struct Delivery {
+    pub seq: u64,
+    pub message: Outbound,
+}
+
+/// 转发给其他会话的剪贴板
+#[derive(Clone)]
+pub enum Outbound {
+    Event(ServerEvent),
+    Binary {
+        clip_id: String,
+        content_type: ClipType,
+        size: i64,
+        frame: Bytes,
+    },
+}
+
+impl Outbound {
+    fn clip_id(&self) -> Option<&str> {
+        match self {
+            Outbound::Event(event) => event.payload.get("id").and_then(|id| id.as_str()),
+            Outbound::Binary { clip_id, .. } => Some(clip_id),
+        }
+    }
+
+    fn is_clip(&self) -> bool {
+        match self {
+            Outbound::Event(event) => CLIP_EVENTS.contains(&event.event.as_str()),
+            Outbound::Binary { .. } => true,
+        }
+    }
+
+    fn clip_summary(&self) -> Option<(ClipType, i64)> {
+        match self {
+            Outbound::Event(event) => clip_summary(&event.event, &event.payload),
+            Outbound::Binary {
+                content_type, size, ..
+            } => Some((*content_type, *size)),
+        }
+    }
+}
+
+/// 携带剪贴板的事件，推送时按设备的同步规则过滤
+const CLIP_EVENTS: &[&str] = &["clip.push", "clip_created", "clip_updated"];
+
+// 剪贴板事件中剪贴板的类型和大小，其他事件返回 None
+fn clip_summary(event: &str, payload: &serde_json::Value) -> Option<(ClipType, i64)> {
+    if !CLIP_EVENTS.contains(&event) {
+        return None;
+    }
+    let content_type = serde_json::from_value(payload.get("content_type")?.clone()).ok()?;
+    Some((content_type, payload.get("size")?.as_i64()?))
+}
+
+// 发送给会话：协商了 acked_delivery 的会话分配序号并保留到客户端确认
+fn deliver(
+    user_id: &str,
+    session_id: &str,
+    entry: &mut SessionEntry,
+    addr: &Addr<MyWs>,
+    message: Outbound,
+) {
+    match (&mut entry.outbox, message) {
+        (Some(outbox), message) => {
+            if outbox.pending.len() >= MAX_PENDING_DELIVERIES {
+                warn!(
+                    user_id = %user_id,
+                    session_id = %session_id,
+                    "outbound queue is full, dropping the oldest delivery",
+                );
+            }
+            addr.do_send(outbox.push(message));
+        }
+        (None, Outbound::Event(event)) => addr.do_send(event),
+        (None, Outbound::Binary { frame, .. }) => addr.do_send(ClientBinary(frame)),
+    }
+}
+
+// 暂停同步的会话收到剪贴板时按策略暂存或丢弃，暂存数量超过上限时丢弃最早的
+fn hold_while_paused(policy: PausedSyncPolicy, held: &mut VecDeque<Outbound>, message: Outbound) {
+    if policy == PausedSyncPolicy::Drop {
+        return;
+    }
+    if held.len() >= MAX_PENDING_DELIVERIES {
+        held.pop_front();
+    }
+    held.push_back(message);
+}
+
+// sync.paused / sync.resumed 事件，ref_id 为客户端 sync.pause / sync.resume 消息的 id
+fn sync_pause_event(
+    paused: bool,
+    policy: PausedSyncPolicy,
+    delivered: usize,
+    ref_id: Option<String>,
+) -> ServerEvent {
+    if paused {
+        ServerEvent {
+            event: "sync.paused".to_string(),
+            payload: serde_json::json!({ "ref_id": ref_id, "policy": policy }),
+        }
+    } else {
+        ServerEvent {
+            event: "sync.resumed".to_string(),
+            payload: serde_json::json!({ "ref_id": ref_id, "delivered": delivered }),
+        }
+    }
+}
+
+// 设备是否同步该剪贴板，没有同步规则或不是剪贴板时总是同步
+fn syncs_clip(
+    rules: Option<&HashMap<String, SyncRule>>,
+    device_id: Option<&str>,
+    clip: Option<(ClipType, i64)>,
+) -> bool {
+    let rule = rules
+        .zip(device_id)
+        .and_then(|(rules, device_id)| rules.get(device_id));
+    match (rule, clip) {
+        (Some(rule), Some((content_type, size))) => rule.allows(content_type, size),
+        _ => true,
+    }
+}
+
+/// 发往单个会话的结构化事件
+#[derive(Message, Clone)]
+#[rtype(result = "()")]
+pub struct ServerEvent {
+    pub event: String,
+    pub payload: serde_json::Value,
+}
+
+/// 会话加入房间，返回加入后房间内的会话数
+#[derive(Message)]
+#[rtype(result = "Result<usize, RoomError>")]
+pub struct JoinRoom {
+    pub user_id: String,
+    pub session_id: String,
+    pub addr: Addr<MyWs>,
+    pub client: ClientInfo,
+    pub login_session_id: Option<String>,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct LeaveRoom {
+    pub user_id: String,
+    pub session_id: String,
+}
+
+/// 广播文本消息给房间内的其他会话，返回送达的会话数
+#[derive(Message)]
+#[rtype(result = "Result<usize, RoomError>")]
+pub struct SendToRoom {
+    pub user_id: String,
+    pub message: String,
+    pub sender_session_id: String,
+}
+
+/// 转发客户端的协议消息给房间内的其他会话，返回送达的会话数
+#[derive(Message)]
+#[rtype(result = "usize")]
+pub struct RelayToRoom {
+    pub user_id: String,
+    pub sender_session_id: String,
+    /// 仅转发给这些设备，None 表示不限制
+    pub devices: Option<HashSet<String>>,
+    pub event: String,
+    pub payload: serde_json::Value,
+}
+
+/// 转发客户端的协议消息给房间内的指定会话，返回送达的会话数（0 或 1）
+///
+/// 与 RelayToRoom 相同，目标会话不在设备分组路由和 devices 范围内时不转发
+#[derive(Message)]
+#[rtype(result = "usize")]
+pub struct SendToSession {
+    pub user_id: String,
+    pub sender_session_id: String,
+    pub to_session: String,
+    pub devices: Option<HashSet<String>>,
+    pub event: String,
+    pub payload: serde_json::Value,
+}
+
+/// 转发二进制剪贴板帧给房间内的其他会话，返回送达的会话数
+#[derive(Message)]
+#[rtype(result = "usize")]
+pub struct RelayBinaryToRoom {
+    pub user_id: String,
+    pub sender_session_id: String,
+    pub devices: Option<HashSet<String>>,
+    pub clip_id: String,
+    pub content_type: ClipType,
+    pub size: i64,
+    pub frame: Bytes,
+}
+
+#[derive(Message)]
+#[rtype(result = "usize")]
+pub struct GetRoomUserCount {
+    pub user_id: String,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct DebugRooms;
+
+#[derive(Message)]
+#[rtype(result = "Vec<SessionInfo>")]
+pub struct GetSessions {
+    pub user_id: String,
+}
+
+/// 查询用户的在线会话（按连接时间排序）
+#[derive(Message)]
+#[rtype(result = "Vec<Presence>")]
+pub struct GetPresence {
+    pub user_id: String,
+}
+
+#[derive(Message)]
+#[rtype(result = "RoomStats")]
+pub struct GetStats;
+
+/// 列出所有房间及其会话数（管理接口使用）
+#[derive(Message)]
+#[rtype(result = "Vec<RoomSummary>")]
+pub struct GetRooms;
+
+/// 清除用户的内存数据，返回断开的会话数
+#[derive(Message)]
+#[rtype(result = "usize")]
+pub struct PurgeUser {
+    pub user_id: String,
+}
+
+/// 清除剪贴板相关的缓存事件，返回移除的事件数
+#[derive(Message)]
+#[rtype(result = "usize")]
+pub struct PurgeClip {
+    pub clip_id: String,
+}
+
+/// 断开用户指定设备的全部会话，返回断开的会话数
+#[derive(Message)]
+#[rtype(result = "usize")]
+pub struct DisconnectDevice {
+    pub user_id: String,
+    pub device_id: String,
+}
+
+/// 断开用户的指定会话（session_id 为空时断开全部会话），返回断开的会话数
+#[derive(Message)]
+#[rtype(result = "usize")]
+pub struct DisconnectSessions {
+    pub user_id: String,
+    pub session_id: Option<String>,
+    pub reason: String,
+}
+
+/// 断开通过指定登录会话的令牌建立的全部会话，返回断开的会话数
+#[derive(Message)]
+#[rtype(result = "usize")]
+pub struct DisconnectLoginSession {
+    pub user_id: String,
+    pub login_session_id: String,
+    pub reason: String,
+}
+
+/// 服务端主动关闭会话
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Disconnect {
+    pub reason: String,
+}
+
+/// 服务器停机，通知所有会话并结束长轮询，返回通知的会话数
+#[derive(Message)]
+#[rtype(result = "usize")]
+pub struct Shutdown {
+    pub reconnect_after_secs: u64,
+}
+
+/// 服务器停机，会话发送 `server.shutdown` 关闭帧后断开
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ServerShutdown {
+    pub reconnect_after_secs: u64,
+}
+
+/// 取出心跳和会话离开时记录的设备在线时间，由调用方批量写入数据库
+#[derive(Message)]
+#[rtype(result = "Vec<DeviceLastSeen>")]
+pub struct TakeDeviceLastSeen;
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct UpdateLatency {
+    pub user_id: String,
+    pub session_id: String,
+    pub rtt_ms: u64,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct UpdateClientInfo {
+    pub user_id: String,
+    pub session_id: String,
+    pub client: ClientInfo,
+    /// 会话协商了 acked_delivery
+    pub acked_delivery: bool,
+}
+
+/// 客户端确认收到 seq 及之前的全部消息
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct AckDeliveries {
+    pub user_id: String,
+    pub session_id: String,
+    pub seq: u64,
+}
+
+/// 会话发送 sync.pause / sync.resume，暂停或恢复本会话的同步，
+/// 处理后向会话发送 sync.paused / sync.resumed 事件，会话不在房间中时返回 false
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct PauseSessionSync {
+    pub user_id: String,
+    pub session_id: String,
+    pub paused: bool,
+    pub ref_id: Option<String>,
+}
+
+/// 暂停或恢复设备的同步（包括之后建立的会话），返回该设备在线的会话数
+#[derive(Message)]
+#[rtype(result = "usize")]
+pub struct PauseDeviceSync {
+    pub user_id: String,
+    pub device_id: String,
+    pub paused: bool,
+}
+
+/// 服务端事件，以 `{"type": event, "payload": payload}` 的 JSON 文本推送给用户房间内所有会话
+///
+/// 返回收到事件的会话数
+#[derive(Message)]
+#[rtype(result = "usize")]
+pub struct PushEvent {
+    pub user_id: String,
+    pub event: String,
+    pub payload: serde_json::Value,
+}
+
+/// 服务端事件，只推送给用户房间内指定设备的会话，返回收到事件的会话数
+#[derive(Message)]
+#[rtype(result = "usize")]
+pub struct PushToDevices {
+    pub user_id: String,
+    pub device_ids: HashSet<String>,
+    pub event: String,
+    pub payload: serde_json::Value,
+}
+
+/// 服务端事件，推送给所有在线会话
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct BroadcastEvent {
+    pub event: String,
+    pub payload: serde_json::Value,
+}
+
+/// 更新用户的设备分组路由规则
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SetDeviceRouting {
+    pub user_id: String,
+    pub isolated_groups: Vec<HashSet<String>>,
+}
+
+/// 更新用户设备的同步规则
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SetSyncRules {
+    pub user_id: String,
+    pub rules: HashMap<String, SyncRule>,
+}
+
+/// 长轮询获取用户房间的新事件
+#[derive(Message)]
+#[rtype(result = "PollResult")]
+pub struct PollEvents {
+    pub user_id: String,
+    pub device_id: Option<String>,
+    pub since: Option<u64>,
+}
+
+// ============ Handler 实现 ============
+
+impl Handler<JoinRoom> for RoomManager {
+    type Result = Result<usize, RoomError>;
+
+    fn handle(&mut self, msg: JoinRoom, _: &mut Context<Self>) -> Self::Result {
+        self.join_room(
+            &msg.user_id,
+            msg.session_id,
+            msg.addr,
+            msg.client,
+            msg.login_session_id,
+        )
+    }
+}
+
+impl Handler<LeaveRoom> for RoomManager {
+    type Result = ();
+
+    fn handle(&mut self, msg: LeaveRoom, _: &mut Context<Self>) -> Self::Result {
+        self.leave_room(&msg.user_id, &msg.session_id);
+    }
+}
+
+impl Handler<SendToRoom> for RoomManager {
+    type Result = Result<usize, RoomError>;
+
+    fn handle(&mut self, msg: SendToRoom, _: &mut Context<Self>) -> Self::Result {
+        self.broadcast_to_room_excluding(&msg.user_id, msg.message, Some(&msg.sender_session_id))
+    }
+}
+
+impl Handler<RelayToRoom> for RoomManager {
+    type Result = usize;
+
+    fn handle(&mut self, msg: RelayToRoom, _: &mut Context<Self>) -> Self::Result {
+        let devices = self.relay_targets(&msg.user_id, &msg.sender_session_id, msg.devices);
+        self.publish(|| ClusterMessage::Relay {
+            user_id: msg.user_id.clone(),
+            sender_session_id: msg.sender_session_id.clone(),
+            devices: devices.clone(),
+            to_session: None,
+            event: msg.event.clone(),
+            payload: msg.payload.clone(),
+        });
+        let event = Outbound::Event(ServerEvent {
+            event: msg.event,
+            payload: msg.payload,
+        });
+        self.relay_to_room(&msg.user_id, &msg.sender_session_id, devices, None, event)
+    }
+}
+
+impl Handler<SendToSession> for RoomManager {
+    type Result = usize;
+
+    fn handle(&mut self, msg: SendToSession, _: &mut Context<Self>) -> Self::Result {
+        // 目标会话不在本节点时交给其他节点转发
+        let local = self
+            .rooms
+            .get(&msg.user_id)
+            .is_some_and(|sessions| sessions.contains_key(&msg.to_session));
+        if !local {
+            self.publish(|| ClusterMessage::Relay {
+                user_id: msg.user_id.clone(),
+                sender_session_id: msg.sender_session_id.clone(),
+                devices: self.relay_targets(&msg.user_id, &msg.sender_session_id, msg.devices),
+                to_session: Some(msg.to_session),
+                event: msg.event,
+                payload: msg.payload,
+            });
+            return 0;
+        }
+        let event = Outbound::Event(ServerEvent {
+            event: msg.event,
+            payload: msg.payload,
+        });
+        self.relay_to_room(
+            &msg.user_id,
+            &msg.sender_session_id,
+            msg.devices,
+            Some(&msg.to_session),
+            event,
+        )
+    }
+}
+
+impl Handler<RelayBinaryToRoom> for RoomManager {
+    type Result = usize;
+
+    fn handle(&mut self, msg: RelayBinaryToRoom, _: &mut Context<Self>) -> Self::Result {
+        let devices = self.relay_targets(&msg.user_id, &msg.sender_session_id, msg.devices);
+        self.publish(|| ClusterMessage::RelayBinary {
+            user_id: msg.user_id.clone(),
+            sender_session_id: msg.sender_session_id.clone(),
+            devices: devices.clone(),
+            clip_id: msg.clip_id.clone(),
+            content_type: msg.content_type,
+            size: msg.size,
+            frame: BASE64.encode(&msg.frame),
+        });
+        let frame = Outbound::Binary {
+            clip_id: msg.clip_id,
+            content_type: msg.content_type,
+            size: msg.size,
+            frame: msg.frame,
+        };
+        self.relay_to_room(&msg.user_id, &msg.sender_session_id, devices, None, frame)
+    }
+}
+
+impl Handler<GetRoomUserCount> for RoomManager {
+    type Result = usize;
+
+    fn handle(&mut self, msg: GetRoomUserCount, _: &mut Context<Self>) -> Self::Result {
+        self.get_room_user_count(&msg.user_id)
+    }
+}
+
+impl Handler<DebugRooms> for RoomManager {
+    type Result = ();
+
+    fn handle(&mut self, _: DebugRooms, _: &mut Context<Self>) -> Self::Result {
+        self.debug_rooms();
+    }
+}
+
+impl Handler<GetSessions> for RoomManager {
+    type Result = MessageResult<GetSessions>;
+
+    fn handle(&mut self, msg: GetSessions, _: &mut Context<Self>) -> Self::Result {
+        MessageResult(self.get_sessions(&msg.user_id))
+    }
+}
+
+impl Handler<GetPresence> for RoomManager {
+    type Result = MessageResult<GetPresence>;
+
+    fn handle(&mut self, msg: GetPresence, _: &mut Context<Self>) -> Self::Result {
+        MessageResult(self.get_presence(&msg.user_id))
+    }
+}
+
+impl Handler<GetStats> for RoomManager {
+    type Result = MessageResult<GetStats>;
+
+    fn handle(&mut self, _: GetStats, _: &mut Context<Self>) -> Self::Result {
+        MessageResult(self.stats())
+    }
+}
+
+impl Handler<GetRooms> for RoomManager {
+    type Result = MessageResult<GetRooms>;
+
+    fn handle(&mut self, _: GetRooms, _: &mut Context<Self>) -> Self::Result {
+        MessageResult(self.get_rooms())
+    }
+}
+
+impl Handler<PurgeUser> for RoomManager {
+    type Result = usize;
+
+    fn handle(&mut self, msg: PurgeUser, _: &mut Context<Self>) -> Self::Result {
+        self.publish(|| ClusterMessage::PurgeUser {
+            user_id: msg.user_id.clone(),
+        });
+        self.purge_user(&msg.user_id)
+    }
+}
+
+impl Handler<PurgeClip> for RoomManager {
+    type Result = usize;
+
+    fn handle(&mut self, msg: PurgeClip, _: &mut Context<Self>) -> Self::Result {
+        self.publish(|| ClusterMessage::PurgeClip {
+            clip_id: msg.clip_id.clone(),
+        });
+        self.purge_clip(&msg.clip_id)
+    }
+}
+
+impl Handler<DisconnectDevice> for RoomManager {
+    type Result = usize;
+
+    fn handle(&mut self, msg: DisconnectDevice, _: &mut Context<Self>) -> Self::Result {
+        self.publish(|| ClusterMessage::DisconnectDevice {
+            user_id: msg.user_id.clone(),
+            device_id: msg.device_id.clone(),
+        });
+        self.disconnect_device(&msg.user_id, &msg.device_id)
+    }
+}
+
+impl Handler<DisconnectSessions> for RoomManager {
+    type Result = usize;
+
+    fn handle(&mut self, msg: DisconnectSessions, _: &mut Context<Self>) -> Self::Result {
+        self.publish(|| ClusterMessage::DisconnectSessions {
+            user_id: msg.user_id.clone(),
+            session_id: msg.session_id.clone(),
+            reason: msg.reason.clone(),
+        });
+        self.disconnect_sessions(&msg.user_id, msg.session_id.as_deref(), &msg.reason)
+    }
+}
+
+impl Handler<DisconnectLoginSession> for RoomManager {
+    type Result = usize;
+
+    fn handle(&mut self, msg: DisconnectLoginSession, _: &mut Context<Self>) -> Self::Result {
+        self.publish(|| ClusterMessage::DisconnectLoginSession {
+            user_id: msg.user_id.clone(),
+            login_session_id: msg.login_session_id.clone(),
+            reason: msg.reason.clone(),
+        });
+        self.disconnect_login_session(&msg.user_id, &msg.login_session_id, &msg.reason)
+    }
+}
+
+impl Handler<Shutdown> for RoomManager {
+    type Result = usize;
+
+    fn handle(&mut self, msg: Shutdown, _: &mut Context<Self>) -> Self::Result {
+        self.shutdown(msg.reconnect_after_secs)
+    }
+}
+
+impl Handler<UpdateLatency> for RoomManager {
+    type Result = ();
+
+    fn handle(&mut self, msg: UpdateLatency, _: &mut Context<Self>) -> Self::Result {
+        self.update_latency(&msg.user_id, &msg.session_id, msg.rtt_ms);
+    }
+}
+
+impl Handler<TakeDeviceLastSeen> for RoomManager {
+    type Result = MessageResult<TakeDeviceLastSeen>;
+
+    fn handle(&mut self, _: TakeDeviceLastSeen, _: &mut Context<Self>) -> Self::Result {
+        MessageResult(self.take_device_last_seen())
+    }
+}
+
+impl Handler<UpdateClientInfo> for RoomManager {
+    type Result = ();
+
+    fn handle(&mut self, msg: UpdateClientInfo, _: &mut Context<Self>) -> Self::Result {
+        self.update_client_info(
+            &msg.user_id,
+            &msg.session_id,
+            msg.client,
+            msg.acked_delivery,
+        );
+    }
+}
+
+impl Handler<AckDeliveries> for RoomManager {
+    type Result = ();
+
+    fn handle(&mut self, msg: AckDeliveries, _: &mut Context<Self>) -> Self::Result {
+        self.ack_deliveries(&msg.user_id, &msg.session_id, msg.seq);
+    }
+}
+
+impl Handler<PauseSessionSync> for RoomManager {
+    type Result = bool;
+
+    fn handle(&mut self, msg: PauseSessionSync, _: &mut Context<Self>) -> Self::Result {
+        let Some(delivered) = self.set_session_paused(&msg.user_id, &msg.session_id, msg.paused)
+        else {
+            return false;
+        };
+        let event = sync_pause_event(msg.paused, self.paused_sync_policy, delivered, msg.ref_id);
+        if let Some(addr) = self
+            .rooms
+            .get(&msg.user_id)
+            .and_then(|sessions| sessions.get(&msg.session_id))
+            .and_then(|entry| entry.addr.upgrade())
+        {
+            addr.do_send(event);
+        }
+        true
+    }
+}
+
+impl Handler<PauseDeviceSync> for RoomManager {
+    type Result = usize;
+
+    fn handle(&mut self, msg: PauseDeviceSync, _: &mut Context<Self>) -> Self::Result {
+        self.publish(|| ClusterMessage::SetDevicePaused {
+            user_id: msg.user_id.clone(),
+            device_id: msg.device_id.clone(),
+            paused: msg.paused,
+        });
+        self.set_device_paused(&msg.user_id, &msg.device_id, msg.paused)
+    }
+}
+
+impl Handler<PushEvent> for RoomManager {
+    type Result = usize;
+
+    fn handle(&mut self, msg: PushEvent, _: &mut Context<Self>) -> Self::Result {
+        self.publish(|| ClusterMessage::Push {
+            user_id: msg.user_id.clone(),
+            devices: None,
+            event: msg.event.clone(),
+            payload: msg.payload.clone(),
+        });
+        let event = ServerEvent {
+            event: msg.event,
+            payload: msg.payload,
+        };
+        self.send_event_to_room(&msg.user_id, event)
+    }
+}
+
+impl Handler<SetDeviceRouting> for RoomManager {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetDeviceRouting, _: &mut Context<Self>) -> Self::Result {
+        self.publish(|| ClusterMessage::SetDeviceRouting {
+            user_id: msg.user_id.clone(),
+            isolated_groups: msg.isolated_groups.clone(),
+        });
+        self.set_device_routing(&msg.user_id, msg.isolated_groups);
+    }
+}
+
+impl Handler<SetSyncRules> for RoomManager {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetSyncRules, _: &mut Context<Self>) -> Self::Result {
+        self.publish(|| ClusterMessage::SetSyncRules {
+            user_id: msg.user_id.clone(),
+            rules: msg.rules.clone(),
+        });
+        self.set_sync_rules(&msg.user_id, msg.rules);
+    }
+}
+
+impl Handler<PollEvents> for RoomManager {
+    type Result = MessageResult<PollEvents>;
+
+    fn handle(&mut self, msg: PollEvents, _: &mut Context<Self>) -> Self::Result {
+        MessageResult(self.poll_events(&msg.user_id, msg.device_id, msg.since))
+    }
+}
+
+impl Handler<PushToDevices> for RoomManager {
+    type Result = usize;
+
+    fn handle(&mut self, msg: PushToDevices, _: &mut Context<Self>) -> Self::Result {
+        self.publish(|| ClusterMessage::Push {
+            user_id: msg.user_id.clone(),
+            devices: Some(msg.device_ids.clone()),
+            event: msg.event.clone(),
+            payload: msg.payload.clone(),
+        });
+        let event = ServerEvent {
+            event: msg.event,
+            payload: msg.payload,
+        };
+        self.send_event_to_devices(&msg.user_id, Some(msg.device_ids), event)
+    }
+}
+
+impl Handler<BroadcastEvent> for RoomManager {
+    type Result = ();
+
+    fn handle(&mut self, msg: BroadcastEvent, _: &mut Context<Self>) -> Self::Result {
+        self.publish(|| ClusterMessage::Broadcast {
+            event: msg.event.clone(),
+            payload: msg.payload.clone(),
+        });
+        let event = ServerEvent {
+            event: msg.event,
+            payload: msg.payload,
+        };
+        self.send_event_to_all(event);
     }
 }
 
-impl Handler<LeaveRoom> for RoomManager {
+impl Handler<RemoteMessage> for RoomManager {
     type Result = ();
 
-    fn handle(&mut self, msg: LeaveRoom, _: &mut Context<Self>) -> Self::Result {
-        self.leave_room(&msg.user_id, &msg.session_id);
+    fn handle(&mut self, msg: RemoteMessage, _: &mut Context<Self>) -> Self::Result {
+        match msg.0 {
+            ClusterMessage::Push {
+                user_id,
+                devices,
+                event,
+                payload,
+            } => {
+                self.send_event_to_devices(&user_id, devices, ServerEvent { event, payload });
+            }
+            ClusterMessage::Relay {
+                user_id,
+                sender_session_id,
+                devices,
+                to_session,
+                event,
+                payload,
+            } => {
+                let event = Outbound::Event(ServerEvent { event, payload });
+                self.relay_to_room(
+                    &user_id,
+                    &sender_session_id,
+                    devices,
+                    to_session.as_deref(),
+                    event,
+                );
+            }
+            ClusterMessage::RelayBinary {
+                user_id,
+                sender_session_id,
+                devices,
+                clip_id,
+                content_type,
+                size,
+                frame,
+            } => {
+                let Ok(frame) = BASE64.decode(frame) else {
+                    warn!(clip_id = %clip_id, "invalid binary frame from another node");
+                    return;
+                };
+                let frame = Outbound::Binary {
+                    clip_id,
+                    content_type,
+                    size,
+                    frame: Bytes::from(frame),
+                };
+                self.relay_to_room(&user_id, &sender_session_id, devices, None, frame);
+            }
+            ClusterMessage::Broadcast { event, payload } => {
+                self.send_event_to_all(ServerEvent { event, payload });
+            }
+            ClusterMessage::SetDeviceRouting {
+                user_id,
+                isolated_groups,
+            } => self.set_device_routing(&user_id, isolated_groups),
+            ClusterMessage::SetSyncRules { user_id, rules } => {
+                self.set_sync_rules(&user_id, rules)
+            }
+            ClusterMessage::DisconnectDevice { user_id, device_id } => {
+                self.disconnect_device(&user_id, &device_id);
+            }
+            ClusterMessage::SetDevicePaused {
+                user_id,
+                device_id,
+                paused,
+            } => {
+                self.set_device_paused(&user_id, &device_id, paused);
+            }
+            ClusterMessage::DisconnectSessions {
+                user_id,
+                session_id,
+                reason,
+            } => {
+                self.disconnect_sessions(&user_id, session_id.as_deref(), &reason);
+            }
+            ClusterMessage::DisconnectLoginSession {
+                user_id,
+                login_session_id,
+                reason,
+            } => {
+                self.disconnect_login_session(&user_id, &login_session_id, &reason);
+            }
+            ClusterMessage::PurgeUser { user_id } => {
+                self.purge_user(&user_id);
+            }
+            ClusterMessage::PurgeClip { clip_id } => {
+                self.purge_clip(&clip_id);
+            }
+        }
     }
 }
 
-impl Handler<SendToRoom> for RoomManager {
-    type Result = ();
+// 客户端控制消息：{"type": ..., "id": ..., "payload": ...}，id 用于在回复和错误帧中引用该消息
+//
+// - auth：握手时未提供令牌的连接发送的第一条消息（{"token": 访问令牌}），认证后才能发送其他消息
+// - hello：客户端元数据和能力协商，回复 welcome
+// - clip.push：保存剪贴板（ClipItem）并推送给其他设备，回复 clip.ack（保存后的 ID、服务器时间和送达的会话数）
+// - direct：与 clip.push 相同，但只推送给 to_session 指定的会话（{"type": "direct", "to_session": ...}）
+// - clip.ack：确认收到剪贴板，以 clip.received 转发给其他设备
+// - ack：协商了 acked_delivery 时确认收到 seq 及之前的全部消息（{"seq": n}），不回复
+// - clip.request_history：按 ClipFilter 查询剪贴板历史，回复 clip.history（附带 next_cursor）
+// - presence：查询在线会话，回复 presence
+// - sync.pause / sync.resume：暂停或恢复本会话的剪贴板同步，回复 sync.paused / sync.resumed
+//   （恢复时附带暂存后发送的剪贴板数）
+#[derive(Deserialize)]
+struct ClientFrame {
+    r#type: String,
+    #[serde(default)]
+    id: Option<String>,
+    /// direct 消息的目标会话
+    #[serde(default)]
+    to_session: Option<String>,
+    #[serde(default)]
+    payload: serde_json::Value,
+}
 
-    fn handle(&mut self, msg: SendToRoom, _: &mut Context<Self>) -> Self::Result {
-        self.broadcast_to_room_excluding(
-            &msg.user_id,
-            msg.message,
-            Some(&msg.sender_session_id),
-        );
-    }
+#[derive(Deserialize)]
+struct HelloPayload {
+    #[serde(flatten)]
+    client: ClientInfo,
+    /// 客户端支持的能力
+    #[serde(default)]
+    capabilities: Vec<String>,
 }
 
-impl Handler<GetRoomUserCount> for RoomManager {
-    type Result = usize;
+/// `clip.ack`：接收方确认收到某个剪贴板
+#[derive(Deserialize)]
+struct ClipAckPayload {
+    clip_id: Uuid,
+}
 
-    fn handle(&mut self, msg: GetRoomUserCount, _: &mut Context<Self>) -> Self::Result {
-        self.get_room_user_count(&msg.user_id)
-    }
+/// `auth`：握手时未提供令牌的连接发送的第一条消息
+#[derive(Deserialize)]
+struct AuthPayload {
+    token: String,
 }
 
-impl Handler<DebugRooms> for RoomManager {
-    type Result = ();
+/// `ack`：确认收到 seq 及之前的全部消息
+#[derive(Deserialize)]
+struct AckPayload {
+    seq: u64,
+}
 
-    fn handle(&mut self, _: DebugRooms, _: &mut Context<Self>) -> Self::Result {
-        self.debug_rooms();
+/// `clip.request_history` 单次返回的默认 / 最大数量
+const DEFAULT_HISTORY_LIMIT: i32 = 20;
+const MAX_HISTORY_LIMIT: i32 = 100;
+
+// 可见范围内的设备，按转发时的分组成员计算，None 表示所有设备
+async fn visible_devices(
+    user_id: &str,
+    visibility: &Visibility,
+    pool: &DbPool,
+) -> Result<Option<HashSet<String>>, sqlx::Error> {
+    if visibility.is_all() {
+        return Ok(None);
     }
+    let groups = db::get_device_groups(user_id, pool).await?;
+    Ok(visibility.device_ids(&groups))
 }
 
 // ============ 心跳检测 ============
 
+/// 平滑延迟超过该值时向客户端发出 latency_warning
+const LATENCY_WARNING_MS: u64 = 1000;
+
+/// 平滑延迟回落到该值以下时发出 latency_recovered（避免在阈值附近反复告警）
+const LATENCY_RECOVERED_MS: u64 = 500;
+
 struct Heartbeat {
     last_heartbeat: Instant,
+    // 超过该时间未收到响应视为连接已断开
+    timeout: Duration,
+    // 最近一次 ping 的序号和发送时间
+    pending_ping: Option<(u64, Instant)>,
+    ping_seq: u64,
+    // 平滑后的往返延迟（毫秒）
+    rtt_ms: Option<u64>,
+    degraded: bool,
+}
+
+/// 一次 pong 带来的延迟状态变化
+enum LatencyChange {
+    Degraded,
+    Recovered,
 }
 
 impl Heartbeat {
-    fn new() -> Self {
+    fn new(timeout: Duration) -> Self {
         Self {
             last_heartbeat: Instant::now(),
+            timeout,
+            pending_ping: None,
+            ping_seq: 0,
+            rtt_ms: None,
+            degraded: false,
         }
     }
 
@@ -305,49 +2399,260 @@ impl Heartbeat {
         self.last_heartbeat = Instant::now();
     }
 
+    // 生成下一个 ping 的负载（序号），记录发送时间
+    fn next_ping(&mut self) -> [u8; 8] {
+        self.ping_seq += 1;
+        self.pending_ping = Some((self.ping_seq, Instant::now()));
+        self.ping_seq.to_be_bytes()
+    }
+
+    // 处理 pong：负载与最近一次 ping 匹配时更新平滑延迟（与 TCP SRTT 相同，新样本权重 1/8）
+    fn pong(&mut self, payload: &[u8]) -> Option<u64> {
+        let (seq, sent_at) = self.pending_ping?;
+        if payload != seq.to_be_bytes() {
+            return None;
+        }
+        self.pending_ping = None;
+        let sample = sent_at.elapsed().as_millis() as u64;
+        let rtt = match self.rtt_ms {
+            Some(rtt) => (rtt * 7 + sample) / 8,
+            None => sample,
+        };
+        self.rtt_ms = Some(rtt);
+        Some(rtt)
+    }
+
+    // 延迟跨过告警阈值时返回状态变化
+    fn latency_change(&mut self) -> Option<LatencyChange> {
+        let rtt = self.rtt_ms?;
+        if !self.degraded && rtt > LATENCY_WARNING_MS {
+            self.degraded = true;
+            Some(LatencyChange::Degraded)
+        } else if self.degraded && rtt < LATENCY_RECOVERED_MS {
+            self.degraded = false;
+            Some(LatencyChange::Recovered)
+        } else {
+            None
+        }
+    }
+
     fn is_alive(&self) -> bool {
-        Instant::now().duration_since(self.last_heartbeat) < Duration::from_secs(30)
+        Instant::now().duration_since(self.last_heartbeat) < self.timeout
     }
 }
 
 // ============ WebSocket Actor ============
 
+/// 会话的认证状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SessionAuth {
+    /// 等待 auth 消息
+    Waiting,
+    /// 已收到 auth 消息，正在校验账号和设备
+    Verifying,
+    Authenticated,
+}
+
 pub struct MyWs {
+    // 未认证时为空
     user_id: String,
+    auth: SessionAuth,
+    auth_timeout: Duration,
+    client: ClientInfo,
+    // 认证所用令牌的登录会话，未认证或旧令牌时为空
+    login_session_id: Option<String>,
+    // 与客户端协商后生效的能力，未发送 hello 的旧客户端为空
+    capabilities: HashSet<String>,
+    // 超过该字节数的文本消息压缩发送（需要协商 compression），0 表示不压缩
+    compression_threshold: usize,
     room_manager: Addr<RoomManager>,
+    content_policy: Arc<ContentPolicy>,
+    pool: DbPool,
+    // 按用户限制消息频率，同一用户的所有会话共用
+    rate_limiter: RateLimiter,
     heartbeat: Heartbeat,
+    heartbeat_interval: Duration,
     session_id: String,
+    // 会话的日志 span（user_id、session_id、设备），父级为建立连接的请求的 span
+    span: Span,
 }
 
 impl MyWs {
-    pub fn new(user_id: String, room_manager: Addr<RoomManager>) -> Self {
+    // bearer_token 为 None 表示握手时未提供令牌，需要在连接后发送 auth 消息
+    pub fn new(
+        bearer_token: Option<BearerToken>,
+        client: ClientInfo,
+        room_manager: Addr<RoomManager>,
+        content_policy: Arc<ContentPolicy>,
+        pool: DbPool,
+        rate_limiter: RateLimiter,
+        config: &SpatialConfig,
+    ) -> Self {
+        let session_id = Uuid::new_v4().to_string();
+        let span = info_span!(
+            "ws_session",
+            user_id = tracing::field::Empty,
+            session_id = %session_id,
+            device = %client.label(),
+        );
+        let auth = match &bearer_token {
+            Some(bearer_token) => {
+                span.record("user_id", tracing::field::display(&bearer_token.user_id));
+                SessionAuth::Authenticated
+            }
+            None => SessionAuth::Waiting,
+        };
+        let (user_id, login_session_id) = match bearer_token {
+            Some(bearer_token) => (bearer_token.user_id, bearer_token.session_id),
+            None => (String::new(), None),
+        };
         Self {
             user_id,
+            auth,
+            auth_timeout: config.auth_timeout(),
+            client,
+            login_session_id,
+            capabilities: HashSet::new(),
+            compression_threshold: config.compression_threshold,
             room_manager,
-            heartbeat: Heartbeat::new(),
-            session_id: Uuid::new_v4().to_string(),
+            content_policy,
+            pool,
+            rate_limiter,
+            heartbeat: Heartbeat::new(config.client_timeout()),
+            heartbeat_interval: config.heartbeat_interval(),
+            session_id,
+            span,
         }
     }
 
+    // 加入房间，成功后发送欢迎消息；房间管理器拒绝时发送 error 帧并关闭连接
     fn join_room(&self, ctx: &mut ws::WebsocketContext<Self>) {
-        let addr = ctx.address();
-
-        self.room_manager.do_send(JoinRoom {
+        let joined = self.room_manager.send(JoinRoom {
             user_id: self.user_id.clone(),
             session_id: self.session_id.clone(),
-            addr,
+            addr: ctx.address(),
+            client: self.client.clone(),
+            login_session_id: self.login_session_id.clone(),
         });
+        ctx.spawn(joined.into_actor(self).map(|result, act, ctx| {
+            let _span = act.span.clone().entered();
+            match result {
+                Ok(Ok(_)) => {
+                    let welcome_msg = format!(
+                        "🚀 WELCOME: Connected as user {}\n\
+                        Session ID: {}\n\
+                        \n\
+                        📝 Commands: HELP | DEBUG | TEST\n\
+                        💬 Type any message to broadcast to your room.",
+                        act.user_id,
+                        &act.session_id[..8]
+                    );
+                    ctx.text(welcome_msg);
+                }
+                Ok(Err(e)) => act.close_with_room_error(ctx, e),
+                Err(_) => {
+                    warn!("room manager unavailable, closing session");
+                    act.send_error(ctx, ErrorCode::InternalError, None, serde_json::Value::Null);
+                    ctx.stop();
+                }
+            }
+        }));
+    }
+
+    // 无法加入房间：发送 error 帧（detail.reason 为失败原因）后关闭连接
+    fn close_with_room_error(&self, ctx: &mut ws::WebsocketContext<Self>, error: RoomError) {
+        info!(reason = %error, "failed to join room");
+        self.send_error(
+            ctx,
+            error.code(),
+            None,
+            serde_json::json!({ "reason": error }),
+        );
+        let code = match error {
+            RoomError::ShuttingDown => ws::CloseCode::Restart,
+            _ => ws::CloseCode::Policy,
+        };
+        ctx.close(Some(ws::CloseReason {
+            code,
+            description: Some(error.to_string()),
+        }));
+        ctx.stop();
+    }
 
-        let welcome_msg = format!(
-            "🚀 WELCOME: Connected as user {}\n\
-            Session ID: {}\n\
-            \n\
-            📝 Commands: HELP | DEBUG | TEST\n\
-            💬 Type any message to broadcast to your room.",
-            self.user_id,
-            &self.session_id[..8]
+    // 加入房间并补发离线期间到期的定时投递（在加入房间之后处理）
+    fn start_session(&self, ctx: &mut ws::WebsocketContext<Self>) {
+        self.join_room(ctx);
+        ctx.spawn(
+            deliver_pending_for_user(
+                self.user_id.clone(),
+                self.pool.clone(),
+                self.room_manager.clone(),
+            )
+            .instrument(self.span.clone())
+            .into_actor(self),
+        );
+    }
+
+    // 未认证的会话只接受 auth 消息：校验令牌、账号和设备后加入房间，失败时关闭连接
+    fn handle_auth(&mut self, message: &str, ctx: &mut ws::WebsocketContext<Self>) {
+        // 正在校验时忽略其他消息
+        if self.auth != SessionAuth::Waiting {
+            return;
+        }
+        let Some(auth) = serde_json::from_str::<ClientFrame>(message)
+            .ok()
+            .filter(|frame| frame.r#type == "auth")
+            .and_then(|frame| serde_json::from_value::<AuthPayload>(frame.payload).ok())
+        else {
+            self.reject_session(ctx, ErrorCode::AuthMissing, "需要先发送 auth 消息");
+            return;
+        };
+        let bearer_token = match bearer_from_access_token(&auth.token) {
+            Ok(bearer_token) => bearer_token,
+            Err(code) => {
+                self.reject_session(ctx, code, code.description());
+                return;
+            }
+        };
+
+        self.auth = SessionAuth::Verifying;
+        let client = self.client.clone();
+        let pool = self.pool.clone();
+        let room_manager = self.room_manager.clone();
+        ctx.spawn(
+            async move {
+                let result = admit_session(&bearer_token, &client, &pool, &room_manager).await;
+                (bearer_token, result)
+            }
+            .instrument(self.span.clone())
+            .into_actor(self)
+            .map(|(bearer_token, result), act, ctx| {
+                let _span = act.span.clone().entered();
+                match result {
+                    Ok(()) => {
+                        act.span
+                            .record("user_id", tracing::field::display(&bearer_token.user_id));
+                        act.user_id = bearer_token.user_id;
+                        act.login_session_id = bearer_token.session_id;
+                        act.auth = SessionAuth::Authenticated;
+                        info!("WebSocket authenticated");
+                        act.start_session(ctx);
+                    }
+                    Err(e) => act.reject_session(ctx, e.code, e.message),
+                }
+            }),
         );
-        ctx.text(welcome_msg);
+    }
+
+    // 认证失败或超时：发送 error 帧后关闭连接
+    fn reject_session(&self, ctx: &mut ws::WebsocketContext<Self>, code: ErrorCode, reason: &str) {
+        info!(code = ?code, "WebSocket authentication failed");
+        self.send_error(ctx, code, None, serde_json::json!({ "reason": reason }));
+        ctx.close(Some(ws::CloseReason {
+            code: ws::CloseCode::Policy,
+            description: Some("authentication failed".to_string()),
+        }));
+        ctx.stop();
     }
 
     fn leave_room(&self) {
@@ -357,69 +2662,731 @@ impl MyWs {
         });
     }
 
-    fn send_to_room(&self, message: String) {
-        self.room_manager.do_send(SendToRoom {
+    // 发送文本消息，协商了 compression 的会话超过阈值时改为发送压缩后的二进制帧
+    fn send_text(&self, ctx: &mut ws::WebsocketContext<Self>, text: String) {
+        if self.compression_threshold > 0
+            && text.len() > self.compression_threshold
+            && self.capabilities.contains("compression")
+            && let Some(frame) = binary::compress_text(&text)
+        {
+            ctx.binary(frame);
+        } else {
+            ctx.text(text);
+        }
+    }
+
+    // 按协商的能力发送事件：支持 json_events 的客户端收到 JSON，旧客户端收到文本
+    fn send_event(
+        &self,
+        ctx: &mut ws::WebsocketContext<Self>,
+        event: &str,
+        payload: serde_json::Value,
+    ) {
+        if self.capabilities.contains("json_events") {
+            let frame = serde_json::json!({
+                "type": event,
+                "payload": payload,
+            });
+            self.send_text(ctx, frame.to_string());
+        } else {
+            self.send_text(ctx, format!("[SYSTEM] {}: {}", event, payload));
+        }
+    }
+
+    // 处理 hello：更新客户端元数据（设备 ID 以握手时校验过的为准）、协商能力并回复 welcome
+    fn handle_hello(&mut self, hello: HelloPayload, ctx: &mut ws::WebsocketContext<Self>) {
+        let device_id = self.client.device_id.take();
+        self.client = ClientInfo {
+            device_id,
+            ..hello.client
+        };
+        self.capabilities = hello
+            .capabilities
+            .into_iter()
+            .filter(|c| SERVER_CAPABILITIES.contains(&c.as_str()))
+            .collect();
+        // 带序号的消息以 JSON 发送，旧的文本格式无法确认
+        if !self.capabilities.contains("json_events") {
+            self.capabilities.remove("acked_delivery");
+        }
+        self.span.record("device", self.client.label());
+        self.room_manager.do_send(UpdateClientInfo {
+            user_id: self.user_id.clone(),
+            session_id: self.session_id.clone(),
+            client: self.client.clone(),
+            acked_delivery: self.capabilities.contains("acked_delivery"),
+        });
+
+        let mut capabilities: Vec<&String> = self.capabilities.iter().collect();
+        capabilities.sort();
+        let welcome = serde_json::json!({
+            "session_id": self.session_id,
+            "protocol_version": PROTOCOL_VERSION,
+            "capabilities": capabilities,
+        });
+        self.send_event(ctx, "welcome", welcome);
+    }
+
+    // 上报延迟，跨过告警阈值时通知客户端
+    fn handle_latency(&mut self, rtt_ms: u64, ctx: &mut ws::WebsocketContext<Self>) {
+        self.room_manager.do_send(UpdateLatency {
+            user_id: self.user_id.clone(),
+            session_id: self.session_id.clone(),
+            rtt_ms,
+        });
+        let event = match self.heartbeat.latency_change() {
+            Some(LatencyChange::Degraded) => {
+                warn!(rtt_ms, "high latency");
+                "latency_warning"
+            }
+            Some(LatencyChange::Recovered) => "latency_recovered",
+            None => return,
+        };
+        let payload = serde_json::json!({
+            "rtt_ms": rtt_ms,
+            "threshold_ms": LATENCY_WARNING_MS,
+        });
+        self.send_event(ctx, event, payload);
+    }
+
+    // 拒绝客户端消息时发送 error 帧，ref_id 为被拒绝消息的 id
+    fn send_error(
+        &self,
+        ctx: &mut ws::WebsocketContext<Self>,
+        code: ErrorCode,
+        ref_id: Option<String>,
+        detail: serde_json::Value,
+    ) {
+        let error = serde_json::json!({
+            "code": code,
+            "message": code.description(),
+            "ref_id": ref_id,
+            "detail": detail,
+        });
+        self.send_event(ctx, "error", error);
+    }
+
+    // 格式错误的消息以 InvalidRequest 拒绝
+    // 消息频率超出限制时丢弃该消息，并告知客户端需要等待的秒数
+    fn within_rate_limit(&self, ctx: &mut ws::WebsocketContext<Self>) -> bool {
+        match self.rate_limiter.check(&self.user_id) {
+            Ok(()) => true,
+            Err(wait) => {
+                let detail = serde_json::json!({ "retry_after": retry_after_secs(wait) });
+                self.send_error(ctx, ErrorCode::RateLimited, None, detail);
+                false
+            }
+        }
+    }
+
+    fn send_invalid(
+        &self,
+        ctx: &mut ws::WebsocketContext<Self>,
+        ref_id: Option<String>,
+        reason: impl Into<String>,
+    ) {
+        self.send_error(
+            ctx,
+            ErrorCode::InvalidRequest,
+            ref_id,
+            serde_json::json!({ "reason": reason.into() }),
+        );
+    }
+
+    // 处理控制消息，未知类型和格式错误的消息以 error 帧拒绝
+    fn handle_frame(&mut self, frame: ClientFrame, ctx: &mut ws::WebsocketContext<Self>) {
+        let ref_id = frame.id;
+        match frame.r#type.as_str() {
+            "hello" => match serde_json::from_value::<HelloPayload>(frame.payload) {
+                Ok(hello) => self.handle_hello(hello, ctx),
+                Err(e) => self.send_invalid(ctx, ref_id, e.to_string()),
+            },
+            "clip.push" => match serde_json::from_value::<ClipItem>(frame.payload) {
+                Ok(clip) => self.handle_clip_push(clip, None, ref_id, ctx),
+                Err(e) => self.send_invalid(ctx, ref_id, e.to_string()),
+            },
+            "direct" => match (
+                frame.to_session,
+                serde_json::from_value::<ClipItem>(frame.payload),
+            ) {
+                (None, _) => self.send_invalid(ctx, ref_id, "missing to_session"),
+                (Some(to_session), Ok(clip)) => {
+                    self.handle_clip_push(clip, Some(to_session), ref_id, ctx)
+                }
+                (_, Err(e)) => self.send_invalid(ctx, ref_id, e.to_string()),
+            },
+            "clip.ack" => match serde_json::from_value::<ClipAckPayload>(frame.payload) {
+                Ok(ack) => self.handle_clip_ack(ack),
+                Err(e) => self.send_invalid(ctx, ref_id, e.to_string()),
+            },
+            "ack" if !self.capabilities.contains("acked_delivery") => {
+                self.send_invalid(ctx, ref_id, "acked_delivery is not negotiated")
+            }
+            "ack" => match serde_json::from_value::<AckPayload>(frame.payload) {
+                Ok(ack) => self.room_manager.do_send(AckDeliveries {
+                    user_id: self.user_id.clone(),
+                    session_id: self.session_id.clone(),
+                    seq: ack.seq,
+                }),
+                Err(e) => self.send_invalid(ctx, ref_id, e.to_string()),
+            },
+            "clip.request_history" => {
+                // 空负载表示不带任何过滤条件
+                let payload = match frame.payload {
+                    serde_json::Value::Null => serde_json::json!({}),
+                    payload => payload,
+                };
+                match serde_json::from_value::<ClipFilter>(payload) {
+                    Ok(filter) => self.handle_request_history(filter, ref_id, ctx),
+                    Err(e) => self.send_invalid(ctx, ref_id, e.to_string()),
+                }
+            }
+            "presence" => self.handle_presence(ref_id, ctx),
+            "sync.pause" => self.pause_sync(true, ref_id, ctx),
+            "sync.resume" => self.pause_sync(false, ref_id, ctx),
+            other => self.send_invalid(ctx, ref_id, format!("unknown message type: {}", other)),
+        }
+    }
+
+    // clip.push：校验来源设备和内容策略后与 POST /clips 一样保存（检查存储配额、按内容去重），
+    // 再把保存后的剪贴板按可见范围转发，回复送达的会话数
+    //
+    // direct 消息只转发给 to_session，目标会话不在线时同样保存（其他设备可增量同步），送达数为 0
+    fn handle_clip_push(
+        &mut self,
+        mut clip: ClipItem,
+        to_session: Option<String>,
+        ref_id: Option<String>,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
+        // 剪贴板必须来自本会话握手时校验过的设备
+        let device_id = clip.device_id.to_string();
+        if self.client.device_id.as_deref() != Some(device_id.as_str()) {
+            let detail = serde_json::json!({ "reason": "device_id does not match the session" });
+            self.send_error(ctx, ErrorCode::Forbidden, ref_id, detail);
+            return;
+        }
+        if let Err(reason) = clip.check_encryption().and(check_expiry(clip.expires_at)) {
+            return self.send_invalid(ctx, ref_id, reason);
+        }
+        // 加密的剪贴板原样转发密文，只校验类型和大小
+        let checked = if clip.encrypted {
+            self.content_policy
+                .check_encrypted_clip(clip.content_type.as_str(), clip.content.len())
+        } else {
+            self.content_policy
+                .check_clip(clip.content_type.as_str(), &clip.content)
+        };
+        if let Err(violation) = checked {
+            let detail = serde_json::json!(violation);
+            self.send_error(ctx, ErrorCode::PolicyViolation, ref_id, detail);
+            return;
+        }
+
+        // 时间、大小、状态和版本号以服务器为准
+        let now = Utc::now().trunc_subsecs(3);
+        clip.created_at = now;
+        clip.accessed_at = now;
+        clip.size = clip.content.len() as i64;
+        clip.sync_status = SyncStatus::Synced;
+        clip.version = 1;
+        clip.tags = normalize_tags(clip.tags);
+        if !clip.encrypted && clip.preview.is_empty() {
+            clip.preview = clip.content.chars().take(PREVIEW_CHARS).collect();
+        }
+        self.content_policy.sanitize_clip(&mut clip);
+        self.content_policy.classify_clip(&mut clip);
+
+        let user_id = self.user_id.clone();
+        let session_id = self.session_id.clone();
+        let pool = self.pool.clone();
+        let room_manager = self.room_manager.clone();
+        let relay = async move {
+            let internal_error = |_| (ErrorCode::InternalError, serde_json::Value::Null);
+            let groups = db::get_device_groups(&user_id, &pool)
+                .await
+                .map_err(internal_error)?;
+            clip.visibility.validate(&groups).map_err(rejected)?;
+            // 按用户的应用规则屏蔽或添加标签
+            load_app_rules(&user_id, &pool)
+                .await
+                .map_err(internal_error)?
+                .apply(&mut clip)
+                .map_err(|violation| (ErrorCode::PolicyViolation, serde_json::json!(violation)))?;
+            if db::get_clip(&user_id, &clip.id, &pool)
+                .await
+                .map_err(internal_error)?
+                .is_some()
+            {
+                let detail = serde_json::json!({ "reason": "clip_id already exists" });
+                return Err((ErrorCode::InvalidRequest, detail));
+            }
+            let hash = content_hash(clip.content_type, &clip.content);
+            let (clip, deduped) = store_clip(&user_id, clip, &hash, &pool)
+                .await
+                .map_err(rejected)?;
+            if !deduped {
+                let created = serde_json::json!(clip);
+                webhook_api::enqueue(&user_id, WebhookEvent::ClipCreated, created, &pool).await;
+            }
+
+            let devices = visible_devices(&user_id, &clip.visibility, &pool)
+                .await
+                .map_err(internal_error)?;
+            let payload = serde_json::json!(clip);
+            let event = "clip.push".to_string();
+            let delivered = match to_session {
+                Some(to_session) => {
+                    room_manager
+                        .send(SendToSession {
+                            user_id,
+                            sender_session_id: session_id,
+                            to_session,
+                            devices,
+                            event,
+                            payload,
+                        })
+                        .await
+                }
+                None => {
+                    room_manager
+                        .send(RelayToRoom {
+                            user_id,
+                            sender_session_id: session_id,
+                            devices,
+                            event,
+                            payload,
+                        })
+                        .await
+                }
+            }
+            .map_err(|_| (ErrorCode::InternalError, serde_json::Value::Null))?;
+            Ok((clip, deduped, delivered))
+        };
+        let relay = relay.instrument(self.span.clone());
+        ctx.spawn(relay.into_actor(self).map(move |stored, act, ctx| {
+            match stored {
+                Ok((clip, deduped, delivered)) => {
+                    let ack = serde_json::json!({
+                        "ref_id": ref_id,
+                        "clip_id": clip.id,
+                        "version": clip.version,
+                        "created_at": clip.created_at,
+                        "deduped": deduped,
+                        "delivered": delivered,
+                    });
+                    act.send_event(ctx, "clip.ack", ack);
+                }
+                Err((code, detail)) => act.send_error(ctx, code, ref_id, detail),
+            }
+        }));
+    }
+
+    // 二进制帧：保存图片 / RTF 剪贴板文件并写入剪贴板记录后转发给其他设备，回复 clip.ack
+    fn handle_binary(&mut self, frame: &[u8], ctx: &mut ws::WebsocketContext<Self>) {
+        let (header, data) = match binary::decode_frame(frame) {
+            Ok(decoded) => decoded,
+            Err(reason) => return self.send_invalid(ctx, None, reason),
+        };
+        let ref_id = header.id;
+        let Some(device_id) = self
+            .client
+            .device_id
+            .as_deref()
+            .and_then(|device_id| Uuid::parse_str(device_id).ok())
+        else {
+            let reason = "binary clips require a registered device";
+            let detail = serde_json::json!({ "reason": reason });
+            return self.send_error(ctx, ErrorCode::Forbidden, ref_id, detail);
+        };
+        if !binary::BINARY_CLIP_TYPES.contains(&header.content_type) {
+            let reason = format!("{} clips cannot be sent as binary", header.content_type.as_str());
+            return self.send_invalid(ctx, ref_id, reason);
+        }
+        if let Err(violation) = self.content_policy.check_blob(
+            header.content_type.as_str(),
+            &header.mime_type,
+            data.len(),
+        ) {
+            let detail = serde_json::json!(violation);
+            return self.send_error(ctx, ErrorCode::PolicyViolation, ref_id, detail);
+        }
+
+        if let Err(reason) = check_encryption(header.key_id.as_deref(), header.nonce.as_deref())
+            .and(check_expiry(header.expires_at))
+        {
+            return self.send_invalid(ctx, ref_id, reason);
+        }
+        let encrypted = header.key_id.is_some();
+        if encrypted && header.preview.is_some() {
+            let reason = "encrypted clips must not contain a plaintext preview";
+            return self.send_invalid(ctx, ref_id, reason);
+        }
+        // 加密的剪贴板为密文，无法识别文件类型
+        if !encrypted
+            && let Err(violation) =
+                binary::check_mime(&header.mime_type, binary::sniff_mime(data))
+        {
+            let detail = serde_json::json!(violation);
+            return self.send_error(ctx, ErrorCode::PolicyViolation, ref_id, detail);
+        }
+
+        let now = Utc::now().trunc_subsecs(3);
+        let mut clip = ClipItem {
+            id: header.clip_id,
+            device_id,
+            content_type: header.content_type,
+            content: String::new(),
+            preview: match header.preview {
+                Some(preview) => preview,
+                None if encrypted => String::new(),
+                None => format!("[{}]", header.mime_type),
+            },
+            size: data.len() as i64,
+            source_app: header.source_app,
+            created_at: now,
+            accessed_at: now,
+            sync_status: SyncStatus::Synced,
+            encrypted,
+            tags: normalize_tags(header.tags),
+            visibility: header.visibility,
+            key_id: header.key_id,
+            nonce: header.nonce,
+            version: 1,
+            pinned: false,
+            source: ClipSource::Own,
+            shared_by: None,
+            expires_at: header.expires_at.map(|at| at.trunc_subsecs(3)),
+            sensitive: None,
+        };
+        let mime_type = header.mime_type;
+        let data = data.to_vec();
+        let user_id = self.user_id.clone();
+        let session_id = self.session_id.clone();
+        let pool = self.pool.clone();
+        let room_manager = self.room_manager.clone();
+        let relay = async move {
+            let internal_error = |_| (ErrorCode::InternalError, serde_json::Value::Null);
+            let groups = db::get_device_groups(&user_id, &pool)
+                .await
+                .map_err(internal_error)?;
+            clip.visibility.validate(&groups).map_err(rejected)?;
+            // 按用户的应用规则屏蔽或添加标签
+            load_app_rules(&user_id, &pool)
+                .await
+                .map_err(internal_error)?
+                .apply(&mut clip)
+                .map_err(|violation| (ErrorCode::PolicyViolation, serde_json::json!(violation)))?;
+            // 不能覆盖已有剪贴板的文件
+            if db::get_clip(&user_id, &clip.id, &pool)
+                .await
+                .map_err(internal_error)?
+                .is_some()
+            {
+                let detail = serde_json::json!({ "reason": "clip_id already exists" });
+                return Err((ErrorCode::InvalidRequest, detail));
+            }
+
+            // 在同一事务中检查存储配额并写入剪贴板，写入失败时删除已保存的文件
+            let mut tx = pool.begin().await.map_err(internal_error)?;
+            check_quota(&user_id, clip.size, &mut tx)
+                .await
+                .map_err(rejected)?;
+            let extension = binary::extension_for(&mime_type);
+            clip.content = binary::save_clip_file(&user_id, &clip.id, extension, &data)
+                .await
+                .map_err(|e| {
+                    warn!(error = %e, "failed to save clip file");
+                    (ErrorCode::InternalError, serde_json::Value::Null)
+                })?;
+            // 文件路径唯一，二进制剪贴板不参与按内容去重
+            let hash = content_hash(clip.content_type, &clip.content);
+            let inserted = match db::write_clip_insert(&user_id, &clip, &hash, &mut tx).await {
+                Ok(_) => tx.commit().await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = inserted {
+                warn!(clip_id = %clip.id, error = %e, "failed to insert binary clip");
+                let _ = blob_store().delete(&clip.content).await;
+                return Err((ErrorCode::InternalError, serde_json::Value::Null));
+            }
+            let created = serde_json::json!(clip);
+            webhook_api::enqueue(&user_id, WebhookEvent::ClipCreated, created, &pool).await;
+
+            let devices = visible_devices(&user_id, &clip.visibility, &pool)
+                .await
+                .map_err(internal_error)?;
+            let push = BinaryClipPush {
+                r#type: "clip.push",
+                mime_type: &mime_type,
+                clip: &clip,
+            };
+            let frame = Bytes::from(binary::encode_frame(&push, &data));
+            let delivered = room_manager
+                .send(RelayBinaryToRoom {
+                    user_id,
+                    sender_session_id: session_id,
+                    devices,
+                    clip_id: clip.id.to_string(),
+                    content_type: clip.content_type,
+                    size: clip.size,
+                    frame,
+                })
+                .await
+                .map_err(|_| (ErrorCode::InternalError, serde_json::Value::Null))?;
+            Ok((clip, delivered))
+        };
+        let relay = relay.instrument(self.span.clone());
+        ctx.spawn(relay.into_actor(self).map(move |relayed, act, ctx| {
+            match relayed {
+                Ok((clip, delivered)) => {
+                    let ack = serde_json::json!({
+                        "ref_id": ref_id,
+                        "clip_id": clip.id,
+                        "version": clip.version,
+                        "created_at": clip.created_at,
+                        "delivered": delivered,
+                        "path": clip.content,
+                        "size": clip.size,
+                    });
+                    act.send_event(ctx, "clip.ack", ack);
+                }
+                Err((code, detail)) => act.send_error(ctx, code, ref_id, detail),
+            }
+        }));
+    }
+
+    // clip.ack：通知其他设备（通常是发送方）本设备已收到剪贴板
+    fn handle_clip_ack(&self, ack: ClipAckPayload) {
+        self.room_manager.do_send(RelayToRoom {
+            user_id: self.user_id.clone(),
+            sender_session_id: self.session_id.clone(),
+            devices: None,
+            event: "clip.received".to_string(),
+            payload: serde_json::json!({
+                "clip_id": ack.clip_id,
+                "device_id": self.client.device_id,
+                "session_id": self.session_id,
+            }),
+        });
+    }
+
+    // clip.request_history：返回本设备可见的剪贴板历史
+    fn handle_request_history(
+        &self,
+        mut filter: ClipFilter,
+        ref_id: Option<String>,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
+        let limit = filter
+            .limit
+            .unwrap_or(DEFAULT_HISTORY_LIMIT)
+            .clamp(1, MAX_HISTORY_LIMIT);
+        // 多查询一条以判断是否还有下一页
+        filter.limit = Some(limit + 1);
+        let user_id = self.user_id.clone();
+        let device_id = self.client.device_id.clone();
+        let pool = self.pool.clone();
+        let query =
+            async move { db::get_clips(&user_id, device_id.as_deref(), &filter, &pool).await };
+        ctx.spawn(
+            query
+                .into_actor(self)
+                .map(move |clips, act, ctx| match clips {
+                    Ok(mut clips) => {
+                        let next_cursor =
+                            truncate_page(&mut clips, limit as usize, |clip| Cursor {
+                                at: clip.created_at.timestamp_millis(),
+                                id: clip.id.to_string(),
+                            });
+                        let history = serde_json::json!({
+                            "ref_id": ref_id,
+                            "clips": clips,
+                            "next_cursor": next_cursor,
+                        });
+                        act.send_event(ctx, "clip.history", history);
+                    }
+                    Err(_) => act.send_error(
+                        ctx,
+                        ErrorCode::InternalError,
+                        ref_id,
+                        serde_json::Value::Null,
+                    ),
+                }),
+        );
+    }
+
+    // presence：返回用户当前的在线会话
+    fn handle_presence(&self, ref_id: Option<String>, ctx: &mut ws::WebsocketContext<Self>) {
+        let sessions = self.room_manager.send(GetSessions {
+            user_id: self.user_id.clone(),
+        });
+        ctx.spawn(sessions.into_actor(self).map(move |sessions, act, ctx| match sessions {
+            Ok(sessions) => {
+                let presence = serde_json::json!({
+                    "ref_id": ref_id,
+                    "session_id": act.session_id,
+                    "sessions": sessions,
+                });
+                act.send_event(ctx, "presence", presence);
+            }
+            Err(_) => {
+                act.send_error(ctx, ErrorCode::InternalError, ref_id, serde_json::Value::Null)
+            }
+        }));
+    }
+
+    // sync.pause / sync.resume：暂停或恢复本会话的剪贴板同步（用于屏幕共享、输入密码等场景），
+    // 由房间管理器回复 sync.paused / sync.resumed
+    fn pause_sync(
+        &self,
+        paused: bool,
+        ref_id: Option<String>,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
+        let result = self.room_manager.send(PauseSessionSync {
+            user_id: self.user_id.clone(),
+            session_id: self.session_id.clone(),
+            paused,
+            ref_id: ref_id.clone(),
+        });
+        ctx.spawn(result.into_actor(self).map(move |result, act, ctx| match result {
+            Ok(true) => {}
+            Ok(false) => {
+                let detail = serde_json::json!({ "reason": RoomError::NotInRoom });
+                act.send_error(ctx, RoomError::NotInRoom.code(), ref_id, detail);
+            }
+            Err(_) => {
+                act.send_error(ctx, ErrorCode::InternalError, ref_id, serde_json::Value::Null)
+            }
+        }));
+    }
+
+    // 广播文本消息，发送失败时向客户端发送 error 帧（detail.reason 为失败原因）
+    fn send_to_room(&self, ctx: &mut ws::WebsocketContext<Self>, message: String) {
+        let sent = self.room_manager.send(SendToRoom {
             user_id: self.user_id.clone(),
             message,
             sender_session_id: self.session_id.clone(),
         });
+        ctx.spawn(sent.into_actor(self).map(|result, act, ctx| match result {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => {
+                let detail = serde_json::json!({ "reason": e });
+                act.send_error(ctx, e.code(), None, detail);
+            }
+            Err(_) => act.send_error(ctx, ErrorCode::InternalError, None, serde_json::Value::Null),
+        }));
     }
 }
 
+// REST 接口的错误响应转为 WebSocket error 帧的错误码和详情
+fn rejected(response: ApiResponse) -> (ErrorCode, serde_json::Value) {
+    let code = response.code.unwrap_or(ErrorCode::InternalError);
+    (code, serde_json::json!({ "reason": response.message, "data": response.data }))
+}
+
 impl Actor for MyWs {
     type Context = ws::WebsocketContext<Self>;
 
     fn started(&mut self, ctx: &mut Self::Context) {
-        println!(
-            "✅ WebSocket started for user: {} (session: {})",
-            self.user_id, &self.session_id[..8]
-        );
+        let _span = self.span.clone().entered();
+        info!("WebSocket started");
 
-        self.join_room(ctx);
+        if self.auth == SessionAuth::Authenticated {
+            self.start_session(ctx);
+        } else {
+            // 未在宽限期内完成认证的连接直接关闭
+            ctx.run_later(self.auth_timeout, |act, ctx| {
+                if act.auth != SessionAuth::Authenticated {
+                    let _span = act.span.clone().entered();
+                    act.reject_session(ctx, ErrorCode::AuthMissing, "认证超时");
+                }
+            });
+        }
 
         // 心跳检测
-        ctx.run_interval(Duration::from_secs(5), |act, ctx| {
+        ctx.run_interval(self.heartbeat_interval, |act, ctx| {
             if !act.heartbeat.is_alive() {
-                println!("💔 Heartbeat failed for user: {} (session: {})", 
-                    act.user_id, &act.session_id[..8]);
+                let _span = act.span.clone().entered();
+                warn!("heartbeat failed");
                 ctx.stop();
                 return;
             }
-            ctx.ping(b"");
+            let payload = act.heartbeat.next_ping();
+            ctx.ping(&payload);
         });
     }
 
     fn stopping(&mut self, _: &mut Self::Context) -> Running {
-        println!(
-            "👋 WebSocket stopping for user: {} (session: {})",
-            self.user_id, &self.session_id[..8]
-        );
+        let _span = self.span.clone().entered();
+        info!("WebSocket stopping");
 
-        self.leave_room();
+        if self.auth == SessionAuth::Authenticated {
+            self.leave_room();
+        }
         Running::Stop
     }
 }
 
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for MyWs {
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        let _span = self.span.clone().entered();
         match msg {
             Ok(ws::Message::Ping(msg)) => {
                 self.heartbeat.heartbeat();
                 ctx.pong(&msg);
             }
-            Ok(ws::Message::Pong(_)) => {
+            Ok(ws::Message::Pong(payload)) => {
                 self.heartbeat.heartbeat();
+                if let Some(rtt_ms) = self.heartbeat.pong(&payload) {
+                    self.handle_latency(rtt_ms, ctx);
+                }
             }
             Ok(ws::Message::Text(text)) => {
                 self.heartbeat.heartbeat();
+                if self.auth != SessionAuth::Authenticated {
+                    self.handle_auth(text.trim(), ctx);
+                    return;
+                }
+                if !self.within_rate_limit(ctx) {
+                    return;
+                }
 
                 let message = text.trim();
+
+                // 控制消息：hello（{"type":"hello","payload":{"platform":...,"capabilities":[...]}}），
+                // 以及协商了 json_events 的客户端发送的其他带 type 的 JSON 消息；
+                // 旧客户端发送的 JSON 文本仍按剪贴板内容广播
+                if let Ok(frame) = serde_json::from_str::<ClientFrame>(message)
+                    && (frame.r#type == "hello" || self.capabilities.contains("json_events"))
+                {
+                    self.handle_frame(frame, ctx);
+                    return;
+                }
+
+                // 违反内容策略的消息不广播，只告知发送方原因
+                if let Err(violation) = self.content_policy.check_clip("text", message) {
+                    self.send_error(
+                        ctx,
+                        ErrorCode::PolicyViolation,
+                        None,
+                        serde_json::json!(violation),
+                    );
+                    return;
+                }
+
                 let timestamp = Local::now().format("%H:%M:%S").to_string();
-                let session_short = &self.session_id[..8];
+                // 已注册的设备以设备 ID 标识消息来源，否则使用会话 ID
+                let source = self
+                    .client
+                    .device_id
+                    .as_deref()
+                    .unwrap_or(&self.session_id[..8]);
 
                 // 发送给房间的其他人
-                let room_msg = format!("[{}] {}: {}", timestamp, session_short, message);
-                self.send_to_room(room_msg);
+                let room_msg = format!("[{}] {}: {}", timestamp, source, message);
+                self.send_to_room(ctx, room_msg);
 
                 // 给自己的回显
                 let my_msg = format!("[You @ {}] {}", timestamp, message);
@@ -427,13 +3394,20 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for MyWs {
             }
             Ok(ws::Message::Binary(bin)) => {
                 self.heartbeat.heartbeat();
-                ctx.binary(bin);
+                // 二进制消息不能用于认证，正在校验时忽略
+                if self.auth != SessionAuth::Authenticated {
+                    if self.auth == SessionAuth::Waiting {
+                        self.reject_session(ctx, ErrorCode::AuthMissing, "需要先发送 auth 消息");
+                    }
+                    return;
+                }
+                if !self.within_rate_limit(ctx) {
+                    return;
+                }
+                self.handle_binary(&bin, ctx);
             }
             Ok(ws::Message::Close(reason)) => {
-                println!(
-                    "🔌 WebSocket closing for user {} (session: {}): {:?}",
-                    self.user_id, &self.session_id[..8], reason
-                );
+                info!(reason = ?reason, "WebSocket closing");
                 ctx.close(reason);
             }
             _ => (),
@@ -445,7 +3419,80 @@ impl Handler<ClientMessage> for MyWs {
     type Result = ();
 
     fn handle(&mut self, msg: ClientMessage, ctx: &mut Self::Context) -> Self::Result {
-        ctx.text(msg.0);
+        self.send_text(ctx, msg.0);
+    }
+}
+
+impl Handler<ClientBinary> for MyWs {
+    type Result = ();
+
+    fn handle(&mut self, msg: ClientBinary, ctx: &mut Self::Context) -> Self::Result {
+        ctx.binary(msg.0);
+    }
+}
+
+impl Handler<Disconnect> for MyWs {
+    type Result = ();
+
+    fn handle(&mut self, msg: Disconnect, ctx: &mut Self::Context) -> Self::Result {
+        ctx.close(Some(ws::CloseReason {
+            code: ws::CloseCode::Policy,
+            description: Some(msg.reason),
+        }));
+        ctx.stop();
+    }
+}
+
+impl Handler<ServerShutdown> for MyWs {
+    type Result = ();
+
+    // 关闭原因为 `{"type":"server.shutdown","payload":{"reconnect_after_secs":...}}`，
+    // 客户端应等待 reconnect_after_secs 秒后重连
+    fn handle(&mut self, msg: ServerShutdown, ctx: &mut Self::Context) -> Self::Result {
+        let reason = serde_json::json!({
+            "type": "server.shutdown",
+            "payload": { "reconnect_after_secs": msg.reconnect_after_secs },
+        });
+        ctx.close(Some(ws::CloseReason {
+            code: ws::CloseCode::Restart,
+            description: Some(reason.to_string()),
+        }));
+        ctx.stop();
+    }
+}
+
+impl Handler<Delivery> for MyWs {
+    type Result = ();
+
+    // 带序号的剪贴板：JSON 事件带有 seq 字段；二进制帧之前先发送带 seq 的 clip.binary 文本帧
+    fn handle(&mut self, msg: Delivery, ctx: &mut Self::Context) -> Self::Result {
+        match msg.message {
+            Outbound::Event(event) => {
+                let frame = serde_json::json!({
+                    "type": event.event,
+                    "seq": msg.seq,
+                    "payload": event.payload,
+                });
+                self.send_text(ctx, frame.to_string());
+            }
+            Outbound::Binary { clip_id, frame, .. } => {
+                let notice = serde_json::json!({
+                    "type": "clip.binary",
+                    "seq": msg.seq,
+                    "payload": { "clip_id": clip_id },
+                });
+                ctx.text(notice.to_string());
+                ctx.binary(frame);
+            }
+        }
+    }
+}
+
+impl Handler<ServerEvent> for MyWs {
+    type Result = ();
+
+    fn handle(&mut self, msg: ServerEvent, ctx: &mut Self::Context) -> Self::Result {
+        self.send_event(ctx, &msg.event, msg.payload);
     }
 }
 
@@ -454,11 +3501,76 @@ impl Handler<ClientMessage> for MyWs {
 #[derive(Clone)]
 pub struct AppState {
     pub room_manager: Addr<RoomManager>,
+    pub content_policy: Arc<ContentPolicy>,
+    pub mailer: Arc<dyn Mailer>,
+    pub rate_limiters: RateLimiters,
+    /// 连续登录失败的账号 / IP 锁定
+    pub login_lockout: LoginLockout,
+    /// 局域网（mDNS）发现的其他服务器
+    pub discovery: LanDiscovery,
+    pub config: Arc<Config>,
 }
 
 impl AppState {
-    pub fn new() -> Self {
-        let room_manager = RoomManager::new().start();
-        Self { room_manager }
+    pub fn new(content_policy: ContentPolicy, mailer: Arc<dyn Mailer>, config: Config) -> Self {
+        Self {
+            room_manager: RoomManager::spawn(&config),
+            content_policy: Arc::new(content_policy),
+            mailer,
+            rate_limiters: RateLimiters::from_env(),
+            login_lockout: LoginLockout::new(config.auth.lockout.clone()),
+            discovery: LanDiscovery::start(&config),
+            config: Arc::new(config),
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(id: &str) -> Outbound {
+        Outbound::Event(ServerEvent {
+            event: "clip.push".to_string(),
+            payload: serde_json::json!({ "id": id }),
+        })
+    }
+
+    #[test]
+    fn outbox_acks_cumulatively_and_stays_bounded() {
+        let mut outbox = Outbox::default();
+        for i in 0..MAX_PENDING_DELIVERIES + 2 {
+            outbox.push(event(&i.to_string()));
+        }
+        assert_eq!(outbox.pending.len(), MAX_PENDING_DELIVERIES);
+        assert_eq!(outbox.pending.front().map(|delivery| delivery.seq), Some(3));
+
+        assert_eq!(outbox.ack(10), 8);
+        assert_eq!(outbox.pending.front().map(|delivery| delivery.seq), Some(11));
+        assert_eq!(outbox.ack(5), 0);
+        assert_eq!(outbox.push(event("next")).seq, MAX_PENDING_DELIVERIES as u64 + 3);
+    }
+
+    #[test]
+    fn sync_rules_filter_only_clip_events() {
+        let phone = SyncRule {
+            clip_types: Some(vec![ClipType::Text, ClipType::Url]),
+            max_size: Some(100),
+        };
+        let rules = HashMap::from([("phone".to_string(), phone)]);
+        let clip = |content_type: &str, size: i64| {
+            serde_json::json!({ "content_type": content_type, "size": size })
+        };
+
+        let text = clip_summary("clip_created", &clip("text", 10));
+        assert!(syncs_clip(Some(&rules), Some("phone"), text));
+        let image = clip_summary("clip.push", &clip("image", 10));
+        assert!(!syncs_clip(Some(&rules), Some("phone"), image));
+        assert!(syncs_clip(Some(&rules), Some("laptop"), image));
+        let large = clip_summary("clip_updated", &clip("url", 101));
+        assert!(!syncs_clip(Some(&rules), Some("phone"), large));
+        assert!(syncs_clip(Some(&rules), None, large));
+
+        let deleted = clip_summary("clip_deleted", &clip("image", 10));
+        assert!(syncs_clip(Some(&rules), Some("phone"), deleted));
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,75 @@
+use std::sync::OnceLock;
+
+/// 数据库后端
+///
+/// 大部分 SQL 在两种后端上通用，表结构、全文搜索、JSON 函数等不通用的部分按后端选择
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Sqlite,
+    Postgres,
+}
+
+static BACKEND: OnceLock<Backend> = OnceLock::new();
+
+impl Backend {
+    /// 根据 DATABASE_URL 的协议判断后端
+    pub fn from_url(url: &str) -> Self {
+        if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+            Self::Postgres
+        } else {
+            Self::Sqlite
+        }
+    }
+
+    /// 记录连接池使用的后端，只在初始化连接池时调用
+    pub fn install(self) {
+        let _ = BACKEND.set(self);
+    }
+}
+
+/// 当前使用的后端，未初始化时（如单元测试）为 SQLite
+pub fn backend() -> Backend {
+    BACKEND.get().copied().unwrap_or(Backend::Sqlite)
+}
+
+/// 文本列的字节数（BIGINT）
+pub fn byte_length(expr: &str) -> String {
+    match backend() {
+        Backend::Sqlite => format!("LENGTH(CAST({} AS BLOB))", expr),
+        Backend::Postgres => format!("CAST(octet_length({}) AS BIGINT)", expr),
+    }
+}
+
+/// 数据库占用的字节数（BIGINT）
+pub fn database_size() -> &'static str {
+    match backend() {
+        Backend::Sqlite => {
+            "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()"
+        }
+        Backend::Postgres => "SELECT pg_database_size(current_database())",
+    }
+}
+
+/// 读取 JSON 文本列中的字段（文本）
+pub fn json_field(column: &str, field: &str) -> String {
+    match backend() {
+        Backend::Sqlite => format!("json_extract({}, '$.{}')", column, field),
+        Backend::Postgres => format!("({}::json ->> '{}')", column, field),
+    }
+}
+
+/// 展开 JSON 字符串数组参数，每个元素一行，元素在 alias.value 列
+pub fn json_array_elements(param: &str, alias: &str) -> String {
+    match backend() {
+        Backend::Sqlite => format!("json_each({}) AS {}", param, alias),
+        Backend::Postgres => format!("json_array_elements_text({}::json) AS {}(value)", param, alias),
+    }
+}
+
+/// haystack 中是否包含 needle（区分大小写）
+pub fn contains(haystack: &str, needle: &str) -> String {
+    match backend() {
+        Backend::Sqlite => format!("instr({}, {}) > 0", haystack, needle),
+        Backend::Postgres => format!("strpos({}, {}) > 0", haystack, needle),
+    }
+}
@@ -1,52 +1,79 @@
+use chrono::{DateTime, Utc};
 use sqlx::{
-    Row, query,
-    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool},
+    Executor, Row, Transaction,
+    any::{AnyArguments, AnyConnectOptions, AnyPoolOptions, AnyRow},
+    query,
+    sqlite::SqliteJournalMode,
 };
 use uuid::Uuid;
-use yansi::Paint;
 
-use crate::user_api::{LoginUser, RegisterUser, User, UserInfo};
+use crate::admin_api::{DatabaseStats, users::AdminUser};
+use crate::announcement_api::Announcement;
+use crate::audit::{AuditLogEntry, AuditLogQuery, AuditRecord};
+use crate::config::DatabaseConfig;
+use crate::clip_api::revisions::{ClipRevision, max_revisions};
+use crate::contact_api::{BlockedUser, Contact};
+use crate::device_api::{Device, SyncRule, last_seen::DeviceLastSeen};
+use crate::device_group_api::{DeviceGroup, Visibility};
+use crate::job_queue::Job;
+use crate::legal_api::{LegalAcceptance, LegalDocument};
+use crate::models::{
+    ChangeOp, ClipChange, ClipFilter, ClipItem, ClipSearchHit, ClipType, SyncStatus, TrashedClip,
+    UserRole, content_hash,
+};
+use crate::org_api::{OrgMember, Organization};
+use crate::report_api::AbuseReport;
+use crate::retention_api::RetainedClip;
+use crate::scheduled_api::ScheduledClip;
+use crate::share_api::Share;
+use crate::spatial_api::binary::BINARY_CLIP_TYPES;
+use crate::stats_api::NamedCount;
+use crate::sync_api::SyncConflict;
+use crate::tag_api::Tag;
+use crate::upload_api::{Upload, UploadChunk};
+use crate::user_api::quota::{QuotaUsage, Usage};
+use crate::user_api::storage::{ClipUsage, TypeUsage};
+use crate::sqlx_utils::backend::{
+    Backend, backend, byte_length, contains, database_size, json_array_elements, json_field,
+};
+use crate::sqlx_utils::{Db, DbPool, migrations, models::Cursor};
+use crate::user_api::oauth::LinkedIdentity;
+use crate::user_api::sessions::{LoginSession, SessionClient};
+use crate::user_api::{RegisterUser, User, UserInfo, UsernameChange};
+use crate::webhook_api::{Webhook, WebhookDelivery, dispatcher::DueDelivery};
 
-/// 初始化 SQLite 连接池
+/// 初始化数据库连接池
 ///
-/// 该函数将创建一个 SQLite 连接池，连接到指定文件名的数据库文件中
+/// 根据 `config.url`（`DATABASE_URL`）的协议选择后端：
 ///
-/// - 文件名：`data.db`，可以根据需要进行修改
-/// - 允许创建文件：`create_if_missing` 选项设置为 `true`，表示如果文件不存在，将自动创建
-/// - 日志模式：`journal_mode` 选项设置为 `SqliteJournalMode::Wal`，表示使用WAL日志模式，可以提高性能
-/// - 锁超时设置：`busy_timeout` 选项设置为 `std::time::Duration::from_secs(5)`，表示如果在5秒内没有可用的连接，将返回错误
-pub async fn init_pool() -> Result<SqlitePool, sqlx::Error> {
-    let options = SqliteConnectOptions::new()
-        .filename("data.db") // 显式指定文件名
-        .create_if_missing(true) // ✅ 关键修复：允许创建文件
-        .journal_mode(SqliteJournalMode::Wal) // 推荐WAL模式提升性能
-        .busy_timeout(std::time::Duration::from_secs(5)); // 锁超时设置
-    sqlx::SqlitePool::connect_with(options).await
-}
-
-/// 用户表结构定义
-const CREATE_USERS_TABLE_SQL: &str = r#"
-CREATE TABLE IF NOT EXISTS users (
-    user_id TEXT PRIMARY KEY NOT NULL,
-    username TEXT NOT NULL,
-    email TEXT UNIQUE NOT NULL,
-    password TEXT NOT NULL,
-    head_uri TEXT 
-);
-
-CREATE INDEX IF NOT EXISTS idx_users_username ON users(username);
-"#;
+/// - `sqlite://`：默认为 `sqlite://data.db`，文件不存在时自动创建，使用WAL日志模式提升性能，
+///   锁超时为 `config.busy_timeout_secs`（默认5秒）
+/// - `postgres://`：需要启用 `postgres` feature
+pub async fn init_pool(config: &DatabaseConfig) -> Result<DbPool, sqlx::Error> {
+    let mut options: AnyConnectOptions = config.url.parse()?;
+    if let Some(sqlite) = options.as_sqlite_mut() {
+        *sqlite = sqlite
+            .clone()
+            .create_if_missing(true) // ✅ 关键修复：允许创建文件
+            .journal_mode(SqliteJournalMode::Wal) // 推荐WAL模式提升性能
+            .busy_timeout(std::time::Duration::from_secs(config.busy_timeout_secs)); // 锁超时设置
+    }
+    let pool = AnyPoolOptions::new().connect_with(options).await?;
+    Backend::from_url(&config.url).install();
+    Ok(pool)
+}
 
-// 初始化数据库
-pub async fn crate_db(pool: &SqlitePool) -> Result<(), sqlx::Error> {
-    sqlx::query(CREATE_USERS_TABLE_SQL).execute(pool).await?;
-    Ok(())
+// 初始化数据库：执行全部表结构迁移，返回当前的数据库版本
+pub async fn crate_db(pool: &DbPool) -> Result<i64, sqlx::Error> {
+    let version = migrations::run_migrations(pool).await?;
+    backfill_content_hashes(pool).await?;
+    Ok(version)
 }
 
 // 插入后返回用户 ID
 pub async fn insert_user(
     register_user: &RegisterUser,
-    pool: &SqlitePool,
+    pool: &DbPool,
 ) -> Result<String, sqlx::Error> {
     let user_id = Uuid::new_v4().to_string();
     query(
@@ -67,11 +94,12 @@ pub async fn insert_user(
 // 根据用户名或者 email 查询用户信息
 pub async fn get_user_by_username_or_email(
     username_or_email: &str,
-    pool: &SqlitePool,
+    pool: &DbPool,
 ) -> Result<User, sqlx::Error> {
     let row = query(
         r#"
-        SELECT user_id, username, email, password, head_uri
+        SELECT user_id, username, email, password, head_uri, disabled, org_id,
+               email_verified_at IS NOT NULL AS email_verified, totp_enabled
         FROM users
         WHERE username = $1 OR email = $2
         "#,
@@ -81,61 +109,178 @@ pub async fn get_user_by_username_or_email(
     .fetch_optional(pool)
     .await?;
 
-    Ok(match row {
-        Some(row) => User {
-            user_id: row.try_get("user_id")?,
-            username_or_email: row.try_get("username")?,
-            password: row.try_get("password")?,
-        },
-        None => return Err(sqlx::Error::RowNotFound),
+    match row {
+        Some(row) => row_to_user(&row),
+        None => Err(sqlx::Error::RowNotFound),
+    }
+}
+
+fn row_to_user(row: &AnyRow) -> Result<User, sqlx::Error> {
+    Ok(User {
+        user_id: row.try_get("user_id")?,
+        username_or_email: row.try_get("username")?,
+        email: row.try_get("email")?,
+        password: row.try_get("password")?,
+        disabled: row.try_get("disabled")?,
+        org_id: row.try_get("org_id")?,
+        email_verified: row.try_get("email_verified")?,
+        totp_enabled: row.try_get("totp_enabled")?,
     })
 }
 
-// 修改用户名
+// 查询绑定了外部身份的用户
+pub async fn get_user_by_identity(
+    provider: &str,
+    subject: &str,
+    pool: &DbPool,
+) -> Result<Option<User>, sqlx::Error> {
+    let row = query(
+        r#"
+        SELECT users.user_id, username, users.email, password, disabled, org_id,
+               email_verified_at IS NOT NULL AS email_verified, totp_enabled
+        FROM identities JOIN users ON users.user_id = identities.user_id
+        WHERE identities.provider = $1 AND identities.subject = $2
+        "#,
+    )
+    .bind(provider)
+    .bind(subject)
+    .fetch_optional(pool)
+    .await?;
+    row.as_ref().map(row_to_user).transpose()
+}
+
+// 查询已验证邮箱的用户 ID，未验证的邮箱不能用于绑定外部身份
+pub async fn get_user_id_by_verified_email(
+    email: &str,
+    pool: &DbPool,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = query("SELECT user_id FROM users WHERE email = $1 AND email_verified_at IS NOT NULL")
+        .bind(email)
+        .fetch_optional(pool)
+        .await?;
+    row.map(|row| row.try_get("user_id")).transpose()
+}
+
+// 修改用户名，同时记录修改历史
+//
+// expected_version 不为空时仅在版本一致时修改，返回修改后的版本号，版本不一致时返回 None
 pub async fn update_username(
     user_id: &str,
+    old_username: &str,
     username: &str,
-    pool: &SqlitePool,
-) -> Result<(), sqlx::Error> {
-    query(
+    expected_version: Option<i64>,
+    pool: &DbPool,
+) -> Result<Option<i64>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let row = query(
         r#"
         UPDATE users
-        SET username = $2
-        WHERE user_id = $1
+        SET username = $2, version = version + 1
+        WHERE user_id = $1 AND ($3 IS NULL OR version = $4)
+        RETURNING version
         "#,
     )
     .bind(user_id)
     .bind(username)
-    .execute(pool)
+    .bind(expected_version)
+    .bind(expected_version)
+    .fetch_optional(&mut tx)
     .await?;
-    Ok(())
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    query(
+        r#"
+        INSERT INTO username_history (user_id, old_username, new_username, changed_at)
+        VALUES ($1, $2, $3, $4)
+        "#,
+    )
+    .bind(user_id)
+    .bind(old_username)
+    .bind(username)
+    .bind(chrono::Utc::now().timestamp())
+    .execute(&mut tx)
+    .await?;
+    tx.commit().await?;
+    Ok(Some(row.try_get("version")?))
+}
+
+// 最近一次修改用户名的时间
+pub async fn get_last_rename_at(
+    user_id: &str,
+    pool: &DbPool,
+) -> Result<Option<i64>, sqlx::Error> {
+    let row = query("SELECT MAX(changed_at) AS changed_at FROM username_history WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+    row.try_get("changed_at")
+}
+
+// 获取用户名修改历史
+pub async fn get_username_history(
+    user_id: &str,
+    pool: &DbPool,
+) -> Result<Vec<UsernameChange>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT old_username, new_username, changed_at
+        FROM username_history
+        WHERE user_id = $1
+        ORDER BY changed_at DESC
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    rows.iter()
+        .map(|row| {
+            Ok(UsernameChange {
+                old_username: row.try_get("old_username")?,
+                new_username: row.try_get("new_username")?,
+                changed_at: row.try_get("changed_at")?,
+            })
+        })
+        .collect()
 }
 
-// 修改头像
+// 修改头像，版本号规则同 update_username
 pub async fn update_head_uri(
     user_id: &str,
     head_uri: &str,
-    pool: &SqlitePool,
-) -> Result<(), sqlx::Error> {
-    query(
+    head_mime: &str,
+    head_bytes: i64,
+    expected_version: Option<i64>,
+    pool: &DbPool,
+) -> Result<Option<i64>, sqlx::Error> {
+    let row = query(
         r#"
         UPDATE users
-        SET head_uri = $2
-        WHERE user_id = $1
+        SET head_uri = $2, head_mime = $5, head_bytes = $6, version = version + 1
+        WHERE user_id = $1 AND ($3 IS NULL OR version = $4)
+        RETURNING version
         "#,
     )
     .bind(user_id)
     .bind(head_uri)
-    .execute(pool)
+    .bind(expected_version)
+    .bind(expected_version)
+    .bind(head_mime)
+    .bind(head_bytes)
+    .fetch_optional(pool)
     .await?;
-    Ok(())
+    match row {
+        Some(row) => Ok(Some(row.try_get("version")?)),
+        None => Ok(None),
+    }
 }
 
 // 修改密码
 pub async fn update_password(
     user_id: &str,
     new_password: &str,
-    pool: &SqlitePool,
+    pool: &DbPool,
 ) -> Result<(), sqlx::Error> {
     query(
         r#"
@@ -151,11 +296,471 @@ pub async fn update_password(
     Ok(())
 }
 
+// 获取用户的密码，用于敏感操作前再次确认；用户不存在时返回 None
+pub async fn get_user_password(
+    user_id: &str,
+    pool: &DbPool,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = query("SELECT password FROM users WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+    row.map(|row| row.try_get("password")).transpose()
+}
+
+// 获取用户加密保存的两步验证密钥和是否已启用，未开始启用时返回 None
+pub async fn get_totp_secret(
+    user_id: &str,
+    pool: &DbPool,
+) -> Result<Option<(String, bool)>, sqlx::Error> {
+    let row = query(
+        r#"
+        SELECT totp_secret, totp_enabled FROM users
+        WHERE user_id = $1 AND totp_secret IS NOT NULL
+        "#,
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+    row.map(|row| Ok((row.try_get("totp_secret")?, row.try_get("totp_enabled")?)))
+        .transpose()
+}
+
+// 保存待确认的两步验证密钥（替换之前未确认的密钥），已启用时不修改并返回 false
+pub async fn set_pending_totp_secret(
+    user_id: &str,
+    secret: &str,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    let result = query(
+        r#"
+        UPDATE users SET totp_secret = $2, totp_last_step = NULL
+        WHERE user_id = $1 AND totp_enabled = FALSE
+        "#,
+    )
+    .bind(user_id)
+    .bind(secret)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// 启用两步验证并保存恢复码的哈希，step 为确认时使用的验证码时间步，已启用时返回 false
+pub async fn enable_totp(
+    user_id: &str,
+    step: i64,
+    recovery_code_hashes: &[String],
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let enabled = query(
+        r#"
+        UPDATE users SET totp_enabled = TRUE, totp_last_step = $2
+        WHERE user_id = $1 AND totp_enabled = FALSE AND totp_secret IS NOT NULL
+        "#,
+    )
+    .bind(user_id)
+    .bind(step)
+    .execute(&mut tx)
+    .await?
+    .rows_affected()
+        > 0;
+    if !enabled {
+        return Ok(false);
+    }
+    query("DELETE FROM totp_recovery_codes WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut tx)
+        .await?;
+    for hash in recovery_code_hashes {
+        query("INSERT INTO totp_recovery_codes (user_id, code_hash) VALUES ($1, $2)")
+            .bind(user_id)
+            .bind(hash)
+            .execute(&mut tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(true)
+}
+
+// 记录已使用的验证码时间步，同一时间步（及更早）的验证码不能再次使用，已使用过时返回 false
+pub async fn use_totp_step(user_id: &str, step: i64, pool: &DbPool) -> Result<bool, sqlx::Error> {
+    let result = query(
+        r#"
+        UPDATE users SET totp_last_step = $2
+        WHERE user_id = $1 AND (totp_last_step IS NULL OR totp_last_step < $2)
+        "#,
+    )
+    .bind(user_id)
+    .bind(step)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// 使用一个恢复码（使用后删除），恢复码不存在时返回 false
+pub async fn use_recovery_code(
+    user_id: &str,
+    code_hash: &str,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    let result = query("DELETE FROM totp_recovery_codes WHERE user_id = $1 AND code_hash = $2")
+        .bind(user_id)
+        .bind(code_hash)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// 统计已启用两步验证的用户数
+pub async fn count_totp_users(pool: &DbPool) -> Result<i64, sqlx::Error> {
+    query("SELECT COUNT(*) AS total FROM users WHERE totp_enabled = TRUE")
+        .fetch_one(pool)
+        .await?
+        .try_get("total")
+}
+
+// 关闭两步验证，删除密钥和剩余的恢复码
+pub async fn disable_totp(user_id: &str, pool: &DbPool) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    query(
+        r#"
+        UPDATE users SET totp_secret = NULL, totp_enabled = FALSE, totp_last_step = NULL
+        WHERE user_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .execute(&mut tx)
+    .await?;
+    query("DELETE FROM totp_recovery_codes WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut tx)
+        .await?;
+    tx.commit().await
+}
+
+// 停用或启用账号，用户不存在时返回 false
+pub async fn set_user_disabled(
+    user_id: &str,
+    disabled: bool,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    let result = query("UPDATE users SET disabled = $2 WHERE user_id = $1")
+        .bind(user_id)
+        .bind(disabled)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// 获取账号角色，用户不存在时视为普通用户
+pub async fn get_user_role(user_id: &str, pool: &DbPool) -> Result<UserRole, sqlx::Error> {
+    let row = query("SELECT role FROM users WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+    match row {
+        Some(row) => row.try_get("role"),
+        None => Ok(UserRole::User),
+    }
+}
+
+// 修改账号角色，用户不存在时返回 false
+pub async fn set_user_role(
+    user_id: &str,
+    role: UserRole,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    let result = query("UPDATE users SET role = $2 WHERE user_id = $1")
+        .bind(user_id)
+        .bind(role)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// 按用户名或邮箱搜索用户（不区分大小写，text 为空时列出全部），返回 (总数, 当前页)
+pub async fn search_users(
+    text: &str,
+    limit: i32,
+    offset: i32,
+    pool: &DbPool,
+) -> Result<(i64, Vec<AdminUser>), sqlx::Error> {
+    let matched = format!(
+        "FROM users WHERE $1 = '' OR {} OR {}",
+        contains("lower(username)", "lower($1)"),
+        contains("lower(email)", "lower($1)"),
+    );
+    let total: i64 = query(&format!("SELECT COUNT(*) AS total {}", matched))
+        .bind(text)
+        .fetch_one(pool)
+        .await?
+        .try_get("total")?;
+    let rows = query(&format!(
+        r#"
+        SELECT user_id, username, email, disabled, role, org_id,
+               email_verified_at IS NOT NULL AS email_verified,
+               (SELECT COUNT(*) FROM clips WHERE clips.user_id = users.user_id) AS clip_count
+        {matched}
+        ORDER BY username, user_id
+        LIMIT $2 OFFSET $3
+        "#
+    ))
+    .bind(text)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await?;
+    let users = rows
+        .iter()
+        .map(|row| {
+            Ok(AdminUser {
+                user_id: row.try_get("user_id")?,
+                username: row.try_get("username")?,
+                email: row.try_get("email")?,
+                disabled: row.try_get("disabled")?,
+                role: row.try_get("role")?,
+                org_id: row.try_get("org_id")?,
+                email_verified: row.try_get("email_verified")?,
+                clip_count: row.try_get("clip_count")?,
+            })
+        })
+        .collect::<Result<_, sqlx::Error>>()?;
+    Ok((total, users))
+}
+
+// 账号是否已停用
+pub async fn is_user_disabled(user_id: &str, pool: &DbPool) -> Result<bool, sqlx::Error> {
+    let row = query("SELECT disabled FROM users WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+    match row {
+        Some(row) => row.try_get("disabled"),
+        None => Ok(false),
+    }
+}
+
+// 保存刷新令牌（只保存哈希值）
+// 保存新登录会话的第一个刷新令牌，返回登录会话 ID
+pub async fn insert_refresh_token(
+    user_id: &str,
+    token_hash: &str,
+    expires_at: i64,
+    client: &SessionClient,
+    pool: &DbPool,
+) -> Result<String, sqlx::Error> {
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now().timestamp();
+    query(
+        r#"
+        INSERT INTO refresh_tokens
+            (id, user_id, token_hash, created_at, expires_at,
+             session_id, device_name, user_agent, ip, signed_in_at, last_used_at)
+        VALUES ($1, $2, $3, $4, $5, $1, $6, $7, $8, $4, $4)
+        "#,
+    )
+    .bind(&id)
+    .bind(user_id)
+    .bind(token_hash)
+    .bind(now)
+    .bind(expires_at)
+    .bind(client.device_name.as_deref())
+    .bind(client.user_agent.as_deref())
+    .bind(client.ip.as_deref())
+    .execute(pool)
+    .await?;
+    Ok(id)
+}
+
+// 轮换刷新令牌：注销旧令牌并保存新令牌（属于同一登录会话），返回 (用户 ID, 登录会话 ID)
+//
+// 旧令牌不存在、已过期或已注销时返回 None；已轮换的令牌再次出现说明令牌可能泄露，
+// 此时注销该用户的全部刷新令牌
+pub async fn rotate_refresh_token(
+    token_hash: &str,
+    new_token_hash: &str,
+    expires_at: i64,
+    client: &SessionClient,
+    pool: &DbPool,
+) -> Result<Option<(String, String)>, sqlx::Error> {
+    let now = Utc::now().timestamp();
+    let mut tx = pool.begin().await?;
+    let row = query(
+        r#"
+        UPDATE refresh_tokens
+        SET revoked_at = $2
+        WHERE token_hash = $1 AND revoked_at IS NULL AND expires_at > $2
+        RETURNING user_id, session_id, device_name, user_agent, signed_in_at
+        "#,
+    )
+    .bind(token_hash)
+    .bind(now)
+    .fetch_optional(&mut tx)
+    .await?;
+    let Some(row) = row else {
+        // 登录会话已被注销时（会话中没有有效的令牌）只拒绝本次刷新
+        let reused = query(
+            r#"
+            SELECT used.user_id FROM refresh_tokens used
+            WHERE used.token_hash = $1 AND used.revoked_at IS NOT NULL
+              AND EXISTS (
+                  SELECT 1 FROM refresh_tokens active
+                  WHERE active.session_id = used.session_id
+                    AND active.revoked_at IS NULL AND active.expires_at > $2
+              )
+            "#,
+        )
+        .bind(token_hash)
+        .bind(now)
+        .fetch_optional(&mut tx)
+        .await?;
+        if let Some(reused) = reused {
+            let user_id: String = reused.try_get("user_id")?;
+            revoke_user_refresh_tokens(&user_id, &mut tx).await?;
+            tx.commit().await?;
+        }
+        return Ok(None);
+    };
+    let user_id: String = row.try_get("user_id")?;
+    let session_id: String = row.try_get("session_id")?;
+    // 客户端未提供设备信息时沿用登录时的记录，IP 和最近使用时间总是更新
+    let device_name: Option<String> = row.try_get("device_name")?;
+    let user_agent: Option<String> = row.try_get("user_agent")?;
+    let signed_in_at: i64 = row.try_get("signed_in_at")?;
+    query(
+        r#"
+        INSERT INTO refresh_tokens
+            (id, user_id, token_hash, created_at, expires_at,
+             session_id, device_name, user_agent, ip, signed_in_at, last_used_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $4)
+        "#,
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(&user_id)
+    .bind(new_token_hash)
+    .bind(now)
+    .bind(expires_at)
+    .bind(&session_id)
+    .bind(client.device_name.clone().or(device_name))
+    .bind(client.user_agent.clone().or(user_agent))
+    .bind(client.ip.as_deref())
+    .bind(signed_in_at)
+    .execute(&mut tx)
+    .await?;
+    tx.commit().await?;
+    Ok(Some((user_id, session_id)))
+}
+
+// 用户未注销、未过期的登录会话（每个会话只有一个有效的刷新令牌），最近使用的在前
+pub async fn get_login_sessions(
+    user_id: &str,
+    pool: &DbPool,
+) -> Result<Vec<LoginSession>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT session_id, device_name, user_agent, ip, signed_in_at, last_used_at
+        FROM refresh_tokens
+        WHERE user_id = $1 AND revoked_at IS NULL AND expires_at > $2
+        ORDER BY last_used_at DESC
+        "#,
+    )
+    .bind(user_id)
+    .bind(Utc::now().timestamp())
+    .fetch_all(pool)
+    .await?;
+    rows.iter()
+        .map(|row| {
+            let last_used_at = row.try_get("last_used_at")?;
+            Ok(LoginSession {
+                id: row.try_get("session_id")?,
+                device_name: row.try_get("device_name")?,
+                user_agent: row.try_get("user_agent")?,
+                ip: row.try_get("ip")?,
+                signed_in_at: row.try_get("signed_in_at")?,
+                last_seen_at: last_used_at,
+                current: false,
+                websocket_sessions: Vec::new(),
+            })
+        })
+        .collect()
+}
+
+// 注销登录会话的刷新令牌，会话不存在或已注销时返回 false
+pub async fn revoke_login_session(
+    user_id: &str,
+    session_id: &str,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    let result = query(
+        r#"
+        UPDATE refresh_tokens SET revoked_at = $3
+        WHERE user_id = $1 AND session_id = $2 AND revoked_at IS NULL
+        "#,
+    )
+    .bind(user_id)
+    .bind(session_id)
+    .bind(Utc::now().timestamp())
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// 登录会话是否仍然有效（存在未注销、未过期的刷新令牌）
+pub async fn is_login_session_active(session_id: &str, pool: &DbPool) -> Result<bool, sqlx::Error> {
+    let row = query(
+        r#"
+        SELECT 1 FROM refresh_tokens
+        WHERE session_id = $1 AND revoked_at IS NULL AND expires_at > $2
+        "#,
+    )
+    .bind(session_id)
+    .bind(Utc::now().timestamp())
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.is_some())
+}
+
+// 注销刷新令牌，返回令牌所属的用户 ID（令牌不存在或已注销时返回 None）
+pub async fn revoke_refresh_token(
+    token_hash: &str,
+    pool: &DbPool,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = query(
+        r#"
+        UPDATE refresh_tokens
+        SET revoked_at = $2
+        WHERE token_hash = $1 AND revoked_at IS NULL
+        RETURNING user_id
+        "#,
+    )
+    .bind(token_hash)
+    .bind(Utc::now().timestamp())
+    .fetch_optional(pool)
+    .await?;
+    row.map(|row| row.try_get("user_id")).transpose()
+}
+
+// 注销用户的全部刷新令牌
+pub async fn revoke_user_refresh_tokens(
+    user_id: &str,
+    executor: impl Executor<'_, Database = Db>,
+) -> Result<u64, sqlx::Error> {
+    let result = query(
+        "UPDATE refresh_tokens SET revoked_at = $2 WHERE user_id = $1 AND revoked_at IS NULL",
+    )
+    .bind(user_id)
+    .bind(Utc::now().timestamp())
+    .execute(executor)
+    .await?;
+    Ok(result.rows_affected())
+}
+
 // 获取用户信息
-pub async fn get_user_by_id(user_id: &str, pool: &SqlitePool) -> Result<UserInfo, sqlx::Error> {
+pub async fn get_user_by_id(user_id: &str, pool: &DbPool) -> Result<UserInfo, sqlx::Error> {
     let row = query(
         r#"
-        SELECT user_id, username, email, password, head_uri
+        SELECT user_id, username, email, password, head_uri, version
         FROM users
         WHERE user_id = $1
         "#,
@@ -167,5 +772,4786 @@ pub async fn get_user_by_id(user_id: &str, pool: &SqlitePool) -> Result<UserInfo
         username: row.try_get("username")?,
         email: row.try_get("email")?,
         head_uri: row.try_get("head_uri")?,
+        version: row.try_get("version")?,
+    })
+}
+
+// 获取用户的全部设置项，返回 (key, JSON 文本)
+pub async fn get_user_settings(
+    user_id: &str,
+    pool: &DbPool,
+) -> Result<Vec<(String, String)>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT key, value
+        FROM user_settings
+        WHERE user_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    rows.iter()
+        .map(|row| Ok((row.try_get("key")?, row.try_get("value")?)))
+        .collect()
+}
+
+// 批量写入用户设置，值为 None 时删除该项（恢复默认值）
+pub async fn upsert_user_settings(
+    user_id: &str,
+    settings: &[(String, Option<String>)],
+    pool: &DbPool,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    write_user_settings(user_id, settings, &mut tx).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+// 在调用方的事务中写入用户设置
+pub async fn write_user_settings(
+    user_id: &str,
+    settings: &[(String, Option<String>)],
+    tx: &mut Transaction<'_, Db>,
+) -> Result<(), sqlx::Error> {
+    let now = chrono::Utc::now().timestamp();
+    for (key, value) in settings {
+        match value {
+            Some(value) => {
+                query(
+                    r#"
+                    INSERT INTO user_settings (user_id, key, value, updated_at)
+                    VALUES ($1, $2, $3, $4)
+                    ON CONFLICT(user_id, key) DO UPDATE
+                    SET value = excluded.value, updated_at = excluded.updated_at
+                    "#,
+                )
+                .bind(user_id)
+                .bind(key)
+                .bind(value)
+                .bind(now)
+                .execute(&mut *tx)
+                .await?;
+            }
+            None => {
+                query(
+                    r#"
+                    DELETE FROM user_settings
+                    WHERE user_id = $1 AND key = $2
+                    "#,
+                )
+                .bind(user_id)
+                .bind(key)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// 保存公告
+pub async fn insert_announcement(
+    announcement: &Announcement,
+    pool: &DbPool,
+) -> Result<(), sqlx::Error> {
+    query(
+        r#"
+        INSERT INTO announcements (id, level, title, content, created_at, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+    )
+    .bind(&announcement.id)
+    .bind(&announcement.level)
+    .bind(&announcement.title)
+    .bind(&announcement.content)
+    .bind(announcement.created_at)
+    .bind(announcement.expires_at)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// 获取指定时间之后发布且未过期的公告
+pub async fn get_announcements_since(
+    since: i64,
+    pool: &DbPool,
+) -> Result<Vec<Announcement>, sqlx::Error> {
+    let now = chrono::Utc::now().timestamp();
+    let rows = query(
+        r#"
+        SELECT id, level, title, content, created_at, expires_at
+        FROM announcements
+        WHERE created_at > $1 AND (expires_at IS NULL OR expires_at > $2)
+        ORDER BY created_at DESC
+        "#,
+    )
+    .bind(since)
+    .bind(now)
+    .fetch_all(pool)
+    .await?;
+
+    rows.iter()
+        .map(|row| {
+            Ok(Announcement {
+                id: row.try_get("id")?,
+                level: row.try_get("level")?,
+                title: row.try_get("title")?,
+                content: row.try_get("content")?,
+                created_at: row.try_get("created_at")?,
+                expires_at: row.try_get("expires_at")?,
+            })
+        })
+        .collect()
+}
+
+fn row_to_scheduled_clip(row: &AnyRow) -> Result<ScheduledClip, sqlx::Error> {
+    let visibility: Option<String> = row.try_get("visibility")?;
+    let content_type: String = row.try_get("content_type")?;
+    Ok(ScheduledClip {
+        id: row.try_get("id")?,
+        user_id: row.try_get("user_id")?,
+        // 早期版本未校验类型，无法识别的类型按 unknown 处理
+        content_type: ClipType::from_name(&content_type).unwrap_or(ClipType::Unknown),
+        content: row.try_get("content")?,
+        deliver_at: row.try_get("deliver_at")?,
+        created_at: row.try_get("created_at")?,
+        status: row.try_get("status")?,
+        visibility: visibility
+            .map(|text| serde_json::from_str(&text))
+            .transpose()
+            .map_err(|e| sqlx::Error::Decode(Box::new(e)))?
+            .unwrap_or_default(),
     })
-}
\ No newline at end of file
+}
+
+// 保存定时投递的剪贴板
+pub async fn insert_scheduled_clip(
+    clip: &ScheduledClip,
+    executor: impl Executor<'_, Database = Db>,
+) -> Result<(), sqlx::Error> {
+    query(
+        r#"
+        INSERT INTO scheduled_clips
+            (id, user_id, content_type, content, deliver_at, created_at, status, visibility)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+    )
+    .bind(&clip.id)
+    .bind(&clip.user_id)
+    .bind(clip.content_type.as_str())
+    .bind(&clip.content)
+    .bind(clip.deliver_at)
+    .bind(clip.created_at)
+    .bind(&clip.status)
+    .bind(visibility_to_json(&clip.visibility))
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+// 获取用户待投递的剪贴板
+pub async fn get_pending_scheduled_clips(
+    user_id: &str,
+    pool: &DbPool,
+) -> Result<Vec<ScheduledClip>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT id, user_id, content_type, content, deliver_at, created_at, status, visibility
+        FROM scheduled_clips
+        WHERE user_id = $1 AND status = 'pending'
+        ORDER BY deliver_at
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    rows.iter().map(row_to_scheduled_clip).collect()
+}
+
+// 获取已到投递时间的剪贴板
+pub async fn get_due_scheduled_clips(
+    now: i64,
+    pool: &DbPool,
+) -> Result<Vec<ScheduledClip>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT id, user_id, content_type, content, deliver_at, created_at, status, visibility
+        FROM scheduled_clips
+        WHERE status = 'pending' AND deliver_at <= $1
+        ORDER BY deliver_at
+        "#,
+    )
+    .bind(now)
+    .fetch_all(pool)
+    .await?;
+    rows.iter().map(row_to_scheduled_clip).collect()
+}
+
+// 修改待投递剪贴板的状态，返回是否修改成功（已不是 pending 状态时返回 false）
+//
+// 投递时先改为 delivered 抢占，没有会话收到时再用 release_scheduled_clip 恢复为 pending
+pub async fn update_scheduled_clip_status(
+    id: &str,
+    user_id: &str,
+    status: &str,
+    executor: impl Executor<'_, Database = Db>,
+) -> Result<bool, sqlx::Error> {
+    let result = query(
+        r#"
+        UPDATE scheduled_clips
+        SET status = $3
+        WHERE id = $1 AND user_id = $2 AND status = 'pending'
+        "#,
+    )
+    .bind(id)
+    .bind(user_id)
+    .bind(status)
+    .execute(executor)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// 投递失败（用户没有在线会话）时恢复为 pending，等待用户重新连接后投递
+pub async fn release_scheduled_clip(id: &str, pool: &DbPool) -> Result<(), sqlx::Error> {
+    query("UPDATE scheduled_clips SET status = 'pending' WHERE id = $1 AND status = 'delivered'")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+// 获取用户已到投递时间的剪贴板（用户重新连接时补发）
+pub async fn get_user_due_scheduled_clips(
+    user_id: &str,
+    now: i64,
+    pool: &DbPool,
+) -> Result<Vec<ScheduledClip>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT id, user_id, content_type, content, deliver_at, created_at, status, visibility
+        FROM scheduled_clips
+        WHERE user_id = $1 AND status = 'pending' AND deliver_at <= $2
+        ORDER BY deliver_at
+        "#,
+    )
+    .bind(user_id)
+    .bind(now)
+    .fetch_all(pool)
+    .await?;
+    rows.iter().map(row_to_scheduled_clip).collect()
+}
+
+// 按来源（clip / scheduled_clip）和内容类型统计用户剪贴板的数量和字节数
+pub async fn get_clip_usage(
+    user_id: &str,
+    pool: &DbPool,
+) -> Result<Vec<(String, String, TypeUsage)>, sqlx::Error> {
+    let rows = query(&format!(
+        r#"
+        SELECT kind, content_type, COUNT(*) AS count, CAST(SUM(bytes) AS BIGINT) AS bytes
+        FROM (
+            SELECT 'clip' AS kind, content_type, {bytes} AS bytes
+            FROM clips
+            WHERE user_id = $1
+            UNION ALL
+            SELECT 'scheduled_clip' AS kind, content_type, {bytes} AS bytes
+            FROM scheduled_clips
+            WHERE user_id = $1
+        ) AS usage
+        GROUP BY kind, content_type
+        "#,
+        bytes = byte_length("content"),
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    rows.iter()
+        .map(|row| {
+            Ok((
+                row.try_get("kind")?,
+                row.try_get("content_type")?,
+                TypeUsage {
+                    count: row.try_get("count")?,
+                    bytes: row.try_get("bytes")?,
+                },
+            ))
+        })
+        .collect()
+}
+
+// 获取用户占用空间最大的剪贴板（包括定时投递），created_at 统一为秒
+pub async fn get_largest_clips(
+    user_id: &str,
+    limit: i64,
+    pool: &DbPool,
+) -> Result<Vec<ClipUsage>, sqlx::Error> {
+    let rows = query(&format!(
+        r#"
+        SELECT 'clip' AS kind, id, content_type, {bytes} AS bytes,
+               created_at / 1000 AS created_at
+        FROM clips
+        WHERE user_id = $1
+        UNION ALL
+        SELECT 'scheduled_clip' AS kind, id, content_type, {bytes} AS bytes,
+               created_at
+        FROM scheduled_clips
+        WHERE user_id = $1
+        ORDER BY bytes DESC
+        LIMIT $2
+        "#,
+        bytes = byte_length("content"),
+    ))
+    .bind(user_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    rows.iter()
+        .map(|row| {
+            Ok(ClipUsage {
+                kind: row.try_get("kind")?,
+                id: row.try_get("id")?,
+                content_type: row.try_get("content_type")?,
+                bytes: row.try_get("bytes")?,
+                created_at: row.try_get("created_at")?,
+            })
+        })
+        .collect()
+}
+
+// 剪贴板总数和内容总字节数
+pub async fn get_clip_totals(user_id: &str, pool: &DbPool) -> Result<(i64, i64), sqlx::Error> {
+    let row = query(
+        r#"
+        SELECT COUNT(*) AS count, CAST(COALESCE(SUM(size), 0) AS BIGINT) AS bytes
+        FROM clips
+        WHERE user_id = $1 AND deleted_at IS NULL
+        "#,
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+    Ok((row.try_get("count")?, row.try_get("bytes")?))
+}
+
+// 按内容类型统计剪贴板数量
+pub async fn get_clip_type_counts(
+    user_id: &str,
+    pool: &DbPool,
+) -> Result<Vec<(ClipType, i64)>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT content_type, COUNT(*) AS count
+        FROM clips
+        WHERE user_id = $1 AND deleted_at IS NULL
+        GROUP BY content_type
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    rows.iter()
+        .map(|row| Ok((row.try_get("content_type")?, row.try_get("count")?)))
+        .collect()
+}
+
+// 按天（UTC）统计 since 之后（毫秒）创建的剪贴板数量，返回 (自 Unix 纪元起的天数, 数量)
+pub async fn get_daily_clip_counts(
+    user_id: &str,
+    since: i64,
+    pool: &DbPool,
+) -> Result<Vec<(i64, i64)>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT created_at / 86400000 AS day, COUNT(*) AS count
+        FROM clips
+        WHERE user_id = $1 AND created_at >= $2 AND deleted_at IS NULL
+        GROUP BY created_at / 86400000
+        "#,
+    )
+    .bind(user_id)
+    .bind(since)
+    .fetch_all(pool)
+    .await?;
+    rows.iter()
+        .map(|row| Ok((row.try_get("day")?, row.try_get("count")?)))
+        .collect()
+}
+
+// 剪贴板最多的来源应用
+pub async fn get_top_source_apps(
+    user_id: &str,
+    limit: i64,
+    pool: &DbPool,
+) -> Result<Vec<NamedCount>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT source_app AS name, COUNT(*) AS count
+        FROM clips
+        WHERE user_id = $1 AND source_app IS NOT NULL AND source_app <> ''
+          AND deleted_at IS NULL
+        GROUP BY source_app
+        ORDER BY count DESC, name
+        LIMIT $2
+        "#,
+    )
+    .bind(user_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    rows.iter().map(row_to_named_count).collect()
+}
+
+// 使用最多的标签（没有剪贴板使用的标签不返回）
+pub async fn get_top_tags(
+    user_id: &str,
+    limit: i64,
+    pool: &DbPool,
+) -> Result<Vec<NamedCount>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT tags.name AS name, COUNT(*) AS count
+        FROM tags
+        JOIN clip_tags ON clip_tags.tag_id = tags.id
+        JOIN clips ON clips.id = clip_tags.clip_id AND clips.deleted_at IS NULL
+        WHERE tags.user_id = $1
+        GROUP BY tags.id, tags.name
+        ORDER BY count DESC, name
+        LIMIT $2
+        "#,
+    )
+    .bind(user_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    rows.iter().map(row_to_named_count).collect()
+}
+
+fn row_to_named_count(row: &AnyRow) -> Result<NamedCount, sqlx::Error> {
+    Ok(NamedCount {
+        name: row.try_get("name")?,
+        count: row.try_get("count")?,
+    })
+}
+
+// 获取所有用户的保留策略设置，返回 (user_id, key, JSON 文本)
+pub async fn get_retention_settings(
+    pool: &DbPool,
+) -> Result<Vec<(String, String, String)>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT user_id, key, value
+        FROM user_settings
+        WHERE key IN ('retention_max_items', 'retention_max_age_days', 'retention_max_bytes')
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+    rows.iter()
+        .map(|row| Ok((row.try_get("user_id")?, row.try_get("key")?, row.try_get("value")?)))
+        .collect()
+}
+
+// 获取用户未固定的剪贴板（按创建时间从新到旧），用于计算需要清理的项目
+pub async fn get_retention_candidates(
+    user_id: &str,
+    pool: &DbPool,
+) -> Result<Vec<RetainedClip>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT id, content_type, content, size, created_at, visibility
+        FROM clips
+        WHERE user_id = $1 AND pinned = FALSE AND deleted_at IS NULL
+        ORDER BY created_at DESC, id DESC
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    rows.iter().map(row_to_retained_clip).collect()
+}
+
+// 获取所有已过期的剪贴板（包括已固定的），返回 (user_id, 剪贴板)
+pub async fn get_expired_clips(
+    now: i64,
+    pool: &DbPool,
+) -> Result<Vec<(String, RetainedClip)>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT user_id, id, content_type, content, size, created_at, visibility
+        FROM clips
+        WHERE expires_at IS NOT NULL AND expires_at <= $1
+        "#,
+    )
+    .bind(now)
+    .fetch_all(pool)
+    .await?;
+    rows.iter()
+        .map(|row| Ok((row.try_get("user_id")?, row_to_retained_clip(row)?)))
+        .collect()
+}
+
+// 获取在 before（毫秒）之前移到回收站的剪贴板，返回 (user_id, 剪贴板)
+pub async fn get_trash_expired_clips(
+    before: i64,
+    pool: &DbPool,
+) -> Result<Vec<(String, RetainedClip)>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT user_id, id, content_type, content, size, created_at, visibility
+        FROM clips
+        WHERE deleted_at IS NOT NULL AND deleted_at <= $1
+        "#,
+    )
+    .bind(before)
+    .fetch_all(pool)
+    .await?;
+    rows.iter()
+        .map(|row| Ok((row.try_get("user_id")?, row_to_retained_clip(row)?)))
+        .collect()
+}
+
+fn row_to_retained_clip(row: &AnyRow) -> Result<RetainedClip, sqlx::Error> {
+    let decode = |e: serde_json::Error| sqlx::Error::Decode(Box::new(e));
+    let id: String = row.try_get("id")?;
+    let visibility: Option<String> = row.try_get("visibility")?;
+    Ok(RetainedClip {
+        id: Uuid::parse_str(&id).map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+        content_type: row.try_get("content_type")?,
+        content: row.try_get("content")?,
+        size: row.try_get("size")?,
+        created_at: row.try_get("created_at")?,
+        visibility: visibility
+            .map(|text| serde_json::from_str(&text))
+            .transpose()
+            .map_err(decode)?
+            .unwrap_or_default(),
+    })
+}
+
+// 查询两个用户之间的联系人关系，返回 (requester_id, status)
+pub async fn get_contact_relation(
+    user_a: &str,
+    user_b: &str,
+    pool: &DbPool,
+) -> Result<Option<(String, String)>, sqlx::Error> {
+    let row = query(
+        r#"
+        SELECT requester_id, status
+        FROM contacts
+        WHERE (requester_id = $1 AND addressee_id = $2)
+           OR (requester_id = $3 AND addressee_id = $4)
+        "#,
+    )
+    .bind(user_a)
+    .bind(user_b)
+    .bind(user_b)
+    .bind(user_a)
+    .fetch_optional(pool)
+    .await?;
+
+    match row {
+        Some(row) => Ok(Some((row.try_get("requester_id")?, row.try_get("status")?))),
+        None => Ok(None),
+    }
+}
+
+// 发起联系人请求
+pub async fn insert_contact_request(
+    requester_id: &str,
+    addressee_id: &str,
+    pool: &DbPool,
+) -> Result<(), sqlx::Error> {
+    let now = chrono::Utc::now().timestamp();
+    query(
+        r#"
+        INSERT INTO contacts (requester_id, addressee_id, status, created_at, updated_at)
+        VALUES ($1, $2, 'pending', $3, $4)
+        "#,
+    )
+    .bind(requester_id)
+    .bind(addressee_id)
+    .bind(now)
+    .bind(now)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// 接受联系人请求，返回是否存在该待处理请求
+pub async fn accept_contact_request(
+    requester_id: &str,
+    addressee_id: &str,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    let result = query(
+        r#"
+        UPDATE contacts
+        SET status = 'accepted', updated_at = $3
+        WHERE requester_id = $1 AND addressee_id = $2 AND status = 'pending'
+        "#,
+    )
+    .bind(requester_id)
+    .bind(addressee_id)
+    .bind(chrono::Utc::now().timestamp())
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// 删除两个用户之间的联系人关系（拒绝/撤回请求或删除联系人）
+pub async fn delete_contact(
+    user_a: &str,
+    user_b: &str,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    let result = query(
+        r#"
+        DELETE FROM contacts
+        WHERE (requester_id = $1 AND addressee_id = $2)
+           OR (requester_id = $3 AND addressee_id = $4)
+        "#,
+    )
+    .bind(user_a)
+    .bind(user_b)
+    .bind(user_b)
+    .bind(user_a)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// 获取用户指定状态的联系人列表
+pub async fn get_contacts(
+    user_id: &str,
+    status: &str,
+    pool: &DbPool,
+) -> Result<Vec<Contact>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT u.user_id, u.username, u.head_uri, c.status, c.requester_id, c.updated_at
+        FROM contacts c
+        JOIN users u
+          ON u.user_id = CASE WHEN c.requester_id = $1 THEN c.addressee_id ELSE c.requester_id END
+        WHERE (c.requester_id = $2 OR c.addressee_id = $3) AND c.status = $4
+        ORDER BY c.updated_at DESC
+        "#,
+    )
+    .bind(user_id)
+    .bind(user_id)
+    .bind(user_id)
+    .bind(status)
+    .fetch_all(pool)
+    .await?;
+
+    rows.iter()
+        .map(|row| {
+            let requester_id: String = row.try_get("requester_id")?;
+            Ok(Contact {
+                user_id: row.try_get("user_id")?,
+                username: row.try_get("username")?,
+                head_uri: row.try_get("head_uri")?,
+                status: row.try_get("status")?,
+                outgoing: requester_id == user_id,
+                updated_at: row.try_get("updated_at")?,
+            })
+        })
+        .collect()
+}
+
+// 拉黑用户，同时解除双方的联系人关系
+pub async fn block_user(
+    user_id: &str,
+    blocked_id: &str,
+    pool: &DbPool,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    query(
+        r#"
+        INSERT INTO blocked_users (user_id, blocked_id, created_at)
+        VALUES ($1, $2, $3)
+        ON CONFLICT DO NOTHING
+        "#,
+    )
+    .bind(user_id)
+    .bind(blocked_id)
+    .bind(chrono::Utc::now().timestamp())
+    .execute(&mut tx)
+    .await?;
+    query(
+        r#"
+        DELETE FROM contacts
+        WHERE (requester_id = $1 AND addressee_id = $2)
+           OR (requester_id = $3 AND addressee_id = $4)
+        "#,
+    )
+    .bind(user_id)
+    .bind(blocked_id)
+    .bind(blocked_id)
+    .bind(user_id)
+    .execute(&mut tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+// 取消拉黑
+pub async fn unblock_user(
+    user_id: &str,
+    blocked_id: &str,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    let result = query(
+        r#"
+        DELETE FROM blocked_users
+        WHERE user_id = $1 AND blocked_id = $2
+        "#,
+    )
+    .bind(user_id)
+    .bind(blocked_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// 两个用户之间是否存在任意方向的拉黑
+// 头像是否对 viewer_id 可见：头像属于自己，或属于未互相拉黑的联系人（含待处理的请求）
+//
+// 不可见或不存在时返回 None，可见时返回记录的图片类型（早期上传的头像没有记录）
+pub async fn get_visible_avatar(
+    viewer_id: &str,
+    head_uri: &str,
+    pool: &DbPool,
+) -> Result<Option<Option<String>>, sqlx::Error> {
+    let row = query(
+        r#"
+        SELECT u.head_mime FROM users u
+        WHERE u.head_uri = $1
+          AND (u.user_id = $2
+               OR (EXISTS (
+                       SELECT 1 FROM contacts c
+                       WHERE (c.requester_id = u.user_id AND c.addressee_id = $2)
+                          OR (c.addressee_id = u.user_id AND c.requester_id = $2))
+                   AND NOT EXISTS (
+                       SELECT 1 FROM blocked_users b
+                       WHERE (b.user_id = u.user_id AND b.blocked_id = $2)
+                          OR (b.user_id = $2 AND b.blocked_id = u.user_id))))
+        "#,
+    )
+    .bind(head_uri)
+    .bind(viewer_id)
+    .fetch_optional(pool)
+    .await?;
+    row.map(|row| row.try_get("head_mime")).transpose()
+}
+
+pub async fn is_blocked_between(
+    user_a: &str,
+    user_b: &str,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    let row = query(
+        r#"
+        SELECT 1 FROM blocked_users
+        WHERE (user_id = $1 AND blocked_id = $2)
+           OR (user_id = $3 AND blocked_id = $4)
+        "#,
+    )
+    .bind(user_a)
+    .bind(user_b)
+    .bind(user_b)
+    .bind(user_a)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.is_some())
+}
+
+// 获取黑名单
+pub async fn get_blocked_users(
+    user_id: &str,
+    pool: &DbPool,
+) -> Result<Vec<BlockedUser>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT u.user_id, u.username, b.created_at
+        FROM blocked_users b
+        JOIN users u ON u.user_id = b.blocked_id
+        WHERE b.user_id = $1
+        ORDER BY b.created_at DESC
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    rows.iter()
+        .map(|row| {
+            Ok(BlockedUser {
+                user_id: row.try_get("user_id")?,
+                username: row.try_get("username")?,
+                blocked_at: row.try_get("created_at")?,
+            })
+        })
+        .collect()
+}
+
+// 发布新版本的条款，版本号为该类型当前最大版本 + 1
+pub async fn insert_legal_document(
+    kind: &str,
+    title: &str,
+    content: &str,
+    pool: &DbPool,
+) -> Result<LegalDocument, sqlx::Error> {
+    let now = chrono::Utc::now().timestamp();
+    let row = query(
+        r#"
+        INSERT INTO legal_documents (kind, version, title, content, published_at)
+        SELECT $1, COALESCE(MAX(version), 0) + 1, $2, $3, $4
+        FROM legal_documents WHERE kind = $5
+        RETURNING version
+        "#,
+    )
+    .bind(kind)
+    .bind(title)
+    .bind(content)
+    .bind(now)
+    .bind(kind)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(LegalDocument {
+        kind: kind.to_string(),
+        version: row.try_get("version")?,
+        title: title.to_string(),
+        content: content.to_string(),
+        published_at: now,
+    })
+}
+
+// 获取每种条款的最新版本
+pub async fn get_current_legal_documents(
+    pool: &DbPool,
+) -> Result<Vec<LegalDocument>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT d.kind, d.version, d.title, d.content, d.published_at
+        FROM legal_documents d
+        JOIN (SELECT kind, MAX(version) AS version FROM legal_documents GROUP BY kind) latest
+          ON d.kind = latest.kind AND d.version = latest.version
+        ORDER BY d.kind
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    rows.iter()
+        .map(|row| {
+            Ok(LegalDocument {
+                kind: row.try_get("kind")?,
+                version: row.try_get("version")?,
+                title: row.try_get("title")?,
+                content: row.try_get("content")?,
+                published_at: row.try_get("published_at")?,
+            })
+        })
+        .collect()
+}
+
+// 记录用户接受了指定版本的条款
+pub async fn insert_legal_acceptance(
+    user_id: &str,
+    kind: &str,
+    version: i64,
+    pool: &DbPool,
+) -> Result<(), sqlx::Error> {
+    query(
+        r#"
+        INSERT INTO legal_acceptances (user_id, kind, version, accepted_at)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT DO NOTHING
+        "#,
+    )
+    .bind(user_id)
+    .bind(kind)
+    .bind(version)
+    .bind(chrono::Utc::now().timestamp())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// 获取用户的条款接受历史
+pub async fn get_legal_acceptances(
+    user_id: &str,
+    pool: &DbPool,
+) -> Result<Vec<LegalAcceptance>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT kind, version, accepted_at
+        FROM legal_acceptances
+        WHERE user_id = $1
+        ORDER BY accepted_at DESC
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    rows.iter()
+        .map(|row| {
+            Ok(LegalAcceptance {
+                kind: row.try_get("kind")?,
+                version: row.try_get("version")?,
+                accepted_at: row.try_get("accepted_at")?,
+            })
+        })
+        .collect()
+}
+
+// 用户是否有未接受的最新条款
+pub async fn has_pending_terms(user_id: &str, pool: &DbPool) -> Result<bool, sqlx::Error> {
+    let row = query(
+        r#"
+        SELECT COUNT(*) AS pending
+        FROM (SELECT kind, MAX(version) AS version FROM legal_documents GROUP BY kind) latest
+        WHERE NOT EXISTS (
+            SELECT 1 FROM legal_acceptances a
+            WHERE a.user_id = $1 AND a.kind = latest.kind AND a.version = latest.version
+        )
+        "#,
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+    let pending: i64 = row.try_get("pending")?;
+    Ok(pending > 0)
+}
+
+// 邮箱是否已被使用
+pub async fn email_exists(email: &str, pool: &DbPool) -> Result<bool, sqlx::Error> {
+    let row = query("SELECT 1 FROM users WHERE email = $1")
+        .bind(email)
+        .fetch_optional(pool)
+        .await?;
+    Ok(row.is_some())
+}
+
+// 保存邮箱修改请求（同一用户只保留最新的一条）
+pub async fn insert_email_change(
+    token: &str,
+    user_id: &str,
+    new_email: &str,
+    expires_at: i64,
+    pool: &DbPool,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    query("DELETE FROM email_changes WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut tx)
+        .await?;
+    query(
+        r#"
+        INSERT INTO email_changes (token, user_id, new_email, expires_at)
+        VALUES ($1, $2, $3, $4)
+        "#,
+    )
+    .bind(token)
+    .bind(user_id)
+    .bind(new_email)
+    .bind(expires_at)
+    .execute(&mut tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+// 取出并删除未过期的邮箱修改请求，返回 (user_id, new_email)
+// 保存邮箱验证令牌（同一用户只保留最新的令牌）
+pub async fn insert_email_verification(
+    token: &str,
+    user_id: &str,
+    expires_at: i64,
+    pool: &DbPool,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    query("DELETE FROM email_verifications WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut tx)
+        .await?;
+    query("INSERT INTO email_verifications (token, user_id, expires_at) VALUES ($1, $2, $3)")
+        .bind(token)
+        .bind(user_id)
+        .bind(expires_at)
+        .execute(&mut tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+// 使用邮箱验证令牌（只能使用一次），验证成功返回用户 ID
+pub async fn verify_email(token: &str, pool: &DbPool) -> Result<Option<String>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let row = query(
+        r#"
+        DELETE FROM email_verifications
+        WHERE token = $1 AND expires_at > $2
+        RETURNING user_id
+        "#,
+    )
+    .bind(token)
+    .bind(chrono::Utc::now().timestamp())
+    .fetch_optional(&mut tx)
+    .await?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    let user_id: String = row.try_get("user_id")?;
+    query(
+        r#"
+        UPDATE users SET email_verified_at = $2
+        WHERE user_id = $1 AND email_verified_at IS NULL
+        "#,
+    )
+    .bind(&user_id)
+    .bind(chrono::Utc::now().timestamp())
+    .execute(&mut tx)
+    .await?;
+    tx.commit().await?;
+    Ok(Some(user_id))
+}
+
+// 直接标记邮箱已验证（管理员创建的账号）
+pub async fn mark_email_verified(user_id: &str, pool: &DbPool) -> Result<(), sqlx::Error> {
+    query(
+        r#"
+        UPDATE users SET email_verified_at = $2
+        WHERE user_id = $1 AND email_verified_at IS NULL
+        "#,
+    )
+    .bind(user_id)
+    .bind(chrono::Utc::now().timestamp())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// 保存找回密码令牌的哈希（之前的令牌失效）
+pub async fn insert_password_reset_token(
+    token_hash: &str,
+    user_id: &str,
+    expires_at: i64,
+    pool: &DbPool,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    query("DELETE FROM password_reset_tokens WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut tx)
+        .await?;
+    query("INSERT INTO password_reset_tokens (token_hash, user_id, expires_at) VALUES ($1, $2, $3)")
+        .bind(token_hash)
+        .bind(user_id)
+        .bind(expires_at)
+        .execute(&mut tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+// 使用找回密码令牌重置密码（只能使用一次），同时注销全部刷新令牌，成功返回用户 ID
+//
+// 能收到重置邮件说明邮箱属于该用户，未验证的邮箱同时标记为已验证
+pub async fn reset_password(
+    token_hash: &str,
+    new_password: &str,
+    pool: &DbPool,
+) -> Result<Option<String>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let row = query(
+        r#"
+        DELETE FROM password_reset_tokens
+        WHERE token_hash = $1 AND expires_at > $2
+        RETURNING user_id
+        "#,
+    )
+    .bind(token_hash)
+    .bind(Utc::now().timestamp())
+    .fetch_optional(&mut tx)
+    .await?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    let user_id: String = row.try_get("user_id")?;
+    query(
+        r#"
+        UPDATE users
+        SET password = $2, email_verified_at = COALESCE(email_verified_at, $3)
+        WHERE user_id = $1
+        "#,
+    )
+    .bind(&user_id)
+    .bind(new_password)
+    .bind(Utc::now().timestamp())
+    .execute(&mut tx)
+    .await?;
+    revoke_user_refresh_tokens(&user_id, &mut tx).await?;
+    tx.commit().await?;
+    Ok(Some(user_id))
+}
+
+pub async fn take_email_change(
+    token: &str,
+    pool: &DbPool,
+) -> Result<Option<(String, String)>, sqlx::Error> {
+    let row = query(
+        r#"
+        DELETE FROM email_changes
+        WHERE token = $1 AND expires_at > $2
+        RETURNING user_id, new_email
+        "#,
+    )
+    .bind(token)
+    .bind(chrono::Utc::now().timestamp())
+    .fetch_optional(pool)
+    .await?;
+
+    match row {
+        Some(row) => Ok(Some((row.try_get("user_id")?, row.try_get("new_email")?))),
+        None => Ok(None),
+    }
+}
+
+// 修改邮箱
+pub async fn update_email(
+    user_id: &str,
+    email: &str,
+    pool: &DbPool,
+) -> Result<(), sqlx::Error> {
+    query(
+        r#"
+        UPDATE users
+        SET email = $2, version = version + 1,
+            email_verified_at = $3
+        WHERE user_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .bind(email)
+    .bind(chrono::Utc::now().timestamp())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// 保存进行中的第三方登录流程（顺带清理已过期的流程），user_id 不为空时绑定到已登录的账号
+pub async fn insert_oauth_state(
+    state: &str,
+    provider: &str,
+    user_id: Option<&str>,
+    redirect_uri: Option<&str>,
+    expires_at: i64,
+    pool: &DbPool,
+) -> Result<(), sqlx::Error> {
+    query("DELETE FROM oauth_states WHERE expires_at <= $1")
+        .bind(Utc::now().timestamp())
+        .execute(pool)
+        .await?;
+    query(
+        r#"
+        INSERT INTO oauth_states (state, provider, user_id, redirect_uri, expires_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+    )
+    .bind(state)
+    .bind(provider)
+    .bind(user_id)
+    .bind(redirect_uri)
+    .bind(expires_at)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// 取出并删除未过期的登录流程（只能使用一次），返回 (user_id, redirect_uri)
+pub async fn take_oauth_state(
+    state: &str,
+    provider: &str,
+    pool: &DbPool,
+) -> Result<Option<(Option<String>, Option<String>)>, sqlx::Error> {
+    let row = query(
+        r#"
+        DELETE FROM oauth_states
+        WHERE state = $1 AND provider = $2 AND expires_at > $3 AND subject IS NULL
+        RETURNING user_id, redirect_uri
+        "#,
+    )
+    .bind(state)
+    .bind(provider)
+    .bind(Utc::now().timestamp())
+    .fetch_optional(pool)
+    .await?;
+    row.map(|row| Ok((row.try_get("user_id")?, row.try_get("redirect_uri")?)))
+        .transpose()
+}
+
+// 保存待客户端确认的绑定，code 为确认码
+pub async fn insert_pending_link(
+    code: &str,
+    provider: &str,
+    user_id: &str,
+    subject: &str,
+    email: Option<&str>,
+    expires_at: i64,
+    pool: &DbPool,
+) -> Result<(), sqlx::Error> {
+    query(
+        r#"
+        INSERT INTO oauth_states (state, provider, user_id, subject, email, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+    )
+    .bind(code)
+    .bind(provider)
+    .bind(user_id)
+    .bind(subject)
+    .bind(email)
+    .bind(expires_at)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// 取出并删除发起绑定的用户未过期的待确认绑定，返回 (subject, email)
+pub async fn take_pending_link(
+    code: &str,
+    provider: &str,
+    user_id: &str,
+    pool: &DbPool,
+) -> Result<Option<(String, Option<String>)>, sqlx::Error> {
+    let row = query(
+        r#"
+        DELETE FROM oauth_states
+        WHERE state = $1 AND provider = $2 AND user_id = $3 AND expires_at > $4
+            AND subject IS NOT NULL
+        RETURNING subject, email
+        "#,
+    )
+    .bind(code)
+    .bind(provider)
+    .bind(user_id)
+    .bind(Utc::now().timestamp())
+    .fetch_optional(pool)
+    .await?;
+    row.map(|row| Ok((row.try_get("subject")?, row.try_get("email")?)))
+        .transpose()
+}
+
+// 把外部身份绑定到用户，已绑定到其他用户时返回 false
+pub async fn insert_identity(
+    provider: &str,
+    subject: &str,
+    user_id: &str,
+    email: Option<&str>,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    query(
+        r#"
+        INSERT INTO identities (provider, subject, user_id, email, created_at)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT DO NOTHING
+        "#,
+    )
+    .bind(provider)
+    .bind(subject)
+    .bind(user_id)
+    .bind(email)
+    .bind(Utc::now().timestamp())
+    .execute(pool)
+    .await?;
+    let owner = query("SELECT user_id FROM identities WHERE provider = $1 AND subject = $2")
+        .bind(provider)
+        .bind(subject)
+        .fetch_one(pool)
+        .await?;
+    Ok(owner.try_get::<String, _>("user_id")? == user_id)
+}
+
+// 以外部身份注册新用户（没有可用的密码，需要时通过找回密码设置）
+pub async fn insert_oauth_user(
+    username: &str,
+    email: &str,
+    email_verified: bool,
+    provider: &str,
+    subject: &str,
+    pool: &DbPool,
+) -> Result<String, sqlx::Error> {
+    let user_id = Uuid::new_v4().to_string();
+    let now = Utc::now().timestamp();
+    let mut tx = pool.begin().await?;
+    query(
+        r#"
+        INSERT INTO users (user_id, username, email, password, email_verified_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+    )
+    .bind(&user_id)
+    .bind(username)
+    .bind(email)
+    .bind(Uuid::new_v4().simple().to_string())
+    .bind(email_verified.then_some(now))
+    .execute(&mut tx)
+    .await?;
+    query(
+        r#"
+        INSERT INTO identities (provider, subject, user_id, email, created_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+    )
+    .bind(provider)
+    .bind(subject)
+    .bind(&user_id)
+    .bind(email)
+    .bind(now)
+    .execute(&mut tx)
+    .await?;
+    tx.commit().await?;
+    Ok(user_id)
+}
+
+// 用户绑定的全部外部身份
+pub async fn get_identities(
+    user_id: &str,
+    pool: &DbPool,
+) -> Result<Vec<LinkedIdentity>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT provider, email, created_at FROM identities
+        WHERE user_id = $1
+        ORDER BY created_at
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    rows.iter()
+        .map(|row| {
+            Ok(LinkedIdentity {
+                provider: row.try_get("provider")?,
+                email: row.try_get("email")?,
+                created_at: row.try_get("created_at")?,
+            })
+        })
+        .collect()
+}
+
+// 解除绑定提供方的外部身份，未绑定时返回 false
+pub async fn delete_identity(
+    user_id: &str,
+    provider: &str,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    let result = query("DELETE FROM identities WHERE user_id = $1 AND provider = $2")
+        .bind(user_id)
+        .bind(provider)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// 占用幂等键（顺带清理该用户已过期的键），键已存在时返回 false
+pub async fn claim_idempotency_key(
+    user_id: &str,
+    key: &str,
+    fingerprint: &str,
+    expired_before: i64,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    query("DELETE FROM idempotency_keys WHERE user_id = $1 AND created_at < $2")
+        .bind(user_id)
+        .bind(expired_before)
+        .execute(&mut tx)
+        .await?;
+    let result = query(
+        r#"
+        INSERT INTO idempotency_keys (user_id, key, fingerprint, created_at)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT DO NOTHING
+        "#,
+    )
+    .bind(user_id)
+    .bind(key)
+    .bind(fingerprint)
+    .bind(chrono::Utc::now().timestamp())
+    .execute(&mut tx)
+    .await?;
+    tx.commit().await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// 查询幂等键记录，返回 (fingerprint, response)
+pub async fn get_idempotency_record(
+    user_id: &str,
+    key: &str,
+    pool: &DbPool,
+) -> Result<Option<(String, Option<String>)>, sqlx::Error> {
+    let row = query(
+        r#"
+        SELECT fingerprint, response
+        FROM idempotency_keys
+        WHERE user_id = $1 AND key = $2
+        "#,
+    )
+    .bind(user_id)
+    .bind(key)
+    .fetch_optional(pool)
+    .await?;
+
+    match row {
+        Some(row) => Ok(Some((row.try_get("fingerprint")?, row.try_get("response")?))),
+        None => Ok(None),
+    }
+}
+
+// 保存幂等键对应的响应
+pub async fn complete_idempotency_key(
+    user_id: &str,
+    key: &str,
+    response: &str,
+    pool: &DbPool,
+) -> Result<(), sqlx::Error> {
+    query(
+        r#"
+        UPDATE idempotency_keys
+        SET response = $3
+        WHERE user_id = $1 AND key = $2
+        "#,
+    )
+    .bind(user_id)
+    .bind(key)
+    .bind(response)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// 释放幂等键，请求失败后允许客户端使用同一个键重试
+pub async fn release_idempotency_key(
+    user_id: &str,
+    key: &str,
+    pool: &DbPool,
+) -> Result<(), sqlx::Error> {
+    query("DELETE FROM idempotency_keys WHERE user_id = $1 AND key = $2")
+        .bind(user_id)
+        .bind(key)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+fn row_to_sync_conflict(row: &AnyRow) -> Result<SyncConflict, sqlx::Error> {
+    let parse = |text: String| -> Result<serde_json::Value, sqlx::Error> {
+        serde_json::from_str(&text).map_err(|e| sqlx::Error::Decode(Box::new(e)))
+    };
+    let resolution: Option<String> = row.try_get("resolution")?;
+    Ok(SyncConflict {
+        id: row.try_get("id")?,
+        item_id: row.try_get("item_id")?,
+        local: parse(row.try_get("local")?)?,
+        remote: parse(row.try_get("remote")?)?,
+        created_at: row.try_get("created_at")?,
+        resolution: resolution.map(parse).transpose()?,
+        resolved_at: row.try_get("resolved_at")?,
+    })
+}
+
+// 统计用户同步冲突记录占用的字节数
+pub async fn get_sync_conflict_bytes(user_id: &str, pool: &DbPool) -> Result<i64, sqlx::Error> {
+    let row = query(&format!(
+        r#"
+        SELECT CAST(COALESCE(SUM({} + {} + COALESCE({}, 0)), 0) AS BIGINT) AS bytes
+        FROM sync_conflicts
+        WHERE user_id = $1
+        "#,
+        byte_length("local"),
+        byte_length("remote"),
+        byte_length("resolution"),
+    ))
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+    row.try_get("bytes")
+}
+
+// 保存同步冲突并把剪贴板标记为冲突状态，同一项目未解决的旧冲突会被替换
+pub async fn insert_sync_conflict(
+    user_id: &str,
+    conflict: &SyncConflict,
+    pool: &DbPool,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    query(
+        r#"
+        DELETE FROM sync_conflicts
+        WHERE user_id = $1 AND item_id = $2 AND resolved_at IS NULL
+        "#,
+    )
+    .bind(user_id)
+    .bind(&conflict.item_id)
+    .execute(&mut tx)
+    .await?;
+    query(
+        r#"
+        INSERT INTO sync_conflicts (id, user_id, item_id, local, remote, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+    )
+    .bind(&conflict.id)
+    .bind(user_id)
+    .bind(&conflict.item_id)
+    .bind(conflict.local.to_string())
+    .bind(conflict.remote.to_string())
+    .bind(conflict.created_at)
+    .execute(&mut tx)
+    .await?;
+    query(
+        "UPDATE clips SET sync_status = $3 WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL",
+    )
+    .bind(&conflict.item_id)
+    .bind(user_id)
+    .bind(SyncStatus::Conflict)
+    .execute(&mut tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+// 获取用户未解决的同步冲突
+pub async fn get_open_sync_conflicts(
+    user_id: &str,
+    pool: &DbPool,
+) -> Result<Vec<SyncConflict>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT id, item_id, local, remote, created_at, resolution, resolved_at
+        FROM sync_conflicts
+        WHERE user_id = $1 AND resolved_at IS NULL
+        ORDER BY created_at DESC
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    rows.iter().map(row_to_sync_conflict).collect()
+}
+
+// 获取剪贴板未解决的同步冲突
+pub async fn get_open_sync_conflict_for_item(
+    user_id: &str,
+    item_id: &str,
+    pool: &DbPool,
+) -> Result<Option<SyncConflict>, sqlx::Error> {
+    let row = query(
+        r#"
+        SELECT id, item_id, local, remote, created_at, resolution, resolved_at
+        FROM sync_conflicts
+        WHERE user_id = $1 AND item_id = $2 AND resolved_at IS NULL
+        "#,
+    )
+    .bind(user_id)
+    .bind(item_id)
+    .fetch_optional(pool)
+    .await?;
+    row.as_ref().map(row_to_sync_conflict).transpose()
+}
+
+// 获取单个同步冲突
+pub async fn get_sync_conflict(
+    id: &str,
+    user_id: &str,
+    pool: &DbPool,
+) -> Result<Option<SyncConflict>, sqlx::Error> {
+    let row = query(
+        r#"
+        SELECT id, item_id, local, remote, created_at, resolution, resolved_at
+        FROM sync_conflicts
+        WHERE id = $1 AND user_id = $2
+        "#,
+    )
+    .bind(id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+    row.as_ref().map(row_to_sync_conflict).transpose()
+}
+
+// 记录冲突的解决结果，并把采用的版本写回剪贴板（同步状态恢复为已同步、版本号加 1）
+//
+// 返回解决时间和剪贴板的新版本号，冲突已被解决时返回 None，剪贴板已被删除时版本号为 None。
+// 内容哈希与其他剪贴板重复时保持为空
+pub async fn resolve_sync_conflict(
+    id: &str,
+    user_id: &str,
+    resolution: &serde_json::Value,
+    clip: Option<&ClipItem>,
+    pool: &DbPool,
+) -> Result<Option<(i64, Option<i64>)>, sqlx::Error> {
+    let now = chrono::Utc::now().timestamp();
+    let mut tx = pool.begin().await?;
+    let result = query(
+        r#"
+        UPDATE sync_conflicts
+        SET resolution = $3, resolved_at = $4
+        WHERE id = $1 AND user_id = $2 AND resolved_at IS NULL
+        "#,
+    )
+    .bind(id)
+    .bind(user_id)
+    .bind(resolution.to_string())
+    .bind(now)
+    .execute(&mut tx)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Ok(None);
+    }
+    let Some(clip) = clip else {
+        tx.commit().await?;
+        return Ok(Some((now, None)));
+    };
+
+    let previous = get_clip_revision_state(user_id, &clip.id, &mut tx).await?;
+    let row = query(
+        r#"
+        UPDATE clips
+        SET content = $3, preview = $4, size = $5, accessed_at = $6, sync_status = $7,
+            content_hash = CASE
+                WHEN EXISTS (SELECT 1 FROM clips AS other
+                             WHERE other.user_id = $2 AND other.content_hash = $8
+                               AND other.id != $1)
+                THEN NULL ELSE $8 END,
+            version = version + 1
+        WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL
+        RETURNING version
+        "#,
+    )
+    .bind(clip.id.to_string())
+    .bind(user_id)
+    .bind(&clip.content)
+    .bind(&clip.preview)
+    .bind(clip.size)
+    .bind(clip.accessed_at.timestamp_millis())
+    .bind(SyncStatus::Synced)
+    .bind(content_hash(clip.content_type, &clip.content))
+    .fetch_optional(&mut tx)
+    .await?;
+    let version = match row {
+        Some(row) => {
+            replace_clip_tags(user_id, &clip.id, &clip.tags, &mut tx).await?;
+            if let Some(previous) = previous {
+                write_clip_revision(user_id, &clip.id, &previous, clip, &mut tx).await?;
+            }
+            Some(row.try_get("version")?)
+        }
+        None => None,
+    };
+    tx.commit().await?;
+    Ok(Some((now, version)))
+}
+
+// 获取用户的设备分组（含组内设备）
+pub async fn get_device_groups(
+    user_id: &str,
+    pool: &DbPool,
+) -> Result<Vec<DeviceGroup>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT id, name, isolated, created_at
+        FROM device_groups
+        WHERE user_id = $1
+        ORDER BY created_at
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    let mut groups = rows
+        .iter()
+        .map(|row| {
+            Ok(DeviceGroup {
+                id: row.try_get("id")?,
+                name: row.try_get("name")?,
+                isolated: row.try_get("isolated")?,
+                device_ids: Vec::new(),
+                created_at: row.try_get("created_at")?,
+            })
+        })
+        .collect::<Result<Vec<_>, sqlx::Error>>()?;
+
+    let members = query(
+        r#"
+        SELECT m.group_id, m.device_id
+        FROM device_group_members m
+        JOIN device_groups g ON g.id = m.group_id
+        WHERE g.user_id = $1
+        ORDER BY m.device_id
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    for row in members {
+        let group_id: String = row.try_get("group_id")?;
+        if let Some(group) = groups.iter_mut().find(|group| group.id == group_id) {
+            group.device_ids.push(row.try_get("device_id")?);
+        }
+    }
+    Ok(groups)
+}
+
+// 写入分组内的设备（先清空再插入）
+async fn replace_device_group_members(
+    group: &DeviceGroup,
+    tx: &mut Transaction<'_, Db>,
+) -> Result<(), sqlx::Error> {
+    query("DELETE FROM device_group_members WHERE group_id = $1")
+        .bind(&group.id)
+        .execute(&mut *tx)
+        .await?;
+    for device_id in &group.device_ids {
+        query(
+            r#"
+            INSERT INTO device_group_members (group_id, device_id) VALUES ($1, $2)
+            ON CONFLICT DO NOTHING
+            "#,
+        )
+        .bind(&group.id)
+        .bind(device_id)
+        .execute(&mut *tx)
+        .await?;
+    }
+    Ok(())
+}
+
+// 创建设备分组，名称已存在时返回 false
+pub async fn insert_device_group(
+    user_id: &str,
+    group: &DeviceGroup,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let result = query(
+        r#"
+        INSERT INTO device_groups (id, user_id, name, isolated, created_at)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT DO NOTHING
+        "#,
+    )
+    .bind(&group.id)
+    .bind(user_id)
+    .bind(&group.name)
+    .bind(group.isolated)
+    .bind(group.created_at)
+    .execute(&mut tx)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+    replace_device_group_members(group, &mut tx).await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+// 修改设备分组，名称与其他分组重复时返回 false
+pub async fn update_device_group(
+    user_id: &str,
+    group: &DeviceGroup,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let duplicate = query(
+        r#"
+        SELECT 1 FROM device_groups
+        WHERE user_id = $1 AND name = $2 AND id <> $3
+        "#,
+    )
+    .bind(user_id)
+    .bind(&group.name)
+    .bind(&group.id)
+    .fetch_optional(&mut tx)
+    .await?;
+    if duplicate.is_some() {
+        return Ok(false);
+    }
+    query(
+        r#"
+        UPDATE device_groups
+        SET name = $3, isolated = $4
+        WHERE id = $1 AND user_id = $2
+        "#,
+    )
+    .bind(&group.id)
+    .bind(user_id)
+    .bind(&group.name)
+    .bind(group.isolated)
+    .execute(&mut tx)
+    .await?;
+    replace_device_group_members(group, &mut tx).await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+// 删除设备分组
+pub async fn delete_device_group(
+    user_id: &str,
+    id: &str,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let result = query("DELETE FROM device_groups WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user_id)
+        .execute(&mut tx)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+    query("DELETE FROM device_group_members WHERE group_id = $1")
+        .bind(id)
+        .execute(&mut tx)
+        .await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+// 获取用户的标签及使用该标签的剪贴板数量，按名称排序
+pub async fn get_tags(user_id: &str, pool: &DbPool) -> Result<Vec<Tag>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT tags.id, tags.name, tags.created_at, COUNT(clips.id) AS clip_count
+        FROM tags
+        LEFT JOIN clip_tags ON clip_tags.tag_id = tags.id
+        LEFT JOIN clips ON clips.id = clip_tags.clip_id AND clips.deleted_at IS NULL
+        WHERE tags.user_id = $1
+        GROUP BY tags.id, tags.name, tags.created_at
+        ORDER BY tags.name
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    rows.iter()
+        .map(|row| {
+            Ok(Tag {
+                id: row.try_get("id")?,
+                name: row.try_get("name")?,
+                clip_count: row.try_get("clip_count")?,
+                created_at: row.try_get("created_at")?,
+            })
+        })
+        .collect()
+}
+
+// 创建标签，名称已存在时返回 false
+pub async fn insert_tag(user_id: &str, tag: &Tag, pool: &DbPool) -> Result<bool, sqlx::Error> {
+    let result = query(
+        r#"
+        INSERT INTO tags (id, user_id, name, created_at)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT DO NOTHING
+        "#,
+    )
+    .bind(&tag.id)
+    .bind(user_id)
+    .bind(&tag.name)
+    .bind(tag.created_at)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// 重命名标签，标签不存在时返回 None，名称与其他标签重复时返回 Some(false)
+pub async fn rename_tag(
+    user_id: &str,
+    id: &str,
+    name: &str,
+    pool: &DbPool,
+) -> Result<Option<bool>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let duplicate = query("SELECT 1 FROM tags WHERE user_id = $1 AND name = $2 AND id <> $3")
+        .bind(user_id)
+        .bind(name)
+        .bind(id)
+        .fetch_optional(&mut tx)
+        .await?;
+    if duplicate.is_some() {
+        return Ok(Some(false));
+    }
+    let result = query("UPDATE tags SET name = $3 WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user_id)
+        .bind(name)
+        .execute(&mut tx)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Ok(None);
+    }
+    tx.commit().await?;
+    Ok(Some(true))
+}
+
+// 删除标签及其与剪贴板的关联
+pub async fn delete_tag(user_id: &str, id: &str, pool: &DbPool) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let result = query("DELETE FROM tags WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user_id)
+        .execute(&mut tx)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+    query("DELETE FROM clip_tags WHERE tag_id = $1")
+        .bind(id)
+        .execute(&mut tx)
+        .await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+// 管理后台统计：用户数、数据库大小和各队列深度
+pub async fn get_database_stats(now: i64, pool: &DbPool) -> Result<DatabaseStats, sqlx::Error> {
+    let row = query(&format!(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM users) AS users,
+            ({}) AS db_bytes,
+            (SELECT COUNT(*) FROM scheduled_clips WHERE status = 'pending')
+                AS pending_scheduled_clips,
+            (SELECT COUNT(*) FROM sync_conflicts WHERE resolved_at IS NULL)
+                AS open_sync_conflicts,
+            (SELECT COUNT(*) FROM clips) AS clips,
+            (SELECT COUNT(*) FROM clips WHERE created_at > $2) AS clips_last_minute,
+            (SELECT COUNT(*) FROM scheduled_clips WHERE created_at > $1)
+                AS scheduled_clips_last_minute
+        "#,
+        database_size(),
+    ))
+    .bind(now - 60)
+    .bind((now - 60) * 1000)
+    .fetch_one(pool)
+    .await?;
+    Ok(DatabaseStats {
+        users: row.try_get("users")?,
+        db_bytes: row.try_get("db_bytes")?,
+        pending_scheduled_clips: row.try_get("pending_scheduled_clips")?,
+        open_sync_conflicts: row.try_get("open_sync_conflicts")?,
+        clips: row.try_get("clips")?,
+        clips_last_minute: row.try_get("clips_last_minute")?,
+        scheduled_clips_last_minute: row.try_get("scheduled_clips_last_minute")?,
+    })
+}
+
+// 把 SQLite 数据库完整复制到 path（VACUUM INTO），目标文件已存在时失败
+pub async fn vacuum_into(path: &str, pool: &DbPool) -> Result<(), sqlx::Error> {
+    query("VACUUM INTO $1").bind(path).execute(pool).await?;
+    Ok(())
+}
+
+// 整理数据库文件，回收已删除数据占用的空间（SQLite 和 PostgreSQL 均为 VACUUM）
+//
+// VACUUM 不能在事务或预编译语句中执行，以普通文本查询发送
+pub async fn vacuum(pool: &DbPool) -> Result<(), sqlx::Error> {
+    pool.execute("VACUUM").await?;
+    Ok(())
+}
+
+fn row_to_abuse_report(row: &AnyRow) -> Result<AbuseReport, sqlx::Error> {
+    Ok(AbuseReport {
+        id: row.try_get("id")?,
+        reporter_id: row.try_get("reporter_id")?,
+        target_type: row.try_get("target_type")?,
+        target_id: row.try_get("target_id")?,
+        reported_user_id: row.try_get("reported_user_id")?,
+        reason: row.try_get("reason")?,
+        details: row.try_get("details")?,
+        created_at: row.try_get("created_at")?,
+        status: row.try_get("status")?,
+        action: row.try_get("action")?,
+        note: row.try_get("note")?,
+        resolved_at: row.try_get("resolved_at")?,
+    })
+}
+
+// 保存举报
+pub async fn insert_abuse_report(
+    report: &AbuseReport,
+    pool: &DbPool,
+) -> Result<(), sqlx::Error> {
+    query(
+        r#"
+        INSERT INTO abuse_reports
+            (id, reporter_id, target_type, target_id, reported_user_id, reason, details, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+    )
+    .bind(&report.id)
+    .bind(&report.reporter_id)
+    .bind(&report.target_type)
+    .bind(&report.target_id)
+    .bind(&report.reported_user_id)
+    .bind(&report.reason)
+    .bind(&report.details)
+    .bind(report.created_at)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// 按状态获取举报，最早的在前
+pub async fn get_abuse_reports(
+    status: &str,
+    pool: &DbPool,
+) -> Result<Vec<AbuseReport>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT id, reporter_id, target_type, target_id, reported_user_id, reason, details,
+               created_at, status, action, note, resolved_at
+        FROM abuse_reports
+        WHERE status = $1
+        ORDER BY created_at
+        "#,
+    )
+    .bind(status)
+    .fetch_all(pool)
+    .await?;
+    rows.iter().map(row_to_abuse_report).collect()
+}
+
+// 获取单个举报
+pub async fn get_abuse_report(
+    id: &str,
+    pool: &DbPool,
+) -> Result<Option<AbuseReport>, sqlx::Error> {
+    let row = query(
+        r#"
+        SELECT id, reporter_id, target_type, target_id, reported_user_id, reason, details,
+               created_at, status, action, note, resolved_at
+        FROM abuse_reports
+        WHERE id = $1
+        "#,
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    row.as_ref().map(row_to_abuse_report).transpose()
+}
+
+// 记录举报的处理结果，返回处理时间（已处理时返回 None）
+pub async fn resolve_abuse_report(
+    id: &str,
+    action: &str,
+    note: Option<&str>,
+    pool: &DbPool,
+) -> Result<Option<i64>, sqlx::Error> {
+    let now = chrono::Utc::now().timestamp();
+    let result = query(
+        r#"
+        UPDATE abuse_reports
+        SET status = 'resolved', action = $2, note = $3, resolved_at = $4
+        WHERE id = $1 AND status = 'open'
+        "#,
+    )
+    .bind(id)
+    .bind(action)
+    .bind(note)
+    .bind(now)
+    .execute(pool)
+    .await?;
+    Ok((result.rows_affected() > 0).then_some(now))
+}
+
+// 在一个事务中执行清除语句（参数均为 $1），返回各表删除的行数
+async fn purge_rows(
+    statements: &[(&'static str, &str)],
+    id: &str,
+    pool: &DbPool,
+) -> Result<Vec<(&'static str, u64)>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let mut deleted = Vec::with_capacity(statements.len());
+    for (table, sql) in statements {
+        let result = query(sql).bind(id).execute(&mut tx).await?;
+        deleted.push((*table, result.rows_affected()));
+    }
+    tx.commit().await?;
+    Ok(deleted)
+}
+
+// 删除用户的全部数据
+pub async fn purge_user_data(
+    user_id: &str,
+    pool: &DbPool,
+) -> Result<Vec<(&'static str, u64)>, sqlx::Error> {
+    const STATEMENTS: &[(&str, &str)] = &[
+        ("user_settings", "DELETE FROM user_settings WHERE user_id = $1"),
+        (
+            "clip_tags",
+            "DELETE FROM clip_tags WHERE clip_id IN (SELECT id FROM clips WHERE user_id = $1)",
+        ),
+        (
+            "clip_revisions",
+            "DELETE FROM clip_revisions WHERE user_id = $1",
+        ),
+        ("clips", "DELETE FROM clips WHERE user_id = $1"),
+        ("shares", "DELETE FROM shares WHERE user_id = $1"),
+        ("change_log", "DELETE FROM change_log WHERE user_id = $1"),
+        ("tags", "DELETE FROM tags WHERE user_id = $1"),
+        ("devices", "DELETE FROM devices WHERE user_id = $1"),
+        (
+            "device_sync_rules",
+            "DELETE FROM device_sync_rules WHERE user_id = $1",
+        ),
+        ("scheduled_clips", "DELETE FROM scheduled_clips WHERE user_id = $1"),
+        (
+            "contacts",
+            "DELETE FROM contacts WHERE requester_id = $1 OR addressee_id = $1",
+        ),
+        (
+            "blocked_users",
+            "DELETE FROM blocked_users WHERE user_id = $1 OR blocked_id = $1",
+        ),
+        ("legal_acceptances", "DELETE FROM legal_acceptances WHERE user_id = $1"),
+        ("email_changes", "DELETE FROM email_changes WHERE user_id = $1"),
+        (
+            "email_verifications",
+            "DELETE FROM email_verifications WHERE user_id = $1",
+        ),
+        (
+            "password_reset_tokens",
+            "DELETE FROM password_reset_tokens WHERE user_id = $1",
+        ),
+        ("username_history", "DELETE FROM username_history WHERE user_id = $1"),
+        ("idempotency_keys", "DELETE FROM idempotency_keys WHERE user_id = $1"),
+        ("refresh_tokens", "DELETE FROM refresh_tokens WHERE user_id = $1"),
+        ("identities", "DELETE FROM identities WHERE user_id = $1"),
+        ("audit_log", "DELETE FROM audit_log WHERE user_id = $1"),
+        (
+            "oauth_states",
+            "DELETE FROM oauth_states WHERE user_id = $1",
+        ),
+        (
+            "totp_recovery_codes",
+            "DELETE FROM totp_recovery_codes WHERE user_id = $1",
+        ),
+        ("sync_conflicts", "DELETE FROM sync_conflicts WHERE user_id = $1"),
+        (
+            "upload_chunks",
+            r#"
+            DELETE FROM upload_chunks
+            WHERE upload_id IN (SELECT id FROM uploads WHERE user_id = $1)
+            "#,
+        ),
+        ("uploads", "DELETE FROM uploads WHERE user_id = $1"),
+        (
+            "device_group_members",
+            r#"
+            DELETE FROM device_group_members
+            WHERE group_id IN (SELECT id FROM device_groups WHERE user_id = $1)
+            "#,
+        ),
+        ("device_groups", "DELETE FROM device_groups WHERE user_id = $1"),
+        (
+            "webhook_deliveries",
+            "DELETE FROM webhook_deliveries WHERE user_id = $1",
+        ),
+        ("webhooks", "DELETE FROM webhooks WHERE user_id = $1"),
+        (
+            "abuse_reports",
+            "DELETE FROM abuse_reports WHERE reporter_id = $1 OR reported_user_id = $1",
+        ),
+        ("users", "DELETE FROM users WHERE user_id = $1"),
+    ];
+    purge_rows(STATEMENTS, user_id, pool).await
+}
+
+// 删除剪贴板的全部数据，包括幂等键中缓存的响应
+pub async fn purge_clip_data(
+    clip_id: &str,
+    pool: &DbPool,
+) -> Result<Vec<(&'static str, u64)>, sqlx::Error> {
+    const STATEMENTS: &[(&str, &str)] = &[
+        ("clip_tags", "DELETE FROM clip_tags WHERE clip_id = $1"),
+        (
+            "clip_revisions",
+            "DELETE FROM clip_revisions WHERE clip_id = $1",
+        ),
+        ("clips", "DELETE FROM clips WHERE id = $1"),
+        ("shares", "DELETE FROM shares WHERE clip_id = $1"),
+        ("scheduled_clips", "DELETE FROM scheduled_clips WHERE id = $1"),
+        ("sync_conflicts", "DELETE FROM sync_conflicts WHERE item_id = $1"),
+        (
+            "abuse_reports",
+            "DELETE FROM abuse_reports WHERE target_type = 'clip' AND target_id = $1",
+        ),
+        (
+            "idempotency_keys",
+            "DELETE FROM idempotency_keys WHERE response LIKE '%' || $1 || '%'",
+        ),
+    ];
+    purge_rows(STATEMENTS, clip_id, pool).await
+}
+
+fn row_to_share(row: &AnyRow) -> Result<Share, sqlx::Error> {
+    Ok(Share {
+        id: row.try_get("id")?,
+        clip_id: row.try_get("clip_id")?,
+        created_at: row.try_get("created_at")?,
+        expires_at: row.try_get("expires_at")?,
+    })
+}
+
+// 创建分块上传会话
+pub async fn insert_upload(
+    user_id: &str,
+    upload: &Upload,
+    pool: &DbPool,
+) -> Result<(), sqlx::Error> {
+    let metadata =
+        serde_json::to_string(&upload.clip).map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+    query(
+        r#"
+        INSERT INTO uploads (
+            id, user_id, clip_id, device_id, content_type, mime_type, size, chunk_size,
+            checksum, metadata, created_at, expires_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+        "#,
+    )
+    .bind(&upload.id)
+    .bind(user_id)
+    .bind(upload.clip_id.to_string())
+    .bind(upload.device_id.to_string())
+    .bind(upload.content_type)
+    .bind(&upload.mime_type)
+    .bind(upload.size)
+    .bind(upload.chunk_size)
+    .bind(&upload.checksum)
+    .bind(metadata)
+    .bind(upload.created_at)
+    .bind(upload.expires_at)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+fn row_to_upload(row: &AnyRow) -> Result<Upload, sqlx::Error> {
+    let decode = |e: uuid::Error| sqlx::Error::Decode(Box::new(e));
+    let clip_id: String = row.try_get("clip_id")?;
+    let device_id: String = row.try_get("device_id")?;
+    let metadata: String = row.try_get("metadata")?;
+    Ok(Upload {
+        id: row.try_get("id")?,
+        clip_id: Uuid::parse_str(&clip_id).map_err(decode)?,
+        device_id: Uuid::parse_str(&device_id).map_err(decode)?,
+        content_type: row.try_get("content_type")?,
+        mime_type: row.try_get("mime_type")?,
+        size: row.try_get("size")?,
+        chunk_size: row.try_get("chunk_size")?,
+        checksum: row.try_get("checksum")?,
+        clip: serde_json::from_str(&metadata).map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+        created_at: row.try_get("created_at")?,
+        expires_at: row.try_get("expires_at")?,
+    })
+}
+
+// 获取用户的分块上传会话（包括已过期的）
+pub async fn get_upload(
+    user_id: &str,
+    id: &str,
+    pool: &DbPool,
+) -> Result<Option<Upload>, sqlx::Error> {
+    let row = query(
+        r#"
+        SELECT id, clip_id, device_id, content_type, mime_type, size, chunk_size, checksum,
+               metadata, created_at, expires_at
+        FROM uploads
+        WHERE id = $1 AND user_id = $2
+        "#,
+    )
+    .bind(id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+    row.as_ref().map(row_to_upload).transpose()
+}
+
+// 获取上传会话已收到的分块，按序号排列
+pub async fn get_upload_chunks(
+    upload_id: &str,
+    pool: &DbPool,
+) -> Result<Vec<UploadChunk>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT chunk_index, size, checksum
+        FROM upload_chunks
+        WHERE upload_id = $1
+        ORDER BY chunk_index
+        "#,
+    )
+    .bind(upload_id)
+    .fetch_all(pool)
+    .await?;
+    rows.iter()
+        .map(|row| {
+            Ok(UploadChunk {
+                index: row.try_get("chunk_index")?,
+                size: row.try_get("size")?,
+                checksum: row.try_get("checksum")?,
+            })
+        })
+        .collect()
+}
+
+// 记录收到的分块（重复上传时覆盖），返回已收到的分块数
+pub async fn save_upload_chunk(
+    upload_id: &str,
+    chunk: &UploadChunk,
+    pool: &DbPool,
+) -> Result<i64, sqlx::Error> {
+    query(
+        r#"
+        INSERT INTO upload_chunks (upload_id, chunk_index, size, checksum)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT(upload_id, chunk_index) DO UPDATE
+        SET size = excluded.size, checksum = excluded.checksum
+        "#,
+    )
+    .bind(upload_id)
+    .bind(chunk.index)
+    .bind(chunk.size)
+    .bind(&chunk.checksum)
+    .execute(pool)
+    .await?;
+    let row = query("SELECT COUNT(*) AS received FROM upload_chunks WHERE upload_id = $1")
+        .bind(upload_id)
+        .fetch_one(pool)
+        .await?;
+    row.try_get("received")
+}
+
+// 删除上传会话及其分块记录，不存在时返回 false
+pub async fn delete_upload(user_id: &str, id: &str, pool: &DbPool) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let deleted = write_upload_delete(user_id, id, &mut tx).await?;
+    if deleted {
+        tx.commit().await?;
+    }
+    Ok(deleted)
+}
+
+// 在调用方的事务中删除上传会话，规则同 delete_upload
+pub async fn write_upload_delete(
+    user_id: &str,
+    id: &str,
+    tx: &mut Transaction<'_, Db>,
+) -> Result<bool, sqlx::Error> {
+    let result = query("DELETE FROM uploads WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+    query("DELETE FROM upload_chunks WHERE upload_id = $1")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    Ok(true)
+}
+
+// 删除已过期的上传会话，返回 (user_id, 上传 ID)，用于删除其分块文件
+pub async fn delete_expired_uploads(
+    now: i64,
+    pool: &DbPool,
+) -> Result<Vec<(String, String)>, sqlx::Error> {
+    let rows = query("SELECT user_id, id FROM uploads WHERE expires_at <= $1")
+        .bind(now)
+        .fetch_all(pool)
+        .await?;
+    let mut expired = Vec::with_capacity(rows.len());
+    for row in rows {
+        let (user_id, id): (String, String) = (row.try_get("user_id")?, row.try_get("id")?);
+        let mut tx = pool.begin().await?;
+        if write_upload_delete(&user_id, &id, &mut tx).await? {
+            tx.commit().await?;
+            expired.push((user_id, id));
+        }
+    }
+    Ok(expired)
+}
+
+// 创建分享链接
+pub async fn insert_share(
+    user_id: &str,
+    share: &Share,
+    token_hash: &str,
+    pool: &DbPool,
+) -> Result<(), sqlx::Error> {
+    query(
+        r#"
+        INSERT INTO shares (id, user_id, clip_id, token_hash, created_at, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+    )
+    .bind(&share.id)
+    .bind(user_id)
+    .bind(&share.clip_id)
+    .bind(token_hash)
+    .bind(share.created_at)
+    .bind(share.expires_at)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// 获取用户未过期且未撤销的分享链接，按创建时间倒序
+pub async fn get_active_shares(
+    user_id: &str,
+    now: i64,
+    pool: &DbPool,
+) -> Result<Vec<Share>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT id, clip_id, created_at, expires_at
+        FROM shares
+        WHERE user_id = $1 AND revoked_at IS NULL AND expires_at > $2
+        ORDER BY created_at DESC
+        "#,
+    )
+    .bind(user_id)
+    .bind(now)
+    .fetch_all(pool)
+    .await?;
+    rows.iter().map(row_to_share).collect()
+}
+
+// 按令牌哈希查找有效的分享链接，返回 (分享者 user_id, 分享)
+pub async fn get_share_by_token(
+    token_hash: &str,
+    now: i64,
+    pool: &DbPool,
+) -> Result<Option<(String, Share)>, sqlx::Error> {
+    let row = query(
+        r#"
+        SELECT id, user_id, clip_id, created_at, expires_at
+        FROM shares
+        WHERE token_hash = $1 AND revoked_at IS NULL AND expires_at > $2
+        "#,
+    )
+    .bind(token_hash)
+    .bind(now)
+    .fetch_optional(pool)
+    .await?;
+    row.map(|row| Ok((row.try_get("user_id")?, row_to_share(&row)?)))
+        .transpose()
+}
+
+// 撤销分享链接，不存在或已撤销时返回 false
+pub async fn revoke_share(user_id: &str, id: &str, pool: &DbPool) -> Result<bool, sqlx::Error> {
+    let result = query(
+        r#"
+        UPDATE shares SET revoked_at = $3
+        WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL
+        "#,
+    )
+    .bind(id)
+    .bind(user_id)
+    .bind(Utc::now().timestamp())
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+fn row_to_organization(row: &AnyRow) -> Result<Organization, sqlx::Error> {
+    Ok(Organization {
+        id: row.try_get("id")?,
+        name: row.try_get("name")?,
+        quota_bytes: row.try_get("quota_bytes")?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+// 创建组织并设置所有者，所有者已属于其他组织时返回 false
+pub async fn insert_organization(
+    org: &Organization,
+    owner_id: &str,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let result = query(
+        r#"
+        UPDATE users
+        SET org_id = $2, org_role = 'owner'
+        WHERE user_id = $1 AND org_id IS NULL
+        "#,
+    )
+    .bind(owner_id)
+    .bind(&org.id)
+    .execute(&mut tx)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+    query(
+        r#"
+        INSERT INTO organizations (id, name, quota_bytes, created_at)
+        VALUES ($1, $2, $3, $4)
+        "#,
+    )
+    .bind(&org.id)
+    .bind(&org.name)
+    .bind(org.quota_bytes)
+    .bind(org.created_at)
+    .execute(&mut tx)
+    .await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+// 获取所有组织
+pub async fn get_organizations(pool: &DbPool) -> Result<Vec<Organization>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT id, name, quota_bytes, created_at
+        FROM organizations
+        ORDER BY created_at
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+    rows.iter().map(row_to_organization).collect()
+}
+
+// 获取组织
+pub async fn get_organization(
+    id: &str,
+    pool: &DbPool,
+) -> Result<Option<Organization>, sqlx::Error> {
+    let row = query(
+        r#"
+        SELECT id, name, quota_bytes, created_at
+        FROM organizations
+        WHERE id = $1
+        "#,
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    row.as_ref().map(row_to_organization).transpose()
+}
+
+// 修改组织配额，返回组织是否存在
+pub async fn update_organization_quota(
+    id: &str,
+    quota_bytes: Option<i64>,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    let result = query("UPDATE organizations SET quota_bytes = $2 WHERE id = $1")
+        .bind(id)
+        .bind(quota_bytes)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// 获取用户所在的组织和角色，返回 (org_id, role)
+pub async fn get_user_org(
+    user_id: &str,
+    pool: &DbPool,
+) -> Result<Option<(String, String)>, sqlx::Error> {
+    let row = query(
+        r#"
+        SELECT org_id, org_role
+        FROM users
+        WHERE user_id = $1 AND org_id IS NOT NULL
+        "#,
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+    row.map(|row| Ok((row.try_get("org_id")?, row.try_get("org_role")?)))
+        .transpose()
+}
+
+// 获取组织成员
+pub async fn get_org_members(
+    org_id: &str,
+    pool: &DbPool,
+) -> Result<Vec<OrgMember>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT user_id, username, email, org_role
+        FROM users
+        WHERE org_id = $1
+        ORDER BY username
+        "#,
+    )
+    .bind(org_id)
+    .fetch_all(pool)
+    .await?;
+    rows.iter()
+        .map(|row| {
+            Ok(OrgMember {
+                user_id: row.try_get("user_id")?,
+                username: row.try_get("username")?,
+                email: row.try_get("email")?,
+                role: row.try_get("org_role")?,
+            })
+        })
+        .collect()
+}
+
+// 把未加入组织的用户加入组织，用户已属于某个组织时返回 false
+pub async fn add_org_member(
+    org_id: &str,
+    user_id: &str,
+    role: &str,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    let result = query(
+        r#"
+        UPDATE users
+        SET org_id = $1, org_role = $3
+        WHERE user_id = $2 AND org_id IS NULL
+        "#,
+    )
+    .bind(org_id)
+    .bind(user_id)
+    .bind(role)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// 修改成员角色（所有者除外）
+pub async fn update_org_member_role(
+    org_id: &str,
+    user_id: &str,
+    role: &str,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    let result = query(
+        r#"
+        UPDATE users
+        SET org_role = $3
+        WHERE org_id = $1 AND user_id = $2 AND org_role != 'owner'
+        "#,
+    )
+    .bind(org_id)
+    .bind(user_id)
+    .bind(role)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// 移除组织成员（所有者除外）
+pub async fn remove_org_member(
+    org_id: &str,
+    user_id: &str,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    let result = query(
+        r#"
+        UPDATE users
+        SET org_id = NULL, org_role = NULL
+        WHERE org_id = $1 AND user_id = $2 AND org_role != 'owner'
+        "#,
+    )
+    .bind(org_id)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// 剪贴板内容字节数之和（BIGINT），没有记录时为 0
+fn sum_content_bytes() -> String {
+    format!("CAST(COALESCE(SUM({}), 0) AS BIGINT)", byte_length("content"))
+}
+
+// 组织成员数据占用的总字节数
+pub async fn get_org_usage_bytes(org_id: &str, pool: &DbPool) -> Result<i64, sqlx::Error> {
+    let row = query(&format!(
+        r#"
+        SELECT
+            (SELECT {bytes}
+             FROM clips
+             WHERE user_id IN (SELECT user_id FROM users WHERE org_id = $1))
+            + (SELECT {bytes}
+               FROM scheduled_clips
+               WHERE user_id IN (SELECT user_id FROM users WHERE org_id = $1)) AS bytes
+        "#,
+        bytes = sum_content_bytes(),
+    ))
+    .bind(org_id)
+    .fetch_one(pool)
+    .await?;
+    row.try_get("bytes")
+}
+
+// 剪贴板占用的总字节数：文件类型的剪贴板 content 为文件路径，按 size 计算
+fn sum_clip_bytes() -> String {
+    let binary_types = BINARY_CLIP_TYPES
+        .iter()
+        .map(|content_type| format!("'{}'", content_type.as_str()))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "CAST(COALESCE(SUM(CASE WHEN content_type IN ({}) THEN size ELSE {} END), 0) AS BIGINT)",
+        binary_types,
+        byte_length("content")
+    )
+}
+
+// 用户的个人配额和已用字节数（剪贴板、定时投递和头像），以及所在组织的配额和已用字节数，
+// 用户不存在时返回 None；个人配额为用户单独设置的值，未设置时为空
+pub async fn get_quota_usage(
+    user_id: &str,
+    executor: impl Executor<'_, Database = Db>,
+) -> Result<Option<QuotaUsage>, sqlx::Error> {
+    let row = query(&format!(
+        r#"
+        SELECT users.quota_bytes,
+            users.head_bytes
+            + (SELECT {clip_bytes} FROM clips WHERE user_id = $1)
+            + (SELECT {bytes} FROM scheduled_clips WHERE user_id = $1) AS used_bytes,
+            organizations.id AS org_id,
+            organizations.quota_bytes AS org_quota_bytes,
+            (SELECT {bytes}
+             FROM clips
+             WHERE user_id IN (SELECT user_id FROM users WHERE org_id = organizations.id))
+            + (SELECT {bytes}
+               FROM scheduled_clips
+               WHERE user_id IN (SELECT user_id FROM users WHERE org_id = organizations.id))
+                AS org_used_bytes
+        FROM users
+        LEFT JOIN organizations ON organizations.id = users.org_id
+        WHERE users.user_id = $1
+        "#,
+        clip_bytes = sum_clip_bytes(),
+        bytes = sum_content_bytes(),
+    ))
+    .bind(user_id)
+    .fetch_optional(executor)
+    .await?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    let org_id: Option<String> = row.try_get("org_id")?;
+    let organization = match org_id {
+        Some(_) => Some(Usage {
+            quota_bytes: row.try_get("org_quota_bytes")?,
+            used_bytes: row.try_get("org_used_bytes")?,
+        }),
+        None => None,
+    };
+    Ok(Some(QuotaUsage {
+        user: Usage {
+            quota_bytes: row.try_get("quota_bytes")?,
+            used_bytes: row.try_get("used_bytes")?,
+        },
+        organization,
+    }))
+}
+
+// 设置用户的个人配额，为 None 时恢复默认配额，用户不存在时返回 false
+pub async fn set_user_quota(
+    user_id: &str,
+    quota_bytes: Option<i64>,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    let result = query("UPDATE users SET quota_bytes = $2 WHERE user_id = $1")
+        .bind(user_id)
+        .bind(quota_bytes)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// 用户当前头像文件的字节数
+pub async fn get_head_bytes(user_id: &str, pool: &DbPool) -> Result<i64, sqlx::Error> {
+    let row = query("SELECT head_bytes FROM users WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+    row.try_get("head_bytes")
+}
+
+// 剪贴板的标签，按名称排序的 JSON 数组文本
+fn clip_tags_column() -> &'static str {
+    match backend() {
+        Backend::Sqlite => {
+            r#"(SELECT json_group_array(name) FROM (
+                   SELECT tags.name FROM clip_tags JOIN tags ON tags.id = clip_tags.tag_id
+                   WHERE clip_tags.clip_id = clips.id ORDER BY tags.name))"#
+        }
+        Backend::Postgres => {
+            r#"(SELECT COALESCE(json_agg(tags.name ORDER BY tags.name), '[]')::text
+                   FROM clip_tags JOIN tags ON tags.id = clip_tags.tag_id
+                   WHERE clip_tags.clip_id = clips.id)"#
+        }
+    }
+}
+
+// 剪贴板对参数 device_param 指定的设备可见（visibility 为空表示所有设备可见）
+fn visible_to(device_param: &str) -> String {
+    format!(
+        r#"(visibility IS NULL
+               OR ({scope} = 'device' AND {device_id} = {param})
+               OR ({scope} = 'group' AND {group_id} IN
+                   (SELECT group_id FROM device_group_members WHERE device_id = {param})))"#,
+        scope = json_field("visibility", "scope"),
+        device_id = json_field("visibility", "device_id"),
+        group_id = json_field("visibility", "group_id"),
+        param = device_param,
+    )
+}
+
+// 可见范围转为数据库中保存的 JSON，所有设备可见时为空
+fn visibility_to_json(visibility: &Visibility) -> Option<String> {
+    (!visibility.is_all()).then(|| serde_json::to_string(visibility).unwrap_or_default())
+}
+
+fn row_to_clip(row: &AnyRow) -> Result<ClipItem, sqlx::Error> {
+    let decode = |e: Box<dyn std::error::Error + Send + Sync>| sqlx::Error::Decode(e);
+    let timestamp = |column: &str| -> Result<DateTime<Utc>, sqlx::Error> {
+        DateTime::from_timestamp_millis(row.try_get(column)?)
+            .ok_or_else(|| decode(format!("invalid timestamp in {}", column).into()))
+    };
+    let id: String = row.try_get("id")?;
+    let device_id: String = row.try_get("device_id")?;
+    let tags: String = row.try_get("tags")?;
+    let visibility: Option<String> = row.try_get("visibility")?;
+    Ok(ClipItem {
+        id: Uuid::parse_str(&id).map_err(|e| decode(Box::new(e)))?,
+        device_id: Uuid::parse_str(&device_id).map_err(|e| decode(Box::new(e)))?,
+        content_type: row.try_get("content_type")?,
+        content: row.try_get("content")?,
+        preview: row.try_get("preview")?,
+        size: row.try_get("size")?,
+        source_app: row.try_get("source_app")?,
+        created_at: timestamp("created_at")?,
+        accessed_at: timestamp("accessed_at")?,
+        sync_status: row.try_get("sync_status")?,
+        encrypted: row.try_get("encrypted")?,
+        tags: serde_json::from_str(&tags).map_err(|e| decode(Box::new(e)))?,
+        visibility: visibility
+            .map(|text| serde_json::from_str(&text))
+            .transpose()
+            .map_err(|e| decode(Box::new(e)))?
+            .unwrap_or_default(),
+        key_id: row.try_get("key_id")?,
+        nonce: row.try_get("nonce")?,
+        version: row.try_get("version")?,
+        pinned: row.try_get("pinned")?,
+        source: row.try_get("source")?,
+        shared_by: row.try_get("shared_by")?,
+        expires_at: row
+            .try_get::<Option<i64>, _>("expires_at")?
+            .map(|millis| {
+                DateTime::from_timestamp_millis(millis)
+                    .ok_or_else(|| decode("invalid timestamp in expires_at".into()))
+            })
+            .transpose()?,
+        sensitive: row.try_get("sensitive")?,
+    })
+}
+
+// 补算没有内容哈希的剪贴板（由新到旧），与已有哈希重复的保持为空
+async fn backfill_content_hashes(pool: &DbPool) -> Result<(), sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT id, content_type, content FROM clips
+        WHERE content_hash IS NULL AND encrypted = FALSE
+        ORDER BY created_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+    for row in rows {
+        let id: String = row.try_get("id")?;
+        let content: String = row.try_get("content")?;
+        query(
+            r#"
+            UPDATE clips SET content_hash = $1
+            WHERE id = $2 AND NOT EXISTS (
+                SELECT 1 FROM clips other
+                WHERE other.user_id = clips.user_id AND other.content_hash = $1)
+            "#,
+        )
+        .bind(content_hash(row.try_get("content_type")?, &content))
+        .bind(id)
+        .execute(pool)
+        .await?;
+    }
+    Ok(())
+}
+
+// 在调用方的事务中保存剪贴板项目及其标签
+//
+// 已有相同内容哈希的剪贴板时不插入新行，而是把已有的剪贴板移到最前
+// （更新创建和访问时间、来源设备和应用，合并标签），返回该剪贴板的 ID
+pub async fn write_clip_insert(
+    user_id: &str,
+    clip: &ClipItem,
+    content_hash: &str,
+    tx: &mut Transaction<'_, Db>,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let existing = query(
+        r#"
+        UPDATE clips
+        SET created_at = $3, accessed_at = $3, device_id = $4, modified_by = $4,
+            source_app = $5, expires_at = $6, deleted_at = NULL, version = version + 1
+        WHERE user_id = $1 AND content_hash = $2
+        RETURNING id
+        "#,
+    )
+    .bind(user_id)
+    .bind(content_hash)
+    .bind(clip.created_at.timestamp_millis())
+    .bind(clip.device_id.to_string())
+    .bind(&clip.source_app)
+    .bind(clip.expires_at.map(|at| at.timestamp_millis()))
+    .fetch_optional(&mut *tx)
+    .await?;
+    if let Some(row) = existing {
+        let id: String = row.try_get("id")?;
+        let id = Uuid::parse_str(&id).map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+        for tag in &clip.tags {
+            add_clip_tag(user_id, &id, tag, tx).await?;
+        }
+        return Ok(Some(id));
+    }
+
+    query(
+        r#"
+        INSERT INTO clips (
+            id, user_id, device_id, content_type, content, preview, size, source_app,
+            created_at, accessed_at, sync_status, encrypted, visibility, content_hash,
+            key_id, nonce, source, shared_by, expires_at, sensitive
+        )
+        VALUES (
+            $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19,
+            $20
+        )
+        "#,
+    )
+    .bind(clip.id.to_string())
+    .bind(user_id)
+    .bind(clip.device_id.to_string())
+    .bind(clip.content_type)
+    .bind(&clip.content)
+    .bind(&clip.preview)
+    .bind(clip.size)
+    .bind(&clip.source_app)
+    .bind(clip.created_at.timestamp_millis())
+    .bind(clip.accessed_at.timestamp_millis())
+    .bind(clip.sync_status)
+    .bind(clip.encrypted)
+    .bind(visibility_to_json(&clip.visibility))
+    .bind(content_hash)
+    .bind(&clip.key_id)
+    .bind(&clip.nonce)
+    .bind(clip.source)
+    .bind(&clip.shared_by)
+    .bind(clip.expires_at.map(|at| at.timestamp_millis()))
+    .bind(clip.sensitive)
+    .execute(&mut *tx)
+    .await?;
+    replace_clip_tags(user_id, &clip.id, &clip.tags, tx).await?;
+    Ok(None)
+}
+
+// 替换剪贴板的标签，不存在的标签自动创建
+async fn replace_clip_tags(
+    user_id: &str,
+    clip_id: &Uuid,
+    tags: &[String],
+    tx: &mut Transaction<'_, Db>,
+) -> Result<(), sqlx::Error> {
+    query("DELETE FROM clip_tags WHERE clip_id = $1")
+        .bind(clip_id.to_string())
+        .execute(&mut *tx)
+        .await?;
+    for tag in tags {
+        add_clip_tag(user_id, clip_id, tag, tx).await?;
+    }
+    Ok(())
+}
+
+// 给剪贴板添加标签，不存在的标签自动创建
+async fn add_clip_tag(
+    user_id: &str,
+    clip_id: &Uuid,
+    tag: &str,
+    tx: &mut Transaction<'_, Db>,
+) -> Result<(), sqlx::Error> {
+    query(
+        r#"
+        INSERT INTO tags (id, user_id, name, created_at)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (user_id, name) DO NOTHING
+        "#,
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(user_id)
+    .bind(tag)
+    .bind(Utc::now().timestamp())
+    .execute(&mut *tx)
+    .await?;
+    query(
+        r#"
+        INSERT INTO clip_tags (clip_id, tag_id)
+        SELECT $1, id FROM tags WHERE user_id = $2 AND name = $3
+        ON CONFLICT DO NOTHING
+        "#,
+    )
+    .bind(clip_id.to_string())
+    .bind(user_id)
+    .bind(tag)
+    .execute(&mut *tx)
+    .await?;
+    Ok(())
+}
+
+// 剪贴板查询条件（get_clips 和 count_clips 共用），参数 $1 到 $10 由 bind_clip_filter 绑定
+//
+// 提供多个标签时按 filter.tag_match 匹配：all 需同时包含所有标签，any 包含任一标签即可
+fn clip_filter_conditions() -> String {
+    format!(
+        r#"
+        user_id = $1
+          AND deleted_at IS NULL
+          AND ($2 IS NULL OR content_type = $2)
+          AND ($3 IS NULL OR device_id = $3)
+          AND ($4 IS NULL OR created_at >= $4)
+          AND ($5 IS NULL OR created_at <= $5)
+          AND ($6 IS NULL OR (sensitive IS NULL AND {in_content}) OR {in_preview})
+          AND ($7 IS NULL
+               OR ($10 = 'all' AND NOT EXISTS (
+                   SELECT 1 FROM {wanted}
+                   WHERE wanted.value NOT IN (
+                       SELECT tags.name FROM clip_tags JOIN tags ON tags.id = clip_tags.tag_id
+                       WHERE clip_tags.clip_id = clips.id)))
+               OR ($10 = 'any' AND EXISTS (
+                   SELECT 1 FROM clip_tags JOIN tags ON tags.id = clip_tags.tag_id
+                   WHERE clip_tags.clip_id = clips.id
+                     AND tags.name IN (SELECT wanted.value FROM {wanted}))))
+          AND {visible}
+          AND ($9 = FALSE OR pinned = TRUE)
+        "#,
+        in_content = contains("lower(content)", "lower($6)"),
+        in_preview = contains("lower(preview)", "lower($6)"),
+        wanted = json_array_elements("$7", "wanted"),
+        visible = visible_to("$8"),
+    )
+}
+
+fn bind_clip_filter<'q>(
+    query: sqlx::query::Query<'q, Db, AnyArguments<'q>>,
+    user_id: &'q str,
+    device_id: Option<&'q str>,
+    filter: &'q ClipFilter,
+) -> sqlx::query::Query<'q, Db, AnyArguments<'q>> {
+    query
+        .bind(user_id)
+        .bind(filter.clip_type)
+        .bind(filter.device_id.map(|id| id.to_string()))
+        .bind(filter.start_date.map(|date| date.timestamp_millis()))
+        .bind(filter.end_date.map(|date| date.timestamp_millis()))
+        .bind(&filter.search_text)
+        .bind(
+            filter
+                .tags
+                .as_ref()
+                .map(|tags| serde_json::to_string(tags).unwrap_or_default()),
+        )
+        .bind(device_id)
+        .bind(filter.pinned_only.unwrap_or(false))
+        .bind(filter.tag_match.unwrap_or_default().as_str())
+}
+
+// 按条件查询剪贴板，只返回 device_id 可见的项目（未提供设备 ID 时只返回所有设备可见的项目）
+//
+// 按创建时间倒序（相同时按 ID 倒序），提供 filter.cursor 时从游标之后开始（keyset 分页），
+// 否则跳过 filter.offset 条
+pub async fn get_clips(
+    user_id: &str,
+    device_id: Option<&str>,
+    filter: &ClipFilter,
+    pool: &DbPool,
+) -> Result<Vec<ClipItem>, sqlx::Error> {
+    let sql = format!(
+        r#"
+        SELECT id, device_id, content_type, content, preview, size, source_app,
+               created_at, accessed_at, sync_status, encrypted,
+               {tags} AS tags,
+               visibility, key_id, nonce, version, pinned, source, shared_by,
+               expires_at, sensitive
+        FROM clips
+        WHERE {conditions}
+          AND ($11 IS NULL OR created_at < $11 OR (created_at = $11 AND id < $12))
+        ORDER BY created_at DESC, id DESC
+        LIMIT $13 OFFSET $14
+        "#,
+        tags = clip_tags_column(),
+        conditions = clip_filter_conditions(),
+    );
+    let cursor = filter.cursor.as_ref();
+    let rows = bind_clip_filter(query(&sql), user_id, device_id, filter)
+        .bind(cursor.map(|cursor| cursor.at))
+        .bind(cursor.map(|cursor| cursor.id.as_str()))
+        .bind(filter.limit.map(i64::from).unwrap_or(i64::MAX))
+        .bind(if cursor.is_some() {
+            0
+        } else {
+            filter.offset.unwrap_or(0)
+        })
+        .fetch_all(pool)
+        .await?;
+    rows.iter().map(row_to_clip).collect()
+}
+
+// 符合条件的剪贴板数量，条件与 get_clips 相同（不受游标和分页影响）
+pub async fn count_clips(
+    user_id: &str,
+    device_id: Option<&str>,
+    filter: &ClipFilter,
+    pool: &DbPool,
+) -> Result<i64, sqlx::Error> {
+    let sql = format!(
+        "SELECT COUNT(*) AS total FROM clips WHERE {}",
+        clip_filter_conditions()
+    );
+    bind_clip_filter(query(&sql), user_id, device_id, filter)
+        .fetch_one(pool)
+        .await?
+        .try_get("total")
+}
+
+// 全文搜索剪贴板，按相关度排序，返回 (匹配总数, 当前页结果)
+//
+// text 整体作为一个短语匹配，可见范围的判断与 get_clips 相同
+//
+// - SQLite：使用 FTS5 trigram 索引，按 bm25 排序
+// - PostgreSQL：使用 pg_trgm 索引按子串匹配（不区分大小写），按 word_similarity 排序
+pub async fn search_clips(
+    user_id: &str,
+    device_id: Option<&str>,
+    text: &str,
+    clip_type: Option<ClipType>,
+    limit: i32,
+    offset: i32,
+    pool: &DbPool,
+) -> Result<(i64, Vec<ClipSearchHit>), sqlx::Error> {
+    let (pattern, matched, score, snippet) = match backend() {
+        // 整体作为一个短语匹配，避免用户输入被解析为 FTS5 查询语法
+        Backend::Sqlite => (
+            format!("\"{}\"", text.replace('"', "\"\"")),
+            "FROM clips_fts JOIN clips ON clips.id = clips_fts.clip_id WHERE clips_fts MATCH $1",
+            // bm25 越相关数值越小（为负数），取反后作为分数
+            "-clips_fts.rank",
+            "snippet(clips_fts, -1, '<mark>', '</mark>', '…', 24)",
+        ),
+        Backend::Postgres => (
+            format!("%{}%", escape_like(text)),
+            "FROM clips WHERE clips.encrypted = FALSE AND clips.sensitive IS NULL \
+             AND (clips.content ILIKE $1 OR clips.preview ILIKE $1)",
+            "CAST(word_similarity($7, clips.content) AS DOUBLE PRECISION)",
+            "NULL",
+        ),
+    };
+    let matched = format!(
+        r#"
+        {matched}
+          AND clips.user_id = $2
+          AND clips.deleted_at IS NULL
+          AND ($3 IS NULL OR clips.content_type = $3)
+          AND {visible}
+        "#,
+        visible = visible_to("$4"),
+    );
+    let mut tx = pool.begin().await?;
+    let total: i64 = query(&format!("SELECT COUNT(*) AS total {}", matched))
+        .bind(&pattern)
+        .bind(user_id)
+        .bind(clip_type)
+        .bind(device_id)
+        .fetch_one(&mut tx)
+        .await?
+        .try_get("total")?;
+    let sql = format!(
+        r#"
+        SELECT clips.id, clips.device_id, content_type, clips.content, clips.preview, size,
+               source_app, created_at, accessed_at, sync_status, encrypted,
+               {tags} AS tags,
+               visibility, key_id, nonce, version, pinned, source, shared_by,
+               expires_at, sensitive,
+               {snippet} AS snippet,
+               {score} AS score
+        {matched}
+        ORDER BY score DESC, created_at DESC
+        LIMIT $5 OFFSET $6
+        "#,
+        tags = clip_tags_column(),
+    );
+    let mut rows = query(&sql)
+        .bind(&pattern)
+        .bind(user_id)
+        .bind(clip_type)
+        .bind(device_id)
+        .bind(limit)
+        .bind(offset);
+    if backend() == Backend::Postgres {
+        rows = rows.bind(text);
+    }
+    let rows = rows.fetch_all(&mut tx).await?;
+    tx.commit().await?;
+
+    let hits = rows
+        .iter()
+        .map(|row| {
+            let clip = row_to_clip(row)?;
+            let snippet = match row.try_get::<Option<String>, _>("snippet")? {
+                Some(snippet) => snippet,
+                None => highlight_snippet(&clip.content, text, 24),
+            };
+            Ok(ClipSearchHit {
+                clip,
+                snippet,
+                score: row.try_get("score")?,
+            })
+        })
+        .collect::<Result<_, sqlx::Error>>()?;
+    Ok((total, hits))
+}
+
+// 转义 LIKE 模式中的通配符（PostgreSQL 默认转义字符为反斜杠）
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+// 截取 content 中第一个匹配 text（不区分大小写）的位置前后共约 width 个字符，
+// 匹配部分用 `<mark></mark>` 标记，截断处用 `…` 表示，与 FTS5 的 snippet 一致
+fn highlight_snippet(content: &str, text: &str, width: usize) -> String {
+    let chars: Vec<char> = content.chars().collect();
+    let lower: Vec<char> = chars.iter().flat_map(|c| c.to_lowercase()).collect();
+    let needle: Vec<char> = text.chars().flat_map(|c| c.to_lowercase()).collect();
+    // 大小写转换改变了字符数时无法对应原文位置，退化为不标记
+    let start = (lower.len() == chars.len())
+        .then(|| lower.windows(needle.len()).position(|w| w == needle.as_slice()))
+        .flatten();
+    let Some(start) = start else {
+        let mut snippet: String = chars.iter().take(width).collect();
+        if chars.len() > width {
+            snippet.push('…');
+        }
+        return snippet;
+    };
+    let end = start + needle.len();
+    let context = width.saturating_sub(needle.len()) / 2;
+    let from = start.saturating_sub(context);
+    let to = (end + context).min(chars.len());
+    let mut snippet = String::new();
+    if from > 0 {
+        snippet.push('…');
+    }
+    snippet.extend(&chars[from..start]);
+    snippet.push_str("<mark>");
+    snippet.extend(&chars[start..end]);
+    snippet.push_str("</mark>");
+    snippet.extend(&chars[end..to]);
+    if to < chars.len() {
+        snippet.push('…');
+    }
+    snippet
+}
+
+// 获取 since 之后的剪贴板变更，同一剪贴板只返回最新的一条，按 seq 升序
+//
+// 创建和修改只返回 device_id 可见的剪贴板，删除不区分可见范围
+pub async fn get_clip_changes(
+    user_id: &str,
+    device_id: Option<&str>,
+    since: i64,
+    limit: i32,
+    pool: &DbPool,
+) -> Result<Vec<ClipChange>, sqlx::Error> {
+    let rows = query(&format!(
+        r#"
+        SELECT change_log.seq, change_log.op, change_log.clip_id,
+               clips.id, clips.device_id, content_type, content, preview, size, source_app,
+               created_at, accessed_at, sync_status, encrypted,
+               {tags} AS tags,
+               visibility, key_id, nonce, version, pinned, source, shared_by,
+               expires_at, sensitive
+        FROM change_log
+        LEFT JOIN clips ON clips.id = change_log.clip_id AND change_log.op != 'deleted'
+            AND clips.deleted_at IS NULL
+        LEFT JOIN device_sync_rules rule ON rule.device_id = $3 AND rule.user_id = $1
+        WHERE change_log.seq IN (
+                SELECT MAX(seq) FROM change_log
+                WHERE user_id = $1 AND seq > $2
+                GROUP BY clip_id)
+          AND (change_log.op = 'deleted'
+               OR (clips.id IS NOT NULL AND {visible}
+                   AND (rule.clip_types IS NULL
+                        OR clips.content_type IN (SELECT types.value FROM {types}))
+                   AND (rule.max_size IS NULL OR clips.size <= rule.max_size)))
+        ORDER BY change_log.seq
+        LIMIT $4
+        "#,
+        tags = clip_tags_column(),
+        visible = visible_to("$3"),
+        types = json_array_elements("rule.clip_types", "types"),
+    ))
+    .bind(user_id)
+    .bind(since)
+    .bind(device_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    rows.iter()
+        .map(|row| {
+            let op: ChangeOp = row.try_get("op")?;
+            let clip_id: String = row.try_get("clip_id")?;
+            Ok(ClipChange {
+                seq: row.try_get("seq")?,
+                op,
+                clip_id: Uuid::parse_str(&clip_id).map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+                clip: match op {
+                    ChangeOp::Deleted => None,
+                    _ => Some(row_to_clip(row)?),
+                },
+            })
+        })
+        .collect()
+}
+
+// 用户最新的变更游标，没有变更时为 0
+pub async fn get_change_cursor(user_id: &str, pool: &DbPool) -> Result<i64, sqlx::Error> {
+    let row = query("SELECT COALESCE(MAX(seq), 0) AS seq FROM change_log WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+    row.try_get("seq")
+}
+
+// 获取单个剪贴板项目
+pub async fn get_clip(
+    user_id: &str,
+    id: &Uuid,
+    pool: &DbPool,
+) -> Result<Option<ClipItem>, sqlx::Error> {
+    let row = query(&format!(
+        r#"
+        SELECT id, device_id, content_type, content, preview, size, source_app,
+               created_at, accessed_at, sync_status, encrypted,
+               {tags} AS tags,
+               visibility, key_id, nonce, version, pinned, source, shared_by,
+               expires_at, sensitive
+        FROM clips
+        WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL
+        "#,
+        tags = clip_tags_column(),
+    ))
+    .bind(id.to_string())
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+    row.as_ref().map(row_to_clip).transpose()
+}
+
+// 获取剪贴板（包括回收站中的），第二项为移到回收站的时间，不在回收站中时为空
+pub async fn get_clip_with_trash(
+    user_id: &str,
+    id: &Uuid,
+    executor: impl Executor<'_, Database = Db>,
+) -> Result<Option<(ClipItem, Option<i64>)>, sqlx::Error> {
+    let row = query(&format!(
+        r#"
+        SELECT id, device_id, content_type, content, preview, size, source_app,
+               created_at, accessed_at, sync_status, encrypted,
+               {tags} AS tags,
+               visibility, key_id, nonce, version, pinned, source, shared_by,
+               expires_at, sensitive, deleted_at
+        FROM clips
+        WHERE id = $1 AND user_id = $2
+        "#,
+        tags = clip_tags_column(),
+    ))
+    .bind(id.to_string())
+    .bind(user_id)
+    .fetch_optional(executor)
+    .await?;
+    row.map(|row| Ok((row_to_clip(&row)?, row.try_get("deleted_at")?)))
+        .transpose()
+}
+
+// 剪贴板 ID 所属的用户，剪贴板不存在时返回 None
+pub async fn get_clip_owner(id: &Uuid, pool: &DbPool) -> Result<Option<String>, sqlx::Error> {
+    let row = query("SELECT user_id FROM clips WHERE id = $1")
+        .bind(id.to_string())
+        .fetch_optional(pool)
+        .await?;
+    row.map(|row| row.try_get("user_id")).transpose()
+}
+
+// 用户是否已有相同内容的剪贴板
+pub async fn has_clip_content(
+    user_id: &str,
+    content_hash: &str,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    let row = query("SELECT id FROM clips WHERE user_id = $1 AND content_hash = $2")
+        .bind(user_id)
+        .bind(content_hash)
+        .fetch_optional(pool)
+        .await?;
+    Ok(row.is_some())
+}
+
+// 获取最近一次修改剪贴板的设备，未记录或剪贴板不存在时返回 None
+pub async fn get_clip_modified_by(
+    user_id: &str,
+    id: &Uuid,
+    pool: &DbPool,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = query("SELECT modified_by FROM clips WHERE id = $1 AND user_id = $2")
+        .bind(id.to_string())
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(row
+        .map(|row| row.try_get("modified_by"))
+        .transpose()?
+        .flatten())
+}
+
+// 修改剪贴板的访问时间和标签，版本号不一致或剪贴板不存在时返回 None，成功时返回新版本号
+//
+// modified_by 为发起修改的设备，用于识别之后来自其他设备的并发修改
+pub async fn update_clip(
+    user_id: &str,
+    clip: &ClipItem,
+    expected_version: Option<i64>,
+    modified_by: Option<&str>,
+    pool: &DbPool,
+) -> Result<Option<i64>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let version = write_clip_update(user_id, clip, expected_version, modified_by, &mut tx).await?;
+    if version.is_some() {
+        tx.commit().await?;
+    }
+    Ok(version)
+}
+
+// 在调用方的事务中修改剪贴板，规则同 update_clip
+pub async fn write_clip_update(
+    user_id: &str,
+    clip: &ClipItem,
+    expected_version: Option<i64>,
+    modified_by: Option<&str>,
+    tx: &mut Transaction<'_, Db>,
+) -> Result<Option<i64>, sqlx::Error> {
+    let previous = get_clip_revision_state(user_id, &clip.id, tx).await?;
+    let row = query(
+        r#"
+        UPDATE clips
+        SET accessed_at = $3, modified_by = $6, version = version + 1
+        WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL AND ($4 IS NULL OR version = $5)
+        RETURNING version
+        "#,
+    )
+    .bind(clip.id.to_string())
+    .bind(user_id)
+    .bind(clip.accessed_at.timestamp_millis())
+    .bind(expected_version)
+    .bind(expected_version)
+    .bind(modified_by)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    replace_clip_tags(user_id, &clip.id, &clip.tags, tx).await?;
+    if let Some(previous) = previous {
+        write_clip_revision(user_id, &clip.id, &previous, clip, tx).await?;
+    }
+    Ok(Some(row.try_get("version")?))
+}
+
+// 把剪贴板的内容和标签恢复为历史版本中的值，规则同 update_clip
+pub async fn restore_clip_revision(
+    user_id: &str,
+    clip: &ClipItem,
+    expected_version: Option<i64>,
+    modified_by: Option<&str>,
+    pool: &DbPool,
+) -> Result<Option<i64>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let previous = get_clip_revision_state(user_id, &clip.id, &mut tx).await?;
+    let row = query(
+        r#"
+        UPDATE clips
+        SET content = $3, preview = $4, size = $5, accessed_at = $6, modified_by = $7,
+            content_hash = CASE
+                WHEN EXISTS (SELECT 1 FROM clips AS other
+                             WHERE other.user_id = $2 AND other.content_hash = $8
+                               AND other.id != $1)
+                THEN NULL ELSE $8 END,
+            version = version + 1
+        WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL AND ($9 IS NULL OR version = $10)
+        RETURNING version
+        "#,
+    )
+    .bind(clip.id.to_string())
+    .bind(user_id)
+    .bind(&clip.content)
+    .bind(&clip.preview)
+    .bind(clip.size)
+    .bind(clip.accessed_at.timestamp_millis())
+    .bind(modified_by)
+    .bind(content_hash(clip.content_type, &clip.content))
+    .bind(expected_version)
+    .bind(expected_version)
+    .fetch_optional(&mut tx)
+    .await?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    replace_clip_tags(user_id, &clip.id, &clip.tags, &mut tx).await?;
+    if let Some(previous) = previous {
+        write_clip_revision(user_id, &clip.id, &previous, clip, &mut tx).await?;
+    }
+    tx.commit().await?;
+    Ok(Some(row.try_get("version")?))
+}
+
+fn row_to_clip_revision(row: &AnyRow) -> Result<ClipRevision, sqlx::Error> {
+    let tags: String = row.try_get("tags")?;
+    Ok(ClipRevision {
+        revision: row.try_get("revision")?,
+        content: row.try_get("content")?,
+        preview: row.try_get("preview")?,
+        size: row.try_get("size")?,
+        tags: serde_json::from_str(&tags).map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+        modified_by: row.try_get("modified_by")?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+// 获取剪贴板的历史版本（按版本号倒序）
+pub async fn get_clip_revisions(
+    user_id: &str,
+    clip_id: &Uuid,
+    pool: &DbPool,
+) -> Result<Vec<ClipRevision>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT revision, content, preview, size, tags, modified_by, created_at
+        FROM clip_revisions
+        WHERE clip_id = $1 AND user_id = $2
+        ORDER BY revision DESC
+        "#,
+    )
+    .bind(clip_id.to_string())
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    rows.iter().map(row_to_clip_revision).collect()
+}
+
+// 获取剪贴板的一个历史版本
+pub async fn get_clip_revision(
+    user_id: &str,
+    clip_id: &Uuid,
+    revision: i64,
+    pool: &DbPool,
+) -> Result<Option<ClipRevision>, sqlx::Error> {
+    let row = query(
+        r#"
+        SELECT revision, content, preview, size, tags, modified_by, created_at
+        FROM clip_revisions
+        WHERE clip_id = $1 AND user_id = $2 AND revision = $3
+        "#,
+    )
+    .bind(clip_id.to_string())
+    .bind(user_id)
+    .bind(revision)
+    .fetch_optional(pool)
+    .await?;
+    row.as_ref().map(row_to_clip_revision).transpose()
+}
+
+// 读取剪贴板修改前的状态，修改成功后由 write_clip_revision 保存为历史版本
+async fn get_clip_revision_state(
+    user_id: &str,
+    clip_id: &Uuid,
+    tx: &mut Transaction<'_, Db>,
+) -> Result<Option<ClipRevision>, sqlx::Error> {
+    let row = query(&format!(
+        r#"
+        SELECT version AS revision, content, preview, size, {tags} AS tags, modified_by,
+               CAST($3 AS BIGINT) AS created_at
+        FROM clips
+        WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL
+        "#,
+        tags = clip_tags_column(),
+    ))
+    .bind(clip_id.to_string())
+    .bind(user_id)
+    .bind(Utc::now().timestamp())
+    .fetch_optional(&mut *tx)
+    .await?;
+    row.as_ref().map(row_to_clip_revision).transpose()
+}
+
+// 内容或标签有变化时把修改前的状态 previous 保存为历史版本，只保留最新的 max_revisions 个
+async fn write_clip_revision(
+    user_id: &str,
+    clip_id: &Uuid,
+    previous: &ClipRevision,
+    current: &ClipItem,
+    tx: &mut Transaction<'_, Db>,
+) -> Result<(), sqlx::Error> {
+    let max_revisions = max_revisions();
+    if max_revisions <= 0 || (previous.content == current.content && previous.tags == current.tags)
+    {
+        return Ok(());
+    }
+    query(
+        r#"
+        INSERT INTO clip_revisions
+            (id, clip_id, user_id, revision, content, preview, size, tags, modified_by, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        ON CONFLICT (clip_id, revision) DO NOTHING
+        "#,
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(clip_id.to_string())
+    .bind(user_id)
+    .bind(previous.revision)
+    .bind(&previous.content)
+    .bind(&previous.preview)
+    .bind(previous.size)
+    .bind(serde_json::to_string(&previous.tags).unwrap_or_default())
+    .bind(&previous.modified_by)
+    .bind(previous.created_at)
+    .execute(&mut *tx)
+    .await?;
+    query(
+        r#"
+        DELETE FROM clip_revisions
+        WHERE clip_id = $1 AND revision NOT IN (
+            SELECT revision FROM clip_revisions WHERE clip_id = $1
+            ORDER BY revision DESC LIMIT $2
+        )
+        "#,
+    )
+    .bind(clip_id.to_string())
+    .bind(max_revisions)
+    .execute(&mut *tx)
+    .await?;
+    Ok(())
+}
+
+// 固定或取消固定剪贴板，版本号不一致或剪贴板不存在时返回 None，成功时返回新版本号
+pub async fn set_clip_pinned(
+    user_id: &str,
+    id: &Uuid,
+    pinned: bool,
+    expected_version: Option<i64>,
+    modified_by: Option<&str>,
+    executor: impl Executor<'_, Database = Db>,
+) -> Result<Option<i64>, sqlx::Error> {
+    let row = query(
+        r#"
+        UPDATE clips
+        SET pinned = $3, modified_by = $6, version = version + 1
+        WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL AND ($4 IS NULL OR version = $5)
+        RETURNING version
+        "#,
+    )
+    .bind(id.to_string())
+    .bind(user_id)
+    .bind(pinned)
+    .bind(expected_version)
+    .bind(expected_version)
+    .bind(modified_by)
+    .fetch_optional(executor)
+    .await?;
+    row.map(|row| row.try_get("version")).transpose()
+}
+
+// 把剪贴板移到回收站，版本号不一致、剪贴板不存在或已在回收站中时返回 false
+//
+// 标签关联保留到彻底删除时，恢复后标签不变
+pub async fn delete_clip(
+    user_id: &str,
+    id: &Uuid,
+    expected_version: Option<i64>,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let deleted = write_clip_delete(user_id, id, expected_version, &mut tx).await?;
+    if deleted {
+        tx.commit().await?;
+    }
+    Ok(deleted)
+}
+
+// 在调用方的事务中把剪贴板移到回收站，规则同 delete_clip
+pub async fn write_clip_delete(
+    user_id: &str,
+    id: &Uuid,
+    expected_version: Option<i64>,
+    tx: &mut Transaction<'_, Db>,
+) -> Result<bool, sqlx::Error> {
+    let result = query(
+        r#"
+        UPDATE clips
+        SET deleted_at = $5, version = version + 1
+        WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL AND ($3 IS NULL OR version = $4)
+        "#,
+    )
+    .bind(id.to_string())
+    .bind(user_id)
+    .bind(expected_version)
+    .bind(expected_version)
+    .bind(Utc::now().timestamp_millis())
+    .execute(&mut *tx)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// 彻底删除剪贴板（包括回收站中的）及其标签关联，剪贴板不存在时返回 false
+pub async fn purge_clip(user_id: &str, id: &Uuid, pool: &DbPool) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let purged = write_clip_purge(user_id, id, None, &mut tx).await?;
+    if purged {
+        tx.commit().await?;
+    }
+    Ok(purged)
+}
+
+// 在调用方的事务中彻底删除剪贴板，版本号不一致或剪贴板不存在时返回 false
+pub async fn write_clip_purge(
+    user_id: &str,
+    id: &Uuid,
+    expected_version: Option<i64>,
+    tx: &mut Transaction<'_, Db>,
+) -> Result<bool, sqlx::Error> {
+    let result =
+        query("DELETE FROM clips WHERE id = $1 AND user_id = $2 AND ($3 IS NULL OR version = $4)")
+            .bind(id.to_string())
+            .bind(user_id)
+            .bind(expected_version)
+            .bind(expected_version)
+            .execute(&mut *tx)
+            .await?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+    query("DELETE FROM clip_tags WHERE clip_id = $1")
+        .bind(id.to_string())
+        .execute(&mut *tx)
+        .await?;
+    query("DELETE FROM clip_revisions WHERE clip_id = $1")
+        .bind(id.to_string())
+        .execute(&mut *tx)
+        .await?;
+    Ok(true)
+}
+
+// 从回收站恢复 device_id 可见的剪贴板，剪贴板不在回收站中时返回 false
+pub async fn restore_clip(
+    user_id: &str,
+    id: &Uuid,
+    device_id: Option<&str>,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    let result = query(&format!(
+        r#"
+        UPDATE clips
+        SET deleted_at = NULL, modified_by = $3, version = version + 1
+        WHERE id = $1 AND user_id = $2 AND deleted_at IS NOT NULL AND {}
+        "#,
+        visible_to("$3"),
+    ))
+    .bind(id.to_string())
+    .bind(user_id)
+    .bind(device_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// 获取回收站中的剪贴板，按删除时间倒序（相同时按 ID 倒序），提供 cursor 时从游标之后开始
+//
+// 可见范围的判断与 get_clips 相同
+pub async fn get_trashed_clips(
+    user_id: &str,
+    device_id: Option<&str>,
+    cursor: Option<&Cursor>,
+    limit: i64,
+    pool: &DbPool,
+) -> Result<Vec<TrashedClip>, sqlx::Error> {
+    let rows = query(&format!(
+        r#"
+        SELECT id, device_id, content_type, content, preview, size, source_app,
+               created_at, accessed_at, sync_status, encrypted,
+               {tags} AS tags,
+               visibility, key_id, nonce, version, pinned, source, shared_by,
+               expires_at, sensitive, deleted_at
+        FROM clips
+        WHERE user_id = $1 AND deleted_at IS NOT NULL AND {visible}
+          AND ($3 IS NULL OR deleted_at < $3 OR (deleted_at = $3 AND id < $4))
+        ORDER BY deleted_at DESC, id DESC
+        LIMIT $5
+        "#,
+        tags = clip_tags_column(),
+        visible = visible_to("$2"),
+    ))
+    .bind(user_id)
+    .bind(device_id)
+    .bind(cursor.map(|cursor| cursor.at))
+    .bind(cursor.map(|cursor| cursor.id.as_str()))
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    rows.iter()
+        .map(|row| {
+            Ok(TrashedClip {
+                clip: row_to_clip(row)?,
+                deleted_at: row.try_get("deleted_at")?,
+            })
+        })
+        .collect()
+}
+
+// 回收站中 device_id 可见的剪贴板数量
+pub async fn count_trashed_clips(
+    user_id: &str,
+    device_id: Option<&str>,
+    pool: &DbPool,
+) -> Result<i64, sqlx::Error> {
+    query(&format!(
+        "SELECT COUNT(*) AS total FROM clips WHERE user_id = $1 AND deleted_at IS NOT NULL AND {}",
+        visible_to("$2"),
+    ))
+    .bind(user_id)
+    .bind(device_id)
+    .fetch_one(pool)
+    .await?
+    .try_get("total")
+}
+
+fn row_to_device(row: &AnyRow) -> Result<Device, sqlx::Error> {
+    Ok(Device {
+        id: row.try_get("id")?,
+        name: row.try_get("name")?,
+        platform: row.try_get("platform")?,
+        created_at: row.try_get("created_at")?,
+        last_seen_at: row.try_get("last_seen_at")?,
+        revoked_at: row.try_get("revoked_at")?,
+    })
+}
+
+// 注册设备
+pub async fn insert_device(
+    user_id: &str,
+    device: &Device,
+    pool: &DbPool,
+) -> Result<(), sqlx::Error> {
+    query(
+        r#"
+        INSERT INTO devices (id, user_id, name, platform, created_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+    )
+    .bind(&device.id)
+    .bind(user_id)
+    .bind(&device.name)
+    .bind(&device.platform)
+    .bind(device.created_at)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// 获取用户的设备，include_revoked 为 false 时只返回未注销的设备
+//
+// 按注册时间排序（相同时按 ID），提供 cursor 时从游标之后开始
+pub async fn get_devices(
+    user_id: &str,
+    include_revoked: bool,
+    cursor: Option<&Cursor>,
+    limit: i64,
+    pool: &DbPool,
+) -> Result<Vec<Device>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT id, name, platform, created_at, last_seen_at, revoked_at
+        FROM devices
+        WHERE user_id = $1 AND ($2 OR revoked_at IS NULL)
+          AND ($3 IS NULL OR created_at > $3 OR (created_at = $3 AND id > $4))
+        ORDER BY created_at, id
+        LIMIT $5
+        "#,
+    )
+    .bind(user_id)
+    .bind(include_revoked)
+    .bind(cursor.map(|cursor| cursor.at))
+    .bind(cursor.map(|cursor| cursor.id.as_str()))
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    rows.iter().map(row_to_device).collect()
+}
+
+// 用户的设备数量，条件与 get_devices 相同
+pub async fn count_devices(
+    user_id: &str,
+    include_revoked: bool,
+    pool: &DbPool,
+) -> Result<i64, sqlx::Error> {
+    query("SELECT COUNT(*) AS total FROM devices WHERE user_id = $1 AND ($2 OR revoked_at IS NULL)")
+        .bind(user_id)
+        .bind(include_revoked)
+        .fetch_one(pool)
+        .await?
+        .try_get("total")
+}
+
+// 获取用户未注销的设备
+pub async fn get_active_device(
+    user_id: &str,
+    id: &str,
+    pool: &DbPool,
+) -> Result<Option<Device>, sqlx::Error> {
+    let row = query(
+        r#"
+        SELECT id, name, platform, created_at, last_seen_at, revoked_at
+        FROM devices
+        WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL
+        "#,
+    )
+    .bind(id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+    row.as_ref().map(row_to_device).transpose()
+}
+
+// 重命名未注销的设备，返回修改后的设备
+pub async fn rename_device(
+    user_id: &str,
+    id: &str,
+    name: &str,
+    pool: &DbPool,
+) -> Result<Option<Device>, sqlx::Error> {
+    let row = query(
+        r#"
+        UPDATE devices
+        SET name = $3
+        WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL
+        RETURNING id, name, platform, created_at, last_seen_at, revoked_at
+        "#,
+    )
+    .bind(id)
+    .bind(user_id)
+    .bind(name)
+    .fetch_optional(pool)
+    .await?;
+    row.as_ref().map(row_to_device).transpose()
+}
+
+// 注销设备
+pub async fn revoke_device(
+    user_id: &str,
+    id: &str,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    let result = query(
+        r#"
+        UPDATE devices
+        SET revoked_at = $3
+        WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL
+        "#,
+    )
+    .bind(id)
+    .bind(user_id)
+    .bind(Utc::now().timestamp())
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// 获取用户所有设备的同步规则
+pub async fn get_device_sync_rules(
+    user_id: &str,
+    pool: &DbPool,
+) -> Result<Vec<(String, SyncRule)>, sqlx::Error> {
+    let rows =
+        query("SELECT device_id, clip_types, max_size FROM device_sync_rules WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_all(pool)
+            .await?;
+    rows.iter()
+        .map(|row| Ok((row.try_get("device_id")?, row_to_sync_rule(row)?)))
+        .collect()
+}
+
+// 获取设备的同步规则，没有设置时返回 None
+pub async fn get_device_sync_rule(
+    user_id: &str,
+    device_id: &str,
+    pool: &DbPool,
+) -> Result<Option<SyncRule>, sqlx::Error> {
+    let row = query(
+        r#"
+        SELECT clip_types, max_size FROM device_sync_rules
+        WHERE user_id = $1 AND device_id = $2
+        "#,
+    )
+    .bind(user_id)
+    .bind(device_id)
+    .fetch_optional(pool)
+    .await?;
+    row.as_ref().map(row_to_sync_rule).transpose()
+}
+
+fn row_to_sync_rule(row: &AnyRow) -> Result<SyncRule, sqlx::Error> {
+    let clip_types: Option<String> = row.try_get("clip_types")?;
+    Ok(SyncRule {
+        clip_types: clip_types
+            .map(|types| serde_json::from_str(&types))
+            .transpose()
+            .map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+        max_size: row.try_get("max_size")?,
+    })
+}
+
+// 设置设备的同步规则（覆盖之前的规则）
+pub async fn set_device_sync_rule(
+    user_id: &str,
+    device_id: &str,
+    rule: &SyncRule,
+    pool: &DbPool,
+) -> Result<(), sqlx::Error> {
+    query(
+        r#"
+        INSERT INTO device_sync_rules (device_id, user_id, clip_types, max_size, updated_at)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT(device_id) DO UPDATE
+        SET clip_types = excluded.clip_types, max_size = excluded.max_size,
+            updated_at = excluded.updated_at
+        "#,
+    )
+    .bind(device_id)
+    .bind(user_id)
+    .bind(
+        rule.clip_types
+            .as_ref()
+            .map(|types| serde_json::to_string(types).unwrap_or_default()),
+    )
+    .bind(rule.max_size)
+    .bind(Utc::now().timestamp())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// 删除设备的同步规则，返回是否存在
+pub async fn delete_device_sync_rule(
+    user_id: &str,
+    device_id: &str,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    let result = query("DELETE FROM device_sync_rules WHERE user_id = $1 AND device_id = $2")
+        .bind(user_id)
+        .bind(device_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// 记录设备最近一次连接的时间
+pub async fn touch_device(user_id: &str, id: &str, pool: &DbPool) -> Result<(), sqlx::Error> {
+    query("UPDATE devices SET last_seen_at = $3 WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user_id)
+        .bind(Utc::now().timestamp())
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+// 在一个事务中批量更新设备最近一次在线的时间，不会把时间改得更早
+pub async fn touch_devices_at(
+    devices: &[DeviceLastSeen],
+    pool: &DbPool,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    for device in devices {
+        query(
+            r#"
+            UPDATE devices SET last_seen_at = $3
+            WHERE id = $1 AND user_id = $2 AND (last_seen_at IS NULL OR last_seen_at < $3)
+            "#,
+        )
+        .bind(&device.device_id)
+        .bind(&device.user_id)
+        .bind(device.last_seen_at)
+        .execute(&mut tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+// 写入审计日志，错误码按其 JSON 名称保存
+pub async fn insert_audit_log(record: &AuditRecord, pool: &DbPool) -> Result<(), sqlx::Error> {
+    let error_code = record
+        .error_code
+        .and_then(|code| serde_json::to_value(code).ok())
+        .and_then(|code| code.as_str().map(str::to_string));
+    query(
+        r#"
+        INSERT INTO audit_log
+            (id, event, user_id, actor_id, success, error_code, ip, user_agent, detail, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        "#,
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(record.event)
+    .bind(record.user_id.as_deref())
+    .bind(record.actor_id.as_deref())
+    .bind(record.error_code.is_none())
+    .bind(error_code)
+    .bind(record.ip.as_deref())
+    .bind(record.user_agent.as_deref())
+    .bind((!record.detail.is_null()).then(|| record.detail.to_string()))
+    .bind(Utc::now().timestamp())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// 审计日志的查询条件，$1 到 $6 依次为 user_id、event、success、ip、since、until
+const AUDIT_LOG_CONDITIONS: &str = r#"
+    ($1 IS NULL OR user_id = $1)
+    AND ($2 IS NULL OR event = $2)
+    AND ($3 IS NULL OR success = $3)
+    AND ($4 IS NULL OR ip = $4)
+    AND ($5 IS NULL OR created_at >= $5)
+    AND ($6 IS NULL OR created_at < $6)
+"#;
+
+fn bind_audit_log_filter<'q>(
+    query: sqlx::query::Query<'q, Db, AnyArguments<'q>>,
+    filter: &'q AuditLogQuery,
+) -> sqlx::query::Query<'q, Db, AnyArguments<'q>> {
+    query
+        .bind(filter.user_id.as_deref())
+        .bind(filter.event)
+        .bind(filter.success)
+        .bind(filter.ip.as_deref())
+        .bind(filter.since)
+        .bind(filter.until)
+}
+
+fn row_to_audit_log_entry(row: &AnyRow) -> Result<AuditLogEntry, sqlx::Error> {
+    let decode = |e| sqlx::Error::Decode(Box::new(e));
+    let error_code: Option<String> = row.try_get("error_code")?;
+    let detail: Option<String> = row.try_get("detail")?;
+    Ok(AuditLogEntry {
+        id: row.try_get("id")?,
+        event: row.try_get("event")?,
+        user_id: row.try_get("user_id")?,
+        actor_id: row.try_get("actor_id")?,
+        success: row.try_get("success")?,
+        error_code: error_code
+            .map(|code| serde_json::from_value(serde_json::Value::String(code)))
+            .transpose()
+            .map_err(decode)?,
+        ip: row.try_get("ip")?,
+        user_agent: row.try_get("user_agent")?,
+        detail: detail
+            .map(|detail| serde_json::from_str(&detail))
+            .transpose()
+            .map_err(decode)?
+            .unwrap_or_default(),
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+// 按条件查询审计日志，按时间倒序（相同时按 ID 倒序），提供 cursor 时从游标之后开始
+pub async fn get_audit_log(
+    filter: &AuditLogQuery,
+    limit: i64,
+    pool: &DbPool,
+) -> Result<Vec<AuditLogEntry>, sqlx::Error> {
+    let sql = format!(
+        r#"
+        SELECT id, event, user_id, actor_id, success, error_code, ip, user_agent, detail,
+               created_at
+        FROM audit_log
+        WHERE {AUDIT_LOG_CONDITIONS}
+          AND ($7 IS NULL OR created_at < $7 OR (created_at = $7 AND id < $8))
+        ORDER BY created_at DESC, id DESC
+        LIMIT $9
+        "#
+    );
+    let cursor = filter.cursor.as_ref();
+    let rows = bind_audit_log_filter(query(&sql), filter)
+        .bind(cursor.map(|cursor| cursor.at))
+        .bind(cursor.map(|cursor| cursor.id.as_str()))
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+    rows.iter().map(row_to_audit_log_entry).collect()
+}
+
+// 符合条件的审计日志数量，条件与 get_audit_log 相同
+pub async fn count_audit_log(filter: &AuditLogQuery, pool: &DbPool) -> Result<i64, sqlx::Error> {
+    let sql = format!("SELECT COUNT(*) AS total FROM audit_log WHERE {AUDIT_LOG_CONDITIONS}");
+    bind_audit_log_filter(query(&sql), filter)
+        .fetch_one(pool)
+        .await?
+        .try_get("total")
+}
+
+// 删除早于 before 的审计日志，返回删除的条数
+pub async fn delete_audit_log_before(before: i64, pool: &DbPool) -> Result<u64, sqlx::Error> {
+    let result = query("DELETE FROM audit_log WHERE created_at < $1")
+        .bind(before)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+fn row_to_webhook(row: &AnyRow) -> Result<Webhook, sqlx::Error> {
+    let events: String = row.try_get("events")?;
+    Ok(Webhook {
+        id: row.try_get("id")?,
+        url: row.try_get("url")?,
+        secret: row.try_get("secret")?,
+        events: serde_json::from_str(&events).map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+        enabled: row.try_get("enabled")?,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+// 获取用户的 Webhook，按创建时间排序
+pub async fn get_webhooks(user_id: &str, pool: &DbPool) -> Result<Vec<Webhook>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT id, url, secret, events, enabled, created_at, updated_at
+        FROM webhooks
+        WHERE user_id = $1
+        ORDER BY created_at, id
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    rows.iter().map(row_to_webhook).collect()
+}
+
+// 获取用户已启用的 Webhook
+pub async fn get_enabled_webhooks(
+    user_id: &str,
+    pool: &DbPool,
+) -> Result<Vec<Webhook>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT id, url, secret, events, enabled, created_at, updated_at
+        FROM webhooks
+        WHERE user_id = $1 AND enabled = TRUE
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    rows.iter().map(row_to_webhook).collect()
+}
+
+// 获取用户的单个 Webhook
+pub async fn get_webhook(
+    user_id: &str,
+    id: &str,
+    pool: &DbPool,
+) -> Result<Option<Webhook>, sqlx::Error> {
+    let row = query(
+        r#"
+        SELECT id, url, secret, events, enabled, created_at, updated_at
+        FROM webhooks
+        WHERE id = $1 AND user_id = $2
+        "#,
+    )
+    .bind(id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+    row.as_ref().map(row_to_webhook).transpose()
+}
+
+// 保存 Webhook
+pub async fn insert_webhook(
+    user_id: &str,
+    webhook: &Webhook,
+    pool: &DbPool,
+) -> Result<(), sqlx::Error> {
+    query(
+        r#"
+        INSERT INTO webhooks (id, user_id, url, secret, events, enabled, created_at, updated_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+    )
+    .bind(&webhook.id)
+    .bind(user_id)
+    .bind(&webhook.url)
+    .bind(&webhook.secret)
+    .bind(serde_json::to_string(&webhook.events).unwrap_or_default())
+    .bind(webhook.enabled)
+    .bind(webhook.created_at)
+    .bind(webhook.updated_at)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// 修改 Webhook 的地址、订阅的事件和启用状态，不存在时返回 false
+pub async fn update_webhook(
+    user_id: &str,
+    webhook: &Webhook,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    let result = query(
+        r#"
+        UPDATE webhooks
+        SET url = $3, events = $4, enabled = $5, updated_at = $6
+        WHERE id = $1 AND user_id = $2
+        "#,
+    )
+    .bind(&webhook.id)
+    .bind(user_id)
+    .bind(&webhook.url)
+    .bind(serde_json::to_string(&webhook.events).unwrap_or_default())
+    .bind(webhook.enabled)
+    .bind(webhook.updated_at)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// 删除 Webhook 及其投递记录
+pub async fn delete_webhook(user_id: &str, id: &str, pool: &DbPool) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let result = query("DELETE FROM webhooks WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user_id)
+        .execute(&mut tx)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+    query("DELETE FROM webhook_deliveries WHERE webhook_id = $1")
+        .bind(id)
+        .execute(&mut tx)
+        .await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+fn row_to_webhook_delivery(row: &AnyRow) -> Result<WebhookDelivery, sqlx::Error> {
+    let event: String = row.try_get("event")?;
+    Ok(WebhookDelivery {
+        id: row.try_get("id")?,
+        webhook_id: row.try_get("webhook_id")?,
+        event: serde_json::from_value(serde_json::Value::String(event))
+            .map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+        status: row.try_get("status")?,
+        attempts: row.try_get("attempts")?,
+        next_attempt_at: row.try_get("next_attempt_at")?,
+        response_status: row.try_get("response_status")?,
+        error: row.try_get("error")?,
+        created_at: row.try_get("created_at")?,
+        delivered_at: row.try_get("delivered_at")?,
+    })
+}
+
+// 保存待投递的事件，payload 为发送的请求体
+pub async fn insert_webhook_delivery(
+    user_id: &str,
+    delivery: &WebhookDelivery,
+    payload: &str,
+    pool: &DbPool,
+) -> Result<(), sqlx::Error> {
+    query(
+        r#"
+        INSERT INTO webhook_deliveries
+            (id, webhook_id, user_id, event, payload, status, attempts, next_attempt_at,
+             created_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        "#,
+    )
+    .bind(&delivery.id)
+    .bind(&delivery.webhook_id)
+    .bind(user_id)
+    .bind(delivery.event.as_str())
+    .bind(payload)
+    .bind(&delivery.status)
+    .bind(delivery.attempts)
+    .bind(delivery.next_attempt_at)
+    .bind(delivery.created_at)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// 获取 Webhook 的投递记录，按时间倒序（相同时按 ID 倒序），提供 cursor 时从游标之后开始
+pub async fn get_webhook_deliveries(
+    webhook_id: &str,
+    cursor: Option<&Cursor>,
+    limit: i64,
+    pool: &DbPool,
+) -> Result<Vec<WebhookDelivery>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT id, webhook_id, event, status, attempts, next_attempt_at, response_status, error,
+               created_at, delivered_at
+        FROM webhook_deliveries
+        WHERE webhook_id = $1
+          AND ($2 IS NULL OR created_at < $2 OR (created_at = $2 AND id < $3))
+        ORDER BY created_at DESC, id DESC
+        LIMIT $4
+        "#,
+    )
+    .bind(webhook_id)
+    .bind(cursor.map(|cursor| cursor.at))
+    .bind(cursor.map(|cursor| cursor.id.as_str()))
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    rows.iter().map(row_to_webhook_delivery).collect()
+}
+
+// Webhook 的投递记录数量
+pub async fn count_webhook_deliveries(webhook_id: &str, pool: &DbPool) -> Result<i64, sqlx::Error> {
+    query("SELECT COUNT(*) AS total FROM webhook_deliveries WHERE webhook_id = $1")
+        .bind(webhook_id)
+        .fetch_one(pool)
+        .await?
+        .try_get("total")
+}
+
+// 获取已到尝试时间的投递（Webhook 已停用的暂不投递），按尝试时间排序
+pub async fn get_due_webhook_deliveries(
+    now: i64,
+    limit: i64,
+    pool: &DbPool,
+) -> Result<Vec<DueDelivery>, sqlx::Error> {
+    let rows = query(
+        r#"
+        SELECT d.id, d.webhook_id, d.event, d.payload, d.status, d.attempts, d.next_attempt_at,
+               d.response_status, d.error, d.created_at, d.delivered_at, w.url, w.secret
+        FROM webhook_deliveries d
+        JOIN webhooks w ON w.id = d.webhook_id
+        WHERE d.status = 'pending' AND d.next_attempt_at <= $1 AND w.enabled = TRUE
+        ORDER BY d.next_attempt_at
+        LIMIT $2
+        "#,
+    )
+    .bind(now)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    rows.iter()
+        .map(|row| {
+            Ok(DueDelivery {
+                delivery: row_to_webhook_delivery(row)?,
+                url: row.try_get("url")?,
+                secret: row.try_get("secret")?,
+                payload: row.try_get("payload")?,
+            })
+        })
+        .collect()
+}
+
+// 发送前抢占投递：把下次尝试时间改为 lease_until，已被其他节点抢占或已修改时返回 false
+pub async fn claim_webhook_delivery(
+    delivery: &WebhookDelivery,
+    lease_until: i64,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    let result = query(
+        r#"
+        UPDATE webhook_deliveries
+        SET next_attempt_at = $4
+        WHERE id = $1 AND status = 'pending' AND attempts = $2 AND next_attempt_at = $3
+        "#,
+    )
+    .bind(&delivery.id)
+    .bind(delivery.attempts)
+    .bind(delivery.next_attempt_at)
+    .bind(lease_until)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// 保存一次尝试的结果
+pub async fn finish_webhook_attempt(
+    delivery: &WebhookDelivery,
+    pool: &DbPool,
+) -> Result<(), sqlx::Error> {
+    query(
+        r#"
+        UPDATE webhook_deliveries
+        SET status = $2, attempts = $3, next_attempt_at = $4, response_status = $5, error = $6,
+            delivered_at = $7
+        WHERE id = $1
+        "#,
+    )
+    .bind(&delivery.id)
+    .bind(&delivery.status)
+    .bind(delivery.attempts)
+    .bind(delivery.next_attempt_at)
+    .bind(delivery.response_status)
+    .bind(&delivery.error)
+    .bind(delivery.delivered_at)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// 删除早于 before 且已完成（成功或放弃）的投递记录，返回删除的条数
+pub async fn delete_webhook_deliveries_before(
+    before: i64,
+    pool: &DbPool,
+) -> Result<u64, sqlx::Error> {
+    let result =
+        query("DELETE FROM webhook_deliveries WHERE created_at < $1 AND status <> 'pending'")
+            .bind(before)
+            .execute(pool)
+            .await?;
+    Ok(result.rows_affected())
+}
+
+fn row_to_job(row: &AnyRow) -> Result<Job, sqlx::Error> {
+    let payload: String = row.try_get("payload")?;
+    Ok(Job {
+        id: row.try_get("id")?,
+        kind: row.try_get("kind")?,
+        payload: serde_json::from_str(&payload).map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+        priority: row.try_get("priority")?,
+        status: row.try_get("status")?,
+        attempts: row.try_get("attempts")?,
+        max_attempts: row.try_get("max_attempts")?,
+        run_at: row.try_get("run_at")?,
+        error: row.try_get("error")?,
+        created_at: row.try_get("created_at")?,
+        finished_at: row.try_get("finished_at")?,
+    })
+}
+
+const JOB_COLUMNS: &str = "id, kind, payload, priority, status, attempts, max_attempts, run_at, \
+                           error, created_at, finished_at";
+
+// 保存待执行的后台任务
+pub async fn insert_job(
+    job: &Job,
+    executor: impl Executor<'_, Database = Db>,
+) -> Result<(), sqlx::Error> {
+    query(&format!(
+        "INSERT INTO jobs ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+        JOB_COLUMNS
+    ))
+    .bind(&job.id)
+    .bind(&job.kind)
+    .bind(job.payload.to_string())
+    .bind(job.priority)
+    .bind(&job.status)
+    .bind(job.attempts)
+    .bind(job.max_attempts)
+    .bind(job.run_at)
+    .bind(&job.error)
+    .bind(job.created_at)
+    .bind(job.finished_at)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+// 获取后台任务
+pub async fn get_job(id: &str, pool: &DbPool) -> Result<Option<Job>, sqlx::Error> {
+    let row = query(&format!("SELECT {} FROM jobs WHERE id = $1", JOB_COLUMNS))
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+    row.as_ref().map(row_to_job).transpose()
+}
+
+// 按状态和类型获取后台任务（按创建时间倒序），返回 (总数, 当前页)
+pub async fn get_jobs(
+    status: Option<&str>,
+    kind: Option<&str>,
+    limit: i64,
+    offset: i64,
+    pool: &DbPool,
+) -> Result<(i64, Vec<Job>), sqlx::Error> {
+    let filter = "FROM jobs WHERE ($1 IS NULL OR status = $1) AND ($2 IS NULL OR kind = $2)";
+    let total = query(&format!("SELECT COUNT(*) AS total {}", filter))
+        .bind(status)
+        .bind(kind)
+        .fetch_one(pool)
+        .await?
+        .try_get("total")?;
+    let rows = query(&format!(
+        "SELECT {} {} ORDER BY created_at DESC, id DESC LIMIT $3 OFFSET $4",
+        JOB_COLUMNS, filter
+    ))
+    .bind(status)
+    .bind(kind)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await?;
+    let jobs = rows.iter().map(row_to_job).collect::<Result<_, _>>()?;
+    Ok((total, jobs))
+}
+
+// 获取已到执行时间的任务（包括租约已到期的执行中任务），按优先级和执行时间排序
+pub async fn get_due_jobs(now: i64, limit: i64, pool: &DbPool) -> Result<Vec<Job>, sqlx::Error> {
+    let rows = query(&format!(
+        r#"
+        SELECT {}
+        FROM jobs
+        WHERE status IN ('pending', 'running') AND run_at <= $1
+        ORDER BY priority DESC, run_at, created_at
+        LIMIT $2
+        "#,
+        JOB_COLUMNS
+    ))
+    .bind(now)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    rows.iter().map(row_to_job).collect()
+}
+
+// 执行前抢占任务：标记为执行中、执行次数加一，run_at 改为租约到期时间 lease_until，
+// 已被其他节点抢占或已修改时返回 false
+pub async fn claim_job(job: &Job, lease_until: i64, pool: &DbPool) -> Result<bool, sqlx::Error> {
+    let result = query(
+        r#"
+        UPDATE jobs
+        SET status = 'running', attempts = attempts + 1, run_at = $4
+        WHERE id = $1 AND status = $2 AND run_at = $3
+        "#,
+    )
+    .bind(&job.id)
+    .bind(&job.status)
+    .bind(job.run_at)
+    .bind(lease_until)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// 保存一次执行的结果
+pub async fn finish_job(job: &Job, pool: &DbPool) -> Result<(), sqlx::Error> {
+    query(
+        r#"
+        UPDATE jobs
+        SET status = $2, run_at = $3, error = $4, finished_at = $5
+        WHERE id = $1
+        "#,
+    )
+    .bind(&job.id)
+    .bind(&job.status)
+    .bind(job.run_at)
+    .bind(&job.error)
+    .bind(job.finished_at)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// 重新执行死信任务（执行次数清零），任务不存在或不是死信时返回 false
+pub async fn retry_job(id: &str, now: i64, pool: &DbPool) -> Result<bool, sqlx::Error> {
+    let result = query(
+        r#"
+        UPDATE jobs
+        SET status = 'pending', attempts = 0, run_at = $2, finished_at = NULL
+        WHERE id = $1 AND status = 'dead'
+        "#,
+    )
+    .bind(id)
+    .bind(now)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// 删除未在执行中的任务，任务不存在或正在执行时返回 false
+pub async fn delete_job(id: &str, pool: &DbPool) -> Result<bool, sqlx::Error> {
+    let result = query("DELETE FROM jobs WHERE id = $1 AND status <> 'running'")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// 删除早于 before 完成的任务（不包括死信），返回删除的条数
+pub async fn delete_jobs_before(before: i64, pool: &DbPool) -> Result<u64, sqlx::Error> {
+    let result = query("DELETE FROM jobs WHERE status = 'done' AND finished_at < $1")
+        .bind(before)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_like_escapes_wildcards() {
+        assert_eq!(escape_like(r"100%_a\b"), r"100\%\_a\\b");
+    }
+
+    #[test]
+    fn highlight_snippet_marks_match_case_insensitively() {
+        assert_eq!(
+            highlight_snippet("Hello World", "world", 24),
+            "Hello <mark>World</mark>"
+        );
+        assert_eq!(
+            highlight_snippet("第一段内容，中文内容测试，最后一段", "中文", 6),
+            "…容，<mark>中文</mark>内容…"
+        );
+    }
+
+    #[test]
+    fn highlight_snippet_without_match_truncates() {
+        assert_eq!(highlight_snippet("abcdef", "xyz", 4), "abcd…");
+    }
+}
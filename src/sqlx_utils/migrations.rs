@@ -0,0 +1,1676 @@
+use log::info;
+use sqlx::{Executor, Row, Transaction, query};
+
+use super::backend::{Backend, backend};
+use super::{Db, DbPool};
+use std::collections::HashSet;
+
+/// 数据库版本迁移
+///
+/// 每个迁移只执行一次，已执行的版本记录在 `schema_migrations` 表中。
+/// 新的表结构修改只能追加新的迁移，不能修改已发布的迁移。
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    /// 执行 sql 之前需要存在的列
+    pub columns: &'static [Column],
+    pub sql: &'static str,
+    /// PostgreSQL 的 sql，表结构与 sql 相同，整数列使用 BIGINT、布尔列使用 BOOLEAN
+    pub postgres: &'static str,
+}
+
+/// 表已存在但缺少该列时补上，表不存在时跳过（由迁移的 sql 创建）
+///
+/// 迁移机制引入之前创建的数据库中，表可能缺少后来增加的列；这些数据库只可能是 SQLite
+pub struct Column {
+    pub table: &'static str,
+    pub name: &'static str,
+    pub definition: &'static str,
+}
+
+/// 迁移记录表结构定义
+const CREATE_SCHEMA_MIGRATIONS_TABLE_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS schema_migrations (
+    version BIGINT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    applied_at BIGINT NOT NULL
+);
+"#;
+
+/// 按版本号顺序排列的全部迁移
+pub const MIGRATIONS: &[Migration] = &[
+    // 迁移机制引入之前由 crate_db 创建的表
+    //
+    // 版本号为 0，在所有迁移之前执行；已执行过其他迁移的数据库升级时也会补上这些表
+    Migration {
+        version: 0,
+        name: "create_base_tables",
+        columns: &[
+            Column {
+                table: "users",
+                name: "version",
+                definition: "INTEGER NOT NULL DEFAULT 1",
+            },
+            Column {
+                table: "users",
+                name: "disabled",
+                definition: "INTEGER NOT NULL DEFAULT 0",
+            },
+            Column {
+                table: "users",
+                name: "org_id",
+                definition: "TEXT",
+            },
+            Column {
+                table: "users",
+                name: "org_role",
+                definition: "TEXT",
+            },
+            Column {
+                table: "scheduled_clips",
+                name: "visibility",
+                definition: "TEXT",
+            },
+        ],
+        sql: r#"
+        -- 用户表
+        CREATE TABLE IF NOT EXISTS users (
+            user_id TEXT PRIMARY KEY NOT NULL,
+            username TEXT NOT NULL,
+            email TEXT UNIQUE NOT NULL,
+            password TEXT NOT NULL,
+            head_uri TEXT,
+            version INTEGER NOT NULL DEFAULT 1,
+            disabled INTEGER NOT NULL DEFAULT 0,
+            org_id TEXT,
+            org_role TEXT
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_users_username ON users(username);
+        CREATE INDEX IF NOT EXISTS idx_users_org ON users(org_id);
+
+        -- 组织表（成员关系保存在 users.org_id / users.org_role）
+        CREATE TABLE IF NOT EXISTS organizations (
+            id TEXT PRIMARY KEY NOT NULL,
+            name TEXT NOT NULL,
+            quota_bytes INTEGER,
+            created_at INTEGER NOT NULL
+        );
+
+        -- 用户设置表（键值对，值为 JSON 文本）
+        CREATE TABLE IF NOT EXISTS user_settings (
+            user_id TEXT NOT NULL,
+            key TEXT NOT NULL,
+            value TEXT NOT NULL,
+            updated_at INTEGER NOT NULL,
+            PRIMARY KEY (user_id, key)
+        );
+
+        -- 公告表
+        CREATE TABLE IF NOT EXISTS announcements (
+            id TEXT PRIMARY KEY NOT NULL,
+            level TEXT NOT NULL,
+            title TEXT NOT NULL,
+            content TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            expires_at INTEGER
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_announcements_created_at ON announcements(created_at);
+
+        -- 定时投递剪贴板表
+        --
+        -- status: pending / delivered / cancelled
+        -- visibility: 可见范围（JSON），为空表示所有设备可见
+        CREATE TABLE IF NOT EXISTS scheduled_clips (
+            id TEXT PRIMARY KEY NOT NULL,
+            user_id TEXT NOT NULL,
+            content_type TEXT NOT NULL,
+            content TEXT NOT NULL,
+            deliver_at INTEGER NOT NULL,
+            created_at INTEGER NOT NULL,
+            status TEXT NOT NULL DEFAULT 'pending',
+            visibility TEXT
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_scheduled_clips_due ON scheduled_clips(status, deliver_at);
+        CREATE INDEX IF NOT EXISTS idx_scheduled_clips_user ON scheduled_clips(user_id);
+
+        -- 联系人表（requester 发起请求，addressee 接受后 status 变为 accepted）
+        CREATE TABLE IF NOT EXISTS contacts (
+            requester_id TEXT NOT NULL,
+            addressee_id TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'pending',
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL,
+            PRIMARY KEY (requester_id, addressee_id)
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_contacts_addressee ON contacts(addressee_id);
+
+        -- 黑名单表
+        CREATE TABLE IF NOT EXISTS blocked_users (
+            user_id TEXT NOT NULL,
+            blocked_id TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            PRIMARY KEY (user_id, blocked_id)
+        );
+
+        -- 服务条款/隐私政策版本表
+        CREATE TABLE IF NOT EXISTS legal_documents (
+            kind TEXT NOT NULL,
+            version INTEGER NOT NULL,
+            title TEXT NOT NULL,
+            content TEXT NOT NULL,
+            published_at INTEGER NOT NULL,
+            PRIMARY KEY (kind, version)
+        );
+
+        CREATE TABLE IF NOT EXISTS legal_acceptances (
+            user_id TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            version INTEGER NOT NULL,
+            accepted_at INTEGER NOT NULL,
+            PRIMARY KEY (user_id, kind, version)
+        );
+
+        -- 待确认的邮箱修改表
+        CREATE TABLE IF NOT EXISTS email_changes (
+            token TEXT PRIMARY KEY NOT NULL,
+            user_id TEXT NOT NULL,
+            new_email TEXT NOT NULL,
+            expires_at INTEGER NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_email_changes_user ON email_changes(user_id);
+
+        -- 昵称修改历史表
+        CREATE TABLE IF NOT EXISTS username_history (
+            user_id TEXT NOT NULL,
+            old_username TEXT NOT NULL,
+            new_username TEXT NOT NULL,
+            changed_at INTEGER NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_username_history_user
+            ON username_history(user_id, changed_at);
+
+        -- 幂等键：同一用户相同键的重试请求直接返回首次请求的响应
+        --
+        -- response 为 NULL 表示首次请求仍在处理中
+        CREATE TABLE IF NOT EXISTS idempotency_keys (
+            user_id TEXT NOT NULL,
+            key TEXT NOT NULL,
+            fingerprint TEXT NOT NULL,
+            response TEXT,
+            created_at INTEGER NOT NULL,
+            PRIMARY KEY (user_id, key)
+        );
+
+        -- 同步冲突表（两个候选版本及解决结果均为 JSON 文本）
+        CREATE TABLE IF NOT EXISTS sync_conflicts (
+            id TEXT PRIMARY KEY NOT NULL,
+            user_id TEXT NOT NULL,
+            item_id TEXT NOT NULL,
+            local TEXT NOT NULL,
+            remote TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            resolution TEXT,
+            resolved_at INTEGER
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_sync_conflicts_user ON sync_conflicts(user_id, resolved_at);
+
+        -- 设备分组表
+        CREATE TABLE IF NOT EXISTS device_groups (
+            id TEXT PRIMARY KEY NOT NULL,
+            user_id TEXT NOT NULL,
+            name TEXT NOT NULL,
+            isolated INTEGER NOT NULL DEFAULT 0,
+            created_at INTEGER NOT NULL,
+            UNIQUE (user_id, name)
+        );
+
+        CREATE TABLE IF NOT EXISTS device_group_members (
+            group_id TEXT NOT NULL,
+            device_id TEXT NOT NULL,
+            PRIMARY KEY (group_id, device_id)
+        );
+
+        -- 举报表
+        --
+        -- status: open / resolved，action 记录管理员的处理动作
+        CREATE TABLE IF NOT EXISTS abuse_reports (
+            id TEXT PRIMARY KEY NOT NULL,
+            reporter_id TEXT NOT NULL,
+            target_type TEXT NOT NULL,
+            target_id TEXT NOT NULL,
+            reported_user_id TEXT NOT NULL,
+            reason TEXT NOT NULL,
+            details TEXT,
+            created_at INTEGER NOT NULL,
+            status TEXT NOT NULL DEFAULT 'open',
+            action TEXT,
+            note TEXT,
+            resolved_at INTEGER
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_abuse_reports_status ON abuse_reports(status, created_at);
+        "#,
+        postgres: r#"
+        -- 用户表
+        CREATE TABLE IF NOT EXISTS users (
+            user_id TEXT PRIMARY KEY NOT NULL,
+            username TEXT NOT NULL,
+            email TEXT UNIQUE NOT NULL,
+            password TEXT NOT NULL,
+            head_uri TEXT,
+            version BIGINT NOT NULL DEFAULT 1,
+            disabled BOOLEAN NOT NULL DEFAULT FALSE,
+            org_id TEXT,
+            org_role TEXT
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_users_username ON users(username);
+        CREATE INDEX IF NOT EXISTS idx_users_org ON users(org_id);
+
+        -- 组织表（成员关系保存在 users.org_id / users.org_role）
+        CREATE TABLE IF NOT EXISTS organizations (
+            id TEXT PRIMARY KEY NOT NULL,
+            name TEXT NOT NULL,
+            quota_bytes BIGINT,
+            created_at BIGINT NOT NULL
+        );
+
+        -- 用户设置表（键值对，值为 JSON 文本）
+        CREATE TABLE IF NOT EXISTS user_settings (
+            user_id TEXT NOT NULL,
+            key TEXT NOT NULL,
+            value TEXT NOT NULL,
+            updated_at BIGINT NOT NULL,
+            PRIMARY KEY (user_id, key)
+        );
+
+        -- 公告表
+        CREATE TABLE IF NOT EXISTS announcements (
+            id TEXT PRIMARY KEY NOT NULL,
+            level TEXT NOT NULL,
+            title TEXT NOT NULL,
+            content TEXT NOT NULL,
+            created_at BIGINT NOT NULL,
+            expires_at BIGINT
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_announcements_created_at ON announcements(created_at);
+
+        -- 定时投递剪贴板表
+        --
+        -- status: pending / delivered / cancelled
+        -- visibility: 可见范围（JSON），为空表示所有设备可见
+        CREATE TABLE IF NOT EXISTS scheduled_clips (
+            id TEXT PRIMARY KEY NOT NULL,
+            user_id TEXT NOT NULL,
+            content_type TEXT NOT NULL,
+            content TEXT NOT NULL,
+            deliver_at BIGINT NOT NULL,
+            created_at BIGINT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'pending',
+            visibility TEXT
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_scheduled_clips_due ON scheduled_clips(status, deliver_at);
+        CREATE INDEX IF NOT EXISTS idx_scheduled_clips_user ON scheduled_clips(user_id);
+
+        -- 联系人表（requester 发起请求，addressee 接受后 status 变为 accepted）
+        CREATE TABLE IF NOT EXISTS contacts (
+            requester_id TEXT NOT NULL,
+            addressee_id TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'pending',
+            created_at BIGINT NOT NULL,
+            updated_at BIGINT NOT NULL,
+            PRIMARY KEY (requester_id, addressee_id)
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_contacts_addressee ON contacts(addressee_id);
+
+        -- 黑名单表
+        CREATE TABLE IF NOT EXISTS blocked_users (
+            user_id TEXT NOT NULL,
+            blocked_id TEXT NOT NULL,
+            created_at BIGINT NOT NULL,
+            PRIMARY KEY (user_id, blocked_id)
+        );
+
+        -- 服务条款/隐私政策版本表
+        CREATE TABLE IF NOT EXISTS legal_documents (
+            kind TEXT NOT NULL,
+            version BIGINT NOT NULL,
+            title TEXT NOT NULL,
+            content TEXT NOT NULL,
+            published_at BIGINT NOT NULL,
+            PRIMARY KEY (kind, version)
+        );
+
+        CREATE TABLE IF NOT EXISTS legal_acceptances (
+            user_id TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            version BIGINT NOT NULL,
+            accepted_at BIGINT NOT NULL,
+            PRIMARY KEY (user_id, kind, version)
+        );
+
+        -- 待确认的邮箱修改表
+        CREATE TABLE IF NOT EXISTS email_changes (
+            token TEXT PRIMARY KEY NOT NULL,
+            user_id TEXT NOT NULL,
+            new_email TEXT NOT NULL,
+            expires_at BIGINT NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_email_changes_user ON email_changes(user_id);
+
+        -- 昵称修改历史表
+        CREATE TABLE IF NOT EXISTS username_history (
+            user_id TEXT NOT NULL,
+            old_username TEXT NOT NULL,
+            new_username TEXT NOT NULL,
+            changed_at BIGINT NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_username_history_user
+            ON username_history(user_id, changed_at);
+
+        -- 幂等键：同一用户相同键的重试请求直接返回首次请求的响应
+        --
+        -- response 为 NULL 表示首次请求仍在处理中
+        CREATE TABLE IF NOT EXISTS idempotency_keys (
+            user_id TEXT NOT NULL,
+            key TEXT NOT NULL,
+            fingerprint TEXT NOT NULL,
+            response TEXT,
+            created_at BIGINT NOT NULL,
+            PRIMARY KEY (user_id, key)
+        );
+
+        -- 同步冲突表（两个候选版本及解决结果均为 JSON 文本）
+        CREATE TABLE IF NOT EXISTS sync_conflicts (
+            id TEXT PRIMARY KEY NOT NULL,
+            user_id TEXT NOT NULL,
+            item_id TEXT NOT NULL,
+            local TEXT NOT NULL,
+            remote TEXT NOT NULL,
+            created_at BIGINT NOT NULL,
+            resolution TEXT,
+            resolved_at BIGINT
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_sync_conflicts_user ON sync_conflicts(user_id, resolved_at);
+
+        -- 设备分组表
+        CREATE TABLE IF NOT EXISTS device_groups (
+            id TEXT PRIMARY KEY NOT NULL,
+            user_id TEXT NOT NULL,
+            name TEXT NOT NULL,
+            isolated BOOLEAN NOT NULL DEFAULT FALSE,
+            created_at BIGINT NOT NULL,
+            UNIQUE (user_id, name)
+        );
+
+        CREATE TABLE IF NOT EXISTS device_group_members (
+            group_id TEXT NOT NULL,
+            device_id TEXT NOT NULL,
+            PRIMARY KEY (group_id, device_id)
+        );
+
+        -- 举报表
+        --
+        -- status: open / resolved，action 记录管理员的处理动作
+        CREATE TABLE IF NOT EXISTS abuse_reports (
+            id TEXT PRIMARY KEY NOT NULL,
+            reporter_id TEXT NOT NULL,
+            target_type TEXT NOT NULL,
+            target_id TEXT NOT NULL,
+            reported_user_id TEXT NOT NULL,
+            reason TEXT NOT NULL,
+            details TEXT,
+            created_at BIGINT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'open',
+            action TEXT,
+            note TEXT,
+            resolved_at BIGINT
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_abuse_reports_status ON abuse_reports(status, created_at);
+        "#,
+    },
+    // 剪贴板项目
+    //
+    // created_at / accessed_at 为毫秒时间戳（同一秒内可能复制多次，需要保证顺序）
+    // visibility 为可见范围（JSON），为空表示所有设备可见
+    Migration {
+        version: 1,
+        name: "create_clips",
+        columns: &[],
+        sql: r#"
+        CREATE TABLE IF NOT EXISTS clips (
+            id TEXT PRIMARY KEY NOT NULL,
+            user_id TEXT NOT NULL,
+            device_id TEXT NOT NULL,
+            content_type TEXT NOT NULL,
+            content TEXT NOT NULL,
+            preview TEXT NOT NULL,
+            size INTEGER NOT NULL,
+            source_app TEXT,
+            created_at INTEGER NOT NULL,
+            accessed_at INTEGER NOT NULL,
+            sync_status TEXT NOT NULL,
+            encrypted INTEGER NOT NULL DEFAULT 0,
+            visibility TEXT
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_clips_user_created ON clips(user_id, created_at);
+        "#,
+        postgres: r#"
+        CREATE TABLE IF NOT EXISTS clips (
+            id TEXT PRIMARY KEY NOT NULL,
+            user_id TEXT NOT NULL,
+            device_id TEXT NOT NULL,
+            content_type TEXT NOT NULL,
+            content TEXT NOT NULL,
+            preview TEXT NOT NULL,
+            size BIGINT NOT NULL,
+            source_app TEXT,
+            created_at BIGINT NOT NULL,
+            accessed_at BIGINT NOT NULL,
+            sync_status TEXT NOT NULL,
+            encrypted BOOLEAN NOT NULL DEFAULT FALSE,
+            visibility TEXT
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_clips_user_created ON clips(user_id, created_at);
+        "#,
+    },
+    // 用户的设备，revoked_at 不为空表示设备已被注销
+    Migration {
+        version: 2,
+        name: "create_devices",
+        columns: &[],
+        sql: r#"
+        CREATE TABLE IF NOT EXISTS devices (
+            id TEXT PRIMARY KEY NOT NULL,
+            user_id TEXT NOT NULL,
+            name TEXT NOT NULL,
+            platform TEXT,
+            created_at INTEGER NOT NULL,
+            last_seen_at INTEGER,
+            revoked_at INTEGER
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_devices_user ON devices(user_id);
+        "#,
+        postgres: r#"
+        CREATE TABLE IF NOT EXISTS devices (
+            id TEXT PRIMARY KEY NOT NULL,
+            user_id TEXT NOT NULL,
+            name TEXT NOT NULL,
+            platform TEXT,
+            created_at BIGINT NOT NULL,
+            last_seen_at BIGINT,
+            revoked_at BIGINT
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_devices_user ON devices(user_id);
+        "#,
+    },
+    // 标签及剪贴板与标签的关联，标签名在同一用户内唯一
+    Migration {
+        version: 3,
+        name: "create_tags",
+        columns: &[],
+        sql: r#"
+        CREATE TABLE IF NOT EXISTS tags (
+            id TEXT PRIMARY KEY NOT NULL,
+            user_id TEXT NOT NULL,
+            name TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            UNIQUE (user_id, name)
+        );
+
+        CREATE TABLE IF NOT EXISTS clip_tags (
+            clip_id TEXT NOT NULL,
+            tag_id TEXT NOT NULL,
+            PRIMARY KEY (clip_id, tag_id)
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_clip_tags_tag ON clip_tags(tag_id);
+        "#,
+        postgres: r#"
+        CREATE TABLE IF NOT EXISTS tags (
+            id TEXT PRIMARY KEY NOT NULL,
+            user_id TEXT NOT NULL,
+            name TEXT NOT NULL,
+            created_at BIGINT NOT NULL,
+            UNIQUE (user_id, name)
+        );
+
+        CREATE TABLE IF NOT EXISTS clip_tags (
+            clip_id TEXT NOT NULL,
+            tag_id TEXT NOT NULL,
+            PRIMARY KEY (clip_id, tag_id)
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_clip_tags_tag ON clip_tags(tag_id);
+        "#,
+    },
+    // 刷新令牌，只保存令牌的哈希值，revoked_at 不为空表示已注销或已轮换
+    Migration {
+        version: 4,
+        name: "create_refresh_tokens",
+        columns: &[],
+        sql: r#"
+        CREATE TABLE IF NOT EXISTS refresh_tokens (
+            id TEXT PRIMARY KEY NOT NULL,
+            user_id TEXT NOT NULL,
+            token_hash TEXT UNIQUE NOT NULL,
+            created_at INTEGER NOT NULL,
+            expires_at INTEGER NOT NULL,
+            revoked_at INTEGER
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_refresh_tokens_user ON refresh_tokens(user_id);
+        "#,
+        postgres: r#"
+        CREATE TABLE IF NOT EXISTS refresh_tokens (
+            id TEXT PRIMARY KEY NOT NULL,
+            user_id TEXT NOT NULL,
+            token_hash TEXT UNIQUE NOT NULL,
+            created_at BIGINT NOT NULL,
+            expires_at BIGINT NOT NULL,
+            revoked_at BIGINT
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_refresh_tokens_user ON refresh_tokens(user_id);
+        "#,
+    },
+    // 剪贴板全文索引，由触发器与 clips 表保持同步
+    //
+    // 使用 trigram 分词，中文等没有空格分隔的内容也能按子串搜索（查询至少 3 个字符）
+    // 加密的剪贴板内容为密文，不建立索引
+    Migration {
+        version: 5,
+        name: "create_clips_fts",
+        columns: &[],
+        sql: r#"
+        CREATE VIRTUAL TABLE IF NOT EXISTS clips_fts USING fts5(
+            clip_id UNINDEXED,
+            content,
+            preview,
+            tokenize = 'trigram'
+        );
+
+        INSERT INTO clips_fts (clip_id, content, preview)
+        SELECT id, content, preview FROM clips WHERE encrypted = 0;
+
+        CREATE TRIGGER IF NOT EXISTS clips_fts_insert AFTER INSERT ON clips
+        WHEN NEW.encrypted = 0
+        BEGIN
+            INSERT INTO clips_fts (clip_id, content, preview)
+            VALUES (NEW.id, NEW.content, NEW.preview);
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS clips_fts_delete AFTER DELETE ON clips
+        BEGIN
+            DELETE FROM clips_fts WHERE clip_id = OLD.id;
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS clips_fts_update
+        AFTER UPDATE OF content, preview, encrypted ON clips
+        BEGIN
+            DELETE FROM clips_fts WHERE clip_id = OLD.id;
+            INSERT INTO clips_fts (clip_id, content, preview)
+            SELECT NEW.id, NEW.content, NEW.preview WHERE NEW.encrypted = 0;
+        END;
+        "#,
+        postgres: r#"
+        CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+        CREATE INDEX IF NOT EXISTS idx_clips_content_trgm ON clips USING gin (content gin_trgm_ops);
+        CREATE INDEX IF NOT EXISTS idx_clips_preview_trgm ON clips USING gin (preview gin_trgm_ops);
+        "#,
+    },
+    // 剪贴板内容哈希，用于去重
+    //
+    // 已有的剪贴板在启动时补算哈希（db::backfill_content_hashes），
+    // 重复的旧剪贴板保留为空，不参与去重
+    Migration {
+        version: 6,
+        name: "add_clips_content_hash",
+        columns: &[],
+        sql: r#"
+        ALTER TABLE clips ADD COLUMN content_hash TEXT;
+
+        CREATE UNIQUE INDEX IF NOT EXISTS idx_clips_user_content_hash
+        ON clips(user_id, content_hash);
+        "#,
+        postgres: r#"
+        ALTER TABLE clips ADD COLUMN content_hash TEXT;
+
+        CREATE UNIQUE INDEX IF NOT EXISTS idx_clips_user_content_hash
+        ON clips(user_id, content_hash);
+        "#,
+    },
+    // 剪贴板变更记录，由触发器写入，seq 单调递增，作为增量同步的游标
+    //
+    // 修改标签时 update_clip 同时会更新 accessed_at，因此也会记录一次 updated
+    Migration {
+        version: 7,
+        name: "create_change_log",
+        columns: &[],
+        sql: r#"
+        CREATE TABLE IF NOT EXISTS change_log (
+            seq INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id TEXT NOT NULL,
+            clip_id TEXT NOT NULL,
+            op TEXT NOT NULL,
+            changed_at INTEGER NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_change_log_user_seq ON change_log(user_id, seq);
+
+        CREATE TRIGGER IF NOT EXISTS change_log_clip_insert AFTER INSERT ON clips
+        BEGIN
+            INSERT INTO change_log (user_id, clip_id, op, changed_at)
+            VALUES (
+                NEW.user_id, NEW.id, 'created',
+                CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)
+            );
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS change_log_clip_update
+        AFTER UPDATE OF device_id, content, preview, source_app, created_at, accessed_at,
+            encrypted, visibility ON clips
+        BEGIN
+            INSERT INTO change_log (user_id, clip_id, op, changed_at)
+            VALUES (
+                NEW.user_id, NEW.id, 'updated',
+                CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)
+            );
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS change_log_clip_delete AFTER DELETE ON clips
+        BEGIN
+            INSERT INTO change_log (user_id, clip_id, op, changed_at)
+            VALUES (
+                OLD.user_id, OLD.id, 'deleted',
+                CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)
+            );
+        END;
+        "#,
+        postgres: r#"
+        CREATE TABLE IF NOT EXISTS change_log (
+            seq BIGSERIAL PRIMARY KEY,
+            user_id TEXT NOT NULL,
+            clip_id TEXT NOT NULL,
+            op TEXT NOT NULL,
+            changed_at BIGINT NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_change_log_user_seq ON change_log(user_id, seq);
+
+        CREATE OR REPLACE FUNCTION change_log_clip() RETURNS trigger AS $$
+        BEGIN
+            IF TG_OP = 'DELETE' THEN
+                INSERT INTO change_log (user_id, clip_id, op, changed_at)
+                VALUES (
+                    OLD.user_id, OLD.id, 'deleted',
+                    (extract(epoch FROM clock_timestamp()) * 1000)::BIGINT
+                );
+                RETURN OLD;
+            END IF;
+            INSERT INTO change_log (user_id, clip_id, op, changed_at)
+            VALUES (
+                NEW.user_id, NEW.id,
+                CASE TG_OP WHEN 'INSERT' THEN 'created' ELSE 'updated' END,
+                (extract(epoch FROM clock_timestamp()) * 1000)::BIGINT
+            );
+            RETURN NEW;
+        END;
+        $$ LANGUAGE plpgsql;
+
+        CREATE TRIGGER change_log_clip_insert AFTER INSERT ON clips
+        FOR EACH ROW EXECUTE FUNCTION change_log_clip();
+
+        CREATE TRIGGER change_log_clip_update
+        AFTER UPDATE OF device_id, content, preview, source_app, created_at, accessed_at,
+            encrypted, visibility ON clips
+        FOR EACH ROW EXECUTE FUNCTION change_log_clip();
+
+        CREATE TRIGGER change_log_clip_delete AFTER DELETE ON clips
+        FOR EACH ROW EXECUTE FUNCTION change_log_clip();
+        "#,
+    },
+    // 端到端加密：客户端使用的密钥 ID 和随机数，服务器只保存密文
+    Migration {
+        version: 8,
+        name: "add_clips_encryption",
+        columns: &[],
+        sql: r#"
+        ALTER TABLE clips ADD COLUMN key_id TEXT;
+        ALTER TABLE clips ADD COLUMN nonce TEXT;
+        "#,
+        postgres: r#"
+        ALTER TABLE clips ADD COLUMN key_id TEXT;
+        ALTER TABLE clips ADD COLUMN nonce TEXT;
+        "#,
+    },
+    // 邮箱验证：email_verified_at 为空表示未验证，已有用户视为已验证
+    Migration {
+        version: 9,
+        name: "add_email_verification",
+        columns: &[],
+        sql: r#"
+        ALTER TABLE users ADD COLUMN email_verified_at INTEGER;
+        UPDATE users SET email_verified_at = CAST(strftime('%s', 'now') AS INTEGER);
+
+        CREATE TABLE IF NOT EXISTS email_verifications (
+            token TEXT PRIMARY KEY NOT NULL,
+            user_id TEXT NOT NULL,
+            expires_at INTEGER NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_email_verifications_user ON email_verifications(user_id);
+        "#,
+        postgres: r#"
+        ALTER TABLE users ADD COLUMN email_verified_at BIGINT;
+        UPDATE users SET email_verified_at = extract(epoch FROM now())::BIGINT;
+
+        CREATE TABLE IF NOT EXISTS email_verifications (
+            token TEXT PRIMARY KEY NOT NULL,
+            user_id TEXT NOT NULL,
+            expires_at BIGINT NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_email_verifications_user ON email_verifications(user_id);
+        "#,
+    },
+    // 找回密码：只保存令牌的哈希，使用一次后删除
+    Migration {
+        version: 10,
+        name: "create_password_reset_tokens",
+        columns: &[],
+        sql: r#"
+        CREATE TABLE IF NOT EXISTS password_reset_tokens (
+            token_hash TEXT PRIMARY KEY NOT NULL,
+            user_id TEXT NOT NULL,
+            expires_at INTEGER NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_password_reset_tokens_user
+            ON password_reset_tokens(user_id);
+        "#,
+        postgres: r#"
+        CREATE TABLE IF NOT EXISTS password_reset_tokens (
+            token_hash TEXT PRIMARY KEY NOT NULL,
+            user_id TEXT NOT NULL,
+            expires_at BIGINT NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_password_reset_tokens_user
+            ON password_reset_tokens(user_id);
+        "#,
+    },
+    // 迁移机制引入之前的 clips 表以 JSON 数组保存标签（clips.tags），复制到 tags / clip_tags 后删除该列
+    //
+    // 新建的数据库没有该列，先补上空列再统一处理；PostgreSQL 数据库从未有过该列，无需处理
+    Migration {
+        version: 11,
+        name: "migrate_clips_tags_column",
+        columns: &[Column {
+            table: "clips",
+            name: "tags",
+            definition: "TEXT NOT NULL DEFAULT '[]'",
+        }],
+        sql: r#"
+        INSERT INTO tags (id, user_id, name, created_at)
+        SELECT
+            lower(
+                hex(randomblob(4)) || '-' || hex(randomblob(2)) || '-4'
+                || substr(hex(randomblob(2)), 2) || '-'
+                || substr('89AB', 1 + abs(random()) % 4, 1) || substr(hex(randomblob(2)), 2)
+                || '-' || hex(randomblob(6))
+            ),
+            user_id,
+            name,
+            CAST(strftime('%s', 'now') AS INTEGER)
+        FROM (
+            SELECT DISTINCT clips.user_id AS user_id, trim(tag.value) AS name
+            FROM clips,
+                json_each(CASE WHEN json_valid(clips.tags) THEN clips.tags ELSE '[]' END) AS tag
+            WHERE tag.type = 'text' AND trim(tag.value) <> ''
+        )
+        WHERE true
+        ON CONFLICT (user_id, name) DO NOTHING;
+
+        INSERT OR IGNORE INTO clip_tags (clip_id, tag_id)
+        SELECT clips.id, tags.id
+        FROM clips,
+            json_each(CASE WHEN json_valid(clips.tags) THEN clips.tags ELSE '[]' END) AS tag
+        JOIN tags ON tags.user_id = clips.user_id AND tags.name = trim(tag.value)
+        WHERE tag.type = 'text';
+
+        ALTER TABLE clips DROP COLUMN tags;
+        "#,
+        postgres: "",
+    },
+    // 剪贴板版本号，修改和删除时通过 If-Match 校验，每次修改加 1
+    Migration {
+        version: 12,
+        name: "add_clips_version",
+        columns: &[],
+        sql: r#"
+        ALTER TABLE clips ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+        "#,
+        postgres: r#"
+        ALTER TABLE clips ADD COLUMN version BIGINT NOT NULL DEFAULT 1;
+        "#,
+    },
+    // 固定（收藏）的剪贴板不会被保留策略清理，固定状态的变化同样记录到变更记录
+    Migration {
+        version: 13,
+        name: "add_clips_pinned",
+        columns: &[],
+        sql: r#"
+        ALTER TABLE clips ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;
+
+        DROP TRIGGER IF EXISTS change_log_clip_update;
+
+        CREATE TRIGGER change_log_clip_update
+        AFTER UPDATE OF device_id, content, preview, source_app, created_at, accessed_at,
+            encrypted, visibility, pinned ON clips
+        BEGIN
+            INSERT INTO change_log (user_id, clip_id, op, changed_at)
+            VALUES (
+                NEW.user_id, NEW.id, 'updated',
+                CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)
+            );
+        END;
+        "#,
+        postgres: r#"
+        ALTER TABLE clips ADD COLUMN pinned BOOLEAN NOT NULL DEFAULT FALSE;
+
+        DROP TRIGGER IF EXISTS change_log_clip_update ON clips;
+
+        CREATE TRIGGER change_log_clip_update
+        AFTER UPDATE OF device_id, content, preview, source_app, created_at, accessed_at,
+            encrypted, visibility, pinned ON clips
+        FOR EACH ROW EXECUTE FUNCTION change_log_clip();
+        "#,
+    },
+    // 剪贴板分享链接，只保存令牌的哈希值，revoked_at 不为空表示已撤销
+    Migration {
+        version: 14,
+        name: "create_shares",
+        columns: &[],
+        sql: r#"
+        CREATE TABLE IF NOT EXISTS shares (
+            id TEXT PRIMARY KEY NOT NULL,
+            user_id TEXT NOT NULL,
+            clip_id TEXT NOT NULL,
+            token_hash TEXT UNIQUE NOT NULL,
+            created_at INTEGER NOT NULL,
+            expires_at INTEGER NOT NULL,
+            revoked_at INTEGER
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_shares_user ON shares(user_id, expires_at);
+        "#,
+        postgres: r#"
+        CREATE TABLE IF NOT EXISTS shares (
+            id TEXT PRIMARY KEY NOT NULL,
+            user_id TEXT NOT NULL,
+            clip_id TEXT NOT NULL,
+            token_hash TEXT UNIQUE NOT NULL,
+            created_at BIGINT NOT NULL,
+            expires_at BIGINT NOT NULL,
+            revoked_at BIGINT
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_shares_user ON shares(user_id, expires_at);
+        "#,
+    },
+    // 剪贴板来源：own 为自己的设备复制，shared 为联系人发送（shared_by 为发送者）
+    Migration {
+        version: 15,
+        name: "add_clips_source",
+        columns: &[],
+        sql: r#"
+        ALTER TABLE clips ADD COLUMN source TEXT NOT NULL DEFAULT 'own';
+        ALTER TABLE clips ADD COLUMN shared_by TEXT;
+        "#,
+        postgres: r#"
+        ALTER TABLE clips ADD COLUMN source TEXT NOT NULL DEFAULT 'own';
+        ALTER TABLE clips ADD COLUMN shared_by TEXT;
+        "#,
+    },
+    // 账号角色：user / admin，管理员的访问令牌带有 admin 角色，可以访问管理接口
+    Migration {
+        version: 16,
+        name: "add_users_role",
+        columns: &[],
+        sql: r#"
+        ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'user';
+        "#,
+        postgres: r#"
+        ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'user';
+        "#,
+    },
+    // 最近一次修改剪贴板的设备：版本号过期的修改来自其他设备时记录为同步冲突
+    Migration {
+        version: 17,
+        name: "add_clips_modified_by",
+        columns: &[],
+        sql: r#"
+        ALTER TABLE clips ADD COLUMN modified_by TEXT;
+        "#,
+        postgres: r#"
+        ALTER TABLE clips ADD COLUMN modified_by TEXT;
+        "#,
+    },    // 分块上传：会话保存完成后写入剪贴板的字段（metadata 为 JSON），分块记录已收到的大小和校验和
+    Migration {
+        version: 18,
+        name: "create_uploads",
+        columns: &[],
+        sql: r#"
+        CREATE TABLE IF NOT EXISTS uploads (
+            id TEXT PRIMARY KEY NOT NULL,
+            user_id TEXT NOT NULL,
+            clip_id TEXT NOT NULL,
+            device_id TEXT NOT NULL,
+            content_type TEXT NOT NULL,
+            mime_type TEXT NOT NULL,
+            size INTEGER NOT NULL,
+            chunk_size INTEGER NOT NULL,
+            checksum TEXT,
+            metadata TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            expires_at INTEGER NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_uploads_user ON uploads(user_id);
+        CREATE INDEX IF NOT EXISTS idx_uploads_expires ON uploads(expires_at);
+
+        CREATE TABLE IF NOT EXISTS upload_chunks (
+            upload_id TEXT NOT NULL,
+            chunk_index INTEGER NOT NULL,
+            size INTEGER NOT NULL,
+            checksum TEXT NOT NULL,
+            PRIMARY KEY (upload_id, chunk_index)
+        );
+        "#,
+        postgres: r#"
+        CREATE TABLE IF NOT EXISTS uploads (
+            id TEXT PRIMARY KEY NOT NULL,
+            user_id TEXT NOT NULL,
+            clip_id TEXT NOT NULL,
+            device_id TEXT NOT NULL,
+            content_type TEXT NOT NULL,
+            mime_type TEXT NOT NULL,
+            size BIGINT NOT NULL,
+            chunk_size BIGINT NOT NULL,
+            checksum TEXT,
+            metadata TEXT NOT NULL,
+            created_at BIGINT NOT NULL,
+            expires_at BIGINT NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_uploads_user ON uploads(user_id);
+        CREATE INDEX IF NOT EXISTS idx_uploads_expires ON uploads(expires_at);
+
+        CREATE TABLE IF NOT EXISTS upload_chunks (
+            upload_id TEXT NOT NULL,
+            chunk_index BIGINT NOT NULL,
+            size BIGINT NOT NULL,
+            checksum TEXT NOT NULL,
+            PRIMARY KEY (upload_id, chunk_index)
+        );
+        "#,
+    },
+    // 头像的图片类型，上传时根据文件头识别
+    Migration {
+        version: 19,
+        name: "add_users_head_mime",
+        columns: &[],
+        sql: r#"
+        ALTER TABLE users ADD COLUMN head_mime TEXT;
+        "#,
+        postgres: r#"
+        ALTER TABLE users ADD COLUMN head_mime TEXT;
+        "#,
+    },
+    // 剪贴板和设备列表按 (时间, ID) 游标分页
+    Migration {
+        version: 20,
+        name: "add_keyset_pagination_indexes",
+        columns: &[],
+        sql: r#"
+        DROP INDEX IF EXISTS idx_clips_user_created;
+        CREATE INDEX IF NOT EXISTS idx_clips_user_created_id ON clips(user_id, created_at, id);
+        CREATE INDEX IF NOT EXISTS idx_devices_user_created_id ON devices(user_id, created_at, id);
+        "#,
+        postgres: r#"
+        DROP INDEX IF EXISTS idx_clips_user_created;
+        CREATE INDEX IF NOT EXISTS idx_clips_user_created_id ON clips(user_id, created_at, id);
+        CREATE INDEX IF NOT EXISTS idx_devices_user_created_id ON devices(user_id, created_at, id);
+        "#,
+    },
+    // 按设备的同步规则过滤推送和增量同步
+    Migration {
+        version: 21,
+        name: "create_device_sync_rules",
+        columns: &[],
+        sql: r#"
+        CREATE TABLE IF NOT EXISTS device_sync_rules (
+            device_id TEXT PRIMARY KEY NOT NULL,
+            user_id TEXT NOT NULL,
+            clip_types TEXT,
+            max_size INTEGER,
+            updated_at INTEGER NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_device_sync_rules_user ON device_sync_rules(user_id);
+        "#,
+        postgres: r#"
+        CREATE TABLE IF NOT EXISTS device_sync_rules (
+            device_id TEXT PRIMARY KEY NOT NULL,
+            user_id TEXT NOT NULL,
+            clip_types TEXT,
+            max_size BIGINT,
+            updated_at BIGINT NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_device_sync_rules_user ON device_sync_rules(user_id);
+        "#,
+    },
+    // 剪贴板的过期时间（毫秒时间戳），到期后由清理器删除
+    Migration {
+        version: 22,
+        name: "add_clips_expires_at",
+        columns: &[],
+        sql: r#"
+        ALTER TABLE clips ADD COLUMN expires_at INTEGER;
+        CREATE INDEX IF NOT EXISTS idx_clips_expires ON clips(expires_at);
+        "#,
+        postgres: r#"
+        ALTER TABLE clips ADD COLUMN expires_at BIGINT;
+        CREATE INDEX IF NOT EXISTS idx_clips_expires ON clips(expires_at);
+        "#,
+    },
+    // 识别出的敏感内容类别，敏感的剪贴板不建立全文索引
+    Migration {
+        version: 23,
+        name: "add_clips_sensitive",
+        columns: &[],
+        sql: r#"
+        ALTER TABLE clips ADD COLUMN sensitive TEXT;
+
+        DROP TRIGGER IF EXISTS clips_fts_insert;
+        CREATE TRIGGER clips_fts_insert AFTER INSERT ON clips
+        WHEN NEW.encrypted = 0 AND NEW.sensitive IS NULL
+        BEGIN
+            INSERT INTO clips_fts (clip_id, content, preview)
+            VALUES (NEW.id, NEW.content, NEW.preview);
+        END;
+
+        DROP TRIGGER IF EXISTS clips_fts_update;
+        CREATE TRIGGER clips_fts_update
+        AFTER UPDATE OF content, preview, encrypted, sensitive ON clips
+        BEGIN
+            DELETE FROM clips_fts WHERE clip_id = OLD.id;
+            INSERT INTO clips_fts (clip_id, content, preview)
+            SELECT NEW.id, NEW.content, NEW.preview
+            WHERE NEW.encrypted = 0 AND NEW.sensitive IS NULL;
+        END;
+        "#,
+        postgres: r#"
+        ALTER TABLE clips ADD COLUMN sensitive TEXT;
+        "#,
+    },
+    // 两步验证（TOTP）：密钥加密保存，totp_last_step 为最后使用的验证码时间步，防止重放；
+    // 恢复码只保存哈希，使用后删除
+    Migration {
+        version: 24,
+        name: "add_users_totp",
+        columns: &[],
+        sql: r#"
+        ALTER TABLE users ADD COLUMN totp_secret TEXT;
+        ALTER TABLE users ADD COLUMN totp_enabled INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE users ADD COLUMN totp_last_step INTEGER;
+
+        CREATE TABLE IF NOT EXISTS totp_recovery_codes (
+            user_id TEXT NOT NULL,
+            code_hash TEXT NOT NULL,
+            PRIMARY KEY (user_id, code_hash)
+        );
+        "#,
+        postgres: r#"
+        ALTER TABLE users ADD COLUMN totp_secret TEXT;
+        ALTER TABLE users ADD COLUMN totp_enabled BOOLEAN NOT NULL DEFAULT FALSE;
+        ALTER TABLE users ADD COLUMN totp_last_step BIGINT;
+
+        CREATE TABLE IF NOT EXISTS totp_recovery_codes (
+            user_id TEXT NOT NULL,
+            code_hash TEXT NOT NULL,
+            PRIMARY KEY (user_id, code_hash)
+        );
+        "#,
+    },
+    // 第三方登录：identities 为绑定到本地账号的外部身份（提供方 + 提供方的用户 ID），
+    // oauth_states 为进行中的登录流程，回调时校验并删除
+    Migration {
+        version: 25,
+        name: "create_identities",
+        columns: &[],
+        sql: r#"
+        CREATE TABLE IF NOT EXISTS identities (
+            provider TEXT NOT NULL,
+            subject TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            email TEXT,
+            created_at INTEGER NOT NULL,
+            PRIMARY KEY (provider, subject)
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_identities_user ON identities(user_id);
+
+        CREATE TABLE IF NOT EXISTS oauth_states (
+            state TEXT PRIMARY KEY NOT NULL,
+            provider TEXT NOT NULL,
+            user_id TEXT,
+            redirect_uri TEXT,
+            expires_at INTEGER NOT NULL
+        );
+        "#,
+        postgres: r#"
+        CREATE TABLE IF NOT EXISTS identities (
+            provider TEXT NOT NULL,
+            subject TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            email TEXT,
+            created_at BIGINT NOT NULL,
+            PRIMARY KEY (provider, subject)
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_identities_user ON identities(user_id);
+
+        CREATE TABLE IF NOT EXISTS oauth_states (
+            state TEXT PRIMARY KEY NOT NULL,
+            provider TEXT NOT NULL,
+            user_id TEXT,
+            redirect_uri TEXT,
+            expires_at BIGINT NOT NULL
+        );
+        "#,
+    },
+    // 登录会话：同一次登录轮换出的刷新令牌共用 session_id（第一个令牌的 id），
+    // 记录登录时的设备信息以及最近一次刷新时的 IP 和时间
+    Migration {
+        version: 26,
+        name: "add_refresh_tokens_session",
+        columns: &[],
+        sql: r#"
+        ALTER TABLE refresh_tokens ADD COLUMN session_id TEXT;
+        ALTER TABLE refresh_tokens ADD COLUMN device_name TEXT;
+        ALTER TABLE refresh_tokens ADD COLUMN user_agent TEXT;
+        ALTER TABLE refresh_tokens ADD COLUMN ip TEXT;
+        ALTER TABLE refresh_tokens ADD COLUMN signed_in_at INTEGER;
+        ALTER TABLE refresh_tokens ADD COLUMN last_used_at INTEGER;
+
+        UPDATE refresh_tokens
+        SET session_id = id, signed_in_at = created_at, last_used_at = created_at;
+
+        CREATE INDEX IF NOT EXISTS idx_refresh_tokens_session ON refresh_tokens(session_id);
+        "#,
+        postgres: r#"
+        ALTER TABLE refresh_tokens ADD COLUMN session_id TEXT;
+        ALTER TABLE refresh_tokens ADD COLUMN device_name TEXT;
+        ALTER TABLE refresh_tokens ADD COLUMN user_agent TEXT;
+        ALTER TABLE refresh_tokens ADD COLUMN ip TEXT;
+        ALTER TABLE refresh_tokens ADD COLUMN signed_in_at BIGINT;
+        ALTER TABLE refresh_tokens ADD COLUMN last_used_at BIGINT;
+
+        UPDATE refresh_tokens
+        SET session_id = id, signed_in_at = created_at, last_used_at = created_at;
+
+        CREATE INDEX IF NOT EXISTS idx_refresh_tokens_session ON refresh_tokens(session_id);
+        "#,
+    },
+    // 安全相关事件的审计日志，user_id 为事件涉及的用户，actor_id 为执行管理员操作的账号
+    Migration {
+        version: 27,
+        name: "create_audit_log",
+        columns: &[],
+        sql: r#"
+        CREATE TABLE IF NOT EXISTS audit_log (
+            id TEXT PRIMARY KEY,
+            event TEXT NOT NULL,
+            user_id TEXT,
+            actor_id TEXT,
+            success INTEGER NOT NULL,
+            error_code TEXT,
+            ip TEXT,
+            user_agent TEXT,
+            detail TEXT,
+            created_at INTEGER NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_audit_log_user ON audit_log(user_id, created_at);
+        CREATE INDEX IF NOT EXISTS idx_audit_log_created ON audit_log(created_at);
+        "#,
+        postgres: r#"
+        CREATE TABLE IF NOT EXISTS audit_log (
+            id TEXT PRIMARY KEY,
+            event TEXT NOT NULL,
+            user_id TEXT,
+            actor_id TEXT,
+            success BOOLEAN NOT NULL,
+            error_code TEXT,
+            ip TEXT,
+            user_agent TEXT,
+            detail TEXT,
+            created_at BIGINT NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_audit_log_user ON audit_log(user_id, created_at);
+        CREATE INDEX IF NOT EXISTS idx_audit_log_created ON audit_log(created_at);
+        "#,
+    },
+    // 回收站：删除剪贴板时只记录删除时间（毫秒时间戳），超过保留天数后由清理器彻底删除
+    //
+    // 移到回收站记录为 deleted、恢复记录为 created，彻底删除已在回收站中的剪贴板不再记录
+    Migration {
+        version: 28,
+        name: "add_clips_deleted_at",
+        columns: &[],
+        sql: r#"
+        ALTER TABLE clips ADD COLUMN deleted_at INTEGER;
+        CREATE INDEX IF NOT EXISTS idx_clips_deleted ON clips(deleted_at);
+
+        DROP TRIGGER IF EXISTS change_log_clip_delete;
+        CREATE TRIGGER change_log_clip_delete AFTER DELETE ON clips
+        WHEN OLD.deleted_at IS NULL
+        BEGIN
+            INSERT INTO change_log (user_id, clip_id, op, changed_at)
+            VALUES (
+                OLD.user_id, OLD.id, 'deleted',
+                CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)
+            );
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS change_log_clip_trash AFTER UPDATE OF deleted_at ON clips
+        WHEN (OLD.deleted_at IS NULL) != (NEW.deleted_at IS NULL)
+        BEGIN
+            INSERT INTO change_log (user_id, clip_id, op, changed_at)
+            VALUES (
+                NEW.user_id, NEW.id,
+                CASE WHEN NEW.deleted_at IS NULL THEN 'created' ELSE 'deleted' END,
+                CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)
+            );
+        END;
+        "#,
+        postgres: r#"
+        ALTER TABLE clips ADD COLUMN deleted_at BIGINT;
+        CREATE INDEX IF NOT EXISTS idx_clips_deleted ON clips(deleted_at);
+
+        CREATE OR REPLACE FUNCTION change_log_clip() RETURNS trigger AS $$
+        BEGIN
+            IF TG_OP = 'DELETE' THEN
+                IF OLD.deleted_at IS NULL THEN
+                    INSERT INTO change_log (user_id, clip_id, op, changed_at)
+                    VALUES (
+                        OLD.user_id, OLD.id, 'deleted',
+                        (extract(epoch FROM clock_timestamp()) * 1000)::BIGINT
+                    );
+                END IF;
+                RETURN OLD;
+            END IF;
+            INSERT INTO change_log (user_id, clip_id, op, changed_at)
+            VALUES (
+                NEW.user_id, NEW.id,
+                CASE TG_OP WHEN 'INSERT' THEN 'created' ELSE 'updated' END,
+                (extract(epoch FROM clock_timestamp()) * 1000)::BIGINT
+            );
+            RETURN NEW;
+        END;
+        $$ LANGUAGE plpgsql;
+
+        CREATE OR REPLACE FUNCTION change_log_clip_trash() RETURNS trigger AS $$
+        BEGIN
+            IF (OLD.deleted_at IS NULL) <> (NEW.deleted_at IS NULL) THEN
+                INSERT INTO change_log (user_id, clip_id, op, changed_at)
+                VALUES (
+                    NEW.user_id, NEW.id,
+                    CASE WHEN NEW.deleted_at IS NULL THEN 'created' ELSE 'deleted' END,
+                    (extract(epoch FROM clock_timestamp()) * 1000)::BIGINT
+                );
+            END IF;
+            RETURN NEW;
+        END;
+        $$ LANGUAGE plpgsql;
+
+        CREATE TRIGGER change_log_clip_trash AFTER UPDATE OF deleted_at ON clips
+        FOR EACH ROW EXECUTE FUNCTION change_log_clip_trash();
+        "#,
+    },
+    // Webhook：用户注册的事件接收地址，events 为订阅的事件名（JSON 数组）
+    //
+    // 每个事件为每个订阅的 Webhook 生成一条投递记录，status 为 pending / delivered / failed，
+    // next_attempt_at 为下次尝试的时间（秒），投递成功或放弃后为空
+    Migration {
+        version: 29,
+        name: "create_webhooks",
+        columns: &[],
+        sql: r#"
+        CREATE TABLE IF NOT EXISTS webhooks (
+            id TEXT PRIMARY KEY NOT NULL,
+            user_id TEXT NOT NULL,
+            url TEXT NOT NULL,
+            secret TEXT NOT NULL,
+            events TEXT NOT NULL,
+            enabled INTEGER NOT NULL DEFAULT 1,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_webhooks_user ON webhooks(user_id);
+
+        CREATE TABLE IF NOT EXISTS webhook_deliveries (
+            id TEXT PRIMARY KEY NOT NULL,
+            webhook_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            event TEXT NOT NULL,
+            payload TEXT NOT NULL,
+            status TEXT NOT NULL,
+            attempts INTEGER NOT NULL DEFAULT 0,
+            next_attempt_at INTEGER,
+            response_status INTEGER,
+            error TEXT,
+            created_at INTEGER NOT NULL,
+            delivered_at INTEGER
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_due
+            ON webhook_deliveries(status, next_attempt_at);
+        CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_webhook
+            ON webhook_deliveries(webhook_id, created_at);
+        "#,
+        postgres: r#"
+        CREATE TABLE IF NOT EXISTS webhooks (
+            id TEXT PRIMARY KEY NOT NULL,
+            user_id TEXT NOT NULL,
+            url TEXT NOT NULL,
+            secret TEXT NOT NULL,
+            events TEXT NOT NULL,
+            enabled BOOLEAN NOT NULL DEFAULT TRUE,
+            created_at BIGINT NOT NULL,
+            updated_at BIGINT NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_webhooks_user ON webhooks(user_id);
+
+        CREATE TABLE IF NOT EXISTS webhook_deliveries (
+            id TEXT PRIMARY KEY NOT NULL,
+            webhook_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            event TEXT NOT NULL,
+            payload TEXT NOT NULL,
+            status TEXT NOT NULL,
+            attempts BIGINT NOT NULL DEFAULT 0,
+            next_attempt_at BIGINT,
+            response_status BIGINT,
+            error TEXT,
+            created_at BIGINT NOT NULL,
+            delivered_at BIGINT
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_due
+            ON webhook_deliveries(status, next_attempt_at);
+        CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_webhook
+            ON webhook_deliveries(webhook_id, created_at);
+        "#,
+    },
+    // 个人存储配额：quota_bytes 为空时使用配置的默认配额；
+    // head_bytes 为头像文件的字节数，上传头像时记录，计入存储占用
+    Migration {
+        version: 30,
+        name: "add_users_quota",
+        columns: &[],
+        sql: r#"
+        ALTER TABLE users ADD COLUMN quota_bytes INTEGER;
+        ALTER TABLE users ADD COLUMN head_bytes INTEGER NOT NULL DEFAULT 0;
+        "#,
+        postgres: r#"
+        ALTER TABLE users ADD COLUMN quota_bytes BIGINT;
+        ALTER TABLE users ADD COLUMN head_bytes BIGINT NOT NULL DEFAULT 0;
+        "#,
+    },
+    // 后台任务队列：kind 为任务类型，payload 为任务参数（JSON），priority 越大越先执行；
+    //
+    // status 为 pending / running / done / dead，run_at 为下次执行的时间（秒），执行中的任务为租约到期时间，
+    // 完成后为空；重试次数用完的任务进入死信（dead），由管理员重试或删除
+    Migration {
+        version: 31,
+        name: "create_jobs",
+        columns: &[],
+        sql: r#"
+        CREATE TABLE IF NOT EXISTS jobs (
+            id TEXT PRIMARY KEY NOT NULL,
+            kind TEXT NOT NULL,
+            payload TEXT NOT NULL,
+            priority INTEGER NOT NULL DEFAULT 0,
+            status TEXT NOT NULL,
+            attempts INTEGER NOT NULL DEFAULT 0,
+            max_attempts INTEGER NOT NULL,
+            run_at INTEGER,
+            error TEXT,
+            created_at INTEGER NOT NULL,
+            finished_at INTEGER
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_jobs_due ON jobs(status, run_at);
+        "#,
+        postgres: r#"
+        CREATE TABLE IF NOT EXISTS jobs (
+            id TEXT PRIMARY KEY NOT NULL,
+            kind TEXT NOT NULL,
+            payload TEXT NOT NULL,
+            priority BIGINT NOT NULL DEFAULT 0,
+            status TEXT NOT NULL,
+            attempts BIGINT NOT NULL DEFAULT 0,
+            max_attempts BIGINT NOT NULL,
+            run_at BIGINT,
+            error TEXT,
+            created_at BIGINT NOT NULL,
+            finished_at BIGINT
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_jobs_due ON jobs(status, run_at);
+        "#,
+    },
+    // 剪贴板的历史版本：内容或标签修改前保存修改前的状态，revision 为当时的版本号，
+    // tags 为标签名（JSON 数组），modified_by 为做出该版本的设备，created_at 为被替换的时间（秒）
+    Migration {
+        version: 32,
+        name: "create_clip_revisions",
+        columns: &[],
+        sql: r#"
+        CREATE TABLE IF NOT EXISTS clip_revisions (
+            id TEXT PRIMARY KEY NOT NULL,
+            clip_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            revision INTEGER NOT NULL,
+            content TEXT NOT NULL,
+            preview TEXT NOT NULL,
+            size INTEGER NOT NULL,
+            tags TEXT NOT NULL,
+            modified_by TEXT,
+            created_at INTEGER NOT NULL,
+            UNIQUE (clip_id, revision)
+        );
+        "#,
+        postgres: r#"
+        CREATE TABLE IF NOT EXISTS clip_revisions (
+            id TEXT PRIMARY KEY NOT NULL,
+            clip_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            revision BIGINT NOT NULL,
+            content TEXT NOT NULL,
+            preview TEXT NOT NULL,
+            size BIGINT NOT NULL,
+            tags TEXT NOT NULL,
+            modified_by TEXT,
+            created_at BIGINT NOT NULL,
+            UNIQUE (clip_id, revision)
+        );
+        "#,
+    },
+    // 绑定第三方账号需要客户端确认：回调时把外部身份保存为待确认的流程，
+    // subject 不为空的 oauth_states 为待确认的绑定，state 为确认码
+    Migration {
+        version: 33,
+        name: "add_oauth_states_identity",
+        columns: &[],
+        sql: r#"
+        ALTER TABLE oauth_states ADD COLUMN subject TEXT;
+        ALTER TABLE oauth_states ADD COLUMN email TEXT;
+        "#,
+        postgres: r#"
+        ALTER TABLE oauth_states ADD COLUMN subject TEXT;
+        ALTER TABLE oauth_states ADD COLUMN email TEXT;
+        "#,
+    },
+];
+
+// 执行尚未执行的迁移，每个迁移在单独的事务中执行，返回当前的数据库版本
+//
+// 按版本号判断是否已执行（而不是只执行大于最大版本号的迁移），以便补上版本号为 0 的基础表
+pub async fn run_migrations(pool: &DbPool) -> Result<i64, sqlx::Error> {
+    pool.execute(CREATE_SCHEMA_MIGRATIONS_TABLE_SQL).await?;
+    let rows = query("SELECT version FROM schema_migrations")
+        .fetch_all(pool)
+        .await?;
+    let applied = rows
+        .iter()
+        .map(|row| row.try_get("version"))
+        .collect::<Result<HashSet<i64>, _>>()?;
+
+    for migration in MIGRATIONS.iter().filter(|m| !applied.contains(&m.version)) {
+        let mut tx = pool.begin().await?;
+        let sql = match backend() {
+            Backend::Sqlite => {
+                for column in migration.columns {
+                    ensure_column(column, &mut tx).await?;
+                }
+                migration.sql
+            }
+            Backend::Postgres => migration.postgres,
+        };
+        // 不带参数直接执行，一次可以执行多条语句
+        if !sql.trim().is_empty() {
+            (&mut *tx).execute(sql).await?;
+        }
+        query("INSERT INTO schema_migrations (version, name, applied_at) VALUES ($1, $2, $3)")
+            .bind(migration.version)
+            .bind(migration.name)
+            .bind(chrono::Utc::now().timestamp())
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        info!("数据库迁移 {} ({}) 已执行", migration.version, migration.name);
+    }
+
+    let row = query("SELECT COALESCE(MAX(version), 0) AS version FROM schema_migrations")
+        .fetch_one(pool)
+        .await?;
+    row.try_get("version")
+}
+
+// 表已存在但缺少该列时补上
+async fn ensure_column(
+    column: &Column,
+    tx: &mut Transaction<'_, Db>,
+) -> Result<(), sqlx::Error> {
+    let table_exists = query("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = $1")
+        .bind(column.table)
+        .fetch_optional(&mut *tx)
+        .await?
+        .is_some();
+    if !table_exists {
+        return Ok(());
+    }
+    let column_exists = query("SELECT 1 FROM pragma_table_info($1) WHERE name = $2")
+        .bind(column.table)
+        .bind(column.name)
+        .fetch_optional(&mut *tx)
+        .await?
+        .is_some();
+    if !column_exists {
+        sqlx::query(&format!(
+            "ALTER TABLE {} ADD COLUMN {} {}",
+            column.table, column.name, column.definition
+        ))
+        .execute(&mut *tx)
+        .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::any::AnyPoolOptions;
+
+    async fn memory_pool() -> DbPool {
+        // 内存数据库按连接隔离，只使用一个连接
+        AnyPoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap()
+    }
+
+    async fn applied_count(pool: &DbPool) -> i64 {
+        query("SELECT COUNT(*) AS n FROM schema_migrations")
+            .fetch_one(pool)
+            .await
+            .unwrap()
+            .get("n")
+    }
+
+    #[test]
+    fn migrations_are_ordered() {
+        assert!(MIGRATIONS.windows(2).all(|w| w[0].version < w[1].version));
+    }
+
+    #[tokio::test]
+    async fn run_migrations_is_idempotent() {
+        let pool = memory_pool().await;
+        let latest = MIGRATIONS.last().unwrap().version;
+
+        assert_eq!(run_migrations(&pool).await.unwrap(), latest);
+        assert_eq!(applied_count(&pool).await, MIGRATIONS.len() as i64);
+
+        assert_eq!(run_migrations(&pool).await.unwrap(), latest);
+        assert_eq!(applied_count(&pool).await, MIGRATIONS.len() as i64);
+    }
+
+    #[tokio::test]
+    async fn run_migrations_creates_clip_version() {
+        let pool = memory_pool().await;
+        run_migrations(&pool).await.unwrap();
+        let row = query("SELECT 1 FROM pragma_table_info('clips') WHERE name = 'version'")
+            .fetch_optional(&pool)
+            .await
+            .unwrap();
+        assert!(row.is_some());
+    }
+}
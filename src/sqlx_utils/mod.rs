@@ -1,3 +1,11 @@
-pub(crate) mod db;
+pub(crate) mod backend;
+pub mod db;
+pub(crate) mod migrations;
 
-pub mod models;
\ No newline at end of file
+pub mod models;
+
+/// 数据库驱动，SQLite 或 PostgreSQL 由 DATABASE_URL 在运行时决定
+pub type Db = sqlx::Any;
+
+/// 数据库连接池
+pub type DbPool = sqlx::AnyPool;
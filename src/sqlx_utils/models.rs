@@ -1,6 +1,12 @@
-use actix_web::web::Json;
+use actix_web::body::BoxBody;
+use actix_web::error::{InternalError, JsonPayloadError};
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse, Responder};
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD as BASE64, URL_SAFE_NO_PAD};
 use serde::de::{self};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use utoipa::ToSchema;
 
 /// 响应数据类型枚举
 #[derive(Debug)]
@@ -34,7 +40,7 @@ impl Serialize for ResponseData {
             ResponseData::Text(s) => serializer.serialize_str(s),
             ResponseData::Binary(data) => {
                 // 将二进制数据转换为Base64字符串
-                let base64_string = base64::encode(data);
+                let base64_string = BASE64.encode(data);
                 serializer.serialize_str(&base64_string)
             }
             ResponseData::Json(v) => v.serialize(serializer),
@@ -74,7 +80,7 @@ impl<'de> Deserialize<'de> for ResponseData {
                     && s.chars()
                         .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '/' || c == '=')
                 {
-                    match base64::decode(&s) {
+                    match BASE64.decode(&s) {
                         Ok(data) => Ok(ResponseData::Binary(data)),
                         Err(_) => Ok(ResponseData::Text(s)),
                     }
@@ -96,21 +102,365 @@ impl<'de> Deserialize<'de> for ResponseData {
     }
 }
 
+/// 机器可读的错误码，客户端据此映射本地化文案
+///
+/// 序列化为 `SCREAMING_SNAKE_CASE`，已发布的错误码不可修改含义
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    InternalError,
+    InvalidRequest,
+    AuthMissing,
+    AuthInvalid,
+    AuthExpired,
+    Forbidden,
+    TermsNotAccepted,
+    InvalidCredentials,
+    RegistrationClosed,
+    DuplicateEmail,
+    UserNotFound,
+    NotFound,
+    AlreadyExists,
+    Blocked,
+    PolicyViolation,
+    RenameCooldown,
+    LinkExpired,
+    MailDeliveryFailed,
+    QuotaExceeded,
+    RateLimited,
+    IdempotencyKeyReused,
+    RequestInProgress,
+    PreconditionRequired,
+    VersionConflict,
+    EditConflict,
+    AccountDisabled,
+    EmailNotVerified,
+    TwoFactorRequired,
+    TwoFactorInvalid,
+    LoginLocked,
+    PayloadTooLarge,
+}
+
+impl ErrorCode {
+    /// 全部错误码，用于错误码目录接口
+    pub const ALL: &'static [ErrorCode] = &[
+        ErrorCode::InternalError,
+        ErrorCode::InvalidRequest,
+        ErrorCode::AuthMissing,
+        ErrorCode::AuthInvalid,
+        ErrorCode::AuthExpired,
+        ErrorCode::Forbidden,
+        ErrorCode::TermsNotAccepted,
+        ErrorCode::InvalidCredentials,
+        ErrorCode::RegistrationClosed,
+        ErrorCode::DuplicateEmail,
+        ErrorCode::UserNotFound,
+        ErrorCode::NotFound,
+        ErrorCode::AlreadyExists,
+        ErrorCode::Blocked,
+        ErrorCode::PolicyViolation,
+        ErrorCode::RenameCooldown,
+        ErrorCode::LinkExpired,
+        ErrorCode::MailDeliveryFailed,
+        ErrorCode::QuotaExceeded,
+        ErrorCode::RateLimited,
+        ErrorCode::IdempotencyKeyReused,
+        ErrorCode::RequestInProgress,
+        ErrorCode::PreconditionRequired,
+        ErrorCode::VersionConflict,
+        ErrorCode::EditConflict,
+        ErrorCode::AccountDisabled,
+        ErrorCode::EmailNotVerified,
+        ErrorCode::TwoFactorRequired,
+        ErrorCode::TwoFactorInvalid,
+        ErrorCode::LoginLocked,
+        ErrorCode::PayloadTooLarge,
+    ];
+
+    /// 错误码说明
+    pub fn description(&self) -> &'static str {
+        match self {
+            ErrorCode::InternalError => "服务器内部错误",
+            ErrorCode::InvalidRequest => "请求参数无效",
+            ErrorCode::AuthMissing => "缺少身份凭证",
+            ErrorCode::AuthInvalid => "身份凭证无效",
+            ErrorCode::AuthExpired => "身份凭证已过期，需要刷新令牌",
+            ErrorCode::Forbidden => "没有权限执行该操作",
+            ErrorCode::TermsNotAccepted => "需要接受最新的服务条款",
+            ErrorCode::InvalidCredentials => "用户名或密码错误",
+            ErrorCode::RegistrationClosed => "服务器未开放注册",
+            ErrorCode::DuplicateEmail => "邮箱已被使用",
+            ErrorCode::UserNotFound => "用户不存在",
+            ErrorCode::NotFound => "资源不存在",
+            ErrorCode::AlreadyExists => "资源已存在",
+            ErrorCode::Blocked => "对方拒绝了该操作",
+            ErrorCode::PolicyViolation => "内容不符合服务器策略",
+            ErrorCode::RenameCooldown => "操作过于频繁，冷却时间未到",
+            ErrorCode::LinkExpired => "链接无效或已过期",
+            ErrorCode::MailDeliveryFailed => "邮件发送失败",
+            ErrorCode::QuotaExceeded => "超出存储配额",
+            ErrorCode::RateLimited => "请求过于频繁",
+            ErrorCode::IdempotencyKeyReused => "幂等键已用于其他请求",
+            ErrorCode::RequestInProgress => "相同幂等键的请求正在处理中",
+            ErrorCode::PreconditionRequired => "缺少 If-Match 请求头",
+            ErrorCode::VersionConflict => "资源已被其他设备修改，请刷新后重试",
+            ErrorCode::EditConflict => "与其他设备的修改冲突，需要先解决冲突",
+            ErrorCode::AccountDisabled => "账号已被停用",
+            ErrorCode::EmailNotVerified => "邮箱尚未验证",
+            ErrorCode::TwoFactorRequired => "需要两步验证码",
+            ErrorCode::TwoFactorInvalid => "两步验证码错误",
+            ErrorCode::LoginLocked => "登录失败次数过多，暂时锁定",
+            ErrorCode::PayloadTooLarge => "请求体过大",
+        }
+    }
+
+    /// 错误码对应的 HTTP 状态码
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ErrorCode::InternalError => StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorCode::InvalidRequest => StatusCode::BAD_REQUEST,
+            ErrorCode::AuthMissing
+            | ErrorCode::AuthInvalid
+            | ErrorCode::AuthExpired
+            | ErrorCode::InvalidCredentials
+            | ErrorCode::TwoFactorRequired
+            | ErrorCode::TwoFactorInvalid => StatusCode::UNAUTHORIZED,
+            ErrorCode::TermsNotAccepted => StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS,
+            ErrorCode::Forbidden
+            | ErrorCode::RegistrationClosed
+            | ErrorCode::Blocked
+            | ErrorCode::AccountDisabled
+            | ErrorCode::EmailNotVerified => StatusCode::FORBIDDEN,
+            ErrorCode::UserNotFound | ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::DuplicateEmail
+            | ErrorCode::AlreadyExists
+            | ErrorCode::RequestInProgress
+            | ErrorCode::EditConflict => StatusCode::CONFLICT,
+            ErrorCode::LinkExpired => StatusCode::GONE,
+            ErrorCode::PreconditionRequired => StatusCode::PRECONDITION_REQUIRED,
+            ErrorCode::VersionConflict => StatusCode::PRECONDITION_FAILED,
+            ErrorCode::PolicyViolation | ErrorCode::IdempotencyKeyReused => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            ErrorCode::RenameCooldown | ErrorCode::RateLimited | ErrorCode::LoginLocked => {
+                StatusCode::TOO_MANY_REQUESTS
+            }
+            ErrorCode::MailDeliveryFailed => StatusCode::BAD_GATEWAY,
+            ErrorCode::QuotaExceeded => StatusCode::INSUFFICIENT_STORAGE,
+            ErrorCode::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+        }
+    }
+}
+
 /// 主要响应结构
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ApiResponse {
     pub message: String,
     pub data: ResponseData,
+    /// 错误码，成功响应不包含该字段
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
     pub timestamp: i64,
 }
 
+/// 与 ApiResponse 结构相同，只用于在 OpenAPI 文档中描述各接口 data 的类型
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct ApiEnvelope<T> {
+    pub message: String,
+    pub data: T,
+    /// 错误码，成功响应不包含该字段
+    pub code: Option<ErrorCode>,
+    pub timestamp: i64,
+}
+
+/// 失败响应，只用于 OpenAPI 文档，HTTP 状态码由 code 决定（见 `/error_codes`）
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct ApiErrorEnvelope {
+    pub message: String,
+    /// 部分错误附带数据（例如编辑冲突时的最新版本），其余为 null
+    pub data: Option<serde_json::Value>,
+    pub code: ErrorCode,
+    pub timestamp: i64,
+}
+
+/// 分页列表，next_cursor 为空表示已经是最后一页
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// 符合条件的总数（不受游标和 limit 影响）
+    pub total: i64,
+    /// 下一页的游标，原样作为 cursor 参数传回
+    pub next_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    /// 由多查询一条的结果构造一页
+    pub fn new(mut items: Vec<T>, total: i64, limit: usize, cursor: impl Fn(&T) -> Cursor) -> Self {
+        let next_cursor = truncate_page(&mut items, limit, cursor);
+        Page {
+            items,
+            total,
+            next_cursor,
+        }
+    }
+}
+
+/// 多查询一条的结果超过 limit 时截断，并以最后一项生成下一页的游标
+pub fn truncate_page<T>(
+    items: &mut Vec<T>,
+    limit: usize,
+    cursor: impl Fn(&T) -> Cursor,
+) -> Option<String> {
+    if items.len() <= limit {
+        return None;
+    }
+    items.truncate(limit);
+    items.last().map(|item| cursor(item).encode())
+}
+
+/// 分页游标（keyset）：上一页最后一项的排序键（时间和 ID），下一页从其后开始，无需 OFFSET 扫描
+///
+/// 对客户端不透明，编码为 URL 安全的 Base64
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Cursor {
+    pub at: i64,
+    pub id: String,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(format!("{}:{}", self.at, self.id))
+    }
+}
+
+impl TryFrom<String> for Cursor {
+    type Error = &'static str;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let decoded = URL_SAFE_NO_PAD
+            .decode(value)
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok());
+        let (at, id) = decoded
+            .as_deref()
+            .and_then(|decoded| decoded.split_once(':'))
+            .ok_or("无效的分页游标")?;
+        Ok(Cursor {
+            at: at.parse().map_err(|_| "无效的分页游标")?,
+            id: id.to_string(),
+        })
+    }
+}
+
+impl From<Cursor> for String {
+    fn from(cursor: Cursor) -> Self {
+        cursor.encode()
+    }
+}
+
 impl ApiResponse {
-    pub fn new(message: &str, data: ResponseData) -> Json<ApiResponse> {
-        let api_response= ApiResponse {
+    pub fn new(message: &str, data: ResponseData) -> ApiResponse {
+        ApiResponse {
+            message: message.to_string(),
+            data,
+            code: None,
+            timestamp: chrono::Utc::now().timestamp(),
+        }
+    }
+
+    // 带错误码的失败响应
+    pub fn error(code: ErrorCode, message: &str) -> ApiResponse {
+        Self::error_with_data(code, message, ResponseData::Null)
+    }
+
+    // 带错误码和附加数据的失败响应
+    pub fn error_with_data(code: ErrorCode, message: &str, data: ResponseData) -> ApiResponse {
+        ApiResponse {
             message: message.to_string(),
             data,
+            code: Some(code),
             timestamp: chrono::Utc::now().timestamp(),
+        }
+    }
+
+    /// 响应的 HTTP 状态码：成功为 200，失败时由错误码决定
+    pub fn status(&self) -> StatusCode {
+        self.code.map_or(StatusCode::OK, |code| code.status())
+    }
+
+    /// 构造带错误码的 actix 错误，用于提取器等无法直接返回响应的场景
+    pub fn into_error(code: ErrorCode, message: &str) -> actix_web::Error {
+        let body = Self::error(code, message);
+        InternalError::from_response(
+            message.to_string(),
+            HttpResponse::build(code.status()).json(body),
+        )
+        .into()
+    }
+}
+
+/// 提取器解析失败（请求体、查询参数、路径参数）时返回的错误
+pub fn invalid_request(error: impl std::fmt::Display) -> actix_web::Error {
+    ApiResponse::into_error(ErrorCode::InvalidRequest, &error.to_string())
+}
+
+/// JSON 请求体解析失败时返回的错误，超出 `server.max_body_bytes` 时返回 PAYLOAD_TOO_LARGE
+pub fn invalid_json(error: JsonPayloadError) -> actix_web::Error {
+    match error {
+        JsonPayloadError::Overflow { limit }
+        | JsonPayloadError::OverflowKnownLength { limit, .. } => ApiResponse::into_error(
+            ErrorCode::PayloadTooLarge,
+            &format!("请求体超过 {} 字节的限制", limit),
+        ),
+        error => invalid_request(error),
+    }
+}
+
+impl Responder for ApiResponse {
+    type Body = BoxBody;
+
+    fn respond_to(self, _req: &HttpRequest) -> HttpResponse<Self::Body> {
+        HttpResponse::build(self.status()).json(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_codes_map_to_error_statuses() {
+        for code in ErrorCode::ALL {
+            let status = ApiResponse::error(*code, "").status();
+            assert!(
+                status.is_client_error() || status.is_server_error(),
+                "{:?}",
+                code
+            );
+        }
+        assert_eq!(ErrorCode::AuthExpired.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            ApiResponse::new("", ResponseData::Null).status(),
+            StatusCode::OK
+        );
+    }
+
+    #[test]
+    fn page_truncates_and_encodes_cursor() {
+        let cursor = |item: &i64| Cursor {
+            at: *item,
+            id: format!("id-{}", item),
         };
-        Json(api_response)
+        let page = Page::new(vec![3, 2, 1], 10, 2, cursor);
+        assert_eq!(page.items, vec![3, 2]);
+        let next = Cursor::try_from(page.next_cursor.unwrap()).unwrap();
+        assert_eq!(next, cursor(&2));
+
+        let last = Page::new(vec![1], 1, 2, cursor);
+        assert!(last.next_cursor.is_none());
+        assert!(Cursor::try_from("not a cursor".to_string()).is_err());
     }
 }
@@ -0,0 +1,135 @@
+use actix_web::{Responder, get, web};
+use chrono::DateTime;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::{
+    models::ClipType,
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiResponse, ErrorCode, ResponseData},
+    },
+    user_api::auth::BearerToken,
+};
+
+pub fn stats_api() -> actix_web::Scope {
+    web::scope("/stats").service(get_stats)
+}
+
+/// 统计结果的缓存时间，期间重复请求直接返回缓存
+const STATS_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// 按天统计的天数（包括今天）
+const DAILY_STATS_DAYS: i64 = 30;
+
+/// 来源应用和标签排行的数量
+const TOP_LIMIT: i64 = 10;
+
+const MILLIS_PER_DAY: i64 = 24 * 3600 * 1000;
+
+/// 名称及其剪贴板数量（来源应用、标签）
+#[derive(Debug, Clone, Serialize)]
+pub struct NamedCount {
+    pub name: String,
+    pub count: i64,
+}
+
+/// 某一天（UTC）创建的剪贴板数量
+#[derive(Debug, Clone, Serialize)]
+pub struct DailyCount {
+    /// `YYYY-MM-DD`
+    pub date: String,
+    pub count: i64,
+}
+
+/// 剪贴板使用统计
+#[derive(Debug, Clone, Serialize)]
+pub struct ClipStats {
+    pub total_clips: i64,
+    /// 剪贴板内容的总字节数
+    pub total_bytes: i64,
+    /// 各内容类型的数量，没有剪贴板的类型为 0
+    pub by_type: BTreeMap<&'static str, i64>,
+    /// 最近 30 天每天的数量，按日期升序，没有剪贴板的日期为 0
+    pub daily: Vec<DailyCount>,
+    pub top_source_apps: Vec<NamedCount>,
+    pub top_tags: Vec<NamedCount>,
+    /// 统计时间（Unix 秒），缓存期间不变
+    pub generated_at: i64,
+}
+
+// 按用户缓存的统计结果
+fn stats_cache() -> &'static Mutex<HashMap<String, (Instant, ClipStats)>> {
+    static CACHE: OnceLock<Mutex<HashMap<String, (Instant, ClipStats)>>> = OnceLock::new();
+    CACHE.get_or_init(Default::default)
+}
+
+// 用聚合查询统计用户的剪贴板
+async fn compute_stats(user_id: &str, pool: &DbPool) -> Result<ClipStats, sqlx::Error> {
+    let now = chrono::Utc::now();
+    let today = now.timestamp_millis().div_euclid(MILLIS_PER_DAY);
+    let first_day = today - (DAILY_STATS_DAYS - 1);
+    let (totals, type_counts, daily_counts, top_source_apps, top_tags) = tokio::try_join!(
+        db::get_clip_totals(user_id, pool),
+        db::get_clip_type_counts(user_id, pool),
+        db::get_daily_clip_counts(user_id, first_day * MILLIS_PER_DAY, pool),
+        db::get_top_source_apps(user_id, TOP_LIMIT, pool),
+        db::get_top_tags(user_id, TOP_LIMIT, pool),
+    )?;
+    let (total_clips, total_bytes) = totals;
+
+    let mut by_type: BTreeMap<&'static str, i64> = ClipType::ALL
+        .iter()
+        .map(|clip_type| (clip_type.as_str(), 0))
+        .collect();
+    for (clip_type, count) in type_counts {
+        *by_type.entry(clip_type.as_str()).or_default() += count;
+    }
+    let daily_counts: HashMap<i64, i64> = daily_counts.into_iter().collect();
+    let daily = (first_day..=today)
+        .map(|day| DailyCount {
+            date: DateTime::from_timestamp(day * 24 * 3600, 0)
+                .map(|date| date.date_naive().to_string())
+                .unwrap_or_default(),
+            count: daily_counts.get(&day).copied().unwrap_or(0),
+        })
+        .collect();
+
+    Ok(ClipStats {
+        total_clips,
+        total_bytes,
+        by_type,
+        daily,
+        top_source_apps,
+        top_tags,
+        generated_at: now.timestamp(),
+    })
+}
+
+// 获取剪贴板使用统计（用于客户端的统计面板），结果缓存一分钟
+#[get("")]
+async fn get_stats(pool: web::Data<DbPool>, bearer_token: BearerToken) -> impl Responder {
+    let user_id = &bearer_token.user_id;
+    let cached = stats_cache()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(user_id)
+        .filter(|(computed_at, _)| computed_at.elapsed() < STATS_CACHE_TTL)
+        .map(|(_, stats)| stats.clone());
+    let stats = match cached {
+        Some(stats) => stats,
+        None => match compute_stats(user_id, &pool).await {
+            Ok(stats) => {
+                let mut cache = stats_cache().lock().unwrap_or_else(|e| e.into_inner());
+                cache.retain(|_, (computed_at, _)| computed_at.elapsed() < STATS_CACHE_TTL);
+                cache.insert(user_id.clone(), (Instant::now(), stats.clone()));
+                stats
+            }
+            Err(_) => return ApiResponse::error(ErrorCode::InternalError, "获取统计失败"),
+        },
+    };
+    ApiResponse::new("获取统计成功", ResponseData::Json(json!(stats)))
+}
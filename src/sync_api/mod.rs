@@ -0,0 +1,280 @@
+use actix_web::{Responder, get, post, web};
+use chrono::{DateTime, SubsecRound, Utc};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    clip_api::{PREVIEW_CHARS, normalize_tags},
+    models::{ClipItem, SyncStatus},
+    spatial_api::models::{AppState, PushEvent},
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiResponse, ErrorCode, ResponseData},
+    },
+    user_api::{auth::BearerToken, quota::check_quota},
+};
+
+pub fn sync_api() -> actix_web::Scope {
+    web::scope("/sync")
+        .service(list_conflicts)
+        .service(report_conflict)
+        .service(resolve_conflict)
+}
+
+/// 同步冲突：某个剪贴板项目在设备上的版本与服务器上的版本不一致（剪贴板标记为 `SyncStatus::Conflict`）
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SyncConflict {
+    pub id: String,
+    /// 冲突的剪贴板项目 ID
+    pub item_id: String,
+    /// 上报冲突的设备上的版本
+    pub local: Value,
+    /// 服务端（其他设备）的版本
+    pub remote: Value,
+    pub created_at: i64,
+    /// 最终采用的内容，未解决时为空
+    pub resolution: Option<Value>,
+    pub resolved_at: Option<i64>,
+}
+
+// 获取未解决的冲突
+#[get("/conflicts")]
+async fn list_conflicts(pool: web::Data<DbPool>, bearer_token: BearerToken) -> impl Responder {
+    match db::get_open_sync_conflicts(&bearer_token.user_id, &pool).await {
+        Ok(conflicts) => ApiResponse::new("获取冲突成功", ResponseData::Json(json!(conflicts))),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取冲突失败"),
+    }
+}
+
+// 上报冲突：local 为设备上的版本（至少包含 version 或 accessed_at），
+// 与服务器上保存的剪贴板不一致时记录冲突，remote 为服务器上的版本
+//
+// 同一项目已有未解决的冲突时替换为最新的两个版本
+#[derive(Deserialize)]
+pub struct ReportConflict {
+    pub item_id: Uuid,
+    pub local: Value,
+}
+
+// 设备上的版本中用于与服务器比较的字段
+#[derive(Deserialize)]
+struct LocalVersion {
+    version: Option<i64>,
+    accessed_at: Option<DateTime<Utc>>,
+}
+
+#[post("/conflicts")]
+async fn report_conflict(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    report: web::Json<ReportConflict>,
+) -> impl Responder {
+    let report = report.into_inner();
+    let Ok(local) = serde_json::from_value::<LocalVersion>(report.local.clone()) else {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "local 格式不正确");
+    };
+    if local.version.is_none() && local.accessed_at.is_none() {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "local 需要包含 version 或 accessed_at");
+    }
+    let clip = match db::get_clip(&bearer_token.user_id, &report.item_id, &pool).await {
+        Ok(Some(clip)) => clip,
+        Ok(None) => return ApiResponse::error(ErrorCode::NotFound, "剪贴板不存在"),
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "冲突上报失败"),
+    };
+    let conflicting = local.version.is_some_and(|version| version != clip.version)
+        || local
+            .accessed_at
+            .is_some_and(|accessed_at| accessed_at != clip.accessed_at);
+    if !conflicting {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "与服务器上的版本一致，没有冲突");
+    }
+
+    let conflict = SyncConflict {
+        id: uuid::Uuid::new_v4().to_string(),
+        item_id: clip.id.to_string(),
+        local: report.local,
+        remote: json!(clip),
+        created_at: chrono::Utc::now().timestamp(),
+        resolution: None,
+        resolved_at: None,
+    };
+    info!(
+        "用户 {} 上报项目 {} 的同步冲突",
+        bearer_token.user_id, conflict.item_id
+    );
+
+    match record_conflict(&bearer_token.user_id, &conflict, &pool, &app_state).await {
+        Ok(_) => ApiResponse::new("冲突上报成功", ResponseData::Json(json!(conflict))),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "冲突上报失败"),
+    }
+}
+
+// 保存冲突并通知用户的其他设备，任意一台设备都可以处理
+pub(crate) async fn record_conflict(
+    user_id: &str,
+    conflict: &SyncConflict,
+    pool: &DbPool,
+    app_state: &AppState,
+) -> Result<(), sqlx::Error> {
+    db::insert_sync_conflict(user_id, conflict, pool).await?;
+    app_state.room_manager.do_send(PushEvent {
+        user_id: user_id.to_string(),
+        event: "sync_conflict".to_string(),
+        payload: json!(conflict),
+    });
+    Ok(())
+}
+
+// 解决冲突：choice 选择 local / remote 其中一个版本，或通过 merged 提供合并后的内容
+//
+// 采用的版本中的 content、preview 和 tags 写回剪贴板，未提供的字段保持不变
+#[derive(Deserialize, ToSchema)]
+pub struct ResolveConflict {
+    pub choice: Option<String>,
+    pub merged: Option<Value>,
+}
+
+// 写回剪贴板的字段
+#[derive(Deserialize)]
+struct Resolution {
+    content: Option<String>,
+    preview: Option<String>,
+    tags: Option<Vec<String>>,
+}
+
+#[post("/conflicts/{id}/resolve")]
+async fn resolve_conflict(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    id: web::Path<String>,
+    resolve: web::Json<ResolveConflict>,
+) -> impl Responder {
+    let user_id = &bearer_token.user_id;
+    let conflict = match db::get_sync_conflict(&id, user_id, &pool).await {
+        Ok(Some(conflict)) if conflict.resolved_at.is_none() => conflict,
+        Ok(_) => return ApiResponse::error(ErrorCode::NotFound, "冲突不存在或已解决"),
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "解决冲突失败"),
+    };
+    resolve_open_conflict(user_id, conflict, resolve.into_inner(), &pool, &app_state).await
+}
+
+// 按请求解决未解决的冲突，/sync/conflicts/{id}/resolve 和 /clips/{id}/resolve 共用
+pub(crate) async fn resolve_open_conflict(
+    user_id: &str,
+    conflict: SyncConflict,
+    resolve: ResolveConflict,
+    pool: &DbPool,
+    app_state: &AppState,
+) -> ApiResponse {
+    let resolution = match (resolve.choice.as_deref(), resolve.merged) {
+        (Some("local"), None) => conflict.local,
+        (Some("remote"), None) => conflict.remote,
+        (None, Some(merged)) => merged,
+        _ => {
+            return ApiResponse::error(
+                ErrorCode::InvalidRequest,
+                "需要提供 choice（local / remote）或 merged 其中之一",
+            );
+        }
+    };
+    let Ok(fields) = serde_json::from_value::<Resolution>(resolution.clone()) else {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "采用的版本格式不正确");
+    };
+
+    // 剪贴板已被删除时只记录解决结果
+    let clip = match Uuid::parse_str(&conflict.item_id) {
+        Ok(item_id) => match db::get_clip(user_id, &item_id, pool).await {
+            Ok(clip) => clip,
+            Err(_) => return ApiResponse::error(ErrorCode::InternalError, "解决冲突失败"),
+        },
+        Err(_) => None,
+    };
+    let clip = match clip {
+        Some(clip) => match apply_resolution(user_id, clip, fields, pool, app_state).await {
+            Ok(clip) => Some(clip),
+            Err(response) => return response,
+        },
+        None => None,
+    };
+
+    match db::resolve_sync_conflict(&conflict.id, user_id, &resolution, clip.as_ref(), pool).await {
+        Ok(Some((resolved_at, version))) => {
+            let clip = clip.zip(version).map(|(mut clip, version)| {
+                clip.version = version;
+                clip
+            });
+            let payload = json!({
+                "id": conflict.id,
+                "item_id": conflict.item_id,
+                "resolution": resolution,
+                "resolved_at": resolved_at,
+                "clip": clip,
+            });
+            // 所有设备都以该结果为准
+            app_state.room_manager.do_send(PushEvent {
+                user_id: user_id.to_string(),
+                event: "sync_conflict_resolved".to_string(),
+                payload: payload.clone(),
+            });
+            ApiResponse::new("冲突已解决", ResponseData::Json(payload))
+        }
+        // 并发情况下已被其他设备解决
+        Ok(None) => ApiResponse::error(ErrorCode::NotFound, "冲突不存在或已解决"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "解决冲突失败"),
+    }
+}
+
+// 把采用的版本合并到剪贴板，内容变化时重新检查内容策略和存储配额
+async fn apply_resolution(
+    user_id: &str,
+    mut clip: ClipItem,
+    fields: Resolution,
+    pool: &DbPool,
+    app_state: &AppState,
+) -> Result<ClipItem, ApiResponse> {
+    if let Some(content) = fields.content
+        && content != clip.content
+    {
+        let checked = if clip.encrypted {
+            app_state
+                .content_policy
+                .check_encrypted_clip(clip.content_type.as_str(), content.len())
+        } else {
+            app_state
+                .content_policy
+                .check_clip(clip.content_type.as_str(), &content)
+        };
+        if let Err(violation) = checked {
+            return Err(ApiResponse::error_with_data(
+                ErrorCode::PolicyViolation,
+                "内容不符合服务器策略",
+                ResponseData::Json(json!(violation)),
+            ));
+        }
+        let size = content.len() as i64;
+        check_quota(user_id, (size - clip.size).max(0), pool).await?;
+        clip.size = size;
+        clip.preview = match fields.preview {
+            _ if clip.encrypted => String::new(),
+            Some(preview) => preview,
+            None => content.chars().take(PREVIEW_CHARS).collect(),
+        };
+        clip.content = content;
+        app_state.content_policy.sanitize_clip(&mut clip);
+    } else if let Some(preview) = fields.preview
+        && !clip.encrypted
+    {
+        clip.preview = preview;
+    }
+    if let Some(tags) = fields.tags {
+        clip.tags = normalize_tags(tags);
+    }
+    clip.accessed_at = Utc::now().trunc_subsecs(3);
+    clip.sync_status = SyncStatus::Synced;
+    Ok(clip)
+}
@@ -0,0 +1,132 @@
+use actix_web::{Responder, delete, get, post, put, web};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    spatial_api::models::{AppState, PushEvent},
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiResponse, ErrorCode, ResponseData},
+    },
+    user_api::auth::BearerToken,
+};
+
+pub fn tag_api() -> actix_web::Scope {
+    web::scope("/tags")
+        .service(list_tags)
+        .service(create_tag)
+        .service(rename_tag)
+        .service(delete_tag)
+}
+
+/// 标签，名称在同一用户内唯一
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Tag {
+    pub id: String,
+    pub name: String,
+    /// 使用该标签的剪贴板数量
+    pub clip_count: i64,
+    pub created_at: i64,
+}
+
+// 标签修改后通知用户的所有设备（附带最新的标签列表）
+async fn tags_changed(user_id: &str, pool: &DbPool, app_state: &AppState) {
+    match db::get_tags(user_id, pool).await {
+        Ok(tags) => app_state.room_manager.do_send(PushEvent {
+            user_id: user_id.to_string(),
+            event: "tags_changed".to_string(),
+            payload: json!(tags),
+        }),
+        Err(e) => warn!("reload tags for {} failed: {}", user_id, e),
+    }
+}
+
+// 获取标签列表
+#[get("")]
+async fn list_tags(pool: web::Data<DbPool>, bearer_token: BearerToken) -> impl Responder {
+    match db::get_tags(&bearer_token.user_id, &pool).await {
+        Ok(tags) => ApiResponse::new("获取标签成功", ResponseData::Json(json!(tags))),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取标签失败"),
+    }
+}
+
+// 创建 / 重命名标签
+#[derive(Deserialize)]
+pub struct TagName {
+    pub name: String,
+}
+
+#[post("")]
+async fn create_tag(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    create: web::Json<TagName>,
+) -> impl Responder {
+    let name = create.name.trim();
+    if name.is_empty() {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "标签名称不能为空");
+    }
+    let tag = Tag {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: name.to_string(),
+        clip_count: 0,
+        created_at: chrono::Utc::now().timestamp(),
+    };
+    info!("用户 {} 创建标签 {}", bearer_token.user_id, tag.name);
+
+    match db::insert_tag(&bearer_token.user_id, &tag, &pool).await {
+        Ok(true) => {
+            tags_changed(&bearer_token.user_id, &pool, &app_state).await;
+            ApiResponse::new("标签创建成功", ResponseData::Json(json!(tag)))
+        }
+        Ok(false) => ApiResponse::error(ErrorCode::AlreadyExists, "标签名称已存在"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "标签创建失败"),
+    }
+}
+
+// 重命名标签，使用该标签的剪贴板随之更新
+#[put("/{id}")]
+async fn rename_tag(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    id: web::Path<String>,
+    rename: web::Json<TagName>,
+) -> impl Responder {
+    let name = rename.name.trim();
+    if name.is_empty() {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "标签名称不能为空");
+    }
+    info!("用户 {} 重命名标签 {} 为 {}", bearer_token.user_id, id, name);
+
+    match db::rename_tag(&bearer_token.user_id, &id, name, &pool).await {
+        Ok(Some(true)) => {
+            tags_changed(&bearer_token.user_id, &pool, &app_state).await;
+            ApiResponse::new("标签修改成功", ResponseData::Null)
+        }
+        Ok(Some(false)) => ApiResponse::error(ErrorCode::AlreadyExists, "标签名称已存在"),
+        Ok(None) => ApiResponse::error(ErrorCode::NotFound, "标签不存在"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "标签修改失败"),
+    }
+}
+
+// 删除标签，同时从所有剪贴板上移除
+#[delete("/{id}")]
+async fn delete_tag(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    id: web::Path<String>,
+) -> impl Responder {
+    match db::delete_tag(&bearer_token.user_id, &id, &pool).await {
+        Ok(true) => {
+            info!("用户 {} 删除标签 {}", bearer_token.user_id, id);
+            tags_changed(&bearer_token.user_id, &pool, &app_state).await;
+            ApiResponse::new("标签已删除", ResponseData::Null)
+        }
+        Ok(false) => ApiResponse::error(ErrorCode::NotFound, "标签不存在"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "标签删除失败"),
+    }
+}
@@ -0,0 +1,149 @@
+use crate::config::TlsConfig;
+
+pub use transport::ServerConfig;
+
+/// 按配置创建 HTTP 服务的 TLS 配置，未配置证书时返回 None（以 HTTP 提供服务）
+///
+/// 已配置证书但无法加载时返回错误，不会退回到未加密的 HTTP
+pub fn server_config(config: &TlsConfig) -> Result<Option<ServerConfig>, String> {
+    match (&config.cert, &config.key) {
+        (Some(cert), Some(key)) => transport::server_config(cert, key).map(Some),
+        (None, None) => Ok(None),
+        _ => Err("tls.cert and tls.key must be set together".to_string()),
+    }
+}
+
+#[cfg(feature = "tls")]
+mod transport {
+    use log::{info, warn};
+    use rustls::crypto::{CryptoProvider, ring::default_provider};
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject};
+    use rustls::server::{ClientHello, ResolvesServerCert};
+    use rustls::sign::CertifiedKey;
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant, SystemTime};
+
+    pub use rustls::ServerConfig;
+
+    /// 检查证书文件是否更新的最小间隔
+    const RELOAD_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+    pub fn server_config(cert: &str, key: &str) -> Result<ServerConfig, String> {
+        let provider = Arc::new(default_provider());
+        let resolver = ReloadingResolver::new(cert, key, provider.clone())?;
+        info!("TLS enabled with certificate {}", cert);
+        Ok(ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .map_err(|e| e.to_string())?
+            .with_no_client_auth()
+            .with_cert_resolver(Arc::new(resolver)))
+    }
+
+    /// 证书解析器：握手时最多每分钟检查一次证书和私钥文件的修改时间，文件更新后重新加载，
+    /// 加载失败时（如续期时文件只写了一半）继续使用之前的证书
+    #[derive(Debug)]
+    struct ReloadingResolver {
+        cert: String,
+        key: String,
+        provider: Arc<CryptoProvider>,
+        state: Mutex<State>,
+    }
+
+    #[derive(Debug)]
+    struct State {
+        current: Arc<CertifiedKey>,
+        modified: Option<SystemTime>,
+        checked_at: Instant,
+    }
+
+    impl ReloadingResolver {
+        fn new(cert: &str, key: &str, provider: Arc<CryptoProvider>) -> Result<Self, String> {
+            // 先记录修改时间再加载，加载期间文件被更新时下次检查会重新加载
+            let modified = modified(cert, key);
+            let current = load(cert, key, &provider)?;
+            Ok(ReloadingResolver {
+                cert: cert.to_string(),
+                key: key.to_string(),
+                provider,
+                state: Mutex::new(State {
+                    current,
+                    modified,
+                    checked_at: Instant::now(),
+                }),
+            })
+        }
+    }
+
+    impl ResolvesServerCert for ReloadingResolver {
+        fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+            let mut state = self.state.lock().ok()?;
+            if state.checked_at.elapsed() >= RELOAD_CHECK_INTERVAL {
+                state.checked_at = Instant::now();
+                let modified = modified(&self.cert, &self.key);
+                if modified != state.modified {
+                    match load(&self.cert, &self.key, &self.provider) {
+                        Ok(current) => {
+                            info!("Reloaded TLS certificate {}", self.cert);
+                            state.current = current;
+                            state.modified = modified;
+                        }
+                        Err(e) => warn!(
+                            "TLS certificate reload failed, keeping the previous one: {}",
+                            e
+                        ),
+                    }
+                }
+            }
+            Some(state.current.clone())
+        }
+    }
+
+    // 读取 PEM 格式的证书链和私钥，并检查私钥与证书是否匹配
+    fn load(cert: &str, key: &str, provider: &CryptoProvider) -> Result<Arc<CertifiedKey>, String> {
+        let chain = CertificateDer::pem_file_iter(cert)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| format!("read {} failed: {}", cert, e))?;
+        if chain.is_empty() {
+            return Err(format!("{} contains no certificate", cert));
+        }
+        let private_key =
+            PrivateKeyDer::from_pem_file(key).map_err(|e| format!("read {} failed: {}", key, e))?;
+        CertifiedKey::from_der(chain, private_key, provider)
+            .map(Arc::new)
+            .map_err(|e| format!("invalid TLS key pair {} / {}: {}", cert, key, e))
+    }
+
+    // 证书和私钥文件中较新的修改时间
+    fn modified(cert: &str, key: &str) -> Option<SystemTime> {
+        [cert, key]
+            .iter()
+            .filter_map(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+            .max()
+    }
+}
+
+#[cfg(not(feature = "tls"))]
+mod transport {
+    /// 未启用 `tls` feature 时不会创建 TLS 配置
+    pub enum ServerConfig {}
+
+    pub fn server_config(_cert: &str, _key: &str) -> Result<ServerConfig, String> {
+        Err("tls.cert is set but the tls feature is disabled".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cert_and_key_must_be_set_together() {
+        assert!(matches!(server_config(&TlsConfig::default()), Ok(None)));
+        let config = TlsConfig {
+            cert: Some("cert.pem".to_string()),
+            key: None,
+        };
+        assert!(server_config(&config).is_err());
+    }
+}
@@ -0,0 +1,648 @@
+use actix_web::{HttpRequest, Responder, delete, get, post, put, web};
+use chrono::{DateTime, SubsecRound, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+
+use crate::{
+    blob_store::blob_store,
+    clip_api::{normalize_tags, notify},
+    content_policy::PolicyViolation,
+    device_group_api::Visibility,
+    models::{
+        ClipItem, ClipSource, ClipType, SyncStatus, check_encryption, check_expiry, content_hash,
+    },
+    spatial_api::{
+        binary::{BINARY_CLIP_TYPES, check_mime, extension_for, sniff_file},
+        models::AppState,
+    },
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiResponse, ErrorCode, ResponseData},
+    },
+    user_api::{auth::BearerToken, quota::check_quota},
+    utils::{SavePayloadError, save_payload_with_dirs},
+};
+
+pub fn upload_api() -> actix_web::Scope {
+    web::scope("/uploads")
+        .service(start_upload)
+        .service(get_upload)
+        .service(upload_chunk)
+        .service(complete_upload)
+        .service(cancel_upload)
+}
+
+/// 未完成上传的分块保存目录：`{UPLOADS_DIR}/{user_id}/{upload_id}/{chunk_index}`
+pub const UPLOADS_DIR: &str = "./static/uploads";
+
+/// 分块的 BLAKE3 校验和（十六进制）请求头
+const CHUNK_CHECKSUM_HEADER: &str = "x-chunk-checksum";
+
+/// 分块上传会话，完成后所有分块按顺序合并为一个图片 / RTF 剪贴板
+#[derive(Debug, Serialize)]
+pub struct Upload {
+    pub id: String,
+    pub clip_id: Uuid,
+    pub device_id: Uuid,
+    pub content_type: ClipType,
+    pub mime_type: String,
+    /// 文件总字节数
+    pub size: i64,
+    /// 分块字节数，只有最后一个分块可以更小
+    pub chunk_size: i64,
+    /// 完整文件的 BLAKE3 校验和（十六进制），提供时在完成上传时校验
+    pub checksum: Option<String>,
+    pub clip: UploadClip,
+    pub created_at: i64,
+    pub expires_at: i64,
+}
+
+impl Upload {
+    pub fn chunk_count(&self) -> i64 {
+        (self.size + self.chunk_size - 1) / self.chunk_size
+    }
+
+    /// 第 index 个分块应有的字节数
+    pub fn chunk_len(&self, index: i64) -> i64 {
+        (self.size - index * self.chunk_size).min(self.chunk_size)
+    }
+}
+
+/// 完成上传后写入剪贴板的其他字段
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadClip {
+    pub preview: Option<String>,
+    pub source_app: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub visibility: Visibility,
+    /// 端到端加密时，上传的字节为密文
+    pub key_id: Option<String>,
+    pub nonce: Option<String>,
+    /// 剪贴板的过期时间
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// 已收到的分块
+#[derive(Debug, Serialize)]
+pub struct UploadChunk {
+    pub index: i64,
+    pub size: i64,
+    pub checksum: String,
+}
+
+fn upload_dir(user_id: &str, id: &str) -> PathBuf {
+    Path::new(UPLOADS_DIR).join(user_id).join(id)
+}
+
+// 删除上传会话的分块目录
+pub async fn remove_upload_dir(user_id: &str, id: &str) {
+    let dir = upload_dir(user_id, id);
+    if let Err(e) = tokio::fs::remove_dir_all(&dir).await
+        && e.kind() != std::io::ErrorKind::NotFound
+    {
+        warn!("remove {} failed: {}", dir.display(), e);
+    }
+}
+
+// 开始上传：size 为文件总字节数，chunk_size 默认为服务器允许的最大分块大小，
+// clip_id 未提供时由服务器生成
+#[derive(Deserialize)]
+pub struct StartUpload {
+    pub clip_id: Option<Uuid>,
+    pub device_id: Uuid,
+    pub content_type: ClipType,
+    pub mime_type: String,
+    pub size: i64,
+    pub chunk_size: Option<i64>,
+    pub checksum: Option<String>,
+    #[serde(flatten)]
+    pub clip: UploadClip,
+}
+
+#[post("")]
+async fn start_upload(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    start: web::Json<StartUpload>,
+) -> impl Responder {
+    let user_id = &bearer_token.user_id;
+    let start = start.into_inner();
+    let limits = &app_state.config.upload;
+    if !BINARY_CLIP_TYPES.contains(&start.content_type) {
+        return ApiResponse::error(
+            ErrorCode::InvalidRequest,
+            "只有图片和 RTF 剪贴板可以分块上传",
+        );
+    }
+    if start.size <= 0 {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "文件大小必须大于 0");
+    }
+    if start.size as usize > limits.max_clip_size {
+        let violation = PolicyViolation::TooLarge {
+            content_type: start.content_type.as_str().to_string(),
+            size: start.size as usize,
+            max_size: limits.max_clip_size,
+        };
+        return ApiResponse::error_with_data(
+            ErrorCode::PolicyViolation,
+            "内容不符合服务器策略",
+            ResponseData::Json(json!(violation)),
+        );
+    }
+    let chunk_size = start.chunk_size.unwrap_or(limits.max_chunk_size as i64);
+    if chunk_size <= 0 || chunk_size as usize > limits.max_chunk_size {
+        return ApiResponse::error(
+            ErrorCode::InvalidRequest,
+            &format!("分块大小需要在 1 到 {} 字节之间", limits.max_chunk_size),
+        );
+    }
+    if let Err(violation) = app_state.content_policy.check_blob(
+        start.content_type.as_str(),
+        &start.mime_type,
+        start.size as usize,
+    ) {
+        return ApiResponse::error_with_data(
+            ErrorCode::PolicyViolation,
+            "内容不符合服务器策略",
+            ResponseData::Json(json!(violation)),
+        );
+    }
+    if check_encryption(start.clip.key_id.as_deref(), start.clip.nonce.as_deref()).is_err() {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "key_id 和 nonce 必须同时提供");
+    }
+    if check_expiry(start.clip.expires_at).is_err() {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "expires_at 必须晚于当前时间");
+    }
+    if start.clip.key_id.is_some() && start.clip.preview.is_some() {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "加密的剪贴板不能包含明文预览");
+    }
+    let checksum = match start.checksum.as_deref().map(blake3::Hash::from_hex) {
+        Some(Ok(hash)) => Some(hash.to_hex().to_string()),
+        Some(Err(_)) => {
+            return ApiResponse::error(ErrorCode::InvalidRequest, "checksum 格式不正确");
+        }
+        None => None,
+    };
+
+    // 剪贴板必须来自已注册且未注销的设备
+    let internal_error = |_| ApiResponse::error(ErrorCode::InternalError, "创建上传失败");
+    match db::get_active_device(user_id, &start.device_id.to_string(), &pool).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return ApiResponse::error(ErrorCode::InvalidRequest, "设备未注册或已注销"),
+        Err(e) => return internal_error(e),
+    }
+    let groups = match db::get_device_groups(user_id, &pool).await {
+        Ok(groups) => groups,
+        Err(e) => return internal_error(e),
+    };
+    if let Err(response) = start.clip.visibility.validate(&groups) {
+        return response;
+    }
+    let clip_id = start.clip_id.unwrap_or_else(Uuid::new_v4);
+    match db::get_clip(user_id, &clip_id, &pool).await {
+        Ok(None) => {}
+        Ok(Some(_)) => return ApiResponse::error(ErrorCode::AlreadyExists, "剪贴板已存在"),
+        Err(e) => return internal_error(e),
+    }
+    // 提前检查存储配额，避免上传完成后才被拒绝
+    if let Err(response) = check_quota(user_id, start.size, &**pool).await {
+        return response;
+    }
+
+    let now = Utc::now().timestamp();
+    let upload = Upload {
+        id: Uuid::new_v4().to_string(),
+        clip_id,
+        device_id: start.device_id,
+        content_type: start.content_type,
+        mime_type: start.mime_type,
+        size: start.size,
+        chunk_size,
+        checksum,
+        clip: start.clip,
+        created_at: now,
+        expires_at: now + limits.session_ttl_secs,
+    };
+    match db::insert_upload(user_id, &upload, &pool).await {
+        Ok(_) => {
+            info!(
+                "用户 {} 开始分块上传 {}（{} 字节，{} 个分块）",
+                user_id,
+                upload.id,
+                upload.size,
+                upload.chunk_count()
+            );
+            ApiResponse::new(
+                "上传已创建",
+                ResponseData::Json(json!({
+                    "upload": upload,
+                    "chunk_count": upload.chunk_count(),
+                })),
+            )
+        }
+        Err(e) => internal_error(e),
+    }
+}
+
+// 未过期的上传会话，不存在或已过期时返回错误响应
+async fn active_upload(user_id: &str, id: &str, pool: &DbPool) -> Result<Upload, ApiResponse> {
+    match db::get_upload(user_id, id, pool).await {
+        Ok(Some(upload)) if upload.expires_at > Utc::now().timestamp() => Ok(upload),
+        Ok(_) => Err(ApiResponse::error(
+            ErrorCode::NotFound,
+            "上传不存在或已过期",
+        )),
+        Err(_) => Err(ApiResponse::error(ErrorCode::InternalError, "获取上传失败")),
+    }
+}
+
+// 查询上传进度，断点续传时只需上传 missing 中的分块
+#[get("/{id}")]
+async fn get_upload(
+    pool: web::Data<DbPool>,
+    bearer_token: BearerToken,
+    id: web::Path<String>,
+) -> impl Responder {
+    let upload = match active_upload(&bearer_token.user_id, &id, &pool).await {
+        Ok(upload) => upload,
+        Err(response) => return response,
+    };
+    let chunks = match db::get_upload_chunks(&upload.id, &pool).await {
+        Ok(chunks) => chunks,
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "获取上传失败"),
+    };
+    let received: HashSet<i64> = chunks.iter().map(|chunk| chunk.index).collect();
+    let missing: Vec<i64> = (0..upload.chunk_count())
+        .filter(|index| !received.contains(index))
+        .collect();
+    let received_bytes: i64 = chunks.iter().map(|chunk| chunk.size).sum();
+    ApiResponse::new(
+        "获取上传成功",
+        ResponseData::Json(json!({
+            "upload": upload,
+            "chunk_count": upload.chunk_count(),
+            "received_bytes": received_bytes,
+            "chunks": chunks,
+            "missing": missing,
+        })),
+    )
+}
+
+// 上传第 index 个分块（从 0 开始），请求体为原始字节，X-Chunk-Checksum 为其 BLAKE3 校验和
+//
+// 重复上传同一分块时覆盖之前的内容，大小或校验和不一致时丢弃该分块
+#[put("/{id}/chunks/{index}")]
+async fn upload_chunk(
+    pool: web::Data<DbPool>,
+    bearer_token: BearerToken,
+    req: HttpRequest,
+    path: web::Path<(String, i64)>,
+    payload: web::Payload,
+) -> impl Responder {
+    let (id, index) = path.into_inner();
+    let user_id = &bearer_token.user_id;
+    let Some(checksum) = req
+        .headers()
+        .get(CHUNK_CHECKSUM_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| blake3::Hash::from_hex(value.trim()).ok())
+    else {
+        return ApiResponse::error(
+            ErrorCode::InvalidRequest,
+            "缺少或无效的 X-Chunk-Checksum 请求头",
+        );
+    };
+    let upload = match active_upload(user_id, &id, &pool).await {
+        Ok(upload) => upload,
+        Err(response) => return response,
+    };
+    if !(0..upload.chunk_count()).contains(&index) {
+        return ApiResponse::error(
+            ErrorCode::InvalidRequest,
+            &format!("分块序号需要在 0 到 {} 之间", upload.chunk_count() - 1),
+        );
+    }
+
+    // 先写入临时文件，校验通过后再替换，避免并发上传同一分块时读到不完整的内容
+    let expected = upload.chunk_len(index);
+    let dir = upload_dir(user_id, &upload.id);
+    let part = dir.join(format!("{}.{}.part", index, Uuid::new_v4()));
+    let part_path = part.to_string_lossy();
+    let size_error = || {
+        ApiResponse::error(
+            ErrorCode::InvalidRequest,
+            &format!("分块大小不正确，应为 {} 字节", expected),
+        )
+    };
+    match save_payload_with_dirs(payload, &part_path, Some(expected as usize)).await {
+        Ok(size) if size as i64 == expected => {}
+        Ok(_) => {
+            let _ = tokio::fs::remove_file(&part).await;
+            return size_error();
+        }
+        Err(SavePayloadError::TooLarge(_)) => return size_error(),
+        Err(SavePayloadError::Other(e)) => {
+            warn!("save chunk {} of upload {} failed: {}", index, upload.id, e);
+            let _ = tokio::fs::remove_file(&part).await;
+            return ApiResponse::error(ErrorCode::InternalError, "分块保存失败");
+        }
+    }
+    let actual = match tokio::fs::read(&part).await {
+        Ok(data) => blake3::hash(&data),
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "分块保存失败"),
+    };
+    if actual != checksum {
+        let _ = tokio::fs::remove_file(&part).await;
+        return ApiResponse::error_with_data(
+            ErrorCode::InvalidRequest,
+            "分块校验和不一致",
+            ResponseData::Json(json!({ "index": index, "checksum": actual.to_hex().as_str() })),
+        );
+    }
+    if tokio::fs::rename(&part, dir.join(index.to_string()))
+        .await
+        .is_err()
+    {
+        let _ = tokio::fs::remove_file(&part).await;
+        return ApiResponse::error(ErrorCode::InternalError, "分块保存失败");
+    }
+
+    let chunk = UploadChunk {
+        index,
+        size: expected,
+        checksum: actual.to_hex().to_string(),
+    };
+    match db::save_upload_chunk(&upload.id, &chunk, &pool).await {
+        Ok(received) => ApiResponse::new(
+            "分块上传成功",
+            ResponseData::Json(json!({
+                "chunk": chunk,
+                "received": received,
+                "chunk_count": upload.chunk_count(),
+            })),
+        ),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "分块保存失败"),
+    }
+}
+
+// 按顺序合并分块并校验，返回合并后文件的校验和
+async fn assemble(
+    upload: &Upload,
+    chunks: &[UploadChunk],
+    dir: &Path,
+    target: &Path,
+) -> Result<blake3::Hash, ApiResponse> {
+    let internal_error = |e: std::io::Error| {
+        warn!("assemble upload {} failed: {}", upload.id, e);
+        ApiResponse::error(ErrorCode::InternalError, "合并分块失败")
+    };
+    let mut file = tokio::fs::File::create(target)
+        .await
+        .map_err(internal_error)?;
+    let mut hasher = blake3::Hasher::new();
+    for chunk in chunks {
+        let data = tokio::fs::read(dir.join(chunk.index.to_string()))
+            .await
+            .map_err(internal_error)?;
+        // 分块文件与记录不一致（例如被并发上传替换）时需要重新上传该分块
+        if blake3::hash(&data).to_hex().as_str() != chunk.checksum {
+            return Err(ApiResponse::error_with_data(
+                ErrorCode::InvalidRequest,
+                "分块校验和不一致，请重新上传",
+                ResponseData::Json(json!({ "missing": [chunk.index] })),
+            ));
+        }
+        hasher.update(&data);
+        file.write_all(&data).await.map_err(internal_error)?;
+    }
+    file.flush().await.map_err(internal_error)?;
+    Ok(hasher.finalize())
+}
+
+// 完成上传：合并全部分块并保存为剪贴板，通知可见范围内的设备
+#[post("/{id}/complete")]
+async fn complete_upload(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    id: web::Path<String>,
+) -> impl Responder {
+    let user_id = &bearer_token.user_id;
+    let upload = match active_upload(user_id, &id, &pool).await {
+        Ok(upload) => upload,
+        Err(response) => return response,
+    };
+    let internal_error = |_| ApiResponse::error(ErrorCode::InternalError, "完成上传失败");
+    let chunks = match db::get_upload_chunks(&upload.id, &pool).await {
+        Ok(chunks) => chunks,
+        Err(e) => return internal_error(e),
+    };
+    let received: HashSet<i64> = chunks.iter().map(|chunk| chunk.index).collect();
+    let missing: Vec<i64> = (0..upload.chunk_count())
+        .filter(|index| !received.contains(index))
+        .collect();
+    if !missing.is_empty() {
+        return ApiResponse::error_with_data(
+            ErrorCode::InvalidRequest,
+            "还有分块未上传",
+            ResponseData::Json(json!({ "missing": missing })),
+        );
+    }
+    // 不能覆盖已有剪贴板的文件
+    match db::get_clip(user_id, &upload.clip_id, &pool).await {
+        Ok(None) => {}
+        Ok(Some(_)) => return ApiResponse::error(ErrorCode::AlreadyExists, "剪贴板已存在"),
+        Err(e) => return internal_error(e),
+    }
+
+    let dir = upload_dir(user_id, &upload.id);
+    let assembled = dir.join(format!("{}.assembled", Uuid::new_v4()));
+    let checksum = match assemble(&upload, &chunks, &dir, &assembled).await {
+        Ok(checksum) => checksum,
+        Err(response) => {
+            let _ = tokio::fs::remove_file(&assembled).await;
+            return response;
+        }
+    };
+    if let Some(expected) = &upload.checksum
+        && checksum.to_hex().as_str() != expected
+    {
+        let _ = tokio::fs::remove_file(&assembled).await;
+        return ApiResponse::error_with_data(
+            ErrorCode::InvalidRequest,
+            "文件校验和不一致",
+            ResponseData::Json(json!({ "checksum": checksum.to_hex().as_str() })),
+        );
+    }
+
+    // 加密的剪贴板为密文，无法识别文件类型
+    let encrypted = upload.clip.key_id.is_some();
+    if !encrypted {
+        let checked = match sniff_file(&assembled).await {
+            Ok(detected) => check_mime(&upload.mime_type, detected),
+            Err(e) => {
+                warn!("sniff upload {} failed: {}", upload.id, e);
+                let _ = tokio::fs::remove_file(&assembled).await;
+                return ApiResponse::error(ErrorCode::InternalError, "完成上传失败");
+            }
+        };
+        if let Err(violation) = checked {
+            let _ = tokio::fs::remove_file(&assembled).await;
+            return ApiResponse::error_with_data(
+                ErrorCode::PolicyViolation,
+                "内容不符合服务器策略",
+                ResponseData::Json(json!(violation)),
+            );
+        }
+    }
+    let now = Utc::now().trunc_subsecs(3);
+    let file_name = format!("{}.{}", upload.clip_id, extension_for(&upload.mime_type));
+    let clip = ClipItem {
+        id: upload.clip_id,
+        device_id: upload.device_id,
+        content_type: upload.content_type,
+        content: format!("clips/{}/{}", user_id, file_name),
+        preview: match upload.clip.preview {
+            Some(preview) => preview,
+            None if encrypted => String::new(),
+            None => format!("[{}]", upload.mime_type),
+        },
+        size: upload.size,
+        source_app: upload.clip.source_app,
+        created_at: now,
+        accessed_at: now,
+        sync_status: SyncStatus::Synced,
+        encrypted,
+        tags: normalize_tags(upload.clip.tags),
+        visibility: upload.clip.visibility,
+        key_id: upload.clip.key_id,
+        nonce: upload.clip.nonce,
+        version: 1,
+        pinned: false,
+        source: ClipSource::Own,
+        shared_by: None,
+        expires_at: upload.clip.expires_at.map(|at| at.trunc_subsecs(3)),
+        sensitive: None,
+    };
+
+    // 在同一事务中删除上传会话（并发完成时只有一个请求成功）、检查存储配额并写入剪贴板
+    let stored = async {
+        let mut tx = pool.begin().await.map_err(internal_error)?;
+        if !db::write_upload_delete(user_id, &upload.id, &mut tx)
+            .await
+            .map_err(internal_error)?
+        {
+            return Err(ApiResponse::error(
+                ErrorCode::NotFound,
+                "上传不存在或已过期",
+            ));
+        }
+        check_quota(user_id, clip.size, &mut tx).await?;
+        if let Err(e) = blob_store().put_file(&clip.content, &assembled).await {
+            warn!("move assembled upload {} failed: {}", upload.id, e);
+            return Err(ApiResponse::error(ErrorCode::InternalError, "完成上传失败"));
+        }
+        // 文件路径唯一，二进制剪贴板不参与按内容去重
+        let hash = content_hash(clip.content_type, &clip.content);
+        let inserted = match db::write_clip_insert(user_id, &clip, &hash, &mut tx).await {
+            Ok(_) => tx.commit().await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = inserted {
+            warn!("insert uploaded clip {} failed: {}", clip.id, e);
+            let _ = blob_store().delete(&clip.content).await;
+            return Err(ApiResponse::error(ErrorCode::InternalError, "完成上传失败"));
+        }
+        Ok(())
+    };
+    if let Err(response) = stored.await {
+        let _ = tokio::fs::remove_file(&assembled).await;
+        return response;
+    }
+    remove_upload_dir(user_id, &upload.id).await;
+
+    info!(
+        "用户 {} 完成分块上传 {}，保存剪贴板 {}",
+        user_id, upload.id, clip.id
+    );
+    notify(
+        user_id,
+        &clip,
+        "clip_created",
+        json!(clip),
+        &pool,
+        &app_state,
+    )
+    .await;
+    ApiResponse::new("上传完成", ResponseData::Json(json!(clip)))
+}
+
+// 取消上传，删除已上传的分块
+#[delete("/{id}")]
+async fn cancel_upload(
+    pool: web::Data<DbPool>,
+    bearer_token: BearerToken,
+    id: web::Path<String>,
+) -> impl Responder {
+    let user_id = &bearer_token.user_id;
+    match db::delete_upload(user_id, &id, &pool).await {
+        Ok(true) => {
+            remove_upload_dir(user_id, &id).await;
+            info!("用户 {} 取消分块上传 {}", user_id, id);
+            ApiResponse::new("上传已取消", ResponseData::Null)
+        }
+        Ok(false) => ApiResponse::error(ErrorCode::NotFound, "上传不存在或已过期"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "取消上传失败"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upload(size: i64, chunk_size: i64) -> Upload {
+        Upload {
+            id: "u1".to_string(),
+            clip_id: Uuid::nil(),
+            device_id: Uuid::nil(),
+            content_type: ClipType::Image,
+            mime_type: "image/png".to_string(),
+            size,
+            chunk_size,
+            checksum: None,
+            clip: UploadClip {
+                preview: None,
+                source_app: None,
+                tags: Vec::new(),
+                visibility: Visibility::default(),
+                key_id: None,
+                nonce: None,
+                expires_at: None,
+            },
+            created_at: 0,
+            expires_at: 0,
+        }
+    }
+
+    #[test]
+    fn chunk_lengths_cover_the_file() {
+        let upload = upload(10, 4);
+        assert_eq!(upload.chunk_count(), 3);
+        assert_eq!(upload.chunk_len(0), 4);
+        assert_eq!(upload.chunk_len(1), 4);
+        assert_eq!(upload.chunk_len(2), 2);
+
+        let exact = self::upload(8, 4);
+        assert_eq!(exact.chunk_count(), 2);
+        assert_eq!(exact.chunk_len(1), 4);
+    }
+}
@@ -0,0 +1,168 @@
+use actix_web::{Responder, get, put, web};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use utoipa::ToSchema;
+
+use crate::{
+    clip_api::normalize_tags,
+    content_policy::PolicyViolation,
+    models::ClipItem,
+    spatial_api::models::{AppState, PushEvent},
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiResponse, ErrorCode, ResponseData},
+    },
+    user_api::{
+        auth::BearerToken,
+        settings::{load_settings, parse_changes},
+    },
+};
+
+/// 保存应用规则的设置项
+pub(crate) const APP_RULES_KEY: &str = "app_rules";
+
+/// 每个用户最多的应用规则数
+const MAX_APP_RULES: usize = 100;
+
+/// 按来源应用（`source_app`，不区分大小写）处理剪贴板的规则，
+/// 在创建剪贴板（包括批量操作）和 WebSocket clip.push 时生效
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct AppRule {
+    pub source_app: String,
+    /// 不保存也不同步来自该应用的剪贴板（如密码管理器）
+    #[serde(default)]
+    pub block: bool,
+    /// 自动添加的标签
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// 用户的全部应用规则
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct AppRules {
+    pub rules: Vec<AppRule>,
+}
+
+impl AppRules {
+    /// 从用户设置中读取应用规则，格式错误时视为没有规则
+    pub fn from_settings(settings: &Map<String, Value>) -> Self {
+        let rules = settings
+            .get(APP_RULES_KEY)
+            .and_then(|rules| serde_json::from_value(rules.clone()).ok())
+            .unwrap_or_default();
+        Self { rules }
+    }
+
+    /// 校验规则：应用名称不能为空或重复，数量不超过上限
+    pub fn validate(rules: &[AppRule]) -> Result<(), String> {
+        if rules.len() > MAX_APP_RULES {
+            return Err(format!("最多 {} 条规则", MAX_APP_RULES));
+        }
+        let mut seen = Vec::with_capacity(rules.len());
+        for rule in rules {
+            let source_app = rule.source_app.trim().to_lowercase();
+            if source_app.is_empty() {
+                return Err("source_app 不能为空".to_string());
+            }
+            if seen.contains(&source_app) {
+                return Err(format!("重复的应用: {}", rule.source_app.trim()));
+            }
+            seen.push(source_app);
+        }
+        Ok(())
+    }
+
+    fn find(&self, source_app: &str) -> Option<&AppRule> {
+        let source_app = source_app.trim();
+        self.rules
+            .iter()
+            .find(|rule| rule.source_app.trim().eq_ignore_ascii_case(source_app))
+    }
+
+    /// 按剪贴板的来源应用执行规则：被屏蔽时返回拒绝原因，否则添加规则中的标签
+    pub fn apply(&self, clip: &mut ClipItem) -> Result<(), PolicyViolation> {
+        let Some(rule) = clip.source_app.as_deref().and_then(|app| self.find(app)) else {
+            return Ok(());
+        };
+        if rule.block {
+            return Err(PolicyViolation::AppBlocked {
+                source_app: rule.source_app.trim().to_string(),
+            });
+        }
+        if !rule.tags.is_empty() {
+            let mut tags = std::mem::take(&mut clip.tags);
+            tags.extend(rule.tags.iter().cloned());
+            clip.tags = normalize_tags(tags);
+        }
+        Ok(())
+    }
+}
+
+/// 读取用户的应用规则
+pub async fn load_app_rules(user_id: &str, pool: &DbPool) -> Result<AppRules, sqlx::Error> {
+    Ok(AppRules::from_settings(
+        &load_settings(user_id, pool).await?,
+    ))
+}
+
+// 获取应用规则
+#[get("/app_rules")]
+pub(crate) async fn get_app_rules(
+    pool: web::Data<DbPool>,
+    bearer_token: BearerToken,
+) -> impl Responder {
+    match load_app_rules(&bearer_token.user_id, &pool).await {
+        Ok(rules) => ApiResponse::new("获取应用规则成功", ResponseData::Json(json!(rules))),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取应用规则失败"),
+    }
+}
+
+// 修改应用规则（整体替换，规则为空时删除全部规则）
+#[put("/app_rules")]
+pub(crate) async fn put_app_rules(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    rules: web::Json<AppRules>,
+) -> impl Responder {
+    let mut rules = rules.into_inner();
+    for rule in &mut rules.rules {
+        rule.source_app = rule.source_app.trim().to_string();
+        rule.tags = normalize_tags(std::mem::take(&mut rule.tags));
+    }
+    info!(
+        "用户 {} 修改应用规则: {} 条",
+        bearer_token.user_id,
+        rules.rules.len()
+    );
+
+    let value = if rules.rules.is_empty() {
+        Value::Null
+    } else {
+        json!(rules.rules)
+    };
+    let updates = match parse_changes(Map::from_iter([(APP_RULES_KEY.to_string(), value)])) {
+        Ok(updates) => updates,
+        Err(reason) => return ApiResponse::error(ErrorCode::InvalidRequest, &reason),
+    };
+    if db::upsert_user_settings(&bearer_token.user_id, &updates, &pool)
+        .await
+        .is_err()
+    {
+        return ApiResponse::error(ErrorCode::InternalError, "应用规则保存失败");
+    }
+
+    match load_settings(&bearer_token.user_id, &pool).await {
+        Ok(settings) => {
+            let rules = AppRules::from_settings(&settings);
+            app_state.room_manager.do_send(PushEvent {
+                user_id: bearer_token.user_id.clone(),
+                event: "settings_changed".to_string(),
+                payload: Value::Object(settings),
+            });
+            ApiResponse::new("应用规则保存成功", ResponseData::Json(json!(rules)))
+        }
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "应用规则保存失败"),
+    }
+}
@@ -0,0 +1,53 @@
+use actix_web::{Responder, get, web};
+use serde_json::json;
+
+use crate::{
+    audit::{AuditLogEntry, AuditLogQuery, DEFAULT_AUDIT_LOG_LIMIT, MAX_AUDIT_LOG_LIMIT},
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiEnvelope, ApiResponse, Cursor, ErrorCode, Page, ResponseData},
+    },
+    user_api::auth::BearerToken,
+};
+
+// 查询审计日志的一页（用户接口和管理接口共用）
+pub(crate) async fn audit_log_page(query: &AuditLogQuery, pool: &DbPool) -> ApiResponse {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_AUDIT_LOG_LIMIT)
+        .clamp(1, MAX_AUDIT_LOG_LIMIT);
+    // 多查询一条以判断是否还有下一页
+    let (entries, total) = match tokio::try_join!(
+        db::get_audit_log(query, limit + 1, pool),
+        db::count_audit_log(query, pool),
+    ) {
+        Ok(result) => result,
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "获取审计日志失败"),
+    };
+    let page = Page::new(entries, total, limit as usize, |entry: &AuditLogEntry| {
+        Cursor {
+            at: entry.created_at,
+            id: entry.id.clone(),
+        }
+    });
+    ApiResponse::new("获取审计日志成功", ResponseData::Json(json!(page)))
+}
+
+#[utoipa::path(
+    summary = "账号的安全审计日志",
+    description = "登录、刷新令牌、修改密码、两步验证、设备注册以及管理员对该账号的操作，\
+        按时间倒序，按 next_cursor 翻页",
+    params(AuditLogQuery),
+    security(("bearer" = [])),
+    responses((status = 200, body = ApiEnvelope<Page<AuditLogEntry>>)),
+)]
+#[get("/audit_log")]
+async fn get_audit_log(
+    pool: web::Data<DbPool>,
+    bearer_token: BearerToken,
+    query: web::Query<AuditLogQuery>,
+) -> impl Responder {
+    let mut query = query.into_inner();
+    query.user_id = Some(bearer_token.user_id);
+    audit_log_page(&query, &pool).await
+}
@@ -1,14 +1,19 @@
 use actix_web::dev::Payload;
 use actix_web::http::header;
-use actix_web::{Error, FromRequest, HttpRequest};
+use actix_web::{Error, FromRequest, HttpRequest, web};
+use jsonwebtoken::errors::ErrorKind;
 use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, decode, encode};
-use log::{info, warn};
+use log::warn;
 use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
 use std::env;
 use std::future::{Ready, ready};
+use std::marker::PhantomData;
 use std::time::SystemTime;
 
-use crate::sqlx_utils::models::{ApiResponse, ResponseData};
+use crate::config::AuthConfig;
+use crate::models::UserRole;
+use crate::sqlx_utils::models::{ApiResponse, ErrorCode};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
@@ -16,17 +21,22 @@ pub struct Claims {
     pub username: String,
     pub exp: usize, // 过期时间戳
     pub iat: usize, // 签发时间戳
+    /// 是否有未接受的最新服务条款/隐私政策
+    #[serde(default)]
+    pub terms_pending: bool,
+    /// 账号角色，旧令牌没有该字段时为普通用户
+    #[serde(default)]
+    pub role: UserRole,
+    /// 签发令牌的登录会话（刷新令牌轮换时保持不变），旧令牌没有该字段
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
-pub struct RefreshResponse {
-    pub access_token: String,
-    pub token_type: String,
-    pub expires_in: i64,
-}
+/// 刷新令牌有效期（秒）
+pub const REFRESH_TOKEN_TTL_SECS: i64 = 30 * 24 * 60 * 60;
 
 // 获取环境变量
-fn get_secret(secret_name: &str) -> String {
+pub(crate) fn get_secret(secret_name: &str) -> String {
     env::var(secret_name).unwrap_or_else(|_| {
         warn!(
             "{} not set, using default secret (insecure for production!)",
@@ -39,8 +49,37 @@ fn get_secret(secret_name: &str) -> String {
     })
 }
 
+// 获取签名 / 加密使用的密钥，未设置或为空时返回 None
+//
+// 与 JWT_SECRET 不同，这类密钥没有默认值：公开的默认值任何人都能用来伪造签名或解密，
+// 未设置时调用方应当拒绝相应的操作
+pub(crate) fn required_secret(secret_name: &str) -> Option<String> {
+    env::var(secret_name)
+        .ok()
+        .filter(|secret| !secret.is_empty())
+}
+
+// 缺少 required_secret 时返回的错误响应
+pub(crate) fn missing_secret(secret_name: &str) -> ApiResponse {
+    warn!(
+        "{} not set, refusing to use an insecure default",
+        secret_name
+    );
+    ApiResponse::error(
+        ErrorCode::InternalError,
+        &format!("服务器未设置 {}，该功能不可用", secret_name),
+    )
+}
+
 // 生成令牌
-pub fn generate_access_token(user_id: &str, username: &str) -> Result<String, String> {
+pub fn generate_access_token(
+    user_id: &str,
+    username: &str,
+    session_id: Option<&str>,
+    terms_pending: bool,
+    role: UserRole,
+    config: &AuthConfig,
+) -> Result<String, String> {
     let secret = get_secret("JWT_SECRET");
     let now = SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
@@ -51,7 +90,10 @@ pub fn generate_access_token(user_id: &str, username: &str) -> Result<String, St
         user_id: user_id.to_string(),
         username: username.to_owned(),
         iat: now,
-        exp: now + 15 * 60, // 15分钟
+        exp: now + config.access_token_ttl_secs,
+        terms_pending,
+        role,
+        sid: session_id.map(str::to_string),
     };
 
     encode(
@@ -63,7 +105,7 @@ pub fn generate_access_token(user_id: &str, username: &str) -> Result<String, St
 }
 
 // 验证令牌
-pub fn validate_access_token(token: &str) -> Result<Claims, String> {
+pub fn validate_access_token(token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
     let secret = get_secret("JWT_SECRET");
 
     decode::<Claims>(
@@ -72,12 +114,80 @@ pub fn validate_access_token(token: &str) -> Result<Claims, String> {
         &Validation::default(),
     )
     .map(|data| data.claims)
-    .map_err(|e| format!("Invalid token: {}", e))
+}
+
+// 生成刷新令牌（随机字符串，不包含任何用户信息）
+pub fn generate_refresh_token() -> String {
+    format!(
+        "{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    )
+}
+
+// 刷新令牌的哈希值，数据库中只保存哈希值
+pub fn hash_refresh_token(token: &str) -> String {
+    blake3::hash(token.as_bytes()).to_hex().to_string()
 }
 
 pub struct BearerToken {
     pub user_id: String,
     pub username: String,
+    pub role: UserRole,
+    /// 登录会话 ID，见 `/user/sessions`
+    pub session_id: Option<String>,
+}
+
+// 验证访问令牌，过期单独返回错误码以便客户端刷新
+fn claims_from_token(token: &str) -> Result<Claims, Error> {
+    validate_access_token(token.trim()).map_err(|e| match e.kind() {
+        ErrorKind::ExpiredSignature => {
+            ApiResponse::into_error(ErrorCode::AuthExpired, "令牌已过期")
+        }
+        _ => ApiResponse::into_error(ErrorCode::AuthInvalid, "无效的令牌格式"),
+    })
+}
+
+// 从 Authorization 头中解析并验证令牌
+fn claims_from_request(req: &HttpRequest) -> Result<Claims, Error> {
+    let auth_header = req.headers().get(header::AUTHORIZATION);
+
+    match auth_header {
+        Some(header_value) => {
+            if let Ok(auth_str) = header_value.to_str() {
+                if let Some(token) = auth_str.strip_prefix("Bearer ") {
+                    claims_from_token(token)
+                } else {
+                    Err(ApiResponse::into_error(
+                        ErrorCode::AuthInvalid,
+                        "无效的令牌格式",
+                    ))
+                }
+            } else {
+                Err(ApiResponse::into_error(
+                    ErrorCode::AuthInvalid,
+                    "无效的header",
+                ))
+            }
+        }
+        None => Err(ApiResponse::into_error(ErrorCode::AuthMissing, "缺少令牌")),
+    }
+}
+
+// 未接受最新条款时拒绝访问，客户端需先调用 /legal/accept
+fn bearer_from_claims(claims: Claims) -> Result<BearerToken, Error> {
+    if claims.terms_pending {
+        return Err(ApiResponse::into_error(
+            ErrorCode::TermsNotAccepted,
+            "需要接受最新的服务条款",
+        ));
+    }
+    Ok(BearerToken {
+        user_id: claims.user_id,
+        username: claims.username,
+        role: claims.role,
+        session_id: claims.sid,
+    })
 }
 
 impl FromRequest for BearerToken {
@@ -85,33 +195,204 @@ impl FromRequest for BearerToken {
     type Future = Ready<Result<Self, Self::Error>>;
 
     fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
-        let auth_header = req.headers().get(header::AUTHORIZATION);
-
-        match auth_header {
-            Some(header_value) => {
-                if let Ok(auth_str) = header_value.to_str() {
-                    if auth_str.starts_with("Bearer ") {
-                        let token = auth_str[7..].trim().to_string();
-                        // 验证刷新令牌
-                        match validate_access_token(&token) {
-                            Ok(claims) => ready(Ok(BearerToken {
-                                user_id: claims.user_id,
-                                username: claims.username,
-                            })),
-                            Err(_) => ready(Err(actix_web::error::ErrorBadRequest(
-                                "无效的令牌格式",
-                            ))),
-                        }
-                    } else {
-                        ready(Err(actix_web::error::ErrorBadRequest(
-                            "无效的令牌格式",
-                        )))
-                    }
-                } else {
-                    ready(Err(actix_web::error::ErrorBadRequest("无效的header")))
-                }
+        ready(claims_from_request(req).and_then(bearer_from_claims))
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenQuery {
+    token: Option<String>,
+}
+
+/// WebSocket 握手使用的令牌
+///
+/// 浏览器无法在握手请求上设置 Authorization 头，没有该请求头时从 `?token=` 查询参数读取；
+/// 两者都没有时返回 None，由连接建立后的 auth 消息完成认证
+pub struct WsToken(pub Option<BearerToken>);
+
+impl FromRequest for WsToken {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        if req.headers().contains_key(header::AUTHORIZATION) {
+            return ready(
+                claims_from_request(req)
+                    .and_then(bearer_from_claims)
+                    .map(|token| WsToken(Some(token))),
+            );
+        }
+        let token = web::Query::<TokenQuery>::from_query(req.query_string())
+            .ok()
+            .and_then(|query| query.into_inner().token);
+        ready(match token {
+            Some(token) => claims_from_token(&token)
+                .and_then(bearer_from_claims)
+                .map(|token| WsToken(Some(token))),
+            None => Ok(WsToken(None)),
+        })
+    }
+}
+
+/// 校验 WebSocket 连接建立后通过 auth 消息提供的访问令牌，失败时返回错误码（与握手时的校验相同）
+pub fn bearer_from_access_token(token: &str) -> Result<BearerToken, ErrorCode> {
+    let claims = validate_access_token(token.trim()).map_err(|e| match e.kind() {
+        ErrorKind::ExpiredSignature => ErrorCode::AuthExpired,
+        _ => ErrorCode::AuthInvalid,
+    })?;
+    if claims.terms_pending {
+        return Err(ErrorCode::TermsNotAccepted);
+    }
+    Ok(BearerToken {
+        user_id: claims.user_id,
+        username: claims.username,
+        role: claims.role,
+        session_id: claims.sid,
+    })
+}
+
+/// 不检查服务条款接受状态的令牌，仅用于条款相关接口
+pub struct TermsExemptToken {
+    pub user_id: String,
+    pub username: String,
+    pub session_id: Option<String>,
+}
+
+impl FromRequest for TermsExemptToken {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(claims_from_request(req).map(|claims| TermsExemptToken {
+            user_id: claims.user_id,
+            username: claims.username,
+            session_id: claims.sid,
+        }))
+    }
+}
+
+/// RequireRole 的角色要求
+pub trait RoleRequirement {
+    /// 满足要求的角色
+    const ROLES: &'static [UserRole];
+    /// 角色不满足要求时的错误信息
+    const DENIED: &'static str;
+}
+
+/// 只允许管理员（修改数据的管理接口）
+pub struct AdminOnly;
+
+impl RoleRequirement for AdminOnly {
+    const ROLES: &'static [UserRole] = &[UserRole::Admin];
+    const DENIED: &'static str = "需要管理员权限";
+}
+
+/// 允许管理员和只读管理员（只查看数据的管理接口）
+pub struct AdminOrReadonly;
+
+impl RoleRequirement for AdminOrReadonly {
+    const ROLES: &'static [UserRole] = &[UserRole::Admin, UserRole::Readonly];
+    const DENIED: &'static str = "需要管理员或只读管理员权限";
+}
+
+/// 按角色限制访问：`Authorization` 中的访问令牌角色需满足 `R`，
+/// 或者（未提供访问令牌时）请求头 `X-Admin-Token` 与环境变量 `ADMIN_TOKEN` 一致，视为管理员
+///
+/// 未配置 `ADMIN_TOKEN` 或配置为空时只接受访问令牌，比较使用常数时间以免泄露令牌内容
+pub struct RequireRole<R: RoleRequirement> {
+    user_id: Option<String>,
+    role: PhantomData<R>,
+}
+
+/// 修改数据的管理接口使用的令牌
+pub type AdminToken = RequireRole<AdminOnly>;
+
+/// 只查看数据的管理接口使用的令牌
+pub type AdminReadToken = RequireRole<AdminOrReadonly>;
+
+impl<R: RoleRequirement> RequireRole<R> {
+    /// 管理员账号的用户 ID，使用 ADMIN_TOKEN 时为空
+    pub fn user_id(&self) -> Option<&str> {
+        self.user_id.as_deref()
+    }
+
+    fn check(role: UserRole, user_id: Option<String>) -> Result<Self, Error> {
+        if R::ROLES.contains(&role) {
+            Ok(RequireRole {
+                user_id,
+                role: PhantomData,
+            })
+        } else {
+            Err(ApiResponse::into_error(ErrorCode::Forbidden, R::DENIED))
+        }
+    }
+}
+
+impl<R: RoleRequirement> FromRequest for RequireRole<R> {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        if !req.headers().contains_key("X-Admin-Token")
+            && req.headers().contains_key(header::AUTHORIZATION)
+        {
+            return ready(
+                claims_from_request(req)
+                    .and_then(|claims| Self::check(claims.role, Some(claims.user_id))),
+            );
+        }
+
+        let expected = env::var("ADMIN_TOKEN").unwrap_or_default();
+        if expected.is_empty() {
+            warn!("ADMIN_TOKEN not set, admin endpoints only accept admin accounts");
+            return ready(Err(ApiResponse::into_error(
+                ErrorCode::Forbidden,
+                "管理员令牌未启用，请使用管理员账号登录",
+            )));
+        }
+
+        match req.headers().get("X-Admin-Token").map(|v| v.to_str()) {
+            Some(Ok(token)) if bool::from(token.as_bytes().ct_eq(expected.as_bytes())) => {
+                ready(Self::check(UserRole::Admin, None))
             }
-            None => ready(Err(actix_web::error::ErrorUnauthorized("缺少令牌"))),
+            Some(_) => ready(Err(ApiResponse::into_error(
+                ErrorCode::Forbidden,
+                "无效的管理员令牌",
+            ))),
+            None => ready(Err(ApiResponse::into_error(
+                ErrorCode::AuthMissing,
+                "缺少管理员令牌",
+            ))),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn require_role_checks_allowed_roles() {
+        assert!(AdminToken::check(UserRole::Admin, None).is_ok());
+        assert!(AdminToken::check(UserRole::Readonly, None).is_err());
+        assert!(AdminToken::check(UserRole::User, None).is_err());
+        assert!(AdminReadToken::check(UserRole::Admin, None).is_ok());
+        assert!(AdminReadToken::check(UserRole::Readonly, None).is_ok());
+        assert!(AdminReadToken::check(UserRole::User, None).is_err());
+    }
+
+    #[test]
+    fn claims_without_role_are_users() {
+        let claims: Claims =
+            serde_json::from_str(r#"{"user_id":"u","username":"n","exp":1,"iat":0}"#).unwrap();
+        assert_eq!(claims.role, UserRole::User);
+    }
+
+    #[test]
+    fn ws_auth_rejects_malformed_token() {
+        assert!(matches!(
+            bearer_from_access_token("not-a-token"),
+            Err(ErrorCode::AuthInvalid)
+        ));
+    }
+}
@@ -0,0 +1,255 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+
+use crate::config::LoginLockoutConfig;
+
+/// 记录数量超过该值时清理已过期的记录，仍然超出时丢弃最久未失败的记录
+const MAX_TRACKED_KEYS: usize = 10_000;
+
+/// 触发锁定的维度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LockoutScope {
+    /// 同一账号连续登录失败
+    Account,
+    /// 同一 IP 连续登录失败
+    Ip,
+}
+
+/// 锁定状态，登录被拒绝时作为错误响应的 data 返回
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+pub struct Lockout {
+    pub scope: LockoutScope,
+    /// 距离解除锁定的秒数
+    pub retry_after: u64,
+    /// 解除锁定的时间（Unix 秒）
+    pub locked_until: i64,
+}
+
+struct Failures {
+    count: u32,
+    last_failed_at: Instant,
+    locked_until: Option<Instant>,
+}
+
+/// 按账号和 IP 分别统计连续登录失败次数，超过阈值后锁定，锁定时长按失败次数指数增长
+#[derive(Clone)]
+pub struct LoginLockout {
+    config: LoginLockoutConfig,
+    failures: Arc<Mutex<HashMap<String, Failures>>>,
+}
+
+impl LoginLockout {
+    pub fn new(config: LoginLockoutConfig) -> Self {
+        LoginLockout {
+            config,
+            failures: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// 账号的计数键：账号存在时使用用户 ID，否则使用登录名，
+    /// 使不存在的账号同样会被锁定，避免据此判断账号是否存在
+    pub fn account_key(user_id: Option<&str>, username_or_email: &str) -> String {
+        match user_id {
+            Some(user_id) => format!("user:{}", user_id),
+            None => format!("name:{}", username_or_email.trim().to_lowercase()),
+        }
+    }
+
+    /// 账号或 IP 处于锁定中时返回剩余时间较长的锁定
+    pub fn check(&self, account: &str, ip: Option<&str>) -> Option<Lockout> {
+        let now = Instant::now();
+        let failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+        let remaining = |key: &str| {
+            failures
+                .get(key)
+                .and_then(|entry| entry.locked_until)
+                .filter(|until| *until > now)
+                .map(|until| until - now)
+        };
+        let account = remaining(account).map(|wait| (LockoutScope::Account, wait));
+        let ip = ip
+            .and_then(|ip| remaining(&ip_key(ip)))
+            .map(|wait| (LockoutScope::Ip, wait));
+        [account, ip]
+            .into_iter()
+            .flatten()
+            .max_by_key(|(_, wait)| *wait)
+            .map(|(scope, wait)| lockout(scope, wait))
+    }
+
+    /// 记录一次登录失败，达到阈值时返回新的锁定
+    pub fn record_failure(&self, account: &str, ip: Option<&str>) -> Option<Lockout> {
+        let now = Instant::now();
+        let mut failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+        if failures.len() > MAX_TRACKED_KEYS {
+            self.evict(&mut failures, now);
+        }
+        let account = self
+            .fail(&mut failures, account, self.config.account_threshold, now)
+            .map(|wait| (LockoutScope::Account, wait));
+        let ip = ip
+            .and_then(|ip| self.fail(&mut failures, &ip_key(ip), self.config.ip_threshold, now))
+            .map(|wait| (LockoutScope::Ip, wait));
+        [account, ip]
+            .into_iter()
+            .flatten()
+            .max_by_key(|(_, wait)| *wait)
+            .map(|(scope, wait)| lockout(scope, wait))
+    }
+
+    /// 登录成功后清除账号的失败记录（IP 的记录保留，避免用自己的账号重置 IP 计数）
+    pub fn record_success(&self, account: &str) {
+        let mut failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+        failures.remove(account);
+    }
+
+    // 失败次数加一，达到阈值时锁定 base_secs * 2^(超出阈值的次数)，不超过 max_secs
+    fn fail(
+        &self,
+        failures: &mut HashMap<String, Failures>,
+        key: &str,
+        threshold: u32,
+        now: Instant,
+    ) -> Option<Duration> {
+        if threshold == 0 {
+            return None;
+        }
+        let forget_after = self.max_lock();
+        let entry = failures.entry(key.to_string()).or_insert(Failures {
+            count: 0,
+            last_failed_at: now,
+            locked_until: None,
+        });
+        if now.duration_since(entry.last_failed_at) > forget_after {
+            entry.count = 0;
+        }
+        entry.count += 1;
+        entry.last_failed_at = now;
+        if entry.count < threshold {
+            return None;
+        }
+        let doublings = (entry.count - threshold).min(31);
+        let secs = self.config.base_secs.saturating_mul(1 << doublings);
+        let wait = Duration::from_secs(secs).min(forget_after);
+        entry.locked_until = Some(now + wait);
+        Some(wait)
+    }
+
+    // 清理距离最近一次失败已超过最长锁定时间的记录，仍然超出上限时丢弃最久未失败的一半
+    fn evict(&self, failures: &mut HashMap<String, Failures>, now: Instant) {
+        let forget_after = self.max_lock();
+        failures.retain(|_, entry| now.duration_since(entry.last_failed_at) <= forget_after);
+        if failures.len() > MAX_TRACKED_KEYS {
+            let mut last_failed: Vec<Instant> = failures
+                .values()
+                .map(|entry| entry.last_failed_at)
+                .collect();
+            last_failed.sort();
+            let cutoff = last_failed[last_failed.len() / 2];
+            failures.retain(|_, entry| entry.last_failed_at >= cutoff);
+        }
+    }
+
+    fn max_lock(&self) -> Duration {
+        Duration::from_secs(self.config.max_secs.max(self.config.base_secs))
+    }
+}
+
+fn ip_key(ip: &str) -> String {
+    format!("ip:{}", ip)
+}
+
+fn lockout(scope: LockoutScope, wait: Duration) -> Lockout {
+    let retry_after = crate::rate_limit::retry_after_secs(wait);
+    Lockout {
+        scope,
+        retry_after,
+        locked_until: chrono::Utc::now().timestamp() + retry_after as i64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lockout_after(account_threshold: u32, ip_threshold: u32) -> LoginLockout {
+        LoginLockout::new(LoginLockoutConfig {
+            account_threshold,
+            ip_threshold,
+            base_secs: 30,
+            max_secs: 3600,
+        })
+    }
+
+    #[test]
+    fn locks_account_after_threshold_with_backoff() {
+        let lockout = lockout_after(3, 0);
+        assert_eq!(lockout.record_failure("user:a", Some("1.2.3.4")), None);
+        assert_eq!(lockout.record_failure("user:a", Some("1.2.3.4")), None);
+        assert!(lockout.check("user:a", None).is_none());
+
+        let first = lockout.record_failure("user:a", None).unwrap();
+        assert_eq!(
+            (first.scope, first.retry_after),
+            (LockoutScope::Account, 30)
+        );
+        assert_eq!(
+            lockout.check("user:a", None).map(|l| l.retry_after),
+            Some(30)
+        );
+        assert!(lockout.check("user:b", Some("1.2.3.4")).is_none());
+
+        assert_eq!(
+            lockout
+                .record_failure("user:a", None)
+                .map(|l| l.retry_after),
+            Some(60)
+        );
+        for _ in 0..10 {
+            lockout.record_failure("user:a", None);
+        }
+        assert_eq!(
+            lockout.check("user:a", None).map(|l| l.retry_after),
+            Some(3600)
+        );
+    }
+
+    #[test]
+    fn locks_ip_across_accounts() {
+        let lockout = lockout_after(5, 2);
+        assert!(lockout.record_failure("user:a", Some("1.2.3.4")).is_none());
+        let locked = lockout.record_failure("user:b", Some("1.2.3.4")).unwrap();
+        assert_eq!(locked.scope, LockoutScope::Ip);
+        assert_eq!(
+            lockout.check("user:c", Some("1.2.3.4")).map(|l| l.scope),
+            Some(LockoutScope::Ip)
+        );
+        assert!(lockout.check("user:c", Some("5.6.7.8")).is_none());
+    }
+
+    #[test]
+    fn success_resets_account_but_not_ip() {
+        let lockout = lockout_after(2, 3);
+        lockout.record_failure("user:a", Some("1.2.3.4"));
+        lockout.record_failure("user:a", Some("1.2.3.4"));
+        assert!(lockout.check("user:a", None).is_some());
+
+        lockout.record_success("user:a");
+        assert!(lockout.check("user:a", None).is_none());
+        let locked = lockout.record_failure("user:b", Some("1.2.3.4")).unwrap();
+        assert_eq!(locked.scope, LockoutScope::Ip);
+    }
+
+    #[test]
+    fn account_key_ignores_case_for_unknown_accounts() {
+        assert_eq!(LoginLockout::account_key(Some("id"), "Alice"), "user:id");
+        assert_eq!(
+            LoginLockout::account_key(None, " Alice@Example.com "),
+            "name:alice@example.com"
+        );
+    }
+}
@@ -1,197 +1,1220 @@
-use actix_web::{Responder, get, post, put, web};
+use actix_web::{
+    Either, HttpRequest, HttpResponse, Responder, delete, get, http::header, post, put, web,
+};
 use log::{debug, info, warn};
 use serde::{Deserialize, Serialize};
-use serde_json::json;
-use sqlx::SqlitePool;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+use utoipa::{IntoParams, OpenApi, ToSchema};
 
 use crate::{
+    admin_api::purge::{PurgeReport, purge_signing_key, purge_user},
+    audit::{AuditEvent, AuditRecord},
+    blob_store::blob_store,
+    concurrency::{IfMatch, Versioned},
+    content_policy::{ContentPolicy, PolicyViolation},
+    idempotency::{self, Idempotency, IdempotencyKey},
+    mailer::public_base_url,
+    spatial_api::{binary, models::AppState},
     sqlx_utils::{
-        db,
-        models::{ApiResponse, ResponseData},
+        DbPool, db,
+        models::{ApiEnvelope, ApiResponse, ErrorCode, ResponseData},
+    },
+    upload_api::UPLOADS_DIR,
+    user_api::{
+        auth::{AdminReadToken, BearerToken, generate_access_token, hash_refresh_token},
+        lockout::{Lockout, LoginLockout},
+        quota::check_quota,
+        service::{UserError, UserService},
+        sessions::SessionClient,
     },
-    user_api::auth::{BearerToken, generate_access_token},
-    utils::save_payload_with_dirs,
+    utils::{SavePayloadError, save_payload_with_dirs},
 };
 
+pub(crate) mod app_rules;
+pub(crate) mod audit_log;
 pub(crate) mod auth;
+pub(crate) mod lockout;
+pub(crate) mod oauth;
+pub mod quota;
+pub(crate) mod sessions;
+pub mod service;
+pub(crate) mod settings;
+pub mod storage;
+pub mod two_factor;
 
 pub fn user_api() -> actix_web::Scope {
-    return web::scope("/user")
+    web::scope("/user")
         .service(register)
         .service(login)
         .service(refresh_token)
+        .service(logout)
         .service(change_nickname)
+        .service(username_history)
         .service(change_head)
         .service(change_password)
-        .service(get_user_info);
+        .service(delete_account)
+        .service(get_user_info)
+        .service(change_email)
+        .service(confirm_email_page)
+        .service(confirm_email)
+        .service(verify_email)
+        .service(resend_verification)
+        .service(forgot_password)
+        .service(reset_password)
+        .service(sessions::list_sessions)
+        .service(sessions::revoke_session)
+        .service(audit_log::get_audit_log)
+        .service(settings::get_settings)
+        .service(settings::put_settings)
+        .service(storage::get_storage)
+        .service(quota::get_quota)
+        .service(two_factor::enroll)
+        .service(two_factor::verify)
+        .service(two_factor::disable)
+        .service(oauth::login)
+        .service(oauth::link)
+        .service(oauth::confirm_link)
+        .service(oauth::callback)
+        .service(oauth::get_identities)
+        .service(oauth::delete_identity)
 }
+
+/// 用户接口的 OpenAPI 文档
+#[derive(OpenApi)]
+#[openapi(paths(
+    register,
+    login,
+    refresh_token,
+    logout,
+    change_nickname,
+    username_history,
+    change_head,
+    change_password,
+    delete_account,
+    get_user_info,
+    change_email,
+    confirm_email_page,
+    confirm_email,
+    verify_email,
+    resend_verification,
+    forgot_password,
+    reset_password,
+    sessions::list_sessions,
+    sessions::revoke_session,
+    audit_log::get_audit_log,
+    settings::get_settings,
+    settings::put_settings,
+    storage::get_storage,
+    quota::get_quota,
+    two_factor::enroll,
+    two_factor::verify,
+    two_factor::disable,
+    oauth::login,
+    oauth::link,
+    oauth::confirm_link,
+    oauth::callback,
+    oauth::get_identities,
+    oauth::delete_identity,
+))]
+pub struct UserApiDoc;
  
 #[derive(Debug, Deserialize)]
 pub struct User {
     pub user_id: String,
     pub username_or_email: String,
+    pub email: String,
     pub password: String,
+    pub disabled: bool,
+    pub org_id: Option<String>,
+    pub email_verified: bool,
+    pub totp_enabled: bool,
 }
 // 用户注册
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct RegisterUser {
     pub username: String,
     pub email: String,
     pub password: String,
+    /// 已接受的条款版本，如 {"tos": 2, "privacy": 1}
+    #[serde(default)]
+    pub accepted_terms: HashMap<String, i64>,
 }
 
+/// 是否开放注册，可通过 `REGISTRATION_OPEN=false` 关闭
+pub fn registration_open() -> bool {
+    env::var("REGISTRATION_OPEN")
+        .map(|v| !matches!(v.as_str(), "false" | "0"))
+        .unwrap_or(true)
+}
+
+/// 是否要求验证邮箱后才能登录，可通过 `EMAIL_VERIFICATION_REQUIRED=true` 开启
+pub fn email_verification_required() -> bool {
+    env::var("EMAIL_VERIFICATION_REQUIRED")
+        .map(|v| matches!(v.as_str(), "true" | "1"))
+        .unwrap_or(false)
+}
+
+/// 邮箱验证码有效期（秒）
+const EMAIL_VERIFICATION_TTL: i64 = 24 * 3600;
+
+// 生成新的邮箱验证码并发送到用户邮箱（之前的验证码失效）
+async fn send_verification(
+    user_id: &str,
+    email: &str,
+    pool: &DbPool,
+    app_state: &AppState,
+) -> Result<(), ApiResponse> {
+    let token = uuid::Uuid::new_v4().simple().to_string();
+    let expires_at = chrono::Utc::now().timestamp() + EMAIL_VERIFICATION_TTL;
+    if db::insert_email_verification(&token, user_id, expires_at, pool)
+        .await
+        .is_err()
+    {
+        return Err(ApiResponse::error(ErrorCode::InternalError, "验证邮件发送失败"));
+    }
+    app_state
+        .mailer
+        .send(
+            email,
+            "验证 ClipFocus 邮箱",
+            &format!("请在 24 小时内于客户端中输入以下验证码完成邮箱验证:\n{}", token),
+        )
+        .await
+        .map_err(|e| {
+            warn!("发送邮箱验证邮件失败: {}", e);
+            ApiResponse::error(ErrorCode::MailDeliveryFailed, "验证邮件发送失败")
+        })
+}
+
+#[utoipa::path(
+    summary = "注册",
+    responses((
+        status = 200,
+        description = "data 为令牌；要求验证邮箱时为 user_id 和验证邮件的发送结果",
+        body = ApiEnvelope<TokenPair>,
+    )),
+)]
 #[post("/register")]
 async fn register(
-    pool: web::Data<SqlitePool>,
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    users: web::Data<UserService>,
     register_user: web::Json<RegisterUser>,
 ) -> impl Responder {
-    // 插入后返回用户 ID
-    match db::insert_user(&register_user.0, &pool).await {
-        Ok(user_id) => match generate_access_token(&user_id, &register_user.username) {
-            Ok(token) => ApiResponse::new("注册成功", ResponseData::Text(token)),
-            Err(_err) => ApiResponse::new("注册失败", ResponseData::Null),
-        },
-        Err(_) => ApiResponse::new("注册失败", ResponseData::Null),
+    let user_id = match users.register(&register_user).await {
+        Ok(user_id) => user_id,
+        Err(e) => return e.into_response("注册失败"),
+    };
+    // 验证邮件发送失败时用户可以重新发送，不影响注册结果
+    let mail_sent = send_verification(&user_id, &register_user.email, &pool, &app_state)
+        .await
+        .is_ok();
+    if email_verification_required() {
+        return ApiResponse::new(
+            "注册成功，请验证邮箱后登录",
+            ResponseData::Json(json!({
+                "user_id": user_id,
+                "email_verification_required": true,
+                "verification_mail_sent": mail_sent,
+            })),
+        );
+    }
+    let client = SessionClient::from_request(&req, &app_state);
+    match users
+        .start_session(&user_id, &register_user.username, &client)
+        .await
+    {
+        Ok(tokens) => ApiResponse::new("注册成功", ResponseData::Json(json!(tokens))),
+        Err(e) => e.into_response("注册失败"),
     }
 }
 
-// 刷新 Token
+/// 登录、注册和刷新令牌时返回的令牌
+#[derive(Serialize, ToSchema)]
+pub struct TokenPair {
+    pub access_token: String,
+    /// 固定为 Bearer
+    pub token_type: &'static str,
+    /// 访问令牌有效期（秒）
+    pub expires_in: usize,
+    pub refresh_token: String,
+    pub refresh_expires_at: i64,
+}
+
+// 刷新 Token：只接受刷新令牌，每次刷新都会轮换刷新令牌
+#[derive(Deserialize, ToSchema)]
+pub struct RefreshTokenRequest {
+    pub refresh_token: String,
+}
+
+#[utoipa::path(
+    summary = "刷新令牌",
+    responses((status = 200, body = ApiEnvelope<TokenPair>)),
+)]
 #[post("/refresh_token")]
-async fn refresh_token(bearer_token: BearerToken) -> impl Responder {
+async fn refresh_token(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    users: web::Data<UserService>,
+    request: web::Json<RefreshTokenRequest>,
+) -> impl Responder {
     info!("刷新令牌请求");
-
-    // 生成新的访问令牌
-    let access_token = match generate_access_token(&bearer_token.user_id, &bearer_token.username) {
-        Ok(token) => token,
-        Err(e) => {
-            warn!("生成新访问令牌失败: {}", e);
-            return ApiResponse::new(&e, ResponseData::Null);
+    let client = SessionClient::from_request(&req, &app_state);
+    match users.refresh(&request.refresh_token, &client).await {
+        Ok((user_id, session_id, tokens)) => {
+            AuditRecord::new(AuditEvent::TokenRefreshed, &req, &app_state)
+                .user(&user_id)
+                .detail(json!({ "session_id": session_id }))
+                .record(&pool)
+                .await;
+            ApiResponse::new("令牌刷新成功", ResponseData::Json(json!(tokens)))
         }
-    };
+        Err(e) => e.into_response("令牌刷新失败"),
+    }
+}
 
-    ApiResponse::new("令牌刷新成功", ResponseData::Text(access_token))
+// 退出登录：注销刷新令牌，all 为 true 时注销该用户在所有设备上的刷新令牌
+#[derive(Deserialize, ToSchema)]
+pub struct LogoutRequest {
+    pub refresh_token: String,
+    #[serde(default)]
+    pub all: bool,
+}
+
+#[utoipa::path(summary = "退出登录", responses((status = 200, body = ApiEnvelope<Value>)))]
+#[post("/logout")]
+async fn logout(
+    users: web::Data<UserService>,
+    request: web::Json<LogoutRequest>,
+) -> impl Responder {
+    match users.logout(&request.refresh_token, request.all).await {
+        Ok(_) => ApiResponse::new("已退出登录", ResponseData::Null),
+        Err(UserError::InvalidRefreshToken) => {
+            ApiResponse::error(ErrorCode::AuthInvalid, "刷新令牌无效或已注销")
+        }
+        Err(e) => e.into_response("退出登录失败"),
+    }
 }
 
 // 用户登录
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct LoginUser {
     pub username_or_email: String,
     pub password: String,
+    /// 已启用两步验证时必填：验证器生成的验证码或一次性恢复码
+    #[serde(default)]
+    pub totp_code: Option<String>,
 }
 
+#[utoipa::path(
+    summary = "登录",
+    responses(
+        (status = 200, body = ApiEnvelope<TokenPair>),
+        (
+            status = 401,
+            description = "密码错误，或已启用两步验证但缺少验证码（TWO_FACTOR_REQUIRED）、验证码错误",
+            body = ApiEnvelope<Value>,
+        ),
+        (
+            status = 429,
+            description = "同一账号或 IP 连续登录失败次数过多，暂时锁定（LOGIN_LOCKED），\
+                data 为锁定状态，锁定期间密码正确也会被拒绝",
+            body = ApiEnvelope<Lockout>,
+        ),
+    ),
+)]
 #[post("/login")]
-async fn login(pool: web::Data<SqlitePool>, login_user: web::Json<LoginUser>) -> impl Responder {
+async fn login(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    users: web::Data<UserService>,
+    login_user: web::Json<LoginUser>,
+) -> impl Responder {
     info!("用户请求登录");
-    match db::get_user_by_username_or_email(&login_user.username_or_email, &pool).await {
-        Ok(user) => {
-            debug!("用户信息: {:#?}", user);
-            if user.password == login_user.password {
-                match generate_access_token(&user.user_id, &user.username_or_email) {
-                    Ok(token) => ApiResponse::new("登录成功", ResponseData::Text(token)),
-                    Err(_err) => ApiResponse::new("登录失败", ResponseData::Null),
-                }
-            } else {
-                ApiResponse::new("登录失败", ResponseData::Null)
+    let mut audit = AuditRecord::new(AuditEvent::Login, &req, &app_state)
+        .detail(json!({ "username_or_email": login_user.username_or_email }));
+    let user = db::get_user_by_username_or_email(&login_user.username_or_email, &pool)
+        .await
+        .ok();
+    if let Some(user) = &user {
+        debug!("用户信息: {:#?}", user);
+        audit = audit.user(&user.user_id);
+    }
+    let login_lockout = &app_state.login_lockout;
+    let account = LoginLockout::account_key(
+        user.as_ref().map(|user| user.user_id.as_str()),
+        &login_user.username_or_email,
+    );
+    let ip = audit.ip.clone();
+
+    let mut response = if let Some(locked) = login_lockout.check(&account, ip.as_deref()) {
+        locked_response(locked)
+    } else {
+        match user {
+            Some(user) if user.password != login_user.password => {
+                ApiResponse::error(ErrorCode::InvalidCredentials, "登录失败")
+            }
+            Some(user) if user.disabled => {
+                ApiResponse::error(ErrorCode::AccountDisabled, "账号已被停用")
+            }
+            Some(user) if !user.email_verified && email_verification_required() => {
+                ApiResponse::error(ErrorCode::EmailNotVerified, "请先验证邮箱")
+            }
+            Some(user) => match check_two_factor(&user, &login_user, &pool).await {
+                Some(response) => response,
+                None => match users
+                    .start_session(
+                        &user.user_id,
+                        &user.username_or_email,
+                        &SessionClient::from_request(&req, &app_state),
+                    )
+                    .await
+                {
+                    Ok(tokens) => ApiResponse::new("登录成功", ResponseData::Json(json!(tokens))),
+                    Err(e) => e.into_response("登录失败"),
+                },
+            },
+            None => ApiResponse::error(ErrorCode::InvalidCredentials, "登录失败"),
+        }
+    };
+
+    // 密码或两步验证码错误计入失败次数，达到阈值的这次请求直接返回锁定状态
+    match response.code {
+        Some(ErrorCode::InvalidCredentials | ErrorCode::TwoFactorInvalid) => {
+            if let Some(locked) = login_lockout.record_failure(&account, ip.as_deref()) {
+                warn!("{} 连续登录失败，锁定 {} 秒", account, locked.retry_after);
+                response = locked_response(locked);
             }
         }
-        Err(_) => ApiResponse::new("登录失败", ResponseData::Null),
+        None => login_lockout.record_success(&account),
+        Some(_) => {}
+    }
+    audit.outcome(response.code).record(&pool).await;
+    response
+}
+
+fn locked_response(locked: Lockout) -> ApiResponse {
+    ApiResponse::error_with_data(
+        ErrorCode::LoginLocked,
+        &format!("登录失败次数过多，请在 {} 秒后重试", locked.retry_after),
+        ResponseData::Json(json!(locked)),
+    )
+}
+
+// 已启用两步验证时校验验证码，通过时返回 None
+async fn check_two_factor(
+    user: &User,
+    login_user: &LoginUser,
+    pool: &DbPool,
+) -> Option<ApiResponse> {
+    if !user.totp_enabled {
+        return None;
+    }
+    let Some(code) = login_user
+        .totp_code
+        .as_deref()
+        .filter(|code| !code.trim().is_empty())
+    else {
+        return Some(ApiResponse::error(
+            ErrorCode::TwoFactorRequired,
+            "请输入两步验证码",
+        ));
+    };
+    match two_factor::check_login_code(&user.user_id, code, pool).await {
+        Ok(true) => None,
+        Ok(false) => Some(ApiResponse::error(
+            ErrorCode::TwoFactorInvalid,
+            "验证码错误",
+        )),
+        Err(_) => Some(ApiResponse::error(ErrorCode::InternalError, "登录失败")),
     }
 }
 
 // 修改昵称
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct ChangeNickName {
     new_nickname: String,
 }
 
+/// 两次修改昵称之间的最短间隔（秒），可通过 `RENAME_COOLDOWN_SECS` 配置
+fn rename_cooldown() -> i64 {
+    env::var("RENAME_COOLDOWN_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(7 * 24 * 3600)
+}
+
+#[utoipa::path(
+    summary = "修改昵称",
+    params(IfMatch, ChangeNickName),
+    security(("bearer" = [])),
+    responses(
+        (
+            status = 200,
+            description = "data 为带有新昵称的访问令牌",
+            headers(("ETag" = String, description = "修改后的资料版本号")),
+            body = ApiEnvelope<String>,
+        ),
+        (status = 412, description = "版本号已过期，data 为当前版本号", body = ApiEnvelope<Value>),
+        (status = 428, description = "缺少 If-Match 请求头", body = ApiEnvelope<Value>),
+    ),
+)]
 #[put("/change_nickname")]
 async fn change_nickname(
-    pool: web::Data<SqlitePool>,
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
     bearer_token: BearerToken,
+    if_match: IfMatch,
     register_user: web::Query<ChangeNickName>,
-) -> impl Responder {
+) -> Versioned {
     info!("新昵称:{}", register_user.new_nickname);
-    match db::update_username(&bearer_token.user_id, &bearer_token.username, &pool).await {
-        Ok(_) => ApiResponse::new(
-            "昵称修改成功",
-            ResponseData::Text(
-                match generate_access_token(&bearer_token.user_id, &bearer_token.username) {
-                    Ok(token) => token,
-                    Err(_err) => _err,
-                },
+    let new_nickname = register_user.new_nickname.trim();
+    if new_nickname.is_empty() {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "昵称不能为空").into();
+    }
+
+    // 冷却期内不允许再次修改
+    let now = chrono::Utc::now().timestamp();
+    match db::get_last_rename_at(&bearer_token.user_id, &pool).await {
+        Ok(Some(last)) if now - last < rename_cooldown() => {
+            let remaining = rename_cooldown() - (now - last);
+            return ApiResponse::error_with_data(
+                ErrorCode::RenameCooldown,
+                &format!("修改昵称过于频繁，请在 {} 秒后重试", remaining),
+                ResponseData::Number(remaining),
+            )
+            .into();
+        }
+        Ok(_) => {}
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "昵称修改失败").into(),
+    }
+
+    let user = match db::get_user_by_id(&bearer_token.user_id, &pool).await {
+        Ok(user) => user,
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "昵称修改失败").into(),
+    };
+    match db::update_username(
+        &bearer_token.user_id,
+        &user.username,
+        new_nickname,
+        if_match.0,
+        &pool,
+    )
+    .await
+    {
+        Ok(Some(version)) => Versioned::new(
+            ApiResponse::new(
+                "昵称修改成功",
+                ResponseData::Text(
+                    match generate_access_token(
+                        &bearer_token.user_id,
+                        new_nickname,
+                        bearer_token.session_id.as_deref(),
+                        false,
+                        bearer_token.role,
+                        &app_state.config.auth,
+                    ) {
+                        Ok(token) => token,
+                        Err(_err) => _err,
+                    },
+                ),
             ),
+            version,
         ),
-        Err(_) => ApiResponse::new("昵称修改失败", ResponseData::Null),
+        Ok(None) => stale_profile(&bearer_token.user_id, &pool).await,
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "昵称修改失败").into(),
+    }
+}
+
+// 资料版本不一致时返回 412 和当前版本号
+async fn stale_profile(user_id: &str, pool: &DbPool) -> Versioned {
+    match db::get_user_by_id(user_id, pool).await {
+        Ok(user) => Versioned::stale(user.version),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取用户信息失败").into(),
     }
 }
 
+// 查看用户的历史昵称（管理员）
+#[utoipa::path(
+    summary = "查看用户的历史昵称（管理员）",
+    security(("bearer" = [])),
+    responses((status = 200, body = ApiEnvelope<Vec<UsernameChange>>)),
+)]
+#[get("/username_history/{user_id}")]
+async fn username_history(
+    _admin: AdminReadToken,
+    pool: web::Data<DbPool>,
+    user_id: web::Path<String>,
+) -> impl Responder {
+    match db::get_username_history(&user_id, &pool).await {
+        Ok(history) => ApiResponse::new("获取昵称历史成功", ResponseData::Json(json!(history))),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取昵称历史失败"),
+    }
+}
+
+fn policy_violation(violation: PolicyViolation) -> ApiResponse {
+    ApiResponse::error_with_data(
+        ErrorCode::PolicyViolation,
+        "内容不符合服务器策略",
+        ResponseData::Json(json!(violation)),
+    )
+}
+
+/// 头像允许的图片格式（根据文件头识别，不信任请求头中的 Content-Type）
+const HEAD_MIME_TYPES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "image/bmp",
+];
+
+// 把头像写入临时文件并识别图片格式，校验通过后移入存储，返回识别出的 MIME 类型；
+// 不是图片或不符合内容策略时删除临时文件
+async fn save_head(
+    payload: web::Payload,
+    file_path: &str,
+    key: &str,
+    content_policy: &ContentPolicy,
+) -> Result<(&'static str, i64), ApiResponse> {
+    let max_size = content_policy.max_size("image");
+    let size = match save_payload_with_dirs(payload, file_path, max_size).await {
+        Ok(size) => size,
+        Err(SavePayloadError::TooLarge(size)) => {
+            return Err(policy_violation(PolicyViolation::TooLarge {
+                content_type: "image".to_string(),
+                size,
+                max_size: max_size.unwrap_or_default(),
+            }));
+        }
+        Err(SavePayloadError::Other(e)) => {
+            warn!("保存头像失败: {}", e);
+            return Err(ApiResponse::error(ErrorCode::InternalError, "头像保存失败"));
+        }
+    };
+    let checked = match binary::sniff_file(file_path).await {
+        Ok(Some(mime_type)) if HEAD_MIME_TYPES.contains(&mime_type) => content_policy
+            .check_blob("image", mime_type, size)
+            .map(|_| (mime_type, size as i64))
+            .map_err(policy_violation),
+        Ok(_) => Err(ApiResponse::error(
+            ErrorCode::InvalidRequest,
+            "头像必须是 PNG、JPEG、GIF、WebP 或 BMP 图片",
+        )),
+        Err(e) => {
+            warn!("读取头像失败: {}", e);
+            Err(ApiResponse::error(ErrorCode::InternalError, "头像保存失败"))
+        }
+    };
+    if checked.is_err() {
+        let _ = tokio::fs::remove_file(file_path).await;
+        return checked;
+    }
+    if let Err(e) = blob_store().put_file(key, Path::new(file_path)).await {
+        warn!("保存头像失败: {}", e);
+        let _ = tokio::fs::remove_file(file_path).await;
+        return Err(ApiResponse::error(ErrorCode::InternalError, "头像保存失败"));
+    }
+    checked
+}
+
+#[utoipa::path(
+    summary = "修改头像",
+    description = "请求体为 PNG、JPEG、GIF、WebP 或 BMP 图片，格式根据文件头识别",
+    params(IdempotencyKey, IfMatch),
+    request_body(content(
+        ("image/png"),
+        ("image/jpeg"),
+        ("image/gif"),
+        ("image/webp"),
+        ("image/bmp"),
+    )),
+    security(("bearer" = [])),
+    responses(
+        (
+            status = 200,
+            description = "data 为新的访问令牌",
+            headers(("ETag" = String, description = "修改后的资料版本号")),
+            body = ApiEnvelope<String>,
+        ),
+        (status = 412, description = "版本号已过期，data 为当前版本号", body = ApiEnvelope<Value>),
+        (status = 428, description = "缺少 If-Match 请求头", body = ApiEnvelope<Value>),
+    ),
+)]
 #[put("/change_head")]
 async fn change_head(
-    pool: web::Data<SqlitePool>,
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
     bearer_token: BearerToken,
+    idempotency_key: IdempotencyKey,
+    if_match: IfMatch,
+    req: HttpRequest,
     payload: web::Payload,
-) -> impl Responder {
+) -> Versioned {
     info!("修改头像");
+    let header_str = |name| {
+        req.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("")
+    };
+    let mime_type = header_str(header::CONTENT_TYPE);
+    // Content-Length 只用于提前拒绝，实际大小在读取请求体时校验
+    let size = header_str(header::CONTENT_LENGTH).parse().unwrap_or(0);
+    if let Err(violation) = app_state.content_policy.check_blob("image", mime_type, size) {
+        return policy_violation(violation).into();
+    }
+    // 请求体是流式读取的，以文件类型和大小作为指纹
+    let fingerprint = idempotency::fingerprint(&req, format!("{}\n{}", mime_type, size).as_bytes());
+    match idempotency_key
+        .begin(&bearer_token.user_id, &fingerprint, &pool)
+        .await
+    {
+        Ok(Idempotency::Proceed) => {}
+        Ok(Idempotency::Replay(response)) | Err(response) => return response.into(),
+    }
+
+    // 修改成功后删除旧头像文件，避免清除用户数据时遗漏
+    let old_head_uri = db::get_user_by_id(&bearer_token.user_id, &pool)
+        .await
+        .ok()
+        .and_then(|user| user.head_uri);
+    // 新头像替换旧头像，存储占用只增加两者的差值
+    let old_head_bytes = db::get_head_bytes(&bearer_token.user_id, &pool)
+        .await
+        .unwrap_or(0);
     let uuid = uuid::Uuid::new_v4();
-    // 将_data保存到本地
-    let file_path = format!("./static/heads/{}", uuid);
-    match save_payload_with_dirs(payload, &file_path).await {
-        Ok(_) => match db::update_head_uri(&bearer_token.user_id, &uuid.to_string(), &pool).await {
-            Ok(_) => ApiResponse::new(
-                "头像修改成功",
-                ResponseData::Text(
-                    match generate_access_token(&bearer_token.user_id, &bearer_token.username) {
-                        Ok(token) => token,
-                        Err(_err) => _err,
-                    },
-                ),
-            ),
-            Err(_) => ApiResponse::new("头像修改失败", ResponseData::Null),
+    // 先写入上传目录下的临时文件
+    let file_path = format!("{}/{}/{}.head", UPLOADS_DIR, bearer_token.user_id, uuid);
+    let head_key = storage::head_key(&uuid.to_string());
+    let mut version = None;
+    let saved = match save_head(payload, &file_path, &head_key, &app_state.content_policy).await {
+        Ok((head_mime, head_bytes)) => {
+            match check_quota(&bearer_token.user_id, head_bytes - old_head_bytes, &**pool).await {
+                Ok(()) => Ok((head_mime, head_bytes)),
+                Err(response) => {
+                    let _ = blob_store().delete(&head_key).await;
+                    Err(response)
+                }
+            }
+        }
+        Err(response) => Err(response),
+    };
+    let response = match saved {
+        Ok((head_mime, head_bytes)) => match db::update_head_uri(
+            &bearer_token.user_id,
+            &uuid.to_string(),
+            head_mime,
+            head_bytes,
+            if_match.0,
+            &pool,
+        )
+        .await
+        {
+            Ok(Some(new_version)) => {
+                version = Some(new_version);
+                if let Some(old_head_uri) = old_head_uri {
+                    let _ = blob_store().delete(&storage::head_key(&old_head_uri)).await;
+                }
+                ApiResponse::new(
+                    "头像修改成功",
+                    ResponseData::Text(
+                        match generate_access_token(
+                            &bearer_token.user_id,
+                            &bearer_token.username,
+                            bearer_token.session_id.as_deref(),
+                            false,
+                            bearer_token.role,
+                            &app_state.config.auth,
+                        ) {
+                            Ok(token) => token,
+                            Err(_err) => _err,
+                        },
+                    ),
+                )
+            }
+            Ok(None) => {
+                // 版本冲突，删除刚保存的文件并释放幂等键
+                let _ = blob_store().delete(&head_key).await;
+                let conflict = ApiResponse::error(ErrorCode::VersionConflict, "头像修改失败");
+                idempotency_key
+                    .finish(&bearer_token.user_id, conflict, &pool)
+                    .await;
+                return stale_profile(&bearer_token.user_id, &pool).await;
+            }
+            Err(_) => ApiResponse::error(ErrorCode::InternalError, "头像修改失败"),
         },
-        Err(_) => todo!(),
+        Err(response) => response,
+    };
+    let response = idempotency_key
+        .finish(&bearer_token.user_id, response, &pool)
+        .await;
+    match version {
+        Some(version) => Versioned::new(response, version),
+        None => response.into(),
     }
 }
 
-// 修改密码
-#[derive(Deserialize)]
+// 修改密码（新密码放在请求体中，避免出现在访问日志和代理日志里）
+#[derive(Deserialize, ToSchema)]
 pub struct ChangePassword {
     new_password: String,
 }
 
+#[utoipa::path(
+    summary = "修改密码",
+    security(("bearer" = [])),
+    responses((status = 200, body = ApiEnvelope<TokenPair>)),
+)]
 #[put("/change_password")]
 async fn change_password(
-    pool: web::Data<SqlitePool>,
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    users: web::Data<UserService>,
     bearer_token: BearerToken,
-    change_password: web::Query<ChangePassword>,
+    change_password: web::Json<ChangePassword>,
 ) -> impl Responder {
-    info!("新密码:{}", change_password.new_password);
-    match db::update_password(&bearer_token.user_id, &change_password.new_password, &pool).await {
-        Ok(_) => ApiResponse::new(
-            "密码修改成功",
-            ResponseData::Text(
-                match generate_access_token(&bearer_token.user_id, &bearer_token.username) {
-                    Ok(token) => token,
-                    Err(_err) => _err,
-                },
-            ),
-        ),
-        Err(_) => ApiResponse::new("密码修改失败", ResponseData::Null),
+    let client = SessionClient::from_request(&req, &app_state);
+    let tokens = match users
+        .change_password(
+            &bearer_token.user_id,
+            &bearer_token.username,
+            &change_password.new_password,
+            &client,
+        )
+        .await
+    {
+        Ok(tokens) => tokens,
+        Err(e) => return e.into_response("密码修改失败"),
+    };
+    AuditRecord::new(AuditEvent::PasswordChanged, &req, &app_state)
+        .user(&bearer_token.user_id)
+        .record(&pool)
+        .await;
+    ApiResponse::new("密码修改成功", ResponseData::Json(json!(tokens)))
+}
+
+// 注销账号，需要再次输入密码确认
+#[derive(Deserialize, ToSchema)]
+pub struct DeleteAccount {
+    password: String,
+}
+
+// 注销账号：断开所有会话后在事务中删除账号及其剪贴板、设备、分享链接和刷新令牌等全部数据，
+// 再删除磁盘上的附件和头像，返回签名的删除报告
+#[utoipa::path(
+    summary = "注销账号",
+    security(("bearer" = [])),
+    responses((status = 200, description = "data 为签名的删除报告", body = ApiEnvelope<Value>)),
+)]
+#[delete("/account")]
+async fn delete_account(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    delete_account: web::Json<DeleteAccount>,
+) -> impl Responder {
+    let user_id = &bearer_token.user_id;
+    match db::get_user_password(user_id, &pool).await {
+        Ok(Some(password)) if password == delete_account.password => {}
+        Ok(Some(_)) => return ApiResponse::error(ErrorCode::InvalidCredentials, "密码错误"),
+        Ok(None) => return ApiResponse::error(ErrorCode::NotFound, "用户不存在"),
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "账号注销失败"),
+    }
+
+    let key = match purge_signing_key() {
+        Ok(key) => key,
+        Err(response) => return response,
+    };
+    let mut report = PurgeReport::new("user", user_id);
+    if let Err(e) = purge_user(&mut report, &pool, &app_state).await {
+        warn!("delete account {} failed: {}", user_id, e);
+        return ApiResponse::error(ErrorCode::InternalError, "账号注销失败");
     }
+    info!("用户 {} 已注销账号（报告 {}）", user_id, report.id);
+
+    let signature = report.sign(&key);
+    ApiResponse::new(
+        "账号已注销",
+        ResponseData::Json(json!({
+            "report": report,
+            "signature": signature,
+            "algorithm": "blake3-keyed",
+        })),
+    )
 }
 
-#[derive(Serialize, Deserialize)]
+/// 昵称修改记录
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct UsernameChange {
+    pub old_username: String,
+    pub new_username: String,
+    pub changed_at: i64,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct UserInfo {
     pub username: String,
     pub email: String,
-    pub head_uri: String,
+    pub head_uri: Option<String>,
+    /// 资料版本号，修改资料时通过 If-Match 携带
+    pub version: i64,
 }
 
 // 获取用户信息
+#[utoipa::path(
+    summary = "获取用户信息",
+    security(("bearer" = [])),
+    responses((
+        status = 200,
+        headers(("ETag" = String, description = "资料版本号")),
+        body = ApiEnvelope<UserInfo>,
+    )),
+)]
 #[get("/get_user_info")]
-async fn get_user_info(pool: web::Data<SqlitePool>, bearer_token: BearerToken) -> impl Responder {
+async fn get_user_info(pool: web::Data<DbPool>, bearer_token: BearerToken) -> Versioned {
     info!("获取用户信息请求");
     match db::get_user_by_id(&bearer_token.user_id, &pool).await {
-        Ok(user) => ApiResponse::new("获取用户信息成功", ResponseData::Json(json!(user))),
-        Err(_) => ApiResponse::new("获取用户信息失败", ResponseData::Null),
+        Ok(user) => Versioned::new(
+            ApiResponse::new("获取用户信息成功", ResponseData::Json(json!(user))),
+            user.version,
+        ),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取用户信息失败").into(),
+    }
+}
+
+// 验证邮箱（注册邮件中的验证码，无需登录）
+#[derive(Deserialize, ToSchema)]
+pub struct VerifyEmail {
+    pub token: String,
+}
+
+#[utoipa::path(summary = "验证邮箱", responses((status = 200, body = ApiEnvelope<Value>)))]
+#[post("/verify_email")]
+async fn verify_email(
+    pool: web::Data<DbPool>,
+    verify: web::Json<VerifyEmail>,
+) -> impl Responder {
+    match db::verify_email(verify.token.trim(), &pool).await {
+        Ok(Some(user_id)) => {
+            info!("用户 {} 邮箱验证成功", user_id);
+            ApiResponse::new("邮箱验证成功", ResponseData::Null)
+        }
+        Ok(None) => ApiResponse::error(ErrorCode::LinkExpired, "验证码无效或已过期"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "邮箱验证失败"),
+    }
+}
+
+// 重新发送验证邮件（无需登录）
+//
+// 账号不存在或已验证时同样返回成功，避免泄露账号是否存在
+#[derive(Deserialize, ToSchema)]
+pub struct ResendVerification {
+    pub username_or_email: String,
+}
+
+#[utoipa::path(
+    summary = "重新发送验证邮件",
+    responses((status = 200, body = ApiEnvelope<Value>)),
+)]
+#[post("/resend_verification")]
+async fn resend_verification(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    resend: web::Json<ResendVerification>,
+) -> impl Responder {
+    let sent = || ApiResponse::new("如果该账号存在且邮箱未验证，验证邮件已发送", ResponseData::Null);
+    let user = match db::get_user_by_username_or_email(resend.username_or_email.trim(), &pool).await
+    {
+        Ok(user) => user,
+        Err(sqlx::Error::RowNotFound) => return sent(),
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "验证邮件发送失败"),
+    };
+    if user.email_verified {
+        return sent();
+    }
+    match send_verification(&user.user_id, &user.email, &pool, &app_state).await {
+        Ok(()) => sent(),
+        Err(response) => response,
+    }
+}
+
+/// 找回密码令牌有效期（秒）
+const PASSWORD_RESET_TTL: i64 = 3600;
+
+// 找回密码：向账号邮箱发送一次性重置令牌（无需登录）
+//
+// 账号不存在或已停用时同样返回成功，避免泄露账号是否存在
+#[derive(Deserialize, ToSchema)]
+pub struct ForgotPassword {
+    pub username_or_email: String,
+}
+
+#[utoipa::path(summary = "找回密码", responses((status = 200, body = ApiEnvelope<Value>)))]
+#[post("/forgot_password")]
+async fn forgot_password(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    forgot: web::Json<ForgotPassword>,
+) -> impl Responder {
+    let sent = || ApiResponse::new("如果该账号存在，重置密码邮件已发送", ResponseData::Null);
+    let user = match db::get_user_by_username_or_email(forgot.username_or_email.trim(), &pool).await
+    {
+        Ok(user) => user,
+        Err(sqlx::Error::RowNotFound) => return sent(),
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "重置密码邮件发送失败"),
+    };
+    if user.disabled {
+        return sent();
+    }
+    // 除按 IP 限流外再按账号限流，避免从多个 IP 向同一邮箱批量发信
+    //
+    // 超出限制时不发信，但返回相同的结果，避免据此判断账号是否存在
+    if app_state
+        .rate_limiters
+        .auth
+        .check(&format!("password_reset:{}", user.user_id))
+        .is_err()
+    {
+        warn!("用户 {} 请求找回密码过于频繁，跳过发送", user.user_id);
+        return sent();
+    }
+
+    let token = uuid::Uuid::new_v4().simple().to_string();
+    let expires_at = chrono::Utc::now().timestamp() + PASSWORD_RESET_TTL;
+    let token_hash = hash_refresh_token(&token);
+    if db::insert_password_reset_token(&token_hash, &user.user_id, expires_at, &pool)
+        .await
+        .is_err()
+    {
+        return ApiResponse::error(ErrorCode::InternalError, "重置密码邮件发送失败");
     }
+    info!("用户 {} 请求找回密码", user.user_id);
+    let mailed = app_state
+        .mailer
+        .send(
+            &user.email,
+            "重置 ClipFocus 密码",
+            &format!(
+                "请在 1 小时内于客户端中输入以下令牌重置密码:\n{}\n如果不是你本人操作，请忽略本邮件。",
+                token
+            ),
+        )
+        .await;
+    if let Err(e) = mailed {
+        warn!("发送重置密码邮件失败: {}", e);
+        return ApiResponse::error(ErrorCode::MailDeliveryFailed, "重置密码邮件发送失败");
+    }
+    sent()
+}
+
+// 使用邮件中的令牌重置密码，重置后所有设备需要重新登录
+#[derive(Deserialize, ToSchema)]
+pub struct ResetPassword {
+    pub token: String,
+    pub new_password: String,
+}
+
+#[utoipa::path(summary = "重置密码", responses((status = 200, body = ApiEnvelope<Value>)))]
+#[post("/reset_password")]
+async fn reset_password(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    reset: web::Json<ResetPassword>,
+) -> impl Responder {
+    if reset.new_password.is_empty() {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "新密码不能为空");
+    }
+    let token_hash = hash_refresh_token(reset.token.trim());
+    match db::reset_password(&token_hash, &reset.new_password, &pool).await {
+        Ok(Some(user_id)) => {
+            info!("用户 {} 已重置密码", user_id);
+            AuditRecord::new(AuditEvent::PasswordChanged, &req, &app_state)
+                .user(&user_id)
+                .detail(json!({ "method": "reset" }))
+                .record(&pool)
+                .await;
+            ApiResponse::new("密码重置成功，请重新登录", ResponseData::Null)
+        }
+        Ok(None) => ApiResponse::error(ErrorCode::LinkExpired, "重置令牌无效或已过期"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "重置密码失败"),
+    }
+}
+
+/// 邮箱修改确认链接有效期（秒）
+const EMAIL_CHANGE_TTL: i64 = 24 * 3600;
+
+// 修改邮箱：向新邮箱发送确认链接，确认后才生效
+#[derive(Deserialize, ToSchema)]
+pub struct ChangeEmail {
+    new_email: String,
+}
+
+#[utoipa::path(
+    summary = "修改邮箱",
+    security(("bearer" = [])),
+    responses((status = 200, body = ApiEnvelope<Value>)),
+)]
+#[post("/change_email")]
+async fn change_email(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    change_email: web::Json<ChangeEmail>,
+) -> impl Responder {
+    let new_email = change_email.new_email.trim().to_string();
+    if !new_email.contains('@') {
+        return ApiResponse::error(ErrorCode::InvalidRequest, "邮箱格式无效");
+    }
+    match db::email_exists(&new_email, &pool).await {
+        Ok(false) => {}
+        Ok(true) => return ApiResponse::error(ErrorCode::DuplicateEmail, "邮箱已被使用"),
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "修改邮箱失败"),
+    }
+    let old_email = match db::get_user_by_id(&bearer_token.user_id, &pool).await {
+        Ok(user) => user.email,
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "修改邮箱失败"),
+    };
+
+    let token = uuid::Uuid::new_v4().simple().to_string();
+    let expires_at = chrono::Utc::now().timestamp() + EMAIL_CHANGE_TTL;
+    if db::insert_email_change(&token, &bearer_token.user_id, &new_email, expires_at, &pool)
+        .await
+        .is_err()
+    {
+        return ApiResponse::error(ErrorCode::InternalError, "修改邮箱失败");
+    }
+
+    info!("用户 {} 请求修改邮箱", bearer_token.user_id);
+    let link = format!(
+        "{}/api/v1/user/confirm_email?token={}",
+        public_base_url(),
+        token
+    );
+    let confirm = app_state
+        .mailer
+        .send(
+            &new_email,
+            "确认修改 ClipFocus 邮箱",
+            &format!("请在 24 小时内打开以下链接确认修改邮箱:\n{}", link),
+        )
+        .await;
+    if let Err(e) = confirm {
+        warn!("发送邮箱确认邮件失败: {}", e);
+        return ApiResponse::error(ErrorCode::MailDeliveryFailed, "确认邮件发送失败");
+    }
+    // 通知旧邮箱，发送失败不影响流程
+    let notice = app_state
+        .mailer
+        .send(
+            &old_email,
+            "ClipFocus 邮箱修改提醒",
+            &format!(
+                "你的账号正在将邮箱修改为 {}，如果不是你本人操作，请立即修改密码。",
+                new_email
+            ),
+        )
+        .await;
+    if let Err(e) = notice {
+        warn!("发送邮箱修改提醒失败: {}", e);
+    }
+
+    ApiResponse::new("确认邮件已发送", ResponseData::Null)
+}
+
+// 确认修改邮箱（无需登录），客户端以 JSON 提交，确认页面以表单提交
+#[derive(Deserialize, IntoParams, ToSchema)]
+#[into_params(parameter_in = Query)]
+pub struct ConfirmEmail {
+    token: String,
+}
+
+// 邮件中的链接打开确认页面，由用户点击按钮后以 POST 提交，避免邮件扫描器预取链接时自动确认
+#[utoipa::path(
+    summary = "邮箱修改确认页面",
+    params(ConfirmEmail),
+    responses((status = 200, description = "HTML 页面", content_type = "text/html")),
+)]
+#[get("/confirm_email")]
+async fn confirm_email_page(confirm: web::Query<ConfirmEmail>) -> HttpResponse {
+    // 令牌只包含十六进制字符，其他内容不写入页面
+    let token = if confirm.token.chars().all(|c| c.is_ascii_hexdigit()) {
+        confirm.token.as_str()
+    } else {
+        ""
+    };
+    html_page(
+        "确认修改邮箱",
+        &format!(
+            r#"<form method="post" action="confirm_email">
+<input type="hidden" name="token" value="{}">
+<p>点击下方按钮确认修改 ClipFocus 账号邮箱。</p>
+<button type="submit">确认修改</button>
+</form>"#,
+            token
+        ),
+    )
+}
+
+#[utoipa::path(
+    summary = "确认修改邮箱",
+    request_body(content(
+        (ConfirmEmail = "application/json"),
+        (ConfirmEmail = "application/x-www-form-urlencoded"),
+    )),
+    responses((
+        status = 200,
+        description = "以 JSON 提交时返回 JSON，以表单提交时返回 HTML 页面",
+        body = ApiEnvelope<Value>,
+    )),
+)]
+#[post("/confirm_email")]
+async fn confirm_email(
+    pool: web::Data<DbPool>,
+    confirm: Either<web::Json<ConfirmEmail>, web::Form<ConfirmEmail>>,
+) -> Either<ApiResponse, HttpResponse> {
+    match confirm {
+        Either::Left(confirm) => Either::Left(confirm_email_change(&confirm.token, &pool).await),
+        Either::Right(confirm) => {
+            let response = confirm_email_change(&confirm.token, &pool).await;
+            Either::Right(html_page(&response.message, &format!("<p>{}</p>", response.message)))
+        }
+    }
+}
+
+async fn confirm_email_change(token: &str, pool: &DbPool) -> ApiResponse {
+    let (user_id, new_email) = match db::take_email_change(token.trim(), pool).await {
+        Ok(Some(change)) => change,
+        Ok(None) => return ApiResponse::error(ErrorCode::LinkExpired, "确认链接无效或已过期"),
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "确认邮箱失败"),
+    };
+
+    // 发送确认邮件后邮箱可能已被其他账号使用，users.email 的唯一约束会拒绝更新
+    match db::update_email(&user_id, &new_email, pool).await {
+        Ok(_) => {
+            info!("用户 {} 邮箱修改成功", user_id);
+            ApiResponse::new("邮箱修改成功", ResponseData::Null)
+        }
+        Err(_) => ApiResponse::error(ErrorCode::DuplicateEmail, "邮箱已被使用"),
+    }
+}
+
+// 浏览器中打开的简单页面（邮件链接），title 和 body 由服务端生成
+fn html_page(title: &str, body: &str) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .body(format!(
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{title}</title></head>\n\
+             <body><h1>{title}</h1>\n{body}\n</body></html>\n"
+        ))
 }
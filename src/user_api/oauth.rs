@@ -0,0 +1,837 @@
+use actix_web::{
+    Either, HttpRequest, HttpResponse, HttpResponseBuilder, Responder,
+    cookie::{Cookie, SameSite, time::Duration},
+    delete, get,
+    http::header,
+    post, web,
+};
+use log::{info, warn};
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use url::Url;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    audit::{AuditEvent, AuditRecord},
+    config::{OAuthClientConfig, OAuthConfig},
+    spatial_api::models::AppState,
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiEnvelope, ApiResponse, ErrorCode, ResponseData},
+    },
+    user_api::{
+        User, auth::BearerToken, registration_open, service::UserService, sessions::SessionClient,
+    },
+};
+
+/// 浏览器登录流程的 state 同时保存在该 Cookie 中，回调时与 state 参数比较，
+/// 防止他人发起的登录流程在当前浏览器中完成
+const STATE_COOKIE: &str = "clipfocus_oauth_state";
+
+/// 第三方登录提供方
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum OAuthProvider {
+    Github,
+    Google,
+}
+
+impl OAuthProvider {
+    pub const ALL: [OAuthProvider; 2] = [OAuthProvider::Github, OAuthProvider::Google];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OAuthProvider::Github => "github",
+            OAuthProvider::Google => "google",
+        }
+    }
+
+    fn authorize_url(&self) -> &'static str {
+        match self {
+            OAuthProvider::Github => "https://github.com/login/oauth/authorize",
+            OAuthProvider::Google => "https://accounts.google.com/o/oauth2/v2/auth",
+        }
+    }
+
+    fn scope(&self) -> &'static str {
+        match self {
+            OAuthProvider::Github => "read:user user:email",
+            OAuthProvider::Google => "openid email profile",
+        }
+    }
+
+    /// 提供方的客户端配置，未配置或未启用 `oauth` feature 时为 None
+    pub fn client<'a>(&self, config: &'a OAuthConfig) -> Option<&'a OAuthClientConfig> {
+        if !cfg!(feature = "oauth") {
+            return None;
+        }
+        match self {
+            OAuthProvider::Github => config.github.as_ref(),
+            OAuthProvider::Google => config.google.as_ref(),
+        }
+    }
+}
+
+/// 已配置的提供方，用于服务器信息
+pub fn configured_providers(config: &OAuthConfig) -> Vec<OAuthProvider> {
+    OAuthProvider::ALL
+        .into_iter()
+        .filter(|provider| provider.client(config).is_some())
+        .collect()
+}
+
+/// 提供方返回的用户身份
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExternalIdentity {
+    /// 提供方的用户 ID
+    pub subject: String,
+    pub username: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+}
+
+/// 已绑定的外部身份
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LinkedIdentity {
+    pub provider: String,
+    pub email: Option<String>,
+    pub created_at: i64,
+}
+
+/// 开始第三方登录的参数
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OAuthStart {
+    /// 登录完成后的回跳地址（需要与 `auth.oauth.allowed_redirects` 中的某个地址匹配），
+    /// 令牌或错误附加在片段（#）中；未指定时回调接口直接返回 JSON
+    redirect_uri: Option<String>,
+}
+
+// 回跳地址需要与某个允许的地址协议、主机和端口相同，路径与之相同，
+// 或允许的路径以 / 结尾且回跳地址的路径以其开头；不能包含用户信息和片段。返回规范化后的地址
+fn allowed_redirect(redirect_uri: &str, allowed: &[String]) -> Option<String> {
+    let uri = Url::parse(redirect_uri).ok()?;
+    if !uri.username().is_empty() || uri.password().is_some() || uri.fragment().is_some() {
+        return None;
+    }
+    allowed
+        .iter()
+        .filter_map(|allowed| Url::parse(allowed).ok())
+        .any(|allowed| {
+            allowed.scheme() == uri.scheme()
+                && allowed.host() == uri.host()
+                && allowed.port_or_known_default() == uri.port_or_known_default()
+                && (allowed.path() == uri.path()
+                    || (allowed.path().ends_with('/') && uri.path().starts_with(allowed.path())))
+        })
+        .then(|| uri.into())
+}
+
+// 保存登录流程并生成提供方的授权地址，返回 (授权地址, state)
+async fn start_flow(
+    provider: OAuthProvider,
+    user_id: Option<&str>,
+    start: &OAuthStart,
+    config: &OAuthConfig,
+    pool: &DbPool,
+) -> Result<(String, String), ApiResponse> {
+    let Some(client) = provider.client(config) else {
+        return Err(ApiResponse::error(ErrorCode::NotFound, "未配置该登录方式"));
+    };
+    let redirect_uri = match &start.redirect_uri {
+        Some(uri) => match allowed_redirect(uri, &config.allowed_redirects) {
+            Some(uri) => Some(uri),
+            None => {
+                return Err(ApiResponse::error(
+                    ErrorCode::InvalidRequest,
+                    "不允许的回跳地址",
+                ));
+            }
+        },
+        None => None,
+    };
+    let state = uuid::Uuid::new_v4().simple().to_string();
+    let expires_at = chrono::Utc::now().timestamp() + config.state_ttl_secs;
+    db::insert_oauth_state(
+        &state,
+        provider.as_str(),
+        user_id,
+        redirect_uri.as_deref(),
+        expires_at,
+        pool,
+    )
+    .await
+    .map_err(|_| ApiResponse::error(ErrorCode::InternalError, "发起登录失败"))?;
+    let encode = |value: &str| utf8_percent_encode(value, NON_ALPHANUMERIC).to_string();
+    let url = format!(
+        "{}?response_type=code&client_id={}&redirect_uri={}&scope={}&state={}",
+        provider.authorize_url(),
+        encode(&client.client_id),
+        encode(&client.redirect_uri),
+        encode(provider.scope()),
+        state
+    );
+    Ok((url, state))
+}
+
+#[utoipa::path(
+    summary = "第三方登录",
+    description = "在浏览器中打开，重定向到提供方的授权页面，授权后回到 /user/oauth/{provider}/callback",
+    params(("provider" = OAuthProvider, Path), OAuthStart),
+    responses(
+        (status = 302, description = "重定向到提供方的授权页面"),
+        (status = 404, description = "未配置该提供方", body = ApiEnvelope<Value>),
+    ),
+)]
+#[get("/oauth/{provider}/login")]
+async fn login(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    provider: web::Path<OAuthProvider>,
+    start: web::Query<OAuthStart>,
+) -> Either<HttpResponse, ApiResponse> {
+    let config = &app_state.config.auth.oauth;
+    match start_flow(*provider, None, &start, config, &pool).await {
+        Ok((url, state)) => {
+            // Cookie 只发送到同一提供方的回调地址
+            let path = req.path().strip_suffix("login").unwrap_or("/").to_string();
+            let cookie = Cookie::build(STATE_COOKIE, state)
+                .path(path)
+                .http_only(true)
+                .secure(req.connection_info().scheme() == "https")
+                .same_site(SameSite::Lax)
+                .max_age(Duration::seconds(config.state_ttl_secs))
+                .finish();
+            Either::Left(found(&url).cookie(cookie).finish())
+        }
+        Err(response) => Either::Right(response),
+    }
+}
+
+#[utoipa::path(
+    summary = "绑定第三方账号",
+    description = "返回提供方的授权地址，客户端在浏览器中打开；授权后回调返回确认码 link_code，\
+                   客户端使用确认码调用 /user/oauth/{provider}/link/confirm 后外部身份绑定到当前账号",
+    params(("provider" = OAuthProvider, Path), OAuthStart),
+    security(("bearer" = [])),
+    responses((status = 200, description = "data 为 {authorize_url}", body = ApiEnvelope<Value>)),
+)]
+#[post("/oauth/{provider}/link")]
+async fn link(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    provider: web::Path<OAuthProvider>,
+    start: web::Query<OAuthStart>,
+) -> impl Responder {
+    let config = &app_state.config.auth.oauth;
+    match start_flow(
+        *provider,
+        Some(&bearer_token.user_id),
+        &start,
+        config,
+        &pool,
+    )
+    .await
+    {
+        Ok((url, _)) => ApiResponse::new(
+            "请在浏览器中完成授权",
+            ResponseData::Json(json!({ "authorize_url": url })),
+        ),
+        Err(response) => response,
+    }
+}
+
+/// 提供方回调的参数
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OAuthCallback {
+    code: Option<String>,
+    state: String,
+    /// 用户拒绝授权等情况下由提供方附带
+    error: Option<String>,
+}
+
+#[utoipa::path(
+    summary = "第三方登录回调",
+    description = "外部身份已绑定时登录对应账号；提供方确认过的邮箱与已验证邮箱的账号相同时自动绑定；\
+                   否则在开放注册时创建新账号。已启用两步验证的账号需要使用密码登录",
+    params(("provider" = OAuthProvider, Path), OAuthCallback),
+    responses(
+        (status = 200, description = "未指定回跳地址时返回令牌", body = ApiEnvelope<super::TokenPair>),
+        (status = 302, description = "重定向到回跳地址，片段中为令牌或 error、message"),
+    ),
+)]
+#[get("/oauth/{provider}/callback")]
+async fn callback(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    users: web::Data<UserService>,
+    provider: web::Path<OAuthProvider>,
+    params: web::Query<OAuthCallback>,
+) -> Either<HttpResponse, ApiResponse> {
+    let provider = *provider;
+    let (link_user_id, redirect_uri) =
+        match db::take_oauth_state(&params.state, provider.as_str(), &pool).await {
+            Ok(Some(state)) => state,
+            Ok(None) => {
+                return Either::Right(ApiResponse::error(
+                    ErrorCode::LinkExpired,
+                    "登录已过期，请重新发起",
+                ));
+            }
+            Err(_) => {
+                return Either::Right(ApiResponse::error(ErrorCode::InternalError, "登录失败"));
+            }
+        };
+    // 浏览器登录流程需要携带发起登录时设置的 Cookie，绑定流程由发起绑定的账号确认
+    let bound = link_user_id.is_some()
+        || req
+            .cookie(STATE_COOKIE)
+            .is_some_and(|cookie| cookie.value() == params.state);
+    let session_client = SessionClient::from_request(&req, &app_state);
+    let result = if bound {
+        complete(
+            provider,
+            link_user_id.clone(),
+            &params,
+            &session_client,
+            &users,
+            &app_state,
+            &pool,
+        )
+        .await
+    } else {
+        Err((
+            ErrorCode::Forbidden,
+            "登录流程不是由当前浏览器发起的，请重新登录",
+        ))
+    };
+    // 绑定在确认时记录审计日志
+    if link_user_id.is_none() {
+        let audit = AuditRecord::new(AuditEvent::Login, &req, &app_state)
+            .detail(json!({ "provider": provider }));
+        let audit = match &result {
+            Ok((_, user_id, _)) => audit.user(user_id),
+            Err(_) => audit,
+        };
+        audit
+            .outcome(result.as_ref().err().map(|(code, _)| *code))
+            .record(&pool)
+            .await;
+    }
+    match (redirect_uri, result) {
+        (Some(uri), Ok((_, _, data))) => Either::Left(redirect(&fragment_uri(&uri, &data))),
+        (Some(uri), Err((code, message))) => {
+            let data = json!({ "error": code, "message": message });
+            Either::Left(redirect(&fragment_uri(&uri, &data)))
+        }
+        (None, Ok((message, _, data))) => {
+            Either::Right(ApiResponse::new(message, ResponseData::Json(data)))
+        }
+        (None, Err((code, message))) => Either::Right(ApiResponse::error(code, message)),
+    }
+}
+
+// 成功时为 (提示信息, 用户 ID, 返回数据)
+type CallbackResult = Result<(&'static str, String, Value), (ErrorCode, &'static str)>;
+
+// 换取外部身份后绑定或登录
+async fn complete(
+    provider: OAuthProvider,
+    link_user_id: Option<String>,
+    params: &OAuthCallback,
+    session_client: &SessionClient,
+    users: &UserService,
+    app_state: &AppState,
+    pool: &DbPool,
+) -> CallbackResult {
+    let internal = |_| (ErrorCode::InternalError, "登录失败");
+    if let Some(error) = &params.error {
+        info!("{} 授权未完成: {}", provider.as_str(), error);
+        return Err((ErrorCode::Forbidden, "授权未完成"));
+    }
+    let (Some(code), Some(client)) = (&params.code, provider.client(&app_state.config.auth.oauth))
+    else {
+        return Err((ErrorCode::InvalidRequest, "缺少授权码"));
+    };
+    let identity = client::fetch_identity(provider, client, code)
+        .await
+        .map_err(|e| {
+            warn!("获取 {} 用户信息失败: {}", provider.as_str(), e);
+            (ErrorCode::InternalError, "获取第三方账号信息失败")
+        })?;
+    let email = identity.email.as_deref();
+
+    // 绑定到发起绑定的账号：保存为待确认的绑定，由该账号携带确认码确认，
+    // 防止他人发起的绑定流程在当前用户的浏览器中完成
+    if let Some(user_id) = link_user_id {
+        let code = uuid::Uuid::new_v4().simple().to_string();
+        let expires_at =
+            chrono::Utc::now().timestamp() + app_state.config.auth.oauth.state_ttl_secs;
+        db::insert_pending_link(
+            &code,
+            provider.as_str(),
+            &user_id,
+            &identity.subject,
+            email,
+            expires_at,
+            pool,
+        )
+        .await
+        .map_err(internal)?;
+        return Ok(("请在客户端确认绑定", user_id, json!({ "link_code": code })));
+    }
+
+    let mut user = db::get_user_by_identity(provider.as_str(), &identity.subject, pool)
+        .await
+        .map_err(internal)?;
+    // 提供方确认过的邮箱与已验证邮箱的账号相同时视为同一人
+    if user.is_none()
+        && identity.email_verified
+        && let Some(email) = email
+        && let Some(user_id) = db::get_user_id_by_verified_email(email, pool)
+            .await
+            .map_err(internal)?
+    {
+        db::insert_identity(
+            provider.as_str(),
+            &identity.subject,
+            &user_id,
+            Some(email),
+            pool,
+        )
+        .await
+        .map_err(internal)?;
+        info!("用户 {} 通过邮箱绑定了 {} 账号", user_id, provider.as_str());
+        user = db::get_user_by_identity(provider.as_str(), &identity.subject, pool)
+            .await
+            .map_err(internal)?;
+    }
+    let user = match user {
+        Some(user) => user,
+        None => register(provider, &identity, pool).await?,
+    };
+
+    if user.disabled {
+        return Err((ErrorCode::AccountDisabled, "账号已被停用"));
+    }
+    if user.totp_enabled {
+        return Err((
+            ErrorCode::TwoFactorRequired,
+            "已启用两步验证，请使用密码和验证码登录",
+        ));
+    }
+    let tokens = users
+        .start_session(&user.user_id, &user.username_or_email, session_client)
+        .await
+        .map_err(|_| (ErrorCode::InternalError, "登录失败"))?;
+    Ok(("登录成功", user.user_id, json!(tokens)))
+}
+
+// 以外部身份注册新账号
+async fn register(
+    provider: OAuthProvider,
+    identity: &ExternalIdentity,
+    pool: &DbPool,
+) -> Result<User, (ErrorCode, &'static str)> {
+    if !registration_open() {
+        return Err((ErrorCode::RegistrationClosed, "服务器未开放注册"));
+    }
+    let Some(email) = &identity.email else {
+        return Err((ErrorCode::InvalidRequest, "第三方账号没有可用的邮箱"));
+    };
+    match db::email_exists(email, pool).await {
+        Ok(false) => {}
+        Ok(true) => {
+            return Err((
+                ErrorCode::DuplicateEmail,
+                "邮箱已被使用，请登录该账号后绑定",
+            ));
+        }
+        Err(_) => return Err((ErrorCode::InternalError, "注册失败")),
+    }
+    let user_id = db::insert_oauth_user(
+        &identity.username,
+        email,
+        identity.email_verified,
+        provider.as_str(),
+        &identity.subject,
+        pool,
+    )
+    .await
+    .map_err(|_| (ErrorCode::InternalError, "注册失败"))?;
+    info!("通过 {} 注册了用户 {}", provider.as_str(), user_id);
+    db::get_user_by_identity(provider.as_str(), &identity.subject, pool)
+        .await
+        .ok()
+        .flatten()
+        .ok_or((ErrorCode::InternalError, "注册失败"))
+}
+
+fn found(url: &str) -> HttpResponseBuilder {
+    let mut builder = HttpResponse::Found();
+    builder
+        .insert_header((header::LOCATION, url))
+        .insert_header((header::CACHE_CONTROL, "no-store"));
+    builder
+}
+
+fn redirect(url: &str) -> HttpResponse {
+    found(url).finish()
+}
+
+// 把 JSON 对象的字段编码到回跳地址的片段中，片段不会发送到服务器，也不会出现在访问日志里
+fn fragment_uri(uri: &str, data: &Value) -> String {
+    let fragment = data
+        .as_object()
+        .into_iter()
+        .flatten()
+        .map(|(key, value)| {
+            let value = match value {
+                Value::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+            format!("{}={}", key, utf8_percent_encode(&value, NON_ALPHANUMERIC))
+        })
+        .collect::<Vec<_>>()
+        .join("&");
+    format!("{}#{}", uri, fragment)
+}
+
+/// 确认绑定第三方账号的参数
+#[derive(Deserialize, ToSchema)]
+pub struct LinkConfirm {
+    /// 绑定回调返回的确认码
+    link_code: String,
+}
+
+#[utoipa::path(
+    summary = "确认绑定第三方账号",
+    description = "只有发起绑定的账号可以确认，确认码只能使用一次",
+    params(("provider" = OAuthProvider, Path)),
+    request_body = LinkConfirm,
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "data 为 {linked}", body = ApiEnvelope<Value>),
+        (status = 410, description = "确认码无效或已过期", body = ApiEnvelope<Value>),
+    ),
+)]
+#[post("/oauth/{provider}/link/confirm")]
+async fn confirm_link(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    provider: web::Path<OAuthProvider>,
+    confirm: web::Json<LinkConfirm>,
+) -> impl Responder {
+    let provider = *provider;
+    let user_id = &bearer_token.user_id;
+    let result =
+        match db::take_pending_link(&confirm.link_code, provider.as_str(), user_id, &pool).await {
+            Ok(Some((subject, email))) => {
+                match db::insert_identity(
+                    provider.as_str(),
+                    &subject,
+                    user_id,
+                    email.as_deref(),
+                    &pool,
+                )
+                .await
+                {
+                    Ok(true) => Ok(()),
+                    Ok(false) => Err((ErrorCode::AlreadyExists, "该第三方账号已绑定其他用户")),
+                    Err(_) => Err((ErrorCode::InternalError, "绑定失败")),
+                }
+            }
+            Ok(None) => Err((ErrorCode::LinkExpired, "确认码无效或已过期，请重新绑定")),
+            Err(_) => Err((ErrorCode::InternalError, "绑定失败")),
+        };
+    AuditRecord::new(AuditEvent::IdentityLinked, &req, &app_state)
+        .user(user_id)
+        .detail(json!({ "provider": provider }))
+        .outcome(result.as_ref().err().map(|(code, _)| *code))
+        .record(&pool)
+        .await;
+    match result {
+        Ok(()) => {
+            info!("用户 {} 绑定了 {} 账号", user_id, provider.as_str());
+            ApiResponse::new(
+                "绑定成功",
+                ResponseData::Json(json!({ "linked": provider })),
+            )
+        }
+        Err((code, message)) => ApiResponse::error(code, message),
+    }
+}
+
+#[utoipa::path(
+    summary = "已绑定的第三方账号",
+    security(("bearer" = [])),
+    responses((status = 200, body = ApiEnvelope<Vec<LinkedIdentity>>)),
+)]
+#[get("/identities")]
+async fn get_identities(pool: web::Data<DbPool>, bearer_token: BearerToken) -> impl Responder {
+    match db::get_identities(&bearer_token.user_id, &pool).await {
+        Ok(identities) => ApiResponse::new("获取成功", ResponseData::Json(json!(identities))),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取失败"),
+    }
+}
+
+#[utoipa::path(
+    summary = "解除绑定第三方账号",
+    description = "通过第三方登录注册的账号没有可用的密码，解除绑定前请先通过找回密码设置密码",
+    params(("provider" = OAuthProvider, Path)),
+    security(("bearer" = [])),
+    responses((status = 200, body = ApiEnvelope<Value>)),
+)]
+#[delete("/identities/{provider}")]
+async fn delete_identity(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    provider: web::Path<OAuthProvider>,
+) -> impl Responder {
+    match db::delete_identity(&bearer_token.user_id, provider.as_str(), &pool).await {
+        Ok(true) => {
+            info!(
+                "用户 {} 解除绑定 {} 账号",
+                bearer_token.user_id,
+                provider.as_str()
+            );
+            AuditRecord::new(AuditEvent::IdentityUnlinked, &req, &app_state)
+                .user(&bearer_token.user_id)
+                .detail(json!({ "provider": *provider }))
+                .record(&pool)
+                .await;
+            ApiResponse::new("已解除绑定", ResponseData::Null)
+        }
+        Ok(false) => ApiResponse::error(ErrorCode::NotFound, "未绑定该第三方账号"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "解除绑定失败"),
+    }
+}
+
+#[cfg(feature = "oauth")]
+mod client {
+    use serde::Deserialize;
+    use serde_json::Value;
+
+    use super::{ExternalIdentity, OAuthProvider};
+    use crate::config::OAuthClientConfig;
+
+    /// GitHub API 要求请求带有 User-Agent
+    const USER_AGENT: &str = "ClipFocusServer";
+
+    #[derive(Deserialize)]
+    struct TokenResponse {
+        access_token: String,
+    }
+
+    async fn read_json(response: reqwest::Response) -> Result<Value, String> {
+        let status = response.status();
+        let body = response.bytes().await.map_err(|e| e.to_string())?;
+        if !status.is_success() {
+            return Err(format!(
+                "responded {}: {}",
+                status,
+                String::from_utf8_lossy(&body)
+            ));
+        }
+        serde_json::from_slice(&body).map_err(|e| e.to_string())
+    }
+
+    async fn get_json(http: &reqwest::Client, url: &str, token: &str) -> Result<Value, String> {
+        let response = http
+            .get(url)
+            .bearer_auth(token)
+            .header(reqwest::header::ACCEPT, "application/json")
+            .header(reqwest::header::USER_AGENT, USER_AGENT)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        read_json(response).await
+    }
+
+    // 用授权码换取访问令牌，再读取提供方的用户信息
+    pub async fn fetch_identity(
+        provider: OAuthProvider,
+        client: &OAuthClientConfig,
+        code: &str,
+    ) -> Result<ExternalIdentity, String> {
+        let http = reqwest::Client::new();
+        let token_url = match provider {
+            OAuthProvider::Github => "https://github.com/login/oauth/access_token",
+            OAuthProvider::Google => "https://oauth2.googleapis.com/token",
+        };
+        let response = http
+            .post(token_url)
+            .header(reqwest::header::ACCEPT, "application/json")
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("client_id", &client.client_id),
+                ("client_secret", &client.client_secret),
+                ("redirect_uri", &client.redirect_uri),
+            ])
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        // GitHub 授权码无效时同样返回 200，响应中没有 access_token
+        let token: TokenResponse =
+            serde_json::from_value(read_json(response).await?).map_err(|e| e.to_string())?;
+        match provider {
+            OAuthProvider::Github => {
+                let user =
+                    get_json(&http, "https://api.github.com/user", &token.access_token).await?;
+                let emails = get_json(
+                    &http,
+                    "https://api.github.com/user/emails",
+                    &token.access_token,
+                )
+                .await?;
+                parse_github(&user, &emails)
+            }
+            OAuthProvider::Google => {
+                let userinfo = get_json(
+                    &http,
+                    "https://openidconnect.googleapis.com/v1/userinfo",
+                    &token.access_token,
+                )
+                .await?;
+                parse_google(&userinfo)
+            }
+        }
+    }
+
+    // GitHub 的邮箱可能不公开，从 /user/emails 中取主邮箱
+    fn parse_github(user: &Value, emails: &Value) -> Result<ExternalIdentity, String> {
+        let subject = user["id"]
+            .as_i64()
+            .ok_or("GitHub user has no id")?
+            .to_string();
+        let primary = emails
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|email| email["primary"].as_bool() == Some(true));
+        Ok(ExternalIdentity {
+            subject,
+            username: user["login"].as_str().unwrap_or_default().to_string(),
+            email: primary
+                .and_then(|email| email["email"].as_str())
+                .map(str::to_string),
+            email_verified: primary.is_some_and(|email| email["verified"].as_bool() == Some(true)),
+        })
+    }
+
+    fn parse_google(userinfo: &Value) -> Result<ExternalIdentity, String> {
+        let subject = userinfo["sub"].as_str().ok_or("Google user has no sub")?;
+        let email = userinfo["email"].as_str().map(str::to_string);
+        let username = userinfo["name"]
+            .as_str()
+            .or_else(|| email.as_deref()?.split('@').next())
+            .unwrap_or_default();
+        Ok(ExternalIdentity {
+            subject: subject.to_string(),
+            username: username.to_string(),
+            email,
+            email_verified: userinfo["email_verified"].as_bool() == Some(true),
+        })
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use serde_json::json;
+
+        #[test]
+        fn provider_profiles_are_parsed() {
+            let github = parse_github(
+                &json!({ "id": 42, "login": "octocat" }),
+                &json!([
+                    { "email": "old@example.com", "primary": false, "verified": true },
+                    { "email": "cat@example.com", "primary": true, "verified": true },
+                ]),
+            )
+            .unwrap();
+            assert_eq!(github.subject, "42");
+            assert_eq!(github.username, "octocat");
+            assert_eq!(github.email.as_deref(), Some("cat@example.com"));
+            assert!(github.email_verified);
+
+            let google = parse_google(&json!({
+                "sub": "1099",
+                "email": "alice@example.com",
+                "email_verified": false,
+            }))
+            .unwrap();
+            assert_eq!(google.subject, "1099");
+            assert_eq!(google.username, "alice");
+            assert!(!google.email_verified);
+            assert!(parse_google(&json!({})).is_err());
+        }
+    }
+}
+
+#[cfg(not(feature = "oauth"))]
+mod client {
+    use super::{ExternalIdentity, OAuthProvider};
+    use crate::config::OAuthClientConfig;
+
+    pub async fn fetch_identity(
+        _provider: OAuthProvider,
+        _client: &OAuthClientConfig,
+        _code: &str,
+    ) -> Result<ExternalIdentity, String> {
+        Err("the oauth feature is disabled".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_returned_in_fragment() {
+        let uri = fragment_uri(
+            "clipfocus://oauth",
+            &json!({ "access_token": "a b", "expires_in": 900 }),
+        );
+        assert_eq!(uri, "clipfocus://oauth#access_token=a%20b&expires_in=900");
+    }
+
+    #[test]
+    fn redirects_must_match_an_allowed_uri() {
+        let allowed = [
+            "clipfocus://oauth".to_string(),
+            "https://app.example.com/cb/".to_string(),
+        ];
+        let check = |uri: &str| allowed_redirect(uri, &allowed);
+        assert_eq!(
+            check("clipfocus://oauth").as_deref(),
+            Some("clipfocus://oauth")
+        );
+        assert!(check("https://app.example.com/cb/done?x=1").is_some());
+        assert!(check("HTTPS://APP.example.com:443/cb/").is_some());
+        for uri in [
+            "clipfocus://oauth.evil.com",
+            "clipfocus://oauth/../x",
+            "https://app.example.com.evil.com/cb/",
+            "https://app.example.com@evil.com/cb/",
+            "https://user@app.example.com/cb/",
+            "https://app.example.com/cbx",
+            "https://app.example.com/cb/../admin",
+            "https://app.example.com:8443/cb/",
+            "http://app.example.com/cb/",
+            "https://app.example.com/cb/#x",
+            "not a uri",
+        ] {
+            assert!(check(uri).is_none(), "{}", uri);
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use actix_web::{Responder, get, web};
+use serde_json::{Value, json};
+use sqlx::Executor;
+use std::sync::OnceLock;
+
+use crate::{
+    config::QuotaConfig,
+    sqlx_utils::{
+        Db, DbPool, db,
+        models::{ApiEnvelope, ApiResponse, ErrorCode, ResponseData},
+    },
+    user_api::auth::BearerToken,
+};
+
+static DEFAULT_QUOTA: OnceLock<Option<i64>> = OnceLock::new();
+
+// 设置默认的个人配额，只在启动时调用一次
+pub fn install(config: &QuotaConfig) {
+    let _ = DEFAULT_QUOTA.set(config.default_user_bytes);
+}
+
+// 默认的个人配额，未设置时不限制
+fn default_quota() -> Option<i64> {
+    DEFAULT_QUOTA.get().copied().flatten()
+}
+
+/// 配额和已用字节数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Usage {
+    /// 配额（字节），为空表示不限制
+    pub quota_bytes: Option<i64>,
+    pub used_bytes: i64,
+}
+
+impl Usage {
+    /// 剩余字节数，不限制时为空
+    pub fn remaining_bytes(&self) -> Option<i64> {
+        self.quota_bytes
+            .map(|quota_bytes| (quota_bytes - self.used_bytes).max(0))
+    }
+
+    fn exceeded_by(&self, additional_bytes: i64) -> bool {
+        self.quota_bytes
+            .is_some_and(|quota_bytes| self.used_bytes + additional_bytes > quota_bytes)
+    }
+}
+
+/// 用户的个人存储占用，以及所在组织（不在组织中时为空）的存储占用
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuotaUsage {
+    pub user: Usage,
+    pub organization: Option<Usage>,
+}
+
+impl QuotaUsage {
+    /// 写入 additional_bytes 后超出个人或组织配额时返回 QUOTA_EXCEEDED 错误响应，
+    /// data 中的 scope 为 user / organization
+    pub fn check(&self, additional_bytes: i64) -> Result<(), ApiResponse> {
+        let (scope, usage, message) = if self.user.exceeded_by(additional_bytes) {
+            ("user", self.user, "超出个人存储配额")
+        } else if let Some(org) = self.organization
+            && org.exceeded_by(additional_bytes)
+        {
+            ("organization", org, "超出组织的存储配额")
+        } else {
+            return Ok(());
+        };
+        Err(ApiResponse::error_with_data(
+            ErrorCode::QuotaExceeded,
+            message,
+            ResponseData::Json(json!({
+                "scope": scope,
+                "quota_bytes": usage.quota_bytes,
+                "used_bytes": usage.used_bytes,
+                "requested_bytes": additional_bytes,
+            })),
+        ))
+    }
+}
+
+// 获取用户的存储占用，未单独设置个人配额时使用默认配额；用户不存在时返回 None
+pub async fn quota_usage(
+    user_id: &str,
+    executor: impl Executor<'_, Database = Db>,
+) -> Result<Option<QuotaUsage>, sqlx::Error> {
+    let mut usage = db::get_quota_usage(user_id, executor).await?;
+    if let Some(usage) = &mut usage {
+        usage.user.quota_bytes = usage.user.quota_bytes.or_else(default_quota);
+    }
+    Ok(usage)
+}
+
+// 检查用户的个人配额和所在组织的配额，写入 additional_bytes 后超出配额时返回错误响应
+pub async fn check_quota(
+    user_id: &str,
+    additional_bytes: i64,
+    executor: impl Executor<'_, Database = Db>,
+) -> Result<(), ApiResponse> {
+    match quota_usage(user_id, executor).await {
+        Ok(Some(usage)) => usage.check(additional_bytes),
+        Ok(None) => Ok(()),
+        Err(_) => Err(ApiResponse::error(
+            ErrorCode::InternalError,
+            "检查存储配额失败",
+        )),
+    }
+}
+
+// 获取存储配额：剪贴板（包括文件）、定时投递和头像占用的字节数，以及所在组织的配额
+#[utoipa::path(
+    summary = "获取存储配额",
+    description = "quota_bytes 为空表示不限制；organization 为所在组织的配额和用量，不在组织中时为空",
+    security(("bearer" = [])),
+    responses((status = 200, body = ApiEnvelope<Value>)),
+)]
+#[get("/quota")]
+pub async fn get_quota(pool: web::Data<DbPool>, bearer_token: BearerToken) -> impl Responder {
+    match quota_usage(&bearer_token.user_id, &**pool).await {
+        Ok(Some(usage)) => ApiResponse::new(
+            "获取存储配额成功",
+            ResponseData::Json(json!({
+                "quota_bytes": usage.user.quota_bytes,
+                "used_bytes": usage.user.used_bytes,
+                "remaining_bytes": usage.user.remaining_bytes(),
+                "organization": usage.organization.map(|org| json!({
+                    "quota_bytes": org.quota_bytes,
+                    "used_bytes": org.used_bytes,
+                    "remaining_bytes": org.remaining_bytes(),
+                })),
+            })),
+        ),
+        Ok(None) => ApiResponse::error(ErrorCode::UserNotFound, "用户不存在"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取存储配额失败"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sqlx_utils::migrations::run_migrations;
+    use crate::user_api::RegisterUser;
+    use sqlx::any::AnyPoolOptions;
+    use std::collections::HashMap;
+
+    #[test]
+    fn check_reports_the_exceeded_scope() {
+        let usage = QuotaUsage {
+            user: Usage {
+                quota_bytes: Some(100),
+                used_bytes: 60,
+            },
+            organization: Some(Usage {
+                quota_bytes: Some(1000),
+                used_bytes: 990,
+            }),
+        };
+        let scope = |additional_bytes| match usage.check(additional_bytes) {
+            Ok(()) => None,
+            Err(response) => match response.data {
+                ResponseData::Json(data) => Some(data["scope"].clone()),
+                _ => None,
+            },
+        };
+        assert_eq!(scope(5), None);
+        assert_eq!(scope(20), Some(json!("organization")));
+        assert_eq!(scope(50), Some(json!("user")));
+        assert_eq!(usage.user.remaining_bytes(), Some(40));
+    }
+
+    #[tokio::test]
+    async fn usage_counts_the_avatar() {
+        // 内存数据库按连接隔离，只使用一个连接
+        let pool = AnyPoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        run_migrations(&pool).await.unwrap();
+        let user = RegisterUser {
+            username: "alice".to_string(),
+            email: "alice@example.com".to_string(),
+            password: "secret".to_string(),
+            accepted_terms: HashMap::new(),
+        };
+        let user_id = db::insert_user(&user, &pool).await.unwrap();
+
+        db::update_head_uri(&user_id, "head", "image/png", 300, None, &pool)
+            .await
+            .unwrap();
+        assert!(
+            db::set_user_quota(&user_id, Some(500), &pool)
+                .await
+                .unwrap()
+        );
+        let usage = quota_usage(&user_id, &pool).await.unwrap().unwrap();
+        assert_eq!(
+            usage.user,
+            Usage {
+                quota_bytes: Some(500),
+                used_bytes: 300,
+            }
+        );
+        assert_eq!(usage.organization, None);
+        assert!(check_quota(&user_id, 200, &pool).await.is_ok());
+        assert!(check_quota(&user_id, 201, &pool).await.is_err());
+        assert!(quota_usage("missing", &pool).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,366 @@
+use log::{info, warn};
+use serde_json::json;
+
+use crate::{
+    config::AuthConfig,
+    legal_api::{LegalDocument, issue_access_token},
+    models::UserRole,
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiResponse, ErrorCode, ResponseData},
+    },
+    user_api::{
+        RegisterUser, TokenPair,
+        auth::{REFRESH_TOKEN_TTL_SECS, generate_refresh_token, hash_refresh_token},
+        registration_open,
+        sessions::SessionClient,
+    },
+};
+
+/// 账号操作失败的原因，由各接口转换为对应的响应
+#[derive(Debug)]
+pub enum UserError {
+    RegistrationClosed,
+    /// 未接受最新的条款，附带当前生效的条款
+    TermsNotAccepted(Vec<LegalDocument>),
+    DuplicateEmail,
+    /// 刷新令牌无效、已过期或已注销
+    InvalidRefreshToken,
+    AccountDisabled,
+    /// 请求参数无效，附带提示
+    Invalid(&'static str),
+    /// 数据库等内部错误
+    Internal(String),
+}
+
+impl From<sqlx::Error> for UserError {
+    fn from(e: sqlx::Error) -> Self {
+        UserError::Internal(e.to_string())
+    }
+}
+
+impl UserError {
+    /// 转换为响应，内部错误只返回 failure（如“注册失败”）
+    pub fn into_response(self, failure: &str) -> ApiResponse {
+        match self {
+            UserError::RegistrationClosed => {
+                ApiResponse::error(ErrorCode::RegistrationClosed, "服务器未开放注册")
+            }
+            UserError::TermsNotAccepted(documents) => ApiResponse::error_with_data(
+                ErrorCode::TermsNotAccepted,
+                "需要接受最新的服务条款",
+                ResponseData::Json(json!(documents)),
+            ),
+            UserError::DuplicateEmail => {
+                ApiResponse::error(ErrorCode::DuplicateEmail, "邮箱已被使用")
+            }
+            UserError::InvalidRefreshToken => {
+                ApiResponse::error(ErrorCode::AuthInvalid, "刷新令牌无效或已过期")
+            }
+            UserError::AccountDisabled => {
+                ApiResponse::error(ErrorCode::AccountDisabled, "账号已被停用")
+            }
+            UserError::Invalid(message) => ApiResponse::error(ErrorCode::InvalidRequest, message),
+            UserError::Internal(e) => {
+                warn!("{}: {}", failure, e);
+                ApiResponse::error(ErrorCode::InternalError, failure)
+            }
+        }
+    }
+}
+
+/// 账号和登录会话的业务逻辑（注册、签发和轮换令牌、修改密码），HTTP 和 gRPC 等接口共用
+#[derive(Clone)]
+pub struct UserService {
+    pool: DbPool,
+    auth: AuthConfig,
+}
+
+impl UserService {
+    pub fn new(pool: DbPool, auth: AuthConfig) -> Self {
+        UserService { pool, auth }
+    }
+
+    /// 创建账号并记录接受的条款，返回用户 ID
+    ///
+    /// 必须接受当前所有条款的最新版本
+    pub async fn register(&self, user: &RegisterUser) -> Result<String, UserError> {
+        if !registration_open() {
+            return Err(UserError::RegistrationClosed);
+        }
+        let documents = db::get_current_legal_documents(&self.pool).await?;
+        if documents
+            .iter()
+            .any(|doc| user.accepted_terms.get(&doc.kind) != Some(&doc.version))
+        {
+            return Err(UserError::TermsNotAccepted(documents));
+        }
+        if db::email_exists(&user.email, &self.pool).await? {
+            return Err(UserError::DuplicateEmail);
+        }
+
+        let user_id = db::insert_user(user, &self.pool).await?;
+        for doc in &documents {
+            if db::insert_legal_acceptance(&user_id, &doc.kind, doc.version, &self.pool)
+                .await
+                .is_err()
+            {
+                warn!("记录用户 {} 的条款接受状态失败", user_id);
+            }
+        }
+        Ok(user_id)
+    }
+
+    /// 由管理员创建账号，返回用户 ID
+    ///
+    /// 不受注册开关限制，邮箱视为已验证；条款由用户登录后自行接受
+    pub async fn create(&self, user: &RegisterUser, role: UserRole) -> Result<String, UserError> {
+        if user.username.is_empty() || user.email.is_empty() || user.password.is_empty() {
+            return Err(UserError::Invalid("用户名、邮箱和密码不能为空"));
+        }
+        if db::email_exists(&user.email, &self.pool).await? {
+            return Err(UserError::DuplicateEmail);
+        }
+
+        let user_id = db::insert_user(user, &self.pool).await?;
+        db::mark_email_verified(&user_id, &self.pool).await?;
+        if role != UserRole::User {
+            db::set_user_role(&user_id, role, &self.pool).await?;
+        }
+        info!("创建用户 {}（{:?}）", user_id, role);
+        Ok(user_id)
+    }
+
+    /// 开始新的登录会话，签发访问令牌和刷新令牌
+    pub async fn start_session(
+        &self,
+        user_id: &str,
+        username: &str,
+        client: &SessionClient,
+    ) -> Result<TokenPair, UserError> {
+        let (refresh_token, refresh_expires_at) = new_refresh_token();
+        let session_id = db::insert_refresh_token(
+            user_id,
+            &hash_refresh_token(&refresh_token),
+            refresh_expires_at,
+            client,
+            &self.pool,
+        )
+        .await?;
+        self.token_pair(
+            user_id,
+            username,
+            &session_id,
+            refresh_token,
+            refresh_expires_at,
+        )
+        .await
+    }
+
+    /// 用刷新令牌换取新的令牌（刷新令牌同时轮换），返回用户 ID、会话 ID 和新令牌
+    pub async fn refresh(
+        &self,
+        refresh_token: &str,
+        client: &SessionClient,
+    ) -> Result<(String, String, TokenPair), UserError> {
+        let (new_refresh_token, refresh_expires_at) = new_refresh_token();
+        let (user_id, session_id) = db::rotate_refresh_token(
+            &hash_refresh_token(refresh_token.trim()),
+            &hash_refresh_token(&new_refresh_token),
+            refresh_expires_at,
+            client,
+            &self.pool,
+        )
+        .await?
+        .ok_or(UserError::InvalidRefreshToken)?;
+        if db::is_user_disabled(&user_id, &self.pool).await? {
+            return Err(UserError::AccountDisabled);
+        }
+        let username = db::get_user_by_id(&user_id, &self.pool)
+            .await
+            .map_err(|_| UserError::InvalidRefreshToken)?
+            .username;
+
+        // 生成新的访问令牌（重新检查条款接受状态）
+        let tokens = self
+            .token_pair(
+                &user_id,
+                &username,
+                &session_id,
+                new_refresh_token,
+                refresh_expires_at,
+            )
+            .await?;
+        Ok((user_id, session_id, tokens))
+    }
+
+    /// 注销刷新令牌，all 为 true 时注销该用户在所有设备上的刷新令牌，返回用户 ID
+    pub async fn logout(&self, refresh_token: &str, all: bool) -> Result<String, UserError> {
+        let user_id =
+            db::revoke_refresh_token(&hash_refresh_token(refresh_token.trim()), &self.pool)
+                .await?
+                .ok_or(UserError::InvalidRefreshToken)?;
+        if all {
+            db::revoke_user_refresh_tokens(&user_id, &self.pool).await?;
+        }
+        info!("用户 {} 退出登录", user_id);
+        Ok(user_id)
+    }
+
+    /// 修改密码：其他设备需要重新登录，为当前设备签发新的令牌
+    pub async fn change_password(
+        &self,
+        user_id: &str,
+        username: &str,
+        new_password: &str,
+        client: &SessionClient,
+    ) -> Result<TokenPair, UserError> {
+        self.reset_password(user_id, new_password).await?;
+        info!("用户 {} 已修改密码", user_id);
+        self.start_session(user_id, username, client).await
+    }
+
+    /// 设置新密码并注销该用户的所有刷新令牌，所有设备需要重新登录
+    pub async fn reset_password(&self, user_id: &str, new_password: &str) -> Result<(), UserError> {
+        if new_password.is_empty() {
+            return Err(UserError::Invalid("新密码不能为空"));
+        }
+        db::update_password(user_id, new_password, &self.pool).await?;
+        if db::revoke_user_refresh_tokens(user_id, &self.pool)
+            .await
+            .is_err()
+        {
+            warn!("注销用户 {} 的刷新令牌失败", user_id);
+        }
+        Ok(())
+    }
+
+    // 为会话签发访问令牌，与刷新令牌一起返回
+    async fn token_pair(
+        &self,
+        user_id: &str,
+        username: &str,
+        session_id: &str,
+        refresh_token: String,
+        refresh_expires_at: i64,
+    ) -> Result<TokenPair, UserError> {
+        let access_token =
+            issue_access_token(user_id, username, Some(session_id), &self.auth, &self.pool)
+                .await
+                .map_err(UserError::Internal)?;
+        Ok(TokenPair {
+            access_token,
+            token_type: "Bearer",
+            expires_in: self.auth.access_token_ttl_secs,
+            refresh_token,
+            refresh_expires_at,
+        })
+    }
+}
+
+// 生成新的刷新令牌和过期时间
+fn new_refresh_token() -> (String, i64) {
+    let expires_at = chrono::Utc::now().timestamp() + REFRESH_TOKEN_TTL_SECS;
+    (generate_refresh_token(), expires_at)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sqlx_utils::migrations::run_migrations;
+    use sqlx::any::AnyPoolOptions;
+    use std::collections::HashMap;
+
+    async fn service() -> UserService {
+        // 内存数据库按连接隔离，只使用一个连接
+        let pool = AnyPoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        run_migrations(&pool).await.unwrap();
+        UserService::new(pool, AuthConfig::default())
+    }
+
+    fn alice() -> RegisterUser {
+        RegisterUser {
+            username: "alice".to_string(),
+            email: "alice@example.com".to_string(),
+            password: "secret".to_string(),
+            accepted_terms: HashMap::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn register_rejects_duplicate_email() {
+        let users = service().await;
+        users.register(&alice()).await.unwrap();
+        assert!(matches!(
+            users.register(&alice()).await,
+            Err(UserError::DuplicateEmail)
+        ));
+    }
+
+    #[tokio::test]
+    async fn refresh_rotates_the_refresh_token() {
+        let users = service().await;
+        let user_id = users.register(&alice()).await.unwrap();
+        let client = SessionClient::default();
+        let tokens = users
+            .start_session(&user_id, "alice", &client)
+            .await
+            .unwrap();
+
+        let (refreshed_user, _, refreshed) =
+            users.refresh(&tokens.refresh_token, &client).await.unwrap();
+        assert_eq!(refreshed_user, user_id);
+        assert_ne!(refreshed.refresh_token, tokens.refresh_token);
+        // 旧的刷新令牌已被轮换
+        assert!(matches!(
+            users.refresh(&tokens.refresh_token, &client).await,
+            Err(UserError::InvalidRefreshToken)
+        ));
+    }
+
+    #[tokio::test]
+    async fn create_skips_registration_checks() {
+        let users = service().await;
+        let user_id = users.create(&alice(), UserRole::Admin).await.unwrap();
+        assert_eq!(
+            db::get_user_role(&user_id, &users.pool).await.unwrap(),
+            UserRole::Admin
+        );
+        assert!(matches!(
+            users.create(&alice(), UserRole::User).await,
+            Err(UserError::DuplicateEmail)
+        ));
+    }
+
+    #[tokio::test]
+    async fn change_password_revokes_other_sessions() {
+        let users = service().await;
+        let user_id = users.register(&alice()).await.unwrap();
+        let client = SessionClient::default();
+        let other = users
+            .start_session(&user_id, "alice", &client)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            users.change_password(&user_id, "alice", "", &client).await,
+            Err(UserError::Invalid(_))
+        ));
+        let current = users
+            .change_password(&user_id, "alice", "changed", &client)
+            .await
+            .unwrap();
+        assert!(matches!(
+            users.logout(&other.refresh_token, false).await,
+            Err(UserError::InvalidRefreshToken)
+        ));
+        assert_eq!(
+            users.logout(&current.refresh_token, false).await.unwrap(),
+            user_id
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use actix_web::{HttpRequest, Responder, delete, get, http::header, web};
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::{Value, json};
+use utoipa::ToSchema;
+
+use crate::{
+    audit::{AuditEvent, AuditRecord},
+    spatial_api::models::{AppState, DisconnectLoginSession, GetSessions, SessionInfo},
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiEnvelope, ApiResponse, ErrorCode, ResponseData},
+    },
+    user_api::auth::BearerToken,
+};
+
+/// 设备名称、User-Agent 保存的最大字符数
+const MAX_CLIENT_FIELD_CHARS: usize = 200;
+
+/// 登录时的客户端信息，保存在登录会话中
+#[derive(Debug, Clone, Default)]
+pub struct SessionClient {
+    /// 客户端通过 `X-Device-Name` 请求头提供的设备名称
+    pub device_name: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+impl SessionClient {
+    pub fn from_request(req: &HttpRequest, app_state: &AppState) -> Self {
+        let header_value = |name| {
+            req.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(|value| value.chars().take(MAX_CLIENT_FIELD_CHARS).collect())
+        };
+        SessionClient {
+            device_name: header_value(header::HeaderName::from_static("x-device-name")),
+            user_agent: header_value(header::USER_AGENT),
+            ip: app_state
+                .rate_limiters
+                .client_ip(req)
+                .map(|ip| ip.to_string()),
+        }
+    }
+}
+
+/// 登录会话：一次登录以及之后轮换出的全部刷新令牌
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LoginSession {
+    pub id: String,
+    pub device_name: Option<String>,
+    pub user_agent: Option<String>,
+    /// 最近一次登录或刷新令牌时的 IP
+    pub ip: Option<String>,
+    pub signed_in_at: i64,
+    /// 最近一次刷新令牌或 WebSocket 心跳的时间
+    pub last_seen_at: i64,
+    /// 是否是发起本次请求的登录会话
+    pub current: bool,
+    /// 通过该登录会话的令牌建立的在线 WebSocket 会话
+    #[schema(value_type = Vec<Object>)]
+    pub websocket_sessions: Vec<SessionInfo>,
+}
+
+// 列出当前用户的登录会话，并附上各会话在线的 WebSocket 连接
+#[utoipa::path(
+    summary = "登录会话列表",
+    description = "每次登录产生一个登录会话，刷新令牌轮换时会话 ID 不变。\
+        登录时可以通过 `X-Device-Name` 请求头提供设备名称",
+    security(("bearer" = [])),
+    responses((status = 200, body = ApiEnvelope<Vec<LoginSession>>)),
+)]
+#[get("/sessions")]
+async fn list_sessions(
+    app_state: web::Data<AppState>,
+    pool: web::Data<DbPool>,
+    bearer_token: BearerToken,
+) -> impl Responder {
+    let mut sessions = match db::get_login_sessions(&bearer_token.user_id, &pool).await {
+        Ok(sessions) => sessions,
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "获取失败"),
+    };
+    let websocket_sessions = app_state
+        .room_manager
+        .send(GetSessions {
+            user_id: bearer_token.user_id.clone(),
+        })
+        .await
+        .unwrap_or_else(|e| {
+            warn!("获取在线会话失败: {}", e);
+            Vec::new()
+        });
+    for websocket_session in websocket_sessions {
+        let Some(session) = sessions.iter_mut().find(|session| {
+            websocket_session.login_session_id.as_deref() == Some(session.id.as_str())
+        }) else {
+            continue;
+        };
+        session.last_seen_at = session
+            .last_seen_at
+            .max(websocket_session.last_heartbeat_at);
+        session.websocket_sessions.push(websocket_session);
+    }
+    for session in &mut sessions {
+        session.current = bearer_token.session_id.as_deref() == Some(session.id.as_str());
+    }
+    sessions.sort_by_key(|session| std::cmp::Reverse(session.last_seen_at));
+    ApiResponse::new("获取成功", ResponseData::Json(json!(sessions)))
+}
+
+// 注销登录会话：注销其刷新令牌并断开通过该会话建立的 WebSocket 连接
+#[utoipa::path(
+    summary = "注销登录会话",
+    description = "已签发的访问令牌在过期前仍可调用 HTTP 接口，但不能再建立 WebSocket 连接",
+    params(("id" = String, Path, description = "登录会话 ID")),
+    security(("bearer" = [])),
+    responses((status = 200, body = ApiEnvelope<Value>)),
+)]
+#[delete("/sessions/{id}")]
+async fn revoke_session(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    pool: web::Data<DbPool>,
+    bearer_token: BearerToken,
+    id: web::Path<String>,
+) -> impl Responder {
+    match db::revoke_login_session(&bearer_token.user_id, &id, &pool).await {
+        Ok(true) => {}
+        Ok(false) => return ApiResponse::error(ErrorCode::NotFound, "登录会话不存在或已注销"),
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "注销失败"),
+    }
+    let disconnected = app_state
+        .room_manager
+        .send(DisconnectLoginSession {
+            user_id: bearer_token.user_id.clone(),
+            login_session_id: id.to_string(),
+            reason: "session revoked".to_string(),
+        })
+        .await
+        .unwrap_or_default();
+    info!(
+        "用户 {} 注销了登录会话 {}，断开 {} 个连接",
+        bearer_token.user_id, id, disconnected
+    );
+    AuditRecord::new(AuditEvent::SessionRevoked, &req, &app_state)
+        .user(&bearer_token.user_id)
+        .detail(json!({ "session_id": id.as_str(), "websocket_sessions": disconnected }))
+        .record(&pool)
+        .await;
+    ApiResponse::new(
+        "已注销",
+        ResponseData::Json(json!({ "disconnected": disconnected })),
+    )
+}
@@ -0,0 +1,328 @@
+use actix_web::{Responder, get, put, web};
+use log::{info, warn};
+use serde_json::{Map, Value};
+
+use crate::{
+    spatial_api::models::{AppState, PushEvent},
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiEnvelope, ApiResponse, ErrorCode, ResponseData},
+    },
+    user_api::{
+        app_rules::{APP_RULES_KEY, AppRule, AppRules},
+        auth::BearerToken,
+    },
+};
+
+/// 设置项的取值类型（附带默认值）
+enum SettingKind {
+    /// 布尔值
+    Bool(bool),
+    /// 整数，默认值为 None 表示未设置（由服务器决定）
+    Integer {
+        default: Option<i64>,
+        min: i64,
+        max: i64,
+    },
+    /// "HH:MM" 格式的时间
+    Time(&'static str),
+    /// 枚举字符串
+    Choice {
+        default: &'static str,
+        options: &'static [&'static str],
+    },
+    /// 按来源应用处理剪贴板的规则列表（AppRule），默认为空
+    AppRules,
+}
+
+/// 设置项定义
+struct SettingSpec {
+    key: &'static str,
+    kind: SettingKind,
+}
+
+/// 允许的设置项列表，未在此列出的键会被拒绝
+const SETTINGS_SCHEMA: &[SettingSpec] = &[
+    // 保留策略覆盖
+    SettingSpec {
+        key: "retention_max_items",
+        kind: SettingKind::Integer {
+            default: None,
+            min: 0,
+            max: 1_000_000,
+        },
+    },
+    SettingSpec {
+        key: "retention_max_age_days",
+        kind: SettingKind::Integer {
+            default: None,
+            min: 0,
+            max: 3650,
+        },
+    },
+    SettingSpec {
+        key: "retention_max_bytes",
+        kind: SettingKind::Integer {
+            default: None,
+            min: 0,
+            max: 1 << 40,
+        },
+    },
+    // 免打扰默认值
+    SettingSpec {
+        key: "dnd_enabled",
+        kind: SettingKind::Bool(false),
+    },
+    SettingSpec {
+        key: "dnd_start",
+        kind: SettingKind::Time("22:00"),
+    },
+    SettingSpec {
+        key: "dnd_end",
+        kind: SettingKind::Time("08:00"),
+    },
+    // 通知选项
+    SettingSpec {
+        key: "notify_level",
+        kind: SettingKind::Choice {
+            default: "all",
+            options: &["all", "important", "none"],
+        },
+    },
+    SettingSpec {
+        key: "notify_sound",
+        kind: SettingKind::Bool(true),
+    },
+    // 剪贴板项目默认存活时间（秒）
+    SettingSpec {
+        key: "default_clip_ttl_secs",
+        kind: SettingKind::Integer {
+            default: None,
+            min: 0,
+            max: 365 * 24 * 3600,
+        },
+    },
+    // 按来源应用屏蔽或自动添加标签，通过 /settings/app_rules 管理
+    SettingSpec {
+        key: APP_RULES_KEY,
+        kind: SettingKind::AppRules,
+    },
+];
+
+impl SettingSpec {
+    fn default_value(&self) -> Value {
+        match &self.kind {
+            SettingKind::Bool(b) => Value::Bool(*b),
+            SettingKind::Integer { default, .. } => default.map(Value::from).unwrap_or(Value::Null),
+            SettingKind::Time(t) => Value::from(*t),
+            SettingKind::Choice { default, .. } => Value::from(*default),
+            SettingKind::AppRules => Value::Array(Vec::new()),
+        }
+    }
+
+    // 校验取值，返回错误原因
+    fn validate(&self, value: &Value) -> Result<(), String> {
+        match &self.kind {
+            SettingKind::Bool(_) => value
+                .as_bool()
+                .map(|_| ())
+                .ok_or_else(|| "需要布尔值".to_string()),
+            SettingKind::Integer { min, max, .. } => match value.as_i64() {
+                Some(n) if n >= *min && n <= *max => Ok(()),
+                _ => Err(format!("需要 {} 到 {} 之间的整数", min, max)),
+            },
+            SettingKind::Time(_) => {
+                let valid = value
+                    .as_str()
+                    .and_then(|s| chrono::NaiveTime::parse_from_str(s, "%H:%M").ok())
+                    .is_some();
+                if valid {
+                    Ok(())
+                } else {
+                    Err("需要 HH:MM 格式的时间".to_string())
+                }
+            }
+            SettingKind::Choice { options, .. } => match value.as_str() {
+                Some(s) if options.contains(&s) => Ok(()),
+                _ => Err(format!("可选值为 {}", options.join("/"))),
+            },
+            SettingKind::AppRules => serde_json::from_value::<Vec<AppRule>>(value.clone())
+                .map_err(|e| format!("需要应用规则列表: {}", e))
+                .and_then(|rules| AppRules::validate(&rules)),
+        }
+    }
+}
+
+fn find_spec(key: &str) -> Option<&'static SettingSpec> {
+    SETTINGS_SCHEMA.iter().find(|spec| spec.key == key)
+}
+
+/// 读取用户设置（未设置的项使用默认值）
+pub async fn load_settings(
+    user_id: &str,
+    pool: &DbPool,
+) -> Result<Map<String, Value>, sqlx::Error> {
+    let mut settings: Map<String, Value> = SETTINGS_SCHEMA
+        .iter()
+        .map(|spec| (spec.key.to_string(), spec.default_value()))
+        .collect();
+
+    for (key, raw) in db::get_user_settings(user_id, pool).await? {
+        // 跳过已废弃或损坏的设置项
+        if find_spec(&key).is_none() {
+            continue;
+        }
+        match serde_json::from_str(&raw) {
+            Ok(value) => {
+                settings.insert(key, value);
+            }
+            Err(e) => warn!("用户 {} 的设置项 {} 无法解析: {}", user_id, key, e),
+        }
+    }
+    Ok(settings)
+}
+
+/// 校验设置修改，返回待写入的 (key, JSON 文本)，值为 null 时为 None（恢复默认）
+pub fn parse_changes(
+    changes: Map<String, Value>,
+) -> Result<Vec<(String, Option<String>)>, String> {
+    let mut updates = Vec::with_capacity(changes.len());
+    for (key, value) in changes {
+        let Some(spec) = find_spec(&key) else {
+            return Err(format!("未知的设置项: {}", key));
+        };
+        if value.is_null() {
+            updates.push((key, None));
+            continue;
+        }
+        if let Err(reason) = spec.validate(&value) {
+            return Err(format!("设置项 {} 无效: {}", key, reason));
+        }
+        updates.push((key, Some(value.to_string())));
+    }
+    Ok(updates)
+}
+
+// 获取用户设置
+#[utoipa::path(
+    summary = "获取用户设置",
+    security(("bearer" = [])),
+    responses((status = 200, description = "data 为所有设置项（含默认值）", body = ApiEnvelope<Value>)),
+)]
+#[get("/settings")]
+pub(crate) async fn get_settings(
+    pool: web::Data<DbPool>,
+    bearer_token: BearerToken,
+) -> impl Responder {
+    match load_settings(&bearer_token.user_id, &pool).await {
+        Ok(settings) => {
+            ApiResponse::new("获取设置成功", ResponseData::Json(Value::Object(settings)))
+        }
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取设置失败"),
+    }
+}
+
+// 修改用户设置（部分更新，值为 null 表示恢复默认）
+#[utoipa::path(
+    summary = "修改用户设置",
+    request_body(content = Object, description = "要修改的设置项，值为 null 表示恢复默认"),
+    security(("bearer" = [])),
+    responses((status = 200, description = "data 为修改后的所有设置项", body = ApiEnvelope<Value>)),
+)]
+#[put("/settings")]
+pub(crate) async fn put_settings(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    changes: web::Json<Map<String, Value>>,
+) -> impl Responder {
+    info!("修改用户设置: {:?}", changes.keys().collect::<Vec<_>>());
+
+    let updates = match parse_changes(changes.into_inner()) {
+        Ok(updates) => updates,
+        Err(reason) => return ApiResponse::error(ErrorCode::InvalidRequest, &reason),
+    };
+
+    if db::upsert_user_settings(&bearer_token.user_id, &updates, &pool)
+        .await
+        .is_err()
+    {
+        return ApiResponse::error(ErrorCode::InternalError, "设置保存失败");
+    }
+
+    match load_settings(&bearer_token.user_id, &pool).await {
+        Ok(settings) => {
+            // 同步给该用户的所有在线设备
+            app_state.room_manager.do_send(PushEvent {
+                user_id: bearer_token.user_id.clone(),
+                event: "settings_changed".to_string(),
+                payload: Value::Object(settings.clone()),
+            });
+            ApiResponse::new("设置保存成功", ResponseData::Json(Value::Object(settings)))
+        }
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "设置保存失败"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn validate(key: &str, value: Value) -> Result<(), String> {
+        find_spec(key).unwrap().validate(&value)
+    }
+
+    #[test]
+    fn validate_bool() {
+        assert!(validate("dnd_enabled", json!(true)).is_ok());
+        assert!(validate("dnd_enabled", json!("true")).is_err());
+    }
+
+    #[test]
+    fn validate_integer_range() {
+        assert!(validate("retention_max_age_days", json!(0)).is_ok());
+        assert!(validate("retention_max_age_days", json!(3650)).is_ok());
+        assert!(validate("retention_max_age_days", json!(3651)).is_err());
+        assert!(validate("retention_max_age_days", json!(-1)).is_err());
+        assert!(validate("retention_max_age_days", json!(1.5)).is_err());
+    }
+
+    #[test]
+    fn validate_time() {
+        assert!(validate("dnd_start", json!("23:30")).is_ok());
+        assert!(validate("dnd_start", json!("24:00")).is_err());
+        assert!(validate("dnd_start", json!("7pm")).is_err());
+        assert!(validate("dnd_start", json!(2230)).is_err());
+    }
+
+    #[test]
+    fn validate_choice() {
+        assert!(validate("notify_level", json!("important")).is_ok());
+        assert!(validate("notify_level", json!("some")).is_err());
+    }
+
+    #[test]
+    fn validate_app_rules() {
+        let rules = json!([
+            { "source_app": "KeePass", "block": true },
+            { "source_app": "Terminal", "tags": ["term"] },
+        ]);
+        assert!(validate("app_rules", rules).is_ok());
+        let duplicated = json!([{ "source_app": "KeePass" }, { "source_app": "keepass " }]);
+        assert!(validate("app_rules", duplicated).is_err());
+        assert!(validate("app_rules", json!([{ "source_app": " " }])).is_err());
+        assert!(validate("app_rules", json!([{ "block": true }])).is_err());
+    }
+
+    #[test]
+    fn parse_changes_rejects_unknown_keys_and_resets_null() {
+        let changes = json!({ "dnd_enabled": true, "notify_level": null });
+        let updates = parse_changes(changes.as_object().unwrap().clone()).unwrap();
+        assert!(updates.contains(&("dnd_enabled".to_string(), Some("true".to_string()))));
+        assert!(updates.contains(&("notify_level".to_string(), None)));
+
+        let changes = json!({ "unknown": 1 });
+        assert!(parse_changes(changes.as_object().unwrap().clone()).is_err());
+    }
+}
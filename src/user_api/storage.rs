@@ -0,0 +1,109 @@
+use actix_web::{Responder, get, web};
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+
+use crate::{
+    blob_store::blob_store,
+    spatial_api::binary,
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiEnvelope, ApiResponse, ErrorCode, ResponseData},
+    },
+    user_api::auth::BearerToken,
+};
+
+/// 返回的最大项目数量
+const LARGEST_CLIPS_LIMIT: i64 = 10;
+
+// 头像文件在存储中的对象名
+pub fn head_key(head_uri: &str) -> String {
+    format!("heads/{}", head_uri)
+}
+
+/// 某一内容类型（ClipType）的占用
+#[derive(Debug, Default, Serialize)]
+pub struct TypeUsage {
+    pub count: i64,
+    pub bytes: i64,
+}
+
+/// 单个剪贴板项目的占用，用于提示用户清理
+#[derive(Debug, Serialize)]
+pub struct ClipUsage {
+    /// clip / scheduled_clip
+    pub kind: String,
+    pub id: String,
+    pub content_type: String,
+    pub bytes: i64,
+    pub created_at: i64,
+}
+
+// 获取存储占用：按内容类型统计，列出最大的项目，并区分文件（blob）与数据库占用
+#[utoipa::path(
+    summary = "获取存储占用",
+    security(("bearer" = [])),
+    responses((status = 200, body = ApiEnvelope<Value>)),
+)]
+#[get("/storage")]
+pub async fn get_storage(pool: web::Data<DbPool>, bearer_token: BearerToken) -> impl Responder {
+    storage_report(&bearer_token.user_id, &pool).await
+}
+
+// 统计用户的存储占用（管理接口也使用）
+pub async fn storage_report(user_id: &str, pool: &DbPool) -> ApiResponse {
+    let result = tokio::try_join!(
+        db::get_clip_usage(user_id, pool),
+        db::get_largest_clips(user_id, LARGEST_CLIPS_LIMIT, pool),
+        db::get_sync_conflict_bytes(user_id, pool),
+        db::get_user_by_id(user_id, pool),
+    );
+    let (usage, largest, conflict_bytes, user) = match result {
+        Ok(result) => result,
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "获取存储占用失败"),
+    };
+
+    // 按内容类型合并普通剪贴板和定时投递，同时按来源汇总字节数
+    let mut by_type: BTreeMap<String, TypeUsage> = BTreeMap::new();
+    let mut by_kind: BTreeMap<String, i64> = BTreeMap::new();
+    for (kind, content_type, type_usage) in usage {
+        *by_kind.entry(kind).or_default() += type_usage.bytes;
+        let entry = by_type.entry(content_type).or_default();
+        entry.count += type_usage.count;
+        entry.bytes += type_usage.bytes;
+    }
+    let clip_bytes: i64 = by_kind.values().sum();
+    let db_bytes = clip_bytes + conflict_bytes;
+
+    // 头像以文件形式保存，文件不存在时按 0 计算
+    let mut avatar_bytes = 0;
+    if let Some(head_uri) = user.head_uri
+        && let Ok(metadata) = blob_store().stat(&head_key(&head_uri)).await
+    {
+        avatar_bytes = metadata.size as i64;
+    }
+    // 通过 WebSocket 二进制帧推送的图片 / RTF 剪贴板
+    let clip_file_bytes: i64 = match binary::user_clip_files(user_id).await {
+        Ok(files) => files.iter().map(|(_, size)| *size as i64).sum(),
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "获取存储占用失败"),
+    };
+    let blob_bytes = avatar_bytes + clip_file_bytes;
+
+    ApiResponse::new(
+        "获取存储占用成功",
+        ResponseData::Json(json!({
+            "total_bytes": db_bytes + blob_bytes,
+            "db_bytes": db_bytes,
+            "blob_bytes": blob_bytes,
+            "by_type": by_type,
+            "largest_clips": largest,
+            "breakdown": {
+                "clips": by_kind.get("clip").copied().unwrap_or(0),
+                "scheduled_clips": by_kind.get("scheduled_clip").copied().unwrap_or(0),
+                "sync_conflicts": conflict_bytes,
+                "clip_files": clip_file_bytes,
+                "avatar": avatar_bytes,
+            },
+        })),
+    )
+}
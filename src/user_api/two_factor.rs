@@ -0,0 +1,363 @@
+use actix_web::{HttpRequest, Responder, delete, post, web};
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::{Engine as _, engine::general_purpose};
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use log::{info, warn};
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use utoipa::ToSchema;
+
+use crate::{
+    audit::{AuditEvent, AuditRecord},
+    spatial_api::models::AppState,
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiEnvelope, ApiResponse, ErrorCode, ResponseData},
+    },
+    user_api::auth::{BearerToken, missing_secret, required_secret},
+};
+
+/// 验证码的时间步长（秒）
+const TOTP_PERIOD: i64 = 30;
+/// 验证码位数
+const TOTP_DIGITS: u32 = 6;
+/// 允许的时钟偏差（前后各一个时间步）
+const TOTP_SKEW: i64 = 1;
+/// 两步验证密钥的加密密钥所在的环境变量
+const ENCRYPTION_KEY_ENV: &str = "TOTP_ENCRYPTION_KEY";
+/// 启用时生成的恢复码数量
+const RECOVERY_CODE_COUNT: usize = 10;
+/// otpauth URI 中的签发方
+const ISSUER: &str = "ClipFocus";
+
+// RFC 4226 HOTP（HMAC-SHA1，动态截断）
+fn hotp(secret: &[u8], counter: u64) -> String {
+    let mut mac =
+        <Hmac<Sha1> as Mac>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = usize::from(hash[hash.len() - 1] & 0x0f);
+    let value = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    format!(
+        "{:0width$}",
+        value % 10u32.pow(TOTP_DIGITS),
+        width = TOTP_DIGITS as usize
+    )
+}
+
+// 在允许的时钟偏差内查找与验证码匹配的时间步（RFC 6238），不匹配时返回 None
+fn verify_totp(secret: &[u8], code: &str, now_secs: i64) -> Option<i64> {
+    let current = now_secs / TOTP_PERIOD;
+    (current - TOTP_SKEW..=current + TOTP_SKEW)
+        .filter(|step| *step >= 0)
+        .find(|step| bool::from(hotp(secret, *step as u64).as_bytes().ct_eq(code.as_bytes())))
+}
+
+// 加密密钥由 TOTP_ENCRYPTION_KEY 派生，数据库泄露时无法直接得到两步验证密钥；
+// 未设置时返回 None，不能启用两步验证
+fn cipher() -> Option<Aes256Gcm> {
+    let secret = required_secret(ENCRYPTION_KEY_ENV)?;
+    Some(cipher_for(&secret))
+}
+
+fn cipher_for(secret: &str) -> Aes256Gcm {
+    let key = Sha256::digest(secret.as_bytes());
+    Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))
+}
+
+// 加密两步验证密钥，结果为 base64(nonce || 密文)
+fn encrypt_secret(cipher: &Aes256Gcm, secret: &[u8]) -> Result<String, aes_gcm::Error> {
+    let nonce: [u8; 12] = rand::random();
+    let mut sealed = nonce.to_vec();
+    sealed.extend(cipher.encrypt(Nonce::from_slice(&nonce), secret)?);
+    Ok(general_purpose::STANDARD.encode(sealed))
+}
+
+fn decrypt_secret(cipher: &Aes256Gcm, sealed: &str) -> Option<Vec<u8>> {
+    let sealed = general_purpose::STANDARD.decode(sealed).ok()?;
+    if sealed.len() < 12 {
+        return None;
+    }
+    let (nonce, ciphertext) = sealed.split_at(12);
+    cipher.decrypt(Nonce::from_slice(nonce), ciphertext).ok()
+}
+
+/// 启动时检查：已有用户启用两步验证时必须设置 TOTP_ENCRYPTION_KEY
+pub async fn check_encryption_key(pool: &DbPool) -> Result<(), String> {
+    if required_secret(ENCRYPTION_KEY_ENV).is_some() {
+        return Ok(());
+    }
+    let enabled = db::count_totp_users(pool)
+        .await
+        .map_err(|e| format!("count two-factor users failed: {}", e))?;
+    if enabled > 0 {
+        return Err(format!(
+            "{} is not set but {} users have two-factor authentication enabled",
+            ENCRYPTION_KEY_ENV, enabled
+        ));
+    }
+    warn!(
+        "{} not set, two-factor authentication cannot be enabled",
+        ENCRYPTION_KEY_ENV
+    );
+    Ok(())
+}
+
+// 恢复码格式为 xxxx-xxxx，输入时忽略大小写、空格和连字符
+fn generate_recovery_code() -> String {
+    let code = BASE32_NOPAD
+        .encode(&rand::random::<[u8; 5]>())
+        .to_lowercase();
+    format!("{}-{}", &code[..4], &code[4..])
+}
+
+fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    blake3::hash(normalized.as_bytes()).to_hex().to_string()
+}
+
+fn now_secs() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+// 校验并消耗一个 TOTP 验证码，同一时间步的验证码只能使用一次
+async fn use_totp_code(
+    user_id: &str,
+    sealed_secret: &str,
+    code: &str,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    let Some(secret) = cipher().and_then(|cipher| decrypt_secret(&cipher, sealed_secret)) else {
+        warn!("用户 {} 的两步验证密钥无法解密", user_id);
+        return Ok(false);
+    };
+    match verify_totp(&secret, code.trim(), now_secs()) {
+        Some(step) => db::use_totp_step(user_id, step, pool).await,
+        None => Ok(false),
+    }
+}
+
+/// 登录时校验两步验证码：6 位数字按 TOTP 验证码校验，其他输入按恢复码校验（使用后失效）
+pub async fn check_login_code(
+    user_id: &str,
+    code: &str,
+    pool: &DbPool,
+) -> Result<bool, sqlx::Error> {
+    let code = code.trim();
+    if code.len() == TOTP_DIGITS as usize && code.bytes().all(|b| b.is_ascii_digit()) {
+        match db::get_totp_secret(user_id, pool).await? {
+            Some((sealed, true)) => use_totp_code(user_id, &sealed, code, pool).await,
+            _ => Ok(false),
+        }
+    } else {
+        db::use_recovery_code(user_id, &hash_recovery_code(code), pool).await
+    }
+}
+
+/// 开始启用两步验证时返回的密钥
+#[derive(Serialize, ToSchema)]
+pub struct TotpEnrollment {
+    /// Base32 编码的密钥，供无法扫码的验证器手动输入
+    secret: String,
+    /// 验证器扫码使用的 otpauth URI
+    otpauth_uri: String,
+}
+
+#[utoipa::path(
+    summary = "开始启用两步验证",
+    description = "生成新的密钥，需要调用 /user/2fa/verify 提交验证器生成的验证码后才会启用",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, body = ApiEnvelope<TotpEnrollment>),
+        (status = 409, description = "已启用两步验证", body = ApiEnvelope<serde_json::Value>),
+    ),
+)]
+#[post("/2fa/enroll")]
+async fn enroll(pool: web::Data<DbPool>, bearer_token: BearerToken) -> impl Responder {
+    let Some(cipher) = cipher() else {
+        return missing_secret(ENCRYPTION_KEY_ENV);
+    };
+    let secret: [u8; 20] = rand::random();
+    let sealed = match encrypt_secret(&cipher, &secret) {
+        Ok(sealed) => sealed,
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "生成密钥失败"),
+    };
+    match db::set_pending_totp_secret(&bearer_token.user_id, &sealed, &pool).await {
+        Ok(true) => {}
+        Ok(false) => return ApiResponse::error(ErrorCode::AlreadyExists, "已启用两步验证"),
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "生成密钥失败"),
+    }
+    let secret = BASE32_NOPAD.encode(&secret);
+    let label = format!("{}:{}", ISSUER, bearer_token.username);
+    let otpauth_uri = format!(
+        "otpauth://totp/{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        utf8_percent_encode(&label, NON_ALPHANUMERIC),
+        secret,
+        ISSUER,
+        TOTP_DIGITS,
+        TOTP_PERIOD
+    );
+    ApiResponse::new(
+        "请使用验证器扫码后提交验证码",
+        ResponseData::Json(serde_json::json!(TotpEnrollment {
+            secret,
+            otpauth_uri
+        })),
+    )
+}
+
+/// 验证器生成的验证码
+#[derive(Deserialize, ToSchema)]
+pub struct TotpCode {
+    code: String,
+}
+
+/// 启用两步验证后返回的恢复码
+#[derive(Serialize, ToSchema)]
+pub struct RecoveryCodes {
+    /// 一次性恢复码，无法使用验证器时代替验证码登录，只在启用时返回一次
+    recovery_codes: Vec<String>,
+}
+
+#[utoipa::path(
+    summary = "确认启用两步验证",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, body = ApiEnvelope<RecoveryCodes>),
+        (status = 401, description = "验证码错误", body = ApiEnvelope<serde_json::Value>),
+    ),
+)]
+#[post("/2fa/verify")]
+async fn verify(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    request: web::Json<TotpCode>,
+) -> impl Responder {
+    let Some(cipher) = cipher() else {
+        return missing_secret(ENCRYPTION_KEY_ENV);
+    };
+    let user_id = &bearer_token.user_id;
+    let sealed = match db::get_totp_secret(user_id, &pool).await {
+        Ok(Some((sealed, false))) => sealed,
+        Ok(Some((_, true))) => {
+            return ApiResponse::error(ErrorCode::AlreadyExists, "已启用两步验证");
+        }
+        Ok(None) => return ApiResponse::error(ErrorCode::NotFound, "请先开始启用两步验证"),
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "启用两步验证失败"),
+    };
+    let step = decrypt_secret(&cipher, &sealed)
+        .and_then(|secret| verify_totp(&secret, request.code.trim(), now_secs()));
+    let Some(step) = step else {
+        return ApiResponse::error(ErrorCode::TwoFactorInvalid, "验证码错误");
+    };
+    let recovery_codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| generate_recovery_code())
+        .collect();
+    let hashes: Vec<String> = recovery_codes
+        .iter()
+        .map(|code| hash_recovery_code(code))
+        .collect();
+    match db::enable_totp(user_id, step, &hashes, &pool).await {
+        Ok(true) => {
+            info!("用户 {} 已启用两步验证", user_id);
+            AuditRecord::new(AuditEvent::TwoFactorEnabled, &req, &app_state)
+                .user(user_id)
+                .record(&pool)
+                .await;
+            ApiResponse::new(
+                "已启用两步验证，请妥善保存恢复码",
+                ResponseData::Json(serde_json::json!(RecoveryCodes { recovery_codes })),
+            )
+        }
+        Ok(false) => ApiResponse::error(ErrorCode::AlreadyExists, "已启用两步验证"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "启用两步验证失败"),
+    }
+}
+
+#[utoipa::path(
+    summary = "关闭两步验证",
+    description = "需要提交验证码或恢复码确认",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, body = ApiEnvelope<serde_json::Value>),
+        (status = 401, description = "验证码错误", body = ApiEnvelope<serde_json::Value>),
+    ),
+)]
+#[delete("/2fa")]
+async fn disable(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    request: web::Json<TotpCode>,
+) -> impl Responder {
+    let user_id = &bearer_token.user_id;
+    match db::get_totp_secret(user_id, &pool).await {
+        Ok(Some((_, true))) => {}
+        Ok(_) => return ApiResponse::error(ErrorCode::NotFound, "未启用两步验证"),
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "关闭两步验证失败"),
+    }
+    match check_login_code(user_id, &request.code, &pool).await {
+        Ok(true) => {}
+        Ok(false) => return ApiResponse::error(ErrorCode::TwoFactorInvalid, "验证码错误"),
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "关闭两步验证失败"),
+    }
+    if db::disable_totp(user_id, &pool).await.is_err() {
+        return ApiResponse::error(ErrorCode::InternalError, "关闭两步验证失败");
+    }
+    info!("用户 {} 已关闭两步验证", user_id);
+    AuditRecord::new(AuditEvent::TwoFactorDisabled, &req, &app_state)
+        .user(user_id)
+        .record(&pool)
+        .await;
+    ApiResponse::new("已关闭两步验证", ResponseData::Null)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn totp_matches_rfc6238_vectors() {
+        // RFC 6238 附录 B 的 SHA1 测试向量（取末 6 位）
+        let secret = b"12345678901234567890";
+        assert_eq!(hotp(secret, 59 / 30), "287082");
+        assert_eq!(hotp(secret, 1111111109 / 30), "081804");
+        assert_eq!(verify_totp(secret, "287082", 59), Some(1));
+        // 相邻时间步内仍然有效，超出偏差范围无效
+        assert_eq!(verify_totp(secret, "287082", 89), Some(1));
+        assert_eq!(verify_totp(secret, "287082", 150), None);
+    }
+
+    #[test]
+    fn secret_round_trips_and_recovery_codes_normalize() {
+        let cipher = cipher_for("test-key");
+        let sealed = encrypt_secret(&cipher, b"secret").unwrap();
+        assert_eq!(decrypt_secret(&cipher, &sealed).unwrap(), b"secret");
+        assert!(decrypt_secret(&cipher_for("other-key"), &sealed).is_none());
+        assert!(decrypt_secret(&cipher, "AAAA").is_none());
+
+        let code = generate_recovery_code();
+        assert_eq!(code.len(), 9);
+        assert_eq!(
+            hash_recovery_code(&code),
+            hash_recovery_code(&code.replace('-', "").to_uppercase())
+        );
+    }
+}
@@ -2,10 +2,28 @@ use actix_web::{web, Error};
 use futures::StreamExt;
 use tokio::{fs, io::AsyncWriteExt};
 
+/// 保存请求体失败的原因
+#[derive(Debug)]
+pub enum SavePayloadError {
+    /// 请求体超过大小限制，附带中止前已读取的字节数
+    TooLarge(usize),
+    Other(Error),
+}
+
+impl<E: Into<Error>> From<E> for SavePayloadError {
+    fn from(e: E) -> Self {
+        SavePayloadError::Other(e.into())
+    }
+}
+
+// 把请求体写入文件，超过 max_size 时中止并删除已写入的部分
+//
+// 按实际读取的字节数计算大小，不依赖 Content-Length（分块上传没有该请求头，且请求头可以伪造）
 pub async fn save_payload_with_dirs(
     mut payload: web::Payload,
     file_path: &str,
-) -> Result<(), Error> {
+    max_size: Option<usize>,
+) -> Result<usize, SavePayloadError> {
     // 自动创建目录
     if let Some(parent) = std::path::Path::new(file_path).parent() {
         fs::create_dir_all(parent).await?;
@@ -13,10 +31,18 @@ pub async fn save_payload_with_dirs(
     
     // 创建文件并写入数据
     let mut file = fs::File::create(file_path).await?;
+    let mut size = 0;
     
     while let Some(chunk) = payload.next().await {
-        tokio::io::AsyncWriteExt::write_all(&mut file, &chunk?).await?;
+        let chunk = chunk?;
+        size += chunk.len();
+        if max_size.is_some_and(|max_size| size > max_size) {
+            drop(file);
+            let _ = fs::remove_file(file_path).await;
+            return Err(SavePayloadError::TooLarge(size));
+        }
+        file.write_all(&chunk).await?;
     }
     
-    Ok(())
+    Ok(size)
 }
\ No newline at end of file
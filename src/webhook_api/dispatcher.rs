@@ -0,0 +1,309 @@
+use actix::prelude::*;
+use hmac::{Hmac, Mac};
+use log::{info, warn};
+use sha2::Sha256;
+use std::time::Duration;
+
+use crate::config::WebhookConfig;
+use crate::sqlx_utils::{DbPool, db};
+use crate::webhook_api::WebhookDelivery;
+
+/// 每次最多取出的待投递事件数
+const DELIVERY_BATCH: i64 = 100;
+
+/// 投递记录的清理间隔
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// 接收方错误信息的最大长度
+const MAX_ERROR_LEN: usize = 500;
+
+/// 已到尝试时间的投递，以及发送所需的 Webhook 地址、密钥和请求体
+pub struct DueDelivery {
+    pub delivery: WebhookDelivery,
+    pub url: String,
+    pub secret: String,
+    pub payload: String,
+}
+
+/// Webhook 投递器，周期性地发送到期的事件，失败时按指数退避重试
+pub struct WebhookDispatcher {
+    pool: DbPool,
+    config: WebhookConfig,
+}
+
+impl WebhookDispatcher {
+    pub fn new(pool: DbPool, config: WebhookConfig) -> Self {
+        Self { pool, config }
+    }
+}
+
+/// 请求签名：`sha256=` 加上以密钥对 `{timestamp}.{body}` 计算的 HMAC-SHA256（十六进制）
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+// 第 attempts 次尝试失败后等待的时间（秒）：从 retry_base_secs 开始每次翻倍，不超过 retry_max_secs
+fn retry_delay(attempts: i64, config: &WebhookConfig) -> i64 {
+    let exponent = attempts.clamp(1, 31) as u32 - 1;
+    config
+        .retry_base_secs
+        .saturating_mul(1 << exponent)
+        .min(config.retry_max_secs)
+}
+
+// 记录一次尝试的结果：成功时完成投递，失败时安排重试，次数用完后放弃
+fn record_attempt(
+    delivery: &mut WebhookDelivery,
+    result: Result<u16, String>,
+    now: i64,
+    config: &WebhookConfig,
+) {
+    delivery.attempts += 1;
+    let error = match result {
+        Ok(status) => {
+            delivery.response_status = Some(status as i64);
+            (!(200..300).contains(&status)).then(|| format!("HTTP {}", status))
+        }
+        Err(e) => {
+            delivery.response_status = None;
+            Some(e.chars().take(MAX_ERROR_LEN).collect())
+        }
+    };
+    match error {
+        None => {
+            delivery.status = "delivered".to_string();
+            delivery.next_attempt_at = None;
+            delivery.delivered_at = Some(now);
+        }
+        Some(_) if delivery.attempts >= config.max_attempts as i64 => {
+            delivery.status = "failed".to_string();
+            delivery.next_attempt_at = None;
+        }
+        Some(_) => delivery.next_attempt_at = Some(now + retry_delay(delivery.attempts, config)),
+    }
+    delivery.error = error;
+}
+
+// 发送一个事件并记录结果
+async fn deliver(due: DueDelivery, pool: &DbPool, config: &WebhookConfig) {
+    let DueDelivery {
+        mut delivery,
+        url,
+        secret,
+        payload,
+    } = due;
+    // 先把下次尝试时间推迟到请求超时之后，避免多个节点或下一轮检查重复发送
+    let now = chrono::Utc::now().timestamp();
+    let lease_until = now + config.timeout().as_secs() as i64 * 2;
+    match db::claim_webhook_delivery(&delivery, lease_until, pool).await {
+        Ok(true) => {}
+        Ok(false) => return,
+        Err(e) => {
+            warn!("claim webhook delivery {} failed: {}", delivery.id, e);
+            return;
+        }
+    }
+
+    let signature = sign(&secret, now, &payload);
+    let result = client::post(&url, &delivery, now, &signature, payload, config.timeout()).await;
+    record_attempt(
+        &mut delivery,
+        result,
+        chrono::Utc::now().timestamp(),
+        config,
+    );
+    if delivery.status == "failed" {
+        info!(
+            "Webhook 投递 {} 失败 {} 次，不再重试: {}",
+            delivery.id,
+            delivery.attempts,
+            delivery.error.as_deref().unwrap_or_default()
+        );
+    }
+    if let Err(e) = db::finish_webhook_attempt(&delivery, pool).await {
+        warn!("save webhook delivery {} failed: {}", delivery.id, e);
+    }
+}
+
+// 发送所有已到尝试时间的事件
+async fn deliver_due(pool: DbPool, config: WebhookConfig) {
+    let now = chrono::Utc::now().timestamp();
+    let due = match db::get_due_webhook_deliveries(now, DELIVERY_BATCH, &pool).await {
+        Ok(due) => due,
+        Err(e) => {
+            warn!("查询待投递的 Webhook 事件失败: {}", e);
+            return;
+        }
+    };
+    futures::future::join_all(due.into_iter().map(|due| deliver(due, &pool, &config))).await;
+}
+
+// 删除超过保留天数的已完成投递记录
+async fn prune_deliveries(pool: DbPool, retention_days: i64) {
+    if retention_days <= 0 {
+        return;
+    }
+    let before = chrono::Utc::now().timestamp() - retention_days * 24 * 3600;
+    match db::delete_webhook_deliveries_before(before, &pool).await {
+        Ok(0) => {}
+        Ok(deleted) => info!("删除 {} 条过期的 Webhook 投递记录", deleted),
+        Err(e) => warn!("清理 Webhook 投递记录失败: {}", e),
+    }
+}
+
+impl Actor for WebhookDispatcher {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        info!("WebhookDispatcher started");
+
+        ctx.run_interval(self.config.poll_interval(), |act, ctx| {
+            deliver_due(act.pool.clone(), act.config.clone())
+                .into_actor(act)
+                .wait(ctx);
+        });
+        ctx.run_interval(PRUNE_INTERVAL, |act, ctx| {
+            prune_deliveries(act.pool.clone(), act.config.log_retention_days)
+                .into_actor(act)
+                .wait(ctx);
+        });
+    }
+}
+
+#[cfg(feature = "webhooks")]
+mod client {
+    use std::sync::OnceLock;
+    use std::time::Duration;
+
+    use crate::webhook_api::WebhookDelivery;
+
+    const USER_AGENT: &str = "ClipFocusServer-Webhook";
+
+    // 所有投递共用连接池
+    fn http() -> &'static reqwest::Client {
+        static HTTP: OnceLock<reqwest::Client> = OnceLock::new();
+        HTTP.get_or_init(reqwest::Client::new)
+    }
+
+    // 发送事件，返回接收方的 HTTP 状态码
+    pub async fn post(
+        url: &str,
+        delivery: &WebhookDelivery,
+        timestamp: i64,
+        signature: &str,
+        payload: String,
+        timeout: Duration,
+    ) -> Result<u16, String> {
+        let response = http()
+            .post(url)
+            .timeout(timeout)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(reqwest::header::USER_AGENT, USER_AGENT)
+            .header("X-ClipFocus-Event", delivery.event.as_str())
+            .header("X-ClipFocus-Delivery", &delivery.id)
+            .header("X-ClipFocus-Timestamp", timestamp.to_string())
+            .header("X-ClipFocus-Signature", signature)
+            .body(payload)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(response.status().as_u16())
+    }
+}
+
+#[cfg(not(feature = "webhooks"))]
+mod client {
+    use std::time::Duration;
+
+    use crate::webhook_api::WebhookDelivery;
+
+    pub async fn post(
+        _url: &str,
+        _delivery: &WebhookDelivery,
+        _timestamp: i64,
+        _signature: &str,
+        _payload: String,
+        _timeout: Duration,
+    ) -> Result<u16, String> {
+        Err("the webhooks feature is disabled".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webhook_api::WebhookEvent;
+
+    fn delivery() -> WebhookDelivery {
+        WebhookDelivery {
+            id: "d1".to_string(),
+            webhook_id: "w1".to_string(),
+            event: WebhookEvent::ClipCreated,
+            status: "pending".to_string(),
+            attempts: 0,
+            next_attempt_at: Some(0),
+            response_status: None,
+            error: None,
+            created_at: 0,
+            delivered_at: None,
+        }
+    }
+
+    #[test]
+    fn sign_covers_timestamp_and_body() {
+        let signature = sign("whsec_test", 1700000000, r#"{"event":"clip.created"}"#);
+        assert!(signature.starts_with("sha256="));
+        assert_eq!(signature.len(), "sha256=".len() + 64);
+        assert_ne!(
+            signature,
+            sign("whsec_test", 1700000001, r#"{"event":"clip.created"}"#)
+        );
+        assert_ne!(
+            signature,
+            sign("whsec_other", 1700000000, r#"{"event":"clip.created"}"#)
+        );
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_max() {
+        let config = WebhookConfig {
+            retry_base_secs: 30,
+            retry_max_secs: 100,
+            ..WebhookConfig::default()
+        };
+        assert_eq!(retry_delay(1, &config), 30);
+        assert_eq!(retry_delay(2, &config), 60);
+        assert_eq!(retry_delay(3, &config), 100);
+        assert_eq!(retry_delay(64, &config), 100);
+    }
+
+    #[test]
+    fn record_attempt_retries_then_gives_up() {
+        let config = WebhookConfig {
+            max_attempts: 2,
+            retry_base_secs: 10,
+            ..WebhookConfig::default()
+        };
+        let mut delivery = delivery();
+        record_attempt(&mut delivery, Ok(500), 100, &config);
+        assert_eq!(delivery.status, "pending");
+        assert_eq!(delivery.next_attempt_at, Some(110));
+        assert_eq!(delivery.error.as_deref(), Some("HTTP 500"));
+
+        record_attempt(&mut delivery, Err("timed out".to_string()), 110, &config);
+        assert_eq!(delivery.status, "failed");
+        assert_eq!(delivery.next_attempt_at, None);
+        assert_eq!(delivery.response_status, None);
+
+        let mut delivery = self::delivery();
+        record_attempt(&mut delivery, Ok(204), 100, &config);
+        assert_eq!(delivery.status, "delivered");
+        assert_eq!(delivery.delivered_at, Some(100));
+        assert_eq!(delivery.error, None);
+    }
+}
@@ -0,0 +1,416 @@
+pub mod dispatcher;
+
+use actix_web::{Responder, delete, get, post, put, web};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    spatial_api::models::AppState,
+    sqlx_utils::{
+        DbPool, db,
+        models::{ApiResponse, Cursor, ErrorCode, Page, ResponseData},
+    },
+    user_api::auth::BearerToken,
+};
+
+pub fn webhook_api() -> actix_web::Scope {
+    web::scope("/webhooks")
+        .service(list_webhooks)
+        .service(create_webhook)
+        .service(get_webhook)
+        .service(update_webhook)
+        .service(delete_webhook)
+        .service(list_deliveries)
+}
+
+/// 地址的最大长度
+const MAX_URL_LEN: usize = 2048;
+
+/// 投递记录列表默认 / 最大返回数量
+const DEFAULT_DELIVERY_LIMIT: i64 = 50;
+const MAX_DELIVERY_LIMIT: i64 = 200;
+
+/// 可以订阅的事件
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum WebhookEvent {
+    /// 新建剪贴板（包括从回收站恢复），data 为剪贴板
+    #[serde(rename = "clip.created")]
+    ClipCreated,
+    /// 剪贴板被删除或被保留策略清理，data 为 `{ "id": ... }`
+    #[serde(rename = "clip.deleted")]
+    ClipDeleted,
+    /// 注册了新设备，data 为设备
+    #[serde(rename = "device.registered")]
+    DeviceRegistered,
+}
+
+impl WebhookEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::ClipCreated => "clip.created",
+            WebhookEvent::ClipDeleted => "clip.deleted",
+            WebhookEvent::DeviceRegistered => "device.registered",
+        }
+    }
+
+    /// 推送给设备的剪贴板事件对应的 Webhook 事件
+    pub fn for_clip_event(event: &str) -> Option<Self> {
+        match event {
+            "clip_created" => Some(WebhookEvent::ClipCreated),
+            "clip_deleted" | "clip.expired" => Some(WebhookEvent::ClipDeleted),
+            _ => None,
+        }
+    }
+}
+
+/// 用户注册的 Webhook，订阅的事件以 POST 请求发送到 url
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Webhook {
+    pub id: String,
+    pub url: String,
+    /// 签名密钥，只在创建时返回
+    #[serde(skip)]
+    pub secret: String,
+    pub events: Vec<WebhookEvent>,
+    /// 停用后不再生成新的投递，尚未完成的投递也会暂停
+    pub enabled: bool,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// 一次事件投递及其最近一次尝试的结果
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub webhook_id: String,
+    pub event: WebhookEvent,
+    /// pending / delivered / failed（重试次数用完后放弃）
+    pub status: String,
+    pub attempts: i64,
+    /// 下次尝试的时间，投递成功或放弃后为空
+    pub next_attempt_at: Option<i64>,
+    /// 最近一次尝试时接收方返回的 HTTP 状态码
+    pub response_status: Option<i64>,
+    /// 最近一次尝试失败的原因
+    pub error: Option<String>,
+    pub created_at: i64,
+    pub delivered_at: Option<i64>,
+}
+
+// 校验接收地址：只允许 https://（配置允许时也可以是 http://），且必须包含主机名
+fn normalize_url(url: &str, allow_http: bool) -> Result<String, &'static str> {
+    let url = url.trim();
+    if url.len() > MAX_URL_LEN || url.chars().any(char::is_whitespace) {
+        return Err("无效的地址");
+    }
+    let lower = url.to_ascii_lowercase();
+    let rest = if let Some(rest) = lower.strip_prefix("https://") {
+        rest
+    } else if let Some(rest) = lower.strip_prefix("http://").filter(|_| allow_http) {
+        rest
+    } else if allow_http {
+        return Err("地址必须以 https:// 或 http:// 开头");
+    } else {
+        return Err("地址必须以 https:// 开头");
+    };
+    let host = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = host.rsplit('@').next().unwrap_or_default();
+    if host.is_empty() || host.starts_with(':') {
+        return Err("地址缺少主机名");
+    }
+    Ok(url.to_string())
+}
+
+// 去除重复的事件，不能为空
+fn normalize_events(mut events: Vec<WebhookEvent>) -> Result<Vec<WebhookEvent>, &'static str> {
+    events.sort_by_key(|event| event.as_str());
+    events.dedup();
+    if events.is_empty() {
+        return Err("至少需要订阅一个事件");
+    }
+    Ok(events)
+}
+
+// 生成签名密钥
+fn generate_secret() -> String {
+    format!("whsec_{}", hex::encode(rand::random::<[u8; 32]>()))
+}
+
+// 为用户订阅了该事件的 Webhook 生成投递记录，由投递器在后台发送
+//
+// 失败只记录日志，不影响触发事件的操作
+pub async fn enqueue(user_id: &str, event: WebhookEvent, data: Value, pool: &DbPool) {
+    let webhooks = match db::get_enabled_webhooks(user_id, pool).await {
+        Ok(webhooks) => webhooks,
+        Err(e) => {
+            warn!("load webhooks of {} failed: {}", user_id, e);
+            return;
+        }
+    };
+    let now = chrono::Utc::now().timestamp();
+    for webhook in webhooks
+        .iter()
+        .filter(|webhook| webhook.events.contains(&event))
+    {
+        let delivery = WebhookDelivery {
+            id: uuid::Uuid::new_v4().to_string(),
+            webhook_id: webhook.id.clone(),
+            event,
+            status: "pending".to_string(),
+            attempts: 0,
+            next_attempt_at: Some(now),
+            response_status: None,
+            error: None,
+            created_at: now,
+            delivered_at: None,
+        };
+        let payload = json!({
+            "id": delivery.id,
+            "event": event,
+            "created_at": now,
+            "data": data,
+        });
+        if let Err(e) =
+            db::insert_webhook_delivery(user_id, &delivery, &payload.to_string(), pool).await
+        {
+            warn!(
+                "enqueue {} for webhook {} failed: {}",
+                event.as_str(),
+                webhook.id,
+                e
+            );
+        }
+    }
+}
+
+// 获取 Webhook 列表
+#[get("")]
+async fn list_webhooks(pool: web::Data<DbPool>, bearer_token: BearerToken) -> impl Responder {
+    match db::get_webhooks(&bearer_token.user_id, &pool).await {
+        Ok(webhooks) => ApiResponse::new("获取 Webhook 成功", ResponseData::Json(json!(webhooks))),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取 Webhook 失败"),
+    }
+}
+
+// 注册 Webhook（需要启用 `webhooks` feature），返回的 secret 用于验证请求签名，之后不再返回
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CreateWebhook {
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+}
+
+#[post("")]
+async fn create_webhook(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    create: web::Json<CreateWebhook>,
+) -> impl Responder {
+    if !cfg!(feature = "webhooks") {
+        return ApiResponse::error(ErrorCode::NotFound, "服务器未启用 Webhook");
+    }
+    let config = &app_state.config.webhook;
+    let create = create.into_inner();
+    let url = match normalize_url(&create.url, config.allow_http) {
+        Ok(url) => url,
+        Err(reason) => return ApiResponse::error(ErrorCode::InvalidRequest, reason),
+    };
+    let events = match normalize_events(create.events) {
+        Ok(events) => events,
+        Err(reason) => return ApiResponse::error(ErrorCode::InvalidRequest, reason),
+    };
+    match db::get_webhooks(&bearer_token.user_id, &pool).await {
+        Ok(webhooks) if webhooks.len() >= config.max_per_user => {
+            return ApiResponse::error(
+                ErrorCode::QuotaExceeded,
+                &format!("最多注册 {} 个 Webhook", config.max_per_user),
+            );
+        }
+        Ok(_) => {}
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "Webhook 注册失败"),
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let webhook = Webhook {
+        id: uuid::Uuid::new_v4().to_string(),
+        url,
+        secret: generate_secret(),
+        events,
+        enabled: true,
+        created_at: now,
+        updated_at: now,
+    };
+    info!("用户 {} 注册 Webhook {}", bearer_token.user_id, webhook.id);
+
+    match db::insert_webhook(&bearer_token.user_id, &webhook, &pool).await {
+        Ok(_) => {
+            let mut data = json!(webhook);
+            data["secret"] = json!(webhook.secret);
+            ApiResponse::new("Webhook 注册成功", ResponseData::Json(data))
+        }
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "Webhook 注册失败"),
+    }
+}
+
+// 获取单个 Webhook
+#[get("/{id}")]
+async fn get_webhook(
+    pool: web::Data<DbPool>,
+    bearer_token: BearerToken,
+    id: web::Path<String>,
+) -> impl Responder {
+    match db::get_webhook(&bearer_token.user_id, &id, &pool).await {
+        Ok(Some(webhook)) => {
+            ApiResponse::new("获取 Webhook 成功", ResponseData::Json(json!(webhook)))
+        }
+        Ok(None) => ApiResponse::error(ErrorCode::NotFound, "Webhook 不存在"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "获取 Webhook 失败"),
+    }
+}
+
+// 修改 Webhook（只修改提供的字段）
+#[derive(Deserialize, ToSchema)]
+pub struct UpdateWebhook {
+    pub url: Option<String>,
+    pub events: Option<Vec<WebhookEvent>>,
+    pub enabled: Option<bool>,
+}
+
+#[put("/{id}")]
+async fn update_webhook(
+    pool: web::Data<DbPool>,
+    app_state: web::Data<AppState>,
+    bearer_token: BearerToken,
+    id: web::Path<String>,
+    update: web::Json<UpdateWebhook>,
+) -> impl Responder {
+    let mut webhook = match db::get_webhook(&bearer_token.user_id, &id, &pool).await {
+        Ok(Some(webhook)) => webhook,
+        Ok(None) => return ApiResponse::error(ErrorCode::NotFound, "Webhook 不存在"),
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "Webhook 修改失败"),
+    };
+
+    let update = update.into_inner();
+    if let Some(url) = update.url {
+        match normalize_url(&url, app_state.config.webhook.allow_http) {
+            Ok(url) => webhook.url = url,
+            Err(reason) => return ApiResponse::error(ErrorCode::InvalidRequest, reason),
+        }
+    }
+    if let Some(events) = update.events {
+        match normalize_events(events) {
+            Ok(events) => webhook.events = events,
+            Err(reason) => return ApiResponse::error(ErrorCode::InvalidRequest, reason),
+        }
+    }
+    if let Some(enabled) = update.enabled {
+        webhook.enabled = enabled;
+    }
+    webhook.updated_at = chrono::Utc::now().timestamp();
+
+    match db::update_webhook(&bearer_token.user_id, &webhook, &pool).await {
+        Ok(true) => ApiResponse::new("Webhook 修改成功", ResponseData::Json(json!(webhook))),
+        Ok(false) => ApiResponse::error(ErrorCode::NotFound, "Webhook 不存在"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "Webhook 修改失败"),
+    }
+}
+
+// 删除 Webhook 及其投递记录
+#[delete("/{id}")]
+async fn delete_webhook(
+    pool: web::Data<DbPool>,
+    bearer_token: BearerToken,
+    id: web::Path<String>,
+) -> impl Responder {
+    match db::delete_webhook(&bearer_token.user_id, &id, &pool).await {
+        Ok(true) => {
+            info!("用户 {} 删除 Webhook {}", bearer_token.user_id, id);
+            ApiResponse::new("Webhook 已删除", ResponseData::Null)
+        }
+        Ok(false) => ApiResponse::error(ErrorCode::NotFound, "Webhook 不存在"),
+        Err(_) => ApiResponse::error(ErrorCode::InternalError, "Webhook 删除失败"),
+    }
+}
+
+// 投递记录查询参数
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeliveryQuery {
+    pub limit: Option<i64>,
+    /// 上一页返回的 next_cursor
+    #[param(value_type = Option<String>)]
+    pub cursor: Option<Cursor>,
+}
+
+// 获取 Webhook 的投递记录，按时间倒序，按 next_cursor 翻页
+#[get("/{id}/deliveries")]
+async fn list_deliveries(
+    pool: web::Data<DbPool>,
+    bearer_token: BearerToken,
+    id: web::Path<String>,
+    query: web::Query<DeliveryQuery>,
+) -> impl Responder {
+    match db::get_webhook(&bearer_token.user_id, &id, &pool).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return ApiResponse::error(ErrorCode::NotFound, "Webhook 不存在"),
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "获取投递记录失败"),
+    }
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_DELIVERY_LIMIT)
+        .clamp(1, MAX_DELIVERY_LIMIT);
+    // 多查询一条以判断是否还有下一页
+    let (deliveries, total) = match tokio::try_join!(
+        db::get_webhook_deliveries(&id, query.cursor.as_ref(), limit + 1, &pool),
+        db::count_webhook_deliveries(&id, &pool),
+    ) {
+        Ok(result) => result,
+        Err(_) => return ApiResponse::error(ErrorCode::InternalError, "获取投递记录失败"),
+    };
+    let page = Page::new(
+        deliveries,
+        total,
+        limit as usize,
+        |delivery: &WebhookDelivery| Cursor {
+            at: delivery.created_at,
+            id: delivery.id.clone(),
+        },
+    );
+    ApiResponse::new("获取投递记录成功", ResponseData::Json(json!(page)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_url_requires_https_and_host() {
+        assert_eq!(
+            normalize_url(" https://example.com/hook ", false).unwrap(),
+            "https://example.com/hook"
+        );
+        assert!(normalize_url("HTTPS://example.com", false).is_ok());
+        assert!(normalize_url("http://example.com/hook", false).is_err());
+        assert!(normalize_url("http://127.0.0.1:8080/hook", true).is_ok());
+        assert!(normalize_url("ftp://example.com", true).is_err());
+        assert!(normalize_url("https:///hook", false).is_err());
+        assert!(normalize_url("https://:443/hook", false).is_err());
+        assert!(normalize_url("https://example.com/a b", false).is_err());
+    }
+
+    #[test]
+    fn normalize_events_dedups_and_rejects_empty() {
+        let events = vec![
+            WebhookEvent::ClipDeleted,
+            WebhookEvent::ClipCreated,
+            WebhookEvent::ClipDeleted,
+        ];
+        assert_eq!(
+            normalize_events(events).unwrap(),
+            vec![WebhookEvent::ClipCreated, WebhookEvent::ClipDeleted]
+        );
+        assert!(normalize_events(Vec::new()).is_err());
+    }
+}